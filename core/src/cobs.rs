@@ -0,0 +1,172 @@
+//! Consistent Overhead Byte Stuffing
+//!
+//! [COBS] removes the zeroes from a payload so that a single `0x00` can delimit
+//! frames: the payload is split at its zeroes into blocks, each sent after a code
+//! byte giving its length plus one.  Blocks of 254 non-zero bytes have the code
+//! `0xFF` and no zero after them.  The overhead is a byte per 254, at most.
+//!
+//! ```
+//! use tokio_serial_core::cobs::Cobs;
+//! use tokio_serial_core::{Deframer, Framer};
+//!
+//! let mut cobs = Cobs::new([0u8; 64]);
+//! let mut frame = [0u8; 16];
+//! let mut len = 0;
+//! cobs.frame(b"\x11\x00\x22", |chunk| {
+//!     frame[len..len + chunk.len()].copy_from_slice(chunk);
+//!     len += chunk.len();
+//! })
+//! .unwrap();
+//! assert_eq!(&frame[..len], b"\x02\x11\x02\x22\x00");
+//!
+//! let (last, rest) = frame[..len].split_last().unwrap();
+//! for &byte in rest {
+//!     assert_eq!(cobs.push(byte), Ok(None));
+//! }
+//! assert_eq!(cobs.push(*last), Ok(Some(&b"\x11\x00\x22"[..])));
+//! ```
+//!
+//! [COBS]: https://doi.org/10.1109/90.769765
+use crate::{Deframer, Framer};
+
+use core::fmt;
+
+/// Delimits frames
+pub const DELIMITER: u8 = 0x00;
+
+// The largest block, whose code is `0xFF`.
+const MAX_BLOCK: usize = 254;
+
+/// Errors of [`Cobs`] frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CobsError {
+    /// The frame does not fit in the buffer of the deframer
+    TooLong,
+    /// The frame ended in the middle of a block
+    Truncated,
+}
+
+impl fmt::Display for CobsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CobsError::TooLong => f.write_str("COBS frame too long"),
+            CobsError::Truncated => f.write_str("truncated COBS frame"),
+        }
+    }
+}
+
+/// A COBS deframer and framer
+///
+/// Received frames are stored in `B`, such as a `[u8; N]` on a device or a
+/// `Vec<u8>` on a host, which gives the maximum frame size.
+#[derive(Debug, Clone)]
+pub struct Cobs<B> {
+    buf: B,
+    len: usize,
+    // Code of the current block, 0 before the first one.
+    code: u8,
+    // Bytes left in the current block.
+    remaining: u8,
+    error: Option<CobsError>,
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Cobs<B> {
+    /// A COBS codec receiving frames into `buf`.
+    pub fn new(buf: B) -> Self {
+        Self {
+            buf,
+            len: 0,
+            code: 0,
+            remaining: 0,
+            error: None,
+        }
+    }
+
+    /// Returns the size of the largest frame which can be received.
+    pub fn capacity(&self) -> usize {
+        self.buf.as_ref().len()
+    }
+
+    fn store(&mut self, byte: u8) {
+        if self.len == self.buf.as_ref().len() {
+            self.error.get_or_insert(CobsError::TooLong);
+        } else {
+            self.buf.as_mut()[self.len] = byte;
+            self.len += 1;
+        }
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Deframer for Cobs<B> {
+    type Error = CobsError;
+
+    fn push(&mut self, byte: u8) -> Result<Option<&[u8]>, CobsError> {
+        if byte == DELIMITER {
+            let (len, started, complete, error) =
+                (self.len, self.code != 0, self.remaining == 0, self.error);
+            self.reset();
+            return match error {
+                Some(error) => Err(error),
+                None if !complete => Err(CobsError::Truncated),
+                None if !started => Ok(None),
+                None => Ok(Some(&self.buf.as_ref()[..len])),
+            };
+        }
+        if self.remaining == 0 {
+            // The zero ending the previous block, the one of the last block is not
+            // part of the payload.
+            if self.code != 0 && self.code != 0xff {
+                self.store(0);
+            }
+            self.code = byte;
+            self.remaining = byte - 1;
+        } else {
+            self.store(byte);
+            self.remaining -= 1;
+        }
+        Ok(None)
+    }
+
+    fn reset(&mut self) {
+        self.len = 0;
+        self.code = 0;
+        self.remaining = 0;
+        self.error = None;
+    }
+}
+
+impl<B> Framer for Cobs<B> {
+    type Error = core::convert::Infallible;
+
+    fn frame<W>(&mut self, payload: &[u8], mut write: W) -> Result<(), Self::Error>
+    where
+        W: FnMut(&[u8]),
+    {
+        let mut rest = payload;
+        loop {
+            let block = &rest[..rest.len().min(MAX_BLOCK)];
+            match block.iter().position(|&b| b == 0) {
+                Some(zero) => {
+                    write(&[zero as u8 + 1]);
+                    write(&rest[..zero]);
+                    rest = &rest[zero + 1..];
+                }
+                None if block.len() == MAX_BLOCK => {
+                    write(&[0xff]);
+                    write(block);
+                    rest = &rest[MAX_BLOCK..];
+                    if rest.is_empty() {
+                        break;
+                    }
+                }
+                None => {
+                    write(&[block.len() as u8 + 1]);
+                    write(block);
+                    break;
+                }
+            }
+        }
+        write(&[DELIMITER]);
+        Ok(())
+    }
+}
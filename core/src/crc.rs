@@ -0,0 +1,214 @@
+//! Cyclic redundancy checks used by serial protocols
+//!
+//! Algorithms are described by their [Rocksoft model] parameters, the catalogue
+//! names of the predefined ones are the ones of the [CRC RevEng catalogue].  The
+//! implementation is bitwise: slower than a table driven one, but it takes no
+//! flash for tables, which matters more at serial line speeds than the few cycles
+//! per byte.
+//!
+//! ```
+//! use tokio_serial_core::crc::CRC16_MODBUS;
+//!
+//! assert_eq!(CRC16_MODBUS.checksum(b"123456789"), 0x4b37);
+//!
+//! let mut digest = CRC16_MODBUS.digest();
+//! digest.update(b"1234");
+//! digest.update(b"56789");
+//! assert_eq!(digest.finish(), 0x4b37);
+//! ```
+//!
+//! [Rocksoft model]: http://www.ross.net/crc/download/crc_v3.txt
+//! [CRC RevEng catalogue]: https://reveng.sourceforge.io/crc-catalogue/
+
+macro_rules! crc_width {
+    ($(#[$algo_doc:meta])* $algorithm:ident, $(#[$digest_doc:meta])* $digest:ident, $word:ty) => {
+        $(#[$algo_doc])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct $algorithm {
+            /// The generator polynomial, in normal (MSB first) form
+            pub poly: $word,
+            /// The register value before the first byte
+            pub init: $word,
+            /// Whether bytes are processed LSB first and the result reflected
+            pub reflected: bool,
+            /// The value XORed into the register to produce the checksum
+            pub xorout: $word,
+        }
+
+        impl $algorithm {
+            /// Compute the checksum of `data` in one go.
+            pub fn checksum(&self, data: &[u8]) -> $word {
+                let mut digest = self.digest();
+                digest.update(data);
+                digest.finish()
+            }
+
+            /// Start an incremental checksum computation.
+            pub fn digest(&self) -> $digest {
+                $digest {
+                    algorithm: *self,
+                    register: if self.reflected {
+                        self.init.reverse_bits()
+                    } else {
+                        self.init
+                    },
+                }
+            }
+        }
+
+        $(#[$digest_doc])*
+        #[derive(Debug, Clone)]
+        pub struct $digest {
+            algorithm: $algorithm,
+            register: $word,
+        }
+
+        impl $digest {
+            /// Feed more data into the checksum.
+            pub fn update(&mut self, data: &[u8]) {
+                const BITS: u32 = <$word>::max_value().count_ones();
+                let algorithm = &self.algorithm;
+                let mut register = self.register;
+                if algorithm.reflected {
+                    let poly = algorithm.poly.reverse_bits();
+                    for &byte in data {
+                        register ^= <$word>::from(byte);
+                        for _ in 0..8 {
+                            register = if register & 1 != 0 {
+                                (register >> 1) ^ poly
+                            } else {
+                                register >> 1
+                            };
+                        }
+                    }
+                } else {
+                    let top = 1 << (BITS - 1);
+                    for &byte in data {
+                        register ^= <$word>::from(byte) << (BITS - 8);
+                        for _ in 0..8 {
+                            register = if register & top != 0 {
+                                (register << 1) ^ algorithm.poly
+                            } else {
+                                register << 1
+                            };
+                        }
+                    }
+                }
+                self.register = register;
+            }
+
+            /// Returns the checksum of all data fed so far.
+            ///
+            /// The digest is left untouched, more data can still be added.
+            pub fn finish(&self) -> $word {
+                self.register ^ self.algorithm.xorout
+            }
+        }
+    };
+}
+
+crc_width!(
+    /// Parameters of an 8 bit CRC
+    Crc8,
+    /// An 8 bit CRC being computed
+    Crc8Digest,
+    u8
+);
+crc_width!(
+    /// Parameters of a 16 bit CRC
+    Crc16,
+    /// A 16 bit CRC being computed
+    Crc16Digest,
+    u16
+);
+crc_width!(
+    /// Parameters of a 32 bit CRC
+    Crc32,
+    /// A 32 bit CRC being computed
+    Crc32Digest,
+    u32
+);
+
+/// CRC-8/SMBUS, used by SMBus packet error checking
+pub const CRC8_SMBUS: Crc8 = Crc8 {
+    poly: 0x07,
+    init: 0x00,
+    reflected: false,
+    xorout: 0x00,
+};
+
+/// CRC-8/MAXIM-DOW, the Dallas/Maxim 1-Wire CRC
+pub const CRC8_MAXIM: Crc8 = Crc8 {
+    poly: 0x31,
+    init: 0x00,
+    reflected: true,
+    xorout: 0x00,
+};
+
+/// CRC-16/ARC, the CRC-16 of DSMR P1 telegrams
+pub const CRC16_ARC: Crc16 = Crc16 {
+    poly: 0x8005,
+    init: 0x0000,
+    reflected: true,
+    xorout: 0x0000,
+};
+
+/// CRC-16/MODBUS, used by Modbus RTU
+///
+/// The checksum is sent low byte first.
+pub const CRC16_MODBUS: Crc16 = Crc16 {
+    poly: 0x8005,
+    init: 0xffff,
+    reflected: true,
+    xorout: 0x0000,
+};
+
+/// CRC-16/XMODEM, used by XMODEM-CRC, YMODEM and ZMODEM
+pub const CRC16_XMODEM: Crc16 = Crc16 {
+    poly: 0x1021,
+    init: 0x0000,
+    reflected: false,
+    xorout: 0x0000,
+};
+
+/// CRC-16/KERMIT, the 16 bit check of the Kermit protocol
+pub const CRC16_KERMIT: Crc16 = Crc16 {
+    poly: 0x1021,
+    init: 0x0000,
+    reflected: true,
+    xorout: 0x0000,
+};
+
+/// CRC-16/IBM-3740, often called CRC-16/CCITT-FALSE
+pub const CRC16_IBM_3740: Crc16 = Crc16 {
+    poly: 0x1021,
+    init: 0xffff,
+    reflected: false,
+    xorout: 0x0000,
+};
+
+/// CRC-16/IBM-SDLC, also known as CRC-16/X-25, the frame check sequence of HDLC
+/// and PPP
+pub const CRC16_IBM_SDLC: Crc16 = Crc16 {
+    poly: 0x1021,
+    init: 0xffff,
+    reflected: true,
+    xorout: 0xffff,
+};
+
+/// CRC-16/MCRF4XX, the X.25 CRC without its final XOR, which MAVLink calls
+/// CRC-16/X.25
+pub const CRC16_MCRF4XX: Crc16 = Crc16 {
+    poly: 0x1021,
+    init: 0xffff,
+    reflected: true,
+    xorout: 0x0000,
+};
+
+/// CRC-32/ISO-HDLC, the CRC-32 of Ethernet, zlib and PNG
+pub const CRC32_ISO_HDLC: Crc32 = Crc32 {
+    poly: 0x04c1_1db7,
+    init: 0xffff_ffff,
+    reflected: true,
+    xorout: 0xffff_ffff,
+};
@@ -0,0 +1,223 @@
+//! DLE STX ... DLE ETX framing, with an optional block check character
+//!
+//! Frames start with `DLE STX` and end with `DLE ETX`; `DLE` bytes of the payload
+//! are doubled.  Bytes received outside of a frame are line noise and ignored.
+//! Many instruments, scales and payment terminals follow the frame with a block
+//! check character, the XOR of the bytes sent from the one after `DLE STX` up to
+//! the final `ETX` included, doubled `DLE`s too, as in Siemens' 3964R.  The BCC
+//! itself is not escaped.
+//!
+//! ```
+//! use tokio_serial_core::dle::Dle;
+//! use tokio_serial_core::{Deframer, Framer};
+//!
+//! let mut dle = Dle::new([0u8; 64]).bcc(true);
+//! let mut frame = [0u8; 16];
+//! let mut len = 0;
+//! dle.frame(b"\x10ok", |chunk| {
+//!     frame[len..len + chunk.len()].copy_from_slice(chunk);
+//!     len += chunk.len();
+//! })
+//! .unwrap();
+//! assert_eq!(&frame[..len], b"\x10\x02\x10\x10ok\x10\x03\x17");
+//!
+//! let (last, rest) = frame[..len].split_last().unwrap();
+//! for &byte in rest {
+//!     assert_eq!(dle.push(byte), Ok(None));
+//! }
+//! assert_eq!(dle.push(*last), Ok(Some(&b"\x10ok"[..])));
+//! ```
+use crate::{Deframer, Framer};
+
+use core::fmt;
+
+/// Escapes the next byte
+pub const DLE: u8 = 0x10;
+/// Starts a frame, after a `DLE`
+pub const STX: u8 = 0x02;
+/// Ends a frame, after a `DLE`
+pub const ETX: u8 = 0x03;
+
+/// Errors of [`Dle`] frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DleError {
+    /// The frame does not fit in the buffer of the deframer
+    TooLong,
+    /// `DLE` was followed by something else than `DLE`, `STX` or `ETX`
+    InvalidEscape(u8),
+    /// A new frame started before the end of the previous one
+    Incomplete,
+    /// The block check character does not match the frame
+    Bcc,
+}
+
+impl fmt::Display for DleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DleError::TooLong => f.write_str("DLE frame too long"),
+            DleError::InvalidEscape(byte) => write!(f, "invalid DLE escape {:#04x}", byte),
+            DleError::Incomplete => f.write_str("DLE frame interrupted by a new frame"),
+            DleError::Bcc => f.write_str("DLE frame block check character mismatch"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    // Waiting for `DLE STX`, `true` after the `DLE`
+    Idle(bool),
+    // In a frame, `true` after a `DLE`
+    Frame(bool),
+    // Waiting for the BCC
+    Bcc,
+}
+
+/// A DLE STX/ETX deframer and framer
+///
+/// Received frames are stored in `B`, such as a `[u8; N]` on a device or a
+/// `Vec<u8>` on a host, which bounds their length.
+#[derive(Debug, Clone)]
+pub struct Dle<B> {
+    buf: B,
+    len: usize,
+    state: State,
+    error: Option<DleError>,
+    check: u8,
+    bcc: bool,
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Dle<B> {
+    /// A DLE codec receiving frames into `buf`, without block check character.
+    pub fn new(buf: B) -> Self {
+        Self {
+            buf,
+            len: 0,
+            state: State::Idle(false),
+            error: None,
+            check: 0,
+            bcc: false,
+        }
+    }
+
+    /// Set whether frames are followed by a block check character, `false` by
+    /// default.
+    pub fn bcc(mut self, bcc: bool) -> Self {
+        self.bcc = bcc;
+        self
+    }
+
+    /// Returns the size of the largest frame which can be received.
+    pub fn capacity(&self) -> usize {
+        self.buf.as_ref().len()
+    }
+
+    fn start(&mut self) {
+        self.len = 0;
+        self.state = State::Frame(false);
+        self.error = None;
+        self.check = 0;
+    }
+
+    fn store(&mut self, byte: u8) {
+        if self.len == self.buf.as_ref().len() {
+            self.error.get_or_insert(DleError::TooLong);
+        } else {
+            self.buf.as_mut()[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn end(&mut self) -> Result<Option<&[u8]>, DleError> {
+        let (len, error) = (self.len, self.error);
+        self.reset();
+        match error {
+            Some(error) => Err(error),
+            None => Ok(Some(&self.buf.as_ref()[..len])),
+        }
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Deframer for Dle<B> {
+    type Error = DleError;
+
+    fn push(&mut self, byte: u8) -> Result<Option<&[u8]>, DleError> {
+        match self.state {
+            State::Idle(false) => {
+                if byte == DLE {
+                    self.state = State::Idle(true);
+                }
+            }
+            State::Idle(true) => match byte {
+                STX => self.start(),
+                DLE => {}
+                _ => self.state = State::Idle(false),
+            },
+            State::Frame(false) => {
+                self.check ^= byte;
+                if byte == DLE {
+                    self.state = State::Frame(true);
+                } else {
+                    self.store(byte);
+                }
+            }
+            State::Frame(true) => {
+                self.check ^= byte;
+                match byte {
+                    DLE => {
+                        self.store(DLE);
+                        self.state = State::Frame(false);
+                    }
+                    ETX if self.bcc => self.state = State::Bcc,
+                    ETX => return self.end(),
+                    STX => {
+                        self.start();
+                        return Err(DleError::Incomplete);
+                    }
+                    _ => {
+                        self.reset();
+                        return Err(DleError::InvalidEscape(byte));
+                    }
+                }
+            }
+            State::Bcc => {
+                if byte != self.check {
+                    self.error.get_or_insert(DleError::Bcc);
+                }
+                return self.end();
+            }
+        }
+        Ok(None)
+    }
+
+    fn reset(&mut self) {
+        self.len = 0;
+        self.state = State::Idle(false);
+        self.error = None;
+        self.check = 0;
+    }
+}
+
+impl<B> Framer for Dle<B> {
+    type Error = core::convert::Infallible;
+
+    fn frame<W>(&mut self, payload: &[u8], mut write: W) -> Result<(), Self::Error>
+    where
+        W: FnMut(&[u8]),
+    {
+        let mut check = 0;
+        write(&[DLE, STX]);
+        for chunk in payload.split_inclusive(|&b| b == DLE) {
+            check = chunk.iter().fold(check, |check, &byte| check ^ byte);
+            write(chunk);
+            if chunk.last() == Some(&DLE) {
+                check ^= DLE;
+                write(&[DLE]);
+            }
+        }
+        write(&[DLE, ETX]);
+        if self.bcc {
+            write(&[check ^ DLE ^ ETX]);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+/// Splits a byte stream into frames
+///
+/// A `Deframer` is a state machine fed one byte at a time, which makes it usable
+/// from an interrupt handler just as well as from a host side codec.  Complete
+/// frames are kept in a buffer owned by the deframer and handed out by reference,
+/// so decoding does not need to allocate.
+pub trait Deframer {
+    /// The error reported for malformed frames
+    type Error;
+
+    /// Feed the next received byte.
+    ///
+    /// Returns the payload of a frame once `byte` completes it.  The payload is valid
+    /// until the next call to `push` or `reset`.
+    ///
+    /// After an error the deframer discards the broken frame and resynchronizes on the
+    /// following input by itself.
+    fn push(&mut self, byte: u8) -> Result<Option<&[u8]>, Self::Error>;
+
+    /// Drop any partially received frame.
+    fn reset(&mut self);
+}
+
+/// Turns payloads into frames ready to be sent
+pub trait Framer {
+    /// The error reported for payloads which cannot be framed
+    type Error;
+
+    /// Frame `payload`, handing the encoded bytes to `write` in order.
+    ///
+    /// `write` may be called any number of times per frame, each slice has to be sent
+    /// as is.
+    fn frame<W>(&mut self, payload: &[u8], write: W) -> Result<(), Self::Error>
+    where
+        W: FnMut(&[u8]);
+}
@@ -0,0 +1,241 @@
+//! HDLC-like asynchronous framing, as used by PPP ([RFC 1662])
+//!
+//! Frames are delimited by flags (`0x7E`).  Flags, control escapes (`0x7D`) and the
+//! control characters of the async control character map are sent as `0x7D`
+//! followed by the byte XORed with `0x20`.  The payload is followed by a frame
+//! check sequence: the X.25 CRC-16 by default, or the CRC-32 of ISO HDLC, sent
+//! least significant byte first.
+//!
+//! ```
+//! use tokio_serial_core::hdlc::Hdlc;
+//! use tokio_serial_core::{Deframer, Framer};
+//!
+//! let mut hdlc = Hdlc::new([0u8; 64]);
+//! let mut frame = [0u8; 16];
+//! let mut len = 0;
+//! hdlc.frame(b"\x7e", |chunk| {
+//!     frame[len..len + chunk.len()].copy_from_slice(chunk);
+//!     len += chunk.len();
+//! })
+//! .unwrap();
+//! assert_eq!(&frame[..len], b"\x7e\x7d\x5e\x81\x6a\x7e");
+//!
+//! let (last, rest) = frame[..len].split_last().unwrap();
+//! for &byte in rest {
+//!     assert_eq!(hdlc.push(byte), Ok(None));
+//! }
+//! assert_eq!(hdlc.push(*last), Ok(Some(&b"\x7e"[..])));
+//! ```
+//!
+//! [RFC 1662]: https://www.rfc-editor.org/rfc/rfc1662
+use crate::crc::{CRC16_IBM_SDLC, CRC32_ISO_HDLC};
+use crate::{Deframer, Framer};
+
+use core::fmt;
+
+/// Delimits frames
+pub const FLAG: u8 = 0x7e;
+/// Escapes the next byte
+pub const ESCAPE: u8 = 0x7d;
+/// XORed into escaped bytes
+pub const ESCAPE_XOR: u8 = 0x20;
+
+/// The frame check sequence following the payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fcs {
+    /// The 16 bit FCS, CRC-16/IBM-SDLC
+    Fcs16,
+    /// The 32 bit FCS, CRC-32/ISO-HDLC
+    Fcs32,
+}
+
+impl Fcs {
+    /// Returns the length of the FCS in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            Fcs::Fcs16 => 2,
+            Fcs::Fcs32 => 4,
+        }
+    }
+
+    // The FCS of `payload`, in transmission order.
+    fn compute(self, payload: &[u8]) -> ([u8; 4], usize) {
+        let mut fcs = [0u8; 4];
+        match self {
+            Fcs::Fcs16 => fcs[..2].copy_from_slice(&CRC16_IBM_SDLC.checksum(payload).to_le_bytes()),
+            Fcs::Fcs32 => fcs.copy_from_slice(&CRC32_ISO_HDLC.checksum(payload).to_le_bytes()),
+        }
+        (fcs, self.size())
+    }
+}
+
+/// Errors of [`Hdlc`] frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HdlcError {
+    /// The frame does not fit in the buffer of the deframer
+    TooLong,
+    /// The frame is shorter than its FCS
+    TooShort,
+    /// The FCS does not match the payload
+    Fcs,
+    /// The sender aborted the frame with `0x7D 0x7E`
+    Aborted,
+}
+
+impl fmt::Display for HdlcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HdlcError::TooLong => f.write_str("HDLC frame too long"),
+            HdlcError::TooShort => f.write_str("HDLC frame too short"),
+            HdlcError::Fcs => f.write_str("HDLC frame check sequence mismatch"),
+            HdlcError::Aborted => f.write_str("HDLC frame aborted"),
+        }
+    }
+}
+
+/// An HDLC-like deframer and framer
+///
+/// Received frames, FCS included, are stored in `B`, such as a `[u8; N]` on a
+/// device or a `Vec<u8>` on a host, which bounds their length.  Payloads are
+/// handed out without their FCS.
+#[derive(Debug, Clone)]
+pub struct Hdlc<B> {
+    buf: B,
+    len: usize,
+    escaped: bool,
+    error: Option<HdlcError>,
+    fcs: Fcs,
+    accm: u32,
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Hdlc<B> {
+    /// An HDLC codec receiving frames into `buf`, with a 16 bit FCS and only flags
+    /// and escapes escaped.
+    pub fn new(buf: B) -> Self {
+        Self {
+            buf,
+            len: 0,
+            escaped: false,
+            error: None,
+            fcs: Fcs::Fcs16,
+            accm: 0,
+        }
+    }
+
+    /// Set the frame check sequence.
+    pub fn fcs(mut self, fcs: Fcs) -> Self {
+        self.fcs = fcs;
+        self
+    }
+
+    /// Set the async control character map: bit `n` set escapes the control
+    /// character `n` in sent frames, and drops it from received ones, where it can
+    /// only be noise inserted by the link.
+    ///
+    /// PPP starts with all of them escaped, `0xFFFF_FFFF`.
+    pub fn accm(mut self, accm: u32) -> Self {
+        self.accm = accm;
+        self
+    }
+
+    /// Change the async control character map, as negotiated by PPP's LCP, see
+    /// [`accm`](Hdlc::accm).
+    pub fn set_accm(&mut self, accm: u32) {
+        self.accm = accm;
+    }
+
+    /// Returns the size of the largest frame which can be received, FCS included.
+    pub fn capacity(&self) -> usize {
+        self.buf.as_ref().len()
+    }
+
+    fn in_accm(&self, byte: u8) -> bool {
+        byte < 0x20 && self.accm & (1 << byte) != 0
+    }
+
+    fn needs_escape(&self, byte: u8) -> bool {
+        byte == FLAG || byte == ESCAPE || self.in_accm(byte)
+    }
+
+    fn store(&mut self, byte: u8) {
+        if self.len == self.buf.as_ref().len() {
+            self.error.get_or_insert(HdlcError::TooLong);
+        } else {
+            self.buf.as_mut()[self.len] = byte;
+            self.len += 1;
+        }
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Deframer for Hdlc<B> {
+    type Error = HdlcError;
+
+    fn push(&mut self, byte: u8) -> Result<Option<&[u8]>, HdlcError> {
+        if byte == FLAG {
+            let (len, escaped, error) = (self.len, self.escaped, self.error);
+            self.reset();
+            if escaped {
+                return Err(HdlcError::Aborted);
+            }
+            if let Some(error) = error {
+                return Err(error);
+            }
+            if len == 0 {
+                // Consecutive flags between frames
+                return Ok(None);
+            }
+            let fcs_len = self.fcs.size();
+            if len < fcs_len {
+                return Err(HdlcError::TooShort);
+            }
+            let (payload, fcs) = self.buf.as_ref()[..len].split_at(len - fcs_len);
+            if self.fcs.compute(payload).0[..fcs_len] != *fcs {
+                return Err(HdlcError::Fcs);
+            }
+            return Ok(Some(payload));
+        }
+        if self.in_accm(byte) {
+            return Ok(None);
+        }
+        if self.escaped {
+            self.escaped = false;
+            self.store(byte ^ ESCAPE_XOR);
+        } else if byte == ESCAPE {
+            self.escaped = true;
+        } else {
+            self.store(byte);
+        }
+        Ok(None)
+    }
+
+    fn reset(&mut self) {
+        self.len = 0;
+        self.escaped = false;
+        self.error = None;
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Framer for Hdlc<B> {
+    type Error = core::convert::Infallible;
+
+    fn frame<W>(&mut self, payload: &[u8], mut write: W) -> Result<(), Self::Error>
+    where
+        W: FnMut(&[u8]),
+    {
+        let (fcs, fcs_len) = self.fcs.compute(payload);
+        write(&[FLAG]);
+        for part in [payload, &fcs[..fcs_len]].iter() {
+            for chunk in part.split_inclusive(|&b| self.needs_escape(b)) {
+                match chunk.split_last() {
+                    Some((&last, rest)) if self.needs_escape(last) => {
+                        write(rest);
+                        write(&[ESCAPE, last ^ ESCAPE_XOR]);
+                    }
+                    _ => write(chunk),
+                }
+            }
+        }
+        write(&[FLAG]);
+        Ok(())
+    }
+}
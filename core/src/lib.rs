@@ -0,0 +1,21 @@
+//! Frame definitions and checksums shared between hosts and devices
+//!
+//! This crate holds the protocol layer of `tokio-serial` which does not need an
+//! operating system: CRCs and byte-oriented framing state machines.  It is
+//! `no_std` and does not allocate, so firmware on the device side of a link can
+//! use the exact same definitions as the host application, which gets them
+//! wrapped into `tokio_util` codecs by `tokio-serial`'s `codec` feature.
+//!
+#![no_std]
+#![deny(missing_docs)]
+#![warn(rust_2018_idioms)]
+
+pub mod cobs;
+pub mod crc;
+pub mod dle;
+mod frame;
+pub mod hdlc;
+pub mod mux;
+pub mod slip;
+
+pub use frame::{Deframer, Framer};
@@ -0,0 +1,242 @@
+//! Frames carrying logical channels over one link
+//!
+//! Each frame starts with [`SYNC`], followed by a header giving the channel, the
+//! [kind](MuxFrame::kind) of the frame and the length of the payload as a little
+//! endian `u16`, then the payload and a CRC-16/MODBUS of the header and payload,
+//! little endian as well:
+//!
+//! ```text
+//! 0xA5 | channel | kind | length (2) | payload (length) | CRC (2)
+//! ```
+//!
+//! [`DATA`] frames carry the bytes of a channel.  Flow control is credit based: a
+//! side may only send as many data bytes on a channel as the other side granted
+//! with [`CREDIT`] frames, whose payload is the number of bytes granted as a
+//! little endian `u32`.  Nothing is granted initially, opening a channel means
+//! granting the size of its receive buffer.
+//!
+//! ```
+//! use tokio_serial_core::mux::{self, MuxDeframer, MuxFrame};
+//!
+//! let mut frame = [0u8; 16];
+//! let mut len = 0;
+//! mux::frame(2, mux::DATA, b"hi", |chunk| {
+//!     frame[len..len + chunk.len()].copy_from_slice(chunk);
+//!     len += chunk.len();
+//! })
+//! .unwrap();
+//!
+//! let mut deframer = MuxDeframer::new([0u8; 64]);
+//! let (last, rest) = frame[..len].split_last().unwrap();
+//! for &byte in rest {
+//!     assert_eq!(deframer.push(byte), Ok(None));
+//! }
+//! assert_eq!(
+//!     deframer.push(*last),
+//!     Ok(Some(MuxFrame {
+//!         channel: 2,
+//!         kind: mux::DATA,
+//!         payload: &b"hi"[..]
+//!     }))
+//! );
+//! ```
+use crate::crc::CRC16_MODBUS;
+
+use core::fmt;
+
+/// Starts a frame
+pub const SYNC: u8 = 0xa5;
+/// The kind of frames carrying data
+pub const DATA: u8 = 0;
+/// The kind of frames granting credit
+pub const CREDIT: u8 = 1;
+
+// Channel, kind and length
+const HEADER_LEN: usize = 4;
+
+/// Errors of multiplexed frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MuxError {
+    /// The payload does not fit in the buffer of the deframer, or in a frame
+    TooLong(usize),
+    /// The CRC of the frame does not match its content
+    Crc,
+}
+
+impl fmt::Display for MuxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MuxError::TooLong(len) => write!(f, "multiplexed frame of {} bytes is too long", len),
+            MuxError::Crc => f.write_str("multiplexed frame CRC mismatch"),
+        }
+    }
+}
+
+/// A frame received by a [`MuxDeframer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MuxFrame<'a> {
+    /// The channel the frame belongs to
+    pub channel: u8,
+    /// [`DATA`], [`CREDIT`], or a kind from a later version to be ignored
+    pub kind: u8,
+    /// The payload, without header or CRC
+    pub payload: &'a [u8],
+}
+
+impl MuxFrame<'_> {
+    /// Returns the number of bytes granted by a [`CREDIT`] frame.
+    ///
+    /// Returns `None` for other kinds of frames, or a payload of the wrong size.
+    pub fn credit(&self) -> Option<u32> {
+        match (self.kind, self.payload) {
+            (CREDIT, &[a, b, c, d]) => Some(u32::from_le_bytes([a, b, c, d])),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Sync,
+    Header(usize),
+    Payload,
+    Crc(usize),
+}
+
+/// A multiplexed frames deframer
+///
+/// Payloads are stored in `B`, such as a `[u8; N]` on a device or a `Vec<u8>` on
+/// a host, which bounds their length.
+#[derive(Debug, Clone)]
+pub struct MuxDeframer<B> {
+    buf: B,
+    state: State,
+    header: [u8; HEADER_LEN],
+    len: usize,
+    crc: [u8; 2],
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> MuxDeframer<B> {
+    /// A deframer receiving payloads into `buf`.
+    pub fn new(buf: B) -> Self {
+        Self {
+            buf,
+            state: State::Sync,
+            header: [0; HEADER_LEN],
+            len: 0,
+            crc: [0; 2],
+        }
+    }
+
+    /// Returns the size of the largest payload which can be received.
+    pub fn capacity(&self) -> usize {
+        self.buf.as_ref().len()
+    }
+
+    fn payload_len(&self) -> usize {
+        usize::from(u16::from_le_bytes([self.header[2], self.header[3]]))
+    }
+
+    /// Feed the next received byte.
+    ///
+    /// Returns a frame once `byte` completes it.  The payload is valid until the next
+    /// call to `push` or `reset`.
+    ///
+    /// After an error the deframer drops the frame and looks for the next
+    /// [`SYNC`].
+    pub fn push(&mut self, byte: u8) -> Result<Option<MuxFrame<'_>>, MuxError> {
+        match self.state {
+            State::Sync => {
+                if byte == SYNC {
+                    self.state = State::Header(0);
+                }
+                return Ok(None);
+            }
+            State::Header(n) => {
+                self.header[n] = byte;
+                if n + 1 < HEADER_LEN {
+                    self.state = State::Header(n + 1);
+                    return Ok(None);
+                }
+                let len = self.payload_len();
+                if len > self.capacity() {
+                    self.reset();
+                    return Err(MuxError::TooLong(len));
+                }
+                self.len = 0;
+                self.state = if len == 0 {
+                    State::Crc(0)
+                } else {
+                    State::Payload
+                };
+                return Ok(None);
+            }
+            State::Payload => {
+                self.buf.as_mut()[self.len] = byte;
+                self.len += 1;
+                if self.len == self.payload_len() {
+                    self.state = State::Crc(0);
+                }
+                return Ok(None);
+            }
+            State::Crc(0) => {
+                self.crc[0] = byte;
+                self.state = State::Crc(1);
+                return Ok(None);
+            }
+            State::Crc(_) => self.crc[1] = byte,
+        }
+
+        self.state = State::Sync;
+        let payload = &self.buf.as_ref()[..self.len];
+        let mut digest = CRC16_MODBUS.digest();
+        digest.update(&self.header);
+        digest.update(payload);
+        if digest.finish() != u16::from_le_bytes(self.crc) {
+            return Err(MuxError::Crc);
+        }
+        Ok(Some(MuxFrame {
+            channel: self.header[0],
+            kind: self.header[1],
+            payload,
+        }))
+    }
+
+    /// Drop any partially received frame.
+    pub fn reset(&mut self) {
+        self.state = State::Sync;
+        self.len = 0;
+    }
+}
+
+/// Frame `payload` for `channel`, handing the encoded bytes to `write` in order.
+///
+/// ## Errors
+///
+/// * `TooLong` if the payload is longer than 65535 bytes.
+pub fn frame<W>(channel: u8, kind: u8, payload: &[u8], mut write: W) -> Result<(), MuxError>
+where
+    W: FnMut(&[u8]),
+{
+    if payload.len() > usize::from(u16::MAX) {
+        return Err(MuxError::TooLong(payload.len()));
+    }
+    let len = (payload.len() as u16).to_le_bytes();
+    let header = [channel, kind, len[0], len[1]];
+    let mut digest = CRC16_MODBUS.digest();
+    digest.update(&header);
+    digest.update(payload);
+    write(&[SYNC]);
+    write(&header);
+    write(payload);
+    write(&digest.finish().to_le_bytes());
+    Ok(())
+}
+
+/// Frame a grant of `credit` bytes on `channel`, see [`frame`].
+pub fn frame_credit<W>(channel: u8, credit: u32, write: W)
+where
+    W: FnMut(&[u8]),
+{
+    let _ = frame(channel, CREDIT, &credit.to_le_bytes(), write);
+}
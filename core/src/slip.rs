@@ -0,0 +1,173 @@
+//! Serial Line Internet Protocol framing, [RFC 1055]
+//!
+//! Frames end with `END` (`0xC0`); `END` and `ESC` (`0xDB`) bytes of the payload
+//! are sent as `ESC ESC_END` and `ESC ESC_ESC`.  Senders usually start frames with
+//! an `END` too, flushing line noise received before the frame, which gives empty
+//! frames the deframer skips.
+//!
+//! ```
+//! use tokio_serial_core::slip::Slip;
+//! use tokio_serial_core::{Deframer, Framer};
+//!
+//! let mut slip = Slip::new([0u8; 64]);
+//! let mut frame = [0u8; 16];
+//! let mut len = 0;
+//! slip.frame(b"\xc0ok", |chunk| {
+//!     frame[len..len + chunk.len()].copy_from_slice(chunk);
+//!     len += chunk.len();
+//! })
+//! .unwrap();
+//! assert_eq!(&frame[..len], b"\xc0\xdb\xdcok\xc0");
+//!
+//! let (last, rest) = frame[..len].split_last().unwrap();
+//! for &byte in rest {
+//!     assert_eq!(slip.push(byte), Ok(None));
+//! }
+//! assert_eq!(slip.push(*last), Ok(Some(&b"\xc0ok"[..])));
+//! ```
+//!
+//! [RFC 1055]: https://www.rfc-editor.org/rfc/rfc1055
+use crate::{Deframer, Framer};
+
+use core::fmt;
+
+/// Ends a frame
+pub const END: u8 = 0xc0;
+/// Escapes the next byte
+pub const ESC: u8 = 0xdb;
+/// `END` in the payload, after an `ESC`
+pub const ESC_END: u8 = 0xdc;
+/// `ESC` in the payload, after an `ESC`
+pub const ESC_ESC: u8 = 0xdd;
+
+/// Errors of [`Slip`] frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SlipError {
+    /// The frame does not fit in the buffer of the deframer
+    TooLong,
+    /// `ESC` was followed by something else than `ESC_END` or `ESC_ESC`
+    InvalidEscape(u8),
+}
+
+impl fmt::Display for SlipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlipError::TooLong => f.write_str("SLIP frame too long"),
+            SlipError::InvalidEscape(byte) => write!(f, "invalid SLIP escape {:#04x}", byte),
+        }
+    }
+}
+
+/// A SLIP deframer and framer
+///
+/// Received frames are stored in `B`, such as a `[u8; N]` on a device or a
+/// `Vec<u8>` on a host, which bounds their length.
+#[derive(Debug, Clone)]
+pub struct Slip<B> {
+    buf: B,
+    len: usize,
+    escaped: bool,
+    error: Option<SlipError>,
+    leading_end: bool,
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Slip<B> {
+    /// A SLIP codec receiving frames into `buf`, which starts frames it sends with
+    /// an `END`.
+    pub fn new(buf: B) -> Self {
+        Self {
+            buf,
+            len: 0,
+            escaped: false,
+            error: None,
+            leading_end: true,
+        }
+    }
+
+    /// Set whether sent frames start with an `END` as well, `true` by default.
+    ///
+    /// Some devices choke on the empty frames this gives.
+    pub fn leading_end(mut self, leading_end: bool) -> Self {
+        self.leading_end = leading_end;
+        self
+    }
+
+    /// Returns the size of the largest frame which can be received.
+    pub fn capacity(&self) -> usize {
+        self.buf.as_ref().len()
+    }
+
+    fn store(&mut self, byte: u8) {
+        if self.len == self.buf.as_ref().len() {
+            self.error.get_or_insert(SlipError::TooLong);
+        } else {
+            self.buf.as_mut()[self.len] = byte;
+            self.len += 1;
+        }
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Deframer for Slip<B> {
+    type Error = SlipError;
+
+    fn push(&mut self, byte: u8) -> Result<Option<&[u8]>, SlipError> {
+        if byte == END {
+            let (len, error) = (self.len, self.error);
+            self.reset();
+            return match error {
+                Some(error) => Err(error),
+                None if len == 0 => Ok(None),
+                None => Ok(Some(&self.buf.as_ref()[..len])),
+            };
+        }
+        if self.escaped {
+            self.escaped = false;
+            match byte {
+                ESC_END => self.store(END),
+                ESC_ESC => self.store(ESC),
+                _ => {
+                    self.error.get_or_insert(SlipError::InvalidEscape(byte));
+                }
+            }
+        } else if byte == ESC {
+            self.escaped = true;
+        } else {
+            self.store(byte);
+        }
+        Ok(None)
+    }
+
+    fn reset(&mut self) {
+        self.len = 0;
+        self.escaped = false;
+        self.error = None;
+    }
+}
+
+impl<B> Framer for Slip<B> {
+    type Error = core::convert::Infallible;
+
+    fn frame<W>(&mut self, payload: &[u8], mut write: W) -> Result<(), Self::Error>
+    where
+        W: FnMut(&[u8]),
+    {
+        if self.leading_end {
+            write(&[END]);
+        }
+        for chunk in payload.split_inclusive(|&b| b == END || b == ESC) {
+            match chunk.split_last() {
+                Some((&END, rest)) => {
+                    write(rest);
+                    write(&[ESC, ESC_END]);
+                }
+                Some((&ESC, rest)) => {
+                    write(rest);
+                    write(&[ESC, ESC_ESC]);
+                }
+                _ => write(chunk),
+            }
+        }
+        write(&[END]);
+        Ok(())
+    }
+}
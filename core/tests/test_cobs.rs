@@ -0,0 +1,76 @@
+use tokio_serial_core::cobs::{Cobs, CobsError};
+use tokio_serial_core::{Deframer, Framer};
+
+fn frame<F: Framer>(framer: &mut F, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let _ = framer.frame(payload, |chunk| out.extend_from_slice(chunk));
+    out
+}
+
+fn deframe<D: Deframer>(deframer: &mut D, data: &[u8]) -> Vec<Result<Vec<u8>, D::Error>> {
+    let mut frames = Vec::new();
+    for &byte in data {
+        match deframer.push(byte) {
+            Ok(Some(frame)) => frames.push(Ok(frame.to_vec())),
+            Ok(None) => {}
+            Err(err) => frames.push(Err(err)),
+        }
+    }
+    frames
+}
+
+// The examples of the Wikipedia article, which follow the paper.
+fn reference_vectors() -> Vec<(Vec<u8>, Vec<u8>)> {
+    let run: Vec<u8> = (0x01..=0xfe).collect();
+    let with = |prefix: &[u8], body: &[u8], suffix: &[u8]| {
+        let mut v = prefix.to_vec();
+        v.extend_from_slice(body);
+        v.extend_from_slice(suffix);
+        v
+    };
+    vec![
+        (vec![0x00], vec![0x01, 0x01, 0x00]),
+        (vec![0x00, 0x00], vec![0x01, 0x01, 0x01, 0x00]),
+        (vec![0x00, 0x11, 0x00], vec![0x01, 0x02, 0x11, 0x01, 0x00]),
+        (
+            vec![0x11, 0x22, 0x00, 0x33],
+            vec![0x03, 0x11, 0x22, 0x02, 0x33, 0x00],
+        ),
+        (
+            vec![0x11, 0x22, 0x33, 0x44],
+            vec![0x05, 0x11, 0x22, 0x33, 0x44, 0x00],
+        ),
+        (
+            vec![0x11, 0x00, 0x00, 0x00],
+            vec![0x02, 0x11, 0x01, 0x01, 0x01, 0x00],
+        ),
+        (run.clone(), with(&[0xff], &run, &[0x00])),
+        (with(&[0x00], &run, &[]), with(&[0x01, 0xff], &run, &[0x00])),
+        (
+            with(&[], &run, &[0xff]),
+            with(&[0xff], &run, &[0x02, 0xff, 0x00]),
+        ),
+    ]
+}
+
+#[test]
+fn payloads_encode_to_reference_vectors() {
+    let mut cobs = Cobs::new(vec![0u8; 512]);
+    for (payload, encoded) in reference_vectors() {
+        assert_eq!(frame(&mut cobs, &payload), encoded, "{:02x?}", payload);
+        assert_eq!(deframe(&mut cobs, &encoded), [Ok(payload)]);
+    }
+}
+
+#[test]
+fn broken_frames_resynchronize() {
+    let mut cobs = Cobs::new([0u8; 4]);
+    assert_eq!(
+        deframe(&mut cobs, b"\x00\x06large\x00\x05ab\x00\x03ok\x00"),
+        [
+            Err(CobsError::TooLong),
+            Err(CobsError::Truncated),
+            Ok(b"ok".to_vec())
+        ]
+    );
+}
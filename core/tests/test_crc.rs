@@ -0,0 +1,27 @@
+use tokio_serial_core::crc::*;
+
+const CHECK: &[u8] = b"123456789";
+
+#[test]
+fn catalogue_check_values() {
+    assert_eq!(CRC8_SMBUS.checksum(CHECK), 0xf4);
+    assert_eq!(CRC8_MAXIM.checksum(CHECK), 0xa1);
+    assert_eq!(CRC16_ARC.checksum(CHECK), 0xbb3d);
+    assert_eq!(CRC16_MODBUS.checksum(CHECK), 0x4b37);
+    assert_eq!(CRC16_XMODEM.checksum(CHECK), 0x31c3);
+    assert_eq!(CRC16_KERMIT.checksum(CHECK), 0x2189);
+    assert_eq!(CRC16_IBM_3740.checksum(CHECK), 0x29b1);
+    assert_eq!(CRC16_IBM_SDLC.checksum(CHECK), 0x906e);
+    assert_eq!(CRC16_MCRF4XX.checksum(CHECK), 0x6f91);
+    assert_eq!(CRC32_ISO_HDLC.checksum(CHECK), 0xcbf4_3926);
+}
+
+#[test]
+fn incremental_matches_one_shot() {
+    let mut digest = CRC32_ISO_HDLC.digest();
+    for chunk in CHECK.chunks(2) {
+        digest.update(chunk);
+    }
+    assert_eq!(digest.finish(), CRC32_ISO_HDLC.checksum(CHECK));
+    assert_eq!(CRC16_MODBUS.checksum(&[]), 0xffff);
+}
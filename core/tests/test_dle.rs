@@ -0,0 +1,72 @@
+use tokio_serial_core::dle::{Dle, DleError};
+use tokio_serial_core::{Deframer, Framer};
+
+fn frame<F: Framer>(framer: &mut F, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let _ = framer.frame(payload, |chunk| out.extend_from_slice(chunk));
+    out
+}
+
+fn deframe<D: Deframer>(deframer: &mut D, data: &[u8]) -> Vec<Result<Vec<u8>, D::Error>> {
+    let mut frames = Vec::new();
+    for &byte in data {
+        match deframer.push(byte) {
+            Ok(Some(frame)) => frames.push(Ok(frame.to_vec())),
+            Ok(None) => {}
+            Err(err) => frames.push(Err(err)),
+        }
+    }
+    frames
+}
+
+#[test]
+fn dle_bytes_are_doubled() {
+    let mut dle = Dle::new([0u8; 16]);
+    assert_eq!(
+        frame(&mut dle, b"\x01\x10\x02\x10"),
+        b"\x10\x02\x01\x10\x10\x02\x10\x10\x10\x03"
+    );
+    assert_eq!(frame(&mut dle, b""), b"\x10\x02\x10\x03");
+
+    // 3964R: the BCC covers the doubled DLE and the closing DLE ETX.
+    let mut dle = Dle::new([0u8; 16]).bcc(true);
+    assert_eq!(
+        frame(&mut dle, b"\x01\x10"),
+        b"\x10\x02\x01\x10\x10\x10\x03\x12"
+    );
+}
+
+#[test]
+fn frames_are_unescaped_and_noise_skipped() {
+    let mut dle = Dle::new(vec![0u8; 16]);
+    assert_eq!(
+        deframe(
+            &mut dle,
+            b"noise\x10\x02\x01\x10\x10\x02\x10\x03\x03\x10\x10\x10\x02ok\x10\x03"
+        ),
+        [Ok(b"\x01\x10\x02".to_vec()), Ok(b"ok".to_vec())]
+    );
+}
+
+#[test]
+fn broken_frames_resynchronize() {
+    let mut dle = Dle::new([0u8; 4]).bcc(true);
+    assert_eq!(
+        deframe(
+            &mut dle,
+            b"\x10\x02too long\x10\x03\x00\
+              \x10\x02bad\x10\x03\x00\
+              \x10\x02cut\x10\x02ok\x10\x03\x17\
+              \x10\x02x\x10\x05\
+              \x10\x02fine\x10\x03\x17"
+        ),
+        [
+            Err(DleError::TooLong),
+            Err(DleError::Bcc),
+            Err(DleError::Incomplete),
+            Ok(b"ok".to_vec()),
+            Err(DleError::InvalidEscape(5)),
+            Ok(b"fine".to_vec()),
+        ]
+    );
+}
@@ -0,0 +1,74 @@
+use tokio_serial_core::hdlc::{Fcs, Hdlc, HdlcError};
+use tokio_serial_core::{Deframer, Framer};
+
+fn frame<F: Framer>(framer: &mut F, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let _ = framer.frame(payload, |chunk| out.extend_from_slice(chunk));
+    out
+}
+
+fn deframe<D: Deframer>(deframer: &mut D, data: &[u8]) -> Vec<Result<Vec<u8>, D::Error>> {
+    let mut frames = Vec::new();
+    for &byte in data {
+        match deframer.push(byte) {
+            Ok(Some(frame)) => frames.push(Ok(frame.to_vec())),
+            Ok(None) => {}
+            Err(err) => frames.push(Err(err)),
+        }
+    }
+    frames
+}
+
+#[test]
+fn frames_carry_the_fcs_of_the_payload() {
+    // The check values of both CRCs, least significant byte first.
+    let mut hdlc = Hdlc::new([0u8; 32]);
+    assert_eq!(frame(&mut hdlc, b"123456789"), b"\x7e123456789\x6e\x90\x7e");
+    let mut hdlc = Hdlc::new([0u8; 32]).fcs(Fcs::Fcs32);
+    assert_eq!(
+        frame(&mut hdlc, b"123456789"),
+        b"\x7e123456789\x26\x39\xf4\xcb\x7e"
+    );
+    assert_eq!(
+        deframe(&mut hdlc, b"\x7e\x7e123456789\x26\x39\xf4\xcb\x7e"),
+        [Ok(b"123456789".to_vec())]
+    );
+}
+
+#[test]
+fn control_characters_are_escaped_per_the_accm() {
+    // LCP configure request header, as PPP sends it before negotiating the ACCM.
+    let mut ppp = Hdlc::new(vec![0u8; 64]).accm(0xffff_ffff);
+    let sent = frame(&mut ppp, b"\xff\x03\xc0\x21\x7d");
+    assert_eq!(sent, b"\x7e\xff\x7d\x23\xc0\x21\x7d\x5d\xf3\x87\x7e");
+    assert_eq!(
+        deframe(&mut ppp, &sent),
+        [Ok(b"\xff\x03\xc0\x21\x7d".to_vec())]
+    );
+
+    // Unescaped control characters are noise.
+    let mut noisy = sent.clone();
+    noisy.insert(3, 0x11);
+    assert_eq!(
+        deframe(&mut ppp, &noisy),
+        [Ok(b"\xff\x03\xc0\x21\x7d".to_vec())]
+    );
+}
+
+#[test]
+fn broken_frames_resynchronize() {
+    let mut hdlc = Hdlc::new([0u8; 8]);
+    assert_eq!(
+        deframe(
+            &mut hdlc,
+            b"\x7efar too long\x7e\x7e\x01\x7eabc\x25\x9f\x7eab\x7d\x7e\x7d\x5e\x81\x6a\x7e"
+        ),
+        [
+            Err(HdlcError::TooLong),
+            Err(HdlcError::TooShort),
+            Err(HdlcError::Fcs),
+            Err(HdlcError::Aborted),
+            Ok(b"\x7e".to_vec())
+        ]
+    );
+}
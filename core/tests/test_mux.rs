@@ -0,0 +1,70 @@
+use tokio_serial_core::mux::{self, MuxDeframer, MuxError, MuxFrame};
+
+fn frame(channel: u8, kind: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    mux::frame(channel, kind, payload, |chunk| out.extend_from_slice(chunk)).unwrap();
+    out
+}
+
+// Channel, kind and payload of the frames received
+type Received = (u8, u8, Vec<u8>);
+
+fn deframe(deframer: &mut MuxDeframer<Vec<u8>>, data: &[u8]) -> Vec<Result<Received, MuxError>> {
+    let mut frames = Vec::new();
+    for &byte in data {
+        match deframer.push(byte) {
+            Ok(Some(frame)) => frames.push(Ok((frame.channel, frame.kind, frame.payload.to_vec()))),
+            Ok(None) => {}
+            Err(err) => frames.push(Err(err)),
+        }
+    }
+    frames
+}
+
+#[test]
+fn frames_have_a_header_and_crc() {
+    let data = frame(3, mux::DATA, b"ab");
+    assert_eq!(&data[..7], b"\xa5\x03\x00\x02\x00ab");
+    assert_eq!(data.len(), 9);
+
+    let mut credit = Vec::new();
+    mux::frame_credit(1, 4096, |chunk| credit.extend_from_slice(chunk));
+    assert_eq!(&credit[..9], b"\xa5\x01\x01\x04\x00\x00\x10\x00\x00");
+}
+
+#[test]
+fn deframer_resynchronizes_after_errors() {
+    let mut deframer = MuxDeframer::new(vec![0u8; 4]);
+    let mut corrupted = frame(0, mux::DATA, b"xyz");
+    corrupted[6] ^= 1;
+
+    let mut data = b"noise".to_vec();
+    data.extend(corrupted);
+    data.extend(frame(0, mux::DATA, b"too long"));
+    data.extend(frame(7, mux::DATA, b""));
+    data.extend(frame(1, mux::CREDIT, &64u32.to_le_bytes()));
+    assert_eq!(
+        deframe(&mut deframer, &data),
+        [
+            Err(MuxError::Crc),
+            Err(MuxError::TooLong(8)),
+            Ok((7, mux::DATA, Vec::new())),
+            Ok((1, mux::CREDIT, 64u32.to_le_bytes().to_vec())),
+        ]
+    );
+}
+
+#[test]
+fn credit_frames_carry_a_count() {
+    let credit = MuxFrame {
+        channel: 0,
+        kind: mux::CREDIT,
+        payload: &[0x00, 0x01, 0x00, 0x00],
+    };
+    assert_eq!(credit.credit(), Some(256));
+    let data = MuxFrame {
+        kind: mux::DATA,
+        ..credit
+    };
+    assert_eq!(data.credit(), None);
+}
@@ -0,0 +1,55 @@
+use tokio_serial_core::slip::{Slip, SlipError};
+use tokio_serial_core::{Deframer, Framer};
+
+fn frame<F: Framer>(framer: &mut F, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let _ = framer.frame(payload, |chunk| out.extend_from_slice(chunk));
+    out
+}
+
+fn deframe<D: Deframer>(deframer: &mut D, data: &[u8]) -> Vec<Result<Vec<u8>, D::Error>> {
+    let mut frames = Vec::new();
+    for &byte in data {
+        match deframer.push(byte) {
+            Ok(Some(frame)) => frames.push(Ok(frame.to_vec())),
+            Ok(None) => {}
+            Err(err) => frames.push(Err(err)),
+        }
+    }
+    frames
+}
+
+#[test]
+fn bytes_are_escaped_as_in_rfc_1055() {
+    let mut slip = Slip::new([0u8; 16]);
+    assert_eq!(
+        frame(&mut slip, b"\x01\xc0\xdb\x02"),
+        b"\xc0\x01\xdb\xdc\xdb\xdd\x02\xc0"
+    );
+    assert_eq!(frame(&mut slip, b""), b"\xc0\xc0");
+
+    let mut slip = Slip::new([0u8; 16]).leading_end(false);
+    assert_eq!(frame(&mut slip, b"\xdb"), b"\xdb\xdd\xc0");
+}
+
+#[test]
+fn frames_are_unescaped_and_empty_ones_skipped() {
+    let mut slip = Slip::new(vec![0u8; 16]);
+    assert_eq!(
+        deframe(&mut slip, b"\xc0\x01\xdb\xdc\xdb\xdd\x02\xc0\xc0\xc0ok\xc0"),
+        [Ok(b"\x01\xc0\xdb\x02".to_vec()), Ok(b"ok".to_vec())]
+    );
+}
+
+#[test]
+fn broken_frames_resynchronize() {
+    let mut slip = Slip::new([0u8; 4]);
+    assert_eq!(
+        deframe(&mut slip, b"\xc0too long\xc0bad\xdb\x00\xc0fine\xc0"),
+        [
+            Err(SlipError::TooLong),
+            Err(SlipError::InvalidEscape(0)),
+            Ok(b"fine".to_vec())
+        ]
+    );
+}
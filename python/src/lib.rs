@@ -0,0 +1,534 @@
+//! Python bindings for `SerialStream`
+//!
+//! The bindings expose an asyncio-compatible `SerialStream` class to Python.  Every
+//! I/O method returns an awaitable that is driven by the tokio runtime managed by
+//! `pyo3-async-runtimes`.
+//!
+//! The port itself is owned by a single task running on that runtime.  Python
+//! objects only ever talk to it through a command channel, so a pending `read()`
+//! never blocks a concurrent `write()` or a settings change issued from another
+//! coroutine.
+//!
+//! `events()` returns an asynchronous iterator of the changes of the modem control
+//! lines and of the breaks received.
+//!
+//! ```python
+//! import asyncio
+//! import tokio_serial
+//!
+//! async def main():
+//!     port = await tokio_serial.open("/dev/ttyUSB0", 115200)
+//!     await port.write(b"AT\r\n")
+//!     print(await port.read(64))
+//!     async for event in port.events():
+//!         print(event.kind, event.level, event.count)
+//!
+//! asyncio.run(main())
+//! ```
+use tokio_serial::{SerialPort, SerialPortBuilderExt, SerialStream};
+
+use futures::channel::{mpsc, oneshot};
+use futures::future::poll_fn;
+use futures::{Stream, StreamExt};
+use pyo3::exceptions::{PyRuntimeError, PyStopAsyncIteration, PyValueError};
+use pyo3::prelude::*;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+type Reply<T> = oneshot::Sender<io::Result<T>>;
+// Runs on the port and answers by itself.
+type Configure = Box<dyn FnOnce(&mut SerialStream) + Send>;
+
+enum Command {
+    Read(usize, Reply<Vec<u8>>),
+    Write(Vec<u8>, Reply<()>),
+    Flush(Reply<()>),
+    Configure(Configure),
+    Watch(Duration, mpsc::UnboundedSender<PyLineEvent>),
+}
+
+/// A change of a modem control line, or breaks received.
+#[pyclass(name = "LineEvent", frozen, get_all)]
+#[derive(Debug)]
+struct PyLineEvent {
+    /// `"cts"`, `"dsr"`, `"ri"` or `"cd"` for the lines, `"break"` for breaks.
+    kind: &'static str,
+    /// The new level of the line, `None` for breaks.
+    level: Option<bool>,
+    /// The number of breaks received in a row, 0 for the lines.
+    count: u32,
+}
+
+#[pymethods]
+impl PyLineEvent {
+    fn __repr__(&self) -> String {
+        match self.level {
+            Some(level) => format!("LineEvent({}, {})", self.kind, level),
+            None => format!("LineEvent({}, {})", self.kind, self.count),
+        }
+    }
+}
+
+const LINES: [&str; 4] = ["cts", "dsr", "ri", "cd"];
+
+/// What the driver watches for `events()`.
+struct Watch {
+    interval: tokio::time::Interval,
+    // The last levels of `LINES`, `None` if the port cannot read them.
+    lines: Option<[bool; 4]>,
+    breaks: Option<tokio_serial::BreakEvents>,
+    events: mpsc::UnboundedSender<PyLineEvent>,
+}
+
+impl Watch {
+    fn new(
+        port: &mut SerialStream,
+        interval: Duration,
+        events: mpsc::UnboundedSender<PyLineEvent>,
+    ) -> Self {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        Self {
+            interval,
+            lines: read_lines(port).ok(),
+            breaks: port.break_events().ok(),
+            events,
+        }
+    }
+
+    fn poll_events(&mut self, port: &mut SerialStream, cx: &mut Context<'_>) {
+        if let Some(breaks) = &mut self.breaks {
+            loop {
+                match Pin::new(&mut *breaks).poll_next(cx) {
+                    Poll::Ready(Some(Ok(count))) => {
+                        let _ = self.events.unbounded_send(PyLineEvent {
+                            kind: "break",
+                            level: None,
+                            count,
+                        });
+                    }
+                    Poll::Ready(Some(Err(_))) | Poll::Ready(None) => {
+                        self.breaks = None;
+                        break;
+                    }
+                    Poll::Pending => break,
+                }
+            }
+        }
+
+        if let Some(previous) = self.lines {
+            while self.interval.poll_tick(cx).is_ready() {
+                let levels = match read_lines(port) {
+                    Ok(levels) => levels,
+                    Err(_) => {
+                        self.lines = None;
+                        break;
+                    }
+                };
+                for ((kind, old), new) in LINES.iter().zip(previous).zip(levels) {
+                    if old != new {
+                        let _ = self.events.unbounded_send(PyLineEvent {
+                            kind,
+                            level: Some(new),
+                            count: 0,
+                        });
+                    }
+                }
+                self.lines = Some(levels);
+            }
+        }
+    }
+}
+
+fn read_lines(port: &mut SerialStream) -> tokio_serial::Result<[bool; 4]> {
+    Ok([
+        port.read_clear_to_send()?,
+        port.read_data_set_ready()?,
+        port.read_ring_indicator()?,
+        port.read_carrier_detect()?,
+    ])
+}
+
+/// State of the task owning the port.
+struct Driver {
+    port: SerialStream,
+    commands: mpsc::UnboundedReceiver<Command>,
+    reads: VecDeque<(usize, Reply<Vec<u8>>)>,
+    writes: VecDeque<(Vec<u8>, usize, Reply<()>)>,
+    flushes: VecDeque<Reply<()>>,
+    watch: Option<Watch>,
+    closed: bool,
+}
+
+impl Driver {
+    fn poll_run(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        while !self.closed {
+            match Pin::new(&mut self.commands).poll_next(cx) {
+                Poll::Ready(Some(Command::Read(n, reply))) => self.reads.push_back((n, reply)),
+                Poll::Ready(Some(Command::Write(data, reply))) => {
+                    self.writes.push_back((data, 0, reply))
+                }
+                Poll::Ready(Some(Command::Flush(reply))) => self.flushes.push_back(reply),
+                Poll::Ready(Some(Command::Configure(configure))) => configure(&mut self.port),
+                Poll::Ready(Some(Command::Watch(interval, events))) => {
+                    self.watch = Some(Watch::new(&mut self.port, interval, events));
+                }
+                Poll::Ready(None) => self.closed = true,
+                Poll::Pending => break,
+            }
+        }
+
+        if self.closed {
+            // Nobody is left to issue more writes, pending reads could wait forever.
+            self.reads.clear();
+            self.watch = None;
+        }
+
+        self.poll_reads(cx);
+        self.poll_writes(cx);
+        if let Some(watch) = &mut self.watch {
+            if watch.events.is_closed() {
+                self.watch = None;
+            } else {
+                watch.poll_events(&mut self.port, cx);
+            }
+        }
+
+        if self.closed && self.writes.is_empty() && self.flushes.is_empty() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn poll_reads(&mut self, cx: &mut Context<'_>) {
+        while let Some((n, _)) = self.reads.front() {
+            let mut data = vec![0u8; *n];
+            let mut buf = ReadBuf::new(&mut data);
+            let result = match Pin::new(&mut self.port).poll_read(cx, &mut buf) {
+                Poll::Ready(result) => result.map(|()| buf.filled().len()),
+                Poll::Pending => return,
+            };
+            let (_, reply) = self.reads.pop_front().unwrap();
+            let _ = reply.send(result.map(|len| {
+                data.truncate(len);
+                data
+            }));
+        }
+    }
+
+    fn poll_writes(&mut self, cx: &mut Context<'_>) {
+        while let Some((data, written, _)) = self.writes.front_mut() {
+            match Pin::new(&mut self.port).poll_write(cx, &data[*written..]) {
+                Poll::Ready(Ok(n)) => {
+                    *written += n;
+                    if *written < data.len() {
+                        continue;
+                    }
+                    let (_, _, reply) = self.writes.pop_front().unwrap();
+                    let _ = reply.send(Ok(()));
+                }
+                Poll::Ready(Err(err)) => {
+                    let (_, _, reply) = self.writes.pop_front().unwrap();
+                    let _ = reply.send(Err(err));
+                }
+                Poll::Pending => return,
+            }
+        }
+
+        // Flushes are only answered once every write queued before them went out.
+        while let Some(reply) = self.flushes.pop_front() {
+            match Pin::new(&mut self.port).poll_flush(cx) {
+                Poll::Ready(result) => {
+                    let _ = reply.send(result);
+                }
+                Poll::Pending => {
+                    self.flushes.push_front(reply);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// An asyncio-compatible serial port.
+///
+/// Instances are created with the module level `open()` coroutine.
+#[pyclass(name = "SerialStream")]
+struct PySerialStream {
+    name: Option<String>,
+    commands: Option<mpsc::UnboundedSender<Command>>,
+}
+
+impl PySerialStream {
+    fn send<T, F>(&self, command: F) -> PyResult<oneshot::Receiver<io::Result<T>>>
+    where
+        F: FnOnce(Reply<T>) -> Command,
+    {
+        let commands = self
+            .commands
+            .as_ref()
+            .ok_or_else(|| PyRuntimeError::new_err("serial port is closed"))?;
+        let (tx, rx) = oneshot::channel();
+        commands
+            .unbounded_send(command(tx))
+            .map_err(|_| PyRuntimeError::new_err("serial port is closed"))?;
+        Ok(rx)
+    }
+
+    fn configure<'py, T, F>(&self, py: Python<'py>, configure: F) -> PyResult<Bound<'py, PyAny>>
+    where
+        T: for<'a> IntoPyObject<'a> + Send + 'static,
+        F: FnOnce(&mut SerialStream) -> tokio_serial::Result<T> + Send + 'static,
+    {
+        let rx = self.send(|reply| {
+            Command::Configure(Box::new(move |port| {
+                let _ = reply.send(configure(port).map_err(io::Error::from));
+            }))
+        })?;
+        pyo3_async_runtimes::tokio::future_into_py(py, wait(rx))
+    }
+
+    fn set<'py, F>(&self, py: Python<'py>, set: F) -> PyResult<Bound<'py, PyAny>>
+    where
+        F: FnOnce(&mut SerialStream) -> tokio_serial::Result<()> + Send + 'static,
+    {
+        // `()` converts to an empty tuple, setters resolve to `None` instead.
+        self.configure(py, move |port| set(port).map(|()| None::<()>))
+    }
+}
+
+async fn wait<T>(rx: oneshot::Receiver<io::Result<T>>) -> PyResult<T> {
+    match rx.await {
+        Ok(result) => result.map_err(PyErr::from),
+        Err(_) => Err(PyRuntimeError::new_err("serial port is closed")),
+    }
+}
+
+#[pymethods]
+impl PySerialStream {
+    /// The name of the port, if it has one.
+    #[getter]
+    fn name(&self) -> Option<String> {
+        self.name.clone()
+    }
+
+    /// Read at most `n` bytes.  Resolves once at least one byte is available.
+    fn read<'py>(&self, py: Python<'py>, n: usize) -> PyResult<Bound<'py, PyAny>> {
+        let rx = self.send(|reply| Command::Read(n, reply))?;
+        pyo3_async_runtimes::tokio::future_into_py(py, wait(rx))
+    }
+
+    /// Write all of `data`.  Writes are queued in the order they were issued.
+    fn write<'py>(&self, py: Python<'py>, data: Vec<u8>) -> PyResult<Bound<'py, PyAny>> {
+        let rx = self.send(|reply| Command::Write(data, reply))?;
+        pyo3_async_runtimes::tokio::future_into_py(py, wait(rx))
+    }
+
+    /// Flush any buffered output once all pending writes completed.
+    fn flush<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let rx = self.send(Command::Flush)?;
+        pyo3_async_runtimes::tokio::future_into_py(py, wait(rx))
+    }
+
+    /// Resolves to the current baud rate.
+    fn baud_rate<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.configure(py, |port| port.baud_rate())
+    }
+
+    /// Change the baud rate of the open port.
+    fn set_baud_rate<'py>(&self, py: Python<'py>, baud_rate: u32) -> PyResult<Bound<'py, PyAny>> {
+        self.set(py, move |port| port.set_baud_rate(baud_rate))
+    }
+
+    /// Set the level of the RTS control line.
+    fn write_request_to_send<'py>(
+        &self,
+        py: Python<'py>,
+        level: bool,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        self.set(py, move |port| port.write_request_to_send(level))
+    }
+
+    /// Set the level of the DTR control line.
+    fn write_data_terminal_ready<'py>(
+        &self,
+        py: Python<'py>,
+        level: bool,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        self.set(py, move |port| port.write_data_terminal_ready(level))
+    }
+
+    /// Resolves to the number of bytes waiting in the input buffer.
+    fn bytes_to_read<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.configure(py, |port| port.bytes_to_read())
+    }
+
+    /// Resolves to the number of bytes waiting in the output buffer.
+    fn bytes_to_write<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.configure(py, |port| port.bytes_to_write())
+    }
+
+    /// Returns an asynchronous iterator of `LineEvent`s.
+    ///
+    /// The modem control lines are checked every `interval` seconds.  Breaks are
+    /// noticed as the data is read on Unix.  Ports which cannot report either, such
+    /// as pseudo terminals for the lines, only miss those events.  Only the last
+    /// iterator gets the events, and the iteration stops once the port is closed.
+    #[pyo3(signature = (interval = 0.05))]
+    fn events(&self, interval: f64) -> PyResult<PyLineEvents> {
+        if !(interval > 0.0 && interval.is_finite()) {
+            return Err(PyValueError::new_err(
+                "interval must be a positive number of seconds",
+            ));
+        }
+        let (tx, rx) = mpsc::unbounded();
+        let commands = self
+            .commands
+            .as_ref()
+            .ok_or_else(|| PyRuntimeError::new_err("serial port is closed"))?;
+        commands
+            .unbounded_send(Command::Watch(Duration::from_secs_f64(interval), tx))
+            .map_err(|_| PyRuntimeError::new_err("serial port is closed"))?;
+        Ok(PyLineEvents {
+            events: Arc::new(futures::lock::Mutex::new(rx)),
+        })
+    }
+
+    /// Close the port once all pending writes completed.
+    ///
+    /// Pending reads fail as soon as the port is closed.
+    fn close(&mut self) {
+        self.commands = None;
+    }
+}
+
+/// The asynchronous iterator returned by `SerialStream.events()`.
+#[pyclass(name = "LineEvents")]
+struct PyLineEvents {
+    events: Arc<futures::lock::Mutex<mpsc::UnboundedReceiver<PyLineEvent>>>,
+}
+
+#[pymethods]
+impl PyLineEvents {
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __anext__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let events = self.events.clone();
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            match events.lock().await.next().await {
+                Some(event) => Ok(event),
+                None => Err(PyStopAsyncIteration::new_err(())),
+            }
+        })
+    }
+}
+
+fn parse_data_bits(data_bits: u8) -> PyResult<tokio_serial::DataBits> {
+    match data_bits {
+        5 => Ok(tokio_serial::DataBits::Five),
+        6 => Ok(tokio_serial::DataBits::Six),
+        7 => Ok(tokio_serial::DataBits::Seven),
+        8 => Ok(tokio_serial::DataBits::Eight),
+        _ => Err(PyValueError::new_err("data_bits must be 5, 6, 7 or 8")),
+    }
+}
+
+fn parse_parity(parity: &str) -> PyResult<tokio_serial::Parity> {
+    match parity {
+        "none" | "N" => Ok(tokio_serial::Parity::None),
+        "odd" | "O" => Ok(tokio_serial::Parity::Odd),
+        "even" | "E" => Ok(tokio_serial::Parity::Even),
+        _ => Err(PyValueError::new_err(
+            "parity must be 'none', 'odd' or 'even'",
+        )),
+    }
+}
+
+fn parse_stop_bits(stop_bits: u8) -> PyResult<tokio_serial::StopBits> {
+    match stop_bits {
+        1 => Ok(tokio_serial::StopBits::One),
+        2 => Ok(tokio_serial::StopBits::Two),
+        _ => Err(PyValueError::new_err("stop_bits must be 1 or 2")),
+    }
+}
+
+fn parse_flow_control(flow_control: &str) -> PyResult<tokio_serial::FlowControl> {
+    match flow_control {
+        "none" => Ok(tokio_serial::FlowControl::None),
+        "software" => Ok(tokio_serial::FlowControl::Software),
+        "hardware" => Ok(tokio_serial::FlowControl::Hardware),
+        _ => Err(PyValueError::new_err(
+            "flow_control must be 'none', 'software' or 'hardware'",
+        )),
+    }
+}
+
+/// Open a serial port, resolving to a `SerialStream`.
+#[pyfunction]
+#[pyo3(signature = (path, baud_rate, data_bits = 8, parity = "none", stop_bits = 1, flow_control = "none"))]
+fn open<'py>(
+    py: Python<'py>,
+    path: String,
+    baud_rate: u32,
+    data_bits: u8,
+    parity: &str,
+    stop_bits: u8,
+    flow_control: &str,
+) -> PyResult<Bound<'py, PyAny>> {
+    let builder = tokio_serial::new(path, baud_rate)
+        .data_bits(parse_data_bits(data_bits)?)
+        .parity(parse_parity(parity)?)
+        .stop_bits(parse_stop_bits(stop_bits)?)
+        .flow_control(parse_flow_control(flow_control)?);
+
+    pyo3_async_runtimes::tokio::future_into_py(py, async move {
+        // Opened on the blocking pool, the event loop keeps running meanwhile.
+        let port = builder.open_async().await.map_err(io::Error::from)?;
+        let name = port.name();
+        let (commands, rx) = mpsc::unbounded();
+
+        let mut driver = Driver {
+            port,
+            commands: rx,
+            reads: VecDeque::new(),
+            writes: VecDeque::new(),
+            flushes: VecDeque::new(),
+            watch: None,
+            closed: false,
+        };
+        tokio::spawn(poll_fn(move |cx| driver.poll_run(cx)));
+
+        Ok(PySerialStream {
+            name,
+            commands: Some(commands),
+        })
+    })
+}
+
+/// Returns the names of the serial ports found on the system.
+#[pyfunction]
+fn available_ports() -> PyResult<Vec<String>> {
+    let ports = tokio_serial::available_ports().map_err(io::Error::from)?;
+    Ok(ports.into_iter().map(|info| info.port_name).collect())
+}
+
+/// The `tokio_serial` Python module.
+#[pymodule]
+#[pyo3(name = "tokio_serial")]
+fn tokio_serial_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PySerialStream>()?;
+    m.add_class::<PyLineEvent>()?;
+    m.add_class::<PyLineEvents>()?;
+    m.add_function(wrap_pyfunction!(open, m)?)?;
+    m.add_function(wrap_pyfunction!(available_ports, m)?)?;
+    Ok(())
+}
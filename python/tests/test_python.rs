@@ -0,0 +1,60 @@
+#![cfg(unix)]
+
+use std::path::PathBuf;
+use std::process::Command;
+
+const SCRIPT: &str = r#"
+import asyncio
+import os
+import tokio_serial
+
+async def main():
+    master, slave = os.openpty()
+    port = await tokio_serial.open(os.ttyname(slave), 9600)
+
+    assert await port.set_baud_rate(19200) is None
+    assert await port.baud_rate() == 19200
+
+    await port.write(b"ping")
+    await port.flush()
+    assert os.read(master, 4) == b"ping"
+    os.write(master, b"p\xffng")
+    assert await port.read(4) == b"p\xffng"
+
+    events = port.events()
+    port.close()
+    async for event in events:
+        raise AssertionError(event)
+
+asyncio.run(main())
+# The runtime threads may still be releasing the GIL, do not race the finalization.
+os._exit(0)
+"#;
+
+// The extension module built next to the test, under the name Python imports.
+fn module_dir() -> PathBuf {
+    let test = std::env::current_exe().unwrap();
+    let library = test.with_file_name("libtokio_serial_python.so");
+    let dir = std::env::temp_dir().join(format!("tokio-serial-python-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy(library, dir.join("tokio_serial.so")).unwrap();
+    dir
+}
+
+#[test]
+fn python_module_drives_a_pty() {
+    let dir = module_dir();
+    let output = Command::new("python3")
+        .arg("-c")
+        .arg(SCRIPT)
+        .env("PYTHONPATH", &dir)
+        .output()
+        .expect("unable to run python3");
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        output.status.success(),
+        "{}: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,185 @@
+//! Finding the baud rate of a device
+//!
+//! [`SerialStream::detect_baud`](crate::SerialStream::detect_baud) tries candidate
+//! baud rates in turn, listening to the traffic at each of them and judging it with
+//! an [`AutobaudProbe`]:
+//!
+//! * [`pattern`](AutobaudProbe::pattern): the data matches a [`Signature`], e.g. a
+//!   prompt or the reply to a command sent at each rate.
+//! * [`printable`](AutobaudProbe::printable): most of the data is printable text,
+//!   which suits consoles and NMEA devices.
+//! * [`error_rate`](AutobaudProbe::error_rate): few characters are received with
+//!   framing or parity errors, any traffic will do.
+use crate::identify::Signature;
+use crate::SerialStream;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+enum Criterion {
+    Pattern(Signature),
+    Printable(f64),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    ErrorRate(f64),
+}
+
+/// How to recognize the right baud rate
+#[derive(Debug, Clone)]
+pub struct AutobaudProbe {
+    criterion: Criterion,
+    command: Option<Vec<u8>>,
+    listen: Duration,
+    min_bytes: usize,
+}
+
+impl AutobaudProbe {
+    fn new(criterion: Criterion) -> Self {
+        Self {
+            criterion,
+            command: None,
+            listen: Duration::from_millis(500),
+            min_bytes: 8,
+        }
+    }
+
+    /// The right rate is the one where the received data matches `signature`.
+    pub fn pattern(signature: Signature) -> Self {
+        Self::new(Criterion::Pattern(signature))
+    }
+
+    /// The right rate is the one where at least `ratio` of the received bytes are
+    /// printable ASCII or whitespace.
+    pub fn printable(ratio: f64) -> Self {
+        Self::new(Criterion::Printable(ratio))
+    }
+
+    /// The right rate is the one where at most `ratio` of the received characters
+    /// have framing or parity errors.
+    ///
+    /// Errors are read from the [counters](crate::SerialStream::counters) of the
+    /// port, which not all drivers keep.  Windows only reports whether errors
+    /// happened, not how many, so this is not available there.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn error_rate(ratio: f64) -> Self {
+        Self::new(Criterion::ErrorRate(ratio))
+    }
+
+    /// Send `command` after switching to each rate, for devices which only talk
+    /// when spoken to.
+    pub fn command<B: Into<Vec<u8>>>(mut self, command: B) -> Self {
+        self.command = Some(command.into());
+        self
+    }
+
+    /// Set how long to listen at each rate, 500ms by default
+    pub fn listen(mut self, listen: Duration) -> Self {
+        self.listen = listen;
+        self
+    }
+
+    /// Set how many bytes must be received to judge a rate, 8 by default
+    ///
+    /// `pattern` probes succeed as soon as the signature matches regardless.
+    pub fn min_bytes(mut self, min_bytes: usize) -> Self {
+        self.min_bytes = min_bytes;
+        self
+    }
+}
+
+fn printable(data: &[u8]) -> usize {
+    data.iter()
+        .filter(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+        .count()
+}
+
+pub(crate) async fn detect(
+    port: &mut SerialStream,
+    candidates: &[u32],
+    probe: &AutobaudProbe,
+) -> crate::Result<Option<u32>> {
+    use crate::SerialPort;
+
+    let mut port = RestoreRate {
+        original: port.baud_rate()?,
+        port,
+        settled: false,
+    };
+    for &baud_rate in candidates {
+        port.port.set_baud_rate(baud_rate)?;
+        port.port.clear_buffers(crate::ClearBuffer::Input).await?;
+        if try_rate(port.port, probe).await? {
+            log::debug!("detected baud rate {}", baud_rate);
+            port.settled = true;
+            return Ok(Some(baud_rate));
+        }
+    }
+    port.settled = true;
+    port.port.set_baud_rate(port.original)?;
+    Ok(None)
+}
+
+// Puts the original rate back unless one was detected, on errors and when the
+// detection is cancelled too.
+struct RestoreRate<'a> {
+    port: &'a mut SerialStream,
+    original: u32,
+    settled: bool,
+}
+
+impl Drop for RestoreRate<'_> {
+    fn drop(&mut self) {
+        use crate::SerialPort;
+
+        if !self.settled {
+            if let Err(e) = self.port.set_baud_rate(self.original) {
+                log::warn!("unable to restore baud rate {}: {}", self.original, e);
+            }
+        }
+    }
+}
+
+async fn try_rate(port: &mut SerialStream, probe: &AutobaudProbe) -> crate::Result<bool> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let counters_before = match probe.criterion {
+        Criterion::ErrorRate(_) => port.counters()?,
+        _ => Default::default(),
+    };
+
+    if let Some(command) = &probe.command {
+        port.write_all(command).await?;
+    }
+
+    let mut data = Vec::new();
+    let mut buf = [0u8; 256];
+    let deadline = tokio::time::Instant::now() + probe.listen;
+    loop {
+        if let Criterion::Pattern(signature) = &probe.criterion {
+            if signature.matches(&data) {
+                return Ok(true);
+            }
+        }
+        let n = match tokio::time::timeout_at(deadline, AsyncReadExt::read(port, &mut buf)).await {
+            Ok(read) => read?,
+            Err(_) => break,
+        };
+        if n == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..n]);
+    }
+
+    if data.len() < probe.min_bytes.max(1) {
+        return Ok(false);
+    }
+    Ok(match probe.criterion {
+        Criterion::Pattern(_) => false,
+        Criterion::Printable(ratio) => printable(&data) as f64 >= ratio * data.len() as f64,
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        Criterion::ErrorRate(ratio) => {
+            let errors = port.counters()?.since(&counters_before).errors();
+            f64::from(errors) <= ratio * data.len() as f64
+        }
+    })
+}
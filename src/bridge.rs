@@ -0,0 +1,374 @@
+//! Serving a serial port over TCP
+//!
+//! A [`BridgeServer`] makes a local port usable from other machines, like
+//! `ser2net` does: what a TCP client sends is written to the port, what the port
+//! receives is sent to the client.  With [`serve_rfc2217`](BridgeServer::serve_rfc2217)
+//! clients also control the line settings and the modem lines of the port.
+//! For links where latency matters more than reliability, [`udp`] serves it over
+//! UDP.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use tokio_serial::bridge::{BridgeServer, ClientPolicy};
+//!
+//! # async fn example() -> std::io::Result<()> {
+//! let port = tokio_serial::SerialStream::open(&tokio_serial::new("/dev/ttyUSB0", 115_200))?;
+//! let mut server = BridgeServer::bind("0.0.0.0:2000").await?;
+//! server.set_policy(ClientPolicy::Takeover);
+//! server.set_idle_timeout(Some(Duration::from_secs(600)));
+//! server.serve(port).await
+//! # }
+//! ```
+use crate::rfc2217::ServerSession;
+use crate::SerialPort;
+
+use futures::future::{self, BoxFuture, Either};
+use futures::ready;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::borrow::Cow;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+pub mod udp;
+
+const BUFFER_SIZE: usize = 4096;
+// Chunks read from the port waiting to be sent to a client, the port is not
+// read further while a client has that many pending.
+const CLIENT_QUEUE: usize = 16;
+
+/// What a [`BridgeServer`] does with clients connecting while another is connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClientPolicy {
+    /// Refuse the new client
+    #[default]
+    Exclusive,
+    /// Disconnect the connected client in favor of the new one
+    Takeover,
+    /// Accept the new client as well
+    ///
+    /// What the port receives goes to every client, what any client sends is
+    /// written to the port.
+    Shared,
+}
+
+/// A TCP server giving its clients access to a serial port
+///
+/// Data is passed through as is, with no protocol of its own.  The port is read
+/// as fast as the slowest client accepts the data, the OS buffers of the port hold
+/// what arrives meanwhile.  Data the port receives while no client is connected is
+/// discarded.
+#[derive(Debug)]
+pub struct BridgeServer {
+    listener: TcpListener,
+    policy: ClientPolicy,
+    idle_timeout: Option<Duration>,
+}
+
+// Where the data the port receives is queued for a client
+type Queue = mpsc::Sender<Arc<[u8]>>;
+
+struct Shared<S> {
+    clients: Mutex<Vec<(u64, Queue)>>,
+    port: Mutex<S>,
+    // Held while writing what a client sent to the port
+    writing: tokio::sync::Mutex<()>,
+}
+
+impl BridgeServer {
+    /// Listen on `addr`.
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(Self::from_listener(TcpListener::bind(addr).await?))
+    }
+
+    /// Accept the clients of `listener`.
+    pub fn from_listener(listener: TcpListener) -> Self {
+        Self {
+            listener,
+            policy: ClientPolicy::default(),
+            idle_timeout: None,
+        }
+    }
+
+    /// Returns the address the server listens on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Set what to do with clients connecting while another is connected,
+    /// [`Exclusive`](ClientPolicy::Exclusive) by default.
+    pub fn set_policy(&mut self, policy: ClientPolicy) {
+        self.policy = policy;
+    }
+
+    /// Disconnect clients after `timeout` without data in either direction, never by
+    /// default.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+    }
+
+    /// Pass the data between `port` and the clients.
+    ///
+    /// Runs until the port reaches end of file or fails, or accepting clients
+    /// fails.  Dropping the future disconnects all the clients.
+    pub async fn serve<S>(self, port: S) -> io::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        self.run(port, || Box::new(Raw)).await
+    }
+
+    /// Pass the data between `port` and the clients, speaking RFC 2217 with them.
+    ///
+    /// Clients, such as an [`Rfc2217Stream`](crate::rfc2217::Rfc2217Stream) or
+    /// pyserial's `rfc2217://` ports, change the settings and the control lines of
+    /// `port` and are notified when its modem lines change.  Settings the port
+    /// rejects are logged, the client is told the settings kept.
+    ///
+    /// Runs until the port reaches end of file or fails, or accepting clients
+    /// fails.  Dropping the future disconnects all the clients.
+    pub async fn serve_rfc2217<S>(self, port: S) -> io::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + SerialPort + Unpin + Send + 'static,
+    {
+        self.run(port, || Box::new(ServerSession::new())).await
+    }
+
+    async fn run<S, F>(self, port: S, session: F) -> io::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        F: Fn() -> Box<dyn Session<S>>,
+    {
+        let shared = Arc::new(Shared {
+            clients: Mutex::new(Vec::new()),
+            port: Mutex::new(port),
+            writing: tokio::sync::Mutex::new(()),
+        });
+        let (spawn, mut spawned) = mpsc::unbounded_channel::<BoxFuture<'static, ()>>();
+
+        let accept = async {
+            let ids = AtomicU64::new(0);
+            loop {
+                let (stream, addr) = match self.listener.accept().await {
+                    Ok(client) => client,
+                    Err(e) => return Err::<(), _>(e),
+                };
+                let mut clients = shared.clients.lock().unwrap();
+                if !clients.is_empty() {
+                    match self.policy {
+                        ClientPolicy::Exclusive => {
+                            log::info!("refusing {}, the port is in use", addr);
+                            continue;
+                        }
+                        ClientPolicy::Takeover => {
+                            log::info!("{} takes the port over", addr);
+                            // The clients stop once their queue is dropped.
+                            clients.clear();
+                        }
+                        ClientPolicy::Shared => {}
+                    }
+                }
+                log::info!("{} connected", addr);
+                let id = ids.fetch_add(1, Ordering::Relaxed);
+                let (queue, chunks) = mpsc::channel(CLIENT_QUEUE);
+                clients.push((id, queue));
+                let client = client(stream, chunks, shared.clone(), session(), self.idle_timeout);
+                let shared = shared.clone();
+                let _ = spawn.send(Box::pin(async move {
+                    if let Err(e) = client.await {
+                        log::info!("{}: {}", addr, e);
+                    }
+                    log::info!("{} disconnected", addr);
+                    shared.clients.lock().unwrap().retain(|(i, _)| *i != id);
+                }));
+            }
+        };
+
+        // Drive the clients without spawning tasks, so they go away with this future.
+        let clients = async {
+            let mut running = FuturesUnordered::new();
+            future::poll_fn(|cx| {
+                while let Poll::Ready(Some(client)) = spawned.poll_recv(cx) {
+                    running.push(client);
+                }
+                while let Poll::Ready(Some(())) = running.poll_next_unpin(cx) {}
+                Poll::<io::Result<()>>::Pending
+            })
+            .await
+        };
+
+        let pump = async {
+            let mut buf = vec![0u8; BUFFER_SIZE];
+            loop {
+                let n = future::poll_fn(|cx| {
+                    let mut read = ReadBuf::new(&mut buf);
+                    let mut port = shared.port.lock().unwrap();
+                    ready!(Pin::new(&mut *port).poll_read(cx, &mut read))?;
+                    Poll::Ready(io::Result::Ok(read.filled().len()))
+                })
+                .await?;
+                if n == 0 {
+                    log::debug!("end of file on the port");
+                    return Ok(());
+                }
+                let chunk: Arc<[u8]> = buf[..n].into();
+                let queues: Vec<_> = shared
+                    .clients
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|(_, queue)| queue.clone())
+                    .collect();
+                for queue in queues {
+                    // Fails if the client is gone meanwhile.
+                    let _ = queue.send(chunk.clone()).await;
+                }
+            }
+        };
+
+        futures::pin_mut!(accept, clients, pump);
+        match future::select(pump, future::try_join(accept, clients)).await {
+            Either::Left((result, _)) => result,
+            Either::Right((result, _)) => result.map(|((), ())| ()),
+        }
+    }
+}
+
+/// The protocol spoken with a client
+pub(crate) trait Session<S>: Send {
+    /// Returns what to send to the client once it is connected.
+    fn greeting(&mut self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Split what the client sent into data for the port and answers to the client.
+    fn client_bytes(
+        &mut self,
+        bytes: &[u8],
+        port: &Mutex<S>,
+        data: &mut Vec<u8>,
+        reply: &mut Vec<u8>,
+    );
+
+    /// Returns what to send to the client for `data` received by the port.
+    fn port_bytes<'a>(&mut self, data: &'a [u8]) -> Cow<'a, [u8]>;
+
+    /// Returns how often to call [`poll`](Session::poll), if at all.
+    fn poll_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Check the port, appending news for the client to `reply`.
+    fn poll(&mut self, _port: &Mutex<S>, _reply: &mut Vec<u8>) {}
+}
+
+// Data passed through as is
+struct Raw;
+
+impl<S> Session<S> for Raw {
+    fn client_bytes(&mut self, bytes: &[u8], _: &Mutex<S>, data: &mut Vec<u8>, _: &mut Vec<u8>) {
+        data.extend_from_slice(bytes);
+    }
+
+    fn port_bytes<'a>(&mut self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        Cow::Borrowed(data)
+    }
+}
+
+enum Event {
+    Received(usize),
+    ToSend(Option<Arc<[u8]>>),
+    Timer,
+}
+
+async fn client<S: AsyncWrite + Unpin>(
+    stream: TcpStream,
+    mut chunks: mpsc::Receiver<Arc<[u8]>>,
+    shared: Arc<Shared<S>>,
+    mut session: Box<dyn Session<S>>,
+    idle_timeout: Option<Duration>,
+) -> io::Result<()> {
+    let _ = stream.set_nodelay(true);
+    let (mut reader, mut writer) = stream.into_split();
+    writer.write_all(&session.greeting()).await?;
+
+    let mut buf = vec![0u8; BUFFER_SIZE];
+    let (mut data, mut reply) = (Vec::new(), Vec::new());
+    let now = Instant::now();
+    let mut deadline = idle_timeout.map(|timeout| now + timeout);
+    let mut next_poll = session.poll_interval().map(|interval| now + interval);
+    loop {
+        let event = {
+            let received = reader.read(&mut buf);
+            let to_send = chunks.recv();
+            let timer = async {
+                match deadline.into_iter().chain(next_poll).min() {
+                    Some(instant) => tokio::time::sleep_until(instant).await,
+                    None => future::pending().await,
+                }
+            };
+            futures::pin_mut!(received, to_send, timer);
+            match future::select(future::select(received, to_send), timer).await {
+                Either::Left((Either::Left((received, _)), _)) => Event::Received(received?),
+                Either::Left((Either::Right((chunk, _)), _)) => Event::ToSend(chunk),
+                Either::Right(_) => Event::Timer,
+            }
+        };
+        // Polling the port is no activity.
+        let active = !matches!(event, Event::Timer);
+        match event {
+            Event::Received(0) | Event::ToSend(None) => return Ok(()),
+            Event::Received(n) => {
+                session.client_bytes(&buf[..n], &shared.port, &mut data, &mut reply);
+                if !data.is_empty() {
+                    write_port(&shared, &data).await?;
+                    data.clear();
+                }
+            }
+            Event::ToSend(Some(chunk)) => writer.write_all(&session.port_bytes(&chunk)).await?,
+            Event::Timer => {
+                let now = Instant::now();
+                if deadline.is_some_and(|deadline| deadline <= now) {
+                    log::debug!("disconnecting an idle client");
+                    return Ok(());
+                }
+                if let Some(interval) = session.poll_interval() {
+                    session.poll(&shared.port, &mut reply);
+                    next_poll = Some(now + interval);
+                }
+            }
+        }
+        if !reply.is_empty() {
+            writer.write_all(&reply).await?;
+            reply.clear();
+        }
+        if active {
+            deadline = idle_timeout.map(|timeout| Instant::now() + timeout);
+        }
+    }
+}
+
+async fn write_port<S: AsyncWrite + Unpin>(shared: &Shared<S>, data: &[u8]) -> io::Result<()> {
+    // Writes of other clients wait, so data of different clients does not mix.
+    let _writing = shared.writing.lock().await;
+    let mut written = 0;
+    future::poll_fn(|cx| {
+        let mut port = shared.port.lock().unwrap();
+        while written < data.len() {
+            match ready!(Pin::new(&mut *port).poll_write(cx, &data[written..]))? {
+                0 => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                n => written += n,
+            }
+        }
+        Pin::new(&mut *port).poll_flush(cx)
+    })
+    .await
+}
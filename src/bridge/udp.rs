@@ -0,0 +1,389 @@
+//! Serving a serial port over UDP
+//!
+//! For telemetry links, data late is worth less than data lost: UDP does not
+//! resend datagrams, nor hold the following ones back while waiting for a missing
+//! one.  Each chunk read from the port or written by the application is sent in a
+//! datagram of its own.
+//!
+//! [`UdpServer`] is the side of the port, [`UdpStream`] the side of the
+//! application.  With [sequence numbers](UdpServer::set_sequence_numbers), set on
+//! both sides, each datagram starts with a 16 bit big endian counter: the
+//! receiver counts the datagrams lost and drops the ones arriving late, see
+//! [`UdpStats`].
+//!
+//! ```no_run
+//! # async fn example() -> std::io::Result<()> {
+//! use tokio_serial::bridge::udp::UdpServer;
+//!
+//! let port = tokio_serial::SerialStream::open(&tokio_serial::new("/dev/ttyUSB0", 57_600))?;
+//! let mut server = UdpServer::bind("0.0.0.0:14550").await?;
+//! server.set_sequence_numbers(true);
+//! server.serve(port).await
+//! # }
+//! ```
+use futures::future::{self, Either};
+use futures::ready;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::{ToSocketAddrs, UdpSocket};
+
+/// The default largest payload of a datagram, which fits in the MTU of most links
+pub const DEFAULT_MAX_PAYLOAD: usize = 1024;
+
+// The largest datagram UDP carries
+const MAX_DATAGRAM: usize = 65_536;
+
+/// The datagrams of one side of a UDP link so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UdpStats {
+    /// Datagrams sent
+    pub sent: u64,
+    /// Datagrams received and passed on
+    pub received: u64,
+    /// Datagrams missing from the sequence, with sequence numbers
+    pub lost: u64,
+    /// Datagrams received out of order or twice, dropped, with sequence numbers
+    pub late: u64,
+}
+
+// The sequence numbers of a link in both directions
+#[derive(Debug, Default)]
+struct Link {
+    sequence_numbers: bool,
+    next: u16,
+    expected: Option<u16>,
+    stats: UdpStats,
+}
+
+impl Link {
+    // The datagram carrying `payload`, `sent` must be called once it is sent.
+    fn frame(&self, payload: &[u8], datagram: &mut Vec<u8>) {
+        datagram.clear();
+        if self.sequence_numbers {
+            datagram.extend_from_slice(&self.next.to_be_bytes());
+        }
+        datagram.extend_from_slice(payload);
+    }
+
+    fn sent(&mut self) {
+        self.next = self.next.wrapping_add(1);
+        self.stats.sent += 1;
+    }
+
+    // The payload of `datagram`, `None` if it is dropped.
+    fn accept<'a>(&mut self, datagram: &'a [u8]) -> Option<&'a [u8]> {
+        if !self.sequence_numbers {
+            self.stats.received += 1;
+            return Some(datagram);
+        }
+        let (sequence, payload) = match datagram {
+            [high, low, payload @ ..] => (u16::from_be_bytes([*high, *low]), payload),
+            _ => {
+                log::debug!("dropping a datagram without sequence number");
+                return None;
+            }
+        };
+        if let Some(expected) = self.expected {
+            // Numbers up to half the range behind are late, the other ones ahead.
+            let gap = sequence.wrapping_sub(expected);
+            if gap >= 0x8000 {
+                self.stats.late += 1;
+                return None;
+            }
+            self.stats.lost += u64::from(gap);
+        }
+        self.expected = Some(sequence.wrapping_add(1));
+        self.stats.received += 1;
+        Some(payload)
+    }
+}
+
+/// A UDP server giving a peer access to a serial port
+///
+/// What the port reads is sent to the peer, the datagrams of the peer are written
+/// to the port.  The peer is whoever sent the last datagram, unless it is set
+/// with [`set_peer`](UdpServer::set_peer); until then, what the port reads is
+/// discarded.
+#[derive(Debug)]
+pub struct UdpServer {
+    socket: UdpSocket,
+    max_payload: usize,
+    fixed_peer: bool,
+    peer: Mutex<Option<SocketAddr>>,
+    link: Mutex<Link>,
+}
+
+impl UdpServer {
+    /// Listen on `addr`.
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(Self::from_socket(UdpSocket::bind(addr).await?))
+    }
+
+    /// Serve the peers of `socket`.
+    pub fn from_socket(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            max_payload: DEFAULT_MAX_PAYLOAD,
+            fixed_peer: false,
+            peer: Mutex::new(None),
+            link: Mutex::new(Link::default()),
+        }
+    }
+
+    /// Returns the address the server listens on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Set whether datagrams start with a sequence number, `false` by default.
+    ///
+    /// The peer must use sequence numbers as well.
+    pub fn set_sequence_numbers(&mut self, enabled: bool) {
+        self.link.get_mut().unwrap().sequence_numbers = enabled;
+    }
+
+    /// Set the largest payload of the datagrams sent, [`DEFAULT_MAX_PAYLOAD`] by
+    /// default.
+    pub fn set_max_payload(&mut self, max_payload: usize) {
+        self.max_payload = max_payload.clamp(1, MAX_DATAGRAM - 2);
+    }
+
+    /// Only exchange datagrams with `peer`, or with whoever sent the last datagram
+    /// if `None`, the default.
+    pub fn set_peer(&mut self, peer: Option<SocketAddr>) {
+        self.fixed_peer = peer.is_some();
+        *self.peer.get_mut().unwrap() = peer;
+    }
+
+    /// Returns where what the port reads is sent.
+    pub fn peer(&self) -> Option<SocketAddr> {
+        *self.peer.lock().unwrap()
+    }
+
+    /// Returns the datagrams exchanged with the peers so far.
+    pub fn stats(&self) -> UdpStats {
+        self.link.lock().unwrap().stats
+    }
+
+    /// Pass the data between `port` and the peer.
+    ///
+    /// Runs until the port reaches end of file or fails.  Failing to send to the
+    /// peer or to receive is logged, datagrams are lost anyway.
+    pub async fn serve<S>(&self, mut port: S) -> io::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut buf = vec![0u8; self.max_payload];
+        let mut received = vec![0u8; MAX_DATAGRAM];
+        let mut datagram = Vec::new();
+        loop {
+            let event = {
+                let read = port.read(&mut buf);
+                let recv = self.socket.recv_from(&mut received);
+                futures::pin_mut!(read, recv);
+                match future::select(read, recv).await {
+                    Either::Left((read, _)) => Either::Left(read?),
+                    Either::Right((recv, _)) => Either::Right(recv),
+                }
+            };
+            match event {
+                Either::Left(0) => {
+                    log::debug!("end of file on the port");
+                    return Ok(());
+                }
+                Either::Left(n) => {
+                    let peer = match self.peer() {
+                        Some(peer) => peer,
+                        None => continue,
+                    };
+                    self.link.lock().unwrap().frame(&buf[..n], &mut datagram);
+                    match self.socket.send_to(&datagram, peer).await {
+                        Ok(_) => self.link.lock().unwrap().sent(),
+                        Err(e) => log::debug!("sending to {}: {}", peer, e),
+                    }
+                }
+                Either::Right(Ok((len, from))) => {
+                    if !self.accept_peer(from) {
+                        continue;
+                    }
+                    let payload = self.link.lock().unwrap().accept(&received[..len]);
+                    if let Some(payload) = payload {
+                        port.write_all(payload).await?;
+                        port.flush().await?;
+                    }
+                }
+                // E.g. the ICMP errors of datagrams sent to a peer gone.
+                Either::Right(Err(e)) => log::debug!("receiving: {}", e),
+            }
+        }
+    }
+
+    // Returns whether the datagrams of `from` are for the port.
+    fn accept_peer(&self, from: SocketAddr) -> bool {
+        let mut peer = self.peer.lock().unwrap();
+        match *peer {
+            Some(peer) if peer == from => true,
+            Some(_) if self.fixed_peer => {
+                log::debug!("dropping a datagram of {}", from);
+                false
+            }
+            _ => {
+                log::info!("{} is the peer", from);
+                *peer = Some(from);
+                // A new peer starts its own sequence.
+                self.link.lock().unwrap().expected = None;
+                true
+            }
+        }
+    }
+}
+
+/// The application side of a [`UdpServer`]
+///
+/// Each write sends a datagram, of at most the
+/// [maximum payload](UdpStream::set_max_payload), reads return the payload of the
+/// datagrams received.  Reads never reach end of file, and a server not
+/// listening is no error: the datagrams are lost.
+#[derive(Debug)]
+pub struct UdpStream {
+    socket: UdpSocket,
+    max_payload: usize,
+    link: Link,
+    datagram: Vec<u8>,
+    // What is left of the last datagram received
+    received: Vec<u8>,
+    start: usize,
+    end: usize,
+}
+
+impl UdpStream {
+    /// Connect to the server at `addr`.
+    ///
+    /// An empty datagram is sent, so the server knows where to send what its port
+    /// reads.
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let mut last_error = None;
+        for addr in tokio::net::lookup_host(addr).await? {
+            let local: SocketAddr = match addr {
+                SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+            };
+            let socket = UdpSocket::bind(local).await?;
+            match socket.connect(addr).await {
+                Ok(()) => {
+                    let stream = Self::from_socket(socket);
+                    stream.socket.send(&[]).await?;
+                    return Ok(stream);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to")
+        }))
+    }
+
+    /// Exchange datagrams with the peer `socket` is connected to.
+    pub fn from_socket(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            max_payload: DEFAULT_MAX_PAYLOAD,
+            link: Link::default(),
+            datagram: Vec::new(),
+            received: vec![0u8; MAX_DATAGRAM],
+            start: 0,
+            end: 0,
+        }
+    }
+
+    /// Set whether datagrams start with a sequence number, `false` by default.
+    ///
+    /// The server must use sequence numbers as well.
+    pub fn set_sequence_numbers(&mut self, enabled: bool) {
+        self.link.sequence_numbers = enabled;
+    }
+
+    /// Set the largest payload of the datagrams sent, [`DEFAULT_MAX_PAYLOAD`] by
+    /// default.
+    pub fn set_max_payload(&mut self, max_payload: usize) {
+        self.max_payload = max_payload.clamp(1, MAX_DATAGRAM - 2);
+    }
+
+    /// Returns the datagrams exchanged with the server so far.
+    pub fn stats(&self) -> UdpStats {
+        self.link.stats
+    }
+
+    /// Returns the local address of the socket.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Returns the address of the server.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.peer_addr()
+    }
+
+    /// Returns a reference to the socket.
+    pub fn get_ref(&self) -> &UdpSocket {
+        &self.socket
+    }
+}
+
+impl AsyncRead for UdpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.start == this.end {
+            let mut read = ReadBuf::new(&mut this.received);
+            match ready!(this.socket.poll_recv(cx, &mut read)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => continue,
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+            let len = read.filled().len();
+            // Empty payloads are skipped, they would read as end of file.
+            if let Some(payload) = this.link.accept(&this.received[..len]) {
+                this.start = len - payload.len();
+                this.end = len;
+            }
+        }
+        let n = buf.remaining().min(this.end - this.start);
+        buf.put_slice(&this.received[this.start..this.start + n]);
+        this.start += n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for UdpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let payload = &buf[..buf.len().min(this.max_payload)];
+        this.link.frame(payload, &mut this.datagram);
+        match ready!(this.socket.poll_send(cx, &this.datagram)) {
+            Ok(_) => this.link.sent(),
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {}
+            Err(e) => return Poll::Ready(Err(e)),
+        }
+        Poll::Ready(Ok(payload.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,403 @@
+//! Sharing a half-duplex bus between tasks
+//!
+//! On an RS-485 bus every device answers the master on the same pair of wires, so
+//! the master must finish one request/response exchange before starting the next.
+//! A [`SharedBus`] lets several drivers, each in its own task, use one port: each
+//! exchange has the port to itself, the others wait in line.
+//!
+//! A [`BusMaster`] polls the slaves of such a bus by their address: a
+//! [`BusProtocol`] frames the requests and parses the responses, each slave has
+//! its own timeouts, and the master keeps track of which slaves answer.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//! use tokio_serial::bus::SharedBus;
+//!
+//! # async fn example(port: tokio_serial::SerialStream) -> std::io::Result<()> {
+//! let mut bus = SharedBus::new(port);
+//! bus.set_timeout(Duration::from_millis(200));
+//!
+//! let meter = bus.clone();
+//! tokio::spawn(async move {
+//!     meter
+//!         .exchange(|port| {
+//!             Box::pin(async move {
+//!                 port.write_all(b"\x02READ\x03").await?;
+//!                 let mut reply = [0u8; 16];
+//!                 port.read_exact(&mut reply).await?;
+//!                 Ok(reply)
+//!             })
+//!         })
+//!         .await
+//! });
+//!
+//! let mut guard = bus.acquire().await;
+//! guard.write_all(b"\x02PING\x03").await?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::SerialStream;
+
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio::time::Instant;
+
+#[derive(Debug)]
+struct Bus {
+    port: SerialStream,
+    // When the last exchange released the bus
+    released: Option<Instant>,
+}
+
+/// A handle to a port shared between tasks, one exchange at a time
+///
+/// Cloning the handle gives another handle to the same port, the settings of each
+/// handle are its own.  Tasks get the port in the order they asked for it.
+#[derive(Debug, Clone)]
+pub struct SharedBus {
+    bus: Arc<Mutex<Bus>>,
+    timeout: Duration,
+    turnaround: Duration,
+}
+
+impl SharedBus {
+    /// Share `port`.
+    pub fn new(port: SerialStream) -> Self {
+        Self {
+            bus: Arc::new(Mutex::new(Bus {
+                port,
+                released: None,
+            })),
+            timeout: Duration::from_secs(1),
+            turnaround: Duration::ZERO,
+        }
+    }
+
+    /// Set how long an [`exchange`](SharedBus::exchange) may take once it has the
+    /// port, 1 second by default.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns how long an exchange may take once it has the port.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Set the time the bus stays quiet between two exchanges, none by default.
+    ///
+    /// Slow devices need some time to switch their transceiver back to receiving
+    /// after answering, e.g. Modbus RTU asks for 3.5 characters of silence.
+    pub fn set_turnaround(&mut self, turnaround: Duration) {
+        self.turnaround = turnaround;
+    }
+
+    /// Returns the time the bus stays quiet between two exchanges.
+    pub fn turnaround(&self) -> Duration {
+        self.turnaround
+    }
+
+    /// Wait for the exchanges requested before to complete, and take the port.
+    ///
+    /// Other tasks wait until the guard is dropped.  Unlike
+    /// [`exchange`](SharedBus::exchange), nothing limits how long the guard is held
+    /// and bytes received since the last exchange are not discarded.
+    pub async fn acquire(&self) -> BusGuard {
+        let bus = self.bus.clone().lock_owned().await;
+        if let Some(released) = bus.released {
+            tokio::time::sleep_until(released + self.turnaround).await;
+        }
+        BusGuard { bus }
+    }
+
+    /// Run one exchange with the port to itself.
+    ///
+    /// Bytes received since the last exchange, e.g. a late response to a request
+    /// which timed out, are discarded first so they are not taken for the response.
+    /// The timeout only starts once the port is acquired.
+    ///
+    /// ## Errors
+    ///
+    /// * `TimedOut` if `exchange` did not complete within the timeout.
+    /// * The errors of `exchange`, or of discarding the received bytes.
+    pub async fn exchange<F, T>(&self, exchange: F) -> io::Result<T>
+    where
+        F: for<'a> FnOnce(&'a mut SerialStream) -> BoxFuture<'a, io::Result<T>>,
+    {
+        let mut guard = self.acquire().await;
+        guard.clear_buffers(crate::ClearBuffer::Input).await?;
+        match tokio::time::timeout(self.timeout, exchange(&mut guard)).await {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "timed out exchanging on the bus",
+            )),
+        }
+    }
+}
+
+/// Exclusive access to the port of a [`SharedBus`], released when dropped
+pub struct BusGuard {
+    bus: OwnedMutexGuard<Bus>,
+}
+
+impl Deref for BusGuard {
+    type Target = SerialStream;
+
+    fn deref(&self) -> &SerialStream {
+        &self.bus.port
+    }
+}
+
+impl DerefMut for BusGuard {
+    fn deref_mut(&mut self) -> &mut SerialStream {
+        &mut self.bus.port
+    }
+}
+
+impl Drop for BusGuard {
+    fn drop(&mut self) {
+        self.bus.released = Some(Instant::now());
+    }
+}
+
+impl fmt::Debug for BusGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("BusGuard").field(&self.bus.port).finish()
+    }
+}
+
+/// How a [`BusMaster`] talks to the slaves of a bus
+pub trait BusProtocol {
+    /// The requests sent to slaves
+    type Request;
+    /// The responses of slaves
+    type Response;
+
+    /// Append the frame of `request` for the slave at `address` to `out`.
+    fn encode(&self, address: u8, request: &Self::Request, out: &mut Vec<u8>);
+
+    /// Parse the response of the slave at `address` from the bytes received so far.
+    ///
+    /// Returns `None` while the response is incomplete.
+    ///
+    /// ## Errors
+    ///
+    /// Malformed responses, or responses from another slave, fail the poll with the
+    /// error returned, usually `InvalidData`.
+    fn decode(&self, address: u8, received: &[u8]) -> io::Result<Option<Self::Response>>;
+}
+
+/// The timing of one slave
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlaveConfig {
+    /// How long the slave may take to send its whole response
+    pub timeout: Duration,
+    /// How long the bus stays quiet before a request to the slave
+    ///
+    /// It adds to the [turnaround](SharedBus::set_turnaround) of the bus.
+    pub turnaround: Duration,
+}
+
+/// Whether a slave answers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SlaveHealth {
+    /// The slave was not polled yet
+    Unknown,
+    /// The last poll of the slave succeeded, or failed less times in a row than the
+    /// [limit](BusMaster::set_offline_after)
+    Online,
+    /// The last polls of the slave all failed
+    Offline,
+}
+
+/// The polls of one slave so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SlaveStats {
+    /// Requests sent
+    pub requests: u64,
+    /// Valid responses received
+    pub responses: u64,
+    /// Polls which timed out
+    pub timeouts: u64,
+    /// Polls which failed otherwise, such as malformed responses
+    pub errors: u64,
+    /// Polls failed since the last valid response
+    pub consecutive_failures: u32,
+    /// Time from the start of the request to the end of the last valid response
+    pub last_latency: Option<Duration>,
+    /// When the last valid response was received
+    pub last_response: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct Slaves {
+    configs: HashMap<u8, SlaveConfig>,
+    stats: HashMap<u8, SlaveStats>,
+}
+
+/// A bus master polling slaves by address
+///
+/// Cloning the master gives another handle to the same bus, sharing the
+/// configuration and the statistics of the slaves.
+#[derive(Debug)]
+pub struct BusMaster<P> {
+    bus: SharedBus,
+    protocol: Arc<P>,
+    slaves: Arc<std::sync::Mutex<Slaves>>,
+    offline_after: u32,
+}
+
+impl<P> Clone for BusMaster<P> {
+    fn clone(&self) -> Self {
+        Self {
+            bus: self.bus.clone(),
+            protocol: self.protocol.clone(),
+            slaves: self.slaves.clone(),
+            offline_after: self.offline_after,
+        }
+    }
+}
+
+impl<P: BusProtocol> BusMaster<P> {
+    /// Poll the slaves on `bus` with `protocol`.
+    ///
+    /// Slaves which are not [configured](BusMaster::configure_slave) get the
+    /// timeout of the bus and no turnaround of their own.
+    pub fn new(bus: SharedBus, protocol: P) -> Self {
+        Self {
+            bus,
+            protocol: Arc::new(protocol),
+            slaves: Arc::default(),
+            offline_after: 3,
+        }
+    }
+
+    /// Set the timing of the slave at `address`.
+    pub fn configure_slave(&self, address: u8, config: SlaveConfig) {
+        self.slaves().configs.insert(address, config);
+    }
+
+    /// Returns the timing of the slave at `address`.
+    pub fn slave_config(&self, address: u8) -> SlaveConfig {
+        self.slaves()
+            .configs
+            .get(&address)
+            .copied()
+            .unwrap_or(SlaveConfig {
+                timeout: self.bus.timeout(),
+                turnaround: Duration::ZERO,
+            })
+    }
+
+    /// Set after how many failed polls in a row a slave is offline, 3 by default.
+    pub fn set_offline_after(&mut self, failures: u32) {
+        self.offline_after = failures.max(1);
+    }
+
+    /// Returns the statistics of the slave at `address`.
+    pub fn stats(&self, address: u8) -> SlaveStats {
+        self.slaves()
+            .stats
+            .get(&address)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns whether the slave at `address` answers.
+    pub fn health(&self, address: u8) -> SlaveHealth {
+        match self.slaves().stats.get(&address) {
+            None => SlaveHealth::Unknown,
+            Some(stats) if stats.consecutive_failures >= self.offline_after => SlaveHealth::Offline,
+            Some(_) => SlaveHealth::Online,
+        }
+    }
+
+    /// Returns the statistics of all the slaves polled so far, by address.
+    pub fn all_stats(&self) -> HashMap<u8, SlaveStats> {
+        self.slaves().stats.clone()
+    }
+
+    /// Send `request` to the slave at `address` and wait for its response.
+    ///
+    /// The bus is held for the whole exchange.  Bytes received before the request
+    /// are discarded, like with [`exchange`](SharedBus::exchange).
+    ///
+    /// ## Errors
+    ///
+    /// * `TimedOut` if the slave did not answer within its timeout.
+    /// * `UnexpectedEof` if the port was closed.
+    /// * The errors of [`BusProtocol::decode`], or of the port.
+    pub async fn poll_slave(&self, address: u8, request: &P::Request) -> io::Result<P::Response> {
+        let config = self.slave_config(address);
+        let mut frame = Vec::new();
+        self.protocol.encode(address, request, &mut frame);
+
+        let mut guard = self.bus.acquire().await;
+        if let Some(released) = guard.bus.released {
+            tokio::time::sleep_until(released + self.bus.turnaround + config.turnaround).await;
+        }
+        guard.clear_buffers(crate::ClearBuffer::Input).await?;
+
+        let started = Instant::now();
+        let protocol = &self.protocol;
+        let port: &mut SerialStream = &mut guard;
+        let exchange = async move {
+            port.write_all(&frame).await?;
+            let mut received = Vec::new();
+            loop {
+                let mut buf = [0u8; 256];
+                let n = port.read(&mut buf).await?;
+                if n == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                received.extend_from_slice(&buf[..n]);
+                if let Some(response) = protocol.decode(address, &received)? {
+                    return Ok(response);
+                }
+            }
+        };
+        let result = match tokio::time::timeout(config.timeout, exchange).await {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("slave {} did not answer", address),
+            )),
+        };
+        drop(guard);
+
+        let mut slaves = self.slaves();
+        let stats = slaves.stats.entry(address).or_default();
+        stats.requests += 1;
+        match &result {
+            Ok(_) => {
+                let now = Instant::now();
+                stats.responses += 1;
+                stats.consecutive_failures = 0;
+                stats.last_latency = Some(now - started);
+                stats.last_response = Some(now);
+            }
+            Err(e) => {
+                if e.kind() == io::ErrorKind::TimedOut {
+                    stats.timeouts += 1;
+                } else {
+                    stats.errors += 1;
+                }
+                stats.consecutive_failures = stats.consecutive_failures.saturating_add(1);
+            }
+        }
+        result
+    }
+
+    fn slaves(&self) -> std::sync::MutexGuard<'_, Slaves> {
+        self.slaves.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,209 @@
+//! `tokio_util` codecs for serial protocols
+//!
+//! The framing state machines and checksums live in the `no_std`
+//! `tokio-serial-core` crate so device firmware can share them, they are
+//! re-exported here.  [`FrameCodec`] adapts any of them to
+//! [`Decoder`]/[`Encoder`] for use with `tokio_util::codec::Framed`.
+//!
+//! Codecs which only make sense on the host, such as [`LinesCodec`], implement
+//! `Decoder`/`Encoder` directly.  Protocols delimiting frames by timing rather than
+//! by their content, such as Modbus RTU, read the port themselves, see
+//! [`ModbusRtuFramed`] and [`TimeoutFramed`].
+//!
+//! [`Decoder`] and [`Encoder`] are re-exported, codecs of an application's own
+//! do not need a dependency on `tokio_util`.
+pub use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, Bytes, BytesMut};
+use std::{error, fmt, io};
+
+pub use tokio_serial_core::{cobs, crc, dle, hdlc, slip, Deframer, Framer};
+
+pub mod at;
+pub use at::AtCodec;
+
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(feature = "cbor")]
+pub use cbor::{CborCodec, CborError};
+
+mod checksum;
+pub use checksum::{Checksum, ChecksumCodec, ChecksumError};
+
+pub mod dlt645;
+pub use dlt645::Dlt645Codec;
+
+pub mod ihex;
+pub use ihex::IhexCodec;
+
+mod length;
+pub use length::{LengthDelimitedCodec, LengthError};
+
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "json")]
+pub use json::{JsonLinesCodec, JsonLinesError};
+
+mod lines;
+pub use lines::{Delimiter, LinesCodec, LinesCodecError, DEFAULT_MAX_LINE_LENGTH};
+
+pub mod mavlink;
+pub use mavlink::MavlinkCodec;
+
+#[cfg(feature = "msgpack")]
+mod msgpack;
+#[cfg(feature = "msgpack")]
+pub use msgpack::{MessagePackCodec, MessagePackError};
+
+pub mod nmea;
+pub use nmea::{GpsClient, NmeaCodec};
+
+pub mod p1;
+pub use p1::P1Codec;
+
+pub mod ppp;
+pub use ppp::PppCodec;
+
+#[cfg(feature = "postcard")]
+mod postcard;
+#[cfg(feature = "postcard")]
+pub use self::postcard::{PostcardCodec, PostcardError};
+
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+#[cfg(feature = "protobuf")]
+pub use protobuf::{ProtobufCodec, ProtobufError};
+
+mod resync;
+pub use resync::{Recovery, Resync};
+
+pub mod sensor;
+pub use sensor::{MhZ19Codec, Pms5003Codec, Sds011Codec};
+
+pub mod slcan;
+pub use slcan::SlcanCodec;
+
+pub mod timeout;
+pub use timeout::TimeoutFramed;
+
+pub mod transaction;
+pub use transaction::TransactionError;
+
+pub mod xbee;
+pub use xbee::XbeeCodec;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod rtu;
+#[cfg(not(target_arch = "wasm32"))]
+pub use rtu::{ModbusRtuError, ModbusRtuFramed};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod modbus;
+#[cfg(not(target_arch = "wasm32"))]
+pub use modbus::ModbusSlave;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod modem;
+#[cfg(not(target_arch = "wasm32"))]
+pub use modem::ModemManager;
+
+/// Errors produced by [`FrameCodec`]
+#[derive(Debug)]
+pub enum FrameError<E> {
+    /// The underlying I/O failed
+    Io(io::Error),
+    /// The framing layer rejected a frame or payload
+    Frame(E),
+}
+
+impl<E: fmt::Display> fmt::Display for FrameError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Io(err) => err.fmt(f),
+            FrameError::Frame(err) => err.fmt(f),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> error::Error for FrameError<E> {}
+
+impl<E> From<io::Error> for FrameError<E> {
+    fn from(err: io::Error) -> Self {
+        FrameError::Io(err)
+    }
+}
+
+/// A codec driving a `tokio-serial-core` [`Deframer`] and/or [`Framer`]
+///
+/// Decoded frames are handed out as [`BytesMut`], payloads can be encoded from
+/// `&[u8]` or [`Bytes`].
+#[derive(Debug, Clone, Default)]
+pub struct FrameCodec<T> {
+    inner: T,
+}
+
+impl<T> FrameCodec<T> {
+    /// Wrap a deframer/framer into a codec.
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+
+    /// Returns a reference to the wrapped state machine.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped state machine.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consumes the codec, returning the wrapped state machine.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Deframer> Decoder for FrameCodec<T> {
+    type Item = BytesMut;
+    type Error = FrameError<T::Error>;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let mut consumed = 0;
+        let mut result = Ok(None);
+        for &byte in src.iter() {
+            consumed += 1;
+            match self.inner.push(byte) {
+                Ok(None) => {}
+                Ok(Some(frame)) => {
+                    result = Ok(Some(BytesMut::from(frame)));
+                    break;
+                }
+                Err(err) => {
+                    result = Err(FrameError::Frame(err));
+                    break;
+                }
+            }
+        }
+        src.advance(consumed);
+        result
+    }
+}
+
+impl<T: Framer> Encoder<&[u8]> for FrameCodec<T> {
+    type Error = FrameError<T::Error>;
+
+    fn encode(&mut self, item: &[u8], dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.inner
+            .frame(item, |chunk| dst.extend_from_slice(chunk))
+            .map_err(FrameError::Frame)
+    }
+}
+
+impl<T: Framer> Encoder<Bytes> for FrameCodec<T> {
+    type Error = FrameError<T::Error>;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&item[..], dst)
+    }
+}
@@ -0,0 +1,567 @@
+//! AT commands, as spoken by modems, cellular and Bluetooth modules
+//!
+//! [`AtCodec`] sends commands and splits what the device answers into the
+//! responses to these commands and unsolicited result codes (URCs), such as `RING`
+//! or `+CMTI: "SM",3`, which the device sends whenever something happens.
+//! [`AtClient`] runs commands one at a time on a port, handing URCs to a separate
+//! stream.
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, BufMut, BytesMut};
+use std::{error, fmt, io};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{SerialFramed, SerialStream};
+#[cfg(not(target_arch = "wasm32"))]
+use futures::{SinkExt, Stream, StreamExt};
+#[cfg(not(target_arch = "wasm32"))]
+use std::pin::Pin;
+#[cfg(not(target_arch = "wasm32"))]
+use std::task::{Context, Poll};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::io::AsyncWriteExt;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::sync::mpsc;
+
+// Ends the data of commands such as `AT+CMGS`.
+#[cfg(not(target_arch = "wasm32"))]
+const CTRL_Z: u8 = 0x1a;
+
+/// Prefixes of the unsolicited result codes recognized by default
+pub const DEFAULT_URCS: &[&str] = &[
+    "RING", "+CRING:", "+CLIP:", "+CMTI:", "+CMT:", "+CDSI:", "+CBM:", "+CREG:", "+CGREG:",
+    "+CEREG:", "+CUSD:",
+];
+
+/// Errors produced by [`AtCodec`] and [`AtClient`]
+#[derive(Debug)]
+pub enum AtError {
+    /// The underlying I/O failed
+    Io(io::Error),
+    /// A line was longer than the maximum length, it is discarded
+    LineTooLong,
+    /// The command contains a line break, which would end it early
+    InvalidCommand,
+    /// No final result was received in time
+    Timeout,
+    /// The port was closed before the final result was received
+    Closed,
+}
+
+impl fmt::Display for AtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtError::Io(err) => err.fmt(f),
+            AtError::LineTooLong => f.write_str("response line too long"),
+            AtError::InvalidCommand => f.write_str("AT command contains a line break"),
+            AtError::Timeout => f.write_str("AT command timed out"),
+            AtError::Closed => f.write_str("port closed before the final result"),
+        }
+    }
+}
+
+impl error::Error for AtError {}
+
+impl From<io::Error> for AtError {
+    fn from(err: io::Error) -> Self {
+        AtError::Io(err)
+    }
+}
+
+/// The final result code ending the response to a command
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AtResult {
+    /// `OK`
+    Ok,
+    /// `CONNECT`, with the text following it such as the connection speed
+    Connect(String),
+    /// `ERROR`
+    Error,
+    /// `+CME ERROR: <err>`, a mobile equipment error, numeric or verbose
+    CmeError(String),
+    /// `+CMS ERROR: <err>`, a message service error, numeric or verbose
+    CmsError(String),
+    /// `NO CARRIER`
+    NoCarrier,
+    /// `BUSY`
+    Busy,
+    /// `NO ANSWER`
+    NoAnswer,
+    /// `NO DIALTONE`
+    NoDialtone,
+}
+
+impl AtResult {
+    /// Parse a final result code, `None` if `line` is not one.
+    pub fn parse(line: &str) -> Option<Self> {
+        Some(match line {
+            "OK" => AtResult::Ok,
+            "ERROR" => AtResult::Error,
+            "NO CARRIER" => AtResult::NoCarrier,
+            "BUSY" => AtResult::Busy,
+            "NO ANSWER" => AtResult::NoAnswer,
+            "NO DIALTONE" | "NO DIAL TONE" => AtResult::NoDialtone,
+            _ => {
+                if let Some(err) = line.strip_prefix("+CME ERROR:") {
+                    AtResult::CmeError(err.trim().to_string())
+                } else if let Some(err) = line.strip_prefix("+CMS ERROR:") {
+                    AtResult::CmsError(err.trim().to_string())
+                } else if let Some(text) = line.strip_prefix("CONNECT") {
+                    AtResult::Connect(text.trim().to_string())
+                } else {
+                    return None;
+                }
+            }
+        })
+    }
+
+    /// Returns whether the command succeeded, with `OK` or `CONNECT`.
+    pub fn is_ok(&self) -> bool {
+        matches!(self, AtResult::Ok | AtResult::Connect(_))
+    }
+}
+
+impl fmt::Display for AtResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtResult::Ok => f.write_str("OK"),
+            AtResult::Connect(text) if text.is_empty() => f.write_str("CONNECT"),
+            AtResult::Connect(text) => write!(f, "CONNECT {}", text),
+            AtResult::Error => f.write_str("ERROR"),
+            AtResult::CmeError(err) => write!(f, "+CME ERROR: {}", err),
+            AtResult::CmsError(err) => write!(f, "+CMS ERROR: {}", err),
+            AtResult::NoCarrier => f.write_str("NO CARRIER"),
+            AtResult::Busy => f.write_str("BUSY"),
+            AtResult::NoAnswer => f.write_str("NO ANSWER"),
+            AtResult::NoDialtone => f.write_str("NO DIALTONE"),
+        }
+    }
+}
+
+/// The response to a command
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AtResponse {
+    /// The information lines received before the final result, such as
+    /// `+CSQ: 20,99`
+    pub lines: Vec<String>,
+    /// The final result code
+    pub result: AtResult,
+}
+
+impl AtResponse {
+    /// Returns whether the command succeeded.
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// What [`AtCodec`] decodes
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AtEvent {
+    /// The response to the command sent last
+    Response(AtResponse),
+    /// An unsolicited result code
+    Urc(String),
+    /// The `>` prompt asking for the data of the command sent, such as the text of
+    /// an SMS after `AT+CMGS`
+    Prompt,
+}
+
+// The command waiting for its final result.
+#[derive(Debug, Clone)]
+struct Pending {
+    command: String,
+    // What responses to the command start with, e.g. `+CSQ:` for `AT+CSQ`.
+    prefix: Option<String>,
+    lines: Vec<String>,
+}
+
+/// A codec for AT commands and their responses
+///
+/// Commands are encoded from `&str`, `AT` prefix included and without the
+/// trailing `\r`, which is appended.  Once a command is sent, lines received are
+/// collected until its final result code, such as `OK` or `+CME ERROR: 10`, and
+/// decoded as an [`AtEvent::Response`].  The echo of the command, if the device
+/// echoes, and empty lines are skipped.
+///
+/// Lines starting with one of the URC prefixes, by default [`DEFAULT_URCS`], are
+/// decoded as [`AtEvent::Urc`] right away, unless they answer the command sent:
+/// `+CREG: 0,1` is part of the response to `AT+CREG?`.  All lines received while no
+/// command is running are URCs, such as `NO CARRIER` once a data call ends, but for
+/// `OK` and errors, which can only be late results of an abandoned command.
+///
+/// A `>` before the first line of the response is the prompt of commands taking
+/// data, it is decoded as [`AtEvent::Prompt`] without waiting for a line break.
+///
+/// ```
+/// use tokio_serial::codec::at::AtCodec;
+///
+/// let codec = AtCodec::new().urc("+QIURC:");
+/// ```
+///
+/// Commands are meant to be sent one at a time: sending one while another is
+/// running abandons the latter.
+#[derive(Debug, Clone)]
+pub struct AtCodec {
+    urcs: Vec<String>,
+    max_length: usize,
+    pending: Option<Pending>,
+    discarding: bool,
+}
+
+impl Default for AtCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AtCodec {
+    /// A codec recognizing [`DEFAULT_URCS`], with lines of at most 1024 bytes.
+    pub fn new() -> Self {
+        Self {
+            urcs: DEFAULT_URCS.iter().map(|urc| urc.to_string()).collect(),
+            max_length: 1024,
+            pending: None,
+            discarding: false,
+        }
+    }
+
+    /// Recognize the lines starting with `prefix` as URCs as well.
+    pub fn urc(mut self, prefix: impl Into<String>) -> Self {
+        self.urcs.push(prefix.into());
+        self
+    }
+
+    /// Set the prefixes of URCs, replacing the default ones.
+    pub fn urcs<I, S>(mut self, prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.urcs = prefixes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the maximum length of a line.
+    pub fn max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Returns whether a command is waiting for its final result.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Abandon the command waiting for its final result, e.g. after a timeout.
+    ///
+    /// The lines received for it until then are dropped, later ones are URCs.
+    pub fn cancel(&mut self) {
+        self.pending = None;
+    }
+
+    // The next line of `src`, without its terminator.
+    fn next_line(&mut self, src: &mut BytesMut) -> Result<Option<String>, AtError> {
+        loop {
+            match src.iter().position(|&b| b == b'\r' || b == b'\n') {
+                Some(at) if self.discarding => {
+                    src.advance(at + 1);
+                    self.discarding = false;
+                }
+                Some(at) if at > self.max_length => {
+                    src.advance(at + 1);
+                    return Err(AtError::LineTooLong);
+                }
+                Some(at) => {
+                    let line = src.split_to(at + 1);
+                    return Ok(Some(String::from_utf8_lossy(&line[..at]).into_owned()));
+                }
+                None if self.discarding => {
+                    src.clear();
+                    return Ok(None);
+                }
+                None if src.len() > self.max_length => {
+                    src.clear();
+                    self.discarding = true;
+                    return Err(AtError::LineTooLong);
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn is_urc(&self, line: &str) -> bool {
+        if let Some(pending) = &self.pending {
+            if let Some(prefix) = &pending.prefix {
+                if line.starts_with(prefix.as_str()) {
+                    return false;
+                }
+            }
+        }
+        self.urcs.iter().any(|urc| line.starts_with(urc.as_str()))
+    }
+}
+
+// `+CSQ:` for `AT+CSQ`, `+CREG:` for `AT+CREG?` or `AT+CREG=2`.
+fn response_prefix(command: &str) -> Option<String> {
+    let name = command.get(2..)?;
+    let end = name.find(&['=', '?'][..]).unwrap_or(name.len());
+    let name = &name[..end];
+    if name.len() > 1 && (name.starts_with('+') || name.starts_with('^')) {
+        Some(format!("{}:", name))
+    } else {
+        None
+    }
+}
+
+impl Decoder for AtCodec {
+    type Item = AtEvent;
+    type Error = AtError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<AtEvent>, AtError> {
+        if let Some(pending) = &self.pending {
+            let start = src.iter().position(|&b| b != b'\r' && b != b'\n');
+            if pending.lines.is_empty() && !self.discarding && start.map(|at| src[at]) == Some(b'>')
+            {
+                // `> `, the space may come later and be skipped as an empty line.
+                src.advance(start.unwrap_or(0) + 1);
+                if src.first() == Some(&b' ') {
+                    src.advance(1);
+                }
+                return Ok(Some(AtEvent::Prompt));
+            }
+        }
+        while let Some(line) = self.next_line(src)? {
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            if self.is_urc(line) {
+                return Ok(Some(AtEvent::Urc(line.to_string())));
+            }
+            let pending = match &mut self.pending {
+                Some(pending) => pending,
+                None => match AtResult::parse(line) {
+                    // Late results of abandoned commands
+                    Some(AtResult::Ok)
+                    | Some(AtResult::Error)
+                    | Some(AtResult::CmeError(_))
+                    | Some(AtResult::CmsError(_)) => {
+                        log::debug!("dropping result code without command: {}", line);
+                        continue;
+                    }
+                    _ => return Ok(Some(AtEvent::Urc(line.to_string()))),
+                },
+            };
+            if pending.lines.is_empty() && line == pending.command {
+                // The echo
+                continue;
+            }
+            match AtResult::parse(line) {
+                Some(result) => {
+                    let lines = self.pending.take().map(|p| p.lines).unwrap_or_default();
+                    return Ok(Some(AtEvent::Response(AtResponse { lines, result })));
+                }
+                None => pending.lines.push(line.to_string()),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Encoder<&str> for AtCodec {
+    type Error = AtError;
+
+    fn encode(&mut self, command: &str, dst: &mut BytesMut) -> Result<(), AtError> {
+        if command.contains(&['\r', '\n'][..]) {
+            return Err(AtError::InvalidCommand);
+        }
+        if let Some(pending) = &self.pending {
+            log::debug!("abandoning command {}", pending.command);
+        }
+        dst.reserve(command.len() + 1);
+        dst.put(command.as_bytes());
+        dst.put_u8(b'\r');
+        self.pending = Some(Pending {
+            command: command.to_string(),
+            prefix: response_prefix(command),
+            lines: Vec::new(),
+        });
+        Ok(())
+    }
+}
+
+/// Runs AT commands on a port
+///
+/// Commands are run one at a time with [`command`](AtClient::command); the URCs
+/// received meanwhile are handed to the [`Urcs`] stream returned with the client.
+/// URCs are only read while a command runs or [`listen`](AtClient::listen) is
+/// polled:
+///
+/// ```no_run
+/// # async fn example(port: tokio_serial::SerialStream) -> Result<(), tokio_serial::codec::at::AtError> {
+/// use futures::StreamExt;
+/// use tokio_serial::codec::at::AtClient;
+///
+/// let (mut modem, mut urcs) = AtClient::new(port);
+/// let signal = modem.command("AT+CSQ").await?;
+/// println!("{:?}", signal.lines);
+///
+/// tokio::select! {
+///     closed = modem.listen() => closed?,
+///     Some(urc) = urcs.next() => println!("{}", urc),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub struct AtClient {
+    framed: SerialFramed<AtCodec>,
+    urcs: mpsc::UnboundedSender<String>,
+    timeout: Duration,
+}
+
+/// The unsolicited result codes received by an [`AtClient`]
+///
+/// The stream ends once the client is dropped.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub struct Urcs {
+    rx: mpsc::UnboundedReceiver<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Stream for Urcs {
+    type Item = String;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<String>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl AtClient {
+    /// A client for the device on `port`, recognizing [`DEFAULT_URCS`].
+    pub fn new(port: SerialStream) -> (Self, Urcs) {
+        Self::with_codec(port, AtCodec::new())
+    }
+
+    /// A client using `codec` to tell URCs and responses apart.
+    pub fn with_codec(port: SerialStream, codec: AtCodec) -> (Self, Urcs) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let client = Self {
+            framed: SerialFramed::new(port, codec),
+            urcs: tx,
+            timeout: Duration::from_secs(5),
+        };
+        (client, Urcs { rx })
+    }
+
+    /// Set how long commands wait for their final result, 5 seconds by default.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns how long commands wait for their final result.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Send `command`, such as `AT+CSQ`, and wait for its response.
+    ///
+    /// Commands failing on the device, e.g. with `ERROR`, are responses too, check
+    /// [`AtResponse::is_ok`].
+    pub async fn command(&mut self, command: &str) -> Result<AtResponse, AtError> {
+        self.framed.send(command).await?;
+        let response = self.response(false).await?;
+        Ok(response.expect("prompts are only handed out when asked for"))
+    }
+
+    /// Send `command`, such as `AT+CMGS="+31612345678"`, then `data` once the
+    /// device prompts for it, and wait for the response.
+    ///
+    /// `data` is followed by Ctrl-Z, which ends it.  Commands refused before the
+    /// prompt return their response right away.
+    pub async fn command_with_data(
+        &mut self,
+        command: &str,
+        data: &[u8],
+    ) -> Result<AtResponse, AtError> {
+        self.framed.send(command).await?;
+        if let Some(response) = self.response(true).await? {
+            return Ok(response);
+        }
+        let port = self.framed.get_mut();
+        port.write_all(data).await?;
+        port.write_all(&[CTRL_Z]).await?;
+        port.flush().await?;
+        let response = self.response(false).await?;
+        Ok(response.expect("prompts are only handed out when asked for"))
+    }
+
+    // The response to the command sent, or `None` when the device prompts for data
+    // and `prompt` is set.
+    async fn response(&mut self, prompt: bool) -> Result<Option<AtResponse>, AtError> {
+        match tokio::time::timeout(self.timeout, self.event(prompt)).await {
+            Ok(response) => response,
+            Err(_) => {
+                self.framed.codec_mut().cancel();
+                Err(AtError::Timeout)
+            }
+        }
+    }
+
+    async fn event(&mut self, prompt: bool) -> Result<Option<AtResponse>, AtError> {
+        loop {
+            match self.framed.next().await {
+                Some(Ok(AtEvent::Response(response))) => return Ok(Some(response)),
+                Some(Ok(AtEvent::Prompt)) if prompt => return Ok(None),
+                Some(Ok(AtEvent::Prompt)) => log::debug!("dropping unexpected prompt"),
+                Some(Ok(AtEvent::Urc(urc))) => {
+                    let _ = self.urcs.send(urc);
+                }
+                Some(Err(err)) => return Err(err),
+                None => return Err(AtError::Closed),
+            }
+        }
+    }
+
+    /// Read URCs while no command runs, until the port is closed.
+    ///
+    /// This is cancel safe, it is meant to be polled alongside the source of the
+    /// next commands.
+    pub async fn listen(&mut self) -> Result<(), AtError> {
+        loop {
+            match self.framed.next().await {
+                Some(Ok(AtEvent::Urc(urc))) => {
+                    let _ = self.urcs.send(urc);
+                }
+                Some(Ok(AtEvent::Response(response))) => {
+                    log::debug!("dropping response of abandoned command: {:?}", response);
+                }
+                Some(Ok(AtEvent::Prompt)) => {}
+                Some(Err(AtError::LineTooLong)) => {}
+                Some(Err(err)) => return Err(err),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Returns a reference to the underlying port.
+    pub fn get_ref(&self) -> &SerialStream {
+        self.framed.get_ref()
+    }
+
+    /// Returns a mutable reference to the underlying port.
+    pub fn get_mut(&mut self) -> &mut SerialStream {
+        self.framed.get_mut()
+    }
+
+    /// Consumes the client, returning the underlying port.
+    pub fn into_inner(self) -> SerialStream {
+        self.framed.into_inner()
+    }
+}
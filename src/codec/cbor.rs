@@ -0,0 +1,212 @@
+//! CBOR encoded values, self-delimited or length prefixed
+use super::{FrameError, LengthDelimitedCodec, LengthError};
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, BytesMut};
+use ciborium::value::Value;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+use std::{error, fmt, io};
+
+/// The default largest value read by a self-delimited [`CborCodec`]
+pub const DEFAULT_MAX_LENGTH: usize = 8 * 1024;
+
+/// Errors produced by [`CborCodec`]
+#[derive(Debug)]
+pub enum CborError {
+    /// The underlying I/O failed
+    Io(io::Error),
+    /// A self-delimited value is longer than the maximum length, the buffered bytes
+    /// are discarded
+    TooLong,
+    /// The length prefix of a frame is invalid, the frame is skipped
+    Length(LengthError),
+    /// The data is not well-formed CBOR.  A self-delimited decoder skips a byte and
+    /// tries again, a length prefixed one skips the frame.
+    Malformed,
+    /// A value is well-formed CBOR but does not match the decoded type, it is
+    /// skipped
+    Deserialize(ciborium::value::Error),
+    /// A value could not be serialized
+    Serialize(ciborium::ser::Error<io::Error>),
+}
+
+impl fmt::Display for CborError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CborError::Io(err) => err.fmt(f),
+            CborError::TooLong => f.write_str("CBOR value too long"),
+            CborError::Length(err) => err.fmt(f),
+            CborError::Malformed => f.write_str("malformed CBOR data"),
+            CborError::Deserialize(err) => write!(f, "invalid CBOR value: {}", err),
+            CborError::Serialize(err) => write!(f, "unable to serialize value: {}", err),
+        }
+    }
+}
+
+impl error::Error for CborError {}
+
+impl From<io::Error> for CborError {
+    fn from(err: io::Error) -> Self {
+        CborError::Io(err)
+    }
+}
+
+impl From<FrameError<LengthError>> for CborError {
+    fn from(err: FrameError<LengthError>) -> Self {
+        match err {
+            FrameError::Io(err) => CborError::Io(err),
+            FrameError::Frame(err) => CborError::Length(err),
+        }
+    }
+}
+
+/// A codec for serde values encoded as [CBOR](https://cbor.io)
+///
+/// CBOR items are self-delimiting, so by default values are sent back to back
+/// without framing, the same as `ciborium::into_writer` on a device writing them
+/// to its UART.  Without framing there is nothing to resynchronize on after
+/// garbage, though: malformed data is skipped a byte at a time until a value
+/// parses again.  With [`length_delimited`](CborCodec::length_delimited), each
+/// value is prefixed by its length and a damaged frame only costs one value.
+///
+/// Decoded values are deserialized into `T`, values which are well-formed but do
+/// not match it are reported as [`CborError::Deserialize`] and skipped.  Any
+/// [`Serialize`] value can be encoded, so requests and responses may be different
+/// types.
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use tokio_serial::codec::{CborCodec, LengthDelimitedCodec};
+///
+/// #[derive(Serialize, Deserialize)]
+/// enum Response {
+///     Ok,
+///     Reading { channel: u8, value: i32 },
+/// }
+///
+/// let codec = CborCodec::<Response>::new().length_delimited(LengthDelimitedCodec::new());
+/// ```
+pub struct CborCodec<T> {
+    framing: Option<LengthDelimitedCodec>,
+    max_length: usize,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> CborCodec<T> {
+    /// A codec for self-delimited values of at most [`DEFAULT_MAX_LENGTH`] bytes.
+    pub fn new() -> Self {
+        Self {
+            framing: None,
+            max_length: DEFAULT_MAX_LENGTH,
+            _value: PhantomData,
+        }
+    }
+
+    /// Prefix values with their length, as written by `framing`.
+    ///
+    /// The maximum length of values is then the one of `framing`.
+    pub fn length_delimited(mut self, framing: LengthDelimitedCodec) -> Self {
+        self.framing = Some(framing);
+        self
+    }
+
+    /// Set the maximum length of a self-delimited value.
+    pub fn max_length(mut self, len: usize) -> Self {
+        self.max_length = len;
+        self
+    }
+
+    fn deserialize(value: Value) -> Result<T, CborError>
+    where
+        T: DeserializeOwned,
+    {
+        value.deserialized().map_err(CborError::Deserialize)
+    }
+}
+
+impl<T> Default for CborCodec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for CborCodec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CborCodec")
+            .field("framing", &self.framing)
+            .field("max_length", &self.max_length)
+            .finish()
+    }
+}
+
+impl<T> Clone for CborCodec<T> {
+    fn clone(&self) -> Self {
+        Self {
+            framing: self.framing.clone(),
+            max_length: self.max_length,
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Decoder for CborCodec<T> {
+    type Item = T;
+    type Error = CborError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>, CborError> {
+        if let Some(framing) = &mut self.framing {
+            let frame = match framing.decode(src)? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+            let mut data = &frame[..];
+            let value = match ciborium::from_reader::<Value, _>(&mut data) {
+                Ok(value) if data.is_empty() => value,
+                _ => return Err(CborError::Malformed),
+            };
+            return Self::deserialize(value).map(Some);
+        }
+
+        if src.is_empty() {
+            return Ok(None);
+        }
+        // Parse a generic value first: it tells where the item ends, even when it
+        // does not match `T`.
+        let mut data = &src[..];
+        match ciborium::from_reader::<Value, _>(&mut data) {
+            Ok(value) => {
+                let consumed = src.len() - data.len();
+                src.advance(consumed);
+                Self::deserialize(value).map(Some)
+            }
+            Err(ciborium::de::Error::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                if src.len() >= self.max_length {
+                    src.clear();
+                    return Err(CborError::TooLong);
+                }
+                Ok(None)
+            }
+            Err(_) => {
+                src.advance(1);
+                Err(CborError::Malformed)
+            }
+        }
+    }
+}
+
+impl<T, U: Serialize> Encoder<U> for CborCodec<T> {
+    type Error = CborError;
+
+    fn encode(&mut self, value: U, dst: &mut BytesMut) -> Result<(), CborError> {
+        let mut data = Vec::new();
+        ciborium::into_writer(&value, &mut data).map_err(CborError::Serialize)?;
+        match &mut self.framing {
+            Some(framing) => framing.encode(&data[..], dst)?,
+            None => dst.extend_from_slice(&data),
+        }
+        Ok(())
+    }
+}
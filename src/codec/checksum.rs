@@ -0,0 +1,238 @@
+//! Checksums appended to the frames of another codec
+use super::crc::{Crc16, Crc32, Crc8};
+use super::FrameError;
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Bytes, BytesMut};
+use std::{error, fmt};
+
+/// The check appended to frames by [`ChecksumCodec`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Checksum {
+    /// An 8 bit CRC
+    Crc8(Crc8),
+    /// A 16 bit CRC
+    Crc16(Crc16),
+    /// A 32 bit CRC
+    Crc32(Crc32),
+    /// The sum of the bytes, modulo 256
+    Sum8,
+    /// The two's complement of the sum of the bytes, so that the bytes and the
+    /// checksum add up to 0
+    Sum8Complement,
+    /// The exclusive or of the bytes
+    Xor8,
+}
+
+impl Checksum {
+    /// Returns the number of bytes of the checksum.
+    pub fn size(&self) -> usize {
+        match self {
+            Checksum::Crc16(_) => 2,
+            Checksum::Crc32(_) => 4,
+            _ => 1,
+        }
+    }
+
+    /// Compute the checksum of `data`.
+    pub fn compute(&self, data: &[u8]) -> u32 {
+        let sum = || data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        match self {
+            Checksum::Crc8(crc) => u32::from(crc.checksum(data)),
+            Checksum::Crc16(crc) => u32::from(crc.checksum(data)),
+            Checksum::Crc32(crc) => crc.checksum(data),
+            Checksum::Sum8 => u32::from(sum()),
+            Checksum::Sum8Complement => u32::from(sum().wrapping_neg()),
+            Checksum::Xor8 => u32::from(data.iter().fold(0, |sum, &byte| sum ^ byte)),
+        }
+    }
+}
+
+/// Errors of [`ChecksumCodec`] frames
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChecksumError<E> {
+    /// The checksum of a frame does not match its content, it is dropped
+    Mismatch {
+        /// The checksum computed over the frame
+        expected: u32,
+        /// The checksum the frame ends with
+        found: u32,
+    },
+    /// A frame is too short to hold a checksum, it is dropped
+    TooShort(usize),
+    /// The wrapped codec failed
+    Inner(E),
+}
+
+impl<E: fmt::Display> fmt::Display for ChecksumError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChecksumError::Mismatch { expected, found } => write!(
+                f,
+                "checksum mismatch: expected {:#x}, found {:#x}",
+                expected, found
+            ),
+            ChecksumError::TooShort(len) => {
+                write!(f, "frame of {} bytes too short for its checksum", len)
+            }
+            ChecksumError::Inner(err) => err.fmt(f),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> error::Error for ChecksumError<E> {}
+
+/// A codec appending a checksum to the frames of another codec
+///
+/// `ChecksumCodec` sits between the payloads and a framing codec handing out
+/// [`BytesMut`], such as a [`FrameCodec`](super::FrameCodec) or a
+/// [`LengthDelimitedCodec`](super::LengthDelimitedCodec): encoded payloads get
+/// their checksum appended before being framed, decoded frames are checked and
+/// handed out without it.  Frames failing the check are reported as
+/// [`ChecksumError::Mismatch`] instead of being passed on, the next frames are
+/// decoded as usual.
+///
+/// Multi-byte checksums are little endian by default, as in Modbus.
+///
+/// ```
+/// use tokio_serial::codec::crc::CRC16_XMODEM;
+/// use tokio_serial::codec::slip::Slip;
+/// use tokio_serial::codec::{Checksum, ChecksumCodec, FrameCodec};
+///
+/// let codec = ChecksumCodec::new(
+///     FrameCodec::new(Slip::new(vec![0; 256])),
+///     Checksum::Crc16(CRC16_XMODEM),
+/// )
+/// .big_endian();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ChecksumCodec<C> {
+    inner: C,
+    checksum: Checksum,
+    big_endian: bool,
+}
+
+impl<C> ChecksumCodec<C> {
+    /// Append `checksum` to the frames of `inner`.
+    pub fn new(inner: C, checksum: Checksum) -> Self {
+        Self {
+            inner,
+            checksum,
+            big_endian: false,
+        }
+    }
+
+    /// Write the checksum big endian.
+    pub fn big_endian(mut self) -> Self {
+        self.big_endian = true;
+        self
+    }
+
+    /// Write the checksum little endian, the default.
+    pub fn little_endian(mut self) -> Self {
+        self.big_endian = false;
+        self
+    }
+
+    /// Returns a reference to the wrapped codec.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped codec.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Consumes the codec, returning the wrapped codec.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    fn append(&self, payload: &[u8]) -> Bytes {
+        let len = self.checksum.size();
+        let value = self.checksum.compute(payload).to_le_bytes();
+        let mut frame = BytesMut::with_capacity(payload.len() + len);
+        frame.extend_from_slice(payload);
+        if self.big_endian {
+            frame.extend(value[..len].iter().rev());
+        } else {
+            frame.extend_from_slice(&value[..len]);
+        }
+        frame.freeze()
+    }
+
+    fn check<E>(&self, mut frame: BytesMut) -> Result<BytesMut, FrameError<ChecksumError<E>>> {
+        let len = self.checksum.size();
+        if frame.len() < len {
+            return Err(FrameError::Frame(ChecksumError::TooShort(frame.len())));
+        }
+        let sent = frame.split_off(frame.len() - len);
+        let found = if self.big_endian {
+            sent.iter()
+                .fold(0u32, |value, &byte| value << 8 | u32::from(byte))
+        } else {
+            sent.iter()
+                .rev()
+                .fold(0u32, |value, &byte| value << 8 | u32::from(byte))
+        };
+        let expected = self.checksum.compute(&frame);
+        if expected != found {
+            return Err(FrameError::Frame(ChecksumError::Mismatch {
+                expected,
+                found,
+            }));
+        }
+        Ok(frame)
+    }
+}
+
+impl<C> Decoder for ChecksumCodec<C>
+where
+    C: Decoder<Item = BytesMut>,
+{
+    type Item = BytesMut;
+    type Error = FrameError<ChecksumError<C::Error>>;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>, Self::Error> {
+        let frame = self
+            .inner
+            .decode(src)
+            .map_err(|err| FrameError::Frame(ChecksumError::Inner(err)))?;
+        frame.map(|frame| self.check(frame)).transpose()
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>, Self::Error> {
+        let frame = self
+            .inner
+            .decode_eof(src)
+            .map_err(|err| FrameError::Frame(ChecksumError::Inner(err)))?;
+        frame.map(|frame| self.check(frame)).transpose()
+    }
+}
+
+impl<C> Encoder<&[u8]> for ChecksumCodec<C>
+where
+    C: Encoder<Bytes>,
+{
+    type Error = FrameError<ChecksumError<C::Error>>;
+
+    fn encode(&mut self, item: &[u8], dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let frame = self.append(item);
+        self.inner
+            .encode(frame, dst)
+            .map_err(|err| FrameError::Frame(ChecksumError::Inner(err)))
+    }
+}
+
+impl<C> Encoder<Bytes> for ChecksumCodec<C>
+where
+    C: Encoder<Bytes>,
+{
+    type Error = FrameError<ChecksumError<C::Error>>;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&item[..], dst)
+    }
+}
@@ -0,0 +1,453 @@
+//! DL/T 645-2007, the protocol of Chinese electricity meters
+//!
+//! Meters are read over RS-485, usually at 2400 baud 8E1, one master polling
+//! meters by their address, a 12 digit BCD number sent least significant byte
+//! first.  A frame is `0x68`, the address, `0x68`, a control code, the length of
+//! the data, the data with `0x33` added to each byte, the 8 bit sum of the frame
+//! and `0x16`.  Masters send a few `0xFE` bytes first to wake up the receivers.
+//!
+//! [`Dlt645Codec`] reads and writes frames, [`Dlt645Client`] sends requests and
+//! waits for the responses:
+//!
+//! ```no_run
+//! # async fn example(port: tokio_serial::SerialStream) -> Result<(), tokio_serial::codec::dlt645::Dlt645Error> {
+//! use tokio_serial::codec::dlt645::Dlt645Client;
+//!
+//! let mut meter = Dlt645Client::new(port);
+//! let address = meter.read_address().await?;
+//! // Combined active energy, BCD in 0.01 kWh
+//! let energy = meter.read_data(address, 0x0000_0000).await?;
+//! # Ok(())
+//! # }
+//! ```
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, Bytes, BytesMut};
+use std::{error, fmt, io};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{SerialFramed, SerialStream};
+#[cfg(not(target_arch = "wasm32"))]
+use futures::{SinkExt, StreamExt};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+
+/// Starts the frame, and the data after the address
+pub const START: u8 = 0x68;
+/// Ends the frame
+pub const END: u8 = 0x16;
+/// Wakes up receivers before a frame
+pub const PREAMBLE: u8 = 0xfe;
+
+// Added to every data byte on the wire.
+const DATA_OFFSET: u8 = 0x33;
+// Start, address, start, control and length.
+const HEADER_LEN: usize = 10;
+
+/// Control codes
+///
+/// The low five bits are the function, ORed with [`RESPONSE`](control::RESPONSE)
+/// in the responses of meters.
+pub mod control {
+    /// Set in the control code of responses
+    pub const RESPONSE: u8 = 0x80;
+    /// Set in the control code of responses rejecting the request
+    pub const ABNORMAL: u8 = 0x40;
+    /// Set in the control code of responses continued in a follow-up frame
+    pub const FOLLOW_UP: u8 = 0x20;
+
+    /// Broadcast the time to all meters
+    pub const BROADCAST_TIME: u8 = 0x08;
+    /// Read the value of a data identifier
+    pub const READ_DATA: u8 = 0x11;
+    /// Read the next frame of a value
+    pub const READ_FOLLOW_UP: u8 = 0x12;
+    /// Read the address of the meter, the only one on the bus
+    pub const READ_ADDRESS: u8 = 0x13;
+    /// Write the value of a data identifier
+    pub const WRITE_DATA: u8 = 0x14;
+    /// Write the address of the meter, the only one on the bus
+    pub const WRITE_ADDRESS: u8 = 0x15;
+    /// Freeze the readings
+    pub const FREEZE: u8 = 0x16;
+    /// Change the baud rate of the port
+    pub const CHANGE_BAUD_RATE: u8 = 0x17;
+}
+
+/// Errors produced by [`Dlt645Codec`] and [`Dlt645Client`]
+#[derive(Debug)]
+pub enum Dlt645Error {
+    /// The underlying I/O failed
+    Io(io::Error),
+    /// The sum of a frame does not match its content, it is dropped
+    Checksum {
+        /// The sum of the content
+        expected: u8,
+        /// The sum sent
+        found: u8,
+    },
+    /// A frame does not end with `0x16`, it is dropped
+    InvalidFrame,
+    /// The data of a frame to send is longer than 255 bytes
+    TooLong,
+    /// The meter rejected the request, with these error flags
+    Abnormal(u8),
+    /// No response was received in time
+    Timeout,
+    /// The port was closed before the response was received
+    Closed,
+}
+
+impl fmt::Display for Dlt645Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dlt645Error::Io(err) => err.fmt(f),
+            Dlt645Error::Checksum { expected, found } => write!(
+                f,
+                "DL/T 645 checksum mismatch: expected {:#04x}, found {:#04x}",
+                expected, found
+            ),
+            Dlt645Error::InvalidFrame => f.write_str("invalid DL/T 645 frame"),
+            Dlt645Error::TooLong => f.write_str("DL/T 645 data too long"),
+            Dlt645Error::Abnormal(flags) => {
+                write!(f, "DL/T 645 request rejected, error flags {:#04x}", flags)
+            }
+            Dlt645Error::Timeout => f.write_str("DL/T 645 request timed out"),
+            Dlt645Error::Closed => f.write_str("port closed before the response"),
+        }
+    }
+}
+
+impl error::Error for Dlt645Error {}
+
+impl From<io::Error> for Dlt645Error {
+    fn from(err: io::Error) -> Self {
+        Dlt645Error::Io(err)
+    }
+}
+
+/// The address of a meter, as sent: least significant byte first
+///
+/// Each byte holds two BCD digits of the 12 digit meter number.  `0xAA` bytes
+/// are wildcards, matching any two digits: masters leave out the high digits of
+/// addresses this way, or address the only meter on the bus with
+/// [`WILDCARD`](Address::WILDCARD).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Address(pub [u8; 6]);
+
+impl Address {
+    /// Addresses every meter, which do not respond
+    pub const BROADCAST: Address = Address([0x99; 6]);
+    /// Matches any meter
+    pub const WILDCARD: Address = Address([0xaa; 6]);
+
+    /// The address of meter number `number`, `None` if it has more than 12 digits.
+    pub fn from_number(mut number: u64) -> Option<Self> {
+        if number >= 1_000_000_000_000 {
+            return None;
+        }
+        let mut address = [0u8; 6];
+        for byte in &mut address {
+            *byte = (number % 10) as u8 | ((number / 10 % 10) as u8) << 4;
+            number /= 100;
+        }
+        Some(Address(address))
+    }
+
+    /// Returns whether `other` is matched by this address, wildcards included.
+    pub fn matches(&self, other: &Address) -> bool {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .all(|(&a, &b)| a == b || a == 0xaa || b == 0xaa)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0.iter().rev() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// A DL/T 645 frame
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Dlt645Frame {
+    /// The address of the meter
+    pub address: Address,
+    /// The control code, see [`control`]
+    pub control: u8,
+    /// The data, without the `0x33` offset of the wire
+    pub data: Bytes,
+}
+
+impl Dlt645Frame {
+    /// A request reading data identifier `id` of the meter at `address`.
+    pub fn read_data(address: Address, id: u32) -> Self {
+        Self {
+            address,
+            control: control::READ_DATA,
+            data: Bytes::copy_from_slice(&id.to_le_bytes()),
+        }
+    }
+
+    /// Returns the function of the frame, the control code without its flags.
+    pub fn function(&self) -> u8 {
+        self.control & 0x1f
+    }
+
+    /// Returns whether the frame is a response of a meter.
+    pub fn is_response(&self) -> bool {
+        self.control & control::RESPONSE != 0
+    }
+
+    /// Returns whether the frame is a response rejecting the request.
+    pub fn is_abnormal(&self) -> bool {
+        self.control & control::ABNORMAL != 0
+    }
+
+    /// Returns whether the response is continued in a follow-up frame.
+    pub fn has_follow_up(&self) -> bool {
+        self.control & control::FOLLOW_UP != 0
+    }
+}
+
+fn sum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// A codec for DL/T 645-2007 frames
+///
+/// Bytes before the `0x68` starting a frame, such as the wake-up preamble, are
+/// skipped.  Encoded frames are preceded by four `0xFE` bytes by default, see
+/// [`preamble`](Dlt645Codec::preamble).
+///
+/// ```
+/// use tokio_serial::codec::dlt645::{Address, Dlt645Codec, Dlt645Frame};
+/// use tokio_util::codec::Encoder;
+///
+/// let mut codec = Dlt645Codec::new().preamble(0);
+/// let mut dst = bytes::BytesMut::new();
+/// let address = Address::from_number(1).unwrap();
+/// codec.encode(Dlt645Frame::read_data(address, 0x0001_0000), &mut dst).unwrap();
+/// assert_eq!(
+///     &dst[..],
+///     b"\x68\x01\x00\x00\x00\x00\x00\x68\x11\x04\x33\x33\x34\x33\xb3\x16"
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Dlt645Codec {
+    preamble: usize,
+}
+
+impl Dlt645Codec {
+    /// A codec sending four wake-up bytes before each frame.
+    pub fn new() -> Self {
+        Self { preamble: 4 }
+    }
+
+    /// Set the number of `0xFE` bytes sent before each frame.
+    pub fn preamble(mut self, len: usize) -> Self {
+        self.preamble = len;
+        self
+    }
+}
+
+impl Default for Dlt645Codec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for Dlt645Codec {
+    type Item = Dlt645Frame;
+    type Error = Dlt645Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Dlt645Frame>, Dlt645Error> {
+        loop {
+            match src.iter().position(|&byte| byte == START) {
+                Some(start) => src.advance(start),
+                None => {
+                    src.clear();
+                    return Ok(None);
+                }
+            }
+            if src.len() < HEADER_LEN {
+                return Ok(None);
+            }
+            if src[7] != START {
+                // Not a frame, `0x68` in the noise.
+                src.advance(1);
+                continue;
+            }
+            let len = usize::from(src[9]);
+            let total = HEADER_LEN + len + 2;
+            if src.len() < total {
+                src.reserve(total - src.len());
+                return Ok(None);
+            }
+            if src[total - 1] != END {
+                src.advance(1);
+                return Err(Dlt645Error::InvalidFrame);
+            }
+            let (expected, found) = (sum(&src[..HEADER_LEN + len]), src[HEADER_LEN + len]);
+            if expected != found {
+                src.advance(1);
+                return Err(Dlt645Error::Checksum { expected, found });
+            }
+
+            let frame = src.split_to(total);
+            let mut address = [0u8; 6];
+            address.copy_from_slice(&frame[1..7]);
+            let data: Vec<u8> = frame[HEADER_LEN..HEADER_LEN + len]
+                .iter()
+                .map(|byte| byte.wrapping_sub(DATA_OFFSET))
+                .collect();
+            return Ok(Some(Dlt645Frame {
+                address: Address(address),
+                control: frame[8],
+                data: data.into(),
+            }));
+        }
+    }
+}
+
+impl Encoder<&Dlt645Frame> for Dlt645Codec {
+    type Error = Dlt645Error;
+
+    fn encode(&mut self, frame: &Dlt645Frame, dst: &mut BytesMut) -> Result<(), Dlt645Error> {
+        if frame.data.len() > usize::from(u8::MAX) {
+            return Err(Dlt645Error::TooLong);
+        }
+        dst.reserve(self.preamble + HEADER_LEN + frame.data.len() + 2);
+        dst.resize(dst.len() + self.preamble, PREAMBLE);
+        let start = dst.len();
+        dst.extend_from_slice(&[START]);
+        dst.extend_from_slice(&frame.address.0);
+        dst.extend_from_slice(&[START, frame.control, frame.data.len() as u8]);
+        dst.extend(frame.data.iter().map(|byte| byte.wrapping_add(DATA_OFFSET)));
+        let sum = sum(&dst[start..]);
+        dst.extend_from_slice(&[sum, END]);
+        Ok(())
+    }
+}
+
+impl Encoder<Dlt645Frame> for Dlt645Codec {
+    type Error = Dlt645Error;
+
+    fn encode(&mut self, frame: Dlt645Frame, dst: &mut BytesMut) -> Result<(), Dlt645Error> {
+        self.encode(&frame, dst)
+    }
+}
+
+/// Sends requests to the meters on a port
+///
+/// Requests are sent one at a time with [`request`](Dlt645Client::request), which
+/// waits for the response of the addressed meter.  Frames which are not that
+/// response, such as the echo of the request on some RS-485 adapters, are
+/// skipped.  Follow-up frames are not read: the data of responses flagged with
+/// [`has_follow_up`](Dlt645Frame::has_follow_up) is only the first part of the
+/// value.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub struct Dlt645Client {
+    framed: SerialFramed<Dlt645Codec>,
+    timeout: Duration,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Dlt645Client {
+    /// A client for the meters on `port`.
+    pub fn new(port: SerialStream) -> Self {
+        Self {
+            framed: SerialFramed::new(port, Dlt645Codec::new()),
+            timeout: Duration::from_millis(500),
+        }
+    }
+
+    /// Set how long requests wait for their response, 500ms by default.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns how long requests wait for their response.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Send `request` and wait for the response of the meter.
+    ///
+    /// ## Errors
+    ///
+    /// * `Abnormal` if the meter rejected the request.
+    /// * `Timeout` if no meter answers in time.
+    pub async fn request(&mut self, request: &Dlt645Frame) -> Result<Dlt645Frame, Dlt645Error> {
+        self.framed.send(request).await?;
+        let (function, address) = (request.function(), request.address);
+        let timeout = self.timeout;
+        let response = async {
+            loop {
+                match self.framed.next().await {
+                    Some(Ok(frame))
+                        if frame.is_response()
+                            && frame.function() == function
+                            && address.matches(&frame.address) =>
+                    {
+                        return Ok(frame)
+                    }
+                    Some(Ok(frame)) => log::debug!("skipping DL/T 645 frame {:?}", frame),
+                    Some(Err(err)) => return Err(err),
+                    None => return Err(Dlt645Error::Closed),
+                }
+            }
+        };
+        let response = tokio::time::timeout(timeout, response)
+            .await
+            .map_err(|_| Dlt645Error::Timeout)??;
+        if response.is_abnormal() {
+            return Err(Dlt645Error::Abnormal(
+                response.data.first().copied().unwrap_or(0),
+            ));
+        }
+        Ok(response)
+    }
+
+    /// Read data identifier `id` of the meter at `address`, returns its value.
+    pub async fn read_data(&mut self, address: Address, id: u32) -> Result<Bytes, Dlt645Error> {
+        let mut response = self.request(&Dlt645Frame::read_data(address, id)).await?;
+        // The value follows the identifier.
+        if response.data.get(..4) != Some(&id.to_le_bytes()[..]) {
+            return Err(Dlt645Error::InvalidFrame);
+        }
+        response.data.advance(4);
+        Ok(response.data)
+    }
+
+    /// Read the address of the only meter on the bus.
+    pub async fn read_address(&mut self) -> Result<Address, Dlt645Error> {
+        let request = Dlt645Frame {
+            address: Address::WILDCARD,
+            control: control::READ_ADDRESS,
+            data: Bytes::new(),
+        };
+        let response = self.request(&request).await?;
+        Ok(response.address)
+    }
+
+    /// Returns a reference to the underlying port.
+    pub fn get_ref(&self) -> &SerialStream {
+        self.framed.get_ref()
+    }
+
+    /// Returns a mutable reference to the underlying port.
+    pub fn get_mut(&mut self) -> &mut SerialStream {
+        self.framed.get_mut()
+    }
+
+    /// Consumes the client, returning the underlying port.
+    pub fn into_inner(self) -> SerialStream {
+        self.framed.into_inner()
+    }
+}
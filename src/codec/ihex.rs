@@ -0,0 +1,368 @@
+//! Intel HEX records
+use super::FrameError;
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::{error, fmt};
+
+/// Start of Intel HEX records
+pub const START: u8 = b':';
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const START_SEGMENT_ADDRESS: u8 = 0x03;
+const EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const START_LINEAR_ADDRESS: u8 = 0x05;
+
+/// Errors of [`IhexCodec`] records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IhexError {
+    /// The checksum of a record does not match, it is skipped
+    Checksum,
+    /// A character which is not a hexadecimal digit inside a record, or outside
+    /// records where only line breaks are allowed
+    InvalidCharacter(u8),
+    /// A record of an unknown type, or of the wrong length for its type
+    InvalidRecord(u8),
+    /// The bytes per record given to the encoder are not 1 to 255
+    InvalidRecordLength(usize),
+}
+
+impl fmt::Display for IhexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IhexError::Checksum => write!(f, "checksum mismatch in Intel HEX record"),
+            IhexError::InvalidCharacter(byte) => {
+                write!(f, "invalid character {:#04x} in Intel HEX", byte)
+            }
+            IhexError::InvalidRecord(kind) => {
+                write!(f, "invalid Intel HEX record of type {:#04x}", kind)
+            }
+            IhexError::InvalidRecordLength(len) => {
+                write!(f, "Intel HEX records cannot carry {} bytes", len)
+            }
+        }
+    }
+}
+
+impl error::Error for IhexError {}
+
+/// An Intel HEX record
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Record {
+    /// Data at `address`
+    ///
+    /// Decoded addresses are absolute: the offset of the record plus the base
+    /// of the last extended address record.  The encoder writes the extended
+    /// linear address records the address needs.
+    Data {
+        /// The address of the first byte
+        address: u32,
+        /// The data
+        data: Bytes,
+    },
+    /// The end of the file
+    EndOfFile,
+    /// The segment of the next data records, shifted by 4 bits into their base
+    ExtendedSegmentAddress(u16),
+    /// The start address of 8086 programs
+    StartSegmentAddress {
+        /// The code segment
+        cs: u16,
+        /// The instruction pointer
+        ip: u16,
+    },
+    /// The upper 16 bits of the address of the next data records
+    ExtendedLinearAddress(u16),
+    /// The start address of 32 bit programs
+    StartLinearAddress(u32),
+}
+
+/// A codec for Intel HEX records, one per line
+///
+/// The decoder checks the checksum of each record and resolves the address of
+/// data records against the extended address records before them, so firmware
+/// images can be written to flash as they are read.  Records failing their
+/// checksum are reported as [`IhexError::Checksum`].  Line breaks are skipped
+/// between records; anything else there, and invalid records, are reported
+/// and skipped up to the next record.
+///
+/// After the end of file record the decoder stops: it returns no more records
+/// and leaves what follows in the buffer, for the next codec of the
+/// bootloader protocol.  [`reset`](IhexCodec::reset) starts a new file.
+///
+/// The encoder splits data into records of 16 bytes, or of
+/// [`bytes_per_record`](IhexCodec::bytes_per_record), at 64KiB boundaries too,
+/// and writes the extended linear address records the addresses need.  Lines end
+/// with `\r\n`.
+///
+/// ```
+/// use tokio_serial::codec::ihex::{IhexCodec, Record};
+/// use tokio_util::codec::Decoder;
+/// use bytes::BytesMut;
+///
+/// let mut codec = IhexCodec::new();
+/// let mut src = BytesMut::from(&b":0200000480007A\r\n:0400100001020304E2\r\n"[..]);
+/// assert_eq!(codec.decode(&mut src).unwrap(), Some(Record::ExtendedLinearAddress(0x8000)));
+/// match codec.decode(&mut src).unwrap() {
+///     Some(Record::Data { address, data }) => {
+///         assert_eq!(address, 0x8000_0010);
+///         assert_eq!(&data[..], [1, 2, 3, 4]);
+///     }
+///     other => panic!("unexpected {:?}", other),
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct IhexCodec {
+    // The base of the addresses of data records, while decoding.
+    base: u32,
+    // The upper 16 bits of the address of data records, while encoding.
+    upper: Option<u16>,
+    bytes_per_record: usize,
+    finished: bool,
+}
+
+impl Default for IhexCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IhexCodec {
+    /// A codec writing 16 bytes per data record.
+    pub fn new() -> Self {
+        Self {
+            base: 0,
+            upper: Some(0),
+            bytes_per_record: 16,
+            finished: false,
+        }
+    }
+
+    /// Write `bytes` per data record, 1 to 255.
+    ///
+    /// Some bootloaders only accept 16 or 32.
+    pub fn bytes_per_record(mut self, bytes: usize) -> Self {
+        self.bytes_per_record = bytes;
+        self
+    }
+
+    /// Returns whether the end of file record was decoded.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Forget the file decoded and encoded so far, starting a new one.
+    pub fn reset(&mut self) {
+        self.base = 0;
+        self.upper = Some(0);
+        self.finished = false;
+    }
+
+    fn encode_record(kind: u8, offset: u16, data: &[u8], dst: &mut BytesMut) {
+        let mut raw = Vec::with_capacity(4 + data.len() + 1);
+        raw.push(data.len() as u8);
+        raw.extend_from_slice(&offset.to_be_bytes());
+        raw.push(kind);
+        raw.extend_from_slice(data);
+        raw.push(checksum(&raw));
+
+        dst.reserve(1 + 2 * raw.len() + 2);
+        dst.put_u8(START);
+        for byte in raw {
+            dst.put_u8(HEX[usize::from(byte >> 4)]);
+            dst.put_u8(HEX[usize::from(byte & 0xf)]);
+        }
+        dst.put_slice(b"\r\n");
+    }
+}
+
+const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes
+        .iter()
+        .fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+        .wrapping_neg()
+}
+
+fn digit(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+// The bytes of the hexadecimal digits in `src`.
+fn unhex(src: &[u8]) -> Result<Vec<u8>, IhexError> {
+    src.chunks(2)
+        .map(|pair| {
+            let high = digit(pair[0]).ok_or(IhexError::InvalidCharacter(pair[0]))?;
+            let low = digit(pair[1]).ok_or(IhexError::InvalidCharacter(pair[1]))?;
+            Ok(high << 4 | low)
+        })
+        .collect()
+}
+
+// Skip to the next record, the start character cannot appear inside records.
+fn skip_record(src: &mut BytesMut) {
+    let next = src[1..]
+        .iter()
+        .position(|&b| b == START)
+        .map_or(src.len(), |i| i + 1);
+    src.advance(next);
+}
+
+impl Decoder for IhexCodec {
+    type Item = Record;
+    type Error = FrameError<IhexError>;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Record>, Self::Error> {
+        if self.finished {
+            return Ok(None);
+        }
+        let skip = src
+            .iter()
+            .take_while(|&&b| b == b'\r' || b == b'\n')
+            .count();
+        src.advance(skip);
+        match src.first() {
+            None => return Ok(None),
+            Some(&START) => {}
+            Some(&byte) => {
+                skip_record(src);
+                return Err(FrameError::Frame(IhexError::InvalidCharacter(byte)));
+            }
+        }
+        if src.len() < 3 {
+            return Ok(None);
+        }
+        let len = match unhex(&src[1..3]) {
+            Ok(len) => usize::from(len[0]),
+            Err(err) => {
+                skip_record(src);
+                return Err(FrameError::Frame(err));
+            }
+        };
+        let record_len = 1 + 2 * (5 + len);
+        if src.len() < record_len {
+            src.reserve(record_len - src.len());
+            return Ok(None);
+        }
+
+        let raw = match unhex(&src[1..record_len]) {
+            Ok(raw) => raw,
+            Err(err) => {
+                skip_record(src);
+                return Err(FrameError::Frame(err));
+            }
+        };
+        if checksum(&raw[..raw.len() - 1]) != raw[raw.len() - 1] {
+            skip_record(src);
+            return Err(FrameError::Frame(IhexError::Checksum));
+        }
+        src.advance(record_len);
+        // The line break too, so that nothing of the file is left after its end.
+        if src.first() == Some(&b'\r') {
+            src.advance(1);
+        }
+        if src.first() == Some(&b'\n') {
+            src.advance(1);
+        }
+
+        let offset = u16::from_be_bytes([raw[1], raw[2]]);
+        let kind = raw[3];
+        let data = &raw[4..4 + len];
+        let word = || u16::from_be_bytes([data[0], data[1]]);
+        let record = match (kind, len) {
+            (DATA, _) => Record::Data {
+                address: self.base.wrapping_add(u32::from(offset)),
+                data: Bytes::copy_from_slice(data),
+            },
+            (END_OF_FILE, 0) => {
+                self.finished = true;
+                Record::EndOfFile
+            }
+            (EXTENDED_SEGMENT_ADDRESS, 2) => {
+                self.base = u32::from(word()) << 4;
+                Record::ExtendedSegmentAddress(word())
+            }
+            (START_SEGMENT_ADDRESS, 4) => Record::StartSegmentAddress {
+                cs: word(),
+                ip: u16::from_be_bytes([data[2], data[3]]),
+            },
+            (EXTENDED_LINEAR_ADDRESS, 2) => {
+                self.base = u32::from(word()) << 16;
+                Record::ExtendedLinearAddress(word())
+            }
+            (START_LINEAR_ADDRESS, 4) => {
+                Record::StartLinearAddress(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+            }
+            (kind, _) => return Err(FrameError::Frame(IhexError::InvalidRecord(kind))),
+        };
+        Ok(Some(record))
+    }
+}
+
+impl Encoder<&Record> for IhexCodec {
+    type Error = FrameError<IhexError>;
+
+    fn encode(&mut self, item: &Record, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        match item {
+            Record::Data { address, data } => {
+                if !(1..=255).contains(&self.bytes_per_record) {
+                    return Err(FrameError::Frame(IhexError::InvalidRecordLength(
+                        self.bytes_per_record,
+                    )));
+                }
+                let mut address = *address;
+                let mut data = &data[..];
+                while !data.is_empty() {
+                    let upper = (address >> 16) as u16;
+                    if self.upper != Some(upper) {
+                        Self::encode_record(EXTENDED_LINEAR_ADDRESS, 0, &upper.to_be_bytes(), dst);
+                        self.upper = Some(upper);
+                    }
+                    let offset = address as u16;
+                    let to_boundary = 0x1_0000 - usize::from(offset);
+                    let len = data.len().min(self.bytes_per_record).min(to_boundary);
+                    Self::encode_record(DATA, offset, &data[..len], dst);
+                    data = &data[len..];
+                    address = address.wrapping_add(len as u32);
+                }
+            }
+            Record::EndOfFile => Self::encode_record(END_OF_FILE, 0, &[], dst),
+            Record::ExtendedSegmentAddress(segment) => {
+                // Segment and linear bases do not mix, write the next linear one.
+                self.upper = None;
+                Self::encode_record(EXTENDED_SEGMENT_ADDRESS, 0, &segment.to_be_bytes(), dst)
+            }
+            Record::StartSegmentAddress { cs, ip } => {
+                let mut data = [0u8; 4];
+                data[..2].copy_from_slice(&cs.to_be_bytes());
+                data[2..].copy_from_slice(&ip.to_be_bytes());
+                Self::encode_record(START_SEGMENT_ADDRESS, 0, &data, dst)
+            }
+            Record::ExtendedLinearAddress(upper) => {
+                self.upper = Some(*upper);
+                Self::encode_record(EXTENDED_LINEAR_ADDRESS, 0, &upper.to_be_bytes(), dst)
+            }
+            Record::StartLinearAddress(address) => {
+                Self::encode_record(START_LINEAR_ADDRESS, 0, &address.to_be_bytes(), dst)
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Encoder<Record> for IhexCodec {
+    type Error = FrameError<IhexError>;
+
+    fn encode(&mut self, item: Record, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&item, dst)
+    }
+}
@@ -0,0 +1,187 @@
+//! Newline delimited JSON values
+use super::{LinesCodec, LinesCodecError};
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{BufMut, BytesMut};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+use std::{error, fmt, io, str};
+
+/// Errors produced by [`JsonLinesCodec`]
+#[derive(Debug)]
+pub enum JsonLinesError {
+    /// The underlying I/O failed
+    Io(io::Error),
+    /// A line was longer than the maximum length, it is discarded up to the next
+    /// newline
+    TooLong,
+    /// A line was not valid UTF-8, it is discarded
+    InvalidUtf8(str::Utf8Error),
+    /// A line is not a valid value, it is discarded
+    Json {
+        /// The line which failed to parse
+        line: String,
+        /// Why it failed
+        error: serde_json::Error,
+    },
+    /// A value could not be serialized
+    Serialize(serde_json::Error),
+}
+
+impl fmt::Display for JsonLinesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonLinesError::Io(err) => err.fmt(f),
+            JsonLinesError::TooLong => f.write_str("line too long"),
+            JsonLinesError::InvalidUtf8(err) => write!(f, "invalid line: {}", err),
+            JsonLinesError::Json { line, error } => {
+                write!(f, "invalid JSON line {:?}: {}", line, error)
+            }
+            JsonLinesError::Serialize(err) => write!(f, "unable to serialize value: {}", err),
+        }
+    }
+}
+
+impl error::Error for JsonLinesError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            JsonLinesError::Io(err) => Some(err),
+            JsonLinesError::InvalidUtf8(err) => Some(err),
+            JsonLinesError::Json { error, .. } | JsonLinesError::Serialize(error) => Some(error),
+            JsonLinesError::TooLong => None,
+        }
+    }
+}
+
+impl From<io::Error> for JsonLinesError {
+    fn from(err: io::Error) -> Self {
+        JsonLinesError::Io(err)
+    }
+}
+
+impl From<LinesCodecError> for JsonLinesError {
+    fn from(err: LinesCodecError) -> Self {
+        match err {
+            LinesCodecError::Io(err) => JsonLinesError::Io(err),
+            LinesCodecError::TooLong => JsonLinesError::TooLong,
+            LinesCodecError::InvalidUtf8(err) => JsonLinesError::InvalidUtf8(err),
+        }
+    }
+}
+
+/// A codec for [JSON Lines](https://jsonlines.org), one JSON value per line
+///
+/// Decoded lines are deserialized into `T`, blank lines are skipped and a `\r`
+/// before the newline is ignored.  A line which does not parse is reported as
+/// [`JsonLinesError::Json`] with its content, the following lines are decoded as
+/// usual, so a glitch on the line only costs one value.  As with [`LinesCodec`],
+/// lines are bounded by a maximum length.
+///
+/// Encoded values are written compact, followed by a `\n`.
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use tokio_serial::codec::JsonLinesCodec;
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Telemetry {
+///     temperature: f32,
+///     humidity: f32,
+/// }
+///
+/// let codec = JsonLinesCodec::<Telemetry>::new(1024);
+/// ```
+pub struct JsonLinesCodec<T> {
+    lines: LinesCodec,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> JsonLinesCodec<T> {
+    /// A codec for lines of at most `max_length` bytes, newline excluded.
+    pub fn new(max_length: usize) -> Self {
+        Self {
+            lines: LinesCodec::new(max_length),
+            _value: PhantomData,
+        }
+    }
+
+    /// Returns the maximum length of a line.
+    pub fn max_length(&self) -> usize {
+        self.lines.max_length()
+    }
+
+    fn parse(line: String) -> Result<Option<T>, JsonLinesError>
+    where
+        T: DeserializeOwned,
+    {
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+        match serde_json::from_str(&line) {
+            Ok(value) => Ok(Some(value)),
+            Err(error) => Err(JsonLinesError::Json { line, error }),
+        }
+    }
+}
+
+impl<T> fmt::Debug for JsonLinesCodec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonLinesCodec")
+            .field("lines", &self.lines)
+            .finish()
+    }
+}
+
+impl<T> Clone for JsonLinesCodec<T> {
+    fn clone(&self) -> Self {
+        Self {
+            lines: self.lines.clone(),
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Decoder for JsonLinesCodec<T> {
+    type Item = T;
+    type Error = JsonLinesError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>, JsonLinesError> {
+        while let Some(line) = self.lines.decode(src)? {
+            if let Some(value) = Self::parse(line)? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<T>, JsonLinesError> {
+        while let Some(line) = self.lines.decode_eof(src)? {
+            if let Some(value) = Self::parse(line)? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<T: Serialize> Encoder<T> for JsonLinesCodec<T> {
+    type Error = JsonLinesError;
+
+    fn encode(&mut self, value: T, dst: &mut BytesMut) -> Result<(), JsonLinesError> {
+        self.encode(&value, dst)
+    }
+}
+
+impl<T: Serialize> Encoder<&T> for JsonLinesCodec<T> {
+    type Error = JsonLinesError;
+
+    fn encode(&mut self, value: &T, dst: &mut BytesMut) -> Result<(), JsonLinesError> {
+        let line = serde_json::to_vec(value).map_err(JsonLinesError::Serialize)?;
+        dst.reserve(line.len() + 1);
+        dst.put_slice(&line);
+        dst.put_u8(b'\n');
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+//! Frames prefixed with their length
+use super::FrameError;
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::convert::TryFrom;
+use std::{error, fmt};
+
+/// Errors of [`LengthDelimitedCodec`] frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthError {
+    /// The frame is longer than the maximum frame length, it is skipped
+    TooLong(usize),
+    /// The length field gives a negative payload length
+    Invalid(u64),
+    /// The payload to encode does not fit the length field
+    Unrepresentable(usize),
+    /// The item to encode is shorter than the header before the length field
+    MissingHeader,
+}
+
+impl fmt::Display for LengthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LengthError::TooLong(len) => write!(f, "frame of {} bytes is too long", len),
+            LengthError::Invalid(field) => write!(f, "invalid length field {}", field),
+            LengthError::Unrepresentable(len) => {
+                write!(f, "payload of {} bytes does not fit the length field", len)
+            }
+            LengthError::MissingHeader => f.write_str("item shorter than the frame header"),
+        }
+    }
+}
+
+impl error::Error for LengthError {}
+
+/// A codec for frames made of a length field and a payload
+///
+/// The frame layout is `header | length | payload`, where `header` is
+/// [`length_field_offset`](LengthDelimitedCodec::length_field_offset) bytes, such
+/// as a start byte or an address, and `length` an unsigned integer of 1 to 8
+/// bytes.  By default the length is a big endian `u16` counting the payload bytes;
+/// [`length_adjustment`](LengthDelimitedCodec::length_adjustment) is added to it
+/// for protocols counting something else, e.g. -2 when the length includes
+/// itself.
+///
+/// ```
+/// use tokio_serial::codec::LengthDelimitedCodec;
+///
+/// // A start byte, a one byte length, the payload
+/// let codec = LengthDelimitedCodec::new()
+///     .length_field_offset(1)
+///     .length_field_length(1)
+///     .max_frame_length(255);
+/// ```
+///
+/// Decoded items are payloads, or whole frames with
+/// [`strip_header(false)`](LengthDelimitedCodec::strip_header).  Encoded items are
+/// payloads prefixed with their header if there is one: the length field is
+/// inserted after it.
+///
+/// Frames over [`max_frame_length`](LengthDelimitedCodec::max_frame_length),
+/// 8 KiB by default, are reported as [`LengthError::TooLong`] and skipped without
+/// being buffered.
+#[derive(Debug, Clone)]
+pub struct LengthDelimitedCodec {
+    offset: usize,
+    field_len: usize,
+    big_endian: bool,
+    adjustment: isize,
+    max_frame_length: usize,
+    strip_header: bool,
+    // Payload length of a frame whose header was parsed.
+    pending: Option<usize>,
+    // Bytes left to skip of a frame which is too long.
+    skipping: usize,
+}
+
+impl Default for LengthDelimitedCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LengthDelimitedCodec {
+    /// A codec for payloads prefixed with a big endian `u16` length.
+    pub fn new() -> Self {
+        Self {
+            offset: 0,
+            field_len: 2,
+            big_endian: true,
+            adjustment: 0,
+            max_frame_length: 8 * 1024,
+            strip_header: true,
+            pending: None,
+            skipping: 0,
+        }
+    }
+
+    /// Set the number of header bytes before the length field.
+    pub fn length_field_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Set the size of the length field in bytes.
+    ///
+    /// # Panics
+    ///
+    /// If `len` is not between 1 and 8.
+    pub fn length_field_length(mut self, len: usize) -> Self {
+        assert!((1..=8).contains(&len), "invalid length field size {}", len);
+        self.field_len = len;
+        self
+    }
+
+    /// Read and write the length field big endian, the default.
+    pub fn big_endian(mut self) -> Self {
+        self.big_endian = true;
+        self
+    }
+
+    /// Read and write the length field little endian.
+    pub fn little_endian(mut self) -> Self {
+        self.big_endian = false;
+        self
+    }
+
+    /// Set the value added to the length field to get the payload length.
+    pub fn length_adjustment(mut self, adjustment: isize) -> Self {
+        self.adjustment = adjustment;
+        self
+    }
+
+    /// Set the maximum length of a payload.
+    pub fn max_frame_length(mut self, len: usize) -> Self {
+        self.max_frame_length = len;
+        self
+    }
+
+    /// Set whether decoded items are payloads, the default, or whole frames.
+    pub fn strip_header(mut self, strip: bool) -> Self {
+        self.strip_header = strip;
+        self
+    }
+
+    fn header_len(&self) -> usize {
+        self.offset + self.field_len
+    }
+
+    fn read_field(&self, field: &[u8]) -> u64 {
+        let mut value = 0u64;
+        if self.big_endian {
+            for &byte in field {
+                value = value << 8 | u64::from(byte);
+            }
+        } else {
+            for &byte in field.iter().rev() {
+                value = value << 8 | u64::from(byte);
+            }
+        }
+        value
+    }
+
+    fn payload_len(&self, field: u64) -> Result<usize, LengthError> {
+        let len = i128::from(field) + self.adjustment as i128;
+        if len < 0 {
+            return Err(LengthError::Invalid(field));
+        }
+        usize::try_from(len).map_err(|_| LengthError::TooLong(usize::MAX))
+    }
+}
+
+impl Decoder for LengthDelimitedCodec {
+    type Item = BytesMut;
+    type Error = FrameError<LengthError>;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>, Self::Error> {
+        if self.skipping > 0 {
+            let skipped = self.skipping.min(src.len());
+            src.advance(skipped);
+            self.skipping -= skipped;
+            if self.skipping > 0 {
+                return Ok(None);
+            }
+        }
+
+        let len = match self.pending {
+            Some(len) => len,
+            None => {
+                let header_len = self.header_len();
+                if src.len() < header_len {
+                    return Ok(None);
+                }
+                let field = self.read_field(&src[self.offset..header_len]);
+                let len = match self.payload_len(field) {
+                    Ok(len) => len,
+                    Err(err) => {
+                        src.advance(header_len);
+                        return Err(FrameError::Frame(err));
+                    }
+                };
+                if len > self.max_frame_length {
+                    src.advance(header_len);
+                    let skipped = len.min(src.len());
+                    src.advance(skipped);
+                    self.skipping = len - skipped;
+                    return Err(FrameError::Frame(LengthError::TooLong(len)));
+                }
+                src.reserve(len);
+                self.pending = Some(len);
+                len
+            }
+        };
+
+        let header_len = self.header_len();
+        if src.len() < header_len + len {
+            return Ok(None);
+        }
+        self.pending = None;
+        let mut frame = src.split_to(header_len + len);
+        if self.strip_header {
+            frame.advance(header_len);
+        }
+        Ok(Some(frame))
+    }
+}
+
+impl Encoder<&[u8]> for LengthDelimitedCodec {
+    type Error = FrameError<LengthError>;
+
+    fn encode(&mut self, item: &[u8], dst: &mut BytesMut) -> Result<(), Self::Error> {
+        if item.len() < self.offset {
+            return Err(FrameError::Frame(LengthError::MissingHeader));
+        }
+        let (header, payload) = item.split_at(self.offset);
+        let len = payload.len();
+        if len > self.max_frame_length {
+            return Err(FrameError::Frame(LengthError::TooLong(len)));
+        }
+        let field = (len as i128) - self.adjustment as i128;
+        let bits = 8 * self.field_len as u32;
+        if field < 0 || (bits < 64 && field >= 1i128 << bits) || field > i128::from(u64::MAX) {
+            return Err(FrameError::Frame(LengthError::Unrepresentable(len)));
+        }
+
+        dst.reserve(self.header_len() + len);
+        dst.put_slice(header);
+        let field = field as u64;
+        if self.big_endian {
+            dst.put_uint(field, self.field_len);
+        } else {
+            dst.put_uint_le(field, self.field_len);
+        }
+        dst.put_slice(payload);
+        Ok(())
+    }
+}
+
+impl Encoder<Bytes> for LengthDelimitedCodec {
+    type Error = FrameError<LengthError>;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&item[..], dst)
+    }
+}
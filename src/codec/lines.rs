@@ -0,0 +1,215 @@
+//! Text lines with a configurable delimiter
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, BufMut, BytesMut};
+use std::{error, fmt, io, str};
+
+/// The maximum line length of [`SerialStream::lines`](crate::SerialStream::lines)
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 8 * 1024;
+
+/// The sequence ending the lines of a [`LinesCodec`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Delimiter {
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    CrLf,
+    /// `\r`
+    Cr,
+    /// Any other non-empty sequence of bytes
+    Bytes(Vec<u8>),
+}
+
+impl Delimiter {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Delimiter::Lf => b"\n",
+            Delimiter::CrLf => b"\r\n",
+            Delimiter::Cr => b"\r",
+            Delimiter::Bytes(bytes) => bytes,
+        }
+    }
+}
+
+/// Errors produced by [`LinesCodec`]
+#[derive(Debug)]
+pub enum LinesCodecError {
+    /// The underlying I/O failed
+    Io(io::Error),
+    /// A line was longer than the maximum length, it is discarded up to the next
+    /// delimiter
+    TooLong,
+    /// A line was not valid UTF-8, it is discarded
+    InvalidUtf8(str::Utf8Error),
+}
+
+impl fmt::Display for LinesCodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinesCodecError::Io(err) => err.fmt(f),
+            LinesCodecError::TooLong => f.write_str("line too long"),
+            LinesCodecError::InvalidUtf8(err) => write!(f, "invalid line: {}", err),
+        }
+    }
+}
+
+impl error::Error for LinesCodecError {}
+
+impl From<io::Error> for LinesCodecError {
+    fn from(err: io::Error) -> Self {
+        LinesCodecError::Io(err)
+    }
+}
+
+/// A codec splitting text into lines
+///
+/// Unlike `tokio_util`'s `LinesCodec` the delimiter is configurable, as devices
+/// end their lines with `\r\n`, `\r` or prompts of their own, and the length of
+/// lines is always bounded: a device sending garbage without delimiters cannot make
+/// the read buffer grow without bounds.  Lines over the limit are reported once as
+/// [`TooLong`](LinesCodecError::TooLong) and skipped.
+///
+/// ```
+/// use tokio_serial::codec::{Delimiter, LinesCodec};
+///
+/// let codec = LinesCodec::new(256).delimiter(Delimiter::CrLf);
+/// ```
+///
+/// Encoded lines get the delimiter appended.
+#[derive(Debug, Clone)]
+pub struct LinesCodec {
+    delimiter: Delimiter,
+    keep_delimiter: bool,
+    max_length: usize,
+    // Where to resume searching for the delimiter in the read buffer.
+    next_index: usize,
+    // Skipping the rest of a line which was too long.
+    discarding: bool,
+}
+
+impl LinesCodec {
+    /// A codec for `\n` terminated lines of at most `max_length` bytes, delimiter
+    /// excluded.
+    pub fn new(max_length: usize) -> Self {
+        Self {
+            delimiter: Delimiter::Lf,
+            keep_delimiter: false,
+            max_length,
+            next_index: 0,
+            discarding: false,
+        }
+    }
+
+    /// Set the sequence ending lines.
+    ///
+    /// # Panics
+    ///
+    /// If `delimiter` is an empty [`Delimiter::Bytes`].
+    pub fn delimiter(mut self, delimiter: Delimiter) -> Self {
+        assert!(
+            !delimiter.as_bytes().is_empty(),
+            "the delimiter cannot be empty"
+        );
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Set whether decoded lines end with their delimiter, `false` by default.
+    pub fn keep_delimiter(mut self, keep: bool) -> Self {
+        self.keep_delimiter = keep;
+        self
+    }
+
+    /// Returns the maximum length of a line.
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    // Position of the first delimiter in `src`, resuming the previous search.
+    fn find(&mut self, src: &[u8]) -> Option<usize> {
+        let delimiter = self.delimiter.as_bytes();
+        let found = src[self.next_index.min(src.len())..]
+            .windows(delimiter.len())
+            .position(|window| window == delimiter)
+            .map(|at| self.next_index + at);
+        // A delimiter may be split across reads.
+        self.next_index = match found {
+            Some(_) => 0,
+            None => (src.len() + 1).saturating_sub(delimiter.len()),
+        };
+        found
+    }
+}
+
+impl Decoder for LinesCodec {
+    type Item = String;
+    type Error = LinesCodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<String>, LinesCodecError> {
+        let delimiter_len = self.delimiter.as_bytes().len();
+        loop {
+            match self.find(src) {
+                Some(at) if self.discarding => {
+                    src.advance(at + delimiter_len);
+                    self.discarding = false;
+                }
+                Some(at) if at > self.max_length => {
+                    src.advance(at + delimiter_len);
+                    return Err(LinesCodecError::TooLong);
+                }
+                Some(at) => {
+                    let mut line = src.split_to(at + delimiter_len);
+                    if !self.keep_delimiter {
+                        line.truncate(at);
+                    }
+                    return match str::from_utf8(&line) {
+                        Ok(line) => Ok(Some(line.to_string())),
+                        Err(err) => Err(LinesCodecError::InvalidUtf8(err)),
+                    };
+                }
+                None if self.discarding => {
+                    // Keep what may be the start of a delimiter.
+                    src.advance(self.next_index);
+                    self.next_index = 0;
+                    return Ok(None);
+                }
+                None if src.len() > self.max_length + delimiter_len - 1 => {
+                    src.advance(self.next_index);
+                    self.next_index = 0;
+                    self.discarding = true;
+                    return Err(LinesCodecError::TooLong);
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<String>, LinesCodecError> {
+        if let Some(line) = self.decode(src)? {
+            return Ok(Some(line));
+        }
+        self.next_index = 0;
+        if src.is_empty() || std::mem::take(&mut self.discarding) {
+            src.clear();
+            return Ok(None);
+        }
+        let line = src.split();
+        match str::from_utf8(&line) {
+            Ok(line) => Ok(Some(line.to_string())),
+            Err(err) => Err(LinesCodecError::InvalidUtf8(err)),
+        }
+    }
+}
+
+impl<T: AsRef<str>> Encoder<T> for LinesCodec {
+    type Error = LinesCodecError;
+
+    fn encode(&mut self, line: T, dst: &mut BytesMut) -> Result<(), LinesCodecError> {
+        let line = line.as_ref();
+        let delimiter = self.delimiter.as_bytes();
+        dst.reserve(line.len() + delimiter.len());
+        dst.put(line.as_bytes());
+        dst.put(delimiter);
+        Ok(())
+    }
+}
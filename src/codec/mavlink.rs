@@ -0,0 +1,349 @@
+//! MAVLink v1 and v2 framing
+use super::crc::CRC16_MCRF4XX;
+use super::FrameError;
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::{error, fmt};
+
+/// Start of MAVLink v1 frames
+pub const MAGIC_V1: u8 = 0xfe;
+/// Start of MAVLink v2 frames
+pub const MAGIC_V2: u8 = 0xfd;
+
+/// The incompatibility flag of signed MAVLink v2 frames
+pub const IFLAG_SIGNED: u8 = 0x01;
+
+const SIGNATURE_LEN: usize = 13;
+// From the magic to the message id.
+const HEADER_V1: usize = 6;
+const HEADER_V2: usize = 10;
+
+/// Errors of [`MavlinkCodec`] frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MavlinkError {
+    /// The CRC of a message does not match, its frame is skipped
+    Crc {
+        /// The id of the message
+        msgid: u32,
+    },
+    /// A v2 frame sets incompatibility flags this codec does not know, it is
+    /// skipped as the specification requires
+    Incompatible(u8),
+    /// The CRC extra of a message to encode is unknown
+    UnknownMessage(u32),
+    /// The payload to encode is longer than 255 bytes
+    TooLong(usize),
+    /// The id of the message to encode does not fit a v1 frame
+    MessageIdTooLarge(u32),
+}
+
+impl fmt::Display for MavlinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MavlinkError::Crc { msgid } => write!(f, "CRC mismatch in MAVLink message {}", msgid),
+            MavlinkError::Incompatible(flags) => {
+                write!(
+                    f,
+                    "unsupported MAVLink incompatibility flags {:#04x}",
+                    flags
+                )
+            }
+            MavlinkError::UnknownMessage(msgid) => {
+                write!(f, "no CRC extra for MAVLink message {}", msgid)
+            }
+            MavlinkError::TooLong(len) => write!(f, "MAVLink payload of {} bytes is too long", len),
+            MavlinkError::MessageIdTooLarge(msgid) => {
+                write!(f, "MAVLink message id {} does not fit a v1 frame", msgid)
+            }
+        }
+    }
+}
+
+impl error::Error for MavlinkError {}
+
+/// The version of a MAVLink frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MavlinkVersion {
+    /// MAVLink 1, with 8 bit message ids
+    V1,
+    /// MAVLink 2, with 24 bit message ids, flags and optional signatures
+    V2,
+}
+
+/// A MAVLink frame, its payload left unparsed
+///
+/// Payloads of v2 frames are received as sent, without the trailing zeros the
+/// sender truncated: pad them with zeros to the length of the message before
+/// parsing.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MavlinkFrame {
+    /// The version of the frame
+    pub version: MavlinkVersion,
+    /// The incompatibility flags, 0 in v1 frames
+    ///
+    /// [`IFLAG_SIGNED`] is set by the encoder when the frame has a signature.
+    pub incompat_flags: u8,
+    /// The compatibility flags, 0 in v1 frames
+    pub compat_flags: u8,
+    /// The sequence number of the frame, per sender
+    pub seq: u8,
+    /// The system of the sender
+    pub sysid: u8,
+    /// The component of the sender within its system
+    pub compid: u8,
+    /// The id of the message
+    pub msgid: u32,
+    /// The payload of the message
+    pub payload: Bytes,
+    /// The signature of signed v2 frames, sent as is
+    pub signature: Option<[u8; SIGNATURE_LEN]>,
+}
+
+impl MavlinkFrame {
+    /// A v2 frame carrying message `msgid`.
+    pub fn new(seq: u8, sysid: u8, compid: u8, msgid: u32, payload: impl Into<Bytes>) -> Self {
+        Self {
+            version: MavlinkVersion::V2,
+            incompat_flags: 0,
+            compat_flags: 0,
+            seq,
+            sysid,
+            compid,
+            msgid,
+            payload: payload.into(),
+            signature: None,
+        }
+    }
+}
+
+type CrcExtraFn = Arc<dyn Fn(u32) -> Option<u8> + Send + Sync>;
+
+/// A codec for MAVLink v1 and v2 frames
+///
+/// The CRC of MAVLink frames covers a CRC extra byte which depends on the
+/// definition of each message, the codec looks it up with the function given to
+/// [`crc_extra`](MavlinkCodec::crc_extra), usually one generated from the dialect.
+/// Received frames of messages it knows are checked; the others, and all of them
+/// if there is no such function, are handed out unchecked.
+///
+/// Bytes outside frames are skipped.  Frames failing their CRC are reported as
+/// [`MavlinkError::Crc`], the search for the next frame restarts at the byte
+/// after their magic so that a false start does not swallow a real frame.
+///
+/// The encoder truncates the trailing zeros of v2 payloads and fills in the
+/// length, the CRC and the signed flag.  It does not sign frames: signatures are
+/// sent as given.
+///
+/// ```
+/// use tokio_serial::codec::mavlink::MavlinkCodec;
+///
+/// // HEARTBEAT, whose CRC extra is 50
+/// let codec = MavlinkCodec::new().crc_extra(|msgid| match msgid {
+///     0 => Some(50),
+///     _ => None,
+/// });
+/// ```
+#[derive(Clone, Default)]
+pub struct MavlinkCodec {
+    crc_extra: Option<CrcExtraFn>,
+}
+
+impl fmt::Debug for MavlinkCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MavlinkCodec")
+            .field("crc_extra", &self.crc_extra.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+impl MavlinkCodec {
+    /// A codec handing out frames unchecked until a CRC extra function is set.
+    pub fn new() -> Self {
+        Self { crc_extra: None }
+    }
+
+    /// Look up the CRC extra of messages with `crc_extra`, `None` for messages
+    /// unknown to the dialect.
+    pub fn crc_extra<F>(mut self, crc_extra: F) -> Self
+    where
+        F: Fn(u32) -> Option<u8> + Send + Sync + 'static,
+    {
+        self.crc_extra = Some(Arc::new(crc_extra));
+        self
+    }
+
+    fn lookup(&self, msgid: u32) -> Option<u8> {
+        self.crc_extra
+            .as_ref()
+            .and_then(|crc_extra| crc_extra(msgid))
+    }
+}
+
+// The CRC of a frame, from the byte after the magic to the end of the payload.
+fn crc(frame: &[u8], extra: u8) -> u16 {
+    let mut digest = CRC16_MCRF4XX.digest();
+    digest.update(frame);
+    digest.update(&[extra]);
+    digest.finish()
+}
+
+impl Decoder for MavlinkCodec {
+    type Item = MavlinkFrame;
+    type Error = FrameError<MavlinkError>;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<MavlinkFrame>, Self::Error> {
+        match src.iter().position(|&b| b == MAGIC_V1 || b == MAGIC_V2) {
+            Some(start) => {
+                if start > 0 {
+                    log::debug!("skipping {} bytes outside MAVLink frames", start);
+                }
+                src.advance(start);
+            }
+            None => {
+                src.clear();
+                return Ok(None);
+            }
+        }
+        let v2 = src[0] == MAGIC_V2;
+        let header_len = if v2 { HEADER_V2 } else { HEADER_V1 };
+        if src.len() < 2 {
+            return Ok(None);
+        }
+        let len = usize::from(src[1]);
+        if v2 && src.len() >= 3 && src[2] & !IFLAG_SIGNED != 0 {
+            let flags = src[2];
+            src.advance(1);
+            return Err(FrameError::Frame(MavlinkError::Incompatible(flags)));
+        }
+        let signed = v2 && src.len() >= 3 && src[2] & IFLAG_SIGNED != 0;
+        let frame_len = header_len + len + 2 + if signed { SIGNATURE_LEN } else { 0 };
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+
+        let header = &src[..header_len];
+        let frame = if v2 {
+            MavlinkFrame {
+                version: MavlinkVersion::V2,
+                incompat_flags: header[2],
+                compat_flags: header[3],
+                seq: header[4],
+                sysid: header[5],
+                compid: header[6],
+                msgid: u32::from_le_bytes([header[7], header[8], header[9], 0]),
+                payload: Bytes::new(),
+                signature: None,
+            }
+        } else {
+            MavlinkFrame {
+                version: MavlinkVersion::V1,
+                incompat_flags: 0,
+                compat_flags: 0,
+                seq: header[2],
+                sysid: header[3],
+                compid: header[4],
+                msgid: u32::from(header[5]),
+                payload: Bytes::new(),
+                signature: None,
+            }
+        };
+        let end = header_len + len;
+        if let Some(extra) = self.lookup(frame.msgid) {
+            let sent = u16::from_le_bytes([src[end], src[end + 1]]);
+            if crc(&src[1..end], extra) != sent {
+                src.advance(1);
+                return Err(FrameError::Frame(MavlinkError::Crc { msgid: frame.msgid }));
+            }
+        }
+
+        let mut raw = src.split_to(frame_len);
+        raw.advance(header_len);
+        let payload = raw.split_to(len).freeze();
+        raw.advance(2);
+        let signature = if signed {
+            let mut signature = [0u8; SIGNATURE_LEN];
+            signature.copy_from_slice(&raw);
+            Some(signature)
+        } else {
+            None
+        };
+        Ok(Some(MavlinkFrame {
+            payload,
+            signature,
+            ..frame
+        }))
+    }
+}
+
+impl Encoder<&MavlinkFrame> for MavlinkCodec {
+    type Error = FrameError<MavlinkError>;
+
+    fn encode(&mut self, item: &MavlinkFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let extra = self
+            .lookup(item.msgid)
+            .ok_or(FrameError::Frame(MavlinkError::UnknownMessage(item.msgid)))?;
+        let mut payload = &item.payload[..];
+        if payload.len() > 255 {
+            return Err(FrameError::Frame(MavlinkError::TooLong(payload.len())));
+        }
+
+        let start = dst.len();
+        match item.version {
+            MavlinkVersion::V1 => {
+                let msgid = u8::try_from(item.msgid)
+                    .map_err(|_| FrameError::Frame(MavlinkError::MessageIdTooLarge(item.msgid)))?;
+                dst.reserve(HEADER_V1 + payload.len() + 2);
+                dst.put_u8(MAGIC_V1);
+                dst.put_u8(payload.len() as u8);
+                dst.put_u8(item.seq);
+                dst.put_u8(item.sysid);
+                dst.put_u8(item.compid);
+                dst.put_u8(msgid);
+            }
+            MavlinkVersion::V2 => {
+                if item.msgid > 0x00ff_ffff {
+                    return Err(FrameError::Frame(MavlinkError::MessageIdTooLarge(
+                        item.msgid,
+                    )));
+                }
+                // Trailing zeros are not sent, but the first payload byte is.
+                while payload.len() > 1 && payload[payload.len() - 1] == 0 {
+                    payload = &payload[..payload.len() - 1];
+                }
+                let mut flags = item.incompat_flags & !IFLAG_SIGNED;
+                if item.signature.is_some() {
+                    flags |= IFLAG_SIGNED;
+                }
+                dst.reserve(HEADER_V2 + payload.len() + 2 + SIGNATURE_LEN);
+                dst.put_u8(MAGIC_V2);
+                dst.put_u8(payload.len() as u8);
+                dst.put_u8(flags);
+                dst.put_u8(item.compat_flags);
+                dst.put_u8(item.seq);
+                dst.put_u8(item.sysid);
+                dst.put_u8(item.compid);
+                dst.put_slice(&item.msgid.to_le_bytes()[..3]);
+            }
+        }
+        dst.put_slice(payload);
+        let crc = crc(&dst[start + 1..], extra);
+        dst.put_u16_le(crc);
+        if let (MavlinkVersion::V2, Some(signature)) = (item.version, &item.signature) {
+            dst.put_slice(signature);
+        }
+        Ok(())
+    }
+}
+
+impl Encoder<MavlinkFrame> for MavlinkCodec {
+    type Error = FrameError<MavlinkError>;
+
+    fn encode(&mut self, item: MavlinkFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&item, dst)
+    }
+}
@@ -0,0 +1,434 @@
+//! Modbus RTU slave runtime
+//!
+//! A [`ModbusSlave`] answers the requests a master sends to its unit id on a
+//! [`ModbusRtuFramed`] port, dispatching the function codes to a
+//! [`ModbusBackend`] holding the coils and registers.  Broadcast writes, to unit
+//! id 0, are executed without answering.  [`RegisterBank`] is a backend keeping
+//! everything in memory, enough to emulate most devices.
+//!
+//! The PDUs are handled by [`handle_pdu`], which bridges to other transports can
+//! use on their own.
+//!
+//! ```no_run
+//! use tokio_serial::codec::modbus::{ModbusSlave, RegisterBank};
+//! use tokio_serial::codec::ModbusRtuFramed;
+//!
+//! # async fn example(port: tokio_serial::SerialStream) -> std::io::Result<()> {
+//! let mut bank = RegisterBank::new(0, 0, 16, 0);
+//! bank.holding_registers[0] = 230;
+//! let mut slave = ModbusSlave::new(ModbusRtuFramed::new(port)?, 17, bank);
+//! slave.run().await
+//! # }
+//! ```
+use super::{FrameError, ModbusRtuFramed};
+
+use futures::{SinkExt, StreamExt};
+
+use std::time::Duration;
+use std::{fmt, io};
+
+/// The unit id masters send broadcasts to
+pub const BROADCAST: u8 = 0;
+
+/// The function codes handled
+pub mod function {
+    /// Read Coils
+    pub const READ_COILS: u8 = 0x01;
+    /// Read Discrete Inputs
+    pub const READ_DISCRETE_INPUTS: u8 = 0x02;
+    /// Read Holding Registers
+    pub const READ_HOLDING_REGISTERS: u8 = 0x03;
+    /// Read Input Registers
+    pub const READ_INPUT_REGISTERS: u8 = 0x04;
+    /// Write Single Coil
+    pub const WRITE_SINGLE_COIL: u8 = 0x05;
+    /// Write Single Register
+    pub const WRITE_SINGLE_REGISTER: u8 = 0x06;
+    /// Write Multiple Coils
+    pub const WRITE_MULTIPLE_COILS: u8 = 0x0f;
+    /// Write Multiple Registers
+    pub const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+}
+
+/// The exceptions a slave answers failed requests with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Exception {
+    /// The function code is not supported
+    IllegalFunction,
+    /// The address range is not valid for the slave
+    IllegalDataAddress,
+    /// A value of the request is not valid
+    IllegalDataValue,
+    /// The slave failed performing the request
+    ServerDeviceFailure,
+    /// The slave accepted the request and needs a long time to perform it
+    Acknowledge,
+    /// The slave is busy with a long request
+    ServerDeviceBusy,
+}
+
+impl Exception {
+    /// Returns the exception code sent to the master.
+    pub fn code(self) -> u8 {
+        match self {
+            Exception::IllegalFunction => 0x01,
+            Exception::IllegalDataAddress => 0x02,
+            Exception::IllegalDataValue => 0x03,
+            Exception::ServerDeviceFailure => 0x04,
+            Exception::Acknowledge => 0x05,
+            Exception::ServerDeviceBusy => 0x06,
+        }
+    }
+}
+
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Exception::IllegalFunction => "illegal function",
+            Exception::IllegalDataAddress => "illegal data address",
+            Exception::IllegalDataValue => "illegal data value",
+            Exception::ServerDeviceFailure => "server device failure",
+            Exception::Acknowledge => "acknowledge",
+            Exception::ServerDeviceBusy => "server device busy",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The data model of a slave
+///
+/// Addresses are the 0-based addresses of the PDUs.  Reads return exactly `count`
+/// values.  Every function answers `IllegalFunction` unless implemented.
+pub trait ModbusBackend {
+    /// Read `count` coils from `address`.
+    fn read_coils(&mut self, _address: u16, _count: u16) -> Result<Vec<bool>, Exception> {
+        Err(Exception::IllegalFunction)
+    }
+
+    /// Read `count` discrete inputs from `address`.
+    fn read_discrete_inputs(&mut self, _address: u16, _count: u16) -> Result<Vec<bool>, Exception> {
+        Err(Exception::IllegalFunction)
+    }
+
+    /// Read `count` holding registers from `address`.
+    fn read_holding_registers(
+        &mut self,
+        _address: u16,
+        _count: u16,
+    ) -> Result<Vec<u16>, Exception> {
+        Err(Exception::IllegalFunction)
+    }
+
+    /// Read `count` input registers from `address`.
+    fn read_input_registers(&mut self, _address: u16, _count: u16) -> Result<Vec<u16>, Exception> {
+        Err(Exception::IllegalFunction)
+    }
+
+    /// Write `values` to the coils from `address`.
+    fn write_coils(&mut self, _address: u16, _values: &[bool]) -> Result<(), Exception> {
+        Err(Exception::IllegalFunction)
+    }
+
+    /// Write `values` to the holding registers from `address`.
+    fn write_registers(&mut self, _address: u16, _values: &[u16]) -> Result<(), Exception> {
+        Err(Exception::IllegalFunction)
+    }
+}
+
+/// A backend keeping the coils and registers in memory
+///
+/// The tables cover addresses from 0 up to their length, the others are illegal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegisterBank {
+    /// The coils, read and written by the master
+    pub coils: Vec<bool>,
+    /// The discrete inputs, read by the master
+    pub discrete_inputs: Vec<bool>,
+    /// The holding registers, read and written by the master
+    pub holding_registers: Vec<u16>,
+    /// The input registers, read by the master
+    pub input_registers: Vec<u16>,
+}
+
+impl RegisterBank {
+    /// A bank with the given number of coils, discrete inputs, holding registers and
+    /// input registers, all zero.
+    pub fn new(coils: usize, discrete_inputs: usize, holding: usize, input: usize) -> Self {
+        Self {
+            coils: vec![false; coils],
+            discrete_inputs: vec![false; discrete_inputs],
+            holding_registers: vec![0; holding],
+            input_registers: vec![0; input],
+        }
+    }
+}
+
+fn range<T>(table: &[T], address: u16, count: usize) -> Result<std::ops::Range<usize>, Exception> {
+    let start = usize::from(address);
+    match start.checked_add(count) {
+        Some(end) if end <= table.len() => Ok(start..end),
+        _ => Err(Exception::IllegalDataAddress),
+    }
+}
+
+impl ModbusBackend for RegisterBank {
+    fn read_coils(&mut self, address: u16, count: u16) -> Result<Vec<bool>, Exception> {
+        let range = range(&self.coils, address, count.into())?;
+        Ok(self.coils[range].to_vec())
+    }
+
+    fn read_discrete_inputs(&mut self, address: u16, count: u16) -> Result<Vec<bool>, Exception> {
+        let range = range(&self.discrete_inputs, address, count.into())?;
+        Ok(self.discrete_inputs[range].to_vec())
+    }
+
+    fn read_holding_registers(&mut self, address: u16, count: u16) -> Result<Vec<u16>, Exception> {
+        let range = range(&self.holding_registers, address, count.into())?;
+        Ok(self.holding_registers[range].to_vec())
+    }
+
+    fn read_input_registers(&mut self, address: u16, count: u16) -> Result<Vec<u16>, Exception> {
+        let range = range(&self.input_registers, address, count.into())?;
+        Ok(self.input_registers[range].to_vec())
+    }
+
+    fn write_coils(&mut self, address: u16, values: &[bool]) -> Result<(), Exception> {
+        let range = range(&self.coils, address, values.len())?;
+        self.coils[range].copy_from_slice(values);
+        Ok(())
+    }
+
+    fn write_registers(&mut self, address: u16, values: &[u16]) -> Result<(), Exception> {
+        let range = range(&self.holding_registers, address, values.len())?;
+        self.holding_registers[range].copy_from_slice(values);
+        Ok(())
+    }
+}
+
+fn word(pdu: &[u8], at: usize) -> Result<u16, Exception> {
+    match pdu.get(at..at + 2) {
+        Some(&[hi, lo]) => Ok(u16::from_be_bytes([hi, lo])),
+        _ => Err(Exception::IllegalDataValue),
+    }
+}
+
+fn pack_bits(function: u8, bits: &[bool], count: u16) -> Result<Vec<u8>, Exception> {
+    if bits.len() != usize::from(count) {
+        return Err(Exception::ServerDeviceFailure);
+    }
+    let mut response = vec![function, bits.len().div_ceil(8) as u8];
+    for chunk in bits.chunks(8) {
+        let byte = chunk
+            .iter()
+            .enumerate()
+            .fold(0u8, |byte, (i, &bit)| byte | (u8::from(bit) << i));
+        response.push(byte);
+    }
+    Ok(response)
+}
+
+fn pack_registers(function: u8, registers: &[u16], count: u16) -> Result<Vec<u8>, Exception> {
+    if registers.len() != usize::from(count) {
+        return Err(Exception::ServerDeviceFailure);
+    }
+    let mut response = vec![function, (registers.len() * 2) as u8];
+    for register in registers {
+        response.extend_from_slice(&register.to_be_bytes());
+    }
+    Ok(response)
+}
+
+fn dispatch<B: ModbusBackend + ?Sized>(backend: &mut B, pdu: &[u8]) -> Result<Vec<u8>, Exception> {
+    use function::*;
+
+    let function = pdu[0];
+    match function {
+        READ_COILS | READ_DISCRETE_INPUTS => {
+            let (address, count) = (word(pdu, 1)?, word(pdu, 3)?);
+            if !(1..=2000).contains(&count) {
+                return Err(Exception::IllegalDataValue);
+            }
+            let bits = if function == READ_COILS {
+                backend.read_coils(address, count)?
+            } else {
+                backend.read_discrete_inputs(address, count)?
+            };
+            pack_bits(function, &bits, count)
+        }
+        READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS => {
+            let (address, count) = (word(pdu, 1)?, word(pdu, 3)?);
+            if !(1..=125).contains(&count) {
+                return Err(Exception::IllegalDataValue);
+            }
+            let registers = if function == READ_HOLDING_REGISTERS {
+                backend.read_holding_registers(address, count)?
+            } else {
+                backend.read_input_registers(address, count)?
+            };
+            pack_registers(function, &registers, count)
+        }
+        WRITE_SINGLE_COIL => {
+            let value = match word(pdu, 3)? {
+                0xff00 => true,
+                0x0000 => false,
+                _ => return Err(Exception::IllegalDataValue),
+            };
+            backend.write_coils(word(pdu, 1)?, &[value])?;
+            Ok(pdu[..5].to_vec())
+        }
+        WRITE_SINGLE_REGISTER => {
+            backend.write_registers(word(pdu, 1)?, &[word(pdu, 3)?])?;
+            Ok(pdu[..5].to_vec())
+        }
+        WRITE_MULTIPLE_COILS => {
+            let (address, count) = (word(pdu, 1)?, word(pdu, 3)?);
+            let bytes = usize::from(count).div_ceil(8);
+            if !(1..=1968).contains(&count)
+                || pdu.get(5).map(|&n| usize::from(n)) != Some(bytes)
+                || pdu.len() < 6 + bytes
+            {
+                return Err(Exception::IllegalDataValue);
+            }
+            let values: Vec<bool> = (0..usize::from(count))
+                .map(|i| pdu[6 + i / 8] & (1 << (i % 8)) != 0)
+                .collect();
+            backend.write_coils(address, &values)?;
+            Ok(pdu[..5].to_vec())
+        }
+        WRITE_MULTIPLE_REGISTERS => {
+            let (address, count) = (word(pdu, 1)?, word(pdu, 3)?);
+            let bytes = usize::from(count) * 2;
+            if !(1..=123).contains(&count)
+                || pdu.get(5).map(|&n| usize::from(n)) != Some(bytes)
+                || pdu.len() < 6 + bytes
+            {
+                return Err(Exception::IllegalDataValue);
+            }
+            let values: Vec<u16> = pdu[6..6 + bytes]
+                .chunks(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            backend.write_registers(address, &values)?;
+            Ok(pdu[..5].to_vec())
+        }
+        _ => Err(Exception::IllegalFunction),
+    }
+}
+
+/// Returns whether `function` writes, the only functions allowed in broadcasts.
+pub fn is_write(function: u8) -> bool {
+    use function::*;
+
+    matches!(
+        function,
+        WRITE_SINGLE_COIL | WRITE_SINGLE_REGISTER | WRITE_MULTIPLE_COILS | WRITE_MULTIPLE_REGISTERS
+    )
+}
+
+/// Perform the request `pdu` on `backend`, returning the response PDU.
+///
+/// Failed requests get an exception response: the function code with its high bit
+/// set, then the [exception code](Exception::code).
+///
+/// ## Panics
+///
+/// If `pdu` is empty.
+pub fn handle_pdu<B: ModbusBackend + ?Sized>(backend: &mut B, pdu: &[u8]) -> Vec<u8> {
+    match dispatch(backend, pdu) {
+        Ok(response) => response,
+        Err(exception) => vec![pdu[0] | 0x80, exception.code()],
+    }
+}
+
+/// A Modbus RTU slave answering to one unit id
+#[derive(Debug)]
+pub struct ModbusSlave<B> {
+    port: ModbusRtuFramed,
+    unit_id: u8,
+    backend: B,
+    response_delay: Duration,
+}
+
+impl<B: ModbusBackend> ModbusSlave<B> {
+    /// Answer the requests to `unit_id` received on `port` from `backend`.
+    pub fn new(port: ModbusRtuFramed, unit_id: u8, backend: B) -> Self {
+        Self {
+            port,
+            unit_id,
+            backend,
+            response_delay: Duration::ZERO,
+        }
+    }
+
+    /// Set the time to wait before answering, none by default.
+    ///
+    /// Responses wait for the silent interval in any case, some masters need more
+    /// to turn their transceiver around.
+    pub fn set_response_delay(&mut self, delay: Duration) {
+        self.response_delay = delay;
+    }
+
+    /// Returns the unit id the slave answers to.
+    pub fn unit_id(&self) -> u8 {
+        self.unit_id
+    }
+
+    /// Returns a reference to the backend.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Returns a mutable reference to the backend.
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    /// Consumes the slave, returning the port and the backend.
+    pub fn into_parts(self) -> (ModbusRtuFramed, B) {
+        (self.port, self.backend)
+    }
+
+    /// Answer requests until the port is closed.
+    ///
+    /// Corrupted frames and requests to other unit ids are ignored, like broadcasts
+    /// of functions which do not write.
+    ///
+    /// ## Errors
+    ///
+    /// The errors of the port.
+    pub async fn run(&mut self) -> io::Result<()> {
+        while let Some(frame) = self.port.next().await {
+            let adu = match frame {
+                Ok(adu) => adu,
+                Err(FrameError::Io(e)) => return Err(e),
+                Err(FrameError::Frame(_)) => continue,
+            };
+            // Address, PDU and CRC
+            let (address, pdu) = (adu[0], &adu[1..adu.len() - 2]);
+            if address == BROADCAST {
+                if is_write(pdu[0]) {
+                    let _ = handle_pdu(&mut self.backend, pdu);
+                }
+                continue;
+            }
+            if address != self.unit_id {
+                continue;
+            }
+
+            let mut response = vec![address];
+            response.extend(handle_pdu(&mut self.backend, pdu));
+            if !self.response_delay.is_zero() {
+                tokio::time::sleep(self.response_delay).await;
+            }
+            match self.port.send(&response[..]).await {
+                Ok(()) => {}
+                Err(FrameError::Io(e)) => return Err(e),
+                // Responses always fit in a frame.
+                Err(FrameError::Frame(e)) => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,908 @@
+//! A cellular modem manager over AT commands
+//!
+//! [`ModemManager`] brings a GSM, LTE or NB-IoT module up and drives it with the
+//! standard commands of 3GPP TS 27.005 and 27.007: PIN entry, signal quality and
+//! network registration, voice calls and SMS, in text or PDU mode.  What the
+//! modem reports by itself, such as an incoming call or a new message, is read
+//! from the [`ModemEvents`] stream returned with the manager.
+//!
+//! ```no_run
+//! # async fn example(port: tokio_serial::SerialStream) -> Result<(), tokio_serial::codec::modem::ModemError> {
+//! use futures::StreamExt;
+//! use tokio_serial::codec::modem::{ModemEvent, ModemManager};
+//!
+//! let (mut modem, mut events) = ModemManager::new(port);
+//! modem.set_pin("1234");
+//! modem.initialize().await?;
+//! modem.send_sms("+31612345678", "hello").await?;
+//!
+//! loop {
+//!     tokio::select! {
+//!         closed = modem.listen() => return closed,
+//!         Some(event) = events.next() => {
+//!             if let ModemEvent::SmsStored { index, .. } = event {
+//!                 println!("{}", modem.read_sms(index).await?.text);
+//!             }
+//!         }
+//!     }
+//! }
+//! # }
+//! ```
+use super::at::{AtClient, AtError, AtResponse, AtResult, Urcs};
+use crate::SerialStream;
+
+use futures::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{error, fmt};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+/// The commands [`ModemManager::initialize`] runs by default before unlocking the
+/// SIM card: no echo, and numeric `+CME ERROR` codes
+pub const DEFAULT_INIT_SEQUENCE: &[&str] = &["ATE0", "AT+CMEE=1"];
+
+/// Errors produced by [`ModemManager`]
+#[derive(Debug)]
+pub enum ModemError {
+    /// Running the command failed
+    At(AtError),
+    /// The modem answered a command with an error
+    Command {
+        /// The command
+        command: String,
+        /// The final result code of the response
+        result: AtResult,
+    },
+    /// The SIM card waits for a code the manager was not given
+    Locked(PinStatus),
+    /// The message does not fit in an SMS, or contains Ctrl-Z or Escape, which
+    /// would end it early
+    InvalidSms,
+    /// The response to a command could not be understood
+    Unexpected(String),
+}
+
+impl fmt::Display for ModemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModemError::At(err) => err.fmt(f),
+            ModemError::Command { command, result } => write!(f, "{} failed: {}", command, result),
+            ModemError::Locked(status) => write!(f, "SIM card locked: {:?}", status),
+            ModemError::InvalidSms => f.write_str("message unfit for an SMS"),
+            ModemError::Unexpected(line) => write!(f, "unexpected response: {}", line),
+        }
+    }
+}
+
+impl error::Error for ModemError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ModemError::At(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<AtError> for ModemError {
+    fn from(err: AtError) -> Self {
+        ModemError::At(err)
+    }
+}
+
+/// What the SIM card waits for, from `AT+CPIN?`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PinStatus {
+    /// Nothing, the card is unlocked
+    Ready,
+    /// The PIN
+    SimPin,
+    /// The PUK, after too many wrong PINs
+    SimPuk,
+    /// The PIN2
+    SimPin2,
+    /// The PUK2
+    SimPuk2,
+    /// Another code, such as `PH-SIM PIN`
+    Other(String),
+}
+
+impl PinStatus {
+    fn parse(code: &str) -> Self {
+        match code {
+            "READY" => PinStatus::Ready,
+            "SIM PIN" => PinStatus::SimPin,
+            "SIM PUK" => PinStatus::SimPuk,
+            "SIM PIN2" => PinStatus::SimPin2,
+            "SIM PUK2" => PinStatus::SimPuk2,
+            _ => PinStatus::Other(code.to_string()),
+        }
+    }
+}
+
+/// The signal quality, from `AT+CSQ`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SignalQuality {
+    /// The received signal strength in dBm, -113 to -51, `None` if unknown
+    pub rssi: Option<i16>,
+    /// The bit error rate, 0 to 7, `None` if unknown
+    pub ber: Option<u8>,
+}
+
+/// The network registration status, from `+CREG`, `+CGREG` and `+CEREG`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Registration {
+    /// Not registered, and not searching
+    NotRegistered,
+    /// Registered on the home network
+    Home,
+    /// Not registered, searching for a network
+    Searching,
+    /// Registration denied
+    Denied,
+    /// Unknown
+    Unknown,
+    /// Registered, roaming
+    Roaming,
+    /// Another status, such as 6 for "SMS only"
+    Other(u8),
+}
+
+impl Registration {
+    fn parse(stat: &str) -> Option<Self> {
+        Some(match stat.parse().ok()? {
+            0 => Registration::NotRegistered,
+            1 => Registration::Home,
+            2 => Registration::Searching,
+            3 => Registration::Denied,
+            4 => Registration::Unknown,
+            5 => Registration::Roaming,
+            other => Registration::Other(other),
+        })
+    }
+
+    /// Returns whether the modem is registered, at home or roaming.
+    pub fn is_registered(&self) -> bool {
+        matches!(self, Registration::Home | Registration::Roaming)
+    }
+}
+
+/// How SMS are exchanged with the modem, set with `AT+CMGF`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SmsMode {
+    /// As hex encoded PDUs, decoded by the manager
+    Pdu,
+    /// As text, decoded by the modem
+    Text,
+}
+
+/// A short message
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Sms {
+    /// The sender, or the recipient of messages stored before they are sent
+    pub address: String,
+    /// When the service center received the message, `yy/MM/dd,hh:mm:ss±zz` with
+    /// the time zone in quarters of an hour
+    pub timestamp: Option<String>,
+    /// The text
+    pub text: String,
+}
+
+/// What a modem reports by itself, see [`ModemEvents`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ModemEvent {
+    /// An incoming call is ringing, `RING` or `+CRING`
+    Ring,
+    /// The number of the caller, `+CLIP`
+    CallerId(String),
+    /// The call or data connection ended, `NO CARRIER`
+    CallEnded,
+    /// A message was received and stored, `+CMTI`, read it with
+    /// [`read_sms`](ModemManager::read_sms)
+    SmsStored {
+        /// The storage, such as `SM` for the SIM card
+        storage: String,
+        /// The index of the message in the storage
+        index: u32,
+    },
+    /// A message was received and not stored, `+CMT`
+    Sms(Sms),
+    /// The network registration changed, `+CREG`, `+CGREG` or `+CEREG`
+    Registration(Registration),
+    /// The signal quality polled by [`listen`](ModemManager::listen)
+    Signal(SignalQuality),
+    /// Any other unsolicited result code
+    Other(String),
+}
+
+/// The events of a [`ModemManager`]
+///
+/// Like the URCs of an [`AtClient`], events are only read while the manager runs
+/// a command or [`listen`](ModemManager::listen) is polled.  The stream ends once
+/// the manager is dropped.
+#[derive(Debug)]
+pub struct ModemEvents {
+    urcs: Option<Urcs>,
+    events: mpsc::UnboundedReceiver<ModemEvent>,
+    // The header of a `+CMT` whose message is the next line.
+    message: Option<String>,
+}
+
+impl ModemEvents {
+    fn event(&mut self, urc: String) -> Option<ModemEvent> {
+        if let Some(header) = self.message.take() {
+            return match parse_message(&params(&header), &urc, true) {
+                Some(sms) => Some(ModemEvent::Sms(sms)),
+                None => {
+                    log::debug!("dropping undecodable message: {}", urc);
+                    None
+                }
+            };
+        }
+        let (name, rest) = match urc.split_once(':') {
+            Some((name, rest)) => (name, rest.trim()),
+            None => (urc.as_str(), ""),
+        };
+        let params = params(rest);
+        let event = match name {
+            "RING" | "+CRING" => ModemEvent::Ring,
+            "NO CARRIER" => ModemEvent::CallEnded,
+            "+CLIP" if !params.is_empty() => ModemEvent::CallerId(params[0].clone()),
+            "+CMTI" if params.len() >= 2 => match params[1].parse() {
+                Ok(index) => ModemEvent::SmsStored {
+                    storage: params[0].clone(),
+                    index,
+                },
+                Err(_) => ModemEvent::Other(urc),
+            },
+            "+CMT" => {
+                self.message = Some(rest.to_string());
+                return None;
+            }
+            // The status comes first in URCs, it follows the mode in responses
+            "+CREG" | "+CGREG" | "+CEREG" => {
+                match params.first().and_then(|stat| Registration::parse(stat)) {
+                    Some(registration) => ModemEvent::Registration(registration),
+                    None => ModemEvent::Other(urc),
+                }
+            }
+            _ => ModemEvent::Other(urc),
+        };
+        Some(event)
+    }
+}
+
+impl Stream for ModemEvents {
+    type Item = ModemEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ModemEvent>> {
+        let this = self.get_mut();
+        if let Poll::Ready(Some(event)) = this.events.poll_recv(cx) {
+            return Poll::Ready(Some(event));
+        }
+        while let Some(urcs) = &mut this.urcs {
+            match Pin::new(urcs).poll_next(cx) {
+                Poll::Ready(Some(urc)) => {
+                    if let Some(event) = this.event(urc) {
+                        return Poll::Ready(Some(event));
+                    }
+                }
+                Poll::Ready(None) => this.urcs = None,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        this.events.poll_recv(cx)
+    }
+}
+
+/// Manages a cellular modem
+///
+/// [`initialize`](ModemManager::initialize) synchronizes with the modem, runs the
+/// initialization sequence, unlocks the SIM card with the PIN given to
+/// [`set_pin`](ModemManager::set_pin), then selects the SMS mode and enables the
+/// `+CMTI` and `+CREG` notifications.  Commands failing on the modem are
+/// [`ModemError::Command`] errors.
+///
+/// Other commands are run through the [`AtClient`], see
+/// [`get_mut`](ModemManager::get_mut).
+#[derive(Debug)]
+pub struct ModemManager {
+    client: AtClient,
+    events: mpsc::UnboundedSender<ModemEvent>,
+    init: Vec<String>,
+    pin: Option<String>,
+    sms_mode: SmsMode,
+    sms_timeout: Duration,
+    signal_interval: Option<Duration>,
+    next_signal: Instant,
+}
+
+impl ModemManager {
+    /// A manager for the modem on `port`.
+    pub fn new(port: SerialStream) -> (Self, ModemEvents) {
+        let (client, urcs) = AtClient::new(port);
+        Self::with_client(client, urcs)
+    }
+
+    /// A manager running its commands with `client`, whose URCs are `urcs`.
+    pub fn with_client(client: AtClient, urcs: Urcs) -> (Self, ModemEvents) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let manager = Self {
+            client,
+            events: tx,
+            init: DEFAULT_INIT_SEQUENCE
+                .iter()
+                .map(|c| c.to_string())
+                .collect(),
+            pin: None,
+            sms_mode: SmsMode::Pdu,
+            sms_timeout: Duration::from_secs(60),
+            signal_interval: None,
+            next_signal: Instant::now(),
+        };
+        let events = ModemEvents {
+            urcs: Some(urcs),
+            events: rx,
+            message: None,
+        };
+        (manager, events)
+    }
+
+    /// Set the commands run before unlocking the SIM card, replacing
+    /// [`DEFAULT_INIT_SEQUENCE`].
+    pub fn set_init_sequence<I, S>(&mut self, commands: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.init = commands.into_iter().map(Into::into).collect();
+    }
+
+    /// Set the PIN unlocking the SIM card.
+    pub fn set_pin(&mut self, pin: impl Into<String>) {
+        self.pin = Some(pin.into());
+    }
+
+    /// Set how SMS are exchanged with the modem, [`SmsMode::Pdu`] by default.
+    ///
+    /// This takes effect with the next [`initialize`](ModemManager::initialize).
+    pub fn set_sms_mode(&mut self, mode: SmsMode) {
+        self.sms_mode = mode;
+    }
+
+    /// Returns how SMS are exchanged with the modem.
+    pub fn sms_mode(&self) -> SmsMode {
+        self.sms_mode
+    }
+
+    /// Set how long sending an SMS may take, 60 seconds by default.
+    pub fn set_sms_timeout(&mut self, timeout: Duration) {
+        self.sms_timeout = timeout;
+    }
+
+    /// Set how often [`listen`](ModemManager::listen) polls the signal quality,
+    /// never by default.  The first poll is one interval from now.
+    pub fn set_signal_interval(&mut self, interval: Option<Duration>) {
+        self.signal_interval = interval;
+        if let Some(interval) = interval {
+            self.next_signal = Instant::now() + interval;
+        }
+    }
+
+    /// Run `command`, failing if the modem answers with an error.
+    pub async fn command(&mut self, command: &str) -> Result<AtResponse, ModemError> {
+        let response = self.client.command(command).await?;
+        checked(command, response)
+    }
+
+    /// Bring the modem up, see [`ModemManager`].
+    pub async fn initialize(&mut self) -> Result<(), ModemError> {
+        // The first commands may be lost while the modem autobauds or boots.
+        for attempt in 1.. {
+            match self.client.command("AT").await {
+                Err(AtError::Timeout) if attempt < 3 => {}
+                response => {
+                    checked("AT", response?)?;
+                    break;
+                }
+            }
+        }
+        for command in self.init.clone() {
+            self.command(&command).await?;
+        }
+        self.unlock().await?;
+        let mode = match self.sms_mode {
+            SmsMode::Pdu => "AT+CMGF=0",
+            SmsMode::Text => "AT+CMGF=1",
+        };
+        self.command(mode).await?;
+        self.command("AT+CNMI=2,1,0,0,0").await?;
+        self.command("AT+CREG=1").await?;
+        Ok(())
+    }
+
+    /// Returns what the SIM card waits for.
+    pub async fn pin_status(&mut self) -> Result<PinStatus, ModemError> {
+        let response = self.command("AT+CPIN?").await?;
+        let code = info(&response, "+CPIN:")?;
+        Ok(PinStatus::parse(code.trim_matches('"')))
+    }
+
+    /// Unlock the SIM card with the PIN given to
+    /// [`set_pin`](ModemManager::set_pin), if it is locked.
+    pub async fn unlock(&mut self) -> Result<(), ModemError> {
+        let status = self.pin_status().await?;
+        let pin = match (&status, &self.pin) {
+            (PinStatus::Ready, _) => return Ok(()),
+            (PinStatus::SimPin, Some(pin)) => pin.clone(),
+            _ => return Err(ModemError::Locked(status)),
+        };
+        self.command(&format!("AT+CPIN=\"{}\"", pin)).await?;
+        // The card takes a moment to get ready.
+        for _ in 0..10 {
+            match self.pin_status().await? {
+                PinStatus::Ready => return Ok(()),
+                PinStatus::SimPin => tokio::time::sleep(Duration::from_millis(500)).await,
+                status => return Err(ModemError::Locked(status)),
+            }
+        }
+        Err(ModemError::Locked(PinStatus::SimPin))
+    }
+
+    /// Returns the signal quality.
+    pub async fn signal_quality(&mut self) -> Result<SignalQuality, ModemError> {
+        let response = self.command("AT+CSQ").await?;
+        let line = info(&response, "+CSQ:")?;
+        let params = params(line);
+        match (params.first(), params.get(1)) {
+            (Some(rssi), Some(ber)) => match (rssi.parse::<i16>(), ber.parse::<u8>()) {
+                (Ok(rssi), Ok(ber)) => Ok(SignalQuality {
+                    rssi: Some(-113 + 2 * rssi).filter(|_| rssi <= 31),
+                    ber: Some(ber).filter(|&ber| ber <= 7),
+                }),
+                _ => Err(ModemError::Unexpected(line.to_string())),
+            },
+            _ => Err(ModemError::Unexpected(line.to_string())),
+        }
+    }
+
+    /// Returns the network registration status.
+    pub async fn registration(&mut self) -> Result<Registration, ModemError> {
+        let response = self.command("AT+CREG?").await?;
+        let line = info(&response, "+CREG:")?;
+        params(line)
+            .get(1)
+            .and_then(|stat| Registration::parse(stat))
+            .ok_or_else(|| ModemError::Unexpected(line.to_string()))
+    }
+
+    /// Start a voice call to `number`.
+    pub async fn dial(&mut self, number: &str) -> Result<(), ModemError> {
+        self.command(&format!("ATD{};", number)).await?;
+        Ok(())
+    }
+
+    /// Answer the incoming call.
+    pub async fn answer(&mut self) -> Result<(), ModemError> {
+        self.command("ATA").await?;
+        Ok(())
+    }
+
+    /// End the current call.
+    pub async fn hang_up(&mut self) -> Result<(), ModemError> {
+        self.command("ATH").await?;
+        Ok(())
+    }
+
+    /// Send `text` to `number`, returning the message reference.
+    pub async fn send_sms(&mut self, number: &str, text: &str) -> Result<u8, ModemError> {
+        let (command, data) = match self.sms_mode {
+            SmsMode::Text => {
+                if text.contains(&['\u{1a}', '\u{1b}'][..]) || text.chars().count() > 160 {
+                    return Err(ModemError::InvalidSms);
+                }
+                (format!("AT+CMGS=\"{}\"", number), text.to_string())
+            }
+            SmsMode::Pdu => {
+                let pdu = encode_submit(number, text)?;
+                // The PDU starts with the default service center.
+                (format!("AT+CMGS={}", pdu.len()), format!("00{}", hex(&pdu)))
+            }
+        };
+        let timeout = self.client.timeout();
+        self.client.set_timeout(self.sms_timeout);
+        let response = self
+            .client
+            .command_with_data(&command, data.as_bytes())
+            .await;
+        self.client.set_timeout(timeout);
+        let response = checked(&command, response?)?;
+        let line = info(&response, "+CMGS:")?;
+        line.parse()
+            .map_err(|_| ModemError::Unexpected(line.to_string()))
+    }
+
+    /// Read the message at `index` of the storage.
+    pub async fn read_sms(&mut self, index: u32) -> Result<Sms, ModemError> {
+        let command = format!("AT+CMGR={}", index);
+        let response = self.command(&command).await?;
+        let mut messages = self.messages(&response.lines, "+CMGR:");
+        match messages.pop() {
+            Some(Some(sms)) if messages.is_empty() => Ok(sms),
+            _ => Err(ModemError::Unexpected(response.lines.join("\n"))),
+        }
+    }
+
+    /// Returns the messages of the storage with their indexes.
+    ///
+    /// Messages which cannot be decoded are skipped.
+    pub async fn list_sms(&mut self) -> Result<Vec<(u32, Sms)>, ModemError> {
+        let command = match self.sms_mode {
+            SmsMode::Pdu => "AT+CMGL=4",
+            SmsMode::Text => "AT+CMGL=\"ALL\"",
+        };
+        let response = self.command(command).await?;
+        let indexes = response
+            .lines
+            .iter()
+            .filter_map(|line| line.strip_prefix("+CMGL:"))
+            .map(|header| params(header).first().and_then(|index| index.parse().ok()));
+        let messages = self.messages(&response.lines, "+CMGL:");
+        Ok(indexes
+            .zip(messages)
+            .filter_map(|(index, sms)| Some((index?, sms?)))
+            .collect())
+    }
+
+    /// Delete the message at `index` of the storage.
+    pub async fn delete_sms(&mut self, index: u32) -> Result<(), ModemError> {
+        self.command(&format!("AT+CMGD={}", index)).await?;
+        Ok(())
+    }
+
+    // The messages of `+CMGR` or `+CMGL` responses, each a header line starting
+    // with `prefix` followed by the PDU or the lines of the text.
+    fn messages(&self, lines: &[String], prefix: &str) -> Vec<Option<Sms>> {
+        let mut messages = Vec::new();
+        let mut lines = lines.iter().peekable();
+        while let Some(line) = lines.next() {
+            let header = match line.strip_prefix(prefix) {
+                Some(header) => params(header),
+                None => continue,
+            };
+            let mut body = Vec::new();
+            while let Some(line) = lines.next_if(|line| !line.starts_with(prefix)) {
+                body.push(line.as_str());
+            }
+            let sms = match self.sms_mode {
+                SmsMode::Pdu => body.first().and_then(|pdu| decode_deliver(&unhex(pdu)?)),
+                // The status, in `+CMGR` skipped along with the index of `+CMGL`
+                SmsMode::Text => {
+                    let skip = if prefix == "+CMGL:" { 2 } else { 1 };
+                    parse_message(
+                        header.get(skip..).unwrap_or_default(),
+                        &body.join("\n"),
+                        false,
+                    )
+                }
+            };
+            if sms.is_none() {
+                log::debug!("skipping undecodable message: {}", line);
+            }
+            messages.push(sms);
+        }
+        messages
+    }
+
+    /// Read the events of the modem while no command runs, and poll the signal
+    /// quality if [`set_signal_interval`](ModemManager::set_signal_interval) asks
+    /// for it, until the port is closed.
+    ///
+    /// This is cancel safe, but while it polls the signal quality: the query is
+    /// then abandoned.
+    pub async fn listen(&mut self) -> Result<(), ModemError> {
+        loop {
+            let interval = match self.signal_interval {
+                Some(interval) => interval,
+                None => return Ok(self.client.listen().await?),
+            };
+            let wait = self.next_signal.saturating_duration_since(Instant::now());
+            if let Ok(closed) = tokio::time::timeout(wait, self.client.listen()).await {
+                return Ok(closed?);
+            }
+            self.next_signal = Instant::now() + interval;
+            let quality = self.signal_quality().await?;
+            let _ = self.events.send(ModemEvent::Signal(quality));
+        }
+    }
+
+    /// Returns a reference to the AT client.
+    pub fn get_ref(&self) -> &AtClient {
+        &self.client
+    }
+
+    /// Returns a mutable reference to the AT client.
+    pub fn get_mut(&mut self) -> &mut AtClient {
+        &mut self.client
+    }
+
+    /// Consumes the manager, returning the AT client.
+    pub fn into_inner(self) -> AtClient {
+        self.client
+    }
+}
+
+fn checked(command: &str, response: AtResponse) -> Result<AtResponse, ModemError> {
+    if response.is_ok() {
+        Ok(response)
+    } else {
+        Err(ModemError::Command {
+            command: command.to_string(),
+            result: response.result,
+        })
+    }
+}
+
+// The parameters of the information line of `response` starting with `prefix`.
+fn info<'a>(response: &'a AtResponse, prefix: &str) -> Result<&'a str, ModemError> {
+    response
+        .lines
+        .iter()
+        .find_map(|line| line.strip_prefix(prefix))
+        .map(str::trim)
+        .ok_or_else(|| ModemError::Unexpected(response.lines.join("\n")))
+}
+
+// The comma separated parameters of a response, unquoted.
+fn params(line: &str) -> Vec<String> {
+    let mut params = Vec::new();
+    let mut param = String::new();
+    let mut quoted = false;
+    for c in line.trim().chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => params.push(std::mem::take(&mut param).trim().to_string()),
+            c => param.push(c),
+        }
+    }
+    params.push(param.trim().to_string());
+    params
+}
+
+// A message from the parameters of its header, in text mode the address, the
+// alpha id and the timestamp, and its body; in PDU mode `+CMT` headers are the
+// alpha id and the length of the PDU.
+fn parse_message(header: &[String], body: &str, urc: bool) -> Option<Sms> {
+    let pdu = urc && header.len() == 2 && header[1].parse::<usize>().is_ok();
+    if pdu {
+        return decode_deliver(&unhex(body)?);
+    }
+    Some(Sms {
+        address: header.first()?.clone(),
+        timestamp: header.get(2).filter(|t| !t.is_empty()).cloned(),
+        text: body.to_string(),
+    })
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    let pairs = text.trim().as_bytes().chunks(2);
+    pairs
+        .map(|pair| match pair {
+            [high, low] => u8::from_str_radix(std::str::from_utf8(&[*high, *low]).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+// The GSM 7 bit default alphabet
+const GSM7: &str = "@£$¥èéùìòÇ\nØø\rÅåΔ_ΦΓΛΩΠΨΣΘΞ\u{1b}ÆæßÉ !\"#¤%&'()*+,-./0123456789:;<=>?\
+    ¡ABCDEFGHIJKLMNOPQRSTUVWXYZÄÖÑÜ§¿abcdefghijklmnopqrstuvwxyzäöñüà";
+
+// The characters of the extension table, after an escape
+const GSM7_EXTENSION: &[(u8, char)] = &[
+    (0x0a, '\u{c}'),
+    (0x14, '^'),
+    (0x28, '{'),
+    (0x29, '}'),
+    (0x2f, '\\'),
+    (0x3c, '['),
+    (0x3d, '~'),
+    (0x3e, ']'),
+    (0x40, '|'),
+    (0x65, '€'),
+];
+
+fn gsm7_encode(text: &str) -> Option<Vec<u8>> {
+    let mut septets = Vec::new();
+    for c in text.chars() {
+        if c == '\u{1b}' {
+            return None;
+        }
+        match GSM7.chars().position(|g| g == c) {
+            Some(septet) => septets.push(septet as u8),
+            None => {
+                let &(septet, _) = GSM7_EXTENSION.iter().find(|(_, e)| *e == c)?;
+                septets.extend_from_slice(&[0x1b, septet]);
+            }
+        }
+    }
+    Some(septets)
+}
+
+fn gsm7_decode(septets: &[u8]) -> String {
+    let mut text = String::new();
+    let mut septets = septets.iter();
+    while let Some(&septet) = septets.next() {
+        if septet == 0x1b {
+            let next = septets.next().copied().unwrap_or(0);
+            match GSM7_EXTENSION.iter().find(|(e, _)| *e == next) {
+                Some(&(_, c)) => text.push(c),
+                None => text.push(' '),
+            }
+        } else {
+            text.extend(GSM7.chars().nth(septet as usize));
+        }
+    }
+    text
+}
+
+fn pack_septets(septets: &[u8]) -> Vec<u8> {
+    let mut packed = vec![0u8; (septets.len() * 7).div_ceil(8)];
+    for (i, &septet) in septets.iter().enumerate() {
+        let bit = i * 7;
+        let value = u16::from(septet & 0x7f) << (bit % 8);
+        packed[bit / 8] |= value as u8;
+        if let Some(next) = packed.get_mut(bit / 8 + 1) {
+            *next |= (value >> 8) as u8;
+        }
+    }
+    packed
+}
+
+fn unpack_septets(packed: &[u8], count: usize) -> Option<Vec<u8>> {
+    (0..count)
+        .map(|i| {
+            let bit = i * 7;
+            let low = u16::from(*packed.get(bit / 8)?);
+            let high = u16::from(packed.get(bit / 8 + 1).copied().unwrap_or(0));
+            Some((((high << 8 | low) >> (bit % 8)) & 0x7f) as u8)
+        })
+        .collect()
+}
+
+// Digits as swapped nibbles, padded with F.
+fn semi_octets(digits: &str) -> Vec<u8> {
+    let nibbles: Vec<u8> = digits.bytes().map(|digit| digit - b'0').collect();
+    nibbles
+        .chunks(2)
+        .map(|pair| pair[0] | pair.get(1).copied().unwrap_or(0xf) << 4)
+        .collect()
+}
+
+fn decimal_semi_octets(octets: &[u8]) -> String {
+    let mut digits = String::new();
+    for octet in octets {
+        for nibble in [octet & 0xf, octet >> 4].iter() {
+            if let Some(digit) = std::char::from_digit(u32::from(*nibble), 10) {
+                digits.push(digit);
+            }
+        }
+    }
+    digits
+}
+
+/// Encode the SMS-SUBMIT PDU sending `text` to `number`, without the service
+/// center address
+///
+/// Texts in the GSM 7 bit alphabet take up to 160 characters, other ones are
+/// sent as UCS-2, up to 70 characters.  Sending longer texts in several messages
+/// is left to the application.
+pub fn encode_submit(number: &str, text: &str) -> Result<Vec<u8>, ModemError> {
+    let international = number.starts_with('+');
+    let digits = number.trim_start_matches('+');
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) || digits.len() > 20 {
+        return Err(ModemError::InvalidSms);
+    }
+    // SMS-SUBMIT without validity period, with the message reference picked by
+    // the modem.
+    let mut pdu = vec![0x01, 0x00, digits.len() as u8];
+    pdu.push(if international { 0x91 } else { 0x81 });
+    pdu.extend(semi_octets(digits));
+    pdu.push(0x00);
+    match gsm7_encode(text) {
+        Some(septets) if septets.len() <= 160 => {
+            pdu.extend_from_slice(&[0x00, septets.len() as u8]);
+            pdu.extend(pack_septets(&septets));
+        }
+        Some(_) => return Err(ModemError::InvalidSms),
+        None => {
+            let units: Vec<u16> = text.encode_utf16().collect();
+            if units.len() > 70 {
+                return Err(ModemError::InvalidSms);
+            }
+            pdu.extend_from_slice(&[0x08, (units.len() * 2) as u8]);
+            pdu.extend(units.iter().flat_map(|unit| unit.to_be_bytes().to_vec()));
+        }
+    }
+    Ok(pdu)
+}
+
+/// Decode an SMS-DELIVER PDU, as read from the modem with the service center
+/// address first
+///
+/// Returns `None` if `pdu` is not a valid SMS-DELIVER.  The user data header of
+/// concatenated messages is skipped, each part is decoded on its own.
+pub fn decode_deliver(pdu: &[u8]) -> Option<Sms> {
+    let mut pdu = pdu.get(usize::from(*pdu.first()?) + 1..)?;
+    let mut take = |n: usize| {
+        let (head, tail) = (pdu.get(..n)?, pdu.get(n..)?);
+        pdu = tail;
+        Some(head)
+    };
+    let first = take(1)?[0];
+    if first & 0x03 != 0x00 {
+        return None;
+    }
+    let digits = usize::from(take(1)?[0]);
+    let kind = take(1)?[0];
+    let octets = take(digits.div_ceil(2))?;
+    let address = match kind & 0x70 {
+        // Alphanumeric, in the GSM 7 bit alphabet
+        0x50 => gsm7_decode(&unpack_septets(octets, digits * 4 / 7)?),
+        0x10 => format!("+{}", decimal_semi_octets(octets)),
+        _ => decimal_semi_octets(octets),
+    };
+    let _pid = take(1)?;
+    let dcs = take(1)?[0];
+    let scts = decimal_semi_octets(take(6)?);
+    let zone = take(1)?[0];
+    let quarters = (zone & 0x07) * 10 + (zone >> 4);
+    let sign = if zone & 0x08 != 0 { '-' } else { '+' };
+    let timestamp = format!(
+        "{}/{}/{},{}:{}:{}{}{:02}",
+        scts.get(0..2)?,
+        scts.get(2..4)?,
+        scts.get(4..6)?,
+        scts.get(6..8)?,
+        scts.get(8..10)?,
+        scts.get(10..12)?,
+        sign,
+        quarters
+    );
+    let length = usize::from(take(1)?[0]);
+    let data = pdu;
+    let header = if first & 0x40 != 0 {
+        usize::from(*data.first()?) + 1
+    } else {
+        0
+    };
+    // The alphabet, from the general or the message class coding groups
+    let text = match (dcs >> 4, dcs & 0x0c) {
+        (0..=3, 0x08) | (0xe, _) => {
+            let units: Vec<u16> = data
+                .get(header..length.min(data.len()))?
+                .chunks_exact(2)
+                .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        (0..=3, 0x04) | (0xf, 0x04..=0x07) => {
+            let octets = data.get(header..length.min(data.len()))?;
+            octets.iter().map(|&b| char::from(b)).collect()
+        }
+        _ => {
+            let septets = unpack_septets(data, length)?;
+            gsm7_decode(septets.get((header * 8).div_ceil(7)..)?)
+        }
+    };
+    Some(Sms {
+        address,
+        timestamp: Some(timestamp),
+        text,
+    })
+}
@@ -0,0 +1,160 @@
+//! MessagePack encoded values in length prefixed frames
+use super::{FrameError, LengthDelimitedCodec, LengthError};
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::BytesMut;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+use std::{error, fmt, io};
+
+/// Errors produced by [`MessagePackCodec`]
+#[derive(Debug)]
+pub enum MessagePackError {
+    /// The underlying I/O failed
+    Io(io::Error),
+    /// The length prefix of a frame is invalid, the frame is skipped
+    Length(LengthError),
+    /// A frame does not hold a valid value, it is skipped
+    Decode(rmp_serde::decode::Error),
+    /// A value could not be serialized
+    Encode(rmp_serde::encode::Error),
+}
+
+impl fmt::Display for MessagePackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessagePackError::Io(err) => err.fmt(f),
+            MessagePackError::Length(err) => err.fmt(f),
+            MessagePackError::Decode(err) => write!(f, "invalid MessagePack value: {}", err),
+            MessagePackError::Encode(err) => write!(f, "unable to serialize value: {}", err),
+        }
+    }
+}
+
+impl error::Error for MessagePackError {}
+
+impl From<io::Error> for MessagePackError {
+    fn from(err: io::Error) -> Self {
+        MessagePackError::Io(err)
+    }
+}
+
+impl From<FrameError<LengthError>> for MessagePackError {
+    fn from(err: FrameError<LengthError>) -> Self {
+        match err {
+            FrameError::Io(err) => MessagePackError::Io(err),
+            FrameError::Frame(err) => MessagePackError::Length(err),
+        }
+    }
+}
+
+/// A codec for serde values encoded as [MessagePack](https://msgpack.org), each
+/// prefixed by its length
+///
+/// Frames are read with a [`LengthDelimitedCodec`], a big endian `u16` length by
+/// default: frames split across reads are buffered until complete, and a frame
+/// which does not decode only costs one value.  Decoded values are deserialized
+/// into `T`, any [`Serialize`] value can be encoded, so requests and responses
+/// may be different types.
+///
+/// Structs are encoded as maps with their field names, as most other MessagePack
+/// implementations expect, or as arrays with [`compact`](MessagePackCodec::compact);
+/// both are decoded.
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use tokio_serial::codec::{LengthDelimitedCodec, MessagePackCodec};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Status {
+///     uptime: u32,
+///     battery: f32,
+/// }
+///
+/// let codec = MessagePackCodec::<Status>::new()
+///     .framing(LengthDelimitedCodec::new().length_field_length(4));
+/// ```
+pub struct MessagePackCodec<T> {
+    framing: LengthDelimitedCodec,
+    compact: bool,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> MessagePackCodec<T> {
+    /// A codec for values prefixed with a big endian `u16` length.
+    pub fn new() -> Self {
+        Self {
+            framing: LengthDelimitedCodec::new(),
+            compact: false,
+            _value: PhantomData,
+        }
+    }
+
+    /// Set the length prefix of frames.
+    pub fn framing(mut self, framing: LengthDelimitedCodec) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Encode structs as arrays of their fields, without names.
+    pub fn compact(mut self) -> Self {
+        self.compact = true;
+        self
+    }
+}
+
+impl<T> Default for MessagePackCodec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for MessagePackCodec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessagePackCodec")
+            .field("framing", &self.framing)
+            .field("compact", &self.compact)
+            .finish()
+    }
+}
+
+impl<T> Clone for MessagePackCodec<T> {
+    fn clone(&self) -> Self {
+        Self {
+            framing: self.framing.clone(),
+            compact: self.compact,
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Decoder for MessagePackCodec<T> {
+    type Item = T;
+    type Error = MessagePackError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>, MessagePackError> {
+        match self.framing.decode(src)? {
+            Some(frame) => rmp_serde::from_slice(&frame)
+                .map(Some)
+                .map_err(MessagePackError::Decode),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<T, U: Serialize> Encoder<U> for MessagePackCodec<T> {
+    type Error = MessagePackError;
+
+    fn encode(&mut self, value: U, dst: &mut BytesMut) -> Result<(), MessagePackError> {
+        let data = if self.compact {
+            rmp_serde::to_vec(&value)
+        } else {
+            rmp_serde::to_vec_named(&value)
+        }
+        .map_err(MessagePackError::Encode)?;
+        self.framing.encode(&data[..], dst)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,567 @@
+//! NMEA 0183 sentences and a GPS client built on them
+//!
+//! Receivers send one sentence per line: `$`, an address made of a talker id and a
+//! sentence type, comma separated fields, then `*` and a checksum as two hex
+//! digits, the XOR of the bytes between `$` and `*`:
+//!
+//! ```text
+//! $GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47
+//! ```
+//!
+//! [`NmeaCodec`] splits the input into [`Sentence`]s.  [`GpsClient`] goes a level
+//! higher and turns the GGA, RMC, GSA and GSV sentences of each measurement into
+//! one [`Fix`].
+use super::{FrameError, Recovery, Resync};
+
+use futures::Stream;
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, BytesMut};
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{error, fmt, io};
+
+/// The default largest sentence accepted by [`NmeaCodec`]
+///
+/// The standard limits sentences to 82 bytes, some receivers send longer ones.
+pub const DEFAULT_MAX_LENGTH: usize = 256;
+
+// Meters per second in a knot
+const KNOT: f64 = 1852.0 / 3600.0;
+
+/// Errors of [`NmeaCodec`] sentences
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NmeaError {
+    /// A sentence is longer than the maximum length, it is skipped
+    TooLong,
+    /// The checksum of a sentence does not match its content, it is dropped
+    Checksum {
+        /// The checksum of the content
+        expected: u8,
+        /// The checksum sent
+        found: u8,
+    },
+    /// A sentence has no checksum, and the codec requires one
+    MissingChecksum,
+    /// A sentence is not ASCII text or has no address
+    Invalid,
+}
+
+impl fmt::Display for NmeaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NmeaError::TooLong => f.write_str("NMEA sentence too long"),
+            NmeaError::Checksum { expected, found } => write!(
+                f,
+                "NMEA checksum mismatch: expected {:02X}, found {:02X}",
+                expected, found
+            ),
+            NmeaError::MissingChecksum => f.write_str("NMEA sentence without checksum"),
+            NmeaError::Invalid => f.write_str("invalid NMEA sentence"),
+        }
+    }
+}
+
+impl error::Error for NmeaError {}
+
+/// An NMEA sentence
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Sentence {
+    /// The address, such as `GPGGA`: a talker id and the sentence type, or a
+    /// proprietary address starting with `P`
+    pub address: String,
+    /// The fields following the address
+    pub fields: Vec<String>,
+}
+
+impl Sentence {
+    /// Returns the talker id, such as `GP` for GPS or `GN` for combined
+    /// constellations, or an empty string for proprietary sentences.
+    pub fn talker(&self) -> &str {
+        match self.address.get(..2) {
+            Some(talker) if !self.address.starts_with('P') => talker,
+            _ => "",
+        }
+    }
+
+    /// Returns the sentence type, such as `GGA`, or the whole address for
+    /// proprietary sentences.
+    pub fn kind(&self) -> &str {
+        match self.talker() {
+            "" => &self.address,
+            talker => &self.address[talker.len()..],
+        }
+    }
+
+    /// Returns field `index`, or `None` if it is missing or empty.
+    pub fn field(&self, index: usize) -> Option<&str> {
+        self.fields
+            .get(index)
+            .map(String::as_str)
+            .filter(|field| !field.is_empty())
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum ^ byte)
+}
+
+/// A codec for NMEA 0183 sentences
+///
+/// Bytes before the `$` or `!` starting a sentence are skipped, sentences end
+/// with a line feed.  Sentences without a checksum are accepted unless
+/// [`require_checksum`](NmeaCodec::require_checksum) is set.  Encoded sentences
+/// always carry a checksum.
+///
+/// ```
+/// use tokio_serial::codec::nmea::NmeaCodec;
+/// use tokio_util::codec::Decoder;
+///
+/// let mut codec = NmeaCodec::new();
+/// let mut src = bytes::BytesMut::from(&b"$GPGLL,4916.45,N,12311.12,W,225444,A*31\r\n"[..]);
+/// let sentence = codec.decode(&mut src).unwrap().unwrap();
+/// assert_eq!(sentence.kind(), "GLL");
+/// assert_eq!(sentence.field(0), Some("4916.45"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct NmeaCodec {
+    max_length: usize,
+    require_checksum: bool,
+}
+
+impl NmeaCodec {
+    /// A codec for sentences of at most [`DEFAULT_MAX_LENGTH`] bytes.
+    pub fn new() -> Self {
+        Self {
+            max_length: DEFAULT_MAX_LENGTH,
+            require_checksum: false,
+        }
+    }
+
+    /// Set the maximum length of a sentence.
+    pub fn max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Set whether sentences without a checksum are rejected.
+    pub fn require_checksum(mut self, require: bool) -> Self {
+        self.require_checksum = require;
+        self
+    }
+
+    fn parse(&self, line: &[u8]) -> Result<Sentence, NmeaError> {
+        let line = std::str::from_utf8(line)
+            .ok()
+            .filter(|line| line.is_ascii())
+            .ok_or(NmeaError::Invalid)?;
+        // Without the leading `$` or `!`
+        let body = match line[1..].rsplit_once('*') {
+            Some((body, sum)) => {
+                let found = u8::from_str_radix(sum, 16).map_err(|_| NmeaError::Invalid)?;
+                let expected = checksum(body.as_bytes());
+                if found != expected {
+                    return Err(NmeaError::Checksum { expected, found });
+                }
+                body
+            }
+            None if self.require_checksum => return Err(NmeaError::MissingChecksum),
+            None => &line[1..],
+        };
+        let mut fields = body.split(',');
+        let address = fields.next().filter(|address| !address.is_empty());
+        Ok(Sentence {
+            address: address.ok_or(NmeaError::Invalid)?.to_owned(),
+            fields: fields.map(str::to_owned).collect(),
+        })
+    }
+}
+
+impl Default for NmeaCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for NmeaCodec {
+    type Item = Sentence;
+    type Error = FrameError<NmeaError>;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Sentence>, Self::Error> {
+        match src.iter().position(|&byte| byte == b'$' || byte == b'!') {
+            Some(start) => src.advance(start),
+            None => {
+                src.clear();
+                return Ok(None);
+            }
+        }
+        let end = match src.iter().position(|&byte| byte == b'\n') {
+            Some(end) if end <= self.max_length => end,
+            None if src.len() <= self.max_length => return Ok(None),
+            _ => {
+                src.advance(1);
+                return Err(FrameError::Frame(NmeaError::TooLong));
+            }
+        };
+        let line = src.split_to(end + 1);
+        let line = line.strip_suffix(b"\n").unwrap_or(&line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        self.parse(line).map(Some).map_err(FrameError::Frame)
+    }
+}
+
+impl Encoder<&Sentence> for NmeaCodec {
+    type Error = FrameError<NmeaError>;
+
+    fn encode(&mut self, sentence: &Sentence, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let mut body = sentence.address.clone();
+        for field in &sentence.fields {
+            body.push(',');
+            body.push_str(field);
+        }
+        // `$`, `*`, the checksum and CR LF
+        if body.len() + 6 > self.max_length {
+            return Err(FrameError::Frame(NmeaError::TooLong));
+        }
+        let line = format!("${}*{:02X}\r\n", body, checksum(body.as_bytes()));
+        dst.extend_from_slice(line.as_bytes());
+        Ok(())
+    }
+}
+
+impl Encoder<Sentence> for NmeaCodec {
+    type Error = FrameError<NmeaError>;
+
+    fn encode(&mut self, sentence: Sentence, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&sentence, dst)
+    }
+}
+
+/// A UTC time of day
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct UtcTime {
+    /// Hours, 0 to 23
+    pub hour: u8,
+    /// Minutes, 0 to 59
+    pub minute: u8,
+    /// Seconds with their fraction, 0 to 60 for leap seconds
+    pub second: f32,
+}
+
+impl UtcTime {
+    fn parse(field: &str) -> Option<Self> {
+        Some(Self {
+            hour: field.get(0..2)?.parse().ok()?,
+            minute: field.get(2..4)?.parse().ok()?,
+            second: field.get(4..)?.parse().ok()?,
+        })
+    }
+}
+
+/// A calendar date
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    /// The year, with its century
+    pub year: u16,
+    /// The month, 1 to 12
+    pub month: u8,
+    /// The day of the month, 1 to 31
+    pub day: u8,
+}
+
+impl Date {
+    fn parse(field: &str) -> Option<Self> {
+        let year: u16 = field.get(4..6)?.parse().ok()?;
+        Some(Self {
+            // RMC sentences have two digit years.
+            year: if year < 80 { 2000 + year } else { 1900 + year },
+            month: field.get(2..4)?.parse().ok()?,
+            day: field.get(0..2)?.parse().ok()?,
+        })
+    }
+}
+
+/// A satellite in view, from GSV sentences
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Satellite {
+    /// The satellite id
+    pub prn: u16,
+    /// The elevation in degrees, 0 to 90
+    pub elevation: Option<u8>,
+    /// The azimuth in degrees from true north, 0 to 359
+    pub azimuth: Option<u16>,
+    /// The signal to noise ratio in dB-Hz, `None` when the satellite is not tracked
+    pub snr: Option<u8>,
+}
+
+/// The dimension of a fix, from GSA sentences
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FixMode {
+    /// No fix
+    NoFix,
+    /// A 2D fix, without altitude
+    Fix2D,
+    /// A 3D fix
+    Fix3D,
+}
+
+/// What a GPS receiver reported for one measurement
+///
+/// Fields the receiver did not send, or sent empty, are `None`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Fix {
+    /// The UTC time of the measurement
+    pub time: Option<UtcTime>,
+    /// The UTC date of the measurement, from RMC sentences
+    pub date: Option<Date>,
+    /// Whether the receiver has a valid position
+    pub valid: bool,
+    /// The latitude in degrees, positive to the north
+    pub latitude: Option<f64>,
+    /// The longitude in degrees, positive to the east
+    pub longitude: Option<f64>,
+    /// The altitude above mean sea level in meters, from GGA sentences
+    pub altitude: Option<f64>,
+    /// The speed over ground in meters per second, from RMC sentences
+    pub speed: Option<f64>,
+    /// The course over ground in degrees from true north, from RMC sentences
+    pub course: Option<f64>,
+    /// The GGA fix quality: 1 for GPS, 2 for differential GPS, 4 and 5 for RTK...
+    pub quality: Option<u8>,
+    /// The dimension of the fix, from GSA sentences
+    pub mode: Option<FixMode>,
+    /// The number of satellites used for the fix
+    pub satellites_used: Option<u8>,
+    /// The satellites in view, of all constellations
+    pub satellites: Vec<Satellite>,
+    /// The horizontal dilution of precision
+    pub hdop: Option<f64>,
+    /// The vertical dilution of precision
+    pub vdop: Option<f64>,
+    /// The position dilution of precision
+    pub pdop: Option<f64>,
+}
+
+fn parse<T: std::str::FromStr>(field: Option<&str>) -> Option<T> {
+    field?.parse().ok()
+}
+
+// `ddmm.mmmm` or `dddmm.mmmm`, with the hemisphere
+fn coordinate(value: Option<&str>, hemisphere: Option<&str>) -> Option<f64> {
+    let value = value?;
+    let degrees_len = value.find('.').unwrap_or(value.len()).checked_sub(2)?;
+    let degrees: f64 = value[..degrees_len].parse().ok()?;
+    let minutes: f64 = value[degrees_len..].parse().ok()?;
+    let coordinate = degrees + minutes / 60.0;
+    match hemisphere? {
+        "N" | "E" => Some(coordinate),
+        "S" | "W" => Some(-coordinate),
+        _ => None,
+    }
+}
+
+// The satellites of the GSV group being received
+#[derive(Debug, Default)]
+struct GsvGroup {
+    total: u8,
+    received: u8,
+    satellites: Vec<Satellite>,
+}
+
+/// A [`Stream`] of [`Fix`]es from a stream of NMEA sentences
+///
+/// Receivers send a burst of sentences for each measurement.  A fix is produced
+/// when a GGA or RMC sentence of the next measurement arrives, or when the
+/// sentences end, so GSA and GSV sentences sent after them are part of it.
+/// Satellites in view are kept until the next complete GSV group of their talker.
+///
+/// Sentences failing their checksum are dropped and counted, see
+/// [`checksum_errors`](GpsClient::checksum_errors).  GSV groups with a missing
+/// part are dropped.
+#[must_use = "streams do nothing unless polled"]
+#[derive(Debug)]
+pub struct GpsClient<S> {
+    sentences: S,
+    fix: Fix,
+    // Whether `fix` holds any data
+    pending: bool,
+    groups: BTreeMap<String, GsvGroup>,
+    satellites: BTreeMap<String, Vec<Satellite>>,
+    checksum_errors: u64,
+    done: bool,
+}
+
+/// The sentences of a port, for [`GpsClient::from_port`]
+pub type NmeaFramed<R> = tokio_util::codec::FramedRead<R, Resync<NmeaCodec>>;
+
+impl<R: tokio::io::AsyncRead> GpsClient<NmeaFramed<R>> {
+    /// Read the fixes of the receiver connected to `port`.
+    ///
+    /// The stream of a `FramedRead` ends after an error, so the codec skips the
+    /// sentences it rejects by itself: they are counted by
+    /// `client.get_ref().decoder().errors()`.
+    pub fn from_port(port: R) -> Self {
+        let codec = Resync::new(NmeaCodec::new(), Recovery::Continue).report_errors(false);
+        Self::new(tokio_util::codec::FramedRead::new(port, codec))
+    }
+}
+
+impl<S> GpsClient<S> {
+    /// Aggregate `sentences` into fixes.
+    pub fn new(sentences: S) -> Self {
+        Self {
+            sentences,
+            fix: Fix::default(),
+            pending: false,
+            groups: BTreeMap::new(),
+            satellites: BTreeMap::new(),
+            checksum_errors: 0,
+            done: false,
+        }
+    }
+
+    /// Returns how many sentences the stream rejected so far, for failing their
+    /// checksum or otherwise.
+    pub fn checksum_errors(&self) -> u64 {
+        self.checksum_errors
+    }
+
+    /// Returns a reference to the stream of sentences.
+    pub fn get_ref(&self) -> &S {
+        &self.sentences
+    }
+
+    /// Returns a mutable reference to the stream of sentences.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sentences
+    }
+
+    // Take the fix of the measurement, if any.
+    fn take_fix(&mut self) -> Option<Fix> {
+        if !std::mem::take(&mut self.pending) {
+            return None;
+        }
+        let mut fix = std::mem::take(&mut self.fix);
+        fix.satellites = self.satellites.values().flatten().copied().collect();
+        Some(fix)
+    }
+
+    // Returns the fix of the previous measurement if `sentence` starts a new one.
+    fn handle(&mut self, sentence: &Sentence) -> Option<Fix> {
+        let time = match sentence.kind() {
+            "GGA" | "RMC" => sentence.field(0).and_then(UtcTime::parse),
+            "GSA" | "GSV" => None,
+            _ => return None,
+        };
+        let previous = match (time, self.fix.time) {
+            (Some(time), Some(current)) if time != current => {
+                // GSV groups do not span measurements.
+                self.groups.clear();
+                self.take_fix()
+            }
+            _ => None,
+        };
+        if time.is_some() {
+            self.fix.time = time;
+        }
+        self.pending = true;
+
+        let fix = &mut self.fix;
+        match sentence.kind() {
+            "GGA" => {
+                fix.latitude = coordinate(sentence.field(1), sentence.field(2));
+                fix.longitude = coordinate(sentence.field(3), sentence.field(4));
+                fix.quality = parse(sentence.field(5));
+                fix.valid = matches!(fix.quality, Some(quality) if quality > 0);
+                fix.satellites_used = parse(sentence.field(6));
+                fix.hdop = fix.hdop.or_else(|| parse(sentence.field(7)));
+                fix.altitude = parse(sentence.field(8));
+            }
+            "RMC" => {
+                // GGA sentences of the measurement are more precise.
+                if fix.quality.is_none() {
+                    fix.valid = sentence.field(1) == Some("A");
+                    fix.latitude = coordinate(sentence.field(2), sentence.field(3));
+                    fix.longitude = coordinate(sentence.field(4), sentence.field(5));
+                }
+                fix.speed = parse::<f64>(sentence.field(6)).map(|knots| knots * KNOT);
+                fix.course = parse(sentence.field(7));
+                fix.date = sentence.field(8).and_then(Date::parse);
+            }
+            "GSA" => {
+                fix.mode = match sentence.field(1) {
+                    Some("1") => Some(FixMode::NoFix),
+                    Some("2") => Some(FixMode::Fix2D),
+                    Some("3") => Some(FixMode::Fix3D),
+                    _ => fix.mode,
+                };
+                fix.pdop = parse(sentence.field(14)).or(fix.pdop);
+                fix.hdop = parse(sentence.field(15)).or(fix.hdop);
+                fix.vdop = parse(sentence.field(16)).or(fix.vdop);
+            }
+            _ => self.gsv(sentence),
+        }
+        previous
+    }
+
+    fn gsv(&mut self, sentence: &Sentence) {
+        let (total, index): (u8, u8) = match (parse(sentence.field(0)), parse(sentence.field(1))) {
+            (Some(total), Some(index)) if index >= 1 && index <= total => (total, index),
+            _ => return,
+        };
+        let talker = sentence.talker().to_owned();
+        let group = self.groups.entry(talker.clone()).or_default();
+        if index == 1 {
+            *group = GsvGroup {
+                total,
+                ..GsvGroup::default()
+            };
+        } else if group.total != total || group.received + 1 != index {
+            // A part is missing, wait for the next group.
+            log::debug!("dropping incomplete {}GSV group", talker);
+            *group = GsvGroup::default();
+            return;
+        }
+        group.received = index;
+        for fields in sentence.fields.get(3..).unwrap_or_default().chunks(4) {
+            let field = |i: usize| fields.get(i).map(String::as_str).filter(|f| !f.is_empty());
+            if let Some(prn) = parse(field(0)) {
+                group.satellites.push(Satellite {
+                    prn,
+                    elevation: parse(field(1)),
+                    azimuth: parse(field(2)),
+                    snr: parse(field(3)),
+                });
+            }
+        }
+        if index == total {
+            let group = std::mem::take(group);
+            self.satellites.insert(talker, group.satellites);
+        }
+    }
+}
+
+impl<S, E> Stream for GpsClient<S>
+where
+    S: Stream<Item = Result<Sentence, FrameError<E>>> + Unpin,
+{
+    type Item = io::Result<Fix>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while !this.done {
+            match futures::ready!(Pin::new(&mut this.sentences).poll_next(cx)) {
+                Some(Ok(sentence)) => {
+                    if let Some(fix) = this.handle(&sentence) {
+                        return Poll::Ready(Some(Ok(fix)));
+                    }
+                }
+                Some(Err(FrameError::Frame(_))) => this.checksum_errors += 1,
+                Some(Err(FrameError::Io(e))) => return Poll::Ready(Some(Err(e))),
+                None => this.done = true,
+            }
+        }
+        Poll::Ready(this.take_fix().map(Ok))
+    }
+}
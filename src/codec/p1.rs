@@ -0,0 +1,288 @@
+//! DSMR P1 telegrams, the output of Dutch and Belgian smart meters
+//!
+//! Meters write a telegram on their P1 port every second or ten seconds: an
+//! identification line starting with `/`, an empty line, one line per COSEM
+//! object, and a last line with `!` and the CRC of the telegram as four hex
+//! digits.  Objects are an OBIS code followed by values in parentheses:
+//!
+//! ```text
+//! /ISk5\2MT382-1000
+//!
+//! 1-0:1.8.1(123456.789*kWh)
+//! 0-1:24.2.1(101209112500W)(12785.123*m3)
+//! !107F
+//! ```
+//!
+//! DSMR 4 and 5 ports run at 115200 baud 8N1, DSMR 2.2 and 3 ones at 9600 baud
+//! 7E1, and send telegrams without a CRC.
+use super::FrameError;
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, BytesMut};
+use std::{error, fmt};
+use tokio_serial_core::crc::CRC16_ARC;
+
+/// The default largest telegram accepted by [`P1Codec`]
+pub const DEFAULT_MAX_LENGTH: usize = 8 * 1024;
+
+/// Errors of [`P1Codec`] telegrams
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum P1Error {
+    /// A telegram is longer than the maximum length, it is skipped
+    TooLong,
+    /// The CRC of a telegram does not match its content, it is dropped
+    Crc {
+        /// The CRC of the content
+        expected: u16,
+        /// The CRC sent
+        found: u16,
+    },
+    /// A telegram has no CRC, and the codec requires one
+    MissingCrc,
+    /// A telegram is not valid ASCII text or has no identification line
+    Invalid,
+}
+
+impl fmt::Display for P1Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            P1Error::TooLong => f.write_str("P1 telegram too long"),
+            P1Error::Crc { expected, found } => write!(
+                f,
+                "P1 telegram CRC mismatch: expected {:04X}, found {:04X}",
+                expected, found
+            ),
+            P1Error::MissingCrc => f.write_str("P1 telegram without CRC"),
+            P1Error::Invalid => f.write_str("invalid P1 telegram"),
+        }
+    }
+}
+
+impl error::Error for P1Error {}
+
+/// A value of a COSEM object, such as `123456.789*kWh`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Value {
+    /// The value, as sent
+    pub value: String,
+    /// The unit of the value, if any
+    pub unit: Option<String>,
+}
+
+/// A COSEM object of a telegram: an OBIS code and its values
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DataObject {
+    /// The OBIS code, such as `1-0:1.8.1`
+    pub obis: String,
+    /// The values in parentheses following the code, in order
+    pub values: Vec<Value>,
+}
+
+/// A P1 telegram
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Telegram {
+    /// The identification line, without the leading `/`
+    pub header: String,
+    /// The lines following the identification up to the final `!`, starting with
+    /// the empty line, line ends included
+    pub data: String,
+}
+
+impl Telegram {
+    /// Parse the data lines into COSEM objects.
+    ///
+    /// Lines starting with a parenthesis continue the object of the previous line,
+    /// as the gas readings of DSMR 2.2 do.  Lines which are not objects are
+    /// skipped.
+    pub fn objects(&self) -> Vec<DataObject> {
+        let mut objects: Vec<DataObject> = Vec::new();
+        for line in self.data.lines() {
+            let open = match line.find('(') {
+                Some(open) => open,
+                None => continue,
+            };
+            let values = line[open..]
+                .split(')')
+                .filter_map(|value| value.strip_prefix('('))
+                .map(|value| match value.split_once('*') {
+                    Some((value, unit)) => Value {
+                        value: value.to_owned(),
+                        unit: Some(unit.to_owned()),
+                    },
+                    None => Value {
+                        value: value.to_owned(),
+                        unit: None,
+                    },
+                });
+            match objects.last_mut() {
+                Some(object) if open == 0 => object.values.extend(values),
+                _ if open == 0 => {}
+                _ => objects.push(DataObject {
+                    obis: line[..open].to_owned(),
+                    values: values.collect(),
+                }),
+            }
+        }
+        objects
+    }
+
+    /// Returns the object with OBIS code `obis`, if the telegram has one.
+    pub fn get(&self, obis: &str) -> Option<DataObject> {
+        self.objects()
+            .into_iter()
+            .find(|object| object.obis == obis)
+    }
+}
+
+/// A codec for DSMR P1 telegrams
+///
+/// Bytes before the `/` starting a telegram, such as the end of a telegram
+/// whose start was missed, are skipped.  A telegram cut short by a new one is
+/// dropped in favor of the new one.  Telegrams without a CRC, as sent by DSMR
+/// 2.2 and 3 meters, are accepted unless [`require_crc`](P1Codec::require_crc)
+/// is set.  Encoded telegrams always carry a CRC, which makes the codec usable
+/// to simulate a meter.
+///
+/// ```
+/// use tokio_serial::codec::P1Codec;
+/// use tokio_util::codec::Decoder;
+///
+/// let mut codec = P1Codec::new();
+/// let mut src = bytes::BytesMut::from(&b"/XMX5LGBBFG10\r\n\r\n1-0:1.8.1(000123.456*kWh)\r\n!"[..]);
+/// src.extend_from_slice(b"4F11\r\n");
+/// let telegram = codec.decode(&mut src).unwrap().unwrap();
+/// let delivered = telegram.get("1-0:1.8.1").unwrap();
+/// assert_eq!(delivered.values[0].value, "000123.456");
+/// assert_eq!(delivered.values[0].unit.as_deref(), Some("kWh"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct P1Codec {
+    max_length: usize,
+    require_crc: bool,
+}
+
+impl P1Codec {
+    /// A codec for telegrams of at most [`DEFAULT_MAX_LENGTH`] bytes.
+    pub fn new() -> Self {
+        Self {
+            max_length: DEFAULT_MAX_LENGTH,
+            require_crc: false,
+        }
+    }
+
+    /// Set the maximum length of a telegram.
+    pub fn max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Set whether telegrams without a CRC are rejected.
+    pub fn require_crc(mut self, require: bool) -> Self {
+        self.require_crc = require;
+        self
+    }
+
+    fn parse(&self, telegram: &[u8], crc: &[u8]) -> Result<Telegram, P1Error> {
+        if !crc.is_empty() {
+            let found = std::str::from_utf8(crc)
+                .ok()
+                .filter(|crc| crc.len() == 4)
+                .and_then(|crc| u16::from_str_radix(crc, 16).ok())
+                .ok_or(P1Error::Invalid)?;
+            let expected = CRC16_ARC.checksum(telegram);
+            if found != expected {
+                return Err(P1Error::Crc { expected, found });
+            }
+        } else if self.require_crc {
+            return Err(P1Error::MissingCrc);
+        }
+        // Without the leading `/` and the final `!`.
+        let text = std::str::from_utf8(&telegram[1..telegram.len() - 1])
+            .ok()
+            .filter(|text| text.is_ascii())
+            .ok_or(P1Error::Invalid)?;
+        let (header, data) = text.split_once("\r\n").ok_or(P1Error::Invalid)?;
+        Ok(Telegram {
+            header: header.to_owned(),
+            data: data.to_owned(),
+        })
+    }
+}
+
+impl Default for P1Codec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for P1Codec {
+    type Item = Telegram;
+    type Error = FrameError<P1Error>;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Telegram>, Self::Error> {
+        match src.iter().position(|&byte| byte == b'/') {
+            Some(start) => src.advance(start),
+            None => {
+                src.clear();
+                return Ok(None);
+            }
+        }
+        let end = src.iter().position(|&byte| byte == b'!');
+        // A telegram starts at the beginning of a line, a CR LF followed by `/`
+        // before the end is the start of a new one.
+        let searched = &src[..end.unwrap_or(src.len())];
+        if let Some(restart) = searched.windows(3).rposition(|w| w == b"\r\n/") {
+            log::debug!("P1 telegram cut short, skipping {} bytes", restart + 2);
+            src.advance(restart + 2);
+            return self.decode(src);
+        }
+        let end = match end {
+            Some(end) if end < self.max_length => end,
+            None if src.len() <= self.max_length => return Ok(None),
+            _ => {
+                src.advance(1);
+                return Err(FrameError::Frame(P1Error::TooLong));
+            }
+        };
+        let crc_len = match src[end + 1..].windows(2).position(|w| w == b"\r\n") {
+            Some(len) => len,
+            // The CRC line cannot be longer than its four digits.
+            None if src.len() - end <= 6 => return Ok(None),
+            None => {
+                src.advance(end + 1);
+                return Err(FrameError::Frame(P1Error::Invalid));
+            }
+        };
+        let frame = src.split_to(end + 1 + crc_len + 2);
+        let (telegram, crc) = frame.split_at(end + 1);
+        self.parse(telegram, &crc[..crc_len])
+            .map(Some)
+            .map_err(FrameError::Frame)
+    }
+}
+
+impl Encoder<&Telegram> for P1Codec {
+    type Error = FrameError<P1Error>;
+
+    fn encode(&mut self, telegram: &Telegram, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let text = format!("/{}\r\n{}!", telegram.header, telegram.data);
+        if text.len() > self.max_length {
+            return Err(FrameError::Frame(P1Error::TooLong));
+        }
+        dst.reserve(text.len() + 6);
+        dst.extend_from_slice(text.as_bytes());
+        dst.extend_from_slice(
+            format!("{:04X}\r\n", CRC16_ARC.checksum(text.as_bytes())).as_bytes(),
+        );
+        Ok(())
+    }
+}
+
+impl Encoder<Telegram> for P1Codec {
+    type Error = FrameError<P1Error>;
+
+    fn encode(&mut self, telegram: Telegram, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&telegram, dst)
+    }
+}
@@ -0,0 +1,138 @@
+//! postcard encoded values in COBS frames
+use super::cobs::{Cobs, CobsError};
+use super::{FrameCodec, FrameError};
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::BytesMut;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+use std::{error, fmt, io};
+
+/// Errors produced by [`PostcardCodec`]
+#[derive(Debug)]
+pub enum PostcardError {
+    /// The underlying I/O failed
+    Io(io::Error),
+    /// A COBS frame is malformed or too long, it is skipped
+    Cobs(CobsError),
+    /// A frame does not hold a valid value, or a value could not be serialized
+    Postcard(::postcard::Error),
+}
+
+impl fmt::Display for PostcardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostcardError::Io(err) => err.fmt(f),
+            PostcardError::Cobs(err) => err.fmt(f),
+            PostcardError::Postcard(err) => write!(f, "postcard: {}", err),
+        }
+    }
+}
+
+impl error::Error for PostcardError {}
+
+impl From<io::Error> for PostcardError {
+    fn from(err: io::Error) -> Self {
+        PostcardError::Io(err)
+    }
+}
+
+impl From<FrameError<CobsError>> for PostcardError {
+    fn from(err: FrameError<CobsError>) -> Self {
+        match err {
+            FrameError::Io(err) => PostcardError::Io(err),
+            FrameError::Frame(err) => PostcardError::Cobs(err),
+        }
+    }
+}
+
+/// A codec for serde values encoded with [postcard], each in a COBS frame
+///
+/// This is the wire format of `postcard::to_slice_cobs`, and of most Rust
+/// firmware talking to a host over a UART: frames end with a `0x00`, so after
+/// garbage or a reset of the device mid-frame the link resynchronizes on the next
+/// one.  Frames which do not decode are reported as errors and skipped.
+///
+/// Decoded values are deserialized into `T`, any [`Serialize`] value can be
+/// encoded, so requests and responses may be different types.
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use tokio_serial::codec::PostcardCodec;
+///
+/// #[derive(Serialize, Deserialize)]
+/// enum Command {
+///     Ping,
+///     SetLed { index: u8, on: bool },
+/// }
+///
+/// let codec = PostcardCodec::<Command>::new(256);
+/// ```
+///
+/// [postcard]: https://docs.rs/postcard
+pub struct PostcardCodec<T> {
+    frames: FrameCodec<Cobs<Vec<u8>>>,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> PostcardCodec<T> {
+    /// A codec receiving values of at most `max_length` bytes once decoded from
+    /// COBS.
+    pub fn new(max_length: usize) -> Self {
+        Self {
+            frames: FrameCodec::new(Cobs::new(vec![0; max_length])),
+            _value: PhantomData,
+        }
+    }
+
+    /// Returns the maximum length of a received value.
+    pub fn max_length(&self) -> usize {
+        self.frames.get_ref().capacity()
+    }
+}
+
+impl<T> fmt::Debug for PostcardCodec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PostcardCodec")
+            .field("max_length", &self.max_length())
+            .finish()
+    }
+}
+
+impl<T> Clone for PostcardCodec<T> {
+    fn clone(&self) -> Self {
+        Self {
+            frames: self.frames.clone(),
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Decoder for PostcardCodec<T> {
+    type Item = T;
+    type Error = PostcardError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>, PostcardError> {
+        match self.frames.decode(src)? {
+            Some(frame) => ::postcard::from_bytes(&frame)
+                .map(Some)
+                .map_err(PostcardError::Postcard),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<T, U: Serialize> Encoder<U> for PostcardCodec<T> {
+    type Error = PostcardError;
+
+    fn encode(&mut self, value: U, dst: &mut BytesMut) -> Result<(), PostcardError> {
+        let data = ::postcard::to_stdvec(&value).map_err(PostcardError::Postcard)?;
+        match self.frames.encode(&data[..], dst) {
+            Ok(()) => Ok(()),
+            Err(FrameError::Io(err)) => Err(PostcardError::Io(err)),
+            Err(FrameError::Frame(never)) => match never {},
+        }
+    }
+}
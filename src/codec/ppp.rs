@@ -0,0 +1,242 @@
+//! PPP in HDLC-like framing, [RFC 1662]
+//!
+//! PPP frames over asynchronous links are [`hdlc`](super::hdlc) frames whose
+//! payload starts with the all-stations address `0xFF` and the unnumbered
+//! information control field `0x03`, followed by the protocol number and the
+//! packet.  Both fields can be compressed once LCP negotiated it: the address and
+//! control fields left out, and protocol numbers below `0x100` sent as one byte.
+//!
+//! [RFC 1662]: https://www.rfc-editor.org/rfc/rfc1662
+use super::hdlc::{Hdlc, HdlcError};
+use super::{FrameCodec, FrameError, Framer};
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Bytes, BytesMut};
+use std::{error, fmt};
+
+/// The all-stations address
+pub const ADDRESS: u8 = 0xff;
+/// The unnumbered information control field
+pub const CONTROL: u8 = 0x03;
+/// The largest information field received by default
+pub const DEFAULT_MRU: usize = 1500;
+/// The async control character map in use until LCP negotiates another one
+pub const DEFAULT_ACCM: u32 = 0xffff_ffff;
+
+/// Protocol numbers
+pub mod protocol {
+    /// Internet Protocol version 4
+    pub const IPV4: u16 = 0x0021;
+    /// Internet Protocol version 6
+    pub const IPV6: u16 = 0x0057;
+    /// IP Control Protocol
+    pub const IPCP: u16 = 0x8021;
+    /// IPv6 Control Protocol
+    pub const IPV6CP: u16 = 0x8057;
+    /// Link Control Protocol
+    pub const LCP: u16 = 0xc021;
+    /// Password Authentication Protocol
+    pub const PAP: u16 = 0xc023;
+    /// Challenge Handshake Authentication Protocol
+    pub const CHAP: u16 = 0xc223;
+}
+
+// Address, control, two byte protocol and FCS-16.
+const OVERHEAD: usize = 6;
+
+/// A PPP frame
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PppFrame {
+    /// The protocol of the packet, see [`protocol`]
+    pub protocol: u16,
+    /// The packet, padding included
+    pub information: Bytes,
+}
+
+/// Errors of [`PppCodec`] frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PppError {
+    /// The HDLC framing is broken, the frame is dropped
+    Hdlc(HdlcError),
+    /// The address and control fields are neither `0xFF 0x03` nor compressed
+    InvalidHeader,
+    /// The protocol field is missing or not a valid protocol number
+    InvalidProtocol,
+    /// The protocol number of a frame to send is not valid
+    Unsendable(u16),
+}
+
+impl fmt::Display for PppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PppError::Hdlc(err) => err.fmt(f),
+            PppError::InvalidHeader => f.write_str("invalid PPP address and control fields"),
+            PppError::InvalidProtocol => f.write_str("invalid PPP protocol field"),
+            PppError::Unsendable(protocol) => {
+                write!(f, "invalid PPP protocol number {:#06x}", protocol)
+            }
+        }
+    }
+}
+
+impl error::Error for PppError {}
+
+/// A codec for PPP frames over an asynchronous serial link
+///
+/// Frames start out with all control characters escaped and the address,
+/// control and protocol fields in full, as until LCP negotiated otherwise.  Once
+/// it did, [`set_send_accm`](PppCodec::set_send_accm) and
+/// [`set_receive_accm`](PppCodec::set_receive_accm) apply the negotiated maps,
+/// the codec being reachable through
+/// [`SerialFramed::codec_mut`](crate::SerialFramed::codec_mut).  LCP packets
+/// are always sent without compression and under the default map, as RFC 1662
+/// requires.  Compressed fields are always accepted when received.
+///
+/// ```
+/// use tokio_serial::codec::ppp::{protocol, PppCodec, PppFrame};
+/// use tokio_util::codec::Encoder;
+///
+/// let mut codec = PppCodec::new();
+/// let mut dst = bytes::BytesMut::new();
+/// let configure_request = PppFrame {
+///     protocol: protocol::LCP,
+///     information: bytes::Bytes::from_static(b"\x01\x01\x00\x04"),
+/// };
+/// codec.encode(&configure_request, &mut dst).unwrap();
+/// assert_eq!(
+///     &dst[..],
+///     b"\x7e\xff\x7d\x23\xc0\x21\x7d\x21\x7d\x21\x7d\x20\x7d\x24\xd1\xb5\x7e"
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct PppCodec {
+    frames: FrameCodec<Hdlc<Vec<u8>>>,
+    framer: Hdlc<[u8; 0]>,
+    lcp_framer: Hdlc<[u8; 0]>,
+    compress_address_control: bool,
+    compress_protocol: bool,
+}
+
+impl PppCodec {
+    /// A codec with an MRU of [`DEFAULT_MRU`] bytes.
+    pub fn new() -> Self {
+        Self::with_mru(DEFAULT_MRU)
+    }
+
+    /// A codec receiving information fields of at most `mru` bytes.
+    pub fn with_mru(mru: usize) -> Self {
+        Self {
+            frames: FrameCodec::new(Hdlc::new(vec![0; mru + OVERHEAD]).accm(DEFAULT_ACCM)),
+            framer: Hdlc::new([]).accm(DEFAULT_ACCM),
+            lcp_framer: Hdlc::new([]).accm(DEFAULT_ACCM),
+            compress_address_control: false,
+            compress_protocol: false,
+        }
+    }
+
+    /// Set the control characters escaped in sent frames, as asked by the peer.
+    pub fn set_send_accm(&mut self, accm: u32) {
+        self.framer.set_accm(accm);
+    }
+
+    /// Set the control characters dropped from received frames, as asked from the
+    /// peer, which escapes them.
+    pub fn set_receive_accm(&mut self, accm: u32) {
+        self.frames.get_mut().set_accm(accm);
+    }
+
+    /// Set whether sent frames leave out the address and control fields.
+    pub fn set_address_control_compression(&mut self, compress: bool) {
+        self.compress_address_control = compress;
+    }
+
+    /// Set whether sent frames carry protocol numbers below `0x100` as one byte.
+    pub fn set_protocol_compression(&mut self, compress: bool) {
+        self.compress_protocol = compress;
+    }
+
+    /// Returns the largest information field which can be received.
+    pub fn mru(&self) -> usize {
+        self.frames.get_ref().capacity() - OVERHEAD
+    }
+
+    fn parse(frame: &[u8]) -> Result<PppFrame, PppError> {
+        let frame = match frame {
+            [ADDRESS, CONTROL, rest @ ..] => rest,
+            [ADDRESS, ..] => return Err(PppError::InvalidHeader),
+            frame => frame,
+        };
+        // Protocol numbers have an odd low byte and an even high byte.
+        let (protocol, information) = match frame {
+            [low, rest @ ..] if low & 1 == 1 => (u16::from(*low), rest),
+            [high, low, rest @ ..] if low & 1 == 1 => (u16::from_be_bytes([*high, *low]), rest),
+            _ => return Err(PppError::InvalidProtocol),
+        };
+        Ok(PppFrame {
+            protocol,
+            information: Bytes::copy_from_slice(information),
+        })
+    }
+}
+
+impl Default for PppCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for PppCodec {
+    type Item = PppFrame;
+    type Error = FrameError<PppError>;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<PppFrame>, Self::Error> {
+        let frame = match self.frames.decode(src) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Ok(None),
+            Err(FrameError::Io(err)) => return Err(FrameError::Io(err)),
+            Err(FrameError::Frame(err)) => return Err(FrameError::Frame(PppError::Hdlc(err))),
+        };
+        Self::parse(&frame).map(Some).map_err(FrameError::Frame)
+    }
+}
+
+impl Encoder<&PppFrame> for PppCodec {
+    type Error = FrameError<PppError>;
+
+    fn encode(&mut self, item: &PppFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let [high, low] = item.protocol.to_be_bytes();
+        if low & 1 == 0 || high & 1 == 1 {
+            return Err(FrameError::Frame(PppError::Unsendable(item.protocol)));
+        }
+        let lcp = item.protocol == protocol::LCP;
+        let mut payload = Vec::with_capacity(item.information.len() + 4);
+        if lcp || !self.compress_address_control {
+            payload.extend_from_slice(&[ADDRESS, CONTROL]);
+        }
+        if !lcp && self.compress_protocol && high == 0 {
+            payload.push(low);
+        } else {
+            payload.extend_from_slice(&[high, low]);
+        }
+        payload.extend_from_slice(&item.information);
+
+        let framer = if lcp {
+            &mut self.lcp_framer
+        } else {
+            &mut self.framer
+        };
+        match framer.frame(&payload, |chunk| dst.extend_from_slice(chunk)) {
+            Ok(()) => Ok(()),
+            Err(never) => match never {},
+        }
+    }
+}
+
+impl Encoder<PppFrame> for PppCodec {
+    type Error = FrameError<PppError>;
+
+    fn encode(&mut self, item: PppFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&item, dst)
+    }
+}
@@ -0,0 +1,201 @@
+//! Protocol Buffers messages, each prefixed by its varint length
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, BytesMut};
+use prost::Message;
+use std::marker::PhantomData;
+use std::{error, fmt, io};
+
+/// The default largest message read or written by [`ProtobufCodec`]
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 4096;
+
+// The longest varint encoding of a `u64`.
+const MAX_VARINT_LEN: usize = 10;
+
+/// Errors produced by [`ProtobufCodec`]
+#[derive(Debug)]
+pub enum ProtobufError {
+    /// The underlying I/O failed
+    Io(io::Error),
+    /// A message is larger than the maximum size.  Received ones are skipped
+    /// without being buffered.
+    TooLong(u64),
+    /// The length prefix of a message is not a valid varint, the byte is skipped
+    InvalidLength,
+    /// A message could not be decoded, it is skipped
+    Decode(prost::DecodeError),
+}
+
+impl fmt::Display for ProtobufError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtobufError::Io(err) => err.fmt(f),
+            ProtobufError::TooLong(len) => write!(f, "protobuf message of {} bytes too long", len),
+            ProtobufError::InvalidLength => f.write_str("invalid protobuf length prefix"),
+            ProtobufError::Decode(err) => write!(f, "invalid protobuf message: {}", err),
+        }
+    }
+}
+
+impl error::Error for ProtobufError {}
+
+impl From<io::Error> for ProtobufError {
+    fn from(err: io::Error) -> Self {
+        ProtobufError::Io(err)
+    }
+}
+
+/// A codec for streams of [prost] messages, each prefixed by its length as a varint
+///
+/// This is the usual way to send several Protocol Buffers messages over a stream,
+/// as written by `writeDelimitedTo` in Java, `SerializeDelimitedToOstream` in C++
+/// and `pb_encode_delimited` in nanopb firmware.  Messages larger than the
+/// maximum size, [`DEFAULT_MAX_MESSAGE_SIZE`] by default, are rejected when
+/// encoded and skipped without being buffered when received, so a corrupt length
+/// cannot make the codec allocate unbounded memory.
+///
+/// `D` is the type of decoded messages, `E` the type of encoded ones, `D` by
+/// default.
+///
+/// ```
+/// use tokio_serial::codec::ProtobufCodec;
+///
+/// #[derive(Clone, PartialEq, prost::Message)]
+/// struct Measurement {
+///     #[prost(uint32, tag = "1")]
+///     sensor: u32,
+///     #[prost(float, tag = "2")]
+///     value: f32,
+/// }
+///
+/// let codec = ProtobufCodec::<Measurement>::new().max_message_size(256);
+/// ```
+///
+/// [prost]: https://docs.rs/prost
+pub struct ProtobufCodec<D, E = D> {
+    max_message_size: usize,
+    // Bytes left to skip of a message over the maximum size.
+    skipping: u64,
+    _messages: PhantomData<fn(E) -> D>,
+}
+
+impl<D, E> ProtobufCodec<D, E> {
+    /// A codec for messages of at most [`DEFAULT_MAX_MESSAGE_SIZE`] bytes.
+    pub fn new() -> Self {
+        Self {
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            skipping: 0,
+            _messages: PhantomData,
+        }
+    }
+
+    /// Set the maximum size of a message, length prefix excluded.
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = size;
+        self
+    }
+
+    // The length prefix at the start of `src` and its size, if complete.
+    fn length(src: &[u8]) -> Result<Option<(u64, usize)>, ProtobufError> {
+        let mut len = 0u64;
+        for (i, &byte) in src.iter().take(MAX_VARINT_LEN).enumerate() {
+            len |= u64::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Ok(Some((len, i + 1)));
+            }
+        }
+        if src.len() >= MAX_VARINT_LEN {
+            return Err(ProtobufError::InvalidLength);
+        }
+        Ok(None)
+    }
+}
+
+impl<D, E> Default for ProtobufCodec<D, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D, E> fmt::Debug for ProtobufCodec<D, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProtobufCodec")
+            .field("max_message_size", &self.max_message_size)
+            .finish()
+    }
+}
+
+impl<D, E> Clone for ProtobufCodec<D, E> {
+    fn clone(&self) -> Self {
+        Self {
+            max_message_size: self.max_message_size,
+            skipping: self.skipping,
+            _messages: PhantomData,
+        }
+    }
+}
+
+impl<D: Message + Default, E> Decoder for ProtobufCodec<D, E> {
+    type Item = D;
+    type Error = ProtobufError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<D>, ProtobufError> {
+        if self.skipping > 0 {
+            let skipped = self.skipping.min(src.len() as u64);
+            src.advance(skipped as usize);
+            self.skipping -= skipped;
+            if self.skipping > 0 {
+                return Ok(None);
+            }
+        }
+
+        let (len, prefix) = match Self::length(src) {
+            Ok(Some(length)) => length,
+            Ok(None) => return Ok(None),
+            Err(err) => {
+                src.advance(1);
+                return Err(err);
+            }
+        };
+        if len > self.max_message_size as u64 {
+            src.advance(prefix);
+            let skipped = len.min(src.len() as u64);
+            src.advance(skipped as usize);
+            self.skipping = len - skipped;
+            return Err(ProtobufError::TooLong(len));
+        }
+        let len = len as usize;
+        if src.len() < prefix + len {
+            src.reserve(prefix + len - src.len());
+            return Ok(None);
+        }
+        src.advance(prefix);
+        let message = src.split_to(len);
+        D::decode(message).map(Some).map_err(ProtobufError::Decode)
+    }
+}
+
+impl<D, E: Message> Encoder<&E> for ProtobufCodec<D, E> {
+    type Error = ProtobufError;
+
+    fn encode(&mut self, message: &E, dst: &mut BytesMut) -> Result<(), ProtobufError> {
+        let len = message.encoded_len();
+        if len > self.max_message_size {
+            return Err(ProtobufError::TooLong(len as u64));
+        }
+        dst.reserve(prost::length_delimiter_len(len) + len);
+        // The buffer was reserved, encoding cannot run out of room.
+        message
+            .encode_length_delimited(dst)
+            .expect("buffer too small for protobuf message");
+        Ok(())
+    }
+}
+
+impl<D, E: Message> Encoder<E> for ProtobufCodec<D, E> {
+    type Error = ProtobufError;
+
+    fn encode(&mut self, message: E, dst: &mut BytesMut) -> Result<(), ProtobufError> {
+        self.encode(&message, dst)
+    }
+}
@@ -0,0 +1,187 @@
+//! Recovery from decoding errors
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, BytesMut};
+use std::fmt;
+
+/// What [`Resync`] does with the input after its codec rejected a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Recovery {
+    /// Keep decoding right after the bytes the codec consumed
+    Continue,
+    /// Also drop the next `n` bytes
+    Drop(usize),
+    /// Frames start with this sync byte: the bytes before it are dropped, and after
+    /// an error the bytes of the rejected frame are searched for the next one
+    ///
+    /// A frame whose length was corrupted does not swallow the next frame this way.
+    /// Codecs skipping input by themselves after an error, such as
+    /// [`LengthDelimitedCodec`](super::LengthDelimitedCodec) after a frame over its
+    /// maximum length, still do so.
+    SkipTo(u8),
+}
+
+/// A codec wrapper recovering from decoding errors
+///
+/// Codecs report malformed input as errors, but how they resume afterwards
+/// depends on the protocol: a framing with a delimiter resynchronizes on the next
+/// one by itself, a length prefix corrupted by noise on an RS-485 line can make a
+/// codec wait for bytes which are the start of the next frames.  `Resync` applies
+/// a [`Recovery`] after each error of the wrapped codec.
+///
+/// Errors are handed out by default.  With
+/// [`report_errors(false)`](Resync::report_errors) they are only logged and
+/// counted, for `tokio_util`'s `Framed`, whose stream ends after an error.  When
+/// the codec rejects input without consuming it, at least a byte is dropped so
+/// decoding always makes progress.
+///
+/// ```
+/// use tokio_serial::codec::{Checksum, ChecksumCodec, LengthDelimitedCodec};
+/// use tokio_serial::codec::{Recovery, Resync};
+///
+/// // A 0x7e start byte, a length and a payload with its sum.
+/// let frames = LengthDelimitedCodec::new()
+///     .length_field_offset(1)
+///     .length_field_length(1);
+/// let codec = Resync::new(
+///     ChecksumCodec::new(frames, Checksum::Sum8),
+///     Recovery::SkipTo(0x7e),
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Resync<C> {
+    inner: C,
+    recovery: Recovery,
+    report_errors: bool,
+    // Bytes left to drop after an error.
+    dropping: usize,
+    errors: u64,
+}
+
+impl<C> Resync<C> {
+    /// Apply `recovery` after the errors of `inner`.
+    pub fn new(inner: C, recovery: Recovery) -> Self {
+        Self {
+            inner,
+            recovery,
+            report_errors: true,
+            dropping: 0,
+            errors: 0,
+        }
+    }
+
+    /// Set whether errors are handed out, `true` by default, or only logged.
+    pub fn report_errors(mut self, report: bool) -> Self {
+        self.report_errors = report;
+        self
+    }
+
+    /// Returns the number of errors of the wrapped codec so far.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Returns a reference to the wrapped codec.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped codec.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the wrapped codec.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    // Drop the bytes the recovery asks for, returns whether decoding can go on.
+    fn skip(&mut self, src: &mut BytesMut) -> bool {
+        if self.dropping > 0 {
+            let dropped = self.dropping.min(src.len());
+            src.advance(dropped);
+            self.dropping -= dropped;
+        }
+        if let Recovery::SkipTo(sync) = self.recovery {
+            match src.iter().position(|&byte| byte == sync) {
+                Some(at) => src.advance(at),
+                None => {
+                    src.clear();
+                    return false;
+                }
+            }
+        }
+        self.dropping == 0
+    }
+
+    fn decode_with<F>(
+        &mut self,
+        src: &mut BytesMut,
+        mut decode: F,
+    ) -> Result<Option<C::Item>, C::Error>
+    where
+        C: Decoder,
+        C::Error: fmt::Display,
+        F: FnMut(&mut C, &mut BytesMut) -> Result<Option<C::Item>, C::Error>,
+    {
+        loop {
+            if !self.skip(src) {
+                return Ok(None);
+            }
+            let len = src.len();
+            // The rejected frame is searched for the sync byte again.
+            let copy = match self.recovery {
+                Recovery::SkipTo(_) => Some(src.clone()),
+                _ => None,
+            };
+            let err = match decode(&mut self.inner, src) {
+                Ok(item) => return Ok(item),
+                Err(err) => err,
+            };
+            self.errors += 1;
+            match self.recovery {
+                Recovery::Continue => {}
+                Recovery::Drop(n) => self.dropping = n,
+                Recovery::SkipTo(_) => {
+                    if let Some(copy) = copy {
+                        *src = copy;
+                    }
+                }
+            }
+            if src.len() == len && self.dropping == 0 && !src.is_empty() {
+                // Nothing was consumed, or everything was given back.
+                src.advance(1);
+            }
+            if self.report_errors {
+                return Err(err);
+            }
+            log::debug!("decoding error, resynchronizing: {}", err);
+        }
+    }
+}
+
+impl<C> Decoder for Resync<C>
+where
+    C: Decoder,
+    C::Error: fmt::Display,
+{
+    type Item = C::Item;
+    type Error = C::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<C::Item>, C::Error> {
+        self.decode_with(src, |inner, src| inner.decode(src))
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<C::Item>, C::Error> {
+        self.decode_with(src, |inner, src| inner.decode_eof(src))
+    }
+}
+
+impl<I, C: Encoder<I>> Encoder<I> for Resync<C> {
+    type Error = C::Error;
+
+    fn encode(&mut self, item: I, dst: &mut BytesMut) -> Result<(), C::Error> {
+        self.inner.encode(item, dst)
+    }
+}
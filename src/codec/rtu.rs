@@ -0,0 +1,291 @@
+//! Modbus RTU framing, delimited by silent intervals
+use super::crc::CRC16_MODBUS;
+use super::FrameError;
+use crate::{SerialSettings, SerialStream, ShutdownLayered};
+
+use futures::future::BoxFuture;
+use futures::{Sink, Stream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+use bytes::{Buf, BufMut, BytesMut};
+use futures::ready;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{error, fmt, io};
+
+/// The largest RTU ADU: address, 253 bytes of PDU and CRC
+pub const MAX_ADU_LEN: usize = 256;
+
+// Address, function code and CRC.
+const MIN_ADU_LEN: usize = 4;
+
+/// Errors of [`ModbusRtuFramed`] frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModbusRtuError {
+    /// The frame is shorter than an address, a function code and a CRC
+    TooShort(usize),
+    /// The frame is longer than [`MAX_ADU_LEN`], it is skipped
+    TooLong(usize),
+    /// The CRC does not match the frame
+    Crc,
+}
+
+impl fmt::Display for ModbusRtuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModbusRtuError::TooShort(len) => write!(f, "RTU frame of {} bytes is too short", len),
+            ModbusRtuError::TooLong(len) => write!(f, "RTU frame of {} bytes is too long", len),
+            ModbusRtuError::Crc => f.write_str("RTU frame CRC mismatch"),
+        }
+    }
+}
+
+impl error::Error for ModbusRtuError {}
+
+/// Returns the silent interval delimiting RTU frames sent with `settings`
+///
+/// This is the time of 3.5 characters, or 1.75ms above 19200 baud as the Modbus
+/// specification recommends.
+pub fn silent_interval(settings: &SerialSettings) -> Duration {
+    if settings.baud_rate > 19200 {
+        Duration::from_micros(1750)
+    } else {
+        settings.char_time() * 7 / 2
+    }
+}
+
+/// A [`Stream`] and [`Sink`] of Modbus RTU frames
+///
+/// RTU frames carry no delimiter: a frame ends when the line stays silent for
+/// 3.5 characters.  The stream collects the bytes received until such a silence,
+/// checks their CRC and hands out the whole ADU, address, PDU and CRC included.
+/// Frames failing the check are reported as [`ModbusRtuError`]s and dropped, the
+/// next frame is received normally.
+///
+/// The sink takes ADUs without their CRC, appends it, and writes each frame only
+/// once the line was silent for the interval since the last byte received or
+/// sent, so frames sent back to back stay apart.
+///
+/// The interval is computed from the settings of the port when the stream is
+/// created, see [`silent_interval`].  Timing happens when data is read from the
+/// OS, which may not deliver bytes as they arrive: USB adapters typically buffer
+/// them for a few milliseconds, in which case the interval should be raised to
+/// this latency with [`set_silent_interval`](ModbusRtuFramed::set_silent_interval).
+///
+/// The stream must be polled while frames arrive, frames which piled up unread in
+/// the OS buffer cannot be told apart anymore.
+///
+/// The 1.5 characters inter-character timeout of the specification is not
+/// checked, operating systems cannot time bytes that accurately.
+#[must_use = "streams do nothing unless polled"]
+#[derive(Debug)]
+pub struct ModbusRtuFramed {
+    port: SerialStream,
+    char_time: Duration,
+    silent_interval: Duration,
+    rd: BytesMut,
+    // Bytes of the frame being received, those over the limit are not buffered.
+    rd_len: usize,
+    // The end of the frame being received, reset by each read.
+    rd_deadline: Pin<Box<Sleep>>,
+    wr: BytesMut,
+    // Length of the frame at the start of `wr`.
+    wr_frame: usize,
+    // When the line is silent again, after the last byte received or sent.
+    quiet_at: Instant,
+    wr_deadline: Pin<Box<Sleep>>,
+}
+
+impl ModbusRtuFramed {
+    /// Frame RTU ADUs on `port`, with the silent interval of its current settings.
+    pub fn new(port: SerialStream) -> crate::Result<Self> {
+        let settings = port.settings()?;
+        let now = Instant::now();
+        Ok(Self {
+            port,
+            char_time: settings.char_time(),
+            silent_interval: silent_interval(&settings),
+            rd: BytesMut::with_capacity(MAX_ADU_LEN),
+            rd_len: 0,
+            rd_deadline: Box::pin(tokio::time::sleep_until(now)),
+            wr: BytesMut::with_capacity(MAX_ADU_LEN),
+            wr_frame: 0,
+            quiet_at: now,
+            wr_deadline: Box::pin(tokio::time::sleep_until(now)),
+        })
+    }
+
+    /// Set the silent interval delimiting frames.
+    pub fn set_silent_interval(&mut self, interval: Duration) {
+        self.silent_interval = interval;
+    }
+
+    /// Returns the silent interval delimiting frames.
+    pub fn silent_interval(&self) -> Duration {
+        self.silent_interval
+    }
+
+    /// Returns a reference to the underlying port.
+    pub fn get_ref(&self) -> &SerialStream {
+        &self.port
+    }
+
+    /// Returns a mutable reference to the underlying port.
+    ///
+    /// Reading from or writing to it directly desynchronizes the framing.
+    pub fn get_mut(&mut self) -> &mut SerialStream {
+        &mut self.port
+    }
+
+    /// Consumes the stream, returning the underlying port.
+    pub fn into_inner(self) -> SerialStream {
+        self.port
+    }
+
+    fn check(frame: &[u8], len: usize) -> Result<(), ModbusRtuError> {
+        if len < MIN_ADU_LEN {
+            return Err(ModbusRtuError::TooShort(len));
+        }
+        if len > MAX_ADU_LEN {
+            return Err(ModbusRtuError::TooLong(len));
+        }
+        // The CRC of a frame followed by its CRC is 0.
+        if CRC16_MODBUS.checksum(frame) != 0 {
+            return Err(ModbusRtuError::Crc);
+        }
+        Ok(())
+    }
+
+    /// Write the frames buffered, respecting the silence between them.
+    fn poll_write_frames(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.wr.is_empty() {
+            if self.wr_frame == 0 {
+                // The next frame starts: wait for the line to be silent.
+                self.wr_deadline.as_mut().reset(self.quiet_at);
+                ready!(self.wr_deadline.as_mut().poll(cx));
+                let len = self.wr.get_u16_le() as usize;
+                self.wr_frame = len;
+            }
+            let n = ready!(Pin::new(&mut self.port).poll_write(cx, &self.wr[..self.wr_frame]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "failed to write frame to serial port",
+                )));
+            }
+            self.wr.advance(n);
+            self.wr_frame -= n;
+            // The OS accepted the bytes, they are on the line after those before.
+            let sent = Instant::now().max(self.quiet_at) + self.char_time * n as u32;
+            self.quiet_at = if self.wr_frame == 0 {
+                sent + self.silent_interval
+            } else {
+                sent
+            };
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Stream for ModbusRtuFramed {
+    type Item = Result<BytesMut, FrameError<ModbusRtuError>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let pin = self.get_mut();
+
+        loop {
+            let mut buf = [0u8; MAX_ADU_LEN];
+            let mut read = ReadBuf::new(&mut buf);
+            match Pin::new(&mut pin.port).poll_read(cx, &mut read) {
+                Poll::Ready(Ok(())) if read.filled().is_empty() => {
+                    // The port was closed, the last frame ends here.
+                    if pin.rd.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    break;
+                }
+                Poll::Ready(Ok(())) => {
+                    let filled = read.filled();
+                    let room = MAX_ADU_LEN.saturating_sub(pin.rd.len());
+                    pin.rd.put_slice(&filled[..filled.len().min(room)]);
+                    pin.rd_len += filled.len();
+                    let now = Instant::now();
+                    pin.quiet_at = pin.quiet_at.max(now + pin.silent_interval);
+                    pin.rd_deadline.as_mut().reset(now + pin.silent_interval);
+                }
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                Poll::Pending if pin.rd.is_empty() => return Poll::Pending,
+                Poll::Pending => {
+                    ready!(pin.rd_deadline.as_mut().poll(cx));
+                    break;
+                }
+            }
+        }
+
+        let frame = pin.rd.split();
+        let len = std::mem::take(&mut pin.rd_len);
+        Poll::Ready(Some(match Self::check(&frame, len) {
+            Ok(()) => Ok(frame),
+            Err(err) => {
+                log::debug!("dropping RTU frame: {}", err);
+                Err(FrameError::Frame(err))
+            }
+        }))
+    }
+}
+
+impl Sink<&[u8]> for ModbusRtuFramed {
+    type Error = FrameError<ModbusRtuError>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Frames are sent one at a time, their silence is timed from the last one.
+        ready!(self.get_mut().poll_write_frames(cx))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: &[u8]) -> Result<(), Self::Error> {
+        let pin = self.get_mut();
+        let len = item.len() + 2;
+        if len < MIN_ADU_LEN {
+            return Err(FrameError::Frame(ModbusRtuError::TooShort(len)));
+        }
+        if len > MAX_ADU_LEN {
+            return Err(FrameError::Frame(ModbusRtuError::TooLong(len)));
+        }
+        // Each frame is preceded by its length.
+        pin.wr.reserve(2 + len);
+        pin.wr.put_u16_le(len as u16);
+        pin.wr.put_slice(item);
+        pin.wr.put_u16_le(CRC16_MODBUS.checksum(item));
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let pin = self.get_mut();
+
+        ready!(pin.poll_write_frames(cx))?;
+        ready!(Pin::new(&mut pin.port).poll_flush(cx))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.poll_flush(cx))?;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl ShutdownLayered for ModbusRtuFramed {
+    /// Writes the frames still buffered.
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(futures::future::poll_fn(move |cx| {
+            self.poll_write_frames(cx)
+        }))
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        Some(&mut self.port)
+    }
+}
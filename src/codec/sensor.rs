@@ -0,0 +1,391 @@
+//! Fixed length frames of cheap UART sensors
+//!
+//! Particulate matter and CO2 sensors such as the Nova SDS011, the Plantower
+//! PMS5003 and the Winsen MH-Z19 send their readings in frames of a fixed
+//! length: a header, the payload, a checksum and, for some, a tail byte.  They
+//! all run at 9600 baud 8N1.  The decoders skip the bytes before the header, and
+//! the frames which turn out to be misaligned, so they can start listening at
+//! any point of the stream.
+//!
+//! ```no_run
+//! # async fn example(port: tokio_serial::SerialStream) -> Result<(), tokio_serial::codec::sensor::SensorError> {
+//! use futures::StreamExt;
+//! use tokio_serial::codec::Sds011Codec;
+//! use tokio_serial::SerialFramed;
+//!
+//! // The SDS011 reports once a second by default.
+//! let mut sensor = SerialFramed::new(port, Sds011Codec::new());
+//! while let Some(reading) = sensor.next().await {
+//!     let reading = reading?;
+//!     println!("PM2.5 {} µg/m³, PM10 {} µg/m³", reading.pm2_5, reading.pm10);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, BytesMut};
+use std::{error, fmt, io};
+
+/// Errors produced by the sensor codecs
+#[derive(Debug)]
+pub enum SensorError {
+    /// The underlying I/O failed
+    Io(io::Error),
+    /// The checksum of a frame does not match its content, it is dropped
+    Checksum,
+}
+
+impl fmt::Display for SensorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SensorError::Io(err) => err.fmt(f),
+            SensorError::Checksum => f.write_str("sensor frame checksum mismatch"),
+        }
+    }
+}
+
+impl error::Error for SensorError {}
+
+impl From<io::Error> for SensorError {
+    fn from(err: io::Error) -> Self {
+        SensorError::Io(err)
+    }
+}
+
+// Skip to the next `header`, or to what may be its start at the end of `src`,
+// returns whether a whole frame of `len` bytes is buffered.
+fn align(src: &mut BytesMut, header: &[u8], len: usize) -> bool {
+    let start = (0..src.len())
+        .find(|&at| {
+            let rest = &src[at..];
+            let n = rest.len().min(header.len());
+            rest[..n] == header[..n]
+        })
+        .unwrap_or(src.len());
+    src.advance(start);
+    if src.len() < len {
+        src.reserve(len - src.len());
+        return false;
+    }
+    true
+}
+
+fn sum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+const SDS011_HEADER: u8 = 0xaa;
+const SDS011_TAIL: u8 = 0xab;
+const SDS011_MEASUREMENT: u8 = 0xc0;
+const SDS011_COMMAND: u8 = 0xb4;
+const SDS011_LEN: usize = 10;
+
+/// A reading of an SDS011
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sds011Reading {
+    /// The PM2.5 concentration, in µg/m³
+    pub pm2_5: f32,
+    /// The PM10 concentration, in µg/m³
+    pub pm10: f32,
+    /// The id of the sensor
+    pub id: u16,
+}
+
+/// Commands of the SDS011, sent to all sensors on the port
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sds011Command {
+    /// Report only when queried (`true`), or continuously, the default
+    SetQueryMode(bool),
+    /// Ask for a reading, in query mode
+    Query,
+    /// Stop the fan and the laser
+    Sleep,
+    /// Resume measuring
+    WakeUp,
+    /// Measure every `n` minutes, sleeping in between, or continuously with 0
+    SetWorkingPeriod(u8),
+}
+
+/// A codec for the readings and commands of Nova SDS011 particulate sensors
+///
+/// Readings are `AA C0`, the PM2.5 and PM10 concentrations in tenths of
+/// µg/m³ and the id of the sensor, little endian, the sum of these six bytes and
+/// `AB`.  The replies of the sensor to commands are skipped.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sds011Codec;
+
+impl Sds011Codec {
+    /// A codec for SDS011 frames.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Decoder for Sds011Codec {
+    type Item = Sds011Reading;
+    type Error = SensorError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Sds011Reading>, SensorError> {
+        loop {
+            if !align(src, &[SDS011_HEADER], SDS011_LEN) {
+                return Ok(None);
+            }
+            if src[SDS011_LEN - 1] != SDS011_TAIL {
+                src.advance(1);
+                continue;
+            }
+            if sum(&src[2..8]) != src[8] {
+                src.advance(1);
+                return Err(SensorError::Checksum);
+            }
+            let frame = src.split_to(SDS011_LEN);
+            if frame[1] != SDS011_MEASUREMENT {
+                log::debug!("skipping SDS011 reply {:02x?}", &frame[..]);
+                continue;
+            }
+            let word = |at: usize| u16::from_le_bytes([frame[at], frame[at + 1]]);
+            return Ok(Some(Sds011Reading {
+                pm2_5: f32::from(word(2)) / 10.0,
+                pm10: f32::from(word(4)) / 10.0,
+                id: word(6),
+            }));
+        }
+    }
+}
+
+impl Encoder<Sds011Command> for Sds011Codec {
+    type Error = SensorError;
+
+    fn encode(&mut self, command: Sds011Command, dst: &mut BytesMut) -> Result<(), SensorError> {
+        let data: [u8; 3] = match command {
+            Sds011Command::SetQueryMode(query) => [0x02, 0x01, query as u8],
+            Sds011Command::Query => [0x04, 0x00, 0x00],
+            Sds011Command::Sleep => [0x06, 0x01, 0x00],
+            Sds011Command::WakeUp => [0x06, 0x01, 0x01],
+            Sds011Command::SetWorkingPeriod(minutes) => [0x08, 0x01, minutes],
+        };
+        // The command, its data padded to 12 bytes and the id of all sensors.
+        let mut payload = [0u8; 15];
+        payload[..3].copy_from_slice(&data);
+        payload[13..].copy_from_slice(&[0xff, 0xff]);
+        dst.reserve(19);
+        dst.extend_from_slice(&[SDS011_HEADER, SDS011_COMMAND]);
+        dst.extend_from_slice(&payload);
+        dst.extend_from_slice(&[sum(&payload), SDS011_TAIL]);
+        Ok(())
+    }
+}
+
+fn be_word(bytes: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([bytes[at], bytes[at + 1]])
+}
+
+const PMS_HEADER: [u8; 2] = [0x42, 0x4d];
+// The header, the length, 13 words of data and the checksum.
+const PMS_LEN: usize = 32;
+
+/// A reading of a PMS5003
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Pms5003Reading {
+    /// The PM1.0 concentration under atmospheric conditions, in µg/m³
+    pub pm1_0: u16,
+    /// The PM2.5 concentration under atmospheric conditions, in µg/m³
+    pub pm2_5: u16,
+    /// The PM10 concentration under atmospheric conditions, in µg/m³
+    pub pm10: u16,
+    /// The PM1.0 concentration of the factory calibration (CF=1), in µg/m³
+    pub pm1_0_cf1: u16,
+    /// The PM2.5 concentration of the factory calibration (CF=1), in µg/m³
+    pub pm2_5_cf1: u16,
+    /// The PM10 concentration of the factory calibration (CF=1), in µg/m³
+    pub pm10_cf1: u16,
+    /// The particles over 0.3, 0.5, 1.0, 2.5, 5.0 and 10µm in 0.1L of air
+    pub particles: [u16; 6],
+}
+
+/// Commands of the PMS5003
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pms5003Command {
+    /// Report continuously, the default
+    Active,
+    /// Report only when asked with [`Read`](Pms5003Command::Read)
+    Passive,
+    /// Ask for a reading, in passive mode
+    Read,
+    /// Stop the fan
+    Sleep,
+    /// Resume measuring, readings are stable 30 seconds later
+    WakeUp,
+}
+
+/// A codec for the readings and commands of Plantower PMS5003 particulate sensors
+///
+/// Readings are `42 4D`, the length of the rest of the frame, 13 big endian
+/// words, and their 16 bit sum.  The similar PMS7003 and PMSA003 send the same
+/// frames.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pms5003Codec;
+
+impl Pms5003Codec {
+    /// A codec for PMS5003 frames.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Decoder for Pms5003Codec {
+    type Item = Pms5003Reading;
+    type Error = SensorError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Pms5003Reading>, SensorError> {
+        loop {
+            if !align(src, &PMS_HEADER, PMS_LEN) {
+                return Ok(None);
+            }
+            if usize::from(be_word(src, 2)) != PMS_LEN - 4 {
+                // The header in the noise, or a reply to a command.
+                src.advance(1);
+                continue;
+            }
+            let expected = src[..PMS_LEN - 2]
+                .iter()
+                .fold(0u16, |sum, &byte| sum.wrapping_add(u16::from(byte)));
+            if expected != be_word(src, PMS_LEN - 2) {
+                src.advance(1);
+                return Err(SensorError::Checksum);
+            }
+            let frame = src.split_to(PMS_LEN);
+            let mut particles = [0u16; 6];
+            for (i, count) in particles.iter_mut().enumerate() {
+                *count = be_word(&frame, 16 + 2 * i);
+            }
+            return Ok(Some(Pms5003Reading {
+                pm1_0_cf1: be_word(&frame, 4),
+                pm2_5_cf1: be_word(&frame, 6),
+                pm10_cf1: be_word(&frame, 8),
+                pm1_0: be_word(&frame, 10),
+                pm2_5: be_word(&frame, 12),
+                pm10: be_word(&frame, 14),
+                particles,
+            }));
+        }
+    }
+}
+
+impl Encoder<Pms5003Command> for Pms5003Codec {
+    type Error = SensorError;
+
+    fn encode(&mut self, command: Pms5003Command, dst: &mut BytesMut) -> Result<(), SensorError> {
+        let (command, data) = match command {
+            Pms5003Command::Active => (0xe1, 0x01),
+            Pms5003Command::Passive => (0xe1, 0x00),
+            Pms5003Command::Read => (0xe2, 0x00),
+            Pms5003Command::Sleep => (0xe4, 0x00),
+            Pms5003Command::WakeUp => (0xe4, 0x01),
+        };
+        let frame = [PMS_HEADER[0], PMS_HEADER[1], command, 0x00, data];
+        let sum = frame
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(u16::from(byte)));
+        dst.reserve(7);
+        dst.extend_from_slice(&frame);
+        dst.extend_from_slice(&sum.to_be_bytes());
+        Ok(())
+    }
+}
+
+const MHZ19_START: u8 = 0xff;
+const MHZ19_READ: u8 = 0x86;
+const MHZ19_LEN: usize = 9;
+
+/// A reading of an MH-Z19
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MhZ19Reading {
+    /// The CO2 concentration, in ppm
+    pub co2: u16,
+    /// The temperature of the sensor, in °C, a rough indication only
+    pub temperature: i16,
+}
+
+/// Commands of the MH-Z19
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MhZ19Command {
+    /// Ask for a reading
+    Read,
+    /// Take the current concentration as 400ppm, after 20 minutes in fresh air
+    CalibrateZero,
+    /// Enable or disable the automatic baseline correction, which takes the lowest
+    /// reading of each day as 400ppm
+    SetAutoCalibration(bool),
+    /// Set the upper end of the range, 2000 or 5000ppm
+    SetRange(u16),
+}
+
+/// A codec for the readings and commands of Winsen MH-Z19 CO2 sensors
+///
+/// The sensor only answers commands: readings are asked for with
+/// [`MhZ19Command::Read`].  Frames are 9 bytes, `FF`, the sensor number or
+/// command, six data bytes and the negated sum of the bytes in between.  Replies
+/// to other commands are skipped.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MhZ19Codec;
+
+impl MhZ19Codec {
+    /// A codec for MH-Z19 frames.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Decoder for MhZ19Codec {
+    type Item = MhZ19Reading;
+    type Error = SensorError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<MhZ19Reading>, SensorError> {
+        loop {
+            if !align(src, &[MHZ19_START], MHZ19_LEN) {
+                return Ok(None);
+            }
+            if sum(&src[1..MHZ19_LEN - 1]).wrapping_neg() != src[MHZ19_LEN - 1] {
+                src.advance(1);
+                return Err(SensorError::Checksum);
+            }
+            let frame = src.split_to(MHZ19_LEN);
+            if frame[1] != MHZ19_READ {
+                log::debug!("skipping MH-Z19 reply {:02x?}", &frame[..]);
+                continue;
+            }
+            return Ok(Some(MhZ19Reading {
+                co2: u16::from_be_bytes([frame[2], frame[3]]),
+                temperature: i16::from(frame[4]) - 40,
+            }));
+        }
+    }
+}
+
+impl Encoder<MhZ19Command> for MhZ19Codec {
+    type Error = SensorError;
+
+    fn encode(&mut self, command: MhZ19Command, dst: &mut BytesMut) -> Result<(), SensorError> {
+        // Sensor number 1, the command and its data.
+        let mut payload = [0x01, 0, 0, 0, 0, 0, 0];
+        match command {
+            MhZ19Command::Read => payload[1] = MHZ19_READ,
+            MhZ19Command::CalibrateZero => payload[1] = 0x87,
+            MhZ19Command::SetAutoCalibration(enabled) => {
+                payload[1] = 0x79;
+                payload[2] = if enabled { 0xa0 } else { 0x00 };
+            }
+            MhZ19Command::SetRange(range) => {
+                payload[1] = 0x99;
+                payload[5..7].copy_from_slice(&range.to_be_bytes());
+            }
+        }
+        dst.reserve(MHZ19_LEN);
+        dst.extend_from_slice(&[MHZ19_START]);
+        dst.extend_from_slice(&payload);
+        dst.extend_from_slice(&[sum(&payload).wrapping_neg()]);
+        Ok(())
+    }
+}
@@ -0,0 +1,403 @@
+//! SLCAN, the LAWICEL protocol of serial line CAN adapters
+//!
+//! Commands and frames are ASCII lines ended by a carriage return, which is also
+//! how adapters acknowledge commands, and `BEL` how they reject them:
+//!
+//! * `O`, `L` and `C` open the channel, open it listening only, and close it;
+//! * `S0` to `S8` set the bitrate, while the channel is closed;
+//! * `t1234A1B2C3D4` sends a frame with the 11 bit id `0x123`, 4 bytes of data;
+//! * `T` frames have 29 bit ids, `r` and `R` are remote frames.
+//!
+//! Received frames come in the same format, followed by a 16 bit millisecond
+//! timestamp once timestamps are enabled with `Z1`.
+//!
+//! ```no_run
+//! # async fn example(port: tokio_serial::SerialStream) -> Result<(), Box<dyn std::error::Error>> {
+//! use futures::{SinkExt, StreamExt};
+//! use tokio_serial::codec::slcan::{Bitrate, CanFrame, CanId, SlcanCommand, SlcanResponse};
+//! use tokio_serial::codec::SlcanCodec;
+//! use tokio_serial::SerialFramed;
+//!
+//! let mut can = SerialFramed::new(port, SlcanCodec::new());
+//! can.send(SlcanCommand::Bitrate(Bitrate::K500)).await?;
+//! can.send(SlcanCommand::Open).await?;
+//! can.send(SlcanCommand::Transmit(CanFrame::new(CanId::Standard(0x123), &[1, 2]).unwrap()))
+//!     .await?;
+//! while let Some(response) = can.next().await {
+//!     if let SlcanResponse::Frame { frame, .. } = response? {
+//!         println!("{:?}: {:02x?}", frame.id(), frame.data());
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use super::FrameError;
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, BufMut, BytesMut};
+use std::fmt::Write;
+use std::{error, fmt};
+
+const OK: u8 = b'\r';
+const BELL: u8 = 0x07;
+// A 29 bit id, 8 bytes of data and a timestamp, with room to spare.
+const MAX_LINE: usize = 64;
+
+/// Errors of [`SlcanCodec`] lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlcanError {
+    /// A line is longer than any SLCAN message, it is skipped
+    TooLong,
+    /// A line is not a SLCAN message
+    Invalid(String),
+}
+
+impl fmt::Display for SlcanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlcanError::TooLong => f.write_str("SLCAN line too long"),
+            SlcanError::Invalid(line) => write!(f, "invalid SLCAN line {:?}", line),
+        }
+    }
+}
+
+impl error::Error for SlcanError {}
+
+/// The identifier of a CAN frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CanId {
+    /// An 11 bit identifier
+    Standard(u16),
+    /// A 29 bit identifier
+    Extended(u32),
+}
+
+impl CanId {
+    fn is_valid(self) -> bool {
+        match self {
+            CanId::Standard(id) => id <= 0x7ff,
+            CanId::Extended(id) => id <= 0x1fff_ffff,
+        }
+    }
+}
+
+/// A classic CAN frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CanFrame {
+    id: CanId,
+    remote: bool,
+    dlc: u8,
+    data: [u8; 8],
+}
+
+impl CanFrame {
+    /// A data frame, `None` if the id is out of range or there are more than 8
+    /// bytes of data.
+    pub fn new(id: CanId, data: &[u8]) -> Option<Self> {
+        if !id.is_valid() || data.len() > 8 {
+            return None;
+        }
+        let mut frame = Self {
+            id,
+            remote: false,
+            dlc: data.len() as u8,
+            data: [0; 8],
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        Some(frame)
+    }
+
+    /// A remote frame asking for `dlc` bytes, `None` if the id is out of range or
+    /// `dlc` is over 8.
+    pub fn remote(id: CanId, dlc: u8) -> Option<Self> {
+        if !id.is_valid() || dlc > 8 {
+            return None;
+        }
+        Some(Self {
+            id,
+            remote: true,
+            dlc,
+            data: [0; 8],
+        })
+    }
+
+    /// Returns the identifier of the frame.
+    pub fn id(&self) -> CanId {
+        self.id
+    }
+
+    /// Returns whether the frame is a remote frame.
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    /// Returns the data length code of the frame.
+    pub fn dlc(&self) -> u8 {
+        self.dlc
+    }
+
+    /// Returns the data of the frame, empty for remote frames.
+    pub fn data(&self) -> &[u8] {
+        if self.remote {
+            &[]
+        } else {
+            &self.data[..usize::from(self.dlc)]
+        }
+    }
+}
+
+/// The bitrates of the `S` command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Bitrate {
+    /// `S0`, 10 kbit/s
+    K10,
+    /// `S1`, 20 kbit/s
+    K20,
+    /// `S2`, 50 kbit/s
+    K50,
+    /// `S3`, 100 kbit/s
+    K100,
+    /// `S4`, 125 kbit/s
+    K125,
+    /// `S5`, 250 kbit/s
+    K250,
+    /// `S6`, 500 kbit/s
+    K500,
+    /// `S7`, 800 kbit/s
+    K800,
+    /// `S8`, 1 Mbit/s
+    M1,
+}
+
+impl Bitrate {
+    fn code(self) -> u8 {
+        match self {
+            Bitrate::K10 => b'0',
+            Bitrate::K20 => b'1',
+            Bitrate::K50 => b'2',
+            Bitrate::K100 => b'3',
+            Bitrate::K125 => b'4',
+            Bitrate::K250 => b'5',
+            Bitrate::K500 => b'6',
+            Bitrate::K800 => b'7',
+            Bitrate::M1 => b'8',
+        }
+    }
+}
+
+/// A command for the adapter
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SlcanCommand {
+    /// `O`, open the channel
+    Open,
+    /// `L`, open the channel without acknowledging or sending frames
+    ListenOnly,
+    /// `C`, close the channel
+    Close,
+    /// `Sn`, set the bitrate
+    Bitrate(Bitrate),
+    /// `sxxyy`, set the bit timing registers of a SJA1000 controller
+    BitTiming(u8, u8),
+    /// `t`, `T`, `r` or `R`, send a frame
+    Transmit(CanFrame),
+    /// `Z0` or `Z1`, timestamp received frames or not
+    Timestamps(bool),
+    /// `F`, read the status flags
+    Status,
+    /// `V`, read the hardware and firmware versions
+    Version,
+    /// `N`, read the serial number
+    SerialNumber,
+}
+
+/// What the adapter sends
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SlcanResponse {
+    /// A frame received from the bus
+    Frame {
+        /// The frame
+        frame: CanFrame,
+        /// When it was received, in milliseconds modulo 60000, if enabled
+        timestamp: Option<u16>,
+    },
+    /// A carriage return, the last command succeeded
+    Ok,
+    /// `BEL`, the last command failed
+    Error,
+    /// `z` or `Z`, a frame was queued for transmission
+    Transmitted,
+    /// `Fxx`, the status flags
+    Status(u8),
+    /// `Vhhff`, the hardware and firmware versions
+    Version(String),
+    /// `Nxxxx`, the serial number
+    SerialNumber(String),
+}
+
+/// A codec for SLCAN adapters
+///
+/// Encodes [`SlcanCommand`]s and decodes what the adapter sends into
+/// [`SlcanResponse`]s: the frames received from the bus as well as the answers to
+/// commands.  Lines which are not SLCAN messages are reported as
+/// [`SlcanError::Invalid`] and skipped.
+///
+/// CAN FD frames are not supported.
+#[derive(Debug, Clone, Default)]
+pub struct SlcanCodec {
+    // Whether the rest of an overlong line is being skipped.
+    skipping: bool,
+}
+
+impl SlcanCodec {
+    /// A codec for SLCAN adapters.
+    pub fn new() -> Self {
+        Self { skipping: false }
+    }
+}
+
+fn hex(digits: &[u8]) -> Option<u32> {
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    u32::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+}
+
+fn parse_frame(line: &[u8]) -> Option<(CanFrame, Option<u16>)> {
+    let (extended, remote) = match line[0] {
+        b't' => (false, false),
+        b'T' => (true, false),
+        b'r' => (false, true),
+        b'R' => (true, true),
+        _ => return None,
+    };
+    let id_len = if extended { 8 } else { 3 };
+    let raw_id = hex(line.get(1..1 + id_len)?)?;
+    let id = if extended {
+        CanId::Extended(raw_id)
+    } else {
+        CanId::Standard(raw_id as u16)
+    };
+    let dlc = hex(line.get(1 + id_len..2 + id_len)?)? as u8;
+    let mut rest = &line[2 + id_len..];
+    let frame = if remote {
+        CanFrame::remote(id, dlc)?
+    } else {
+        let mut data = [0u8; 8];
+        let data = data.get_mut(..usize::from(dlc))?;
+        for byte in data.iter_mut() {
+            *byte = hex(rest.get(..2)?)? as u8;
+            rest = &rest[2..];
+        }
+        CanFrame::new(id, data)?
+    };
+    let timestamp = match rest.len() {
+        0 => None,
+        4 => Some(hex(rest)? as u16),
+        _ => return None,
+    };
+    Some((frame, timestamp))
+}
+
+fn parse(line: &[u8]) -> Option<SlcanResponse> {
+    let text = || String::from_utf8_lossy(&line[1..]).into_owned();
+    Some(match line.first() {
+        None => SlcanResponse::Ok,
+        Some(b'z') | Some(b'Z') if line.len() == 1 => SlcanResponse::Transmitted,
+        Some(b't') | Some(b'T') | Some(b'r') | Some(b'R') => {
+            let (frame, timestamp) = parse_frame(line)?;
+            SlcanResponse::Frame { frame, timestamp }
+        }
+        Some(b'F') if line.len() == 3 => SlcanResponse::Status(hex(&line[1..])? as u8),
+        Some(b'V') => SlcanResponse::Version(text()),
+        Some(b'N') => SlcanResponse::SerialNumber(text()),
+        Some(_) => return None,
+    })
+}
+
+impl Decoder for SlcanCodec {
+    type Item = SlcanResponse;
+    type Error = FrameError<SlcanError>;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<SlcanResponse>, Self::Error> {
+        let end = match src.iter().position(|&b| b == OK || b == BELL) {
+            Some(end) => end,
+            None => {
+                if src.len() > MAX_LINE {
+                    src.clear();
+                    if !self.skipping {
+                        self.skipping = true;
+                        return Err(FrameError::Frame(SlcanError::TooLong));
+                    }
+                }
+                return Ok(None);
+            }
+        };
+        let line = src.split_to(end);
+        let terminator = src.get_u8();
+        if std::mem::take(&mut self.skipping) {
+            return self.decode(src);
+        }
+        if terminator == BELL {
+            // Errors are a lone BEL, anything before it is noise.
+            if !line.is_empty() {
+                log::debug!("dropping {} bytes before a SLCAN error", line.len());
+            }
+            return Ok(Some(SlcanResponse::Error));
+        }
+        match parse(&line) {
+            Some(response) => Ok(Some(response)),
+            None => Err(FrameError::Frame(SlcanError::Invalid(
+                String::from_utf8_lossy(&line).into_owned(),
+            ))),
+        }
+    }
+}
+
+impl Encoder<&SlcanCommand> for SlcanCodec {
+    type Error = FrameError<SlcanError>;
+
+    fn encode(&mut self, item: &SlcanCommand, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let mut line = String::with_capacity(32);
+        match item {
+            SlcanCommand::Open => line.push('O'),
+            SlcanCommand::ListenOnly => line.push('L'),
+            SlcanCommand::Close => line.push('C'),
+            SlcanCommand::Bitrate(bitrate) => {
+                line.push('S');
+                line.push(char::from(bitrate.code()));
+            }
+            SlcanCommand::BitTiming(btr0, btr1) => {
+                let _ = write!(line, "s{:02X}{:02X}", btr0, btr1);
+            }
+            SlcanCommand::Transmit(frame) => {
+                let _ = match (frame.id, frame.remote) {
+                    (CanId::Standard(id), false) => write!(line, "t{:03X}", id),
+                    (CanId::Extended(id), false) => write!(line, "T{:08X}", id),
+                    (CanId::Standard(id), true) => write!(line, "r{:03X}", id),
+                    (CanId::Extended(id), true) => write!(line, "R{:08X}", id),
+                };
+                let _ = write!(line, "{}", frame.dlc);
+                for byte in frame.data() {
+                    let _ = write!(line, "{:02X}", byte);
+                }
+            }
+            SlcanCommand::Timestamps(on) => line.push_str(if *on { "Z1" } else { "Z0" }),
+            SlcanCommand::Status => line.push('F'),
+            SlcanCommand::Version => line.push('V'),
+            SlcanCommand::SerialNumber => line.push('N'),
+        }
+        dst.reserve(line.len() + 1);
+        dst.put_slice(line.as_bytes());
+        dst.put_u8(OK);
+        Ok(())
+    }
+}
+
+impl Encoder<SlcanCommand> for SlcanCodec {
+    type Error = FrameError<SlcanError>;
+
+    fn encode(&mut self, item: SlcanCommand, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&item, dst)
+    }
+}
@@ -0,0 +1,235 @@
+//! Framing delimited by idle gaps on the line
+use crate::ShutdownLayered;
+
+use futures::future::BoxFuture;
+use futures::{Sink, Stream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+use bytes::{Bytes, BytesMut};
+use futures::ready;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// The default largest frame handed out by [`TimeoutFramed`]
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 4096;
+
+/// A [`Stream`] and [`Sink`] of frames delimited by idle gaps
+///
+/// Many devices send replies without any delimiter or length, the end of a frame
+/// being the line going quiet.  The stream collects the bytes received until the
+/// line stays idle for the configured timeout and hands them out as one frame.
+/// Frames reaching the maximum length are handed out right away, the following
+/// bytes starting the next one.
+///
+/// The sink writes each frame as is, once the line was idle for the timeout
+/// since the last byte received or sent, so frames sent back to back stay apart.
+/// The time the OS takes to actually send the bytes is not known, the timeout
+/// should be longer than the frames take on the wire at the baud rate in use.
+///
+/// Timing happens when data is read from the OS, which may not deliver bytes as
+/// they arrive: USB adapters typically buffer them for a few milliseconds, the
+/// timeout should be well above this latency.  The stream must be polled while
+/// frames arrive, frames which piled up unread in the OS buffer cannot be told
+/// apart anymore.  [`ModbusRtuFramed`](super::ModbusRtuFramed) does the same for
+/// Modbus RTU, with its CRC.
+#[must_use = "streams do nothing unless polled"]
+#[derive(Debug)]
+pub struct TimeoutFramed<T> {
+    inner: T,
+    timeout: Duration,
+    max_frame_length: usize,
+    rd: BytesMut,
+    // The end of the frame being received, reset by each read.
+    rd_deadline: Pin<Box<Sleep>>,
+    wr: VecDeque<Bytes>,
+    // Whether the frame at the front of `wr` was partially written.
+    wr_started: bool,
+    // When the line is idle again, after the last byte received or sent.
+    quiet_at: Instant,
+    wr_deadline: Pin<Box<Sleep>>,
+    eof: bool,
+}
+
+impl<T> TimeoutFramed<T> {
+    /// Frame the data of `inner` by idle gaps of `timeout`.
+    pub fn new(inner: T, timeout: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            inner,
+            timeout,
+            max_frame_length: DEFAULT_MAX_FRAME_LENGTH,
+            rd: BytesMut::new(),
+            rd_deadline: Box::pin(tokio::time::sleep_until(now)),
+            wr: VecDeque::new(),
+            wr_started: false,
+            quiet_at: now,
+            wr_deadline: Box::pin(tokio::time::sleep_until(now)),
+            eof: false,
+        }
+    }
+
+    /// Set the idle time ending frames.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the idle time ending frames.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Set the length at which frames are handed out without waiting for the line
+    /// to go idle, [`DEFAULT_MAX_FRAME_LENGTH`] by default.
+    ///
+    /// # Panics
+    ///
+    /// If `len` is 0.
+    pub fn set_max_frame_length(&mut self, len: usize) {
+        assert!(len > 0, "frames must be allowed at least a byte");
+        self.max_frame_length = len;
+    }
+
+    /// Returns the length at which frames are handed out.
+    pub fn max_frame_length(&self) -> usize {
+        self.max_frame_length
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying stream.
+    ///
+    /// Reading from or writing to it directly desynchronizes the framing.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consumes the stream, returning the underlying stream.
+    ///
+    /// The bytes of a partially received frame are lost.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: AsyncWrite + Unpin> TimeoutFramed<T> {
+    /// Write the frames buffered, respecting the gaps between them.
+    fn poll_write_frames(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while let Some(frame) = self.wr.front_mut() {
+            if !self.wr_started {
+                // The next frame starts: wait for the line to be idle.
+                self.wr_deadline.as_mut().reset(self.quiet_at);
+                ready!(self.wr_deadline.as_mut().poll(cx));
+                self.wr_started = true;
+            }
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, frame))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "failed to write frame",
+                )));
+            }
+            let _ = frame.split_to(n);
+            let done = frame.is_empty();
+            self.quiet_at = Instant::now() + self.timeout;
+            if done {
+                self.wr.pop_front();
+                self.wr_started = false;
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncRead + Unpin> Stream for TimeoutFramed<T> {
+    type Item = io::Result<BytesMut>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let pin = self.get_mut();
+
+        while pin.rd.len() < pin.max_frame_length {
+            if pin.eof {
+                if pin.rd.is_empty() {
+                    return Poll::Ready(None);
+                }
+                break;
+            }
+            let mut buf = [0u8; 1024];
+            let room = buf.len().min(pin.max_frame_length - pin.rd.len());
+            let mut read = ReadBuf::new(&mut buf[..room]);
+            match Pin::new(&mut pin.inner).poll_read(cx, &mut read) {
+                Poll::Ready(Ok(())) if read.filled().is_empty() => {
+                    // The stream was closed, the last frame ends here.
+                    pin.eof = true;
+                }
+                Poll::Ready(Ok(())) => {
+                    pin.rd.extend_from_slice(read.filled());
+                    let now = Instant::now();
+                    pin.quiet_at = pin.quiet_at.max(now + pin.timeout);
+                    pin.rd_deadline.as_mut().reset(now + pin.timeout);
+                }
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err))),
+                Poll::Pending if pin.rd.is_empty() => return Poll::Pending,
+                Poll::Pending => {
+                    ready!(pin.rd_deadline.as_mut().poll(cx));
+                    break;
+                }
+            }
+        }
+
+        Poll::Ready(Some(Ok(pin.rd.split())))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> Sink<&[u8]> for TimeoutFramed<T> {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Frames are sent one at a time, their gap is timed from the last one.
+        self.get_mut().poll_write_frames(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: &[u8]) -> Result<(), Self::Error> {
+        if !item.is_empty() {
+            self.get_mut().wr.push_back(Bytes::copy_from_slice(item));
+        }
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let pin = self.get_mut();
+
+        ready!(pin.poll_write_frames(cx))?;
+        Pin::new(&mut pin.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let pin = self.get_mut();
+
+        ready!(pin.poll_write_frames(cx))?;
+        Pin::new(&mut pin.inner).poll_shutdown(cx)
+    }
+}
+
+impl<T> ShutdownLayered for TimeoutFramed<T>
+where
+    T: AsyncWrite + Unpin + ShutdownLayered,
+{
+    /// Writes the frames still buffered.
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(futures::future::poll_fn(move |cx| {
+            self.poll_write_frames(cx)
+        }))
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        Some(&mut self.inner)
+    }
+}
@@ -0,0 +1,210 @@
+//! Request/response transactions over a framed port
+//!
+//! Most serial devices answer requests one at a time: the driver sends a frame,
+//! waits for the matching response, and retries when none comes.  A [`Client`]
+//! does this over any [`Sink`] and [`Stream`] of frames, such as a
+//! [`SerialFramed`](crate::SerialFramed), and lets any number of tasks share the
+//! port: requests wait in line, in the order they were made.
+//!
+//! ```no_run
+//! # async fn example(port: tokio_serial::SerialStream) -> Result<(), Box<dyn std::error::Error>> {
+//! use std::time::Duration;
+//! use tokio_serial::codec::transaction::Client;
+//! use tokio_serial::codec::LinesCodec;
+//! use tokio_serial::retry::RetryPolicy;
+//!
+//! // Responses repeat the command they answer: `TEMP?` gets `TEMP 21.5`.
+//! let mut client = Client::with_correlation(port.lines(), |request: &String, response: &String| {
+//!     response.starts_with(request.trim_end_matches('?'))
+//! });
+//! client.set_timeout(Duration::from_millis(200));
+//! client.set_policy(RetryPolicy::fixed(Duration::from_millis(50)).max_attempts(3));
+//!
+//! let other = client.clone();
+//! tokio::spawn(async move { other.request("HUMIDITY?".to_string()).await });
+//! let temperature = client.request("TEMP?".to_string()).await?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::retry::RetryPolicy;
+
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{error, fmt};
+use tokio::sync::{Mutex, MutexGuard};
+
+/// Errors produced by a [`Client`]
+#[derive(Debug)]
+pub enum TransactionError<E> {
+    /// The transport failed to send the request or to receive a response
+    Transport(E),
+    /// No matching response came in time, after all the retries
+    Timeout,
+    /// The transport ended while waiting for a response
+    Closed,
+}
+
+impl<E: fmt::Display> fmt::Display for TransactionError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionError::Transport(err) => err.fmt(f),
+            TransactionError::Timeout => f.write_str("timed out waiting for a response"),
+            TransactionError::Closed => f.write_str("the port was closed"),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> error::Error for TransactionError<E> {}
+
+/// Tells whether a frame is the response to a request, see
+/// [`Client::with_correlation`]
+pub type Correlation<Req, Resp> = Arc<dyn Fn(&Req, &Resp) -> bool + Send + Sync>;
+
+/// Sends requests over a transport and waits for their responses, one at a time
+///
+/// The transport sends `Req` frames and receives `Resp` frames, its sink and stream
+/// failing with the same error type.  Cloning a client gives another handle to the
+/// same transport, the settings of each handle are its own.
+///
+/// Frames which do not answer the pending request, e.g. late responses to a request
+/// which timed out, are dropped.  A request whose future is dropped before the
+/// response came leaves that response to be dropped by the next one, unless the
+/// client was created with [`new`](Client::new), which accepts any frame.
+///
+/// Only timeouts are retried, errors from the transport are returned right away.
+pub struct Client<T, Req, Resp> {
+    transport: Arc<Mutex<T>>,
+    correlation: Correlation<Req, Resp>,
+    timeout: Duration,
+    policy: RetryPolicy,
+}
+
+impl<T, Req, Resp> Client<T, Req, Resp> {
+    /// A client taking the first frame received after a request as its response.
+    pub fn new(transport: T) -> Self {
+        Self::with_correlation(transport, |_: &Req, _: &Resp| true)
+    }
+
+    /// A client taking the first frame for which `correlation` returns `true` as the
+    /// response to a request.
+    pub fn with_correlation<F>(transport: T, correlation: F) -> Self
+    where
+        F: Fn(&Req, &Resp) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            transport: Arc::new(Mutex::new(transport)),
+            correlation: Arc::new(correlation),
+            timeout: Duration::from_secs(1),
+            policy: RetryPolicy::never(),
+        }
+    }
+
+    /// Set how long each attempt waits for the response, 1 second by default.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns how long each attempt waits for the response.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Set the policy resending requests which timed out, by default they are not.
+    pub fn set_policy(&mut self, policy: RetryPolicy) {
+        self.policy = policy;
+    }
+
+    /// Wait for the requests made before to complete, and take the transport.
+    ///
+    /// Requests wait while the guard is held, for exchanges of several frames or to
+    /// read frames the device sends by itself.
+    pub async fn lock(&self) -> MutexGuard<'_, T> {
+        self.transport.lock().await
+    }
+}
+
+impl<T, Req, Resp, E> Client<T, Req, Resp>
+where
+    T: Sink<Req, Error = E> + Stream<Item = Result<Resp, E>> + Unpin,
+    Req: Clone,
+{
+    /// Send `request` and wait for the response matching the correlation of the
+    /// client.
+    pub async fn request(&self, request: Req) -> Result<Resp, TransactionError<E>> {
+        let correlation = self.correlation.clone();
+        self.transact(request, move |request, response| {
+            correlation(request, response)
+        })
+        .await
+    }
+
+    /// Send `request` and wait for a response for which `matches` returns `true`.
+    pub async fn request_matching<F>(
+        &self,
+        request: Req,
+        mut matches: F,
+    ) -> Result<Resp, TransactionError<E>>
+    where
+        F: FnMut(&Resp) -> bool,
+    {
+        self.transact(request, move |_, response| matches(response))
+            .await
+    }
+
+    async fn transact<F>(&self, request: Req, mut matches: F) -> Result<Resp, TransactionError<E>>
+    where
+        F: FnMut(&Req, &Resp) -> bool,
+    {
+        let timeout = self.timeout;
+        let mut transport = self.transport.lock().await;
+        let mut retry = 0;
+        loop {
+            transport
+                .send(request.clone())
+                .await
+                .map_err(TransactionError::Transport)?;
+            let response = async {
+                loop {
+                    match transport.next().await {
+                        Some(Ok(response)) if matches(&request, &response) => return Ok(response),
+                        Some(Ok(_)) => log::debug!("dropping a frame not matching the request"),
+                        Some(Err(err)) => return Err(TransactionError::Transport(err)),
+                        None => return Err(TransactionError::Closed),
+                    }
+                }
+            };
+            if let Ok(response) = tokio::time::timeout(timeout, response).await {
+                return response;
+            }
+            retry += 1;
+            match self.policy.delay(retry) {
+                Some(delay) => {
+                    log::debug!("no response, retrying (retry {})", retry);
+                    tokio::time::sleep(delay).await;
+                }
+                None => return Err(TransactionError::Timeout),
+            }
+        }
+    }
+}
+
+impl<T, Req, Resp> Clone for Client<T, Req, Resp> {
+    fn clone(&self) -> Self {
+        Self {
+            transport: self.transport.clone(),
+            correlation: self.correlation.clone(),
+            timeout: self.timeout,
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+impl<T, Req, Resp> fmt::Debug for Client<T, Req, Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("timeout", &self.timeout)
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,478 @@
+//! Digi XBee API frames
+use super::FrameError;
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::{error, fmt};
+
+/// Start of API frames
+pub const START: u8 = 0x7e;
+/// Escape byte of API mode 2
+pub const ESCAPE: u8 = 0x7d;
+
+/// Frame type of [`AtCommand`]
+pub const AT_COMMAND: u8 = 0x08;
+/// Frame type of [`TransmitRequest`]
+pub const TRANSMIT_REQUEST: u8 = 0x10;
+/// Frame type of [`AtCommandResponse`]
+pub const AT_COMMAND_RESPONSE: u8 = 0x88;
+/// Frame type of [`TransmitStatus`]
+pub const TRANSMIT_STATUS: u8 = 0x8b;
+/// Frame type of [`ReceivePacket`]
+pub const RECEIVE_PACKET: u8 = 0x90;
+
+/// The 64 bit address of broadcasts
+pub const BROADCAST: u64 = 0xffff;
+/// The 16 bit address to use when it is unknown
+pub const UNKNOWN_16: u16 = 0xfffe;
+
+/// Errors of [`XbeeCodec`] frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XbeeError {
+    /// The checksum does not match the frame, it is skipped
+    Checksum,
+    /// The frame is longer than the maximum frame length, it is skipped
+    TooLong(usize),
+    /// The frame is too short for its frame type
+    Malformed(u8),
+}
+
+impl fmt::Display for XbeeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XbeeError::Checksum => f.write_str("XBee frame checksum mismatch"),
+            XbeeError::TooLong(len) => write!(f, "XBee frame of {} bytes is too long", len),
+            XbeeError::Malformed(kind) => write!(f, "malformed XBee frame of type {:#04x}", kind),
+        }
+    }
+}
+
+impl error::Error for XbeeError {}
+
+/// An AT command for the local module
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AtCommand {
+    /// Matches the response to the command, 0 for no response
+    pub frame_id: u8,
+    /// The two letters of the command, such as `*b"NI"`
+    pub command: [u8; 2],
+    /// The value to set, empty to query it
+    pub parameter: Bytes,
+}
+
+/// The response of the local module to an [`AtCommand`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AtCommandResponse {
+    /// The frame id of the command
+    pub frame_id: u8,
+    /// The two letters of the command
+    pub command: [u8; 2],
+    /// 0 if the command succeeded
+    pub status: u8,
+    /// The value queried
+    pub data: Bytes,
+}
+
+/// Data to send to another module
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TransmitRequest {
+    /// Matches the [`TransmitStatus`] to the request, 0 for no status
+    pub frame_id: u8,
+    /// The 64 bit address of the destination, [`BROADCAST`] for all modules
+    pub destination: u64,
+    /// The 16 bit address of the destination, [`UNKNOWN_16`] if unknown
+    pub destination_16: u16,
+    /// The number of hops of broadcasts, 0 for the maximum
+    pub broadcast_radius: u8,
+    /// Transmit options, 0 for the defaults of the module
+    pub options: u8,
+    /// The data to send
+    pub data: Bytes,
+}
+
+impl TransmitRequest {
+    /// A request sending `data` to the module with the 64 bit address
+    /// `destination`.
+    pub fn new(frame_id: u8, destination: u64, data: impl Into<Bytes>) -> Self {
+        Self {
+            frame_id,
+            destination,
+            destination_16: UNKNOWN_16,
+            broadcast_radius: 0,
+            options: 0,
+            data: data.into(),
+        }
+    }
+}
+
+/// Whether a [`TransmitRequest`] was delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransmitStatus {
+    /// The frame id of the request
+    pub frame_id: u8,
+    /// The 16 bit address the data was sent to
+    pub destination_16: u16,
+    /// The number of retransmissions
+    pub retries: u8,
+    /// 0 if the data was delivered
+    pub delivery_status: u8,
+    /// How the route to the destination was discovered
+    pub discovery_status: u8,
+}
+
+/// Data received from another module
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReceivePacket {
+    /// The 64 bit address of the sender
+    pub source: u64,
+    /// The 16 bit address of the sender
+    pub source_16: u16,
+    /// Receive options, such as whether it was a broadcast
+    pub options: u8,
+    /// The data received
+    pub data: Bytes,
+}
+
+/// An XBee API frame
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum XbeeFrame {
+    /// An AT command for the local module
+    AtCommand(AtCommand),
+    /// The response to an AT command
+    AtCommandResponse(AtCommandResponse),
+    /// Data to send to another module
+    TransmitRequest(TransmitRequest),
+    /// Whether a transmit request was delivered
+    TransmitStatus(TransmitStatus),
+    /// Data received from another module
+    ReceivePacket(ReceivePacket),
+    /// Any other frame, with the data following its frame type
+    Other {
+        /// The frame type
+        frame_type: u8,
+        /// The frame data after the frame type
+        data: Bytes,
+    },
+}
+
+impl XbeeFrame {
+    /// Returns the frame type of the frame.
+    pub fn frame_type(&self) -> u8 {
+        match self {
+            XbeeFrame::AtCommand(_) => AT_COMMAND,
+            XbeeFrame::AtCommandResponse(_) => AT_COMMAND_RESPONSE,
+            XbeeFrame::TransmitRequest(_) => TRANSMIT_REQUEST,
+            XbeeFrame::TransmitStatus(_) => TRANSMIT_STATUS,
+            XbeeFrame::ReceivePacket(_) => RECEIVE_PACKET,
+            XbeeFrame::Other { frame_type, .. } => *frame_type,
+        }
+    }
+
+    // The frame data, from the frame type to the checksum.
+    fn encode(&self, dst: &mut Vec<u8>) {
+        dst.push(self.frame_type());
+        match self {
+            XbeeFrame::AtCommand(at) => {
+                dst.push(at.frame_id);
+                dst.extend_from_slice(&at.command);
+                dst.extend_from_slice(&at.parameter);
+            }
+            XbeeFrame::AtCommandResponse(at) => {
+                dst.push(at.frame_id);
+                dst.extend_from_slice(&at.command);
+                dst.push(at.status);
+                dst.extend_from_slice(&at.data);
+            }
+            XbeeFrame::TransmitRequest(tx) => {
+                dst.push(tx.frame_id);
+                dst.extend_from_slice(&tx.destination.to_be_bytes());
+                dst.extend_from_slice(&tx.destination_16.to_be_bytes());
+                dst.push(tx.broadcast_radius);
+                dst.push(tx.options);
+                dst.extend_from_slice(&tx.data);
+            }
+            XbeeFrame::TransmitStatus(status) => {
+                dst.push(status.frame_id);
+                dst.extend_from_slice(&status.destination_16.to_be_bytes());
+                dst.push(status.retries);
+                dst.push(status.delivery_status);
+                dst.push(status.discovery_status);
+            }
+            XbeeFrame::ReceivePacket(rx) => {
+                dst.extend_from_slice(&rx.source.to_be_bytes());
+                dst.extend_from_slice(&rx.source_16.to_be_bytes());
+                dst.push(rx.options);
+                dst.extend_from_slice(&rx.data);
+            }
+            XbeeFrame::Other { data, .. } => dst.extend_from_slice(data),
+        }
+    }
+
+    fn decode(frame_type: u8, mut data: Bytes) -> Result<Self, XbeeError> {
+        let min = match frame_type {
+            AT_COMMAND => 3,
+            AT_COMMAND_RESPONSE => 4,
+            TRANSMIT_REQUEST => 13,
+            TRANSMIT_STATUS => 6,
+            RECEIVE_PACKET => 11,
+            _ => 0,
+        };
+        if data.len() < min {
+            return Err(XbeeError::Malformed(frame_type));
+        }
+        Ok(match frame_type {
+            AT_COMMAND => XbeeFrame::AtCommand(AtCommand {
+                frame_id: data.get_u8(),
+                command: [data.get_u8(), data.get_u8()],
+                parameter: data,
+            }),
+            AT_COMMAND_RESPONSE => XbeeFrame::AtCommandResponse(AtCommandResponse {
+                frame_id: data.get_u8(),
+                command: [data.get_u8(), data.get_u8()],
+                status: data.get_u8(),
+                data,
+            }),
+            TRANSMIT_REQUEST => XbeeFrame::TransmitRequest(TransmitRequest {
+                frame_id: data.get_u8(),
+                destination: data.get_u64(),
+                destination_16: data.get_u16(),
+                broadcast_radius: data.get_u8(),
+                options: data.get_u8(),
+                data,
+            }),
+            TRANSMIT_STATUS => XbeeFrame::TransmitStatus(TransmitStatus {
+                frame_id: data.get_u8(),
+                destination_16: data.get_u16(),
+                retries: data.get_u8(),
+                delivery_status: data.get_u8(),
+                discovery_status: data.get_u8(),
+            }),
+            RECEIVE_PACKET => XbeeFrame::ReceivePacket(ReceivePacket {
+                source: data.get_u64(),
+                source_16: data.get_u16(),
+                options: data.get_u8(),
+                data,
+            }),
+            _ => XbeeFrame::Other { frame_type, data },
+        })
+    }
+}
+
+macro_rules! from_frame {
+    ($($variant:ident),*) => {
+        $(
+            impl From<$variant> for XbeeFrame {
+                fn from(frame: $variant) -> Self {
+                    XbeeFrame::$variant(frame)
+                }
+            }
+        )*
+    };
+}
+
+from_frame!(
+    AtCommand,
+    AtCommandResponse,
+    TransmitRequest,
+    TransmitStatus,
+    ReceivePacket
+);
+
+fn needs_escape(byte: u8) -> bool {
+    matches!(byte, START | ESCAPE | 0x11 | 0x13)
+}
+
+/// A codec for XBee API frames
+///
+/// Frames are a start byte, a big endian length, the frame data and a checksum.
+/// In API mode 2, set with [`escaped`](XbeeCodec::escaped), the bytes following
+/// the start byte are escaped so that `0x7E` only starts frames and flow control
+/// characters never appear; in API mode 1, the default, they are sent as is.
+///
+/// Decoded frames are typed [`XbeeFrame`]s.  Frames failing their checksum are
+/// reported as [`XbeeError::Checksum`] and the search for the next frame restarts
+/// after their start byte.  In API mode 2 a start byte also ends a truncated
+/// frame.  Frames longer than
+/// [`max_frame_length`](XbeeCodec::max_frame_length), 1 KiB by default, are
+/// reported as [`XbeeError::TooLong`].
+///
+/// ```
+/// use tokio_serial::codec::xbee::{TransmitRequest, XbeeCodec, BROADCAST};
+/// use tokio_serial::codec::xbee::XbeeFrame;
+///
+/// let codec = XbeeCodec::new().escaped(true);
+/// let frame = XbeeFrame::from(TransmitRequest::new(1, BROADCAST, &b"hello"[..]));
+/// ```
+#[derive(Debug, Clone)]
+pub struct XbeeCodec {
+    escaped: bool,
+    max_frame_length: usize,
+}
+
+impl Default for XbeeCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl XbeeCodec {
+    /// A codec for API mode 1, without escapes.
+    pub fn new() -> Self {
+        Self {
+            escaped: false,
+            max_frame_length: 1024,
+        }
+    }
+
+    /// Set whether frames are escaped, as in API mode 2.
+    pub fn escaped(mut self, escaped: bool) -> Self {
+        self.escaped = escaped;
+        self
+    }
+
+    /// Set the maximum length of the frame data.
+    pub fn max_frame_length(mut self, len: usize) -> Self {
+        self.max_frame_length = len;
+        self
+    }
+}
+
+// Where reading a frame stopped.
+enum Unescaped {
+    Done(Vec<u8>, usize),
+    Incomplete,
+    // A start byte at this offset interrupted the frame.
+    Interrupted(usize),
+}
+
+impl XbeeCodec {
+    /// Read `len` bytes following the start byte at the front of `src`.
+    fn unescape(&self, src: &[u8], len: usize) -> Unescaped {
+        let mut out = Vec::with_capacity(len);
+        let mut at = 1;
+        while out.len() < len {
+            let byte = match src.get(at) {
+                Some(&byte) => byte,
+                None => return Unescaped::Incomplete,
+            };
+            at += 1;
+            if !self.escaped {
+                out.push(byte);
+                continue;
+            }
+            match byte {
+                START => return Unescaped::Interrupted(at - 1),
+                ESCAPE => match src.get(at) {
+                    Some(&START) => return Unescaped::Interrupted(at),
+                    Some(&byte) => {
+                        at += 1;
+                        out.push(byte ^ 0x20);
+                    }
+                    None => return Unescaped::Incomplete,
+                },
+                byte => out.push(byte),
+            }
+        }
+        Unescaped::Done(out, at)
+    }
+}
+
+impl Decoder for XbeeCodec {
+    type Item = XbeeFrame;
+    type Error = FrameError<XbeeError>;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<XbeeFrame>, Self::Error> {
+        loop {
+            match src.iter().position(|&b| b == START) {
+                Some(start) => {
+                    if start > 0 {
+                        log::debug!("skipping {} bytes outside XBee frames", start);
+                    }
+                    src.advance(start);
+                }
+                None => {
+                    src.clear();
+                    return Ok(None);
+                }
+            }
+
+            let len = match self.unescape(src, 2) {
+                Unescaped::Done(field, _) => usize::from(u16::from_be_bytes([field[0], field[1]])),
+                Unescaped::Incomplete => return Ok(None),
+                Unescaped::Interrupted(at) => {
+                    src.advance(at);
+                    continue;
+                }
+            };
+            if len > self.max_frame_length {
+                src.advance(1);
+                return Err(FrameError::Frame(XbeeError::TooLong(len)));
+            }
+            // The length, the frame data and the checksum
+            let (frame, used) = match self.unescape(src, 2 + len + 1) {
+                Unescaped::Done(frame, used) => (frame, used),
+                Unescaped::Incomplete => {
+                    src.reserve(3 + len + 1);
+                    return Ok(None);
+                }
+                Unescaped::Interrupted(at) => {
+                    log::debug!("dropping truncated XBee frame");
+                    src.advance(at);
+                    continue;
+                }
+            };
+            let data = &frame[2..];
+            if data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != 0xff {
+                src.advance(1);
+                return Err(FrameError::Frame(XbeeError::Checksum));
+            }
+            src.advance(used);
+            if len == 0 {
+                return Err(FrameError::Frame(XbeeError::Malformed(0)));
+            }
+            let data = Bytes::copy_from_slice(&data[1..len]);
+            return XbeeFrame::decode(frame[2], data)
+                .map(Some)
+                .map_err(FrameError::Frame);
+        }
+    }
+}
+
+impl Encoder<&XbeeFrame> for XbeeCodec {
+    type Error = FrameError<XbeeError>;
+
+    fn encode(&mut self, item: &XbeeFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let mut data = Vec::new();
+        item.encode(&mut data);
+        if data.len() > self.max_frame_length || data.len() > usize::from(u16::MAX) {
+            return Err(FrameError::Frame(XbeeError::TooLong(data.len())));
+        }
+        let checksum = 0xff - data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+
+        let mut frame = Vec::with_capacity(data.len() + 3);
+        frame.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        frame.extend_from_slice(&data);
+        frame.push(checksum);
+
+        dst.reserve(1 + frame.len());
+        dst.put_u8(START);
+        for byte in frame {
+            if self.escaped && needs_escape(byte) {
+                dst.put_u8(ESCAPE);
+                dst.put_u8(byte ^ 0x20);
+            } else {
+                dst.put_u8(byte);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Encoder<XbeeFrame> for XbeeCodec {
+    type Error = FrameError<XbeeError>;
+
+    fn encode(&mut self, item: XbeeFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&item, dst)
+    }
+}
@@ -0,0 +1,478 @@
+//! Port configurations for command lines and configuration files
+use crate::{DataBits, FlowControl, Parity, SerialPortBuilder, SerialSettings, StopBits};
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A port path and its line settings
+///
+/// Command line tools can take a whole configuration as a single URL, parsed with
+/// [`FromStr`] and printed back with [`Display`](fmt::Display).  The format is
+/// `serial://<path>?<key>=<value>&...`, e.g.
+/// `serial:///dev/ttyUSB0?baud=115200&parity=even&flow=hardware` or
+/// `serial://COM3?mode=8N1`.  The keys are:
+///
+/// * `baud`: the baud rate, 9600 if not given.
+/// * `data_bits`: 5 to 8.
+/// * `parity`: `none`, `odd` or `even`.
+/// * `stop_bits`: 1 or 2.
+/// * `flow`: `none`, `software` or `hardware`.
+/// * `mode`: data bits, parity and stop bits at once, e.g. `8N1` or `7E2`.
+///
+/// Settings which are not given take the defaults of `tokio_serial::new`.  The path
+/// and the values are percent-decoded, so `serial:///dev/serial/by-id/usb-My%20Adapter`
+/// opens `/dev/serial/by-id/usb-My Adapter`.
+/// [`SerialPortBuilderExt::from_url`](crate::SerialPortBuilderExt::from_url) parses
+/// a URL straight into a builder.
+///
+/// ```
+/// use tokio_serial::PortConfig;
+///
+/// # fn example() -> tokio_serial::Result<()> {
+/// let config: PortConfig = "serial:///dev/ttyUSB0?baud=115200&parity=even".parse()?;
+/// assert_eq!(config.path, "/dev/ttyUSB0");
+/// assert_eq!(config.settings.baud_rate, 115_200);
+/// let builder = config.builder();
+/// # Ok(())
+/// # }
+/// ```
+///
+/// With the `serde` feature, configurations can also be loaded from configuration
+/// files.  The settings are flattened next to the path, with the same values and
+/// defaults as in URLs, only `baud_rate` is required:
+///
+/// ```toml
+/// path = "/dev/ttyUSB0"
+/// baud_rate = 115200
+/// data_bits = 8
+/// parity = "even"
+/// stop_bits = 1
+/// flow_control = "none"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortConfig {
+    /// The path of the port, or its name on Windows
+    pub path: String,
+    /// The line settings
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub settings: SerialSettings,
+}
+
+/// The baud rate of URLs which do not give one
+const DEFAULT_BAUD_RATE: u32 = 9600;
+
+impl PortConfig {
+    /// Create a builder opening the port with these settings.
+    pub fn builder(&self) -> SerialPortBuilder {
+        self.settings.builder(self.path.as_str())
+    }
+
+    /// Read a configuration from the environment variables starting with `prefix`
+    ///
+    /// With the prefix `MYAPP`, the variables are:
+    ///
+    /// * `MYAPP_SERIAL_PORT`: the path of the port, required.
+    /// * `MYAPP_SERIAL_BAUD`: the baud rate, 9600 if not set.
+    /// * `MYAPP_SERIAL_DATA_BITS`, `MYAPP_SERIAL_PARITY`, `MYAPP_SERIAL_STOP_BITS`,
+    ///   `MYAPP_SERIAL_FLOW` and `MYAPP_SERIAL_MODE`, with the values of the
+    ///   corresponding URL keys.
+    ///
+    /// [`SerialPortBuilderExt::from_env`](crate::SerialPortBuilderExt::from_env)
+    /// makes a builder of them directly.
+    ///
+    /// ## Errors
+    ///
+    /// * `InvalidInput` naming the variable if `_PORT` is not set or a value is
+    ///   invalid.
+    pub fn from_env(prefix: &str) -> crate::Result<Self> {
+        let var = |name: &str| {
+            let name = format!("{}_SERIAL_{}", prefix, name);
+            match std::env::var(&name) {
+                Ok(value) => Ok(Some((name, value))),
+                Err(std::env::VarError::NotPresent) => Ok(None),
+                Err(std::env::VarError::NotUnicode(_)) => {
+                    Err(invalid(format!("{} is not valid unicode", name)))
+                }
+            }
+        };
+        let bad_value =
+            |(name, value): &(String, String)| invalid(format!("invalid {} `{}`", name, value));
+
+        let path = var("PORT")?
+            .ok_or_else(|| invalid(format!("{}_SERIAL_PORT is not set", prefix)))?
+            .1;
+        let mut settings = SerialSettings::new(DEFAULT_BAUD_RATE);
+        if let Some(var) = var("BAUD")? {
+            settings.baud_rate = var.1.parse().map_err(|_| bad_value(&var))?;
+        }
+        if let Some(var) = var("MODE")? {
+            let mode = parse_mode(&var.1).ok_or_else(|| bad_value(&var))?;
+            settings.data_bits = mode.0;
+            settings.parity = mode.1;
+            settings.stop_bits = mode.2;
+        }
+        if let Some(var) = var("DATA_BITS")? {
+            settings.data_bits = data_bits(&var.1).ok_or_else(|| bad_value(&var))?;
+        }
+        if let Some(var) = var("PARITY")? {
+            settings.parity = parity(&var.1).ok_or_else(|| bad_value(&var))?;
+        }
+        if let Some(var) = var("STOP_BITS")? {
+            settings.stop_bits = stop_bits(&var.1).ok_or_else(|| bad_value(&var))?;
+        }
+        if let Some(var) = var("FLOW")? {
+            settings.flow_control = flow_control(&var.1).ok_or_else(|| bad_value(&var))?;
+        }
+        Ok(Self { path, settings })
+    }
+
+    /// Check whether the device supports these settings, without opening the port
+    ///
+    /// Returns the settings the device or its driver cannot do, see
+    /// [`validation`](crate::validation) for what can be checked on each platform.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io(NotFound)` if the port does not exist.
+    /// * an I/O error if the capabilities of the device cannot be read.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn validate(&self) -> crate::Result<crate::validation::ValidationReport> {
+        crate::validation::validate(self)
+    }
+}
+
+impl From<PortConfig> for SerialPortBuilder {
+    fn from(config: PortConfig) -> Self {
+        config.builder()
+    }
+}
+
+// Read the path and line settings back out of `builder`.
+//
+// `SerialPortBuilder` has no getters.  The enums are found by setting each value
+// and comparing with the builder, the path and baud rate can only be read from its
+// derived `Debug` output.  Whatever is read is checked by rebuilding the builder
+// from it, so a different `Debug` format fails instead of giving wrong settings.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn from_builder(builder: &SerialPortBuilder) -> crate::Result<PortConfig> {
+    fn probe<T: Copy>(
+        builder: &SerialPortBuilder,
+        values: &[T],
+        set: fn(SerialPortBuilder, T) -> SerialPortBuilder,
+    ) -> Option<T> {
+        values
+            .iter()
+            .copied()
+            .find(|&value| set(builder.clone(), value) == *builder)
+    }
+
+    let unreadable = || {
+        crate::Error::new(
+            crate::ErrorKind::Unknown,
+            "unable to read the settings of the builder",
+        )
+    };
+    let debug = format!("{:?}", builder);
+    let (path, rest) = debug
+        .strip_prefix("SerialPortBuilder { path: ")
+        .and_then(debug_string)
+        .ok_or_else(unreadable)?;
+    let baud_rate = rest
+        .strip_prefix(", baud_rate: ")
+        .and_then(|rest| rest.split(',').next())
+        .and_then(|baud_rate| baud_rate.parse().ok())
+        .ok_or_else(unreadable)?;
+    let settings = SerialSettings {
+        baud_rate,
+        data_bits: probe(
+            builder,
+            &[
+                DataBits::Five,
+                DataBits::Six,
+                DataBits::Seven,
+                DataBits::Eight,
+            ],
+            SerialPortBuilder::data_bits,
+        )
+        .ok_or_else(unreadable)?,
+        parity: probe(
+            builder,
+            &[Parity::None, Parity::Odd, Parity::Even],
+            SerialPortBuilder::parity,
+        )
+        .ok_or_else(unreadable)?,
+        stop_bits: probe(
+            builder,
+            &[StopBits::One, StopBits::Two],
+            SerialPortBuilder::stop_bits,
+        )
+        .ok_or_else(unreadable)?,
+        flow_control: probe(
+            builder,
+            &[
+                FlowControl::None,
+                FlowControl::Software,
+                FlowControl::Hardware,
+            ],
+            SerialPortBuilder::flow_control,
+        )
+        .ok_or_else(unreadable)?,
+    };
+
+    if settings.apply_to(builder.clone().path(path.as_str())) != *builder {
+        return Err(unreadable());
+    }
+    Ok(PortConfig { path, settings })
+}
+
+// Decode a string as written by `Debug`, returning it and what follows it.
+#[cfg(not(target_arch = "wasm32"))]
+fn debug_string(text: &str) -> Option<(String, &str)> {
+    let mut chars = text.strip_prefix('"')?.char_indices();
+    let mut decoded = String::new();
+    loop {
+        let (i, c) = chars.next()?;
+        match c {
+            '"' => return Some((decoded, &text[i + 2..])),
+            '\\' => decoded.push(match chars.next()?.1 {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                '0' => '\0',
+                'u' => {
+                    let start = chars.next().filter(|&(_, c)| c == '{')?.0 + 2;
+                    let end = chars.by_ref().find(|&(_, c)| c == '}')?.0 + 1;
+                    char::from_u32(u32::from_str_radix(&text[start..end], 16).ok()?)?
+                }
+                c => c,
+            }),
+            c => decoded.push(c),
+        }
+    }
+}
+
+fn invalid(message: String) -> crate::Error {
+    crate::Error::new(crate::ErrorKind::InvalidInput, message)
+}
+
+pub(crate) fn data_bits(value: &str) -> Option<DataBits> {
+    Some(match value {
+        "5" => DataBits::Five,
+        "6" => DataBits::Six,
+        "7" => DataBits::Seven,
+        "8" => DataBits::Eight,
+        _ => return None,
+    })
+}
+
+pub(crate) fn parity(value: &str) -> Option<Parity> {
+    Some(match value.to_ascii_lowercase().as_str() {
+        "none" | "n" => Parity::None,
+        "odd" | "o" => Parity::Odd,
+        "even" | "e" => Parity::Even,
+        _ => return None,
+    })
+}
+
+pub(crate) fn stop_bits(value: &str) -> Option<StopBits> {
+    Some(match value {
+        "1" => StopBits::One,
+        "2" => StopBits::Two,
+        _ => return None,
+    })
+}
+
+pub(crate) fn flow_control(value: &str) -> Option<FlowControl> {
+    Some(match value.to_ascii_lowercase().as_str() {
+        "none" => FlowControl::None,
+        "software" | "xonxoff" => FlowControl::Software,
+        "hardware" | "rtscts" => FlowControl::Hardware,
+        _ => return None,
+    })
+}
+
+// A mode such as `8N1`.
+fn parse_mode(value: &str) -> Option<(DataBits, Parity, StopBits)> {
+    if value.len() != 3 || !value.is_ascii() {
+        return None;
+    }
+    Some((
+        data_bits(&value[0..1])?,
+        parity(&value[1..2])?,
+        stop_bits(&value[2..3])?,
+    ))
+}
+
+impl FromStr for PortConfig {
+    type Err = crate::Error;
+
+    fn from_str(url: &str) -> crate::Result<Self> {
+        let rest = url
+            .strip_prefix("serial://")
+            .ok_or_else(|| invalid(format!("`{}` is not a serial:// URL", url)))?;
+        let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+        if path.is_empty() {
+            return Err(invalid(format!("`{}` has no port path", url)));
+        }
+        let path = percent_decode(path)?;
+
+        let mut settings = SerialSettings::new(DEFAULT_BAUD_RATE);
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("`{}` has no value", pair)))?;
+            let value = percent_decode(value)?;
+            let value = value.as_str();
+            let bad_value = || invalid(format!("invalid {} `{}`", key, value));
+            match key {
+                "baud" => settings.baud_rate = value.parse().map_err(|_| bad_value())?,
+                "data_bits" => settings.data_bits = data_bits(value).ok_or_else(bad_value)?,
+                "parity" => settings.parity = parity(value).ok_or_else(bad_value)?,
+                "stop_bits" => settings.stop_bits = stop_bits(value).ok_or_else(bad_value)?,
+                "flow" => settings.flow_control = flow_control(value).ok_or_else(bad_value)?,
+                "mode" => {
+                    let mode = parse_mode(value).ok_or_else(bad_value)?;
+                    settings.data_bits = mode.0;
+                    settings.parity = mode.1;
+                    settings.stop_bits = mode.2;
+                }
+                _ => return Err(invalid(format!("unknown setting `{}`", key))),
+            }
+        }
+
+        Ok(Self { path, settings })
+    }
+}
+
+// Decode the `%XX` escapes of a part of a URL.
+fn percent_decode(part: &str) -> crate::Result<String> {
+    let bad_escape = || invalid(format!("invalid percent-encoding in `{}`", part));
+    let mut bytes = Vec::with_capacity(part.len());
+    let mut rest = part.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail.get(..2).ok_or_else(bad_escape)?;
+            let hex = std::str::from_utf8(hex).map_err(|_| bad_escape())?;
+            bytes.push(u8::from_str_radix(hex, 16).map_err(|_| bad_escape())?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| bad_escape())
+}
+
+// Escape what would not parse back as a path: its own escapes, the start of the
+// query, whitespace and control characters.
+fn percent_encode(part: &str) -> String {
+    let mut encoded = String::with_capacity(part.len());
+    for &byte in part.as_bytes() {
+        match byte {
+            b'%' | b'?' | b'#' => encoded.push_str(&format!("%{:02X}", byte)),
+            _ if byte.is_ascii_graphic() => encoded.push(char::from(byte)),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+pub(crate) fn data_bits_number(data_bits: DataBits) -> u8 {
+    match data_bits {
+        DataBits::Five => 5,
+        DataBits::Six => 6,
+        DataBits::Seven => 7,
+        DataBits::Eight => 8,
+    }
+}
+
+pub(crate) fn parity_name(parity: Parity) -> &'static str {
+    match parity {
+        Parity::None => "none",
+        Parity::Odd => "odd",
+        Parity::Even => "even",
+    }
+}
+
+pub(crate) fn stop_bits_number(stop_bits: StopBits) -> u8 {
+    match stop_bits {
+        StopBits::One => 1,
+        StopBits::Two => 2,
+    }
+}
+
+pub(crate) fn flow_control_name(flow_control: FlowControl) -> &'static str {
+    match flow_control {
+        FlowControl::None => "none",
+        FlowControl::Software => "software",
+        FlowControl::Hardware => "hardware",
+    }
+}
+
+impl fmt::Display for PortConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = &self.settings;
+        write!(
+            f,
+            "serial://{}?baud={}&mode={}{}{}&flow={}",
+            percent_encode(&self.path),
+            s.baud_rate,
+            data_bits_number(s.data_bits),
+            parity_name(s.parity)[..1].to_ascii_uppercase(),
+            stop_bits_number(s.stop_bits),
+            flow_control_name(s.flow_control)
+        )
+    }
+}
+
+/// `serde(with)` modules for the line settings, which are foreign types
+#[cfg(feature = "serde")]
+pub(crate) mod serde_fields {
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    macro_rules! field {
+        ($name:ident: $ty:ty, $repr:ty, $to:expr, $from:expr, $default:expr) => {
+            pub(crate) mod $name {
+                use super::*;
+
+                pub(crate) fn serialize<S: Serializer>(
+                    value: &$ty,
+                    serializer: S,
+                ) -> Result<S::Ok, S::Error> {
+                    serde::Serialize::serialize(&$to(*value), serializer)
+                }
+
+                pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+                    deserializer: D,
+                ) -> Result<$ty, D::Error> {
+                    let value = <$repr>::deserialize(deserializer)?;
+                    $from(&value.to_string()).ok_or_else(|| {
+                        D::Error::custom(format!(
+                            concat!("invalid ", stringify!($name), " `{}`"),
+                            value
+                        ))
+                    })
+                }
+
+                pub(crate) fn default() -> $ty {
+                    $default
+                }
+            }
+        };
+    }
+
+    use super::*;
+
+    field!(data_bits: DataBits, u8, data_bits_number, data_bits, DataBits::Eight);
+    field!(parity: Parity, String, parity_name, parity, Parity::None);
+    field!(stop_bits: StopBits, u8, stop_bits_number, stop_bits, StopBits::One);
+    field!(
+        flow_control: FlowControl,
+        String,
+        flow_control_name,
+        flow_control,
+        FlowControl::None
+    );
+}
@@ -0,0 +1,387 @@
+//! Human-oriented consoles
+//!
+//! [`attach`] runs an interactive session on a port, as `picocom` or `minicom`
+//! do: what is typed is sent to the port, what the port receives is displayed.
+//! On Unix, [`ControlChar`] and [`SerialStream::set_canonical`] configure the
+//! line discipline for devices whose user is a human at a terminal.
+//!
+//! [`SerialStream::set_canonical`]: crate::SerialStream::set_canonical
+use futures::future::{self, Either};
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+#[cfg(unix)]
+use std::mem::MaybeUninit;
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+
+const BUFFER_SIZE: usize = 1024;
+
+/// A line ending
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Newline {
+    /// Leave line endings as they are
+    #[default]
+    Keep,
+    /// End lines with a carriage return
+    Cr,
+    /// End lines with a line feed
+    Lf,
+    /// End lines with a carriage return and a line feed
+    CrLf,
+}
+
+impl Newline {
+    fn bytes(self) -> &'static [u8] {
+        match self {
+            Newline::Keep => b"",
+            Newline::Cr => b"\r",
+            Newline::Lf => b"\n",
+            Newline::CrLf => b"\r\n",
+        }
+    }
+}
+
+// Replaces CR, LF and CR LF with a line ending, across chunks.
+#[derive(Debug)]
+struct Translator {
+    newline: Newline,
+    after_cr: bool,
+}
+
+impl Translator {
+    fn new(newline: Newline) -> Self {
+        Self {
+            newline,
+            after_cr: false,
+        }
+    }
+
+    fn translate(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        if self.newline == Newline::Keep {
+            out.extend_from_slice(data);
+            return;
+        }
+        for &byte in data {
+            match byte {
+                b'\n' if self.after_cr => {}
+                b'\r' | b'\n' => out.extend_from_slice(self.newline.bytes()),
+                byte => out.push(byte),
+            }
+            self.after_cr = byte == b'\r';
+        }
+    }
+}
+
+/// Why [`attach`] returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Exit {
+    /// The escape sequence to exit was typed
+    Escape,
+    /// The input reached its end
+    InputClosed,
+    /// The port reached its end
+    PortClosed,
+}
+
+/// The options of an interactive session, see [`attach`]
+///
+/// ```no_run
+/// # async fn example(mut port: tokio_serial::SerialStream) -> std::io::Result<()> {
+/// use tokio_serial::console::{Console, Newline};
+///
+/// // The device wants CR LF and sends bare LFs.
+/// let console = Console::new()
+///     .escape(Some(0x01))
+///     .local_echo(true)
+///     .input_newline(Newline::CrLf)
+///     .output_newline(Newline::CrLf);
+/// console
+///     .attach(&mut port, tokio::io::stdin(), tokio::io::stdout())
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Console {
+    escape: Option<u8>,
+    local_echo: bool,
+    input_newline: Newline,
+    output_newline: Newline,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Console {
+    /// A session with `~` as the escape character, without local echo nor line
+    /// ending translation.
+    pub fn new() -> Self {
+        Self {
+            escape: Some(b'~'),
+            local_echo: false,
+            input_newline: Newline::Keep,
+            output_newline: Newline::Keep,
+        }
+    }
+
+    /// Set the escape character, or disable escape sequences with `None`.
+    pub fn escape(mut self, escape: Option<u8>) -> Self {
+        self.escape = escape;
+        self
+    }
+
+    /// Set whether what is typed is displayed as well, for devices which do not
+    /// echo.
+    pub fn local_echo(mut self, enabled: bool) -> Self {
+        self.local_echo = enabled;
+        self
+    }
+
+    /// Set the line ending replacing the ones typed, before they are sent.
+    pub fn input_newline(mut self, newline: Newline) -> Self {
+        self.input_newline = newline;
+        self
+    }
+
+    /// Set the line ending replacing the ones received, before they are
+    /// displayed.
+    pub fn output_newline(mut self, newline: Newline) -> Self {
+        self.output_newline = newline;
+        self
+    }
+
+    /// Run an interactive session on `port`, see [`attach`].
+    pub async fn attach<S, R, W>(
+        &self,
+        mut port: S,
+        mut input: R,
+        mut output: W,
+    ) -> io::Result<Exit>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut local_echo = self.local_echo;
+        let mut to_port = Translator::new(self.input_newline);
+        let mut to_output = Translator::new(self.output_newline);
+        let mut echo = Translator::new(self.output_newline);
+        let (mut typed, mut received) = (vec![0u8; BUFFER_SIZE], vec![0u8; BUFFER_SIZE]);
+        let (mut sent, mut displayed) = (Vec::new(), Vec::new());
+        let mut line_start = true;
+        let mut escaped = false;
+        loop {
+            let event = {
+                let typed = input.read(&mut typed);
+                let received = port.read(&mut received);
+                futures::pin_mut!(typed, received);
+                match future::select(typed, received).await {
+                    Either::Left((typed, _)) => Either::Left(typed?),
+                    Either::Right((received, _)) => Either::Right(received?),
+                }
+            };
+            match event {
+                Either::Left(0) => return Ok(Exit::InputClosed),
+                Either::Right(0) => return Ok(Exit::PortClosed),
+                Either::Left(n) => {
+                    let mut keys = Vec::with_capacity(n);
+                    let mut exit = false;
+                    for &byte in &typed[..n] {
+                        let (mut key, mut len) = ([byte, 0], 1);
+                        if escaped {
+                            escaped = false;
+                            line_start = false;
+                            match byte {
+                                b'.' => {
+                                    exit = true;
+                                    break;
+                                }
+                                b'e' => {
+                                    local_echo = !local_echo;
+                                    len = 0;
+                                }
+                                byte if Some(byte) == self.escape => {}
+                                byte => {
+                                    key = [self.escape.unwrap_or(byte), byte];
+                                    len = 2;
+                                }
+                            }
+                        } else if line_start && Some(byte) == self.escape {
+                            escaped = true;
+                            continue;
+                        } else {
+                            line_start = byte == b'\r' || byte == b'\n';
+                        }
+                        keys.extend_from_slice(&key[..len]);
+                        if local_echo {
+                            echo.translate(&key[..len], &mut displayed);
+                        }
+                    }
+                    to_port.translate(&keys, &mut sent);
+                    port.write_all(&sent).await?;
+                    port.flush().await?;
+                    sent.clear();
+                    if exit {
+                        output.write_all(&displayed).await?;
+                        output.flush().await?;
+                        return Ok(Exit::Escape);
+                    }
+                }
+                Either::Right(n) => to_output.translate(&received[..n], &mut displayed),
+            }
+            if !displayed.is_empty() {
+                output.write_all(&displayed).await?;
+                output.flush().await?;
+                displayed.clear();
+            }
+        }
+    }
+}
+
+/// Run an interactive session on `port` with the default [`Console`] options
+///
+/// What is read from `input` is written to the port, what the port receives is
+/// written to `output`, until either reaches its end or the escape sequence to
+/// exit is typed.  Pass `&mut port` to use the port afterwards.
+///
+/// Escape sequences are the escape character, `~` by default, typed at the start
+/// of a line and followed by:
+/// - `.` to exit,
+/// - `e` to toggle the local echo,
+/// - the escape character to send it.
+///
+/// The escape character followed by anything else is sent as it is.  To read
+/// keys as they are typed, the terminal of the application must be in raw mode,
+/// which is left to the application.
+///
+/// ```no_run
+/// # async fn example() -> std::io::Result<()> {
+/// let port = tokio_serial::SerialStream::open(&tokio_serial::new("/dev/ttyUSB0", 115_200))?;
+/// tokio_serial::console::attach(port, tokio::io::stdin(), tokio::io::stdout()).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn attach<S, R, W>(port: S, input: R, output: W) -> io::Result<Exit>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    Console::new().attach(port, input, output).await
+}
+
+/// A special character of the terminal interface (an index of termios' `c_cc`)
+///
+/// Most of them only have an effect in [canonical mode](crate::SerialStream::set_canonical)
+/// or when the corresponding input processing is enabled.
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ControlChar {
+    /// End of file (`VEOF`), completes a read without a line terminator
+    Eof,
+    /// Additional end of line (`VEOL`)
+    Eol,
+    /// Second additional end of line (`VEOL2`)
+    Eol2,
+    /// Erase the previous character (`VERASE`)
+    Erase,
+    /// Erase the previous word (`VWERASE`)
+    WordErase,
+    /// Erase the line (`VKILL`)
+    Kill,
+    /// Reprint the line (`VREPRINT`)
+    Reprint,
+    /// Take the next character literally (`VLNEXT`)
+    LiteralNext,
+    /// Send `SIGINT` (`VINTR`)
+    Interrupt,
+    /// Send `SIGQUIT` (`VQUIT`)
+    Quit,
+    /// Send `SIGTSTP` (`VSUSP`)
+    Suspend,
+    /// Restart output with software flow control (`VSTART`)
+    Start,
+    /// Stop output with software flow control (`VSTOP`)
+    Stop,
+    /// Minimum number of bytes for a non-canonical read (`VMIN`), not a character
+    Min,
+    /// Timeout of a non-canonical read in tenths of a second (`VTIME`), not a character
+    Time,
+}
+
+#[cfg(unix)]
+impl ControlChar {
+    fn index(self) -> usize {
+        match self {
+            ControlChar::Eof => libc::VEOF,
+            ControlChar::Eol => libc::VEOL,
+            ControlChar::Eol2 => libc::VEOL2,
+            ControlChar::Erase => libc::VERASE,
+            ControlChar::WordErase => libc::VWERASE,
+            ControlChar::Kill => libc::VKILL,
+            ControlChar::Reprint => libc::VREPRINT,
+            ControlChar::LiteralNext => libc::VLNEXT,
+            ControlChar::Interrupt => libc::VINTR,
+            ControlChar::Quit => libc::VQUIT,
+            ControlChar::Suspend => libc::VSUSP,
+            ControlChar::Start => libc::VSTART,
+            ControlChar::Stop => libc::VSTOP,
+            ControlChar::Min => libc::VMIN,
+            ControlChar::Time => libc::VTIME,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn get(fd: RawFd) -> crate::Result<libc::termios> {
+    let mut termios = MaybeUninit::<libc::termios>::uninit();
+    if unsafe { libc::tcgetattr(fd, termios.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(unsafe { termios.assume_init() })
+}
+
+#[cfg(unix)]
+fn set(fd: RawFd, termios: &libc::termios) -> crate::Result<()> {
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, termios) } == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) fn control_chars(fd: RawFd) -> crate::Result<Vec<u8>> {
+    Ok(get(fd)?.c_cc.to_vec())
+}
+
+#[cfg(unix)]
+pub(crate) fn control_char(fd: RawFd, c: ControlChar) -> crate::Result<u8> {
+    Ok(get(fd)?.c_cc[c.index()])
+}
+
+#[cfg(unix)]
+pub(crate) fn set_control_char(fd: RawFd, c: ControlChar, value: u8) -> crate::Result<()> {
+    let mut termios = get(fd)?;
+    termios.c_cc[c.index()] = value;
+    set(fd, &termios)
+}
+
+#[cfg(unix)]
+pub(crate) fn canonical(fd: RawFd) -> crate::Result<bool> {
+    Ok(get(fd)?.c_lflag & libc::ICANON != 0)
+}
+
+#[cfg(unix)]
+pub(crate) fn set_canonical(fd: RawFd, enabled: bool) -> crate::Result<()> {
+    let mut termios = get(fd)?;
+    if enabled {
+        termios.c_lflag |= libc::ICANON;
+    } else {
+        termios.c_lflag &= !libc::ICANON;
+    }
+    set(fd, &termios)
+}
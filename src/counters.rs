@@ -0,0 +1,232 @@
+//! UART error and traffic counters
+#[cfg(any(target_os = "linux", target_os = "android", windows))]
+use std::io;
+
+/// Line quality counters of a port
+///
+/// On Linux these are the driver's totals since the port was opened, as reported by
+/// `TIOCGICOUNT`.  Windows only reports which errors happened since the last check,
+/// so each error counter is 0 or 1 there and the traffic totals are unknown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineCounters {
+    /// Bytes received, if known
+    pub rx: Option<u32>,
+    /// Bytes sent, if known
+    pub tx: Option<u32>,
+    /// Characters received with a framing error
+    pub frame: u32,
+    /// Characters received with a parity error
+    pub parity: u32,
+    /// Characters lost because the UART overran
+    pub overrun: u32,
+    /// Characters lost because the driver's input buffer overflowed
+    pub buffer_overrun: u32,
+    /// Break conditions received
+    pub breaks: u32,
+}
+
+impl LineCounters {
+    /// Returns what was counted since `earlier`, an older reading of the counters of
+    /// the same port.
+    ///
+    /// The driver's counters wrap around, the difference is taken modulo 2³².  The
+    /// Windows counters already only cover the time since the previous reading, so
+    /// there the counters are returned unchanged.
+    pub fn since(&self, earlier: &LineCounters) -> LineCounters {
+        if cfg!(windows) {
+            return *self;
+        }
+        let total = |now: Option<u32>, then: Option<u32>| Some(now?.wrapping_sub(then?));
+        LineCounters {
+            rx: total(self.rx, earlier.rx),
+            tx: total(self.tx, earlier.tx),
+            frame: self.frame.wrapping_sub(earlier.frame),
+            parity: self.parity.wrapping_sub(earlier.parity),
+            overrun: self.overrun.wrapping_sub(earlier.overrun),
+            buffer_overrun: self.buffer_overrun.wrapping_sub(earlier.buffer_overrun),
+            breaks: self.breaks.wrapping_sub(earlier.breaks),
+        }
+    }
+
+    /// Returns the number of characters received with a framing or parity error.
+    pub fn errors(&self) -> u32 {
+        self.frame.wrapping_add(self.parity)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn read(fd: std::os::unix::io::RawFd) -> crate::Result<LineCounters> {
+    // `struct serial_icounter_struct` from <linux/serial.h>
+    #[repr(C)]
+    #[derive(Default)]
+    struct SerialIcounter {
+        cts: libc::c_int,
+        dsr: libc::c_int,
+        rng: libc::c_int,
+        dcd: libc::c_int,
+        rx: libc::c_int,
+        tx: libc::c_int,
+        frame: libc::c_int,
+        overrun: libc::c_int,
+        parity: libc::c_int,
+        brk: libc::c_int,
+        buf_overrun: libc::c_int,
+        reserved: [libc::c_int; 9],
+    }
+
+    let mut icount = SerialIcounter::default();
+    if unsafe { libc::ioctl(fd, libc::TIOCGICOUNT as _, &mut icount) } == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(LineCounters {
+        rx: Some(icount.rx as u32),
+        tx: Some(icount.tx as u32),
+        frame: icount.frame as u32,
+        parity: icount.parity as u32,
+        overrun: icount.overrun as u32,
+        buffer_overrun: icount.buf_overrun as u32,
+        breaks: icount.brk as u32,
+    })
+}
+
+#[cfg(windows)]
+pub(crate) fn read(handle: std::os::windows::io::RawHandle) -> crate::Result<LineCounters> {
+    use windows_sys::Win32::Devices::Communication::{
+        ClearCommError, CE_BREAK, CE_FRAME, CE_OVERRUN, CE_RXOVER, CE_RXPARITY, COMSTAT,
+    };
+
+    let mut errors = 0;
+    let mut stat: COMSTAT = unsafe { std::mem::zeroed() };
+    if unsafe { ClearCommError(handle as _, &mut errors, &mut stat) } == 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let count = |flag| u32::from(errors & flag != 0);
+    Ok(LineCounters {
+        rx: None,
+        tx: None,
+        frame: count(CE_FRAME),
+        parity: count(CE_RXPARITY),
+        overrun: count(CE_OVERRUN),
+        buffer_overrun: count(CE_RXOVER),
+        breaks: count(CE_BREAK),
+    })
+}
+
+pub(crate) type BreakSender = futures::channel::mpsc::UnboundedSender<crate::Result<u32>>;
+
+/// A stream of the break conditions received by a port
+///
+/// Created by `SerialStream::break_events`.  Each item is a number of breaks
+/// received in a row, at least 1.  The stream ends when the port is closed, and
+/// on Unix when `break_events` is called again: only one stream gets the breaks.
+///
+/// On Unix breaks are marked in the received data with `PARMRK` and removed from
+/// it by the reads of the port, so they are only reported as the data is read.
+/// On Windows the driver signals them with `EV_BREAK`, which a thread waits for.
+#[cfg(any(unix, windows))]
+#[derive(Debug)]
+pub struct BreakEvents {
+    receiver: futures::channel::mpsc::UnboundedReceiver<crate::Result<u32>>,
+    #[cfg(windows)]
+    port: std::sync::Arc<std::os::windows::io::OwnedHandle>,
+}
+
+#[cfg(unix)]
+impl BreakEvents {
+    pub(crate) fn new() -> (BreakSender, Self) {
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        (sender, Self { receiver })
+    }
+}
+
+#[cfg(windows)]
+impl BreakEvents {
+    pub(crate) fn new(port: std::os::windows::io::OwnedHandle) -> crate::Result<Self> {
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::Devices::Communication::{SetCommMask, EV_BREAK};
+
+        let port = std::sync::Arc::new(port);
+        if unsafe { SetCommMask(port.as_raw_handle() as _, EV_BREAK) } == 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        let handle = port.clone();
+        std::thread::Builder::new()
+            .name("break events".into())
+            .spawn(move || wait_breaks(&handle, &sender))?;
+        Ok(Self { receiver, port })
+    }
+}
+
+// Report the `EV_BREAK` events of `port` until the stream goes away.
+#[cfg(windows)]
+fn wait_breaks(port: &std::os::windows::io::OwnedHandle, sender: &BreakSender) {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Devices::Communication::{WaitCommEvent, EV_BREAK};
+    use windows_sys::Win32::Foundation::{CloseHandle, GetLastError, ERROR_IO_PENDING};
+    use windows_sys::Win32::System::Threading::CreateEventW;
+    use windows_sys::Win32::System::IO::{GetOverlappedResult, OVERLAPPED};
+
+    let handle = port.as_raw_handle() as _;
+    let event = unsafe { CreateEventW(std::ptr::null(), 1, 0, std::ptr::null()) };
+    if event.is_null() {
+        let _ = sender.unbounded_send(Err(io::Error::last_os_error().into()));
+        return;
+    }
+    while !sender.is_closed() {
+        let mut mask = 0;
+        let mut overlapped: OVERLAPPED = unsafe { std::mem::zeroed() };
+        overlapped.hEvent = event;
+        let waited = unsafe { WaitCommEvent(handle, &mut mask, &mut overlapped) } != 0
+            || unsafe { GetLastError() } == ERROR_IO_PENDING && {
+                let mut transferred = 0;
+                unsafe { GetOverlappedResult(handle, &overlapped, &mut transferred, 1) != 0 }
+            };
+        if !waited {
+            let _ = sender.unbounded_send(Err(io::Error::last_os_error().into()));
+            break;
+        }
+        // The mask is cleared when the stream is dropped.
+        if mask & EV_BREAK != 0 {
+            let _ = sender.unbounded_send(Ok(1));
+        }
+    }
+    unsafe { CloseHandle(event) };
+}
+
+#[cfg(windows)]
+impl Drop for BreakEvents {
+    fn drop(&mut self) {
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::Devices::Communication::SetCommMask;
+
+        self.receiver.close();
+        // Wakes up the thread.
+        unsafe { SetCommMask(self.port.as_raw_handle() as _, 0) };
+    }
+}
+
+#[cfg(any(unix, windows))]
+impl futures::Stream for BreakEvents {
+    type Item = crate::Result<u32>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        std::pin::Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+/// How much of the data written to a port was transmitted
+///
+/// Returned by `SerialStream::write_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteStats {
+    /// Bytes accepted by the OS since the port was opened
+    pub accepted: u64,
+    /// Bytes accepted but still waiting in the output buffer
+    pub queued: u32,
+    /// Bytes accepted and no longer in the output buffer
+    pub transmitted: u64,
+}
@@ -0,0 +1,17 @@
+//! Short waits on the line, for breaks and the gaps around them
+use std::time::{Duration, Instant};
+
+// Waits shorter than this are busy loops, timers are not precise enough for them.
+const SPIN_BELOW: Duration = Duration::from_millis(2);
+
+// Wait for `duration`, without oversleeping short waits by a timer tick.
+pub(crate) async fn wait(duration: Duration) {
+    if duration >= SPIN_BELOW {
+        tokio::time::sleep(duration).await;
+        return;
+    }
+    let end = Instant::now() + duration;
+    while Instant::now() < end {
+        std::hint::spin_loop();
+    }
+}
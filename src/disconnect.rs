@@ -0,0 +1,42 @@
+//! Recognizing ports whose device went away
+use std::io;
+use std::time::Duration;
+
+/// How often `SerialStream::closed` checks the port when the OS does not notify
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Errors reads and writes fail with once the device is gone, they only mean a
+// disconnection if the port cannot be queried anymore either.
+#[cfg(unix)]
+const CODES: &[i32] = &[libc::EIO, libc::ENXIO, libc::ENODEV];
+#[cfg(windows)]
+const CODES: &[i32] = {
+    use windows_sys::Win32::Foundation::*;
+    &[
+        ERROR_ACCESS_DENIED as i32,
+        ERROR_BAD_COMMAND as i32,
+        ERROR_GEN_FAILURE as i32,
+        ERROR_OPERATION_ABORTED as i32,
+        ERROR_DEVICE_NOT_CONNECTED as i32,
+    ]
+};
+
+pub(crate) fn is_candidate(err: &io::Error) -> bool {
+    err.raw_os_error().is_some_and(|code| CODES.contains(&code))
+}
+
+pub(crate) fn error() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "device disconnected")
+}
+
+// Whether the OS name of a removed port designates the port opened as `name`.
+pub(crate) fn same_port(removed: &str, name: &str) -> bool {
+    #[cfg(unix)]
+    {
+        removed == name
+    }
+    #[cfg(windows)]
+    {
+        removed.eq_ignore_ascii_case(name.trim_start_matches(r"\\.\"))
+    }
+}
@@ -0,0 +1,201 @@
+//! DMX512 output
+//!
+//! DMX512 drives stage lighting over RS-485 at 250000 baud, 8N2.  A frame, or
+//! packet, is a break of at least 92µs, a mark after break of at least 12µs, then
+//! a start code and up to 512 slots, one per channel.  Fixtures only act on
+//! frames starting with a break, so it cannot be left to the UART: [`DmxSender`]
+//! waits for the previous frame to be transmitted, then toggles the break itself
+//! before writing each frame.
+//!
+//! ```no_run
+//! use tokio_serial::dmx::DmxSender;
+//! use tokio_serial::{presets, SerialStream};
+//!
+//! # async fn example() -> tokio_serial::Result<()> {
+//! let port = SerialStream::open(&presets::dmx512("/dev/ttyUSB0"))?;
+//! let mut dmx = DmxSender::new(port)?;
+//! for level in 0..=255 {
+//!     // Fade in the dimmer on channel 1.
+//!     dmx.set_channel(1, level);
+//!     dmx.tick().await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::delay::wait;
+use crate::{SerialPort, SerialSettings, SerialStream, StopBits};
+
+use tokio::io::AsyncWriteExt;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+use std::time::Duration;
+
+/// The baud rate of DMX512
+pub const BAUD_RATE: u32 = 250_000;
+
+/// The number of channels of a universe
+pub const CHANNELS: usize = 512;
+
+/// The start code of dimmer levels, the usual frames
+pub const NULL_START_CODE: u8 = 0x00;
+
+/// The settings of DMX512 lines: 250000 baud, 8 data bits, no parity, two stop bits
+pub fn settings() -> SerialSettings {
+    SerialSettings {
+        stop_bits: StopBits::Two,
+        ..SerialSettings::new(BAUD_RATE)
+    }
+}
+
+/// A DMX512 universe sent out of a serial port
+///
+/// The sender keeps the levels of the universe, which [`send`](DmxSender::send)
+/// writes as one frame, and [`tick`](DmxSender::tick) at the frame rate, 40 frames
+/// per second by default.  Fixtures expect frames continuously, and consider the
+/// signal lost after a second without one.
+///
+/// The break lasts 176µs and the mark after break 16µs by default, safely over
+/// the minimum of the standard.  Waits this short block the thread: timers would
+/// stretch them to a millisecond or more, which fixtures accept but which lowers
+/// the frame rate.  The break is asserted through the OS and some USB adapters
+/// add their own latency to it, which only lengthens it.
+///
+/// At 250000 baud a full frame takes about 23ms, so at most 44 frames of 512
+/// channels fit in a second; universes with fewer
+/// [`slots`](DmxSender::set_slots) can be refreshed faster.
+#[derive(Debug)]
+pub struct DmxSender {
+    port: SerialStream,
+    // The start code, then the levels of the channels.
+    frame: Vec<u8>,
+    break_time: Duration,
+    mark_after_break: Duration,
+    interval: Interval,
+}
+
+impl DmxSender {
+    /// Send a universe of 512 channels, all at 0, out of `port`, configured for
+    /// DMX512.
+    pub fn new(mut port: SerialStream) -> crate::Result<Self> {
+        port.apply_settings(&settings())?;
+        let mut frame = vec![0; 1 + CHANNELS];
+        frame[0] = NULL_START_CODE;
+        Ok(Self {
+            port,
+            frame,
+            break_time: Duration::from_micros(176),
+            mark_after_break: Duration::from_micros(16),
+            interval: Self::interval(Duration::from_millis(25)),
+        })
+    }
+
+    fn interval(period: Duration) -> Interval {
+        let mut interval = tokio::time::interval_at(Instant::now(), period);
+        // Fixtures need frames regularly, not bursts making up for a late one.
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    }
+
+    /// Set the level of `channel`, numbered from 1 as on fixtures.
+    ///
+    /// # Panics
+    ///
+    /// If `channel` is 0 or over the number of slots.
+    pub fn set_channel(&mut self, channel: usize, level: u8) {
+        assert!(
+            (1..self.frame.len()).contains(&channel),
+            "invalid DMX channel {}",
+            channel
+        );
+        self.frame[channel] = level;
+    }
+
+    /// Returns the level of `channel`, numbered from 1.
+    pub fn channel(&self, channel: usize) -> Option<u8> {
+        match channel {
+            0 => None,
+            _ => self.frame.get(channel).copied(),
+        }
+    }
+
+    /// Returns the levels of the channels, channel 1 first.
+    pub fn channels(&self) -> &[u8] {
+        &self.frame[1..]
+    }
+
+    /// Returns the levels of the channels to change them, channel 1 first.
+    pub fn channels_mut(&mut self) -> &mut [u8] {
+        &mut self.frame[1..]
+    }
+
+    /// Set the start code of the frames, [`NULL_START_CODE`] for dimmer levels.
+    pub fn set_start_code(&mut self, start_code: u8) {
+        self.frame[0] = start_code;
+    }
+
+    /// Set the number of channels sent in each frame, 512 at most.
+    ///
+    /// Channels added are at 0.
+    ///
+    /// # Panics
+    ///
+    /// If `slots` is over 512.
+    pub fn set_slots(&mut self, slots: usize) {
+        assert!(slots <= CHANNELS, "DMX universes have 512 channels at most");
+        self.frame.resize(1 + slots, 0);
+    }
+
+    /// Set how long the break lasts, 92µs at least.
+    pub fn set_break_time(&mut self, duration: Duration) {
+        self.break_time = duration;
+    }
+
+    /// Set how long the mark after break lasts, 12µs at least.
+    pub fn set_mark_after_break(&mut self, duration: Duration) {
+        self.mark_after_break = duration;
+    }
+
+    /// Set the number of frames [`tick`](DmxSender::tick) sends per second.
+    ///
+    /// # Panics
+    ///
+    /// If `rate` is not positive.
+    pub fn set_frame_rate(&mut self, rate: f64) {
+        assert!(rate > 0.0, "invalid DMX frame rate {}", rate);
+        self.interval = Self::interval(Duration::from_secs_f64(1.0 / rate));
+    }
+
+    /// Returns a reference to the underlying port.
+    pub fn get_ref(&self) -> &SerialStream {
+        &self.port
+    }
+
+    /// Consumes the sender, returning the underlying port.
+    pub fn into_inner(self) -> SerialStream {
+        self.port
+    }
+
+    /// Send the universe as one frame now.
+    ///
+    /// Waits for the previous frame to be transmitted first, a break would cut it
+    /// short.  Returns once the frame is handed to the OS.
+    pub async fn send(&mut self) -> crate::Result<()> {
+        self.port.wait_transmitted().await?;
+        self.port.set_break()?;
+        wait(self.break_time).await;
+        self.port.clear_break()?;
+        wait(self.mark_after_break).await;
+        self.port.write_all(&self.frame).await?;
+        self.port.flush().await?;
+        Ok(())
+    }
+
+    /// Wait for the next frame time and send the universe.
+    ///
+    /// Ticks missed because the caller was late are not made up for, the next
+    /// frames are sent at the frame rate from then on.
+    pub async fn tick(&mut self) -> crate::Result<()> {
+        self.interval.tick().await;
+        self.send().await
+    }
+}
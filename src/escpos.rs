@@ -0,0 +1,516 @@
+//! ESC/POS receipt printers
+//!
+//! ESC/POS is the command set of Epson receipt printers, spoken by most other
+//! brands too: text is printed as is, formatting and paper handling are escape
+//! sequences.  [`Commands`] builds them, [`Printer`] sends them and keeps track of
+//! the status of the printer.
+//!
+//! Printers report their status in two ways, both read by [`Printer`]:
+//!
+//! * real-time status requests, `DLE EOT n`, which the printer answers as soon as
+//!   it receives them, even while it is busy printing what came before, see
+//!   [`Printer::status`];
+//! * automatic status back, enabled with
+//!   [`Commands::automatic_status_back`], with which the printer sends its
+//!   status on its own whenever it changes.  A [`Printer`] stops sending data
+//!   once the printer reports its cover open or the paper out.
+//!
+//! ```no_run
+//! use futures::SinkExt;
+//! use tokio_serial::escpos::{Align, Commands, Cut, Printer};
+//!
+//! # async fn example(port: tokio_serial::SerialStream) -> Result<(), tokio_serial::escpos::PrinterError> {
+//! let mut printer = Printer::new(port);
+//! if !printer.status().await?.is_ready() {
+//!     return Ok(());
+//! }
+//! let receipt = Commands::new()
+//!     .initialize()
+//!     .automatic_status_back(true)
+//!     .align(Align::Center)
+//!     .bold(true)
+//!     .line("CORNER SHOP")
+//!     .bold(false)
+//!     .align(Align::Left)
+//!     .line("1 x Coffee         2.50")
+//!     .feed(3)
+//!     .cut(Cut::Partial);
+//! printer.send(receipt.as_bytes()).await?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::shutdown::ShutdownLayered;
+use crate::SerialStream;
+
+use futures::future::BoxFuture;
+use futures::{ready, Sink};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{error, fmt, io};
+
+/// Escape
+pub const ESC: u8 = 0x1b;
+/// Group separator, starting the `GS` commands
+pub const GS: u8 = 0x1d;
+/// Data link escape, starting the real-time commands
+pub const DLE: u8 = 0x10;
+/// End of transmission, `DLE EOT n` requests the status
+pub const EOT: u8 = 0x04;
+/// Line feed, prints the line
+pub const LF: u8 = 0x0a;
+
+/// Errors produced by [`Printer`]
+#[derive(Debug)]
+pub enum PrinterError {
+    /// The port failed
+    Io(io::Error),
+    /// The printer did not answer a status request in time
+    Timeout,
+    /// The printer cannot print, the data not sent yet stays buffered
+    NotReady(Status),
+}
+
+impl fmt::Display for PrinterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrinterError::Io(err) => err.fmt(f),
+            PrinterError::Timeout => f.write_str("printer status request timed out"),
+            PrinterError::NotReady(status) => write!(f, "printer not ready: {}", status),
+        }
+    }
+}
+
+impl error::Error for PrinterError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PrinterError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PrinterError {
+    fn from(err: io::Error) -> Self {
+        PrinterError::Io(err)
+    }
+}
+
+impl From<crate::Error> for PrinterError {
+    fn from(err: crate::Error) -> Self {
+        PrinterError::Io(err.into())
+    }
+}
+
+/// The status of a printer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Status {
+    /// The printer is offline: its cover is open, it is out of paper, failed, or
+    /// paper is being fed with the button
+    pub offline: bool,
+    /// The cover is open
+    pub cover_open: bool,
+    /// Paper is being fed with the feed button
+    pub paper_feed: bool,
+    /// The paper roll is nearly empty
+    pub paper_near_end: bool,
+    /// The printer is out of paper
+    pub paper_end: bool,
+    /// The autocutter failed, for instance jammed
+    pub cutter_error: bool,
+    /// An error which requires switching the printer off and on
+    pub unrecoverable_error: bool,
+    /// An error which clears by itself, such as the print head overheating
+    pub recoverable_error: bool,
+    /// The level of pin 3 of the cash drawer connector, the drawer open sensor
+    pub drawer_sensor: bool,
+}
+
+impl Status {
+    /// Returns whether the printer can print: its cover is closed, it has paper,
+    /// and it reports no error.
+    pub fn is_ready(&self) -> bool {
+        !(self.cover_open
+            || self.paper_end
+            || self.cutter_error
+            || self.unrecoverable_error
+            || self.recoverable_error)
+    }
+
+    /// The status from the answers to `DLE EOT 1` to `DLE EOT 4`, in order.
+    pub fn from_responses(responses: [u8; 4]) -> Self {
+        let [printer, offline, error, paper] = responses;
+        Self {
+            offline: printer & 0x08 != 0,
+            cover_open: offline & 0x04 != 0,
+            paper_feed: offline & 0x08 != 0,
+            paper_near_end: paper & 0x0c != 0,
+            paper_end: offline & 0x20 != 0 || paper & 0x60 != 0,
+            cutter_error: error & 0x08 != 0,
+            unrecoverable_error: error & 0x20 != 0,
+            recoverable_error: error & 0x40 != 0,
+            drawer_sensor: printer & 0x04 != 0,
+        }
+    }
+
+    /// The status from the four bytes of an automatic status back.
+    pub fn from_automatic(status: [u8; 4]) -> Self {
+        Self {
+            offline: status[0] & 0x08 != 0,
+            cover_open: status[0] & 0x20 != 0,
+            paper_feed: status[0] & 0x40 != 0,
+            paper_near_end: status[2] & 0x03 != 0,
+            paper_end: status[2] & 0x0c != 0,
+            cutter_error: status[1] & 0x08 != 0,
+            unrecoverable_error: status[1] & 0x20 != 0,
+            recoverable_error: status[1] & 0x40 != 0,
+            drawer_sensor: status[0] & 0x04 != 0,
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let conditions = [
+            (self.cover_open, "cover open"),
+            (self.paper_end, "paper out"),
+            (self.paper_near_end, "paper near end"),
+            (self.cutter_error, "cutter error"),
+            (self.unrecoverable_error, "unrecoverable error"),
+            (self.recoverable_error, "recoverable error"),
+            (self.paper_feed, "feeding paper"),
+            (self.offline, "offline"),
+        ];
+        let mut conditions = conditions.iter().filter(|(set, _)| *set);
+        match conditions.next() {
+            Some((_, first)) => f.write_str(first)?,
+            None => return f.write_str("ready"),
+        }
+        for (_, condition) in conditions {
+            write!(f, ", {}", condition)?;
+        }
+        Ok(())
+    }
+}
+
+/// The justification of the lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Align {
+    /// Left justified, the default
+    Left,
+    /// Centered
+    Center,
+    /// Right justified
+    Right,
+}
+
+/// How the paper is cut
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Cut {
+    /// Cut through
+    Full,
+    /// Leave a point uncut, so the receipt does not fall
+    Partial,
+}
+
+/// A sequence of ESC/POS commands
+///
+/// Text is sent as is: characters outside of ASCII are replaced by `?`, as the
+/// code pages of printers differ; [`raw`](Commands::raw) sends text already
+/// encoded for the code page of the printer.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Commands {
+    buf: Vec<u8>,
+}
+
+impl Commands {
+    /// An empty sequence.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reset the printer to its power on settings, `ESC @`.
+    pub fn initialize(self) -> Self {
+        self.raw(&[ESC, b'@'])
+    }
+
+    /// Print `text`, lines being ended by `\n`.
+    pub fn text(mut self, text: &str) -> Self {
+        self.buf.extend(
+            text.chars()
+                .map(|c| if c.is_ascii() { c as u8 } else { b'?' }),
+        );
+        self
+    }
+
+    /// Print `text` and end the line.
+    pub fn line(self, text: &str) -> Self {
+        self.text(text).raw(&[LF])
+    }
+
+    /// Set bold printing, `ESC E`.
+    pub fn bold(self, bold: bool) -> Self {
+        self.raw(&[ESC, b'E', bold as u8])
+    }
+
+    /// Set underlined printing, `ESC -`.
+    pub fn underline(self, underline: bool) -> Self {
+        self.raw(&[ESC, b'-', underline as u8])
+    }
+
+    /// Set the justification of the next lines, `ESC a`.
+    pub fn align(self, align: Align) -> Self {
+        let n = match align {
+            Align::Left => 0,
+            Align::Center => 1,
+            Align::Right => 2,
+        };
+        self.raw(&[ESC, b'a', n])
+    }
+
+    /// Set the size of characters, 1 to 8 times their normal width and height,
+    /// `GS !`.
+    ///
+    /// # Panics
+    ///
+    /// If `width` or `height` is not 1 to 8.
+    pub fn size(self, width: u8, height: u8) -> Self {
+        assert!(
+            (1..=8).contains(&width) && (1..=8).contains(&height),
+            "character sizes are 1 to 8, not {}x{}",
+            width,
+            height
+        );
+        self.raw(&[GS, b'!', (width - 1) << 4 | (height - 1)])
+    }
+
+    /// Print the buffer and feed `lines` lines, `ESC d`.
+    pub fn feed(self, lines: u8) -> Self {
+        self.raw(&[ESC, b'd', lines])
+    }
+
+    /// Cut the paper, `GS V`.
+    pub fn cut(self, cut: Cut) -> Self {
+        let m = match cut {
+            Cut::Full => 0,
+            Cut::Partial => 1,
+        };
+        self.raw(&[GS, b'V', m])
+    }
+
+    /// Enable or disable automatic status back for all conditions, `GS a`.
+    pub fn automatic_status_back(self, enable: bool) -> Self {
+        self.raw(&[GS, b'a', if enable { 0x0f } else { 0x00 }])
+    }
+
+    /// Send `bytes` as is.
+    pub fn raw(mut self, bytes: &[u8]) -> Self {
+        self.buf.extend_from_slice(bytes);
+        self
+    }
+
+    /// Returns the encoded commands.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Consumes the sequence, returning the encoded commands.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// A receipt printer on a serial port
+///
+/// `Printer` is a [`Sink`] of ESC/POS data.  While it writes, it reads what the
+/// printer sends: automatic status backs update the
+/// [`last_status`](Printer::last_status), and writing fails with
+/// [`NotReady`](PrinterError::NotReady) while it is not
+/// [ready](Status::is_ready), keeping the data not sent yet for the next flush.
+/// It fails until the printer reports being ready again, by itself or when asked
+/// with [`status`](Printer::status).
+///
+/// [`status`](Printer::status) sends real-time status requests ahead of the data
+/// still buffered, and waits 1 second by default for the answers.
+#[derive(Debug)]
+pub struct Printer {
+    port: SerialStream,
+    wr: Vec<u8>,
+    rd: Vec<u8>,
+    responses: Vec<u8>,
+    status: Option<Status>,
+    timeout: Duration,
+}
+
+impl Printer {
+    /// Drive the printer on `port`.
+    pub fn new(port: SerialStream) -> Self {
+        Self {
+            port,
+            wr: Vec::new(),
+            rd: Vec::new(),
+            responses: Vec::new(),
+            status: None,
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Set how long to wait for the answers to status requests.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the last status reported by the printer, if it reported any.
+    pub fn last_status(&self) -> Option<Status> {
+        self.status
+    }
+
+    /// Returns a reference to the underlying port.
+    pub fn get_ref(&self) -> &SerialStream {
+        &self.port
+    }
+
+    /// Consumes the printer, returning the underlying port.  Data not sent yet is
+    /// dropped.
+    pub fn into_inner(self) -> SerialStream {
+        self.port
+    }
+
+    /// Request the status of the printer.
+    ///
+    /// The requests are written right away, ahead of the data still buffered;
+    /// the printer answers them even while busy printing what it received before.
+    ///
+    /// ## Errors
+    ///
+    /// * `Timeout` if the printer does not answer in time, for instance because
+    ///   it is switched off.
+    pub async fn status(&mut self) -> Result<Status, PrinterError> {
+        self.responses.clear();
+        let mut requests = Vec::with_capacity(12);
+        for n in 1..=4 {
+            requests.extend_from_slice(&[DLE, EOT, n]);
+        }
+        self.port.write_all(&requests).await?;
+        self.port.flush().await?;
+
+        let timeout = self.timeout;
+        let read = async {
+            while self.responses.len() < 4 {
+                if self.port.read_buf(&mut self.rd).await? == 0 {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+                }
+                self.parse();
+            }
+            Ok(())
+        };
+        tokio::time::timeout(timeout, read)
+            .await
+            .map_err(|_| PrinterError::Timeout)??;
+        let mut responses = [0u8; 4];
+        responses.copy_from_slice(&self.responses[..4]);
+        self.responses.clear();
+        let status = Status::from_responses(responses);
+        self.status = Some(status);
+        Ok(status)
+    }
+
+    // Sort the bytes received into answers to status requests and automatic
+    // status backs, by their fixed bits.
+    fn parse(&mut self) {
+        while let Some(&first) = self.rd.first() {
+            if first & 0x93 == 0x12 {
+                self.responses.push(first);
+                self.rd.remove(0);
+            } else if first & 0x93 == 0x10 {
+                if self.rd.len() < 4 {
+                    return;
+                }
+                let mut status = [0u8; 4];
+                status.copy_from_slice(&self.rd[..4]);
+                self.rd.drain(..4);
+                let status = Status::from_automatic(status);
+                log::debug!("printer status: {}", status);
+                self.status = Some(status);
+            } else {
+                log::debug!("skipping byte {:#04x} from the printer", first);
+                self.rd.remove(0);
+            }
+        }
+    }
+
+    fn poll_read_status(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        let mut buf = [0u8; 64];
+        loop {
+            let mut read = ReadBuf::new(&mut buf);
+            match Pin::new(&mut self.port).poll_read(cx, &mut read) {
+                Poll::Ready(Ok(())) if !read.filled().is_empty() => {
+                    self.rd.extend_from_slice(read.filled());
+                    self.parse();
+                }
+                // End of file, the write side reports it if it matters.
+                Poll::Ready(Ok(())) => return Ok(()),
+                Poll::Ready(Err(err)) => return Err(err),
+                Poll::Pending => return Ok(()),
+            }
+        }
+    }
+
+    fn poll_write_buffered(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), PrinterError>> {
+        while !self.wr.is_empty() {
+            self.poll_read_status(cx)?;
+            if let Some(status) = self.status.filter(|status| !status.is_ready()) {
+                return Poll::Ready(Err(PrinterError::NotReady(status)));
+            }
+            let n = ready!(Pin::new(&mut self.port).poll_write(cx, &self.wr))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::Error::from(io::ErrorKind::WriteZero).into()));
+            }
+            self.wr.drain(..n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Sink<&[u8]> for Printer {
+    type Error = PrinterError;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: &[u8]) -> Result<(), Self::Error> {
+        self.get_mut().wr.extend_from_slice(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let pin = self.get_mut();
+
+        ready!(pin.poll_write_buffered(cx))?;
+        ready!(Pin::new(&mut pin.port).poll_flush(cx))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl ShutdownLayered for Printer {
+    /// Writes the data still buffered.
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(futures::future::poll_fn(move |cx| {
+            self.poll_write_buffered(cx).map_err(|err| match err {
+                PrinterError::Io(err) => err,
+                err => io::Error::other(err.to_string()),
+            })
+        }))
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        Some(&mut self.port)
+    }
+}
@@ -0,0 +1,314 @@
+//! Software flow control handled by the crate
+//!
+//! How drivers implement `FlowControl::Software` varies wildly, many USB adapters
+//! ignore it altogether.  [`SoftwareFlowControl`] implements XON/XOFF on top of any
+//! port instead: XOFF and XON received from the other end are removed from the
+//! incoming data and hold back, respectively release, writes, and XOFF is sent to
+//! the other end while more than a configurable amount of received data is waiting
+//! to be read.
+//!
+//! The port itself should be opened without flow control, so that the driver leaves
+//! the control characters alone.
+use crate::{AsyncSerialPort, ShutdownLayered};
+
+use futures::future::BoxFuture;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+/// The XON (DC1) character, asking the receiver to resume sending.
+pub const XON: u8 = 0x11;
+
+/// The XOFF (DC3) character, asking the receiver to stop sending.
+pub const XOFF: u8 = 0x13;
+
+const DEFAULT_RX_HIGH_WATERMARK: usize = 4096;
+const DEFAULT_RX_LOW_WATERMARK: usize = 1024;
+
+/// A port wrapper implementing XON/XOFF flow control
+///
+/// Data keeps being read while writes are held back by XOFF, to notice the XON
+/// releasing them.  It is buffered until the application reads it, and XOFF is sent
+/// once the buffer goes over the [high watermark](SoftwareFlowControl::set_rx_watermarks),
+/// followed by XON once reading brings it back to the low watermark.
+#[derive(Debug)]
+pub struct SoftwareFlowControl<S> {
+    inner: S,
+    rx: VecDeque<u8>,
+    low_watermark: usize,
+    high_watermark: usize,
+    // Whether the other end sent XOFF
+    tx_paused: bool,
+    // Whether XOFF was sent, because the buffer filled or on request
+    remote_paused: bool,
+    held: bool,
+    pending_control: Option<u8>,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+impl<S> SoftwareFlowControl<S> {
+    /// Handle XON/XOFF for `inner`.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            rx: VecDeque::new(),
+            low_watermark: DEFAULT_RX_LOW_WATERMARK,
+            high_watermark: DEFAULT_RX_HIGH_WATERMARK,
+            tx_paused: false,
+            remote_paused: false,
+            held: false,
+            pending_control: None,
+            read_waker: None,
+            write_waker: None,
+        }
+    }
+
+    /// Set how much received data may be buffered before XOFF is sent, and how
+    /// little is left when XON is sent again.
+    ///
+    /// # Panics
+    ///
+    /// If `low` is greater than `high`.
+    pub fn set_rx_watermarks(&mut self, low: usize, high: usize) {
+        assert!(low <= high, "low watermark above high watermark");
+        self.low_watermark = low;
+        self.high_watermark = high;
+    }
+
+    /// Returns the low and high watermarks of the receive buffer.
+    pub fn rx_watermarks(&self) -> (usize, usize) {
+        (self.low_watermark, self.high_watermark)
+    }
+
+    /// Returns whether writes are held back by an XOFF from the other end.
+    pub fn tx_paused(&self) -> bool {
+        self.tx_paused
+    }
+
+    /// Returns whether XOFF was sent to the other end and not yet followed by XON.
+    pub fn remote_paused(&self) -> bool {
+        self.remote_paused
+    }
+
+    /// Ask the other end to stop sending.
+    ///
+    /// XOFF is sent with the next read or write, and XON once
+    /// [`resume_remote`](SoftwareFlowControl::resume_remote) is called.
+    pub fn pause_remote(&mut self) {
+        self.held = true;
+        if !self.remote_paused {
+            self.remote_paused = true;
+            self.pending_control = Some(XOFF);
+        }
+    }
+
+    /// Let the other end resume sending after
+    /// [`pause_remote`](SoftwareFlowControl::pause_remote).
+    ///
+    /// XON is held back while the receive buffer is above its low watermark.
+    pub fn resume_remote(&mut self) {
+        self.held = false;
+        self.release_remote();
+    }
+
+    /// Returns a reference to the wrapped port.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped port.
+    ///
+    /// Reading from it directly bypasses XON/XOFF handling.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes the wrapper, returning the port.
+    ///
+    /// Buffered data which was not read yet is lost.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn release_remote(&mut self) {
+        if self.remote_paused && !self.held && self.rx.len() <= self.low_watermark {
+            self.remote_paused = false;
+            self.pending_control = Some(XON);
+        }
+    }
+
+    fn receive(&mut self, data: &[u8]) {
+        for &byte in data {
+            match byte {
+                XOFF => self.tx_paused = true,
+                XON => {
+                    self.tx_paused = false;
+                    if let Some(waker) = self.write_waker.take() {
+                        waker.wake();
+                    }
+                }
+                byte => self.rx.push_back(byte),
+            }
+        }
+        if !self.remote_paused && self.rx.len() > self.high_watermark {
+            self.remote_paused = true;
+            self.pending_control = Some(XOFF);
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> SoftwareFlowControl<S> {
+    /// Read from the port into the buffer, returning `true` at end of file.
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<bool>> {
+        let mut data = [0u8; 256];
+        let mut buf = ReadBuf::new(&mut data);
+        futures::ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf))?;
+        let eof = buf.filled().is_empty();
+        self.receive(buf.filled());
+        Poll::Ready(Ok(eof))
+    }
+
+    fn poll_control(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(byte) = self.pending_control {
+            if futures::ready!(Pin::new(&mut self.inner).poll_write(cx, &[byte]))? == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending_control = None;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    // Control characters go out as soon as possible, without blocking the current
+    // operation on them.
+    fn try_control(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        match self.poll_control(cx) {
+            Poll::Ready(Err(e)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for SoftwareFlowControl<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.try_control(cx)?;
+
+        while this.rx.is_empty() {
+            match this.poll_fill(cx) {
+                Poll::Ready(Ok(true)) => return Poll::Ready(Ok(())),
+                Poll::Ready(Ok(false)) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => {
+                    this.read_waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        }
+
+        let n = this.rx.len().min(buf.remaining());
+        let (front, back) = this.rx.as_slices();
+        let from_front = n.min(front.len());
+        buf.put_slice(&front[..from_front]);
+        buf.put_slice(&back[..n - from_front]);
+        this.rx.drain(..n);
+
+        this.release_remote();
+        this.try_control(cx)?;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for SoftwareFlowControl<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        futures::ready!(this.poll_control(cx))?;
+
+        // Look for XOFF before writing, and for XON while paused.
+        let mut eof = false;
+        while this.tx_paused || this.rx.len() <= this.high_watermark {
+            match this.poll_fill(cx) {
+                Poll::Ready(Ok(false)) => {}
+                Poll::Ready(Ok(true)) => {
+                    eof = true;
+                    break;
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => break,
+            }
+        }
+        // Reading from here took over the read wakeup of a pending reader.
+        if let Some(waker) = this.read_waker.take() {
+            waker.wake();
+        }
+        this.try_control(cx)?;
+
+        // At end of file the write itself reports the closed port.
+        if this.tx_paused && !eof {
+            this.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        Pin::new(&mut this.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        futures::ready!(this.poll_control(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        futures::ready!(this.poll_control(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+impl<S: AsyncSerialPort> AsyncSerialPort for SoftwareFlowControl<S> {
+    fn port_name(&self) -> Option<String> {
+        self.inner.port_name()
+    }
+
+    fn change_baud_rate(&mut self, baud_rate: u32) -> crate::Result<()> {
+        self.inner.change_baud_rate(baud_rate)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> crate::Result<()> {
+        self.inner.set_dtr(level)
+    }
+
+    fn set_rts(&mut self, level: bool) -> crate::Result<()> {
+        self.inner.set_rts(level)
+    }
+
+    fn set_break_condition(&mut self, asserted: bool) -> crate::Result<()> {
+        self.inner.set_break_condition(asserted)
+    }
+}
+
+impl<S> ShutdownLayered for SoftwareFlowControl<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + ShutdownLayered,
+{
+    /// Sends the pending XON or XOFF, if any.
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(futures::future::poll_fn(move |cx| self.poll_control(cx)))
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        Some(&mut self.inner)
+    }
+}
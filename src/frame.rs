@@ -1,17 +1,20 @@
 //! A unified [`Stream`] and [`Sink`] interface to an underlying `SerialStream`, using
 //! the `Encoder` and `Decoder` traits to encode and decode frames.
-use super::SerialStream;
+use super::codec::{LinesCodec, DEFAULT_MAX_LINE_LENGTH};
+use super::{SerialStream, ShutdownLayered};
 
 use tokio_util::codec::{Decoder, Encoder};
 
+use futures::future::BoxFuture;
 use futures::{Sink, Stream};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
-use bytes::{BufMut, BytesMut};
+use bytes::{Buf, BufMut, BytesMut};
 use futures::ready;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::{io, mem::MaybeUninit};
+use tokio::sync::watch;
 
 /// A unified [`Stream`] and [`Sink`] interface to an underlying `SerialStream`, using
 /// the `Encoder` and `Decoder` traits to encode and decode frames.
@@ -33,15 +36,27 @@ use std::{io, mem::MaybeUninit};
 /// [`Stream`]: futures_core::Stream
 /// [`Sink`]: futures_sink::Sink
 /// [`split`]: https://docs.rs/futures/0.3/futures/stream/trait.StreamExt.html#method.split
+/// ## Back-pressure
+///
+/// Encoded frames are buffered and written out as the port accepts them.  The amount
+/// of buffered data is bounded by a pair of watermarks: once it reaches the high
+/// watermark the sink is *congested* and [`poll_ready`](Sink::poll_ready) only
+/// resolves after the buffer drained to the low watermark.  Applications can watch
+/// the congestion state through [`congestion`](SerialFramed::congestion) to throttle
+/// their producers explicitly.  By default both watermarks are 0, every frame is
+/// written out before the next one is accepted.
 #[must_use = "sinks do nothing unless polled"]
 #[derive(Debug)]
 pub struct SerialFramed<C> {
     port: SerialStream,
     codec: C,
     rd: BytesMut,
+    // Room made in `rd` before each read.
+    rd_capacity: usize,
     wr: BytesMut,
-    flushed: bool,
-    is_readable: bool,
+    low_watermark: usize,
+    high_watermark: usize,
+    congested: watch::Sender<bool>,
 }
 
 const INITIAL_RD_CAPACITY: usize = 64 * 1024;
@@ -53,22 +68,15 @@ impl<C: Decoder + Unpin> Stream for SerialFramed<C> {
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let pin = self.get_mut();
 
-        pin.rd.reserve(INITIAL_RD_CAPACITY);
-
         loop {
-            // Are there still bytes left in the read buffer to decode?
-            if pin.is_readable {
-                if let Some(frame) = pin.codec.decode_eof(&mut pin.rd)? {
-                    return Poll::Ready(Some(Ok(frame)));
-                }
-
-                // if this line has been reached then decode has returned `None`.
-                pin.is_readable = false;
-                pin.rd.clear();
+            // Are there still complete frames left in the read buffer?
+            if let Some(frame) = pin.codec.decode(&mut pin.rd)? {
+                return Poll::Ready(Some(Ok(frame)));
             }
 
             // We're out of data. Try and fetch more data to decode
-            unsafe {
+            pin.rd.reserve(pin.rd_capacity);
+            let n = unsafe {
                 // Convert `&mut [MaybeUnit<u8>]` to `&mut [u8]` because we will be
                 // writing to it via `poll_recv_from` and therefore initializing the memory.
                 let buf = &mut *(pin.rd.chunk_mut() as *mut _ as *mut [MaybeUninit<u8>]);
@@ -77,11 +85,37 @@ impl<C: Decoder + Unpin> Stream for SerialFramed<C> {
                 ready!(Pin::new(&mut pin.port).poll_read(cx, &mut read))?;
 
                 assert_eq!(ptr, read.filled().as_ptr());
-                pin.rd.advance_mut(read.filled().len());
+                let n = read.filled().len();
+                pin.rd.advance_mut(n);
+                n
             };
 
-            pin.is_readable = true;
+            if n == 0 {
+                // The port was closed, hand out what is left.
+                return Poll::Ready(pin.codec.decode_eof(&mut pin.rd).transpose());
+            }
+        }
+    }
+}
+
+impl<C> SerialFramed<C> {
+    /// Write buffered data until at most `target` bytes are left.
+    fn poll_write_until(&mut self, cx: &mut Context<'_>, target: usize) -> Poll<io::Result<()>> {
+        while self.wr.len() > target {
+            let n = ready!(Pin::new(&mut self.port).poll_write(cx, &self.wr))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "failed to write frame to serial port",
+                )));
+            }
+            self.wr.advance(n);
+        }
+
+        if *self.congested.borrow() && self.wr.len() <= self.low_watermark {
+            self.congested.send_replace(false);
         }
+        Poll::Ready(Ok(()))
     }
 }
 
@@ -89,10 +123,15 @@ impl<I, C: Encoder<I> + Unpin> Sink<I> for SerialFramed<C> {
     type Error = C::Error;
 
     fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        if !self.flushed {
-            match self.poll_flush(cx)? {
-                Poll::Ready(()) => {}
-                Poll::Pending => return Poll::Pending,
+        let pin = self.get_mut();
+
+        if *pin.congested.borrow() {
+            let low_watermark = pin.low_watermark;
+            ready!(pin.poll_write_until(cx, low_watermark))?;
+        } else if !pin.wr.is_empty() {
+            // Keep the data flowing without waiting for the port, there is room left.
+            if let Poll::Ready(result) = pin.poll_write_until(cx, 0) {
+                result?;
             }
         }
 
@@ -103,40 +142,23 @@ impl<I, C: Encoder<I> + Unpin> Sink<I> for SerialFramed<C> {
         let pin = self.get_mut();
 
         pin.codec.encode(item, &mut pin.wr)?;
-        pin.flushed = false;
+        if pin.wr.len() > pin.high_watermark && !*pin.congested.borrow() {
+            log::trace!(
+                "write buffer over the high watermark: {} bytes",
+                pin.wr.len()
+            );
+            pin.congested.send_replace(true);
+        }
 
         Ok(())
     }
 
-    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        if self.flushed {
-            return Poll::Ready(Ok(()));
-        }
-
-        let Self {
-            ref mut port,
-            ref mut wr,
-            ..
-        } = *self;
-
-        let pinned = Pin::new(port);
-        let n = ready!(pinned.poll_write(cx, &wr))?;
-
-        let wrote_all = n == self.wr.len();
-        self.wr.clear();
-        self.flushed = true;
-
-        let res = if wrote_all {
-            Ok(())
-        } else {
-            Err(io::Error::new(
-                io::ErrorKind::Other,
-                "failed to write entire datagram to socket",
-            )
-            .into())
-        };
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let pin = self.get_mut();
 
-        Poll::Ready(res)
+        ready!(pin.poll_write_until(cx, 0))?;
+        ready!(Pin::new(&mut pin.port).poll_flush(cx))?;
+        Poll::Ready(Ok(()))
     }
 
     fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
@@ -145,22 +167,79 @@ impl<I, C: Encoder<I> + Unpin> Sink<I> for SerialFramed<C> {
     }
 }
 
+impl<C: Send> ShutdownLayered for SerialFramed<C> {
+    /// Writes the frames still buffered.
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(futures::future::poll_fn(move |cx| {
+            self.poll_write_until(cx, 0)
+        }))
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        Some(&mut self.port)
+    }
+}
+
 impl<C> SerialFramed<C> {
     /// Create a new `SerialFramed` backed by the given socket and codec.
     ///
     /// See struct level documentation for more details.
-    #[allow(dead_code)]
     pub fn new(port: SerialStream, codec: C) -> SerialFramed<C> {
+        Self::with_capacity(port, codec, INITIAL_RD_CAPACITY)
+    }
+
+    /// Create a new `SerialFramed` reading up to `capacity` bytes at once, 64 KiB
+    /// for [`new`](SerialFramed::new).
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is 0.
+    pub fn with_capacity(port: SerialStream, codec: C, capacity: usize) -> SerialFramed<C> {
+        assert!(capacity > 0, "the read buffer cannot be empty");
         Self {
             port,
             codec,
-            rd: BytesMut::with_capacity(INITIAL_RD_CAPACITY),
+            rd: BytesMut::with_capacity(capacity),
+            rd_capacity: capacity,
             wr: BytesMut::with_capacity(INITIAL_WR_CAPACITY),
-            flushed: true,
-            is_readable: false,
+            low_watermark: 0,
+            high_watermark: 0,
+            congested: watch::channel(false).0,
         }
     }
 
+    /// Set the write buffer watermarks, in bytes.
+    ///
+    /// The sink becomes congested when more than `high` bytes are buffered, and
+    /// accepts frames again once at most `low` bytes are left.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `low` is greater than `high`.
+    pub fn set_write_watermarks(&mut self, low: usize, high: usize) {
+        assert!(low <= high, "low watermark above high watermark");
+        self.low_watermark = low;
+        self.high_watermark = high;
+    }
+
+    /// Returns the low and high write buffer watermarks.
+    pub fn write_watermarks(&self) -> (usize, usize) {
+        (self.low_watermark, self.high_watermark)
+    }
+
+    /// Watch the congestion state of the sink
+    ///
+    /// The value changes to `true` when a frame pushes the write buffer over the high
+    /// watermark, and back to `false` once the buffer drained to the low watermark.
+    pub fn congestion(&self) -> watch::Receiver<bool> {
+        self.congested.subscribe()
+    }
+
+    /// Returns the number of bytes waiting in the write buffer.
+    pub fn write_buffer_len(&self) -> usize {
+        self.wr.len()
+    }
+
     /// Returns a reference to the underlying I/O stream wrapped by `Framed`.
     ///
     /// # Note
@@ -168,7 +247,6 @@ impl<C> SerialFramed<C> {
     /// Care should be taken to not tamper with the underlying stream of data
     /// coming in as it may corrupt the stream of frames otherwise being worked
     /// with.
-    #[allow(dead_code)]
     pub fn get_ref(&self) -> &SerialStream {
         &self.port
     }
@@ -181,13 +259,11 @@ impl<C> SerialFramed<C> {
     /// Care should be taken to not tamper with the underlying stream of data
     /// coming in as it may corrupt the stream of frames otherwise being worked
     /// with.
-    #[allow(dead_code)]
     pub fn get_mut(&mut self) -> &mut SerialStream {
         &mut self.port
     }
 
     /// Consumes the `Framed`, returning its underlying I/O stream.
-    #[allow(dead_code)]
     pub fn into_inner(self) -> SerialStream {
         self.port
     }
@@ -197,7 +273,6 @@ impl<C> SerialFramed<C> {
     ///
     /// Note that care should be taken to not tamper with the underlying codec
     /// as it may corrupt the stream of frames otherwise being worked with.
-    #[allow(dead_code)]
     pub fn codec(&self) -> &C {
         &self.codec
     }
@@ -207,20 +282,54 @@ impl<C> SerialFramed<C> {
     ///
     /// Note that care should be taken to not tamper with the underlying codec
     /// as it may corrupt the stream of frames otherwise being worked with.
-    #[allow(dead_code)]
     pub fn codec_mut(&mut self) -> &mut C {
         &mut self.codec
     }
 
     /// Returns a reference to the read buffer.
-    #[allow(dead_code)]
     pub fn read_buffer(&self) -> &BytesMut {
         &self.rd
     }
 
     /// Returns a mutable reference to the read buffer.
-    #[allow(dead_code)]
     pub fn read_buffer_mut(&mut self) -> &mut BytesMut {
         &mut self.rd
     }
 }
+
+impl SerialStream {
+    /// Frame this port with `codec`, see [`SerialFramed`].
+    ///
+    /// ```no_run
+    /// use futures::StreamExt;
+    /// use tokio_serial::codec::SlcanCodec;
+    /// use tokio_serial::SerialStream;
+    ///
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let port = SerialStream::open(&tokio_serial::new("/dev/ttyACM0", 115_200))?;
+    /// let mut can = port.framed(SlcanCodec::new());
+    /// while let Some(frame) = can.next().await {
+    ///     println!("{:?}", frame?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn framed<C>(self, codec: C) -> SerialFramed<C> {
+        SerialFramed::new(self, codec)
+    }
+
+    /// Frame this port with `codec`, reading up to `capacity` bytes at once, see
+    /// [`SerialFramed::with_capacity`].
+    pub fn framed_with_capacity<C>(self, codec: C, capacity: usize) -> SerialFramed<C> {
+        SerialFramed::with_capacity(self, codec, capacity)
+    }
+
+    /// Split the data of this port into `\n` terminated lines of at most
+    /// [`DEFAULT_MAX_LINE_LENGTH`](crate::codec::DEFAULT_MAX_LINE_LENGTH) bytes.
+    ///
+    /// Use [`framed`](SerialStream::framed) with a [`LinesCodec`] of its own for
+    /// other delimiters or lengths.
+    pub fn lines(self) -> SerialFramed<LinesCodec> {
+        self.framed(LinesCodec::new(DEFAULT_MAX_LINE_LENGTH))
+    }
+}
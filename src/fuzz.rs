@@ -0,0 +1,319 @@
+//! Helpers for fuzzing protocol parsers
+//!
+//! The functions of this module are meant to be called from fuzz targets, e.g. with
+//! `cargo fuzz`:
+//!
+//! ```ignore
+//! #![no_main]
+//! libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+//!     tokio_serial::fuzz::decode(&mut my_protocol::Codec::default(), data);
+//! });
+//! ```
+//!
+//! * [`decode`] drives any `Decoder`, splitting the input across several reads to
+//!   exercise partial frames, and [`round_trip`] checks that framing a payload and
+//!   deframing it gives the payload back.
+//! * [`Corpus`] writes seed inputs to a corpus directory, starting from
+//!   [`SEEDS`], frames taken from the test vectors of the crate's codecs.
+//! * [`StreamFuzzer`] feeds mutated device output to a protocol handler through a
+//!   [mock port](crate::mock), without a coverage-guided fuzzer.
+use crate::codec::{Deframer, Framer};
+use crate::mock::{BusEndpoint, MockBus};
+
+use bytes::BytesMut;
+use tokio::io::AsyncWriteExt;
+use tokio_util::codec::Decoder;
+
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Inputs worth starting a corpus from
+///
+/// Frames of the test vectors of the crate's own tests, one or two per protocol,
+/// plus the CRC check input.
+pub const SEEDS: &[&[u8]] = &[
+    b"",
+    b"123456789",
+    // SLIP, HDLC, PPP, COBS and DLE framing
+    b"\xc0\x01\xdb\xdc\xdb\xdd\x02\xc0",
+    b"\x7e123456789\x26\x39\xf4\xcb\x7e",
+    b"\x7e\xff\x7d\x23\xc0\x21\x7d\x5d\xf3\x87\x7e",
+    b"\x03\x11\x22\x02\x33\x00",
+    b"\x10\x02\x01\x10\x10\x02\x10\x10\x10\x03",
+    // Modbus RTU: read holding registers 0 to 9 of slave 1
+    b"\x01\x03\x00\x00\x00\x0a\xc5\xcd",
+    // NMEA 0183
+    b"$GPGGA,123519,,N*39\r\n",
+    // MAVLink v1 and v2 heartbeats
+    b"\xfe\x09\x07\x01\x01\x00\x00\x00\x00\x00\x02\x03\x51\x04\x03\xfa\xad",
+    b"\xfd\x09\x00\x00\x07\x01\x01\x00\x00\x00\x00\x00\x00\x00\x02\x03\x51\x04\x03\xa4\xac",
+    // XBee API: AT command NJ
+    b"\x7e\x00\x04\x08\x52\x4e\x4a\x0d",
+    // SLCAN standard frame
+    b"t1234A1B2C3D4\r",
+    // AT modem response with an unsolicited result code
+    b"AT+CSQ\r\r\n+CSQ: 20,99\r\n\r\nRING\r\n\r\nOK\r\n",
+];
+
+/// Feed `data` to `decoder` as a stream, ignoring decoding errors
+///
+/// The first byte of `data` selects how the rest is split into reads, so a fuzzer
+/// explores partial frames as well as frame contents.  Errors are expected from
+/// malformed input; panics, hangs and excessive allocations are what fuzzing
+/// looks for.
+pub fn decode<D: Decoder>(decoder: &mut D, data: &[u8]) {
+    let (split, data) = match data.split_first() {
+        Some((&split, data)) => (usize::from(split) + 1, data),
+        None => return,
+    };
+
+    let mut src = BytesMut::new();
+    for chunk in data.chunks(split) {
+        src.extend_from_slice(chunk);
+        loop {
+            match decoder.decode(&mut src) {
+                Ok(Some(_)) => {}
+                Ok(None) => break,
+                Err(_) => return,
+            }
+        }
+    }
+    while let Ok(Some(_)) = decoder.decode_eof(&mut src) {}
+}
+
+/// Frame `payload` with `codec` and check that deframing gives it back
+///
+/// Payloads the framer rejects are skipped.
+///
+/// # Panics
+///
+/// If the frame is not decoded, or decoded to something else than `payload`.
+pub fn round_trip<T: Deframer + Framer>(codec: &mut T, payload: &[u8]) {
+    let mut frame = Vec::new();
+    if codec
+        .frame(payload, |bytes| frame.extend_from_slice(bytes))
+        .is_err()
+    {
+        return;
+    }
+
+    codec.reset();
+    let mut decoded = None;
+    for &byte in &frame {
+        if let Ok(Some(out)) = codec.push(byte) {
+            assert!(decoded.is_none(), "frame {:?} decoded twice", frame);
+            decoded = Some(out.to_vec());
+        }
+    }
+    assert_eq!(
+        decoded.as_deref(),
+        Some(payload),
+        "frame {:?} decoded incorrectly",
+        frame
+    );
+}
+
+/// A corpus directory for a fuzz target
+#[derive(Debug, Clone, Default)]
+pub struct Corpus {
+    inputs: Vec<Vec<u8>>,
+}
+
+impl Corpus {
+    /// A corpus containing [`SEEDS`]
+    pub fn new() -> Self {
+        Self::default().seeds(SEEDS.iter().copied())
+    }
+
+    /// Add an input.
+    pub fn seed<B: Into<Vec<u8>>>(mut self, input: B) -> Self {
+        self.inputs.push(input.into());
+        self
+    }
+
+    /// Add several inputs.
+    pub fn seeds<I, B>(mut self, inputs: I) -> Self
+    where
+        I: IntoIterator<Item = B>,
+        B: Into<Vec<u8>>,
+    {
+        self.inputs.extend(inputs.into_iter().map(Into::into));
+        self
+    }
+
+    /// Returns the inputs.
+    pub fn inputs(&self) -> &[Vec<u8>] {
+        &self.inputs
+    }
+
+    /// Write every input to its own file in `dir`, creating it if needed.
+    ///
+    /// Files are named after a hash of their content, as fuzzers do, so writing the
+    /// same corpus again does not duplicate it.
+    pub fn write_to<P: AsRef<Path>>(&self, dir: P) -> io::Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        for input in &self.inputs {
+            let mut hasher = DefaultHasher::new();
+            input.hash(&mut hasher);
+            std::fs::write(dir.join(format!("seed-{:016x}", hasher.finish())), input)?;
+        }
+        Ok(())
+    }
+}
+
+/// Statistics of a [`StreamFuzzer`] run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FuzzReport {
+    /// Number of inputs fed to the handler
+    pub iterations: usize,
+    /// Number of inputs the handler had not finished with before the timeout
+    pub timeouts: usize,
+}
+
+/// A mutation-based fuzzer feeding a protocol handler over a mock port
+///
+/// Every iteration mutates one of the seeds (flipping bits, inserting, removing,
+/// duplicating or truncating bytes), connects a fresh [`BusEndpoint`] to a mock
+/// device and writes the mutated input to it in randomly sized chunks.  The handler
+/// owns the endpoint and is given a timeout to finish; panics propagate, so run it
+/// from a test.
+///
+/// The mutations only depend on the seed passed to [`new`](StreamFuzzer::new),
+/// inputs are logged at debug level to reproduce failures.
+#[derive(Debug, Clone)]
+pub struct StreamFuzzer {
+    state: u64,
+    timeout: Duration,
+}
+
+impl StreamFuzzer {
+    /// A fuzzer with the given random seed
+    pub fn new(seed: u64) -> Self {
+        Self {
+            // xorshift gets stuck at 0
+            state: seed | 1,
+            timeout: Duration::from_millis(100),
+        }
+    }
+
+    /// Set how long the handler is given with each input, 100ms by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+
+    /// Returns a mutation of `input`.
+    pub fn mutate(&mut self, input: &[u8]) -> Vec<u8> {
+        let mut data = input.to_vec();
+        for _ in 0..=self.below(4) {
+            let at = self.below(data.len() + 1);
+            match self.below(5) {
+                0 if !data.is_empty() => {
+                    let at = at.min(data.len() - 1);
+                    data[at] ^= 1 << self.below(8);
+                }
+                1 => data.insert(at, self.next() as u8),
+                2 if !data.is_empty() => {
+                    data.remove(at.min(data.len() - 1));
+                }
+                3 => {
+                    let end = (at + 1 + self.below(8)).min(data.len());
+                    let chunk = data[at..end].to_vec();
+                    data.splice(at..at, chunk);
+                }
+                4 => data.truncate(at),
+                _ => data.push(self.next() as u8),
+            }
+        }
+        data
+    }
+
+    /// Feed `iterations` mutated seeds to `handler`.
+    ///
+    /// Seeds are used in turn, without seeds the mutations start from empty inputs.
+    pub async fn run<P, F, Fut>(
+        &mut self,
+        seeds: &[P],
+        iterations: usize,
+        mut handler: F,
+    ) -> FuzzReport
+    where
+        P: AsRef<[u8]>,
+        F: FnMut(BusEndpoint) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let mut report = FuzzReport::default();
+        for i in 0..iterations {
+            let seed = seeds
+                .get(i % seeds.len().max(1))
+                .map_or(&[][..], AsRef::as_ref);
+            let input = self.mutate(seed);
+            log::debug!("fuzz input {}: {:?}", i, input);
+
+            let mut splits = Vec::new();
+            let mut rest = input.len();
+            while rest > 0 {
+                let n = 1 + self.below(rest);
+                splits.push(n);
+                rest -= n;
+            }
+
+            let bus = MockBus::new();
+            let mut device = bus.endpoint();
+            let port = bus.endpoint();
+            let feed = async {
+                let mut at = 0;
+                for n in splits {
+                    // Writes to the mock bus always complete.
+                    let _ = device.write_all(&input[at..at + n]).await;
+                    at += n;
+                    YieldNow(false).await;
+                }
+            };
+            let handle = tokio::time::timeout(self.timeout, handler(port));
+            let ((), handled) = futures::join!(feed, handle);
+
+            report.iterations += 1;
+            if handled.is_err() {
+                report.timeouts += 1;
+            }
+        }
+        report
+    }
+}
+
+// Gives the handler a chance to read between chunks, without depending on a
+// particular runtime.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
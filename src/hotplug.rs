@@ -0,0 +1,331 @@
+//! Platform backends of [`watch_ports`](crate::inventory::watch_ports)
+#[cfg(windows)]
+pub(crate) use cfgmgr::Monitor;
+#[cfg(target_os = "linux")]
+pub(crate) use udev::{parse as parse_udev_message, Monitor};
+
+#[cfg(target_os = "linux")]
+mod udev {
+    use crate::inventory::PortEvent;
+    use crate::{SerialPortInfo, SerialPortType, UsbPortInfo};
+
+    use futures::ready;
+    use tokio::io::unix::AsyncFd;
+
+    use std::collections::HashMap;
+    use std::convert::TryInto;
+    use std::io;
+    use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+    use std::task::{Context, Poll};
+
+    // The multicast group udev rebroadcasts kernel events on once its rules ran.
+    const UDEV_MONITOR_GROUP: u32 = 2;
+    // `magic` of `struct udev_monitor_netlink_header`, in network byte order.
+    const UDEV_MONITOR_MAGIC: u32 = 0xfeed_cafe;
+
+    /// A netlink socket receiving udev events
+    #[derive(Debug)]
+    pub(crate) struct Monitor {
+        socket: AsyncFd<OwnedFd>,
+    }
+
+    impl Monitor {
+        pub(crate) fn new() -> io::Result<Self> {
+            let fd = unsafe {
+                libc::socket(
+                    libc::AF_NETLINK,
+                    libc::SOCK_RAW | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+                    libc::NETLINK_KOBJECT_UEVENT,
+                )
+            };
+            if fd == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+            let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+            addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+            addr.nl_groups = UDEV_MONITOR_GROUP;
+            let ret = unsafe {
+                libc::bind(
+                    socket.as_raw_fd(),
+                    &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+                )
+            };
+            if ret == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(Self {
+                socket: AsyncFd::new(socket)?,
+            })
+        }
+
+        pub(crate) fn poll_event(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<PortEvent>> {
+            let mut buf = [0u8; 8192];
+            loop {
+                let mut guard = ready!(self.socket.poll_read_ready(cx))?;
+                let received = guard.try_io(|socket| {
+                    let n = unsafe {
+                        libc::recv(
+                            socket.as_raw_fd(),
+                            buf.as_mut_ptr() as *mut libc::c_void,
+                            buf.len(),
+                            0,
+                        )
+                    };
+                    if n == -1 {
+                        Err(io::Error::last_os_error())
+                    } else {
+                        Ok(n as usize)
+                    }
+                });
+                match received {
+                    Ok(Ok(n)) => {
+                        if let Some(event) = parse(&buf[..n]) {
+                            return Poll::Ready(Ok(event));
+                        }
+                    }
+                    Ok(Err(e)) => return Poll::Ready(Err(e)),
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+    }
+
+    // Decode a udev message into an event about a serial port.
+    pub(crate) fn parse(message: &[u8]) -> Option<PortEvent> {
+        // `struct udev_monitor_netlink_header` from libudev
+        let field = |at: usize| -> Option<u32> {
+            let bytes = message.get(at..at + 4)?;
+            Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+        };
+        if !message.starts_with(b"libudev\0")
+            || field(8).map(u32::from_be) != Some(UDEV_MONITOR_MAGIC)
+        {
+            return None;
+        }
+        let offset = field(16)? as usize;
+        let len = field(20)? as usize;
+        let properties: HashMap<&str, &str> = message
+            .get(offset..offset.checked_add(len)?)?
+            .split(|&b| b == 0)
+            .filter_map(|pair| std::str::from_utf8(pair).ok()?.split_once('='))
+            .collect();
+
+        if properties.get("SUBSYSTEM") != Some(&"tty")
+            || properties.get("DEVPATH")?.contains("/virtual/")
+        {
+            return None;
+        }
+        let port = port_info(&properties)?;
+        match *properties.get("ACTION")? {
+            "add" => Some(PortEvent::Added(port)),
+            "remove" => Some(PortEvent::Removed(port)),
+            _ => None,
+        }
+    }
+
+    fn port_info(properties: &HashMap<&str, &str>) -> Option<SerialPortInfo> {
+        let port_name = properties.get("DEVNAME")?.to_string();
+        let text = |keys: &[&str]| {
+            keys.iter()
+                .find_map(|key| properties.get(key))
+                .map(|value| value.replace('_', " "))
+        };
+        let port_type = match properties.get("ID_BUS").copied() {
+            Some("usb") => SerialPortType::UsbPort(UsbPortInfo {
+                vid: u16::from_str_radix(properties.get("ID_VENDOR_ID")?, 16).ok()?,
+                pid: u16::from_str_radix(properties.get("ID_MODEL_ID")?, 16).ok()?,
+                serial_number: properties.get("ID_SERIAL_SHORT").map(|s| s.to_string()),
+                manufacturer: text(&["ID_VENDOR_FROM_DATABASE", "ID_VENDOR"]),
+                product: text(&["ID_MODEL_FROM_DATABASE", "ID_MODEL"]),
+                interface: properties
+                    .get("ID_USB_INTERFACE_NUM")
+                    .and_then(|number| u8::from_str_radix(number, 16).ok()),
+            }),
+            Some("pci") => SerialPortType::PciPort,
+            _ if port_name.contains("rfcomm") => SerialPortType::BluetoothPort,
+            _ => SerialPortType::Unknown,
+        };
+        Some(SerialPortInfo {
+            port_name,
+            port_type,
+        })
+    }
+}
+
+#[cfg(windows)]
+mod cfgmgr {
+    use crate::inventory::PortEvent;
+    use crate::SerialPortInfo;
+
+    use windows_sys::core::GUID;
+    use windows_sys::Win32::Devices::DeviceAndDriverInstallation::{
+        CM_Register_Notification, CM_Unregister_Notification, CM_NOTIFY_ACTION,
+        CM_NOTIFY_EVENT_DATA, CM_NOTIFY_FILTER, CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE, CR_SUCCESS,
+        HCMNOTIFICATION,
+    };
+
+    use std::collections::VecDeque;
+    use std::io;
+    use std::sync::{Arc, Mutex, MutexGuard};
+    use std::task::{Context, Poll, Waker};
+
+    // `GUID_DEVINTERFACE_COMPORT` from <ntddser.h>
+    const GUID_DEVINTERFACE_COMPORT: GUID = GUID::from_u128(0x86e0d1e0_8089_11d0_9ce4_08003e301f73);
+
+    #[derive(Debug, Default)]
+    struct State {
+        // The last enumeration, `None` until the initial one is done
+        ports: Option<Vec<SerialPortInfo>>,
+        events: VecDeque<PortEvent>,
+        waker: Option<Waker>,
+        // Enumerations started and the latest one applied, callbacks run
+        // concurrently on the thread pool and may finish out of order
+        started: u64,
+        applied: u64,
+        // Whether a change was notified before the initial enumeration was done
+        missed: bool,
+    }
+
+    fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+        state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Enumerate the ports again, queueing what changed.  Enumerating takes a while,
+    // the lock is not held meanwhile so events can still be taken.
+    fn refresh(state: &Mutex<State>) {
+        let started = {
+            let mut state = lock(state);
+            if state.ports.is_none() {
+                state.missed = true;
+                return;
+            }
+            state.started += 1;
+            state.started
+        };
+        let ports = match crate::available_ports() {
+            Ok(ports) => ports,
+            Err(e) => {
+                log::warn!("unable to enumerate ports after a device change: {}", e);
+                return;
+            }
+        };
+
+        let mut state = lock(state);
+        if started <= state.applied {
+            // A more recent enumeration was applied already.
+            return;
+        }
+        state.applied = started;
+        let old = state.ports.replace(ports).unwrap_or_default();
+        let State { ports, events, .. } = &mut *state;
+        let ports = ports.as_deref().unwrap_or_default();
+        for port in ports {
+            if !old.iter().any(|old| old.port_name == port.port_name) {
+                events.push_back(PortEvent::Added(port.clone()));
+            }
+        }
+        for old in old {
+            if !ports.iter().any(|port| port.port_name == old.port_name) {
+                events.push_back(PortEvent::Removed(old));
+            }
+        }
+        if !state.events.is_empty() {
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// A registration for COM port interface arrivals and removals
+    ///
+    /// The configuration manager calls back on a thread pool thread, which
+    /// enumerates the ports and diffs them with the previous enumeration to tell
+    /// the COM names and USB ids of the ports which came and went.
+    #[derive(Debug)]
+    pub(crate) struct Monitor {
+        notification: HCMNOTIFICATION,
+        state: Arc<Mutex<State>>,
+    }
+
+    // The notification handle is only used to unregister.
+    unsafe impl Send for Monitor {}
+    unsafe impl Sync for Monitor {}
+
+    unsafe extern "system" fn notify(
+        _notification: HCMNOTIFICATION,
+        context: *const core::ffi::c_void,
+        _action: CM_NOTIFY_ACTION,
+        _data: *const CM_NOTIFY_EVENT_DATA,
+        _size: u32,
+    ) -> u32 {
+        refresh(unsafe { &*(context as *const Mutex<State>) });
+        0
+    }
+
+    impl Monitor {
+        pub(crate) fn new() -> io::Result<Self> {
+            // Register before enumerating, so no port plugged in meanwhile is missed.
+            let state = Arc::new(Mutex::new(State::default()));
+            let mut filter = CM_NOTIFY_FILTER {
+                cbSize: std::mem::size_of::<CM_NOTIFY_FILTER>() as u32,
+                FilterType: CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE,
+                ..CM_NOTIFY_FILTER::default()
+            };
+            filter.u.DeviceInterface.ClassGuid = GUID_DEVINTERFACE_COMPORT;
+            let mut notification = std::ptr::null_mut();
+            // The state outlives the registration: `drop` unregisters before
+            // releasing it, which waits for running callbacks.
+            let ret = unsafe {
+                CM_Register_Notification(
+                    &filter,
+                    Arc::as_ptr(&state) as *const core::ffi::c_void,
+                    Some(notify),
+                    &mut notification,
+                )
+            };
+            if ret != CR_SUCCESS {
+                return Err(io::Error::other(format!(
+                    "CM_Register_Notification failed with CONFIGRET {}",
+                    ret
+                )));
+            }
+
+            let monitor = Self {
+                notification,
+                state,
+            };
+
+            let ports = crate::available_ports()?;
+            let missed = {
+                let mut state = lock(&monitor.state);
+                state.ports = Some(ports);
+                std::mem::take(&mut state.missed)
+            };
+            if missed {
+                refresh(&monitor.state);
+            }
+            Ok(monitor)
+        }
+
+        pub(crate) fn poll_event(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<PortEvent>> {
+            let mut state = lock(&self.state);
+            match state.events.pop_front() {
+                Some(event) => Poll::Ready(Ok(event)),
+                None => {
+                    state.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    impl Drop for Monitor {
+        fn drop(&mut self) {
+            unsafe { CM_Unregister_Notification(self.notification) };
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Find out which device is attached to a port
+//!
+//! Gateways and test rigs often accept one of several device types on the same
+//! port.  An [`Identifier`] holds a list of known devices, each described by
+//! [`Probe`]s: a command to send and the [`Signature`] expected in the reply.
+//! [`Identifier::identify`] runs the probes in registration order and returns the
+//! first device whose probes all match, along with whatever configuration was
+//! registered for it.
+//!
+//! ```no_run
+//! use tokio_serial::identify::{Identifier, Probe, Signature};
+//!
+//! # async fn example() -> tokio_serial::Result<()> {
+//! let identifier = Identifier::new()
+//!     .device("sim800", vec![Probe::new(b"AT+CGMM\r\n", Signature::contains(b"SIM800"))])
+//!     .device("quectel", vec![Probe::new(b"ATI\r\n", Signature::contains(b"Quectel"))]);
+//!
+//! let (port, device) = identifier.open(&tokio_serial::new("/dev/ttyUSB0", 115_200)).await?;
+//! println!("found {:?}", device);
+//! # Ok(())
+//! # }
+//! ```
+use crate::AsyncSerialPort;
+
+use futures::FutureExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long a probe waits for a matching reply unless specified otherwise.
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Replies longer than this cannot come from a device answering the probe.
+const MAX_REPLY_LEN: usize = 4096;
+
+/// A predicate matching replies, see [`Signature::custom`]
+pub type ReplyPredicate = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+/// A pattern expected in the reply to a probe
+#[derive(Clone)]
+pub enum Signature {
+    /// The reply contains these bytes
+    Contains(Vec<u8>),
+    /// The reply starts with these bytes, ignoring leading whitespace
+    StartsWith(Vec<u8>),
+    /// The predicate returns `true` for the reply received so far
+    Custom(ReplyPredicate),
+}
+
+impl Signature {
+    /// A reply containing `pattern`
+    pub fn contains<B: Into<Vec<u8>>>(pattern: B) -> Self {
+        Signature::Contains(pattern.into())
+    }
+
+    /// A reply starting with `pattern`, ignoring leading whitespace
+    pub fn starts_with<B: Into<Vec<u8>>>(pattern: B) -> Self {
+        Signature::StartsWith(pattern.into())
+    }
+
+    /// A reply accepted by `predicate`
+    ///
+    /// The predicate is called every time more of the reply arrives.
+    pub fn custom<F>(predicate: F) -> Self
+    where
+        F: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        Signature::Custom(Arc::new(predicate))
+    }
+
+    /// Returns whether `reply` matches the signature.
+    pub fn matches(&self, reply: &[u8]) -> bool {
+        match self {
+            Signature::Contains(pattern) => {
+                pattern.is_empty() || reply.windows(pattern.len()).any(|w| w == &pattern[..])
+            }
+            Signature::StartsWith(pattern) => {
+                let start = reply
+                    .iter()
+                    .position(|b| !b.is_ascii_whitespace())
+                    .unwrap_or(reply.len());
+                reply[start..].starts_with(pattern)
+            }
+            Signature::Custom(predicate) => predicate(reply),
+        }
+    }
+}
+
+impl fmt::Debug for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Signature::Contains(pattern) => f.debug_tuple("Contains").field(pattern).finish(),
+            Signature::StartsWith(pattern) => f.debug_tuple("StartsWith").field(pattern).finish(),
+            Signature::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// A command sent to the port and the reply expected from a given device
+#[derive(Debug, Clone)]
+pub struct Probe {
+    command: Vec<u8>,
+    signature: Signature,
+    timeout: Duration,
+}
+
+impl Probe {
+    /// Send `command` and expect a reply matching `signature`.
+    pub fn new<B: Into<Vec<u8>>>(command: B, signature: Signature) -> Self {
+        Self {
+            command: command.into(),
+            signature,
+            timeout: DEFAULT_PROBE_TIMEOUT,
+        }
+    }
+
+    /// Set how long to wait for a matching reply
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send the command and check the reply.
+    ///
+    /// Input still pending from previous exchanges is discarded first.
+    pub async fn run<P: AsyncSerialPort>(&self, port: &mut P) -> crate::Result<bool> {
+        let mut buf = [0u8; 256];
+        while let Some(read) = port.read(&mut buf).now_or_never() {
+            if read? == 0 {
+                break;
+            }
+        }
+
+        port.write_all(&self.command).await?;
+        port.flush().await?;
+
+        let mut reply = Vec::new();
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        while reply.len() < MAX_REPLY_LEN {
+            let read = match tokio::time::timeout_at(deadline, port.read(&mut buf)).await {
+                Ok(read) => read?,
+                Err(_) => break,
+            };
+            if read == 0 {
+                break;
+            }
+            reply.extend_from_slice(&buf[..read]);
+            if self.signature.matches(&reply) {
+                return Ok(true);
+            }
+        }
+        log::trace!("probe {:?} got {:?}", self.command, reply);
+        Ok(false)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Known<T> {
+    device: T,
+    probes: Vec<Probe>,
+}
+
+/// A list of known devices and how to recognize them
+///
+/// `T` is whatever should be known about a device once it is identified: a name, an
+/// enum, or the configuration to use for it.
+#[derive(Debug, Clone)]
+pub struct Identifier<T> {
+    known: Vec<Known<T>>,
+}
+
+impl<T> Default for Identifier<T> {
+    fn default() -> Self {
+        Self { known: Vec::new() }
+    }
+}
+
+impl<T> Identifier<T> {
+    /// An identifier without known devices
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `device`, recognized by all of `probes` matching
+    ///
+    /// Devices are tried in registration order, register more specific devices first
+    /// when several answer the same probe.
+    pub fn device<I>(mut self, device: T, probes: I) -> Self
+    where
+        I: IntoIterator<Item = Probe>,
+    {
+        self.known.push(Known {
+            device,
+            probes: probes.into_iter().collect(),
+        });
+        self
+    }
+
+    /// Run the probes on `port`, returning the first device they all match.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io` for any error while talking to the port.  Not getting a (matching) reply
+    ///   is not an error, [`None`] is returned if no device matches.
+    pub async fn identify<P: AsyncSerialPort>(&self, port: &mut P) -> crate::Result<Option<&T>> {
+        'devices: for known in &self.known {
+            for probe in &known.probes {
+                if !probe.run(port).await? {
+                    continue 'devices;
+                }
+            }
+            return Ok(Some(&known.device));
+        }
+        Ok(None)
+    }
+
+    /// Open the port described by `builder` and identify the attached device.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn open(
+        &self,
+        builder: &crate::SerialPortBuilder,
+    ) -> crate::Result<(crate::SerialStream, Option<&T>)> {
+        let mut port = crate::SerialStream::open(builder)?;
+        let device = self.identify(&mut port).await?;
+        Ok((port, device))
+    }
+}
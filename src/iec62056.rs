@@ -0,0 +1,322 @@
+//! IEC 62056-21 meter readout, mode C
+//!
+//! Electricity, gas and heat meters with an optical port answer IEC 62056-21,
+//! formerly IEC 61107.  The reader starts every session at 300 baud, 7E1, with a
+//! sign-on request, `/?!` followed by CR LF.  The meter replies with its
+//! identification, `/XXXZident`, where `XXX` is the manufacturer and `Z` the
+//! fastest baud rate it offers.  In mode C the reader acknowledges with
+//! `ACK 0 Z 0`, asking for a data readout at baud rate `Z`; both ends then switch
+//! to that rate, and the meter sends its data message: `STX`, the data sets,
+//! `!`, `ETX` and a block check character.
+//!
+//! The switch happens in the middle of the session: the acknowledgement has to
+//! leave at 300 baud, and the port has to be at the new rate before the meter,
+//! which waits at least 200ms, starts sending.  [`Meter`] waits for the
+//! acknowledgement to be transmitted before changing the rate.
+//!
+//! ```no_run
+//! use tokio_serial::iec62056::{self, Meter};
+//! use tokio_serial::SerialStream;
+//!
+//! # async fn example() -> tokio_serial::Result<()> {
+//! let builder = tokio_serial::new("/dev/ttyUSB0", iec62056::INITIAL_BAUD_RATE);
+//! let port = SerialStream::open(&builder)?;
+//! let mut meter = Meter::new(port);
+//! let readout = meter.read_out().await?;
+//! for data_set in &readout.data {
+//!     println!("{} = {} {}", data_set.address, data_set.value, data_set.unit.as_deref().unwrap_or(""));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::delay::wait;
+use crate::{ClearBuffer, DataBits, ErrorKind, Parity, SerialPort, SerialSettings, SerialStream};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use std::io;
+use std::time::Duration;
+
+/// The baud rate sessions start at
+pub const INITIAL_BAUD_RATE: u32 = 300;
+
+/// Starts the data message
+pub const STX: u8 = 0x02;
+/// Ends the data message, followed by the block check character
+pub const ETX: u8 = 0x03;
+/// Acknowledges the identification and selects the baud rate
+pub const ACK: u8 = 0x06;
+
+// The longest identification message, `/XXXZ` and 16 characters, with slack for
+// meters exceeding it.
+const MAX_IDENTIFICATION: usize = 64;
+
+// Bounds the data message read from a line sending garbage.
+const MAX_DATA_MESSAGE: usize = 64 * 1024;
+
+/// The settings sessions start with: 300 baud, 7 data bits, even parity, one stop bit
+pub fn settings() -> SerialSettings {
+    SerialSettings {
+        data_bits: DataBits::Seven,
+        parity: Parity::Even,
+        ..SerialSettings::new(INITIAL_BAUD_RATE)
+    }
+}
+
+/// The mode C baud rate of identification character `code`, `'0'` to `'6'`.
+pub fn baud_rate(code: u8) -> Option<u32> {
+    match code {
+        b'0'..=b'6' => Some(300 << (code - b'0')),
+        _ => None,
+    }
+}
+
+/// The block check character of a data message: the XOR of the bytes after `STX`,
+/// `ETX` included.
+pub fn bcc(data: &[u8]) -> u8 {
+    data.iter().fold(0, |bcc, &byte| bcc ^ byte)
+}
+
+/// The identification message of a meter
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Identification {
+    /// The three letter code of the manufacturer, as registered with the FLAG
+    /// association
+    pub manufacturer: String,
+    /// The fastest baud rate the meter offers
+    pub baud_rate: u32,
+    /// The identification of the meter, model and often firmware version
+    pub identification: String,
+}
+
+/// A data set of a readout, such as `1.8.0(012345.6*kWh)`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DataSet {
+    /// The address of the value, usually an OBIS code such as `1.8.0`
+    ///
+    /// Data sets following another one on the same line without an address, such
+    /// as the second value of `1.8.0(1)(2)`, have an empty address.
+    pub address: String,
+    /// The value, as sent
+    pub value: String,
+    /// The unit of the value, if any
+    pub unit: Option<String>,
+}
+
+/// A data readout
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Readout {
+    /// The identification the meter answered the sign-on with
+    pub identification: Identification,
+    /// The data sets of the data message, in order
+    pub data: Vec<DataSet>,
+}
+
+fn invalid_data(message: &str) -> crate::Error {
+    crate::Error::new(ErrorKind::Io(io::ErrorKind::InvalidData), message)
+}
+
+fn parse_identification(line: &[u8]) -> crate::Result<Identification> {
+    let line = std::str::from_utf8(line)
+        .ok()
+        .and_then(|line| line.strip_prefix('/'))
+        .filter(|line| line.len() >= 4 && line.is_char_boundary(4))
+        .ok_or_else(|| invalid_data("invalid IEC 62056-21 identification"))?;
+    let baud_rate = baud_rate(line.as_bytes()[3])
+        .ok_or_else(|| invalid_data("meter does not offer IEC 62056-21 mode C"))?;
+    Ok(Identification {
+        manufacturer: line[..3].to_owned(),
+        baud_rate,
+        identification: line[4..].to_owned(),
+    })
+}
+
+fn parse_data(block: &[u8]) -> crate::Result<Vec<DataSet>> {
+    let block =
+        std::str::from_utf8(block).map_err(|_| invalid_data("invalid IEC 62056-21 data"))?;
+    let mut data = Vec::new();
+    for line in block.split("\r\n") {
+        if line == "!" {
+            break;
+        }
+        let mut rest = line;
+        while let Some(open) = rest.find('(') {
+            let close = rest[open..]
+                .find(')')
+                .ok_or_else(|| invalid_data("unterminated IEC 62056-21 data set"))?;
+            let (value, unit) = match rest[open + 1..open + close].split_once('*') {
+                Some((value, unit)) => (value, Some(unit.to_owned())),
+                None => (&rest[open + 1..open + close], None),
+            };
+            data.push(DataSet {
+                address: rest[..open].to_owned(),
+                value: value.to_owned(),
+                unit,
+            });
+            rest = &rest[open + close + 1..];
+        }
+    }
+    Ok(data)
+}
+
+/// A meter read out through an optical probe or a current loop
+///
+/// Each [`read_out`](Meter::read_out) is a full session: the port is set to the
+/// initial [`settings`], and left at the baud rate of the readout.  The fastest
+/// rate the meter offers is used, up to [`set_max_baud_rate`](Meter::set_max_baud_rate);
+/// optical probes are often only reliable up to 9600 baud.
+///
+/// Every byte is waited for at most 1.5s by default, the longest the standard
+/// lets a meter pause.
+#[derive(Debug)]
+pub struct Meter {
+    port: SerialStream,
+    address: String,
+    max_baud_rate: u32,
+    reaction_time: Duration,
+    timeout: Duration,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl Meter {
+    /// Read out the meter on the other end of `port`.
+    pub fn new(port: SerialStream) -> Self {
+        Self {
+            port,
+            address: String::new(),
+            max_baud_rate: 19200,
+            reaction_time: Duration::from_millis(200),
+            timeout: Duration::from_millis(1500),
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Set the address sent in the sign-on request, which selects one meter of
+    /// several on a bus; empty by default, which every meter answers.
+    pub fn set_address(&mut self, address: &str) {
+        self.address = address.to_owned();
+    }
+
+    /// Set the fastest baud rate to switch to, 19200 by default.
+    pub fn set_max_baud_rate(&mut self, baud_rate: u32) {
+        self.max_baud_rate = baud_rate;
+    }
+
+    /// Set how long to wait after the identification before acknowledging it, 200ms
+    /// by default, the least the standard allows.
+    pub fn set_reaction_time(&mut self, reaction_time: Duration) {
+        self.reaction_time = reaction_time;
+    }
+
+    /// Set how long to wait for each byte from the meter.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns a reference to the underlying port.
+    pub fn get_ref(&self) -> &SerialStream {
+        &self.port
+    }
+
+    /// Consumes the meter, returning the underlying port.
+    pub fn into_inner(self) -> SerialStream {
+        self.port
+    }
+
+    async fn next_byte(&mut self) -> crate::Result<u8> {
+        if self.pos == self.buf.len() {
+            self.buf.resize(256, 0);
+            let read = tokio::time::timeout(self.timeout, self.port.read(&mut self.buf));
+            let n = read.await.map_err(|_| {
+                crate::Error::new(
+                    ErrorKind::Io(io::ErrorKind::TimedOut),
+                    "no answer from the meter in time",
+                )
+            })??;
+            if n == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            self.buf.truncate(n);
+            self.pos = 0;
+        }
+        self.pos += 1;
+        Ok(self.buf[self.pos - 1])
+    }
+
+    /// Sign on, switch to the fastest baud rate both ends support and read the data
+    /// message.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io(TimedOut)` if the meter does not answer, or pauses for longer than the
+    ///   timeout.
+    /// * `Io(InvalidData)` if the identification is malformed or not mode C, or the
+    ///   block check character of the data message does not match.
+    pub async fn read_out(&mut self) -> crate::Result<Readout> {
+        self.port.apply_settings(&settings())?;
+        self.port.clear(ClearBuffer::Input)?;
+        self.buf.clear();
+        self.pos = 0;
+
+        let request = format!("/?{}!\r\n", self.address);
+        self.port.write_all(request.as_bytes()).await?;
+        self.port.flush().await?;
+
+        let mut line = Vec::new();
+        while !line.ends_with(b"\r\n") {
+            if line.len() == MAX_IDENTIFICATION {
+                return Err(invalid_data("IEC 62056-21 identification too long"));
+            }
+            let byte = self.next_byte().await?;
+            // Echoes of the request and noise from the probe before the reply.
+            if line.is_empty() && byte != b'/' {
+                continue;
+            }
+            line.push(byte);
+        }
+        line.truncate(line.len() - 2);
+        let identification = parse_identification(&line)?;
+        log::debug!("IEC 62056-21 identification {:?}", identification);
+
+        let code = (b'0'..=b'6')
+            .rev()
+            .find(|&code| {
+                let rate = baud_rate(code).unwrap_or(0);
+                rate <= identification.baud_rate && rate <= self.max_baud_rate
+            })
+            .unwrap_or(b'0');
+        wait(self.reaction_time).await;
+        self.port
+            .write_all(&[ACK, b'0', code, b'0', b'\r', b'\n'])
+            .await?;
+        self.port.flush().await?;
+        // The acknowledgement is sent at 300 baud, whatever the new rate.
+        self.port.wait_transmitted().await?;
+        if let Some(rate) = baud_rate(code).filter(|&rate| rate != INITIAL_BAUD_RATE) {
+            self.port.set_baud_rate(rate)?;
+        }
+
+        while self.next_byte().await? != STX {}
+        let mut block = Vec::new();
+        loop {
+            if block.len() == MAX_DATA_MESSAGE {
+                return Err(invalid_data("IEC 62056-21 data message too long"));
+            }
+            let byte = self.next_byte().await?;
+            block.push(byte);
+            if byte == ETX {
+                break;
+            }
+        }
+        if self.next_byte().await? != bcc(&block) {
+            return Err(invalid_data("IEC 62056-21 block check character mismatch"));
+        }
+        block.pop();
+        Ok(Readout {
+            identification,
+            data: parse_data(&block)?,
+        })
+    }
+}
@@ -0,0 +1,888 @@
+//! Keeping track of the ports available on the system
+//!
+//! Enumerating ports is not free, especially on Windows where every call walks the
+//! device tree.  A [`PortInventory`] keeps the result of the last enumeration around
+//! and tells what changed each time it is refreshed, either on demand with
+//! [`refresh`](PortInventory::refresh) or, with the `rt` feature, periodically with
+//! `PortInventory::changed`.
+//!
+//! The order in which the OS reports ports fluctuates between runs and machines.
+//! [`EnumerationOptions`] filters ports by kind or USB id and sorts them
+//! deterministically, by name unless specified otherwise.  To bind to one specific
+//! adapter, [`find_port`] looks it up by USB ids and serial number, and
+//! [`stable_path`] gives a name to store in configuration files.  [`wait_for_port`]
+//! waits for a device to show up, e.g. a board enumerating again after a reset.
+//!
+//! On Linux and Windows, [`watch_ports`] is notified by the OS as soon as a port
+//! appears or disappears, without polling.
+//!
+//! ```no_run
+//! use tokio_serial::inventory::PortInventory;
+//! use std::time::Duration;
+//!
+//! # #[cfg(feature = "rt")]
+//! # async fn example() -> tokio_serial::Result<()> {
+//! let mut inventory = PortInventory::new();
+//! inventory.refresh_async().await?;
+//! loop {
+//!     let changes = inventory.changed(Duration::from_secs(1)).await?;
+//!     for port in &changes.added {
+//!         println!("{} plugged in", port.port_name);
+//!     }
+//! }
+//! # }
+//! ```
+use crate::{SerialPortBuilder, SerialPortInfo, SerialPortType, SerialStream, UsbPortInfo};
+
+use std::cmp::Ordering;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How a port is connected, without the details of [`SerialPortType`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PortKind {
+    /// Connected via USB
+    Usb,
+    /// Connected via PCI (permanent port)
+    Pci,
+    /// Connected via Bluetooth
+    Bluetooth,
+    /// Connected in an unknown way
+    Unknown,
+}
+
+impl PortKind {
+    /// Returns how a port of type `port_type` is connected.
+    pub fn of(port_type: &SerialPortType) -> Self {
+        match port_type {
+            SerialPortType::UsbPort(_) => PortKind::Usb,
+            SerialPortType::PciPort => PortKind::Pci,
+            SerialPortType::BluetoothPort => PortKind::Bluetooth,
+            SerialPortType::Unknown => PortKind::Unknown,
+        }
+    }
+}
+
+/// The order of enumerated ports
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// The order reported by the OS, which may change between runs
+    Enumeration,
+    /// By name, with numbers compared by value so that `ttyUSB2` comes before
+    /// `ttyUSB10`
+    #[default]
+    Name,
+    /// By [kind](PortKind), then by name
+    Kind,
+    /// USB ports first by vendor id, product id and serial number, then the other
+    /// ports by name
+    UsbId,
+}
+
+/// Filtering and ordering of enumerated ports
+///
+/// Without any filter all ports are kept.  When several filters of the same kind
+/// are added, ports matching any of them are kept.
+///
+/// ```no_run
+/// use tokio_serial::inventory::EnumerationOptions;
+///
+/// # fn example() -> tokio_serial::Result<()> {
+/// // FTDI adapters, in the same order on every run
+/// let ports = EnumerationOptions::new().usb_vendor(0x0403).enumerate()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnumerationOptions {
+    kinds: Vec<PortKind>,
+    usb_ids: Vec<(u16, Option<u16>)>,
+    sort: SortOrder,
+}
+
+impl EnumerationOptions {
+    /// Keep all ports, sorted by name
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep ports of `kind`.
+    pub fn kind(mut self, kind: PortKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    /// Keep USB ports with vendor id `vid` and product id `pid`.
+    pub fn usb_id(mut self, vid: u16, pid: u16) -> Self {
+        self.usb_ids.push((vid, Some(pid)));
+        self
+    }
+
+    /// Keep USB ports with vendor id `vid`.
+    pub fn usb_vendor(mut self, vid: u16) -> Self {
+        self.usb_ids.push((vid, None));
+        self
+    }
+
+    /// Set the order of the ports
+    pub fn sort_by(mut self, order: SortOrder) -> Self {
+        self.sort = order;
+        self
+    }
+
+    /// Returns whether `port` passes the filters.
+    pub fn matches(&self, port: &SerialPortInfo) -> bool {
+        let kind = PortKind::of(&port.port_type);
+        if !self.kinds.is_empty() && !self.kinds.contains(&kind) {
+            return false;
+        }
+        if self.usb_ids.is_empty() {
+            return true;
+        }
+        match &port.port_type {
+            SerialPortType::UsbPort(usb) => self
+                .usb_ids
+                .iter()
+                .any(|&(vid, pid)| usb.vid == vid && pid.is_none_or(|pid| usb.pid == pid)),
+            _ => false,
+        }
+    }
+
+    /// Filter and sort `ports`.
+    pub fn apply(&self, mut ports: Vec<SerialPortInfo>) -> Vec<SerialPortInfo> {
+        ports.retain(|port| self.matches(port));
+        match self.sort {
+            SortOrder::Enumeration => {}
+            SortOrder::Name => ports.sort_by(|a, b| natural_cmp(&a.port_name, &b.port_name)),
+            SortOrder::Kind => ports.sort_by(|a, b| {
+                PortKind::of(&a.port_type)
+                    .cmp(&PortKind::of(&b.port_type))
+                    .then_with(|| natural_cmp(&a.port_name, &b.port_name))
+            }),
+            SortOrder::UsbId => ports.sort_by(|a, b| {
+                let id = |port: &SerialPortInfo| match &port.port_type {
+                    SerialPortType::UsbPort(usb) => {
+                        Some((usb.vid, usb.pid, usb.serial_number.clone()))
+                    }
+                    _ => None,
+                };
+                match (id(a), id(b)) {
+                    (Some(a_id), Some(b_id)) => a_id.cmp(&b_id),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                }
+                .then_with(|| natural_cmp(&a.port_name, &b.port_name))
+            }),
+        }
+        ports
+    }
+
+    /// Enumerate the available ports, filtered and sorted.
+    pub fn enumerate(&self) -> crate::Result<Vec<SerialPortInfo>> {
+        Ok(self.apply(crate::available_ports()?))
+    }
+
+    /// Enumerate the available ports with their [`details`], filtered and sorted.
+    pub fn enumerate_detailed(&self) -> crate::Result<Vec<PortDetails>> {
+        Ok(self.enumerate()?.into_iter().map(details).collect())
+    }
+}
+
+// Compare names, runs of digits by value.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let a_len = a.iter().take_while(|c| c.is_ascii_digit()).count();
+                let b_len = b.iter().take_while(|c| c.is_ascii_digit()).count();
+                let (a_num, b_num) = (&a[..a_len], &b[..b_len]);
+                let trim = |num: &[u8]| -> usize { num.iter().take_while(|&&c| c == b'0').count() };
+                let (a_val, b_val) = (&a_num[trim(a_num)..], &b_num[trim(b_num)..]);
+                let ordering = a_val
+                    .len()
+                    .cmp(&b_val.len())
+                    .then_with(|| a_val.cmp(b_val))
+                    .then_with(|| a_len.cmp(&b_len));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a = &a[a_len..];
+                b = &b[b_len..];
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y);
+                }
+                a = &a[1..];
+                b = &b[1..];
+            }
+        }
+    }
+}
+
+/// A port with the information device pickers show, see [`details`]
+///
+/// Fields the platform does not provide are [`None`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortDetails {
+    /// The port as enumerated
+    pub info: SerialPortInfo,
+    /// Name of the kernel driver, e.g. `ftdi_sio` or `cdc_acm`
+    pub driver: Option<String>,
+    /// Number of the USB interface the port belongs to, on composite devices
+    pub interface: Option<u8>,
+    /// Name of the USB interface (its string descriptor), e.g. `CMSIS-DAP` or
+    /// `Console`
+    pub interface_name: Option<String>,
+    /// Where the device sits on its bus: the USB bus and port chain such as
+    /// `1-1.2`, or the device path of other ports
+    pub location: Option<String>,
+    /// Whether a live process holds a UUCP lock file for the port in `/var/lock`,
+    /// which tools such as minicom or pppd create when they open it
+    ///
+    /// Only the lock file is looked at: checking for a `flock` or `TIOCEXCL` would
+    /// mean opening the port, which toggles the modem lines of most devices.  Lock
+    /// files being a Unix convention, this is [`None`] on other platforms.
+    pub uucp_locked: Option<bool>,
+}
+
+/// Gather the details of an enumerated port.
+///
+/// On Linux they are read from sysfs.  Other platforms only report whether the
+/// port is locked on Unix.
+pub fn details(info: SerialPortInfo) -> PortDetails {
+    #[cfg(target_os = "linux")]
+    {
+        details_from(info, Path::new("/sys"))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        bare_details(info)
+    }
+}
+
+fn bare_details(info: SerialPortInfo) -> PortDetails {
+    #[cfg(unix)]
+    let uucp_locked = Some(crate::lock::is_locked(&info.port_name));
+    #[cfg(not(unix))]
+    let uucp_locked = None;
+    let interface = match &info.port_type {
+        SerialPortType::UsbPort(usb) => usb.interface,
+        _ => None,
+    };
+
+    PortDetails {
+        info,
+        driver: None,
+        interface,
+        interface_name: None,
+        location: None,
+        uucp_locked,
+    }
+}
+
+/// Gather the details of an enumerated port from the sysfs mounted at `sysfs`,
+/// such as the one of the host bind-mounted into a container.
+///
+/// [`details`] reads them from `/sys`.
+#[cfg(target_os = "linux")]
+pub fn details_from(info: SerialPortInfo, sysfs: &Path) -> PortDetails {
+    let mut details = bare_details(info);
+    sysfs_details(&mut details, sysfs);
+    details
+}
+
+#[cfg(target_os = "linux")]
+fn sysfs_details(details: &mut PortDetails, sysfs: &Path) {
+    // Ports of symlinks such as `/dev/serial/by-id/...` are named after their
+    // target.
+    let path = Path::new(&details.info.port_name);
+    let name = match std::fs::canonicalize(path) {
+        Ok(path) => path.file_name().map(|name| name.to_owned()),
+        Err(_) => path.file_name().map(|name| name.to_owned()),
+    };
+    let name = match name {
+        Some(name) => name,
+        None => return,
+    };
+    let device = match std::fs::canonicalize(sysfs.join("class/tty").join(name).join("device")) {
+        Ok(device) => device,
+        Err(_) => return,
+    };
+
+    details.driver = std::fs::read_link(device.join("driver"))
+        .ok()
+        .and_then(|driver| Some(driver.file_name()?.to_string_lossy().into_owned()));
+    // USB serial converters put a port device below the interface, CDC-ACM ports
+    // are the interface itself.
+    let interface = device
+        .ancestors()
+        .take(2)
+        .find(|dir| dir.join("bInterfaceNumber").exists());
+    match interface {
+        Some(interface) => {
+            details.interface = std::fs::read_to_string(interface.join("bInterfaceNumber"))
+                .ok()
+                .and_then(|number| u8::from_str_radix(number.trim(), 16).ok())
+                .or(details.interface);
+            details.interface_name = std::fs::read_to_string(interface.join("interface"))
+                .ok()
+                .map(|name| name.trim().to_string());
+            // Interfaces are named `<bus>-<ports>:<configuration>.<interface>`.
+            details.location = interface
+                .file_name()
+                .and_then(|name| name.to_str()?.split(':').next().map(str::to_string));
+        }
+        None => {
+            let devices = std::fs::canonicalize(sysfs.join("devices"));
+            details.location = devices
+                .ok()
+                .and_then(|devices| device.strip_prefix(devices).ok())
+                .map(|path| path.display().to_string());
+        }
+    }
+}
+
+/// A port still present whose metadata changed between two enumerations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortChange {
+    /// The port as previously enumerated
+    pub old: SerialPortInfo,
+    /// The port as currently enumerated
+    pub new: SerialPortInfo,
+}
+
+/// The differences between two enumerations
+///
+/// Ports are identified by name.  `added` and `changed` follow the order of the
+/// latest enumeration, `removed` the order of the previous one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortChanges {
+    /// Ports which appeared
+    pub added: Vec<SerialPortInfo>,
+    /// Ports which disappeared
+    pub removed: Vec<SerialPortInfo>,
+    /// Ports present in both enumerations with different metadata
+    pub changed: Vec<PortChange>,
+}
+
+impl PortChanges {
+    /// Returns whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// A cache of the available ports
+#[derive(Debug, Clone, Default)]
+pub struct PortInventory {
+    ports: Vec<SerialPortInfo>,
+    refreshed: Option<Instant>,
+    options: EnumerationOptions,
+}
+
+impl PortInventory {
+    /// An empty inventory, nothing is enumerated until it is refreshed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An empty inventory keeping only the ports selected by `options`, in their
+    /// order.
+    pub fn with_options(options: EnumerationOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the ports found by the last refresh, in the order of the inventory's
+    /// [`EnumerationOptions`].
+    pub fn ports(&self) -> &[SerialPortInfo] {
+        &self.ports
+    }
+
+    /// Returns the port named `name`, if it was found by the last refresh.
+    pub fn get(&self, name: &str) -> Option<&SerialPortInfo> {
+        self.ports.iter().find(|port| port.port_name == name)
+    }
+
+    /// Returns when the inventory was last refreshed, or [`None`] if it never was.
+    pub fn last_refresh(&self) -> Option<Instant> {
+        self.refreshed
+    }
+
+    /// Enumerate the ports again, returning what changed since the last refresh.
+    ///
+    /// The first refresh reports all ports as added.
+    pub fn refresh(&mut self) -> crate::Result<PortChanges> {
+        Ok(self.update(self.options.enumerate()?))
+    }
+
+    /// Refresh unless the last refresh is more recent than `max_age`.
+    ///
+    /// Returns [`None`] if the cached enumeration was still fresh enough.
+    pub fn refresh_if_older(&mut self, max_age: Duration) -> crate::Result<Option<PortChanges>> {
+        match self.refreshed {
+            Some(refreshed) if refreshed.elapsed() < max_age => Ok(None),
+            _ => self.refresh().map(Some),
+        }
+    }
+
+    /// Like [`refresh`](PortInventory::refresh), enumerating on tokio's blocking
+    /// pool.
+    #[cfg(feature = "rt")]
+    pub async fn refresh_async(&mut self) -> crate::Result<PortChanges> {
+        let ports = crate::available_ports_async().await?;
+        Ok(self.update(ports))
+    }
+
+    /// Refresh every `period` until something changes.
+    ///
+    /// Ports are enumerated on tokio's blocking pool, see
+    /// [`refresh_async`](PortInventory::refresh_async).
+    #[cfg(feature = "rt")]
+    pub async fn changed(&mut self, period: Duration) -> crate::Result<PortChanges> {
+        loop {
+            tokio::time::sleep(period).await;
+            let changes = self.refresh_async().await?;
+            if !changes.is_empty() {
+                return Ok(changes);
+            }
+        }
+    }
+
+    /// Replace the cached ports with `ports`, returning what changed.
+    ///
+    /// This is what [`refresh`](PortInventory::refresh) does with the result of
+    /// `available_ports`, it is useful to feed enumerations obtained another way.
+    /// The inventory's [`EnumerationOptions`] are applied to `ports`.
+    pub fn update(&mut self, ports: Vec<SerialPortInfo>) -> PortChanges {
+        let ports = self.options.apply(ports);
+        let mut changes = PortChanges::default();
+        for port in &ports {
+            match self.get(&port.port_name) {
+                None => changes.added.push(port.clone()),
+                Some(old) if old != port => changes.changed.push(PortChange {
+                    old: old.clone(),
+                    new: port.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        changes.removed = self
+            .ports
+            .drain(..)
+            .filter(|old| !ports.iter().any(|port| port.port_name == old.port_name))
+            .collect();
+
+        self.ports = ports;
+        self.refreshed = Some(Instant::now());
+        changes
+    }
+}
+
+/// Criteria selecting a specific USB adapter, see [`find_port`]
+///
+/// Unset fields match anything.  The serial number has to match exactly, the
+/// manufacturer, product and interface names are matched as case insensitive
+/// substrings.
+///
+/// Composite devices, such as debug probes, expose one port per interface.  The
+/// interface number is enumerated on all platforms, the interface name comes from
+/// the [`details`] of the ports, so it only matches where these are known (on
+/// Linux).
+///
+/// ```no_run
+/// use tokio_serial::inventory::UsbFilter;
+/// use tokio_serial::SerialPortBuilderExt;
+///
+/// # async fn example() -> tokio_serial::Result<()> {
+/// let filter = UsbFilter {
+///     vid: Some(0x0403),
+///     pid: Some(0x6001),
+///     serial_number: Some("A601EXYZ".into()),
+///     ..UsbFilter::default()
+/// };
+/// let port = tokio_serial::new("", 115_200).open_usb_async(&filter).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UsbFilter {
+    /// Vendor id
+    pub vid: Option<u16>,
+    /// Product id
+    pub pid: Option<u16>,
+    /// Serial number
+    pub serial_number: Option<String>,
+    /// Part of the manufacturer name
+    pub manufacturer: Option<String>,
+    /// Part of the product name
+    pub product: Option<String>,
+    /// Number of the USB interface
+    pub interface: Option<u8>,
+    /// Part of the USB interface name (its string descriptor)
+    pub interface_name: Option<String>,
+}
+
+impl UsbFilter {
+    /// Returns whether `port` is a USB port matching the filter, ignoring the
+    /// interface name, and the interface number if it was not enumerated.
+    pub fn matches(&self, port: &SerialPortInfo) -> bool {
+        match &port.port_type {
+            SerialPortType::UsbPort(usb) => {
+                self.matches_usb(usb)
+                    && (usb.interface.is_none()
+                        || self.interface.is_none()
+                        || usb.interface == self.interface)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns whether `port` is a USB port matching all the criteria of the filter.
+    pub fn matches_details(&self, port: &PortDetails) -> bool {
+        match &port.info.port_type {
+            SerialPortType::UsbPort(usb) => {
+                self.matches_usb(usb)
+                    && (self.interface.is_none() || port.interface == self.interface)
+                    && contains(&port.interface_name, &self.interface_name)
+            }
+            _ => false,
+        }
+    }
+
+    fn matches_usb(&self, usb: &UsbPortInfo) -> bool {
+        self.vid.is_none_or(|vid| usb.vid == vid)
+            && self.pid.is_none_or(|pid| usb.pid == pid)
+            && (self.serial_number.is_none() || usb.serial_number == self.serial_number)
+            && contains(&usb.manufacturer, &self.manufacturer)
+            && contains(&usb.product, &self.product)
+    }
+
+    // Whether the details of the ports are needed, serialport does not enumerate the
+    // interface number of all ports.
+    fn has_interface_criteria(&self) -> bool {
+        self.interface.is_some() || self.interface_name.is_some()
+    }
+}
+
+// Whether `value` contains `part` ignoring case, a missing `part` always matches.
+fn contains(value: &Option<String>, part: &Option<String>) -> bool {
+    match part {
+        Some(part) => value
+            .as_ref()
+            .is_some_and(|value| value.to_lowercase().contains(&part.to_lowercase())),
+        None => true,
+    }
+}
+
+/// Find the first port, by name, matching `filter`.
+///
+/// Returns [`None`] if no port matches.
+pub fn find_port(filter: &UsbFilter) -> crate::Result<Option<SerialPortInfo>> {
+    let options = EnumerationOptions::new();
+    if filter.has_interface_criteria() {
+        return Ok(options
+            .enumerate_detailed()?
+            .into_iter()
+            .find(|port| filter.matches_details(port))
+            .map(|port| port.info));
+    }
+    Ok(options
+        .enumerate()?
+        .into_iter()
+        .find(|port| filter.matches(port)))
+}
+
+/// Open the first port, by name, for which `predicate` returns `true`, with the
+/// settings of `builder`.
+///
+/// Ports are enumerated and opened on tokio's blocking pool, see
+/// [`available_ports_async`](crate::available_ports_async) and
+/// [`open_async`](crate::SerialPortBuilderExt::open_async).  Ports which match but
+/// fail to open, e.g. because another program uses them, are skipped.  Returns the
+/// port opened along with the stream.
+///
+/// ## Errors
+///
+/// * `NoDevice` if no port matches.
+/// * The error of the last port tried if none of the matching ports could be opened.
+#[cfg(feature = "rt")]
+pub async fn open_first_matching<F>(
+    mut predicate: F,
+    builder: &SerialPortBuilder,
+) -> crate::Result<(SerialPortInfo, SerialStream)>
+where
+    F: FnMut(&SerialPortInfo) -> bool,
+{
+    use crate::SerialPortBuilderExt;
+
+    let ports = EnumerationOptions::new().apply(crate::available_ports_async().await?);
+    let mut last_error = None;
+    for port in ports {
+        if !predicate(&port) {
+            continue;
+        }
+        match builder.clone().path(&port.port_name).open_async().await {
+            Ok(stream) => return Ok((port, stream)),
+            Err(e) => {
+                log::debug!("skipping {}: {}", port.port_name, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        crate::Error::new(crate::ErrorKind::NoDevice, "no port matches the predicate")
+    }))
+}
+
+/// Returns a path designating the port of `info` which survives reboots and replugs.
+///
+/// Kernel names such as `/dev/ttyUSB0` are handed out in detection order and change
+/// when several adapters are plugged in.  On Linux, the `/dev/serial/by-id` symlink
+/// of the port is returned, named after the USB ids and serial number of the
+/// adapter, or else its `/dev/serial/by-path` symlink, named after the USB port it
+/// is plugged in.  Both can be opened like the kernel name.  [`None`] is returned
+/// when udev created neither.
+///
+/// macOS derives port names from the serial number of the adapter and Windows
+/// remembers the COM number it assigned to each device, so the port name is
+/// returned as is there.
+pub fn stable_path(info: &SerialPortInfo) -> Option<String> {
+    #[cfg(target_os = "linux")]
+    {
+        stable_path_in(info, &["/dev/serial/by-id", "/dev/serial/by-path"])
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        Some(info.port_name.clone())
+    }
+}
+
+/// Returns the first symlink designating the port of `info` in the first of `dirs`
+/// having one, as [`stable_path`] does on Linux with `/dev/serial/by-id` and
+/// `/dev/serial/by-path`.
+///
+/// For udev rules creating their own links elsewhere.  When several links of a
+/// directory designate the port, the first in path order is returned.
+pub fn stable_path_in<P: AsRef<Path>>(info: &SerialPortInfo, dirs: &[P]) -> Option<String> {
+    let target = std::fs::canonicalize(&info.port_name).ok()?;
+    dirs.iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .find_map(|entries| {
+            let mut links: Vec<_> = entries
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|link| std::fs::canonicalize(link).is_ok_and(|path| path == target))
+                .collect();
+            // Several links can designate the same port, pick one deterministically.
+            links.sort();
+            links.into_iter().next()
+        })
+        .map(|link| link.display().to_string())
+}
+
+/// A port appearing or disappearing, see [`watch_ports`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortEvent {
+    /// A port was plugged in
+    Added(SerialPortInfo),
+    /// A port was unplugged
+    Removed(SerialPortInfo),
+}
+
+impl PortEvent {
+    /// Returns the port the event is about.
+    pub fn port(&self) -> &SerialPortInfo {
+        match self {
+            PortEvent::Added(port) | PortEvent::Removed(port) => port,
+        }
+    }
+
+    /// Decode a message of the udev netlink monitor, for programs listening to it
+    /// on their own socket.
+    ///
+    /// Returns [`None`] for messages which are not from udev, are malformed or are
+    /// not about a serial port being added or removed.
+    #[cfg(target_os = "linux")]
+    pub fn from_udev_message(message: &[u8]) -> Option<Self> {
+        crate::hotplug::parse_udev_message(message)
+    }
+}
+
+/// A stream of [`PortEvent`]s, returned by [`watch_ports`]
+#[cfg(any(target_os = "linux", windows))]
+#[must_use = "streams do nothing unless polled"]
+#[derive(Debug)]
+pub struct PortEvents {
+    monitor: crate::hotplug::Monitor,
+}
+
+#[cfg(any(target_os = "linux", windows))]
+impl futures::Stream for PortEvents {
+    type Item = crate::Result<PortEvent>;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.get_mut()
+            .monitor
+            .poll_event(cx)
+            .map(|event| Some(event.map_err(Into::into)))
+    }
+}
+
+/// Watch ports being plugged in and unplugged.
+///
+/// Ports already present are not reported, combine with
+/// [`available_ports`](crate::available_ports) to get them.  The stream never ends.
+///
+/// On Linux, events come from udev once its rules ran, so the device node has its
+/// final permissions and symlinks.  This requires udev to be running and must be
+/// called from within a tokio runtime.
+///
+/// On Windows, the configuration manager notifies COM port interface changes
+/// (`CM_Register_Notification`) and the ports are enumerated again to find the
+/// ones which came and went.
+#[cfg(any(target_os = "linux", windows))]
+pub fn watch_ports() -> crate::Result<PortEvents> {
+    Ok(PortEvents {
+        monitor: crate::hotplug::Monitor::new()?,
+    })
+}
+
+/// The port [`wait_for_port`] waits for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortSelector {
+    /// The port at this path or, on Windows, with this COM name
+    Path(String),
+    /// The first USB port matching the filter, see [`find_port`]
+    Usb(UsbFilter),
+}
+
+impl From<&str> for PortSelector {
+    fn from(path: &str) -> Self {
+        PortSelector::Path(path.to_string())
+    }
+}
+
+impl From<String> for PortSelector {
+    fn from(path: String) -> Self {
+        PortSelector::Path(path)
+    }
+}
+
+impl From<UsbFilter> for PortSelector {
+    fn from(filter: UsbFilter) -> Self {
+        PortSelector::Usb(filter)
+    }
+}
+
+// How often to try again when no hotplug notification arrives, e.g. while udev
+// is still fixing the permissions of a new device node.
+const WAIT_RETRY_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Wait until `port` exists and can be opened, and open it with the settings of
+/// `builder`.
+///
+/// The port is tried right away, then every time a port is plugged in and at
+/// least every 250ms.  The path of `builder` is ignored.  With the `rt` feature,
+/// ports are enumerated and opened on tokio's blocking pool.
+///
+/// ## Errors
+///
+/// * `Io(TimedOut)` if the port could not be opened within `timeout`.  Why the
+///   attempts failed is logged at debug level.
+/// * `InvalidInput` right away if the settings of `builder` are rejected.
+/// * `Unknown` if the OS stops notifying new ports.
+pub async fn wait_for_port<P: Into<PortSelector>>(
+    port: P,
+    builder: &SerialPortBuilder,
+    timeout: Duration,
+) -> crate::Result<SerialStream> {
+    let port = port.into();
+    let deadline = tokio::time::Instant::now() + timeout;
+    // Watch before the first attempt, so a port plugged in meanwhile is not missed.
+    #[cfg(any(target_os = "linux", windows))]
+    let mut events = watch_ports().ok();
+
+    loop {
+        let path = match &port {
+            PortSelector::Path(path) => Some(path.clone()),
+            #[cfg(feature = "rt")]
+            PortSelector::Usb(filter) => {
+                let filter = filter.clone();
+                tokio::task::spawn_blocking(move || find_port(&filter))
+                    .await
+                    .map_err(|e| crate::Error::new(crate::ErrorKind::Unknown, e.to_string()))??
+                    .map(|info| info.port_name)
+            }
+            #[cfg(not(feature = "rt"))]
+            PortSelector::Usb(filter) => find_port(filter)?.map(|info| info.port_name),
+        };
+        if let Some(path) = path {
+            #[cfg(feature = "rt")]
+            let opened = {
+                use crate::SerialPortBuilderExt;
+                builder.clone().path(&path).open_async().await
+            };
+            #[cfg(not(feature = "rt"))]
+            let opened = SerialStream::open(&builder.clone().path(&path));
+            match opened {
+                Ok(stream) => return Ok(stream),
+                Err(e) if e.kind() == crate::ErrorKind::InvalidInput => return Err(e),
+                Err(e) => log::debug!("{} not ready yet: {}", path, e),
+            }
+        }
+
+        let retry = tokio::time::sleep(WAIT_RETRY_INTERVAL);
+        #[cfg(any(target_os = "linux", windows))]
+        let retry = async {
+            use futures::future::Either;
+            use futures::StreamExt;
+
+            let events = match &mut events {
+                Some(events) => events,
+                None => {
+                    retry.await;
+                    return Ok(());
+                }
+            };
+            let retry = std::pin::pin!(retry);
+            match futures::future::select(retry, events.next()).await {
+                Either::Left(_) | Either::Right((Some(Ok(_)), _)) => Ok(()),
+                // Back off rather than spinning on a failing monitor.
+                Either::Right((Some(Err(e)), retry)) => {
+                    log::debug!("unable to watch ports: {}", e);
+                    retry.await;
+                    Ok(())
+                }
+                Either::Right((None, _)) => Err(crate::Error::new(
+                    crate::ErrorKind::Unknown,
+                    "port notifications stopped",
+                )),
+            }
+        };
+        #[cfg(not(any(target_os = "linux", windows)))]
+        let retry = async {
+            retry.await;
+            crate::Result::Ok(())
+        };
+        match tokio::time::timeout_at(deadline, retry).await {
+            Ok(retried) => retried?,
+            Err(_) => {
+                return Err(crate::Error::new(
+                    crate::ErrorKind::Io(std::io::ErrorKind::TimedOut),
+                    format!("timed out waiting for {:?}", port),
+                ))
+            }
+        }
+    }
+}
@@ -8,20 +8,162 @@
 #![warn(rust_2018_idioms)]
 
 // Re-export serialport types and traits from mio_serial
+#[cfg(not(target_arch = "wasm32"))]
 pub use mio_serial::{
     available_ports, new, ClearBuffer, DataBits, Error, ErrorKind, FlowControl, Parity, SerialPort,
     SerialPortBuilder, SerialPortInfo, SerialPortType, StopBits, UsbPortInfo,
 };
 
+// `mio` does not support wasm, the types are taken straight from serialport there
+#[cfg(target_arch = "wasm32")]
+pub use serialport::{
+    new, ClearBuffer, DataBits, Error, ErrorKind, FlowControl, Parity, SerialPort,
+    SerialPortBuilder, SerialPortInfo, SerialPortType, StopBits, UsbPortInfo,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
+#[cfg(not(target_arch = "wasm32"))]
 use std::io::{Read, Result as IoResult, Write};
+#[cfg(not(target_arch = "wasm32"))]
 use std::pin::Pin;
-use std::task::{Context, Poll};
-use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::task::{Context, Poll, Waker};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "codec")]
+pub mod codec;
+
+#[cfg(all(feature = "codec", not(target_arch = "wasm32")))]
 mod frame;
+#[cfg(all(feature = "codec", not(target_arch = "wasm32")))]
+pub use frame::SerialFramed;
+
+mod port;
+pub use port::AsyncSerialPort;
+
+mod shutdown;
+pub use shutdown::ShutdownLayered;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod autobaud;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bridge;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bus;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod delay;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod dmx;
+
+pub mod flow;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod escpos;
+
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+
+pub mod identify;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod iec62056;
+
+#[cfg(feature = "test-support")]
+pub mod mock;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod inventory;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod lin;
+
+#[cfg(any(target_os = "linux", windows))]
+mod hotplug;
+
+#[cfg(feature = "mux")]
+pub mod mux;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod pacing;
+
+pub mod power;
+
+pub mod presets;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod reconnect;
+
+pub mod retry;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod rfc2217;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tee;
+
+pub mod transcript;
+
+#[cfg(feature = "transfer")]
+pub mod transfer;
+
+#[cfg(all(feature = "web-serial", target_arch = "wasm32"))]
+pub mod web;
+
+#[cfg(unix)]
+mod lock;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod console;
+#[cfg(unix)]
+pub use console::ControlChar;
+
+#[cfg(unix)]
+mod marking;
+#[cfg(unix)]
+pub use marking::LineEvent;
+
+mod settings;
+pub use settings::SerialSettings;
+
+mod config;
+pub use config::PortConfig;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod validation;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod watcher;
+#[cfg(not(target_arch = "wasm32"))]
+pub use watcher::{SettingsEvent, SettingsWatcher};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native;
+#[cfg(all(
+    target_os = "linux",
+    not(any(target_arch = "sparc", target_arch = "sparc64"))
+))]
+pub use native::Rs485Config;
+#[cfg(unix)]
+pub use native::UnixSerialStream;
+#[cfg(windows)]
+pub use native::WindowsSerialStream;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod disconnect;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod counters;
+#[cfg(any(unix, windows))]
+pub use counters::BreakEvents;
+#[cfg(not(target_arch = "wasm32"))]
+pub use counters::{LineCounters, WriteStats};
 
 #[cfg(unix)]
 mod os_prelude {
@@ -31,16 +173,30 @@ mod os_prelude {
 
 #[cfg(windows)]
 mod os_prelude {
+    pub use futures::ready;
     pub use std::mem;
     pub use std::ops::{Deref, DerefMut};
     pub use std::os::windows::prelude::*;
     pub use tokio::net::windows::named_pipe;
+    pub use windows_sys::Win32::Foundation::ERROR_OPERATION_ABORTED;
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 use crate::os_prelude::*;
 
 /// A type for results generated by interacting with serial ports.
-pub type Result<T> = mio_serial::Result<T>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Returns a list of all serial ports on the system, without blocking the reactor.
+///
+/// Enumerating ports reads the filesystem or the registry, so it runs on tokio's
+/// blocking pool.  Must be called from within a tokio runtime.
+#[cfg(all(feature = "rt", not(target_arch = "wasm32")))]
+pub async fn available_ports_async() -> Result<Vec<SerialPortInfo>> {
+    tokio::task::spawn_blocking(available_ports)
+        .await
+        .map_err(|e| Error::new(ErrorKind::Unknown, e.to_string()))?
+}
 
 /// Async serial port I/O
 ///
@@ -51,8 +207,13 @@ pub type Result<T> = mio_serial::Result<T>;
 /// [`AsyncReadExt`]: trait@tokio::io::AsyncReadExt
 /// [`AsyncWriteExt`]: trait@tokio::io::AsyncWriteExt
 ///
+#[cfg(not(target_arch = "wasm32"))]
 #[derive(Debug)]
 pub struct SerialStream {
+    // Advisory UUCP lock, released when the stream is dropped. Declared before `inner` so
+    // it is dropped while the file descriptor it `flock`ed is still open.
+    #[cfg(unix)]
+    lock: Option<lock::UucpLock>,
     #[cfg(unix)]
     inner: AsyncFd<mio_serial::SerialStream>,
     // Named pipes and COM ports are actually two entirely different things that hardly have anything in common.
@@ -65,17 +226,38 @@ pub struct SerialStream {
     // The com port is kept around for serialport related methods
     #[cfg(windows)]
     com: mem::ManuallyDrop<mio_serial::SerialStream>,
+    // Set by `pause_reads`, holding the waker of a read waiting for `resume_reads`
+    paused: Option<Option<Waker>>,
+    #[cfg(unix)]
+    marks: Option<marking::MarkDecoder>,
+    // Set by `clear_buffers`, whose purge may abort a write
+    #[cfg(windows)]
+    purged_write: bool,
+    // Set by `break_events`, removing the marks of breaks from the data
+    #[cfg(unix)]
+    breaks: Option<(marking::MarkDecoder, counters::BreakSender)>,
+    // Bytes handed to the OS by writes
+    tx_accepted: u64,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl SerialStream {
     /// Open serial port from a provided path, using the default reactor.
     pub fn open(builder: &crate::SerialPortBuilder) -> crate::Result<Self> {
-        let port = mio_serial::SerialStream::open(builder)?;
+        Self::from_mio(mio_serial::SerialStream::open(builder)?)
+    }
 
+    // Register an open port with the reactor.
+    fn from_mio(port: mio_serial::SerialStream) -> crate::Result<Self> {
         #[cfg(unix)]
         {
             Ok(Self {
+                lock: None,
                 inner: AsyncFd::new(port)?,
+                paused: None,
+                marks: None,
+                breaks: None,
+                tx_accepted: 0,
             })
         }
 
@@ -87,6 +269,9 @@ impl SerialStream {
             Ok(Self {
                 inner: unsafe { named_pipe::NamedPipeClient::from_raw_handle(handle)? },
                 com,
+                paused: None,
+                purged_write: false,
+                tx_accepted: 0,
             })
         }
     }
@@ -105,14 +290,136 @@ impl SerialStream {
         let (master, slave) = mio_serial::SerialStream::pair()?;
 
         let master = SerialStream {
+            lock: None,
             inner: AsyncFd::new(master)?,
+            paused: None,
+            marks: None,
+            breaks: None,
+            tx_accepted: 0,
         };
         let slave = SerialStream {
+            lock: None,
             inner: AsyncFd::new(slave)?,
+            paused: None,
+            marks: None,
+            breaks: None,
+            tx_accepted: 0,
         };
         Ok((master, slave))
     }
 
+    /// Apply the line settings of `builder` to the open port
+    ///
+    /// Like [`apply_settings`](SerialStream::apply_settings), all of them are changed
+    /// at once.  The path, timeout and open-time options of `builder` are ignored.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io` if the driver rejects the settings, e.g. an unsupported baud rate.
+    pub fn reconfigure(&mut self, builder: &SerialPortBuilder) -> crate::Result<()> {
+        self.apply_settings(&config::from_builder(builder)?.settings)
+    }
+
+    /// Apply `settings` to the open port
+    ///
+    /// The baud rate, data bits, parity, stop bits and flow control are changed with a
+    /// single `termios` (or `termios2` on Linux) update on Unix, and a single
+    /// `SetCommState` on Windows.  Either all of them take effect or, on error, none,
+    /// whereas calling the individual setters can leave the port half-configured.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io` if the driver rejects the settings, e.g. an unsupported baud rate.
+    pub fn apply_settings(&mut self, settings: &SerialSettings) -> crate::Result<()> {
+        #[cfg(unix)]
+        {
+            settings::apply(std::os::unix::io::AsRawFd::as_raw_fd(&self.inner), settings)
+        }
+
+        #[cfg(windows)]
+        {
+            settings::apply(self.com.as_raw_handle(), settings)
+        }
+    }
+
+    /// Capture the current line settings of the port
+    pub fn settings(&self) -> crate::Result<SerialSettings> {
+        Ok(SerialSettings {
+            baud_rate: self.baud_rate()?,
+            data_bits: self.data_bits()?,
+            parity: self.parity()?,
+            stop_bits: self.stop_bits()?,
+            flow_control: self.flow_control()?,
+        })
+    }
+
+    /// Read the error and traffic counters of the UART
+    ///
+    /// See [`LineCounters`] for the platform differences.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io` if the driver does not keep counters, which is the case of pseudo
+    ///   terminals and some USB adapters.
+    #[cfg(any(target_os = "linux", target_os = "android", windows))]
+    pub fn counters(&self) -> crate::Result<LineCounters> {
+        #[cfg(unix)]
+        {
+            counters::read(std::os::unix::io::AsRawFd::as_raw_fd(&self.inner))
+        }
+        #[cfg(windows)]
+        {
+            counters::read(self.com.as_raw_handle())
+        }
+    }
+
+    /// Watch for break conditions received by the port
+    ///
+    /// Returns a stream of [`BreakEvents`].  This enables `PARMRK` for breaks, which
+    /// reads then remove from the data instead of returning NUL bytes, for as long
+    /// as the port is open.  Breaks are thus only noticed as the data is read.  With
+    /// [error marking](SerialStream::set_error_marking), they are also reported by
+    /// [`read_with_errors`](SerialStream::read_with_errors) in order with the data.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io` for any error while configuring the port.
+    #[cfg(unix)]
+    pub fn break_events(&mut self) -> crate::Result<BreakEvents> {
+        marking::set_breaks(std::os::unix::io::AsRawFd::as_raw_fd(&self.inner))?;
+        let (sender, events) = BreakEvents::new();
+        let decoder = match self.breaks.take() {
+            Some((decoder, _)) => decoder,
+            None => marking::MarkDecoder::new(),
+        };
+        self.breaks = Some((decoder, sender));
+        Ok(events)
+    }
+
+    /// Watch for break conditions received by the port
+    ///
+    /// Returns a stream of [`BreakEvents`], which the driver reports with
+    /// `EV_BREAK` regardless of reading.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io` for any error while configuring the port.
+    #[cfg(windows)]
+    pub fn break_events(&mut self) -> crate::Result<BreakEvents> {
+        let port =
+            unsafe { BorrowedHandle::borrow_raw(self.com.as_raw_handle()) }.try_clone_to_owned()?;
+        BreakEvents::new(port)
+    }
+
+    /// Create a builder reopening this port with its current line settings
+    ///
+    /// Unnamed ports, such as the ones created by [`pair`](SerialStream::pair), get an
+    /// empty path.
+    pub fn to_builder(&self) -> crate::Result<crate::SerialPortBuilder> {
+        let path = self.name().unwrap_or_default();
+        Ok(self.settings()?.builder(path))
+    }
+
     /// Sets the exclusivity of the port
     ///
     /// If a port is exclusive, then trying to open the same device path again
@@ -137,6 +444,176 @@ impl SerialStream {
         self.inner.get_ref().exclusive()
     }
 
+    /// Returns termios' `c_cc` array, indexed by the platform's `V*` constants
+    ///
+    /// [`control_char`](SerialStream::control_char) reads a single entry by name.
+    #[cfg(unix)]
+    pub fn control_chars(&self) -> crate::Result<Vec<u8>> {
+        console::control_chars(std::os::unix::io::AsRawFd::as_raw_fd(&self.inner))
+    }
+
+    /// Returns the value of a special character
+    #[cfg(unix)]
+    pub fn control_char(&self, c: ControlChar) -> crate::Result<u8> {
+        console::control_char(std::os::unix::io::AsRawFd::as_raw_fd(&self.inner), c)
+    }
+
+    /// Sets the value of a special character
+    ///
+    /// Setting a character to the platform's `_POSIX_VDISABLE` disables it.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io` for any error while configuring the port.
+    #[cfg(unix)]
+    pub fn set_control_char(&mut self, c: ControlChar, value: u8) -> crate::Result<()> {
+        console::set_control_char(std::os::unix::io::AsRawFd::as_raw_fd(&self.inner), c, value)
+    }
+
+    /// Enable or disable canonical mode
+    ///
+    /// In canonical mode the kernel buffers input by line: reads complete once a
+    /// newline, [`Eol`](ControlChar::Eol), [`Eol2`](ControlChar::Eol2) or
+    /// [`Eof`](ControlChar::Eof) is received, and line editing characters such as
+    /// [`Erase`](ControlChar::Erase) are applied.  Echo stays disabled.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io` for any error while configuring the port.
+    #[cfg(unix)]
+    pub fn set_canonical(&mut self, enabled: bool) -> crate::Result<()> {
+        console::set_canonical(std::os::unix::io::AsRawFd::as_raw_fd(&self.inner), enabled)
+    }
+
+    /// Returns whether canonical mode is enabled
+    #[cfg(unix)]
+    pub fn canonical(&self) -> crate::Result<bool> {
+        console::canonical(std::os::unix::io::AsRawFd::as_raw_fd(&self.inner))
+    }
+
+    /// Report parity and framing errors in band
+    ///
+    /// Enables `PARMRK` and input parity checking, so that erroneous bytes and
+    /// breaks are marked in the received data instead of silently dropped or
+    /// mangled.  Use [`read_with_errors`](SerialStream::read_with_errors) to read
+    /// the data with the errors decoded; plain reads return the raw marked data.
+    ///
+    /// Changing the parity, or any setting through
+    /// [`apply_settings`](SerialStream::apply_settings), resets the input checking
+    /// flags, enable marking again afterwards.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io` for any error while configuring the port.
+    #[cfg(unix)]
+    pub fn set_error_marking(&mut self, enabled: bool) -> crate::Result<()> {
+        let fd = std::os::unix::io::AsRawFd::as_raw_fd(&self.inner);
+        marking::set(fd, enabled)?;
+        if enabled {
+            if self.marks.is_none() {
+                self.marks = Some(marking::MarkDecoder::new());
+            }
+        } else {
+            self.marks = None;
+            // Go back to what `serialport` sets for the current parity.
+            self.apply_settings(&self.settings()?)?;
+            if self.breaks.is_some() {
+                marking::set_breaks(fd)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether errors are reported in band, see
+    /// [`set_error_marking`](SerialStream::set_error_marking).
+    #[cfg(unix)]
+    pub fn error_marking(&self) -> bool {
+        self.marks.is_some()
+    }
+
+    /// Read data along with the parity and framing errors received in between
+    ///
+    /// Returns the events decoded from a single read, or no event at end of file.
+    /// Without [error marking](SerialStream::set_error_marking) all data is
+    /// returned as is.
+    #[cfg(unix)]
+    pub async fn read_with_errors(&mut self) -> IoResult<Vec<LineEvent>> {
+        use tokio::io::AsyncReadExt;
+
+        let mut buf = [0u8; 256];
+        let mut events = Vec::new();
+        while events.is_empty() {
+            let n = AsyncReadExt::read(self, &mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            match &mut self.marks {
+                Some(marks) => marks.decode(&buf[..n], &mut events),
+                None => events.push(LineEvent::Data(buf[..n].to_vec())),
+            }
+        }
+        if let Some((_, sender)) = &self.breaks {
+            let breaks = events.iter().filter(|e| **e == LineEvent::Break).count();
+            if breaks > 0 {
+                let _ = sender.unbounded_send(Ok(breaks as u32));
+            }
+        }
+        Ok(events)
+    }
+
+    /// Take a UUCP-style advisory lock on the port
+    ///
+    /// Creates `/var/lock/LCK..<device>` holding the PID of this process and
+    /// `flock`s the port, so tools honoring UUCP locks (minicom, pppd, cu, ...)
+    /// leave the port alone.  Stale lock files of processes which no longer exist
+    /// are replaced.  The lock is released when the stream is dropped or
+    /// [`unlock`](SerialStream::unlock) is called.
+    ///
+    /// This is independent of [`set_exclusive`](SerialStream::set_exclusive).
+    ///
+    /// ## Errors
+    ///
+    /// * `Io(AlreadyExists)` if another process holds the lock.
+    /// * `Io` for any other error while creating the lock, e.g. missing permissions
+    ///   on the lock directory.
+    #[cfg(unix)]
+    pub fn lock(&mut self) -> crate::Result<()> {
+        self.lock_in(lock::DEFAULT_LOCK_DIR)
+    }
+
+    /// Take a UUCP-style advisory lock on the port with the lock file in `dir`
+    ///
+    /// See [`lock`](SerialStream::lock) for details.
+    #[cfg(unix)]
+    pub fn lock_in<P: AsRef<std::path::Path>>(&mut self, dir: P) -> crate::Result<()> {
+        if self.lock.is_some() {
+            return Ok(());
+        }
+
+        let name = self.name().ok_or_else(|| {
+            crate::Error::new(
+                crate::ErrorKind::NoDevice,
+                "Cannot lock a port without a device name",
+            )
+        })?;
+        let fd = std::os::unix::io::AsRawFd::as_raw_fd(&self.inner);
+        let shared = !self.exclusive();
+        self.lock = Some(lock::UucpLock::acquire(&name, fd, dir.as_ref(), shared)?);
+        Ok(())
+    }
+
+    /// Release the lock taken with [`lock`](SerialStream::lock), if any.
+    #[cfg(unix)]
+    pub fn unlock(&mut self) {
+        self.lock = None;
+    }
+
+    /// Returns the path of the lock file if the port is locked.
+    #[cfg(unix)]
+    pub fn lock_file(&self) -> Option<&std::path::Path> {
+        self.lock.as_ref().map(|lock| lock.path())
+    }
+
     /// Borrow a reference to the underlying mio-serial::SerialStream object.
     #[inline(always)]
     fn borrow(&self) -> &mio_serial::SerialStream {
@@ -171,14 +648,15 @@ impl SerialStream {
     /// When there is no pending data, `Err(io::ErrorKind::WouldBlock)` is
     /// returned. This function is usually paired with `readable()`.
     pub fn try_read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
-        #[cfg(unix)]
-        {
-            self.inner.get_mut().read(buf)
+        if self.paused.is_some() {
+            return Err(std::io::ErrorKind::WouldBlock.into());
         }
+
+        #[cfg(unix)]
+        let read = self.inner.get_mut().read(buf);
         #[cfg(windows)]
-        {
-            self.inner.try_read(buf)
-        }
+        let read = self.inner.try_read(buf);
+        self.checked_read(read, buf.len())
     }
 
     /// Wait for the port to become readable.
@@ -193,18 +671,377 @@ impl SerialStream {
         Ok(())
     }
 
+    /// Wait until at least `n` bytes are waiting in the input buffer.
+    ///
+    /// Fixed-size frame protocols can use this to read a whole frame with a single
+    /// `try_read()` instead of waking up and reading for every byte that arrives.
+    /// Readiness notifications still arrive per byte, but they are only used to
+    /// re-check [`SerialPort::bytes_to_read`] internally.
+    ///
+    /// Only available on Unix: on Windows part of the received data is already held
+    /// by the read this stream keeps in flight and cannot be counted.
+    ///
+    /// [`SerialPort::bytes_to_read`]: crate::SerialPort::bytes_to_read
+    #[cfg(unix)]
+    pub async fn readable_at_least(&self, n: u32) -> IoResult<()> {
+        loop {
+            let mut guard = self.inner.readable().await?;
+            if self.borrow().bytes_to_read()? >= n {
+                return Ok(());
+            }
+            // Readiness reported after the guard was taken is kept, so bytes arriving
+            // since the check above still wake us up.
+            guard.clear_ready();
+        }
+    }
+
     /// Try to write bytes on the serial port.  On success returns the number of bytes written.
     ///
     /// When the write would block, `Err(io::ErrorKind::WouldBlock)` is
     /// returned. This function is usually paired with `writable()`.
     pub fn try_write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        #[cfg(unix)]
+        let written = self.inner.get_mut().write(buf);
+        #[cfg(windows)]
+        let written = loop {
+            let written = self.inner.try_write(buf);
+            if !self.aborted_by_purge(&written) {
+                break written;
+            }
+        };
+        let written = self.checked(written);
+        self.accepted(written)
+    }
+
+    // Whether `written` is the error of a write aborted by `clear_buffers`, the
+    // write is to be tried again.
+    #[cfg(windows)]
+    fn aborted_by_purge(&mut self, written: &IoResult<usize>) -> bool {
+        match written {
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => false,
+            Err(err) if err.raw_os_error() == Some(ERROR_OPERATION_ABORTED as i32) => {
+                mem::take(&mut self.purged_write)
+            }
+            _ => {
+                self.purged_write = false;
+                false
+            }
+        }
+    }
+
+    // Whether the device of the port is gone: it cannot even be queried anymore.
+    fn is_gone(&self) -> bool {
+        self.borrow().bytes_to_read().is_err()
+    }
+
+    // Report the failures a removed device causes as `NotConnected`.
+    fn checked<T>(&self, result: IoResult<T>) -> IoResult<T> {
+        match result {
+            Err(err) if disconnect::is_candidate(&err) && self.is_gone() => {
+                log::debug!("device disconnected: {}", err);
+                Err(disconnect::error())
+            }
+            result => result,
+        }
+    }
+
+    // Like `checked`, also catching the end of file hung up ttys read on Unix.
+    fn checked_read(&self, read: IoResult<usize>, requested: usize) -> IoResult<usize> {
+        match read {
+            Ok(0) if requested > 0 && self.is_gone() => {
+                log::debug!("device disconnected: end of file");
+                Err(disconnect::error())
+            }
+            read => self.checked(read),
+        }
+    }
+
+    fn accepted(&mut self, written: IoResult<usize>) -> IoResult<usize> {
+        if let Ok(n) = written {
+            self.tx_accepted += n as u64;
+            log::trace!(
+                "{} bytes accepted by the OS, {} in total",
+                n,
+                self.tx_accepted
+            );
+        }
+        written
+    }
+
+    /// Returns how much of the data written was actually transmitted
+    ///
+    /// A write completes once the OS accepted the data into its output buffer,
+    /// which can take a long time to drain at low baud rates.  Data still queued
+    /// when the port is closed or the direction of a half-duplex line is switched
+    /// is lost, this tells how much of it there is.
+    ///
+    /// On Windows data accepted by this stream but not yet handed to the driver
+    /// counts as transmitted.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io` if the size of the output buffer cannot be read.
+    pub fn write_stats(&self) -> crate::Result<WriteStats> {
+        let queued = self.bytes_to_write()?;
+        Ok(WriteStats {
+            accepted: self.tx_accepted,
+            queued,
+            transmitted: self.tx_accepted.saturating_sub(u64::from(queued)),
+        })
+    }
+
+    /// Wait until everything written so far was transmitted.
+    ///
+    /// This is [`wait_tx_below(0)`](SerialStream::wait_tx_below), returning the
+    /// [`write_stats`](SerialStream::write_stats) once the output buffer is empty.
+    pub async fn wait_transmitted(&self) -> crate::Result<WriteStats> {
+        self.wait_tx_below(0).await?;
+        let stats = self.write_stats()?;
+        log::trace!(
+            "output buffer drained, {} bytes transmitted",
+            stats.transmitted
+        );
+        Ok(stats)
+    }
+
+    /// Find the baud rate of the device on the other end
+    ///
+    /// Tries each of `candidates` in order, listening to the traffic at that rate
+    /// and judging it with `probe`, see [`autobaud`] for the available criteria.
+    /// Input received before switching to a rate is discarded.
+    ///
+    /// Returns the detected rate, the port is left configured at it.  If no
+    /// candidate fits, [`None`] is returned and the original rate restored, as it
+    /// also is on errors or when the returned future is dropped.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io` for any error while reconfiguring or talking to the port.
+    pub async fn detect_baud(
+        &mut self,
+        candidates: &[u32],
+        probe: autobaud::AutobaudProbe,
+    ) -> crate::Result<Option<u32>> {
+        autobaud::detect(self, candidates, &probe).await
+    }
+
+    /// Read bytes along with the time they were noticed
+    ///
+    /// Operating systems do not timestamp data received on serial ports, so the
+    /// timestamp is taken as soon as the stream finds the port readable, before the
+    /// data is copied out.  Its accuracy depends on how promptly the reading task is
+    /// scheduled, keep it busy with nothing else for best results.  On Windows, where
+    /// data is read ahead in the background, it is taken when the read completes.
+    ///
+    /// Returns the number of bytes read, `0` at end of file.
+    pub async fn read_timestamped(&mut self, buf: &mut [u8]) -> IoResult<(usize, Instant)> {
+        futures::future::poll_fn(|cx| {
+            ready!(Pin::new(&mut *self).poll_resumed(cx));
+
+            #[cfg(unix)]
+            loop {
+                let mut guard = ready!(self.inner.poll_read_ready(cx))?;
+                let at = Instant::now();
+                let requested = buf.len();
+                match guard.try_io(|inner| inner.get_ref().read(buf)) {
+                    Ok(read) => {
+                        return Poll::Ready(self.checked_read(read, requested).map(|n| (n, at)))
+                    }
+                    Err(_would_block) => continue,
+                }
+            }
+
+            #[cfg(windows)]
+            {
+                let requested = buf.len();
+                let mut read_buf = ReadBuf::new(buf);
+                let read = ready!(Pin::new(&mut self.inner).poll_read(cx, &mut read_buf))
+                    .map(|()| read_buf.filled().len());
+                let n = self.checked_read(read, requested)?;
+                Poll::Ready(Ok((n, Instant::now())))
+            }
+        })
+        .await
+    }
+
+    /// Wait until the device of the port is disconnected, e.g. a USB adapter unplugged
+    ///
+    /// Once it is, reads and writes fail with `io::ErrorKind::NotConnected` instead of
+    /// the various errors each platform reports.
+    ///
+    /// On Linux and Windows the OS notifies removals as they happen, see
+    /// [`watch_ports`](inventory::watch_ports); the port is also checked every
+    /// 500ms in case a notification is missed or the OS does not send any.
+    pub async fn closed(&self) -> crate::Result<()> {
+        use futures::StreamExt;
+
+        #[cfg(unix)]
+        let name = self.name().map(|name| {
+            std::fs::canonicalize(&name).map_or(name, |path| path.display().to_string())
+        });
+        #[cfg(windows)]
+        let name = self.name();
+
+        #[cfg(any(target_os = "linux", windows))]
+        let mut removals = name.and_then(|name| {
+            let events = inventory::watch_ports().ok()?;
+            Some(events.filter(move |event| {
+                futures::future::ready(matches!(
+                    event,
+                    Ok(inventory::PortEvent::Removed(port)) if disconnect::same_port(&port.port_name, &name)
+                ))
+            }))
+        });
+        #[cfg(not(any(target_os = "linux", windows)))]
+        let _ = name;
+
+        let mut interval = tokio::time::interval(disconnect::POLL_INTERVAL);
+        loop {
+            if self.is_gone() {
+                return Ok(());
+            }
+            #[cfg(any(target_os = "linux", windows))]
+            if let Some(events) = &mut removals {
+                let tick = std::pin::pin!(interval.tick());
+                match futures::future::select(tick, events.next()).await {
+                    futures::future::Either::Left(_) => {}
+                    futures::future::Either::Right((Some(_), _)) => return Ok(()),
+                    futures::future::Either::Right((None, _)) => removals = None,
+                }
+                continue;
+            }
+            interval.tick().await;
+        }
+    }
+
+    /// Discard the contents of the input and/or output buffers
+    ///
+    /// Unlike the synchronous [`SerialPort::clear`], this also takes care of the I/O
+    /// this stream keeps in flight on the port so no stale data or spurious errors
+    /// surface afterwards:
+    ///
+    /// * On Unix the cached read readiness is reset before the buffers are flushed with
+    ///   `tcflush`, so the next read waits for data arriving after the purge.
+    /// * On Windows `PurgeComm` aborts the overlapped operations issued on behalf of
+    ///   this stream.  This function waits for the read to complete and discards its
+    ///   result (data read before the purge or the `ERROR_OPERATION_ABORTED` error)
+    ///   before new reads are issued.  The `ERROR_OPERATION_ABORTED` of an aborted
+    ///   write comes with the next write, which is then tried again.
+    ///
+    /// Taking `&mut self` guarantees no read or write through this stream is pending
+    /// while the buffers are cleared.
+    ///
+    /// [`SerialPort::clear`]: crate::SerialPort::clear
+    pub async fn clear_buffers(
+        &mut self,
+        buffer_to_clear: crate::ClearBuffer,
+    ) -> crate::Result<()> {
+        let input = matches!(
+            buffer_to_clear,
+            crate::ClearBuffer::Input | crate::ClearBuffer::All
+        );
+        let output = matches!(
+            buffer_to_clear,
+            crate::ClearBuffer::Output | crate::ClearBuffer::All
+        );
+
         #[cfg(unix)]
         {
-            self.inner.get_mut().write(buf)
+            let _ = output;
+            if input {
+                use futures::FutureExt;
+                if let Some(guard) = self.inner.readable().now_or_never() {
+                    guard?.clear_ready();
+                }
+            }
+            self.borrow().clear(buffer_to_clear)
         }
+
         #[cfg(windows)]
         {
-            self.inner.try_write(buf)
+            self.com.clear(buffer_to_clear)?;
+
+            let is_aborted =
+                |err: &std::io::Error| err.raw_os_error() == Some(ERROR_OPERATION_ABORTED as i32);
+            if input {
+                // The read in flight completes with data received before the purge or
+                // aborted, then everything it buffered goes too.
+                let mut scratch = [0u8; 256];
+                let mut completed = false;
+                loop {
+                    if !completed {
+                        self.inner.readable().await?;
+                    }
+                    match self.inner.try_read(&mut scratch) {
+                        Ok(0) => break,
+                        Ok(_) => completed = true,
+                        Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                            if completed {
+                                break;
+                            }
+                        }
+                        Err(err) if is_aborted(&err) => completed = true,
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+            }
+            if output {
+                // The error of an aborted write is only returned by the next one.
+                self.purged_write = true;
+            }
+            Ok(())
+        }
+    }
+
+    /// Stop reading from the port until [`resume_reads`](SerialStream::resume_reads)
+    ///
+    /// Reads through this stream stay pending (and `try_read()` reports
+    /// `WouldBlock`) while the port stays registered with the reactor, so another tool
+    /// can temporarily own the incoming data, e.g. to flash the attached device.
+    ///
+    /// With hardware flow control enabled RTS is deasserted, asking the other end to
+    /// stop sending, and asserted again on resume.
+    ///
+    /// On Windows data already fetched by the read this stream keeps in flight is
+    /// still returned after resuming.
+    pub fn pause_reads(&mut self) -> crate::Result<()> {
+        if self.paused.is_some() {
+            return Ok(());
+        }
+        if self.flow_control()? == crate::FlowControl::Hardware {
+            self.write_request_to_send(false)?;
+        }
+        self.paused = Some(None);
+        Ok(())
+    }
+
+    /// Resume reading after [`pause_reads`](SerialStream::pause_reads)
+    pub fn resume_reads(&mut self) -> crate::Result<()> {
+        let waker = match self.paused.take() {
+            Some(waker) => waker,
+            None => return Ok(()),
+        };
+        if self.flow_control()? == crate::FlowControl::Hardware {
+            self.write_request_to_send(true)?;
+        }
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Returns whether reads are paused.
+    pub fn reads_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    fn poll_resumed(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        match &mut self.get_mut().paused {
+            Some(waker) => {
+                *waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            None => Poll::Ready(()),
         }
     }
 
@@ -219,6 +1056,34 @@ impl SerialStream {
         let _ = self.inner.writable().await?;
         Ok(())
     }
+
+    /// Wait until fewer than `bytes` bytes are waiting in the output buffer.
+    ///
+    /// `writable()` only reports room in the buffer, which lets a writer queue up
+    /// seconds of data at low baud rates.  Streaming large payloads such as firmware
+    /// images with this in between writes bounds the amount of data queued in the
+    /// kernel/driver instead.  A threshold of `0` waits for the buffer to be empty.
+    ///
+    /// Drivers do not signal the output buffer draining, so this sleeps for roughly the
+    /// time the excess bytes take on the line at the current baud rate and checks
+    /// [`SerialPort::bytes_to_write`] again.
+    ///
+    /// [`SerialPort::bytes_to_write`]: crate::SerialPort::bytes_to_write
+    pub async fn wait_tx_below(&self, bytes: u32) -> crate::Result<()> {
+        let threshold = bytes.max(1);
+        loop {
+            let queued = self.bytes_to_write()?;
+            if queued < threshold {
+                return Ok(());
+            }
+
+            // 10 bits per character covers the usual 8N1 framing.
+            let excess = u64::from(queued - threshold + 1);
+            let baud_rate = u64::from(self.baud_rate()?.max(1));
+            let micros = (excess * 10_000_000 / baud_rate).clamp(1_000, 100_000);
+            tokio::time::sleep(Duration::from_micros(micros)).await;
+        }
+    }
 }
 
 #[cfg(unix)]
@@ -241,21 +1106,35 @@ impl AsyncRead for SerialStream {
     ///
     /// This function may encounter any standard I/O error except `WouldBlock`.
     fn poll_read(
-        self: Pin<&mut Self>,
+        mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<IoResult<()>> {
+        ready!(self.as_mut().poll_resumed(cx));
+
         loop {
             let mut guard = ready!(self.inner.poll_read_ready(cx))?;
 
+            let requested = buf.remaining();
             match guard.try_io(|inner| inner.get_ref().read(buf.initialize_unfilled())) {
-                Ok(Ok(bytes_read)) => {
+                Ok(read) => {
+                    let mut bytes_read = self.checked_read(read, requested)?;
+                    let this = &mut *self;
+                    if let (None, Some((decoder, sender))) = (&this.marks, &mut this.breaks) {
+                        let data = &mut buf.initialize_unfilled()[..bytes_read];
+                        let (len, breaks) = decoder.strip(data);
+                        if breaks > 0 {
+                            let _ = sender.unbounded_send(Ok(breaks));
+                        }
+                        if len == 0 && bytes_read > 0 {
+                            // Only breaks, which are not an end of file.
+                            continue;
+                        }
+                        bytes_read = len;
+                    }
                     buf.advance(bytes_read);
                     return Poll::Ready(Ok(()));
                 }
-                Ok(Err(err)) => {
-                    return Poll::Ready(Err(err));
-                }
                 Err(_would_block) => continue,
             }
         }
@@ -286,7 +1165,10 @@ impl AsyncWrite for SerialStream {
             let mut guard = ready!(self.inner.poll_write_ready(cx))?;
 
             match guard.try_io(|inner| inner.get_ref().write(buf)) {
-                Ok(result) => return Poll::Ready(result),
+                Ok(result) => {
+                    let result = self.checked(result);
+                    return Poll::Ready(self.get_mut().accepted(result));
+                }
                 Err(_would_block) => continue,
             }
         }
@@ -316,15 +1198,27 @@ impl AsyncRead for SerialStream {
         buf: &mut ReadBuf<'_>,
     ) -> Poll<IoResult<()>> {
         let mut self_ = self;
-        Pin::new(&mut self_.inner).poll_read(cx, buf)
+        ready!(self_.as_mut().poll_resumed(cx));
+        let requested = buf.remaining();
+        let before = buf.filled().len();
+        let read = ready!(Pin::new(&mut self_.inner).poll_read(cx, buf))
+            .map(|()| buf.filled().len() - before);
+        Poll::Ready(self_.checked_read(read, requested).map(drop))
     }
 }
 
 #[cfg(windows)]
 impl AsyncWrite for SerialStream {
     fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IoResult<usize>> {
-        let mut self_ = self;
-        Pin::new(&mut self_.inner).poll_write(cx, buf)
+        let self_ = self.get_mut();
+        let written = loop {
+            let written = ready!(Pin::new(&mut self_.inner).poll_write(cx, buf));
+            if !self_.aborted_by_purge(&written) {
+                break written;
+            }
+        };
+        let written = self_.checked(written);
+        Poll::Ready(self_.accepted(written))
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
@@ -338,6 +1232,7 @@ impl AsyncWrite for SerialStream {
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl crate::SerialPort for SerialStream {
     #[inline(always)]
     fn name(&self) -> Option<String> {
@@ -472,12 +1367,29 @@ impl crate::SerialPort for SerialStream {
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+impl ShutdownLayered for SerialStream {
+    fn finish(&mut self) -> futures::future::BoxFuture<'_, IoResult<()>> {
+        Box::pin(async move {
+            tokio::io::AsyncWriteExt::flush(self).await?;
+            self.wait_transmitted().await?;
+            Ok(())
+        })
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        None
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 impl Read for SerialStream {
     fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
         self.try_read(buf)
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl Write for SerialStream {
     fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
         self.try_write(buf)
@@ -512,19 +1424,267 @@ mod io {
 
 /// An extension trait for serialport::SerialPortBuilder
 ///
-/// This trait adds one method to SerialPortBuilder:
+/// This trait adds methods to SerialPortBuilder to open asynchronous ports, such as:
 ///
 /// - open_native_async
+/// - open_platform_async
+/// - open_async
 ///
-/// This method mirrors the `open_native` method of SerialPortBuilder
+/// The first mirrors the `open_native` method of SerialPortBuilder
+#[cfg(not(target_arch = "wasm32"))]
 pub trait SerialPortBuilderExt {
     /// Open a platform-specific interface to the port with the specified settings
     fn open_native_async(self) -> Result<SerialStream>;
+
+    /// Open the port with the specified settings, with access to its termios
+    ///
+    /// The stream dereferences to a [`SerialStream`] and adds termios and RS-485
+    /// access.
+    #[cfg(unix)]
+    fn open_platform_async(self) -> Result<UnixSerialStream>;
+
+    /// Open the port with the specified settings, with access to its `DCB`
+    ///
+    /// The stream dereferences to a [`SerialStream`] and adds `DCB` and `COMMPROP`
+    /// access.
+    #[cfg(windows)]
+    fn open_platform_async(self) -> Result<WindowsSerialStream>;
+
+    /// Check whether the device supports the specified settings, without opening
+    /// the port
+    ///
+    /// The same as [`PortConfig::validate`] with the path and line settings of the
+    /// builder.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io(NotFound)` if the port does not exist.
+    /// * an I/O error if the capabilities of the device cannot be read.
+    fn validate(&self) -> Result<validation::ValidationReport>;
+
+    /// Create a builder from the environment variables starting with `prefix`
+    ///
+    /// See [`PortConfig::from_env`] for the variables read and the errors.
+    ///
+    /// ```no_run
+    /// use tokio_serial::{SerialPortBuilder, SerialPortBuilderExt};
+    ///
+    /// # fn example() -> tokio_serial::Result<()> {
+    /// let port = SerialPortBuilder::from_env("MYAPP")?.open_native_async()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn from_env(prefix: &str) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Create a builder from a `serial://` URL
+    ///
+    /// See [`PortConfig`] for the format.
+    ///
+    /// ```no_run
+    /// use tokio_serial::{SerialPortBuilder, SerialPortBuilderExt};
+    ///
+    /// # fn example() -> tokio_serial::Result<()> {
+    /// let url = "serial:///dev/ttyUSB0?baud=115200&parity=even&flow=hardware";
+    /// let port = SerialPortBuilder::from_url(url)?.open_native_async()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// ## Errors
+    ///
+    /// * `InvalidInput` if `url` is not a valid port URL.
+    fn from_url(url: &str) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Open the first USB port matching `filter` with the specified settings
+    ///
+    /// The path of the builder is replaced by the one of the port found by
+    /// [`find_port`](inventory::find_port).  Fails with [`ErrorKind::NoDevice`] if no
+    /// port matches.
+    ///
+    /// With the `rt` feature, the ports are enumerated and the port is opened on
+    /// tokio's blocking pool like `open_async`.
+    fn open_usb_async(
+        self,
+        filter: &inventory::UsbFilter,
+    ) -> futures::future::BoxFuture<'static, Result<SerialStream>>;
+
+    /// Open the port of interface `interface` of the USB device `vid:pid` with the
+    /// specified settings
+    ///
+    /// Composite devices, such as debug probes with a console and a data channel,
+    /// expose one port per interface.  See
+    /// [`open_usb_async`](SerialPortBuilderExt::open_usb_async).
+    fn open_usb_interface_async(
+        self,
+        vid: u16,
+        pid: u16,
+        interface: u8,
+    ) -> futures::future::BoxFuture<'static, Result<SerialStream>>;
+
+    /// Open the port with the specified settings without blocking the runtime
+    ///
+    /// Opening a port makes blocking syscalls, and some drivers take a while to
+    /// initialize the device.  The port is opened on tokio's blocking pool and only
+    /// registered with the reactor once open.
+    #[cfg(feature = "rt")]
+    fn open_async(self) -> futures::future::BoxFuture<'static, Result<SerialStream>>;
+
+    /// Open the port with the specified settings, giving up after `timeout`
+    ///
+    /// Like [`open_async`](SerialPortBuilderExt::open_async), so an open hanging on
+    /// a flaky USB hub or a network backed tty cannot stall the runtime.  An open
+    /// still running when the deadline passes cannot be interrupted: the port is
+    /// closed as soon as it completes.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io(TimedOut)` if the port was not open within `timeout`.
+    #[cfg(feature = "rt")]
+    fn open_async_timeout(
+        self,
+        timeout: Duration,
+    ) -> futures::future::BoxFuture<'static, Result<SerialStream>>;
+
+    /// Open the port with the specified settings, retrying according to `policy`
+    ///
+    /// Only errors which go away by themselves are retried: the device not being
+    /// there yet (`NoDevice`, `Io(NotFound)`), its permissions not being set up yet
+    /// while udev settles (`Io(PermissionDenied)`), or another program using it.
+    /// Returns the error of the last attempt.
+    ///
+    /// With the `rt` feature, each attempt opens the port on tokio's blocking pool
+    /// like `open_async`.
+    fn open_async_retry(
+        self,
+        policy: &retry::RetryPolicy,
+    ) -> futures::future::BoxFuture<'static, Result<SerialStream>>;
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl SerialPortBuilderExt for SerialPortBuilder {
     /// Open a platform-specific interface to the port with the specified settings
     fn open_native_async(self) -> Result<SerialStream> {
         SerialStream::open(&self)
     }
+
+    #[cfg(unix)]
+    fn open_platform_async(self) -> Result<UnixSerialStream> {
+        SerialStream::open(&self).map(UnixSerialStream::new)
+    }
+
+    #[cfg(windows)]
+    fn open_platform_async(self) -> Result<WindowsSerialStream> {
+        SerialStream::open(&self).map(WindowsSerialStream::new)
+    }
+
+    fn validate(&self) -> Result<validation::ValidationReport> {
+        config::from_builder(self)?.validate()
+    }
+
+    fn from_env(prefix: &str) -> Result<Self> {
+        PortConfig::from_env(prefix).map(Self::from)
+    }
+
+    fn from_url(url: &str) -> Result<Self> {
+        url.parse::<PortConfig>().map(Self::from)
+    }
+
+    fn open_usb_async(
+        self,
+        filter: &inventory::UsbFilter,
+    ) -> futures::future::BoxFuture<'static, Result<SerialStream>> {
+        let filter = filter.clone();
+        Box::pin(async move {
+            let find = move || {
+                let port = inventory::find_port(&filter)?.ok_or_else(|| {
+                    Error::new(ErrorKind::NoDevice, "no USB port matches the filter")
+                })?;
+                log::debug!("found {} matching {:?}", port.port_name, filter);
+                Ok::<_, Error>(port.port_name)
+            };
+            #[cfg(feature = "rt")]
+            {
+                let path = tokio::task::spawn_blocking(find)
+                    .await
+                    .map_err(|e| Error::new(ErrorKind::Unknown, e.to_string()))??;
+                self.path(path).open_async().await
+            }
+            #[cfg(not(feature = "rt"))]
+            SerialStream::open(&self.path(find()?))
+        })
+    }
+
+    fn open_usb_interface_async(
+        self,
+        vid: u16,
+        pid: u16,
+        interface: u8,
+    ) -> futures::future::BoxFuture<'static, Result<SerialStream>> {
+        self.open_usb_async(&inventory::UsbFilter {
+            vid: Some(vid),
+            pid: Some(pid),
+            interface: Some(interface),
+            ..inventory::UsbFilter::default()
+        })
+    }
+
+    #[cfg(feature = "rt")]
+    fn open_async(self) -> futures::future::BoxFuture<'static, Result<SerialStream>> {
+        Box::pin(async move {
+            let port = tokio::task::spawn_blocking(move || mio_serial::SerialStream::open(&self))
+                .await
+                .map_err(|e| Error::new(ErrorKind::Unknown, e.to_string()))??;
+            SerialStream::from_mio(port)
+        })
+    }
+
+    #[cfg(feature = "rt")]
+    fn open_async_timeout(
+        self,
+        timeout: Duration,
+    ) -> futures::future::BoxFuture<'static, Result<SerialStream>> {
+        Box::pin(async move {
+            match tokio::time::timeout(timeout, self.open_async()).await {
+                Ok(port) => port,
+                Err(_) => Err(Error::new(
+                    ErrorKind::Io(std::io::ErrorKind::TimedOut),
+                    "timed out opening the port",
+                )),
+            }
+        })
+    }
+
+    fn open_async_retry(
+        self,
+        policy: &retry::RetryPolicy,
+    ) -> futures::future::BoxFuture<'static, Result<SerialStream>> {
+        let policy = policy.clone();
+        Box::pin(async move {
+            let transient = |e: &Error| {
+                let transient = matches!(
+                    e.kind(),
+                    ErrorKind::NoDevice
+                        | ErrorKind::Io(
+                            std::io::ErrorKind::NotFound
+                                | std::io::ErrorKind::PermissionDenied
+                                | std::io::ErrorKind::AlreadyExists
+                                | std::io::ErrorKind::ResourceBusy
+                        )
+                );
+                if transient {
+                    log::debug!("retrying to open the port: {}", e);
+                }
+                transient
+            };
+            #[cfg(feature = "rt")]
+            let open = || self.clone().open_async();
+            #[cfg(not(feature = "rt"))]
+            let open = || futures::future::ready(SerialStream::open(&self));
+            policy.retry_if(open, transient).await
+        })
+    }
 }
@@ -0,0 +1,484 @@
+//! LIN bus frames
+//!
+//! LIN 2.x is a single wire bus at up to 20000 baud, 8N1, driven by one master.
+//! The master starts every frame with a header: a break of at least 13 bit
+//! times, the sync byte `0x55` and the protected identifier, a 6 bit frame id
+//! with two parity bits.  The response, 1 to 8 data bytes and a checksum, is then
+//! published by the master or by one of the slaves.  Which node publishes the
+//! response of each frame, and its length, are set by the description of the
+//! cluster rather than sent on the wire.
+//!
+//! [`LinMaster`] sends headers and responses, and reads the responses of slaves.
+//! [`LinSlave`] waits for the headers of the master, on Unix.
+//!
+//! ```no_run
+//! use tokio_serial::lin::LinMaster;
+//! use tokio_serial::SerialStream;
+//!
+//! # async fn example() -> tokio_serial::Result<()> {
+//! let port = SerialStream::open(&tokio_serial::new("/dev/ttyUSB0", 19200))?;
+//! let mut lin = LinMaster::new(port)?;
+//! // The adapter reads back what it sends on the bus.
+//! lin.set_echo(true);
+//! lin.send_frame(0x10, &[0x01, 0x02]).await?;
+//! let status = lin.request(0x21, 4).await?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::delay::wait;
+use crate::{ErrorKind, SerialPort, SerialStream};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use std::io;
+use std::time::Duration;
+
+/// The usual baud rate of LIN buses
+pub const DEFAULT_BAUD_RATE: u32 = 19200;
+
+/// The sync byte following the break of headers
+pub const SYNC: u8 = 0x55;
+
+/// The largest frame id
+pub const MAX_ID: u8 = 0x3f;
+
+/// The most data bytes a response carries
+pub const MAX_DATA: usize = 8;
+
+/// The frame id of master requests, diagnostic frames sent by the master
+pub const MASTER_REQUEST_ID: u8 = 0x3c;
+
+/// The frame id of slave responses, diagnostic frames sent by a slave
+pub const SLAVE_RESPONSE_ID: u8 = 0x3d;
+
+/// The protected identifier of frame `id`: the id and its two parity bits.
+///
+/// # Panics
+///
+/// If `id` is over [`MAX_ID`].
+pub fn protected_id(id: u8) -> u8 {
+    assert!(id <= MAX_ID, "invalid LIN frame id {:#04x}", id);
+    let bit = |n: u8| (id >> n) & 1;
+    let p0 = bit(0) ^ bit(1) ^ bit(2) ^ bit(4);
+    let p1 = !(bit(1) ^ bit(3) ^ bit(4) ^ bit(5)) & 1;
+    id | p0 << 6 | p1 << 7
+}
+
+/// The frame id of protected identifier `pid`, `None` if its parity is wrong.
+pub fn frame_id(pid: u8) -> Option<u8> {
+    let id = pid & MAX_ID;
+    if protected_id(id) == pid {
+        Some(id)
+    } else {
+        None
+    }
+}
+
+/// The checksum model of LIN responses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Checksum {
+    /// Over the data bytes only, as in LIN 1.x
+    Classic,
+    /// Over the protected identifier and the data bytes, as in LIN 2.x
+    ///
+    /// The diagnostic frames, [`MASTER_REQUEST_ID`] and [`SLAVE_RESPONSE_ID`],
+    /// keep the classic checksum.
+    Enhanced,
+}
+
+impl Checksum {
+    /// The checksum of a response of frame `pid` carrying `data`.
+    ///
+    /// Both models are the inverted 8 bit sum with carry of their bytes.
+    pub fn compute(self, pid: u8, data: &[u8]) -> u8 {
+        let classic = matches!(
+            frame_id(pid),
+            Some(MASTER_REQUEST_ID) | Some(SLAVE_RESPONSE_ID)
+        );
+        let mut sum: u16 = match self {
+            Checksum::Enhanced if !classic => u16::from(pid),
+            _ => 0,
+        };
+        for &byte in data {
+            sum += u16::from(byte);
+            if sum > 0xff {
+                sum -= 0xff;
+            }
+        }
+        !(sum as u8)
+    }
+}
+
+fn invalid_data(message: &str) -> crate::Error {
+    crate::Error::new(ErrorKind::Io(io::ErrorKind::InvalidData), message)
+}
+
+fn check_response(id: u8, len: usize) {
+    assert!(
+        (1..=MAX_DATA).contains(&len),
+        "LIN responses carry 1 to 8 bytes, not {}",
+        len
+    );
+    assert!(id <= MAX_ID, "invalid LIN frame id {:#04x}", id);
+}
+
+// A response of `len` data bytes plus its checksum, in at most `timeout`.
+async fn read_response<F>(
+    next: F,
+    pid: u8,
+    len: usize,
+    checksum: Checksum,
+    timeout: Duration,
+) -> crate::Result<Vec<u8>>
+where
+    F: std::future::Future<Output = crate::Result<Vec<u8>>>,
+{
+    let mut response = tokio::time::timeout(timeout, next).await.map_err(|_| {
+        crate::Error::new(
+            ErrorKind::Io(io::ErrorKind::TimedOut),
+            "no LIN response in time",
+        )
+    })??;
+    let sent = response.pop();
+    debug_assert_eq!(response.len(), len);
+    if sent != Some(checksum.compute(pid, &response)) {
+        return Err(invalid_data("LIN checksum mismatch"));
+    }
+    Ok(response)
+}
+
+/// The master of a LIN bus
+///
+/// Breaks last 13 bit times at the baud rate of the port, followed by a break
+/// delimiter of one bit time; both are waited for in a busy loop, timers are
+/// too coarse for them.  Checksums are [enhanced](Checksum::Enhanced) by default.
+///
+/// LIN transceivers read back everything on the bus, including what the master
+/// sends.  With [`set_echo`](LinMaster::set_echo) the master reads its own bytes
+/// back, and fails the frames where they do not match.
+#[derive(Debug)]
+pub struct LinMaster {
+    port: SerialStream,
+    checksum: Checksum,
+    break_time: Duration,
+    break_delimiter: Duration,
+    response_timeout: Duration,
+    echo: bool,
+}
+
+impl LinMaster {
+    /// Drive a LIN bus through `port`, at its current baud rate.
+    pub fn new(port: SerialStream) -> crate::Result<Self> {
+        let bit = Duration::from_secs_f64(1.0 / f64::from(port.settings()?.baud_rate));
+        Ok(Self {
+            port,
+            checksum: Checksum::Enhanced,
+            break_time: bit * 13,
+            break_delimiter: bit,
+            response_timeout: Duration::from_millis(50),
+            echo: false,
+        })
+    }
+
+    /// Set the checksum model of the responses sent and read.
+    pub fn set_checksum(&mut self, checksum: Checksum) {
+        self.checksum = checksum;
+    }
+
+    /// Set how long the break lasts, 13 bit times at least.
+    pub fn set_break_time(&mut self, duration: Duration) {
+        self.break_time = duration;
+    }
+
+    /// Set how long the line stays idle between the break and the sync byte, one
+    /// bit time at least.
+    pub fn set_break_delimiter(&mut self, duration: Duration) {
+        self.break_delimiter = duration;
+    }
+
+    /// Set how long to wait for responses, and for the echo of the bytes sent,
+    /// 50ms by default.
+    ///
+    /// The specification allows a slave 1.4 times the nominal duration of its
+    /// response, a few milliseconds; the default also leaves room for the
+    /// latency of USB adapters.
+    pub fn set_response_timeout(&mut self, timeout: Duration) {
+        self.response_timeout = timeout;
+    }
+
+    /// Set whether the port reads back the bytes sent, as LIN transceivers do.
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    /// Returns a reference to the underlying port.
+    pub fn get_ref(&self) -> &SerialStream {
+        &self.port
+    }
+
+    /// Consumes the master, returning the underlying port.
+    pub fn into_inner(self) -> SerialStream {
+        self.port
+    }
+
+    /// Send the header of frame `id`: the break, the sync byte and the protected
+    /// identifier.
+    ///
+    /// With echo, returns once the header is read back.
+    ///
+    /// # Panics
+    ///
+    /// If `id` is over [`MAX_ID`].
+    pub async fn send_header(&mut self, id: u8) -> crate::Result<()> {
+        let pid = protected_id(id);
+        // A break would cut the bytes still in the output queue short.
+        self.port.wait_transmitted().await?;
+        self.port.set_break()?;
+        wait(self.break_time).await;
+        self.port.clear_break()?;
+        wait(self.break_delimiter).await;
+        self.port.write_all(&[SYNC, pid]).await?;
+        self.port.flush().await?;
+        if self.echo {
+            let port = &mut self.port;
+            // The break reads back as a NUL byte on most drivers, or not at all.
+            let skip = async move {
+                let mut last = [0u8; 2];
+                while last != [SYNC, pid] {
+                    last[0] = last[1];
+                    last[1] = port.read_u8().await?;
+                }
+                Ok::<_, io::Error>(())
+            };
+            tokio::time::timeout(self.response_timeout, skip)
+                .await
+                .map_err(|_| invalid_data("LIN header not read back"))??;
+        }
+        Ok(())
+    }
+
+    /// Send frame `id` with the master publishing `data` as its response.
+    ///
+    /// # Panics
+    ///
+    /// If `id` is over [`MAX_ID`], or `data` does not hold 1 to 8 bytes.
+    pub async fn send_frame(&mut self, id: u8, data: &[u8]) -> crate::Result<()> {
+        check_response(id, data.len());
+        self.send_header(id).await?;
+        let pid = protected_id(id);
+        let mut response = data.to_vec();
+        response.push(self.checksum.compute(pid, data));
+        self.port.write_all(&response).await?;
+        self.port.flush().await?;
+        if self.echo {
+            let mut echo = vec![0u8; response.len()];
+            let port = &mut self.port;
+            let read = async move {
+                port.read_exact(&mut echo).await?;
+                Ok::<_, io::Error>(echo)
+            };
+            let echo = tokio::time::timeout(self.response_timeout, read)
+                .await
+                .map_err(|_| invalid_data("LIN response not read back"))??;
+            if echo != response {
+                return Err(invalid_data("LIN bus collision"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the header of frame `id` and read the `len` data bytes a slave
+    /// publishes in response.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io(TimedOut)` if the response is not complete within the response
+    ///   timeout, usually because no slave answers the frame.
+    /// * `Io(InvalidData)` if the checksum of the response does not match.
+    ///
+    /// # Panics
+    ///
+    /// If `id` is over [`MAX_ID`], or `len` is not 1 to 8.
+    pub async fn request(&mut self, id: u8, len: usize) -> crate::Result<Vec<u8>> {
+        check_response(id, len);
+        self.send_header(id).await?;
+        let port = &mut self.port;
+        let next = async move {
+            let mut response = vec![0u8; len + 1];
+            port.read_exact(&mut response).await?;
+            Ok::<_, crate::Error>(response)
+        };
+        read_response(
+            next,
+            protected_id(id),
+            len,
+            self.checksum,
+            self.response_timeout,
+        )
+        .await
+    }
+}
+
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Received {
+    Byte(u8),
+    Break,
+}
+
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Header {
+    Idle,
+    Break,
+    Sync,
+}
+
+/// A slave node of a LIN bus
+///
+/// Headers are found by their break, which aligns the slave on the frames of
+/// the master whatever came before.  With
+/// [error marking](SerialStream::set_error_marking) enabled on the port, breaks
+/// are told apart from the data and only they start headers.  Without it, the
+/// break reads as a NUL byte like on most UARTs, and a NUL byte followed by the
+/// sync byte and a valid protected identifier is taken as a header.
+///
+/// The slave reads back its own responses, which are skipped while looking for
+/// the next header.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct LinSlave {
+    port: SerialStream,
+    checksum: Checksum,
+    response_timeout: Duration,
+    received: std::collections::VecDeque<Received>,
+}
+
+#[cfg(unix)]
+impl LinSlave {
+    /// Listen to a LIN bus through `port`.
+    pub fn new(port: SerialStream) -> Self {
+        Self {
+            port,
+            checksum: Checksum::Enhanced,
+            response_timeout: Duration::from_millis(50),
+            received: std::collections::VecDeque::new(),
+        }
+    }
+
+    /// Set the checksum model of the responses sent and read.
+    pub fn set_checksum(&mut self, checksum: Checksum) {
+        self.checksum = checksum;
+    }
+
+    /// Set how long to wait for responses published by other nodes, 50ms by
+    /// default.
+    pub fn set_response_timeout(&mut self, timeout: Duration) {
+        self.response_timeout = timeout;
+    }
+
+    /// Returns a reference to the underlying port.
+    pub fn get_ref(&self) -> &SerialStream {
+        &self.port
+    }
+
+    /// Consumes the slave, returning the underlying port.
+    pub fn into_inner(self) -> SerialStream {
+        self.port
+    }
+
+    async fn next(&mut self) -> crate::Result<Received> {
+        while self.received.is_empty() {
+            let events = self.port.read_with_errors().await?;
+            if events.is_empty() {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            for event in events {
+                match event {
+                    crate::LineEvent::Data(data) => {
+                        self.received.extend(data.into_iter().map(Received::Byte))
+                    }
+                    // Bytes are not expected to have parity, the checksum
+                    // catches framing errors.
+                    crate::LineEvent::ParityError(byte) => {
+                        self.received.push_back(Received::Byte(byte))
+                    }
+                    crate::LineEvent::Break => self.received.push_back(Received::Break),
+                }
+            }
+        }
+        Ok(self.received.pop_front().unwrap())
+    }
+
+    /// Wait for the next header of the master, returning its frame id.
+    ///
+    /// Headers with a wrong protected identifier are skipped.
+    pub async fn next_header(&mut self) -> crate::Result<u8> {
+        let marked = self.port.error_marking();
+        let mut header = Header::Idle;
+        loop {
+            let received = self.next().await?;
+            header = match (header, received) {
+                (Header::Sync, Received::Byte(pid)) => match frame_id(pid) {
+                    Some(id) => return Ok(id),
+                    None => {
+                        log::debug!("skipping LIN header with bad parity {:#04x}", pid);
+                        Header::Idle
+                    }
+                },
+                (_, Received::Break) => Header::Break,
+                (_, Received::Byte(0)) if !marked => Header::Break,
+                (Header::Break, Received::Byte(SYNC)) => Header::Sync,
+                _ => Header::Idle,
+            };
+        }
+    }
+
+    /// Publish `data` as the response of frame `id`.
+    ///
+    /// # Panics
+    ///
+    /// If `id` is over [`MAX_ID`], or `data` does not hold 1 to 8 bytes.
+    pub async fn respond(&mut self, id: u8, data: &[u8]) -> crate::Result<()> {
+        check_response(id, data.len());
+        let mut response = data.to_vec();
+        response.push(self.checksum.compute(protected_id(id), data));
+        self.port.write_all(&response).await?;
+        self.port.flush().await?;
+        Ok(())
+    }
+
+    /// Read the `len` data bytes another node publishes in response to frame
+    /// `id`, after its header.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io(TimedOut)` if the response is not complete within the response
+    ///   timeout.
+    /// * `Io(InvalidData)` if the checksum of the response does not match, or a
+    ///   break interrupts it.
+    ///
+    /// # Panics
+    ///
+    /// If `id` is over [`MAX_ID`], or `len` is not 1 to 8.
+    pub async fn read_response(&mut self, id: u8, len: usize) -> crate::Result<Vec<u8>> {
+        check_response(id, len);
+        let timeout = self.response_timeout;
+        let checksum = self.checksum;
+        let next = async {
+            let mut response = Vec::with_capacity(len + 1);
+            while response.len() < len + 1 {
+                match self.next().await? {
+                    Received::Byte(byte) => response.push(byte),
+                    Received::Break => {
+                        // Leave the break to the next header.
+                        self.received.push_front(Received::Break);
+                        return Err(invalid_data("LIN response interrupted by a break"));
+                    }
+                }
+            }
+            Ok::<_, crate::Error>(response)
+        };
+        read_response(next, protected_id(id), len, checksum, timeout).await
+    }
+}
@@ -0,0 +1,153 @@
+//! UUCP-style advisory lock files
+//!
+//! Tools like minicom, pppd or cu do not look at `TIOCEXCL`.  Instead they
+//! cooperate through lock files named `LCK..<device>` in a shared lock directory
+//! which contain the PID of the owning process in the HDB UUCP format (ten
+//! right-aligned ASCII digits followed by a newline).
+//!
+//! On top of the lock file the port's file descriptor is `flock`ed exclusively,
+//! which is what newer tools such as `lockdev` based programs check.  `serialport`
+//! already holds a shared `flock` on ports opened non-exclusively, so releasing the
+//! lock downgrades back to that instead of unlocking the descriptor.
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+
+/// The directory lock files are created in unless specified otherwise.
+pub(crate) const DEFAULT_LOCK_DIR: &str = "/var/lock";
+
+/// A held UUCP lock, removed again on drop.
+#[derive(Debug)]
+pub(crate) struct UucpLock {
+    path: PathBuf,
+    fd: RawFd,
+    shared: bool,
+}
+
+impl UucpLock {
+    /// Lock the device at `device` whose open descriptor is `fd`, placing the lock
+    /// file in `dir`.
+    ///
+    /// `shared` tells whether `fd` held a shared `flock` which has to be restored on
+    /// release.
+    pub(crate) fn acquire(
+        device: &str,
+        fd: RawFd,
+        dir: &Path,
+        shared: bool,
+    ) -> crate::Result<Self> {
+        let path = dir.join(lock_file_name(device));
+
+        if unsafe { libc::flock(fd, libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let err = io::Error::last_os_error();
+            return Err(if err.kind() == io::ErrorKind::WouldBlock {
+                locked_error(device)
+            } else {
+                err.into()
+            });
+        }
+
+        match create_lock_file(&path) {
+            Ok(()) => Ok(Self { path, fd, shared }),
+            Err(err) => {
+                release_flock(fd, shared);
+                Err(if err.kind() == io::ErrorKind::AlreadyExists {
+                    locked_error(device)
+                } else {
+                    err.into()
+                })
+            }
+        }
+    }
+
+    /// The path of the lock file.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for UucpLock {
+    fn drop(&mut self) {
+        log::trace!("removing lock file: {}", self.path.display());
+        fs::remove_file(&self.path).ok();
+        release_flock(self.fd, self.shared);
+    }
+}
+
+/// Whether a live process holds the lock of `device` in the default lock directory.
+pub(crate) fn is_locked(device: &str) -> bool {
+    let path = Path::new(DEFAULT_LOCK_DIR).join(lock_file_name(device));
+    path.exists() && !is_stale(&path)
+}
+
+fn release_flock(fd: RawFd, shared: bool) {
+    if shared {
+        unsafe { libc::flock(fd, libc::LOCK_SH | libc::LOCK_NB) };
+    }
+}
+
+fn locked_error(device: &str) -> crate::Error {
+    crate::Error::new(
+        crate::ErrorKind::Io(io::ErrorKind::AlreadyExists),
+        format!("{} is locked by another process", device),
+    )
+}
+
+/// Build the `LCK..` file name for a device path.
+///
+/// Symlinks such as `/dev/serial/by-id/...` are resolved first so every alias
+/// of a device maps to the same lock.  Devices in sub-directories of `/dev` have
+/// their separators replaced, e.g. `/dev/pts/3` becomes `LCK..pts_3`.
+fn lock_file_name(device: &str) -> String {
+    let device = fs::canonicalize(device).unwrap_or_else(|_| PathBuf::from(device));
+    let name = device
+        .strip_prefix("/dev")
+        .unwrap_or(&device)
+        .to_string_lossy()
+        .trim_start_matches('/')
+        .replace('/', "_");
+    format!("LCK..{}", name)
+}
+
+fn create_lock_file(path: &Path) -> io::Result<()> {
+    // Two attempts: the second one after removing a stale lock left behind by a
+    // process which no longer exists.
+    for _ in 0..2 {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                log::trace!("created lock file: {}", path.display());
+                return file.write_all(format!("{:>10}\n", std::process::id()).as_bytes());
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                if !is_stale(path) {
+                    return Err(err);
+                }
+                log::debug!("removing stale lock file: {}", path.display());
+                fs::remove_file(path).ok();
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Err(io::ErrorKind::AlreadyExists.into())
+}
+
+/// A lock is stale when it does not name a running process.
+fn is_stale(path: &Path) -> bool {
+    let pid = match fs::read_to_string(path) {
+        // Another process may have created the file without writing its PID yet.
+        Ok(contents) if contents.trim().is_empty() => return false,
+        Ok(contents) => contents.trim().parse::<libc::pid_t>().ok(),
+        // Lock files removed in the meantime are as good as stale.
+        Err(err) => return err.kind() == io::ErrorKind::NotFound,
+    };
+
+    match pid {
+        Some(pid) if pid > 0 => {
+            let alive = unsafe { libc::kill(pid, 0) } == 0;
+            !alive && io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH)
+        }
+        // Garbage in a lock file means nobody can be holding it.
+        _ => true,
+    }
+}
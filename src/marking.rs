@@ -0,0 +1,150 @@
+//! Receiving parity and framing errors in band, with `PARMRK`
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::io::RawFd;
+
+/// An item of the received data, see `SerialStream::read_with_errors`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineEvent {
+    /// Bytes received without error
+    Data(Vec<u8>),
+    /// A byte received with a parity or framing error
+    ///
+    /// The termios interface does not tell which of the two happened.
+    ParityError(u8),
+    /// A break condition
+    ///
+    /// A NUL byte received with a parity or framing error is reported the same way.
+    Break,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Normal,
+    // Received `\377`
+    Mark,
+    // Received `\377\0`
+    MarkNul,
+}
+
+/// Decoder of the `\377 \0 <byte>` sequences `PARMRK` inserts in the data
+#[derive(Debug)]
+pub(crate) struct MarkDecoder {
+    state: State,
+}
+
+impl MarkDecoder {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: State::Normal,
+        }
+    }
+
+    pub(crate) fn decode(&mut self, data: &[u8], events: &mut Vec<LineEvent>) {
+        fn push_data(events: &mut Vec<LineEvent>, byte: u8) {
+            match events.last_mut() {
+                Some(LineEvent::Data(data)) => data.push(byte),
+                _ => events.push(LineEvent::Data(vec![byte])),
+            }
+        }
+
+        for &byte in data {
+            self.state = match (self.state, byte) {
+                (State::Normal, 0xff) => State::Mark,
+                (State::Normal, byte) => {
+                    push_data(events, byte);
+                    State::Normal
+                }
+                // A literal `\377` is doubled.
+                (State::Mark, 0xff) => {
+                    push_data(events, 0xff);
+                    State::Normal
+                }
+                (State::Mark, 0) => State::MarkNul,
+                (State::Mark, byte) => {
+                    push_data(events, 0xff);
+                    push_data(events, byte);
+                    State::Normal
+                }
+                (State::MarkNul, 0) => {
+                    events.push(LineEvent::Break);
+                    State::Normal
+                }
+                (State::MarkNul, byte) => {
+                    events.push(LineEvent::ParityError(byte));
+                    State::Normal
+                }
+            };
+        }
+    }
+
+    /// Remove the marks from `data` in place, keeping the bytes received with errors
+    ///
+    /// Returns the length of what is left and the number of breaks removed.  A
+    /// `\377` followed by anything but `\377` or `\0` is not produced by the
+    /// driver and is dropped.
+    pub(crate) fn strip(&mut self, data: &mut [u8]) -> (usize, u32) {
+        let mut len = 0;
+        let mut breaks = 0;
+        for i in 0..data.len() {
+            let byte = data[i];
+            let (state, keep) = match (self.state, byte) {
+                (State::Normal, 0xff) => (State::Mark, false),
+                (State::Normal, _) => (State::Normal, true),
+                (State::Mark, 0) => (State::MarkNul, false),
+                (State::Mark, _) => (State::Normal, byte == 0xff),
+                (State::MarkNul, 0) => {
+                    breaks += 1;
+                    (State::Normal, false)
+                }
+                (State::MarkNul, _) => (State::Normal, true),
+            };
+            self.state = state;
+            if keep {
+                data[len] = byte;
+                len += 1;
+            }
+        }
+        (len, breaks)
+    }
+}
+
+fn update(fd: RawFd, change: impl FnOnce(&mut libc::termios)) -> crate::Result<()> {
+    let mut termios = MaybeUninit::<libc::termios>::uninit();
+    if unsafe { libc::tcgetattr(fd, termios.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let mut termios = unsafe { termios.assume_init() };
+    change(&mut termios);
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) } == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Mark breaks only
+///
+/// Breaks are marked instead of read as NUL bytes or turned into signals, the
+/// checking of parity is left as it is.
+pub(crate) fn set_breaks(fd: RawFd) -> crate::Result<()> {
+    update(fd, |termios| {
+        termios.c_iflag |= libc::PARMRK;
+        termios.c_iflag &= !(libc::IGNBRK | libc::BRKINT | libc::ISTRIP);
+    })
+}
+
+/// Turn error marking on or off
+///
+/// When enabled, erroneous bytes and breaks are marked instead of ignored or
+/// turned into signals, and bytes are no longer stripped to seven bits so that
+/// literal `\377`s can be told apart from marks.
+pub(crate) fn set(fd: RawFd, enabled: bool) -> crate::Result<()> {
+    update(fd, |termios| {
+        if enabled {
+            termios.c_iflag |= libc::PARMRK | libc::INPCK;
+            termios.c_iflag &= !(libc::IGNPAR | libc::IGNBRK | libc::BRKINT | libc::ISTRIP);
+        } else {
+            termios.c_iflag &= !libc::PARMRK;
+        }
+    })
+}
@@ -0,0 +1,689 @@
+//! In-memory ports for tests
+//!
+//! [`MockSerialStream`] plays a [`Script`] of the writes a device expects and the
+//! responses it sends back, so drivers can be tested against a simulated device:
+//!
+//! ```
+//! use std::time::Duration;
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//! use tokio_serial::mock::{MockSerialStream, Script};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> std::io::Result<()> {
+//! let mut port = MockSerialStream::new(
+//!     Script::new()
+//!         .expect_write(b"ATI\r")
+//!         .delay(Duration::from_millis(10))
+//!         .respond(b"MODEM 1.0\r"),
+//! );
+//!
+//! port.write_all(b"ATI\r").await?;
+//! let mut reply = [0u8; 10];
+//! port.read_exact(&mut reply).await?;
+//! assert_eq!(&reply, b"MODEM 1.0\r");
+//! port.assert_done();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`MockBus`] simulates a multi-drop bus, such as RS-485: every
+//! [`BusEndpoint`] created from it receives what the other endpoints write, so a
+//! master and any number of slaves can be tested without hardware.  Optionally,
+//! writes overlapping in time [collide](MockBus::with_collisions).
+//!
+//! ```
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//! use tokio_serial::mock::MockBus;
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> std::io::Result<()> {
+//! let bus = MockBus::new();
+//! let mut master = bus.endpoint();
+//! let mut slave = bus.endpoint();
+//!
+//! master.write_all(b"\x01\x03").await?;
+//! let mut request = [0u8; 2];
+//! slave.read_exact(&mut request).await?;
+//! assert_eq!(&request, b"\x01\x03");
+//! # Ok(())
+//! # }
+//! ```
+use crate::{
+    AsyncSerialPort, ClearBuffer, DataBits, Error, ErrorKind, FlowControl, Parity, SerialPort,
+    SerialSettings, ShutdownLayered, StopBits,
+};
+
+use futures::future::{self, BoxFuture};
+use futures::ready;
+use futures::task::noop_waker_ref;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// What a receiver gets in place of every byte of a colliding write
+pub const COLLISION_BYTE: u8 = 0xff;
+
+#[derive(Debug, Default)]
+struct Endpoint {
+    rx: VecDeque<u8>,
+    waker: Option<Waker>,
+}
+
+#[derive(Debug, Default)]
+struct Bus {
+    endpoints: HashMap<usize, Endpoint>,
+    next_id: usize,
+    echo: bool,
+    // The baud rate used to compute how long a write occupies the bus
+    collision_baud_rate: Option<u32>,
+    // The endpoint transmitting and when its transmission ends
+    busy: Option<(usize, Instant)>,
+    collisions: usize,
+}
+
+impl Bus {
+    fn transmit(&mut self, from: usize, data: &[u8]) {
+        let mut collided = false;
+        if let Some(baud_rate) = self.collision_baud_rate {
+            let now = Instant::now();
+            // One start bit, eight data bits and one stop bit.
+            let duration = Duration::from_secs_f64(data.len() as f64 * 10.0 / baud_rate as f64);
+            let mut end = now + duration;
+            if let Some((owner, until)) = self.busy {
+                if now < until {
+                    collided = owner != from;
+                    end = end.max(until);
+                }
+            }
+            self.busy = Some((from, end));
+        }
+        if collided {
+            self.collisions += 1;
+        }
+
+        for (&id, endpoint) in self.endpoints.iter_mut() {
+            if id == from && !self.echo {
+                continue;
+            }
+            if collided {
+                endpoint.rx.extend(data.iter().map(|_| COLLISION_BYTE));
+            } else {
+                endpoint.rx.extend(data);
+            }
+            if let Some(waker) = endpoint.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// A simulated multi-drop bus
+///
+/// Cloning a `MockBus` gives another handle to the same bus.
+#[derive(Debug, Clone, Default)]
+pub struct MockBus {
+    bus: Arc<Mutex<Bus>>,
+}
+
+impl MockBus {
+    /// A bus without collisions, writes are delivered instantly.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A bus where writes collide if they overlap in time
+    ///
+    /// Every write occupies the bus for as long as sending it at `baud_rate` would
+    /// take.  A write from another endpoint while the bus is occupied collides: all
+    /// receivers get [`COLLISION_BYTE`] in place of each of its bytes.
+    pub fn with_collisions(baud_rate: u32) -> Self {
+        let bus = Self::new();
+        bus.lock().collision_baud_rate = Some(baud_rate.max(1));
+        bus
+    }
+
+    /// Set whether endpoints receive their own writes, like RS-485 transceivers
+    /// with the receiver left enabled.
+    pub fn set_echo(&self, echo: bool) {
+        self.lock().echo = echo;
+    }
+
+    /// Connect a new endpoint to the bus.
+    ///
+    /// It receives data written from the moment it is connected.
+    pub fn endpoint(&self) -> BusEndpoint {
+        let mut bus = self.lock();
+        let id = bus.next_id;
+        bus.next_id += 1;
+        bus.endpoints.insert(id, Endpoint::default());
+        BusEndpoint {
+            id,
+            bus: self.clone(),
+        }
+    }
+
+    /// Returns how many writes collided so far.
+    pub fn collisions(&self) -> usize {
+        self.lock().collisions
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Bus> {
+        self.bus.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A port connected to a [`MockBus`]
+///
+/// Writes complete immediately, modem control lines and baud rate changes are
+/// accepted and ignored.
+#[derive(Debug)]
+pub struct BusEndpoint {
+    id: usize,
+    bus: MockBus,
+}
+
+impl BusEndpoint {
+    /// Returns the bus the endpoint is connected to.
+    pub fn bus(&self) -> &MockBus {
+        &self.bus
+    }
+
+    /// Returns how many received bytes are waiting to be read.
+    pub fn bytes_to_read(&self) -> usize {
+        self.bus.lock().endpoints[&self.id].rx.len()
+    }
+}
+
+impl Drop for BusEndpoint {
+    fn drop(&mut self) {
+        self.bus.lock().endpoints.remove(&self.id);
+    }
+}
+
+impl AsyncRead for BusEndpoint {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut bus = self.bus.lock();
+        let endpoint = bus.endpoints.get_mut(&self.id).expect("endpoint removed");
+        if endpoint.rx.is_empty() {
+            endpoint.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let n = endpoint.rx.len().min(buf.remaining());
+        for byte in endpoint.rx.drain(..n) {
+            buf.put_slice(&[byte]);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for BusEndpoint {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.bus.lock().transmit(self.id, buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSerialPort for BusEndpoint {
+    fn port_name(&self) -> Option<String> {
+        Some(format!("mock-bus-{}", self.id))
+    }
+
+    fn change_baud_rate(&mut self, _baud_rate: u32) -> crate::Result<()> {
+        Ok(())
+    }
+
+    fn set_dtr(&mut self, _level: bool) -> crate::Result<()> {
+        Ok(())
+    }
+
+    fn set_rts(&mut self, _level: bool) -> crate::Result<()> {
+        Ok(())
+    }
+
+    fn set_break_condition(&mut self, _asserted: bool) -> crate::Result<()> {
+        Ok(())
+    }
+}
+
+impl ShutdownLayered for BusEndpoint {
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(future::ready(Ok(())))
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Write(Vec<u8>),
+    Respond(Vec<u8>),
+    Delay(Duration),
+}
+
+/// What a [`MockSerialStream`] expects to be written and answers, in order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Script {
+    steps: VecDeque<Step>,
+}
+
+impl Script {
+    /// An empty script
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expect `data` to be written next.
+    ///
+    /// The data may be written in any number of chunks.
+    pub fn expect_write(mut self, data: impl AsRef<[u8]>) -> Self {
+        self.steps.push_back(Step::Write(data.as_ref().to_vec()));
+        self
+    }
+
+    /// Make `data` available to read.
+    pub fn respond(mut self, data: impl AsRef<[u8]>) -> Self {
+        self.steps.push_back(Step::Respond(data.as_ref().to_vec()));
+        self
+    }
+
+    /// Wait for `duration` before going on with the next steps.
+    ///
+    /// Writes wait for the delay to be over, like the following steps.
+    pub fn delay(mut self, duration: Duration) -> Self {
+        self.steps.push_back(Step::Delay(duration));
+        self
+    }
+
+    /// Returns whether all the steps were played.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+/// A port playing a [`Script`]
+///
+/// Steps are played in order: responses become readable as soon as the writes
+/// expected before them are done.  Writing anything else than the next expected
+/// write fails with `InvalidData`, reading waits for the next response.  Once the
+/// script is over, reads wait forever like a silent device and writes fail.
+///
+/// Settings and modem control lines are recorded, the state of the lines driven
+/// by the device is set with [`set_clear_to_send`](MockSerialStream::set_clear_to_send)
+/// and the like.
+#[derive(Debug)]
+pub struct MockSerialStream {
+    script: Script,
+    rx: Mutex<VecDeque<u8>>,
+    sleep: Option<Pin<Box<Sleep>>>,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+    settings: SerialSettings,
+    timeout: Duration,
+    rts: bool,
+    dtr: bool,
+    cts: bool,
+    dsr: bool,
+    ri: bool,
+    cd: bool,
+    break_state: AtomicBool,
+}
+
+impl MockSerialStream {
+    /// A port playing `script`, at 9600 bauds 8N1.
+    pub fn new(script: Script) -> Self {
+        Self {
+            script,
+            rx: Mutex::new(VecDeque::new()),
+            sleep: None,
+            read_waker: None,
+            write_waker: None,
+            settings: SerialSettings::new(9600),
+            timeout: Duration::ZERO,
+            rts: false,
+            dtr: false,
+            cts: false,
+            dsr: false,
+            ri: false,
+            cd: false,
+            break_state: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the steps left to play.
+    pub fn remaining(&self) -> &Script {
+        &self.script
+    }
+
+    /// Panics unless the whole script was played.
+    #[track_caller]
+    pub fn assert_done(&self) {
+        assert!(
+            self.script.is_empty(),
+            "the script is not over, remaining steps: {:?}",
+            self.script.steps
+        );
+    }
+
+    /// Returns the settings the port was configured with.
+    pub fn settings(&self) -> SerialSettings {
+        self.settings
+    }
+
+    /// Returns the level of the RTS line.
+    pub fn request_to_send(&self) -> bool {
+        self.rts
+    }
+
+    /// Returns the level of the DTR line.
+    pub fn data_terminal_ready(&self) -> bool {
+        self.dtr
+    }
+
+    /// Returns whether a break is being transmitted.
+    pub fn break_state(&self) -> bool {
+        self.break_state.load(Ordering::Relaxed)
+    }
+
+    /// Set the level of the CTS line.
+    pub fn set_clear_to_send(&mut self, level: bool) {
+        self.cts = level;
+    }
+
+    /// Set the level of the DSR line.
+    pub fn set_data_set_ready(&mut self, level: bool) {
+        self.dsr = level;
+    }
+
+    /// Set the level of the RI line.
+    pub fn set_ring_indicator(&mut self, level: bool) {
+        self.ri = level;
+    }
+
+    /// Set the level of the CD line.
+    pub fn set_carrier_detect(&mut self, level: bool) {
+        self.cd = level;
+    }
+
+    // Play the steps up to the next expected write, pending while a delay runs.
+    fn advance(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            match self.script.steps.front() {
+                Some(Step::Respond(data)) => {
+                    self.rx.get_mut().unwrap().extend(data);
+                }
+                Some(Step::Delay(duration)) => {
+                    let duration = *duration;
+                    let sleep = self
+                        .sleep
+                        .get_or_insert_with(|| Box::pin(tokio::time::sleep(duration)));
+                    ready!(sleep.as_mut().poll(cx));
+                    self.sleep = None;
+                }
+                _ => return Poll::Ready(()),
+            }
+            self.script.steps.pop_front();
+            self.wake();
+        }
+    }
+
+    fn wake(&mut self) {
+        let wakers = [self.read_waker.take(), self.write_waker.take()];
+        for waker in IntoIterator::into_iter(wakers).flatten() {
+            waker.wake();
+        }
+    }
+}
+
+impl AsyncRead for MockSerialStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let _ = this.advance(cx);
+        let rx = this.rx.get_mut().unwrap();
+        if rx.is_empty() {
+            this.read_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let n = rx.len().min(buf.remaining());
+        for byte in rx.drain(..n) {
+            buf.put_slice(&[byte]);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for MockSerialStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.advance(cx).is_pending() {
+            this.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let expected = match this.script.steps.front_mut() {
+            Some(Step::Write(expected)) => expected,
+            _ => {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected write {:02x?} after the end of the script", buf),
+                )))
+            }
+        };
+        let n = expected.len().min(buf.len());
+        if buf[..n] != expected[..n] {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected write {:02x?}, expected {:02x?}", buf, expected),
+            )));
+        }
+        expected.drain(..n);
+        if expected.is_empty() {
+            this.script.steps.pop_front();
+            this.wake();
+            let _ = this.advance(cx);
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn would_block<T>(poll: Poll<io::Result<T>>) -> io::Result<T> {
+    match poll {
+        Poll::Ready(result) => result,
+        Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+    }
+}
+
+/// Non-blocking: fails with `WouldBlock` when there is nothing to read.
+impl Read for MockSerialStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut buf = ReadBuf::new(buf);
+        would_block(Pin::new(&mut *self).poll_read(&mut cx, &mut buf))?;
+        Ok(buf.filled().len())
+    }
+}
+
+/// Non-blocking: fails with `WouldBlock` while a delay runs.
+impl Write for MockSerialStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        would_block(Pin::new(self).poll_write(&mut cx, buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for MockSerialStream {
+    fn name(&self) -> Option<String> {
+        Some("mock".to_string())
+    }
+
+    fn baud_rate(&self) -> crate::Result<u32> {
+        Ok(self.settings.baud_rate)
+    }
+
+    fn data_bits(&self) -> crate::Result<DataBits> {
+        Ok(self.settings.data_bits)
+    }
+
+    fn flow_control(&self) -> crate::Result<FlowControl> {
+        Ok(self.settings.flow_control)
+    }
+
+    fn parity(&self) -> crate::Result<Parity> {
+        Ok(self.settings.parity)
+    }
+
+    fn stop_bits(&self) -> crate::Result<StopBits> {
+        Ok(self.settings.stop_bits)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> crate::Result<()> {
+        self.settings.baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> crate::Result<()> {
+        self.settings.data_bits = data_bits;
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> crate::Result<()> {
+        self.settings.flow_control = flow_control;
+        Ok(())
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> crate::Result<()> {
+        self.settings.parity = parity;
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> crate::Result<()> {
+        self.settings.stop_bits = stop_bits;
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> crate::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, level: bool) -> crate::Result<()> {
+        self.rts = level;
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, level: bool) -> crate::Result<()> {
+        self.dtr = level;
+        Ok(())
+    }
+
+    fn read_clear_to_send(&mut self) -> crate::Result<bool> {
+        Ok(self.cts)
+    }
+
+    fn read_data_set_ready(&mut self) -> crate::Result<bool> {
+        Ok(self.dsr)
+    }
+
+    fn read_ring_indicator(&mut self) -> crate::Result<bool> {
+        Ok(self.ri)
+    }
+
+    fn read_carrier_detect(&mut self) -> crate::Result<bool> {
+        Ok(self.cd)
+    }
+
+    /// Returns the number of bytes of the responses played and not read yet.
+    fn bytes_to_read(&self) -> crate::Result<u32> {
+        Ok(self.rx.lock().unwrap().len() as u32)
+    }
+
+    /// Writes complete immediately, nothing is ever waiting to be written.
+    fn bytes_to_write(&self) -> crate::Result<u32> {
+        Ok(0)
+    }
+
+    /// Discard the responses played and not read yet.
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> crate::Result<()> {
+        if let ClearBuffer::Input | ClearBuffer::All = buffer_to_clear {
+            self.rx.lock().unwrap().clear();
+        }
+        Ok(())
+    }
+
+    /// Cloning a `MockSerialStream` is not supported.
+    fn try_clone(&self) -> crate::Result<Box<dyn SerialPort>> {
+        Err(Error::new(
+            ErrorKind::Io(io::ErrorKind::Unsupported),
+            "Cannot clone mock ports",
+        ))
+    }
+
+    fn set_break(&self) -> crate::Result<()> {
+        self.break_state.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn clear_break(&self) -> crate::Result<()> {
+        self.break_state.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl ShutdownLayered for MockSerialStream {
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(future::ready(Ok(())))
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        None
+    }
+}
@@ -0,0 +1,402 @@
+//! Logical channels multiplexed over one port
+//!
+//! Firmware with a single UART often combines a console, telemetry and bulk data
+//! on it.  A [`Mux`] splits a port into up to 256 independent [`Channel`]s, each
+//! an `AsyncRead` and `AsyncWrite` of its own.  Frames start with [`SYNC`] and
+//! carry the channel, their kind, the length of the payload and a CRC-16, see the
+//! `mux` module of the `no_std` `tokio-serial-core` crate which firmware can
+//! share.  Every channel has its own credit based flow control: a channel nobody
+//! reads does not hold the others up, its peer just stops sending on it.
+//!
+//! The frames are moved by a [`MuxDriver`], a future to spawn on the runtime.
+//!
+//! ```no_run
+//! use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+//! use tokio_serial::mux::Mux;
+//!
+//! # async fn example(port: tokio_serial::SerialStream) -> std::io::Result<()> {
+//! let (mux, driver) = Mux::new(port);
+//! tokio::spawn(driver);
+//!
+//! let mut console = mux.channel(0)?;
+//! let telemetry = mux.channel(1)?;
+//! console.write_all(b"reboot\n").await?;
+//!
+//! let mut lines = BufReader::new(telemetry).lines();
+//! while let Some(line) = lines.next_line().await? {
+//!     println!("{}", line);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+pub use tokio_serial_core::mux::{
+    frame, frame_credit, MuxDeframer, MuxError, MuxFrame, CREDIT, DATA, SYNC,
+};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+#[derive(Debug, Default)]
+struct ChannelState {
+    rx: VecDeque<u8>,
+    tx: VecDeque<u8>,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+    // Bytes the peer allows us to send
+    send_credit: usize,
+    // Bytes read since the last grant to the peer
+    to_grant: usize,
+}
+
+impl ChannelState {
+    fn wake_reader(&mut self) {
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn wake_writer(&mut self) {
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    channels: HashMap<u8, ChannelState>,
+    window: usize,
+    max_payload: usize,
+    corrupted: u64,
+    driver: Option<Waker>,
+    // Set once the driver stopped, with the error which stopped it if any
+    stopped: Option<Option<io::ErrorKind>>,
+}
+
+impl Shared {
+    fn wake_driver(&mut self) {
+        if let Some(waker) = self.driver.take() {
+            waker.wake();
+        }
+    }
+
+    fn receive(&mut self, frame: &MuxFrame<'_>) {
+        let window = self.window;
+        let state = match self.channels.get_mut(&frame.channel) {
+            Some(state) => state,
+            // Closed channel
+            None => return,
+        };
+        match frame.kind {
+            DATA => {
+                // More than granted, the peer is broken.
+                let n = frame
+                    .payload
+                    .len()
+                    .min(window.saturating_sub(state.rx.len()));
+                state.rx.extend(&frame.payload[..n]);
+                state.wake_reader();
+            }
+            CREDIT => {
+                if let Some(credit) = frame.credit() {
+                    state.send_credit += credit as usize;
+                }
+            }
+            // A later version of the protocol
+            _ => {}
+        }
+    }
+
+    // Frame the grants and the data which can be sent into `out`.
+    fn send(&mut self, out: &mut Vec<u8>) {
+        let window = self.window;
+        let max_payload = self.max_payload.max(1);
+        for (&id, state) in self.channels.iter_mut() {
+            if state.to_grant > 0 && (state.to_grant * 2 >= window || state.rx.is_empty()) {
+                frame_credit(id, state.to_grant as u32, |chunk| {
+                    out.extend_from_slice(chunk)
+                });
+                state.to_grant = 0;
+            }
+            let mut sent = false;
+            while !state.tx.is_empty() && state.send_credit > 0 {
+                let n = state.tx.len().min(state.send_credit).min(max_payload);
+                let payload: Vec<u8> = state.tx.drain(..n).collect();
+                let _ = frame(id, DATA, &payload, |chunk| out.extend_from_slice(chunk));
+                state.send_credit -= n;
+                sent = true;
+            }
+            if sent {
+                state.wake_writer();
+            }
+        }
+    }
+}
+
+fn lock(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A handle opening channels on a multiplexed port
+///
+/// Cloning the handle gives another handle to the same port.
+#[derive(Debug, Clone)]
+pub struct Mux {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Mux {
+    /// Multiplex `port`, returning the handle and the driver moving the frames.
+    ///
+    /// Channels receive up to 4 KiB ahead of the application and data frames carry
+    /// at most 256 bytes, which the firmware must be able to receive.
+    pub fn new<S>(port: S) -> (Self, MuxDriver<S>) {
+        let shared = Arc::new(Mutex::new(Shared {
+            channels: HashMap::new(),
+            window: 4096,
+            max_payload: 256,
+            corrupted: 0,
+            driver: None,
+            stopped: None,
+        }));
+        let driver = MuxDriver {
+            port,
+            shared: shared.clone(),
+            deframer: MuxDeframer::new(vec![0u8; usize::from(u16::MAX)]),
+            out: Vec::new(),
+        };
+        (Self { shared }, driver)
+    }
+
+    /// Set how many bytes a channel opened afterwards receives ahead of the
+    /// application, 4 KiB by default.
+    ///
+    /// As much is buffered for sending.
+    pub fn set_window(&self, window: usize) {
+        lock(&self.shared).window = window.clamp(1, u32::MAX as usize);
+    }
+
+    /// Set the largest payload of the data frames sent, 256 bytes by default.
+    pub fn set_max_payload(&self, max_payload: usize) {
+        lock(&self.shared).max_payload = max_payload.clamp(1, usize::from(u16::MAX));
+    }
+
+    /// Returns how many corrupted frames were received so far.
+    pub fn corrupted_frames(&self) -> u64 {
+        lock(&self.shared).corrupted
+    }
+
+    /// Open channel `id`, granting the peer the window.
+    ///
+    /// ## Errors
+    ///
+    /// * `AlreadyExists` if the channel is open already.
+    /// * `BrokenPipe` if the driver stopped.
+    pub fn channel(&self, id: u8) -> io::Result<Channel> {
+        let mut shared = lock(&self.shared);
+        if shared.stopped.is_some() {
+            return Err(stopped());
+        }
+        if shared.channels.contains_key(&id) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("channel {} is already open", id),
+            ));
+        }
+        let to_grant = shared.window;
+        shared.channels.insert(
+            id,
+            ChannelState {
+                to_grant,
+                ..ChannelState::default()
+            },
+        );
+        shared.wake_driver();
+        Ok(Channel {
+            id,
+            shared: self.shared.clone(),
+        })
+    }
+}
+
+fn stopped() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "the multiplexer stopped")
+}
+
+/// The future moving the frames of a [`Mux`] to and from the port
+///
+/// It completes when the port reaches end of file, or fails with the errors of the
+/// port.  Channels then read end of file and fail to write.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct MuxDriver<S> {
+    port: S,
+    shared: Arc<Mutex<Shared>>,
+    deframer: MuxDeframer<Vec<u8>>,
+    // Framed bytes not written to the port yet
+    out: Vec<u8>,
+}
+
+impl<S> MuxDriver<S> {
+    fn stop(&mut self, result: io::Result<()>) -> Poll<io::Result<()>> {
+        let mut shared = lock(&self.shared);
+        shared.stopped = Some(result.as_ref().err().map(io::Error::kind));
+        for state in shared.channels.values_mut() {
+            state.wake_reader();
+            state.wake_writer();
+        }
+        Poll::Ready(result)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Future for MuxDriver<S> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            let mut progress = false;
+            lock(&this.shared).driver = Some(cx.waker().clone());
+
+            let mut received = [0u8; 1024];
+            let mut buf = ReadBuf::new(&mut received);
+            match Pin::new(&mut this.port).poll_read(cx, &mut buf) {
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => return this.stop(Ok(())),
+                Poll::Ready(Ok(())) => {
+                    let mut shared = lock(&this.shared);
+                    for &byte in buf.filled() {
+                        match this.deframer.push(byte) {
+                            Ok(Some(frame)) => shared.receive(&frame),
+                            Ok(None) => {}
+                            Err(_) => shared.corrupted += 1,
+                        }
+                    }
+                    progress = true;
+                }
+                Poll::Ready(Err(e)) => return this.stop(Err(e)),
+                Poll::Pending => {}
+            }
+
+            if this.out.is_empty() {
+                lock(&this.shared).send(&mut this.out);
+            }
+            while !this.out.is_empty() {
+                match Pin::new(&mut this.port).poll_write(cx, &this.out) {
+                    Poll::Ready(Ok(0)) => return this.stop(Err(io::ErrorKind::WriteZero.into())),
+                    Poll::Ready(Ok(n)) => {
+                        this.out.drain(..n);
+                        progress = true;
+                    }
+                    Poll::Ready(Err(e)) => return this.stop(Err(e)),
+                    Poll::Pending => break,
+                }
+            }
+
+            if !progress {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+/// One logical channel of a [`Mux`]
+///
+/// Dropping the channel closes it, data the peer still sends on it is dropped.
+#[derive(Debug)]
+pub struct Channel {
+    id: u8,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Channel {
+    /// Returns the id of the channel.
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        lock(&self.shared).channels.remove(&self.id);
+    }
+}
+
+impl AsyncRead for Channel {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut shared = lock(&self.shared);
+        let stopped = shared.stopped;
+        let state = shared.channels.get_mut(&self.id).expect("channel removed");
+        if state.rx.is_empty() {
+            return match stopped {
+                Some(Some(kind)) => Poll::Ready(Err(kind.into())),
+                Some(None) => Poll::Ready(Ok(())),
+                None => {
+                    state.read_waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            };
+        }
+        let n = state.rx.len().min(buf.remaining());
+        let (front, back) = state.rx.as_slices();
+        let first = front.len().min(n);
+        buf.put_slice(&front[..first]);
+        buf.put_slice(&back[..n - first]);
+        state.rx.drain(..n);
+        state.to_grant += n;
+        shared.wake_driver();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Channel {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut shared = lock(&self.shared);
+        if shared.stopped.is_some() {
+            return Poll::Ready(Err(stopped()));
+        }
+        let window = shared.window;
+        let state = shared.channels.get_mut(&self.id).expect("channel removed");
+        let n = buf.len().min(window.saturating_sub(state.tx.len()));
+        if n == 0 && !buf.is_empty() {
+            state.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        state.tx.extend(&buf[..n]);
+        shared.wake_driver();
+        Poll::Ready(Ok(n))
+    }
+
+    /// Waits until the data written was handed to the port, which takes as long as
+    /// the peer does not grant credit for it.
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut shared = lock(&self.shared);
+        let is_stopped = shared.stopped.is_some();
+        let state = shared.channels.get_mut(&self.id).expect("channel removed");
+        if state.tx.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+        if is_stopped {
+            return Poll::Ready(Err(stopped()));
+        }
+        state.write_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
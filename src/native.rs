@@ -0,0 +1,277 @@
+//! Platform-specific serial streams
+//!
+//! [`SerialPortBuilderExt::open_platform_async`](crate::SerialPortBuilderExt::open_platform_async)
+//! opens a [`UnixSerialStream`] or a [`WindowsSerialStream`].  They dereference to the
+//! portable [`SerialStream`] and add what only makes sense on their platform.  Use
+//! [`SerialPortBuilderExt::open_native_async`](crate::SerialPortBuilderExt::open_native_async) or
+//! [`SerialStream::open`] to get a portable stream, or convert with `into()`.
+use crate::SerialStream;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+macro_rules! native_stream {
+    ($(#[$attr:meta])* $name:ident) => {
+        $(#[$attr])*
+        #[derive(Debug)]
+        pub struct $name(SerialStream);
+
+        impl $name {
+            pub(crate) fn new(stream: SerialStream) -> Self {
+                Self(stream)
+            }
+
+            /// Get the portable stream back.
+            pub fn into_inner(self) -> SerialStream {
+                self.0
+            }
+        }
+
+        impl From<$name> for SerialStream {
+            fn from(stream: $name) -> Self {
+                stream.0
+            }
+        }
+
+        impl Deref for $name {
+            type Target = SerialStream;
+
+            fn deref(&self) -> &SerialStream {
+                &self.0
+            }
+        }
+
+        impl DerefMut for $name {
+            fn deref_mut(&mut self) -> &mut SerialStream {
+                &mut self.0
+            }
+        }
+
+        impl AsyncRead for $name {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.0).poll_read(cx, buf)
+            }
+        }
+
+        impl AsyncWrite for $name {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                Pin::new(&mut self.0).poll_write(cx, buf)
+            }
+
+            fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.0).poll_flush(cx)
+            }
+
+            fn poll_shutdown(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.0).poll_shutdown(cx)
+            }
+        }
+    };
+}
+
+#[cfg(unix)]
+native_stream! {
+    /// A serial stream with access to the termios of the port
+    ///
+    /// On Linux it can also configure the RS-485 mode of the UART.
+    UnixSerialStream
+}
+
+#[cfg(windows)]
+native_stream! {
+    /// A serial stream with access to the `DCB` and `COMMPROP` of the port
+    WindowsSerialStream
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::UnixSerialStream;
+
+    use std::io;
+    use std::mem::MaybeUninit;
+    use std::os::unix::io::AsRawFd;
+
+    fn check(res: libc::c_int) -> crate::Result<()> {
+        if res == -1 {
+            Err(io::Error::last_os_error().into())
+        } else {
+            Ok(())
+        }
+    }
+
+    impl AsRawFd for UnixSerialStream {
+        fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+            self.0.as_raw_fd()
+        }
+    }
+
+    impl UnixSerialStream {
+        /// Read the termios of the port.
+        pub fn termios(&self) -> crate::Result<libc::termios> {
+            let mut termios = MaybeUninit::<libc::termios>::uninit();
+            check(unsafe { libc::tcgetattr(self.as_raw_fd(), termios.as_mut_ptr()) })?;
+            Ok(unsafe { termios.assume_init() })
+        }
+
+        /// Apply `termios` to the port immediately.
+        ///
+        /// This gives access to flags `SerialPort` has no setter for, such as
+        /// `HUPCL` or the control characters.  A stream whose termios was changed
+        /// this way may report other settings than the builder it was opened with.
+        pub fn set_termios(&mut self, termios: &libc::termios) -> crate::Result<()> {
+            check(unsafe { libc::tcsetattr(self.as_raw_fd(), libc::TCSANOW, termios) })
+        }
+    }
+
+    #[cfg(all(
+        target_os = "linux",
+        not(any(target_arch = "sparc", target_arch = "sparc64"))
+    ))]
+    mod rs485 {
+        use super::{check, UnixSerialStream};
+        use crate::native::Rs485Config;
+
+        use std::os::unix::io::AsRawFd;
+        use std::time::Duration;
+
+        // `struct serial_rs485` from <linux/serial.h>
+        #[repr(C)]
+        #[derive(Default)]
+        struct SerialRs485 {
+            flags: u32,
+            delay_rts_before_send: u32,
+            delay_rts_after_send: u32,
+            padding: [u32; 5],
+        }
+
+        const SER_RS485_ENABLED: u32 = 1 << 0;
+        const SER_RS485_RTS_ON_SEND: u32 = 1 << 1;
+        const SER_RS485_RTS_AFTER_SEND: u32 = 1 << 2;
+        const SER_RS485_RX_DURING_TX: u32 = 1 << 4;
+
+        impl UnixSerialStream {
+            /// Read the RS-485 configuration of the UART.
+            ///
+            /// ## Errors
+            ///
+            /// * `Io(Other)` with `ENOTTY` if the driver has no RS-485 support.
+            pub fn rs485(&self) -> crate::Result<Rs485Config> {
+                let mut raw = SerialRs485::default();
+                check(unsafe { libc::ioctl(self.as_raw_fd(), libc::TIOCGRS485 as _, &mut raw) })?;
+                Ok(Rs485Config {
+                    enabled: raw.flags & SER_RS485_ENABLED != 0,
+                    rts_on_send: raw.flags & SER_RS485_RTS_ON_SEND != 0,
+                    rts_after_send: raw.flags & SER_RS485_RTS_AFTER_SEND != 0,
+                    rx_during_tx: raw.flags & SER_RS485_RX_DURING_TX != 0,
+                    delay_rts_before_send: Duration::from_millis(raw.delay_rts_before_send.into()),
+                    delay_rts_after_send: Duration::from_millis(raw.delay_rts_after_send.into()),
+                })
+            }
+
+            /// Configure the RS-485 mode of the UART.
+            ///
+            /// The driver toggles RTS around transmissions to drive the transceiver,
+            /// with the delays rounded down to milliseconds.
+            pub fn set_rs485(&mut self, config: &Rs485Config) -> crate::Result<()> {
+                let millis = |delay: Duration| delay.as_millis().min(u32::MAX.into()) as u32;
+                let flag = |set: bool, flag: u32| if set { flag } else { 0 };
+                let raw = SerialRs485 {
+                    flags: flag(config.enabled, SER_RS485_ENABLED)
+                        | flag(config.rts_on_send, SER_RS485_RTS_ON_SEND)
+                        | flag(config.rts_after_send, SER_RS485_RTS_AFTER_SEND)
+                        | flag(config.rx_during_tx, SER_RS485_RX_DURING_TX),
+                    delay_rts_before_send: millis(config.delay_rts_before_send),
+                    delay_rts_after_send: millis(config.delay_rts_after_send),
+                    ..SerialRs485::default()
+                };
+                check(unsafe { libc::ioctl(self.as_raw_fd(), libc::TIOCSRS485 as _, &raw) })
+            }
+        }
+    }
+}
+
+/// The RS-485 mode of a Linux UART, see [`UnixSerialStream::set_rs485`]
+#[cfg(all(
+    target_os = "linux",
+    not(any(target_arch = "sparc", target_arch = "sparc64"))
+))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rs485Config {
+    /// Whether the RS-485 mode is enabled
+    pub enabled: bool,
+    /// The logical level of RTS while sending
+    pub rts_on_send: bool,
+    /// The logical level of RTS after sending
+    pub rts_after_send: bool,
+    /// Whether to keep receiving while sending, to read back the transmission
+    pub rx_during_tx: bool,
+    /// How long to assert RTS before sending
+    pub delay_rts_before_send: std::time::Duration,
+    /// How long to keep RTS asserted after sending
+    pub delay_rts_after_send: std::time::Duration,
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::WindowsSerialStream;
+
+    use std::io;
+    use std::mem;
+    use std::os::windows::io::{AsRawHandle, RawHandle};
+    use windows_sys::Win32::Devices::Communication::{
+        GetCommProperties, GetCommState, SetCommState, COMMPROP, DCB,
+    };
+
+    impl AsRawHandle for WindowsSerialStream {
+        fn as_raw_handle(&self) -> RawHandle {
+            self.0.as_raw_handle()
+        }
+    }
+
+    impl WindowsSerialStream {
+        /// Read the device control block of the port.
+        pub fn comm_state(&self) -> crate::Result<DCB> {
+            let mut dcb: DCB = unsafe { mem::zeroed() };
+            dcb.DCBlength = mem::size_of::<DCB>() as u32;
+            if unsafe { GetCommState(self.as_raw_handle() as _, &mut dcb) } == 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            Ok(dcb)
+        }
+
+        /// Apply a device control block to the port.
+        ///
+        /// This gives access to settings `SerialPort` has no setter for, such as the
+        /// XON/XOFF characters and limits or `fAbortOnError`.
+        pub fn set_comm_state(&mut self, dcb: &DCB) -> crate::Result<()> {
+            if unsafe { SetCommState(self.as_raw_handle() as _, dcb) } == 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            Ok(())
+        }
+
+        /// Read the capabilities the driver reports for the port.
+        pub fn comm_properties(&self) -> crate::Result<COMMPROP> {
+            let mut properties = COMMPROP::default();
+            if unsafe { GetCommProperties(self.as_raw_handle() as _, &mut properties) } == 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            Ok(properties)
+        }
+    }
+}
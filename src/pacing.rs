@@ -0,0 +1,232 @@
+//! Sending at the pace of the line
+//!
+//! USB adapters accept data as fast as the host sends it and buffer it, so a write
+//! returns long before the bytes are on the wire.  A [`PacedWriter`] holds the
+//! bytes back and passes them to the port no faster than the line sends them, with
+//! optional gaps between characters and between frames, for device simulators and
+//! protocols whose receivers rely on the timing.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use tokio::io::AsyncWriteExt;
+//! use tokio_serial::pacing::PacedWriter;
+//! use tokio_serial::SerialSettings;
+//!
+//! # async fn example(port: tokio_serial::SerialStream) -> std::io::Result<()> {
+//! let mut port = PacedWriter::new(port, &SerialSettings::new(9600));
+//! port.set_frame_gap(Duration::from_millis(20));
+//!
+//! // Each flush ends a frame.
+//! port.write_all(b"\x02STATUS\x03").await?;
+//! port.flush().await?;
+//! port.write_all(b"\x02RESET\x03").await?;
+//! port.flush().await?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::{AsyncSerialPort, SerialSettings, ShutdownLayered};
+
+use futures::future::BoxFuture;
+use futures::ready;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+// How far ahead of the line bytes are passed to the port.  Timers are not precise
+// enough to release fast characters one by one, so they go in bursts this long.
+const BURST: Duration = Duration::from_millis(1);
+
+/// A port wrapper releasing written bytes at the line rate
+///
+/// Bytes are passed to the port when the line would start sending them, or in
+/// bursts of at most a millisecond of data at fast baud rates.  Each character
+/// takes the [time of a character](SerialSettings::char_time) at the configured
+/// settings plus the [character gap](PacedWriter::set_char_gap), and a
+/// [frame gap](PacedWriter::set_frame_gap) follows every flush.
+///
+/// Reads go straight to the wrapped port.
+#[derive(Debug)]
+pub struct PacedWriter<S> {
+    inner: S,
+    settings: SerialSettings,
+    char_gap: Duration,
+    frame_gap: Duration,
+    // When the line is done with the bytes released so far
+    line_free: Instant,
+    // Whether a frame gap is due before the next byte
+    frame_ended: bool,
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl<S> PacedWriter<S> {
+    /// Pace the writes to `inner`, configured with `settings`.
+    pub fn new(inner: S, settings: &SerialSettings) -> Self {
+        let now = Instant::now();
+        Self {
+            inner,
+            settings: *settings,
+            char_gap: Duration::ZERO,
+            frame_gap: Duration::ZERO,
+            line_free: now,
+            frame_ended: false,
+            sleep: Box::pin(tokio::time::sleep_until(now)),
+        }
+    }
+
+    /// Set the settings used to compute the time of a character.
+    ///
+    /// Changing the baud rate through [`AsyncSerialPort::change_baud_rate`] updates
+    /// them.
+    pub fn set_settings(&mut self, settings: &SerialSettings) {
+        self.settings = *settings;
+    }
+
+    /// Returns the settings used to compute the time of a character.
+    pub fn settings(&self) -> &SerialSettings {
+        &self.settings
+    }
+
+    /// Set the idle time after every character, none by default.
+    pub fn set_char_gap(&mut self, gap: Duration) {
+        self.char_gap = gap;
+    }
+
+    /// Returns the idle time after every character.
+    pub fn char_gap(&self) -> Duration {
+        self.char_gap
+    }
+
+    /// Set the idle time between frames, none by default.
+    ///
+    /// A frame ends when the writer is flushed.
+    pub fn set_frame_gap(&mut self, gap: Duration) {
+        self.frame_gap = gap;
+    }
+
+    /// Returns the idle time between frames.
+    pub fn frame_gap(&self) -> Duration {
+        self.frame_gap
+    }
+
+    /// Returns a reference to the wrapped port.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped port.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Returns the wrapped port.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // Wait until `at`.
+    fn poll_until(&mut self, cx: &mut Context<'_>, at: Instant) -> Poll<()> {
+        if Instant::now() >= at {
+            return Poll::Ready(());
+        }
+        self.sleep.as_mut().reset(at);
+        self.sleep.as_mut().poll(cx)
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for PacedWriter<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for PacedWriter<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        if this.frame_ended {
+            this.line_free += this.frame_gap;
+            this.frame_ended = false;
+        }
+        let line_free = this.line_free;
+        ready!(this.poll_until(cx, line_free));
+
+        // Keep the cadence after a late wake up, start afresh after an idle line.
+        let now = Instant::now();
+        let start = if now - this.line_free < BURST {
+            this.line_free
+        } else {
+            now
+        };
+        let per_char = this.settings.char_time() + this.char_gap;
+        let burst = (BURST.as_nanos() / per_char.as_nanos().max(1)).max(1) as usize;
+        let n = ready!(Pin::new(&mut this.inner).poll_write(cx, &buf[..burst.min(buf.len())]))?;
+        this.line_free = start + per_char * n as u32;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(Pin::new(&mut this.inner).poll_flush(cx))?;
+        this.frame_ended = true;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl<S: AsyncSerialPort> AsyncSerialPort for PacedWriter<S> {
+    fn port_name(&self) -> Option<String> {
+        self.inner.port_name()
+    }
+
+    fn change_baud_rate(&mut self, baud_rate: u32) -> crate::Result<()> {
+        self.inner.change_baud_rate(baud_rate)?;
+        self.settings.baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn set_dtr(&mut self, level: bool) -> crate::Result<()> {
+        self.inner.set_dtr(level)
+    }
+
+    fn set_rts(&mut self, level: bool) -> crate::Result<()> {
+        self.inner.set_rts(level)
+    }
+
+    fn set_break_condition(&mut self, asserted: bool) -> crate::Result<()> {
+        self.inner.set_break_condition(asserted)
+    }
+}
+
+/// Finishing waits until the line is done with the bytes released.
+impl<S: ShutdownLayered> ShutdownLayered for PacedWriter<S> {
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        let line_free = self.line_free;
+        Box::pin(async move {
+            tokio::time::sleep_until(line_free).await;
+            Ok(())
+        })
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        Some(&mut self.inner)
+    }
+}
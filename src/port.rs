@@ -0,0 +1,66 @@
+//! A portable interface to asynchronous serial ports
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::SerialPort;
+
+/// An asynchronous serial port
+///
+/// `AsyncSerialPort` is the surface shared by all serial port backends: a byte stream
+/// through [`AsyncRead`]/[`AsyncWrite`] plus the line control protocol code commonly
+/// needs.  Protocol and codec code written against it runs unchanged on a
+/// [`SerialStream`](crate::SerialStream) or, with the `web-serial` feature on
+/// `wasm32`, on a WebSerial port in the browser.
+///
+/// Every type implementing the async I/O traits as well as [`SerialPort`] implements
+/// this trait.  The method names differ from the ones of [`SerialPort`] so both traits
+/// can be in scope at the same time.
+pub trait AsyncSerialPort: AsyncRead + AsyncWrite + Unpin {
+    /// Returns the name of the port, if it has one.
+    fn port_name(&self) -> Option<String>;
+
+    /// Change the baud rate of the open port.
+    ///
+    /// ## Errors
+    ///
+    /// Backends which cannot change the rate of an open port return
+    /// `Io(Unsupported)`.
+    fn change_baud_rate(&mut self, baud_rate: u32) -> crate::Result<()>;
+
+    /// Set the level of the DTR (Data Terminal Ready) control line.
+    fn set_dtr(&mut self, level: bool) -> crate::Result<()>;
+
+    /// Set the level of the RTS (Request To Send) control line.
+    fn set_rts(&mut self, level: bool) -> crate::Result<()>;
+
+    /// Start (`true`) or stop (`false`) transmitting a break.
+    fn set_break_condition(&mut self, asserted: bool) -> crate::Result<()>;
+}
+
+impl<T> AsyncSerialPort for T
+where
+    T: AsyncRead + AsyncWrite + SerialPort + Unpin,
+{
+    fn port_name(&self) -> Option<String> {
+        self.name()
+    }
+
+    fn change_baud_rate(&mut self, baud_rate: u32) -> crate::Result<()> {
+        self.set_baud_rate(baud_rate)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> crate::Result<()> {
+        self.write_data_terminal_ready(level)
+    }
+
+    fn set_rts(&mut self, level: bool) -> crate::Result<()> {
+        self.write_request_to_send(level)
+    }
+
+    fn set_break_condition(&mut self, asserted: bool) -> crate::Result<()> {
+        if asserted {
+            self.set_break()
+        } else {
+            self.clear_break()
+        }
+    }
+}
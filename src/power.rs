@@ -0,0 +1,164 @@
+//! Powering attached devices on and off
+//!
+//! Test rigs and gateways commonly power or reset the device on the other end of the
+//! link through a modem control line, a transistor on DTR being the classic way.
+//! [`PowerControl`] is the common interface for that, implemented on top of the
+//! control lines by [`LinePower`], and by [`PowerFn`] for anything else, such as a
+//! USB or network controlled relay.
+use crate::AsyncSerialPort;
+
+use futures::future::{self, BoxFuture};
+
+use std::future::Future;
+use std::time::Duration;
+
+/// A switchable power supply for an attached device
+pub trait PowerControl: Send {
+    /// Turn the device on.
+    fn power_on(&mut self) -> BoxFuture<'_, crate::Result<()>>;
+
+    /// Turn the device off.
+    fn power_off(&mut self) -> BoxFuture<'_, crate::Result<()>>;
+
+    /// Turn the device off, wait for `hold` and turn it back on.
+    ///
+    /// `hold` should be long enough for the supply of the device to discharge, the
+    /// default implementation simply sleeps in between.
+    fn power_cycle(&mut self, hold: Duration) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            self.power_off().await?;
+            tokio::time::sleep(hold).await;
+            self.power_on().await
+        })
+    }
+}
+
+/// The control line(s) driving the supply of a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerLine {
+    /// DTR (Data Terminal Ready)
+    Dtr,
+    /// RTS (Request To Send)
+    Rts,
+    /// DTR and RTS, switched together
+    Both,
+}
+
+/// [`PowerControl`] through the modem control lines of a serial port
+///
+/// By default the device is powered while the line is asserted, use
+/// [`inverted`](LinePower::inverted) for supplies which are enabled by a deasserted
+/// line instead.
+///
+/// `port` is commonly a `&mut SerialStream`, keeping the stream usable once the
+/// `LinePower` is dropped.
+#[derive(Debug)]
+pub struct LinePower<P> {
+    port: P,
+    line: PowerLine,
+    asserted_on: bool,
+}
+
+impl<P: AsyncSerialPort> LinePower<P> {
+    /// Switch power through `line` of `port`.
+    pub fn new(port: P, line: PowerLine) -> Self {
+        Self {
+            port,
+            line,
+            asserted_on: true,
+        }
+    }
+
+    /// Switch power through the DTR line of `port`.
+    pub fn dtr(port: P) -> Self {
+        Self::new(port, PowerLine::Dtr)
+    }
+
+    /// Switch power through the RTS line of `port`.
+    pub fn rts(port: P) -> Self {
+        Self::new(port, PowerLine::Rts)
+    }
+
+    /// Power the device while the line is deasserted.
+    pub fn inverted(mut self) -> Self {
+        self.asserted_on = !self.asserted_on;
+        self
+    }
+
+    /// Returns a mutable reference to the port.
+    pub fn get_mut(&mut self) -> &mut P {
+        &mut self.port
+    }
+
+    /// Consumes the `LinePower`, returning the port.
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    fn switch(&mut self, on: bool) -> crate::Result<()> {
+        let level = on == self.asserted_on;
+        match self.line {
+            PowerLine::Dtr => self.port.set_dtr(level),
+            PowerLine::Rts => self.port.set_rts(level),
+            PowerLine::Both => {
+                self.port.set_dtr(level)?;
+                self.port.set_rts(level)
+            }
+        }
+    }
+}
+
+impl<P: AsyncSerialPort + Send> PowerControl for LinePower<P> {
+    fn power_on(&mut self) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(future::ready(self.switch(true)))
+    }
+
+    fn power_off(&mut self) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(future::ready(self.switch(false)))
+    }
+}
+
+/// [`PowerControl`] through a user provided function
+///
+/// The function is called with `true` to turn the device on and `false` to turn it
+/// off, and returns a future completing once the supply has been switched.
+///
+/// ```no_run
+/// use tokio_serial::power::{PowerControl, PowerFn};
+/// # async fn switch_relay(_: u8, _: bool) -> tokio_serial::Result<()> { Ok(()) }
+///
+/// # async fn example() -> tokio_serial::Result<()> {
+/// let mut power = PowerFn::new(|on| switch_relay(3, on));
+/// power.power_cycle(std::time::Duration::from_millis(500)).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PowerFn<F> {
+    switch: F,
+}
+
+impl<F, Fut> PowerFn<F>
+where
+    F: FnMut(bool) -> Fut + Send,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+    /// Switch power by calling `switch`.
+    pub fn new(switch: F) -> Self {
+        Self { switch }
+    }
+}
+
+impl<F, Fut> PowerControl for PowerFn<F>
+where
+    F: FnMut(bool) -> Fut + Send,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+    fn power_on(&mut self) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin((self.switch)(true))
+    }
+
+    fn power_off(&mut self) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin((self.switch)(false))
+    }
+}
@@ -0,0 +1,72 @@
+//! Builders preconfigured for common classes of devices
+//!
+//! Each preset is a `SerialPortBuilder` with the settings the devices use out of
+//! the box, which can still be changed before opening the port:
+//!
+//! ```no_run
+//! use tokio_serial::{presets, SerialPortBuilderExt};
+//!
+//! # fn example() -> tokio_serial::Result<()> {
+//! let gps = presets::nmea_gps("/dev/ttyUSB0").open_native_async()?;
+//! let plc = presets::modbus_rtu("/dev/ttyUSB1").baud_rate(9600).open_native_async()?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::{Parity, SerialPortBuilder, SerialSettings, StopBits};
+
+use std::borrow::Cow;
+
+/// Modbus RTU: 19200 baud, 8 data bits, even parity, one stop bit
+///
+/// The defaults of the Modbus over serial line specification.  Devices configured
+/// without parity use two stop bits instead, see [`modbus_rtu_no_parity`].
+pub fn modbus_rtu<'a>(path: impl Into<Cow<'a, str>>) -> SerialPortBuilder {
+    SerialSettings {
+        parity: Parity::Even,
+        ..SerialSettings::new(19200)
+    }
+    .builder(path)
+}
+
+/// Modbus RTU without parity: 19200 baud, 8 data bits, no parity, two stop bits
+pub fn modbus_rtu_no_parity<'a>(path: impl Into<Cow<'a, str>>) -> SerialPortBuilder {
+    SerialSettings {
+        stop_bits: StopBits::Two,
+        ..SerialSettings::new(19200)
+    }
+    .builder(path)
+}
+
+/// NMEA 0183 GPS receivers: 4800 baud, 8 data bits, no parity, one stop bit
+///
+/// Many recent receivers default to 9600 or more, change the baud rate of the
+/// builder for them.
+pub fn nmea_gps<'a>(path: impl Into<Cow<'a, str>>) -> SerialPortBuilder {
+    SerialSettings::new(4800).builder(path)
+}
+
+/// Serial consoles of boards and embedded Linux systems: 115200 baud, 8N1, no flow
+/// control
+pub fn console<'a>(path: impl Into<Cow<'a, str>>) -> SerialPortBuilder {
+    SerialSettings::new(115_200).builder(path)
+}
+
+/// The STM32 system memory bootloader: 115200 baud, 8 data bits, even parity, one
+/// stop bit
+///
+/// The bootloader measures the baud rate, lower ones work as well.
+pub fn stm32_bootloader<'a>(path: impl Into<Cow<'a, str>>) -> SerialPortBuilder {
+    SerialSettings {
+        parity: Parity::Even,
+        ..SerialSettings::new(115_200)
+    }
+    .builder(path)
+}
+
+/// DMX512 lighting: 250000 baud, 8 data bits, no parity, two stop bits
+///
+/// Frames also need breaks, see [`DmxSender`](crate::dmx::DmxSender).
+#[cfg(not(target_arch = "wasm32"))]
+pub fn dmx512<'a>(path: impl Into<Cow<'a, str>>) -> SerialPortBuilder {
+    crate::dmx::settings().builder(path)
+}
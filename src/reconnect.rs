@@ -0,0 +1,367 @@
+//! A serial port which reopens itself when its device comes back
+//!
+//! USB adapters get unplugged, reset by a flaky hub or re-enumerated by the OS.
+//! A [`ReconnectingSerialStream`] keeps the settings the port was opened with and,
+//! when the device goes away, reopens it following a [`RetryPolicy`] while reads
+//! and writes wait for it to be back.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use tokio::io::AsyncReadExt;
+//! use tokio_serial::reconnect::{ConnectionState, ReconnectingSerialStream};
+//! use tokio_serial::retry::RetryPolicy;
+//!
+//! # async fn example() -> std::io::Result<()> {
+//! let mut port = ReconnectingSerialStream::new(tokio_serial::new("/dev/ttyUSB0", 115_200));
+//! port.set_policy(RetryPolicy::fixed(Duration::from_secs(1)));
+//!
+//! let mut state = port.subscribe();
+//! tokio::spawn(async move {
+//!     while state.changed().await.is_ok() {
+//!         if let ConnectionState::Disconnected { .. } = *state.borrow() {
+//!             log::warn!("adapter unplugged");
+//!         }
+//!     }
+//! });
+//!
+//! let mut buf = [0u8; 256];
+//! loop {
+//!     // Waits for the adapter to be plugged back in.
+//!     let n = port.read(&mut buf).await?;
+//!     // ...
+//! }
+//! # }
+//! ```
+use crate::retry::RetryPolicy;
+use crate::{SerialPortBuilder, SerialStream};
+
+use futures::ready;
+use futures::task::ArcWake;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::watch;
+use tokio::time::{Instant, Sleep};
+
+/// Whether a [`ReconnectingSerialStream`] currently has its port open
+#[derive(Debug, Clone)]
+pub enum ConnectionState {
+    /// The port is not open, `attempts` opens failed since it was lost
+    ///
+    /// This is also the state before the port is opened the first time.
+    Disconnected {
+        /// Number of opens which failed so far
+        attempts: u32,
+    },
+    /// The port is open
+    Connected,
+    /// The retry policy gave up, with the error of the last open
+    ///
+    /// Reads and writes fail from then on.
+    Failed(crate::Error),
+}
+
+impl ConnectionState {
+    /// Returns `true` if the port is open.
+    pub fn is_connected(&self) -> bool {
+        matches!(self, ConnectionState::Connected)
+    }
+}
+
+enum State {
+    Connected(SerialStream),
+    // Waiting for the next attempt to open the port
+    Waiting(Pin<Box<Sleep>>),
+    Failed(crate::Error),
+}
+
+// The tasks waiting for the port to be open, as the read and write halves may be
+// polled by different tasks.
+#[derive(Default)]
+struct Wakers {
+    read: Mutex<Option<Waker>>,
+    write: Mutex<Option<Waker>>,
+}
+
+impl ArcWake for Wakers {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        for waker in [&arc_self.read, &arc_self.write] {
+            if let Some(waker) = waker.lock().unwrap().take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Read,
+    Write,
+}
+
+/// A [`SerialStream`] which reopens the port when its device is disconnected
+///
+/// The port is opened from a [`SerialPortBuilder`] on first use.  When a read or a
+/// write fails because the device went away (see [`SerialStream::closed`]), the
+/// error is not returned: the port is reopened following the retry policy and the
+/// operation continues on the new port, so a read in progress just takes longer.
+///
+/// Data still in the buffers of the old port when the device was lost is gone, and
+/// a write is only retried if the OS did not accept any of it.  Protocols which
+/// cannot lose bytes should watch the [state](ReconnectingSerialStream::subscribe)
+/// and resynchronize after a reconnection.
+///
+/// Once the policy gives up, reads and writes fail with the error of the last open.
+pub struct ReconnectingSerialStream {
+    builder: SerialPortBuilder,
+    // For the logs, the builder does not give its path: the name of the port once
+    // it was opened
+    name: Option<String>,
+    policy: RetryPolicy,
+    state: State,
+    // Opens which failed since the port was lost
+    attempts: u32,
+    wakers: Arc<Wakers>,
+    events: watch::Sender<ConnectionState>,
+}
+
+impl ReconnectingSerialStream {
+    /// Open the port of `builder` on first use, reopening it with the default
+    /// [`RetryPolicy`] when it is lost.
+    pub fn new(builder: SerialPortBuilder) -> Self {
+        let (events, _) = watch::channel(ConnectionState::Disconnected { attempts: 0 });
+        Self {
+            builder,
+            name: None,
+            policy: RetryPolicy::default(),
+            state: State::Waiting(Box::pin(tokio::time::sleep(std::time::Duration::ZERO))),
+            attempts: 0,
+            wakers: Arc::default(),
+            events,
+        }
+    }
+
+    /// Set the policy deciding when to try to reopen the port, and when to give up.
+    ///
+    /// The first attempt after the port is lost happens right away, the policy gives
+    /// the delays after the attempts which failed.
+    pub fn set_policy(&mut self, policy: RetryPolicy) {
+        self.policy = policy;
+    }
+
+    /// The settings the port is opened with
+    pub fn builder(&self) -> &SerialPortBuilder {
+        &self.builder
+    }
+
+    /// Returns the current state of the connection.
+    pub fn state(&self) -> ConnectionState {
+        self.events.borrow().clone()
+    }
+
+    /// Returns a receiver notified of every change of the state of the connection.
+    pub fn subscribe(&self) -> watch::Receiver<ConnectionState> {
+        self.events.subscribe()
+    }
+
+    /// Wait until the port is open, opening it if needed.
+    ///
+    /// ## Errors
+    ///
+    /// The error of the last open if the retry policy gave up.
+    pub async fn connected(&mut self) -> io::Result<()> {
+        futures::future::poll_fn(|cx| self.poll_connected(cx, Direction::Read).map_ok(|_| ())).await
+    }
+
+    /// Returns the open port, if any.
+    pub fn get_ref(&self) -> Option<&SerialStream> {
+        match &self.state {
+            State::Connected(port) => Some(port),
+            _ => None,
+        }
+    }
+
+    /// Returns the open port mutably, if any.
+    ///
+    /// Settings changed through it are lost when the port is reopened with those of
+    /// the [builder](ReconnectingSerialStream::builder).
+    pub fn get_mut(&mut self) -> Option<&mut SerialStream> {
+        match &mut self.state {
+            State::Connected(port) => Some(port),
+            _ => None,
+        }
+    }
+
+    /// Returns the open port, if any.
+    pub fn into_inner(self) -> Option<SerialStream> {
+        match self.state {
+            State::Connected(port) => Some(port),
+            _ => None,
+        }
+    }
+
+    fn set_state(&self, state: ConnectionState) {
+        self.events.send_replace(state);
+    }
+
+    fn poll_connected(
+        &mut self,
+        cx: &mut Context<'_>,
+        direction: Direction,
+    ) -> Poll<io::Result<&mut SerialStream>> {
+        loop {
+            match &mut self.state {
+                State::Connected(_) => break,
+                State::Failed(e) => return Poll::Ready(Err(e.clone().into())),
+                State::Waiting(sleep) => {
+                    let slot = match direction {
+                        Direction::Read => &self.wakers.read,
+                        Direction::Write => &self.wakers.write,
+                    };
+                    *slot.lock().unwrap() = Some(cx.waker().clone());
+                    let waker = futures::task::waker_ref(&self.wakers);
+                    ready!(sleep.as_mut().poll(&mut Context::from_waker(&waker)));
+                    self.open();
+                }
+            }
+        }
+        match &mut self.state {
+            State::Connected(port) => Poll::Ready(Ok(port)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn open(&mut self) {
+        match SerialStream::open(&self.builder) {
+            Ok(port) => {
+                self.name = crate::SerialPort::name(&port);
+                log::debug!(
+                    "opened {} after {} attempts",
+                    self.label(),
+                    self.attempts + 1
+                );
+                self.attempts = 0;
+                self.state = State::Connected(port);
+                self.set_state(ConnectionState::Connected);
+                // The other direction may be waiting as well.
+                ArcWake::wake_by_ref(&self.wakers);
+            }
+            Err(e) => {
+                self.attempts += 1;
+                match self.policy.delay(self.attempts) {
+                    Some(delay) => {
+                        log::debug!("unable to open {}: {}", self.label(), e);
+                        self.state = State::Waiting(Box::pin(tokio::time::sleep_until(
+                            Instant::now() + delay,
+                        )));
+                        self.set_state(ConnectionState::Disconnected {
+                            attempts: self.attempts,
+                        });
+                    }
+                    None => {
+                        log::warn!("giving up opening {}: {}", self.label(), e);
+                        self.state = State::Failed(e.clone());
+                        self.set_state(ConnectionState::Failed(e));
+                        ArcWake::wake_by_ref(&self.wakers);
+                    }
+                }
+            }
+        }
+    }
+
+    fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or("the port")
+    }
+
+    // Drop the port if `err` means its device went away, returns whether it did.
+    fn lost(&mut self, err: &io::Error) -> bool {
+        if !is_disconnection(err) {
+            return false;
+        }
+        log::debug!("lost {}: {}", self.label(), err);
+        self.attempts = 0;
+        self.state = State::Waiting(Box::pin(tokio::time::sleep(std::time::Duration::ZERO)));
+        self.set_state(ConnectionState::Disconnected { attempts: 0 });
+        true
+    }
+}
+
+// `SerialStream` reports removed devices as `NotConnected`, hung up ttys (e.g. the
+// other side of a pty closed) fail with the raw OS errors.
+fn is_disconnection(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::NotConnected || crate::disconnect::is_candidate(err)
+}
+
+impl std::fmt::Debug for ReconnectingSerialStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReconnectingSerialStream")
+            .field("name", &self.name)
+            .field("policy", &self.policy)
+            .field("state", &*self.events.borrow())
+            .finish()
+    }
+}
+
+impl AsyncRead for ReconnectingSerialStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            let port = ready!(this.poll_connected(cx, Direction::Read))?;
+            let requested = buf.remaining();
+            let read = match ready!(Pin::new(port).poll_read(cx, buf)) {
+                // A hung up tty reads end of file.
+                Ok(()) if requested > 0 && buf.remaining() == requested => {
+                    Err(crate::disconnect::error())
+                }
+                read => read,
+            };
+            match read {
+                Err(e) if this.lost(&e) => continue,
+                read => return Poll::Ready(read),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for ReconnectingSerialStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            let port = ready!(this.poll_connected(cx, Direction::Write))?;
+            match ready!(Pin::new(port).poll_write(cx, buf)) {
+                Err(e) if this.lost(&e) => continue,
+                written => return Poll::Ready(written),
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            let port = ready!(this.poll_connected(cx, Direction::Write))?;
+            match ready!(Pin::new(port).poll_flush(cx)) {
+                // Nothing is left to flush on a new port.
+                Err(e) if this.lost(&e) => continue,
+                flushed => return Poll::Ready(flushed),
+            }
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().state {
+            State::Connected(port) => Pin::new(port).poll_shutdown(cx),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+}
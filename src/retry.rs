@@ -0,0 +1,240 @@
+//! Retry and backoff policies
+//!
+//! A [`RetryPolicy`] tells how long to wait before each new attempt of an operation
+//! which failed, and when to give up.  The same type configures every part of the
+//! crate which retries, so an application can define its policy once.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use tokio_serial::retry::RetryPolicy;
+//!
+//! # async fn example() -> tokio_serial::Result<()> {
+//! let policy = RetryPolicy::exponential(Duration::from_millis(100))
+//!     .jitter(0.2)
+//!     .max_delay(Duration::from_secs(5))
+//!     .max_attempts(10);
+//!
+//! let builder = tokio_serial::new("/dev/ttyUSB0", 115_200);
+//! let port = policy.retry(|| async { tokio_serial::SerialStream::open(&builder) }).await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A function returning the delay before a retry, see [`RetryPolicy::custom`]
+pub type BackoffFn = Arc<dyn Fn(u32) -> Option<Duration> + Send + Sync>;
+
+#[derive(Clone)]
+enum Backoff {
+    Fixed(Duration),
+    Exponential { initial: Duration, factor: f64 },
+    Fibonacci(Duration),
+    Custom(BackoffFn),
+}
+
+/// When and how often to retry a failed operation
+///
+/// Retries are numbered from 1: retry 1 happens after the first attempt failed.
+/// By default there is no limit on the number of attempts.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    backoff: Backoff,
+    max_attempts: Option<u32>,
+    max_delay: Option<Duration>,
+    jitter: f64,
+}
+
+impl RetryPolicy {
+    fn with(backoff: Backoff) -> Self {
+        Self {
+            backoff,
+            max_attempts: None,
+            max_delay: None,
+            jitter: 0.0,
+        }
+    }
+
+    /// Do not retry.
+    pub fn never() -> Self {
+        Self::fixed(Duration::ZERO).max_attempts(1)
+    }
+
+    /// Wait `delay` before every retry.
+    pub fn fixed(delay: Duration) -> Self {
+        Self::with(Backoff::Fixed(delay))
+    }
+
+    /// Wait `initial` before the first retry, doubling the delay every time.
+    ///
+    /// Use [`factor`](RetryPolicy::factor) to grow the delay at another rate.
+    pub fn exponential(initial: Duration) -> Self {
+        Self::with(Backoff::Exponential {
+            initial,
+            factor: 2.0,
+        })
+    }
+
+    /// Wait `unit` times the Fibonacci sequence: 1, 1, 2, 3, 5, ... units.
+    pub fn fibonacci(unit: Duration) -> Self {
+        Self::with(Backoff::Fibonacci(unit))
+    }
+
+    /// Ask `backoff` for the delay before each retry, given the retry number.
+    ///
+    /// Returning [`None`] gives up.
+    pub fn custom<F>(backoff: F) -> Self
+    where
+        F: Fn(u32) -> Option<Duration> + Send + Sync + 'static,
+    {
+        Self::with(Backoff::Custom(Arc::new(backoff)))
+    }
+
+    /// Set the growth factor of an [exponential](RetryPolicy::exponential) policy
+    ///
+    /// Other policies are left unchanged.
+    pub fn factor(mut self, factor: f64) -> Self {
+        if let Backoff::Exponential { factor: f, .. } = &mut self.backoff {
+            *f = factor;
+        }
+        self
+    }
+
+    /// Give up after `attempts` attempts in total, the first one included.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// Never wait longer than `delay` between attempts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = Some(delay);
+        self
+    }
+
+    /// Shorten every delay by a random fraction of up to `jitter` of it
+    ///
+    /// Jitter keeps many clients from retrying in lockstep.  `jitter` is clamped
+    /// to `0.0..=1.0`.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Returns how long to wait before retry number `retry`, or [`None`] to give up.
+    pub fn delay(&self, retry: u32) -> Option<Duration> {
+        if retry == 0 {
+            return Some(Duration::ZERO);
+        }
+        if self.max_attempts.is_some_and(|max| retry >= max) {
+            return None;
+        }
+
+        let delay = match &self.backoff {
+            Backoff::Fixed(delay) => *delay,
+            Backoff::Exponential { initial, factor } => {
+                let scale = factor.powi(retry.saturating_sub(1).min(i32::MAX as u32) as i32);
+                Duration::try_from_secs_f64(initial.as_secs_f64() * scale).unwrap_or(Duration::MAX)
+            }
+            Backoff::Fibonacci(unit) => {
+                let (mut a, mut b) = (1u32, 1u32);
+                for _ in 1..retry {
+                    let next = a.saturating_add(b);
+                    a = b;
+                    b = next;
+                }
+                unit.saturating_mul(a)
+            }
+            Backoff::Custom(backoff) => backoff(retry)?,
+        };
+        let delay = match self.max_delay {
+            Some(max) => delay.min(max),
+            None => delay,
+        };
+        if self.jitter > 0.0 {
+            Some(delay.mul_f64(1.0 - self.jitter * random()))
+        } else {
+            Some(delay)
+        }
+    }
+
+    /// Run `operation` until it succeeds or the policy gives up.
+    ///
+    /// Returns the result of the last attempt.
+    pub async fn retry<F, Fut, T, E>(&self, operation: F) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.retry_if(operation, |_| true).await
+    }
+
+    /// Run `operation` until it succeeds, fails with an error for which `retryable`
+    /// returns `false`, or the policy gives up.
+    ///
+    /// Returns the result of the last attempt.
+    pub async fn retry_if<F, Fut, T, E, P>(
+        &self,
+        mut operation: F,
+        mut retryable: P,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        P: FnMut(&E) -> bool,
+    {
+        let mut retry = 0;
+        loop {
+            let err = match operation().await {
+                Ok(value) => return Ok(value),
+                Err(err) if retryable(&err) => err,
+                Err(err) => return Err(err),
+            };
+            retry += 1;
+            match self.delay(retry) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return Err(err),
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Exponential backoff from 100ms up to 30s
+    fn default() -> Self {
+        Self::exponential(Duration::from_millis(100)).max_delay(Duration::from_secs(30))
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = f.debug_struct("RetryPolicy");
+        match &self.backoff {
+            Backoff::Fixed(delay) => s.field("fixed", delay),
+            Backoff::Exponential { initial, factor } => {
+                s.field("exponential", initial).field("factor", factor)
+            }
+            Backoff::Fibonacci(unit) => s.field("fibonacci", unit),
+            Backoff::Custom(_) => s.field("custom", &".."),
+        };
+        s.field("max_attempts", &self.max_attempts)
+            .field("max_delay", &self.max_delay)
+            .field("jitter", &self.jitter)
+            .finish()
+    }
+}
+
+// A number in `0.0..1.0`, random enough for jitter without a dependency.
+fn random() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |t| t.as_nanos() as u64),
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,1016 @@
+//! Serial ports shared over the network with RFC 2217
+//!
+//! RFC 2217 extends Telnet with a "COM Port Control" option, letting a client
+//! change the line settings and the modem lines of a serial port attached to a
+//! remote server, such as `ser2net` or pyserial's `rfc2217_server.py`.
+//!
+//! An [`Rfc2217Stream`] is such a client.  It implements the async I/O traits as
+//! well as [`SerialPort`], so code written for a [`SerialStream`](crate::SerialStream)
+//! or against [`AsyncSerialPort`](crate::AsyncSerialPort) also runs on a remote port.
+//! The other way around, [`BridgeServer::serve_rfc2217`](crate::bridge::BridgeServer::serve_rfc2217)
+//! shares a local port with RFC 2217 clients.
+//!
+//! ```no_run
+//! use tokio::io::AsyncWriteExt;
+//! use tokio_serial::rfc2217::Rfc2217Stream;
+//! use tokio_serial::{SerialPort, SerialSettings};
+//!
+//! # async fn example() -> tokio_serial::Result<()> {
+//! let mut port = Rfc2217Stream::connect("192.168.1.10:2217", &SerialSettings::new(115_200)).await?;
+//! port.write_request_to_send(false)?;
+//! port.write_all(b"AT\r").await?;
+//! println!("CTS is {}", port.read_clear_to_send()?);
+//! # Ok(())
+//! # }
+//! ```
+use crate::{
+    ClearBuffer, DataBits, Error, ErrorKind, FlowControl, Parity, SerialPort, SerialSettings,
+    StopBits,
+};
+
+use futures::ready;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+// Telnet
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const BINARY: u8 = 0;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+const COM_PORT_OPTION: u8 = 44;
+
+// COM Port Control commands, the server answers with the command plus 100
+const SET_BAUDRATE: u8 = 1;
+const SET_DATASIZE: u8 = 2;
+const SET_PARITY: u8 = 3;
+const SET_STOPSIZE: u8 = 4;
+const SET_CONTROL: u8 = 5;
+const NOTIFY_LINESTATE: u8 = 6;
+const NOTIFY_MODEMSTATE: u8 = 7;
+const FLOWCONTROL_SUSPEND: u8 = 8;
+const FLOWCONTROL_RESUME: u8 = 9;
+const SET_LINESTATE_MASK: u8 = 10;
+const SET_MODEMSTATE_MASK: u8 = 11;
+const PURGE_DATA: u8 = 12;
+const SERVER_OFFSET: u8 = 100;
+
+// Values of SET-CONTROL
+const FLOW_NONE: u8 = 1;
+const FLOW_SOFTWARE: u8 = 2;
+const FLOW_HARDWARE: u8 = 3;
+const BREAK_STATE: u8 = 4;
+const BREAK_ON: u8 = 5;
+const BREAK_OFF: u8 = 6;
+const DTR_STATE: u8 = 7;
+const DTR_ON: u8 = 8;
+const DTR_OFF: u8 = 9;
+const RTS_STATE: u8 = 10;
+const RTS_ON: u8 = 11;
+const RTS_OFF: u8 = 12;
+
+// Bits of NOTIFY-MODEMSTATE, the low nibble tells which lines changed
+const CTS: u8 = 0x10;
+const DSR: u8 = 0x20;
+const RI: u8 = 0x40;
+const CD: u8 = 0x80;
+const DELTA_CTS: u8 = 0x01;
+const DELTA_DSR: u8 = 0x02;
+const TRAILING_EDGE_RI: u8 = 0x04;
+const DELTA_CD: u8 = 0x08;
+
+// How often servers check the modem lines of their port
+const MODEM_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Above this many bytes queued for the network, writes wait for them to be sent.
+const MAX_QUEUED: usize = 8 * 1024;
+
+/// Splits a Telnet byte stream into data, negotiations and subnegotiations
+#[derive(Debug, Default)]
+struct Telnet {
+    state: TelnetState,
+    sub: Vec<u8>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+enum TelnetState {
+    #[default]
+    Data,
+    Iac,
+    Negotiation(u8),
+    Sub,
+    SubIac,
+}
+
+#[derive(Debug)]
+enum TelnetEvent {
+    Data(u8),
+    Negotiation(u8, u8),
+    // Option and payload
+    Subnegotiation(Vec<u8>),
+}
+
+impl Telnet {
+    fn push(&mut self, byte: u8) -> Option<TelnetEvent> {
+        let (state, event) = match (self.state, byte) {
+            (TelnetState::Data, IAC) => (TelnetState::Iac, None),
+            (TelnetState::Data, byte) => (TelnetState::Data, Some(TelnetEvent::Data(byte))),
+            (TelnetState::Iac, IAC) => (TelnetState::Data, Some(TelnetEvent::Data(IAC))),
+            (TelnetState::Iac, DO | DONT | WILL | WONT) => (TelnetState::Negotiation(byte), None),
+            (TelnetState::Iac, SB) => {
+                self.sub.clear();
+                (TelnetState::Sub, None)
+            }
+            // Other commands, such as NOP or go ahead, mean nothing here.
+            (TelnetState::Iac, _) => (TelnetState::Data, None),
+            (TelnetState::Negotiation(verb), option) => (
+                TelnetState::Data,
+                Some(TelnetEvent::Negotiation(verb, option)),
+            ),
+            (TelnetState::Sub, IAC) => (TelnetState::SubIac, None),
+            (TelnetState::Sub, byte) => {
+                self.sub.push(byte);
+                (TelnetState::Sub, None)
+            }
+            (TelnetState::SubIac, SE) => (
+                TelnetState::Data,
+                Some(TelnetEvent::Subnegotiation(std::mem::take(&mut self.sub))),
+            ),
+            (TelnetState::SubIac, byte) => {
+                self.sub.push(byte);
+                (TelnetState::Sub, None)
+            }
+        };
+        self.state = state;
+        event
+    }
+}
+
+// Append `data` to `out`, doubling the bytes which would be taken for IAC.
+fn escape(data: &[u8], out: &mut Vec<u8>) {
+    for &byte in data {
+        out.push(byte);
+        if byte == IAC {
+            out.push(IAC);
+        }
+    }
+}
+
+fn com_port_command(out: &mut Vec<u8>, command: u8, value: &[u8]) {
+    out.extend_from_slice(&[IAC, SB, COM_PORT_OPTION, command]);
+    escape(value, out);
+    out.extend_from_slice(&[IAC, SE]);
+}
+
+fn data_bits_value(data_bits: DataBits) -> u8 {
+    crate::config::data_bits_number(data_bits)
+}
+
+fn parity_value(parity: Parity) -> u8 {
+    match parity {
+        Parity::None => 1,
+        Parity::Odd => 2,
+        Parity::Even => 3,
+    }
+}
+
+fn stop_bits_value(stop_bits: StopBits) -> u8 {
+    match stop_bits {
+        StopBits::One => 1,
+        StopBits::Two => 2,
+    }
+}
+
+fn flow_control_value(flow_control: FlowControl) -> u8 {
+    match flow_control {
+        FlowControl::None => FLOW_NONE,
+        FlowControl::Software => FLOW_SOFTWARE,
+        FlowControl::Hardware => FLOW_HARDWARE,
+    }
+}
+
+fn data_bits_from_value(value: u8) -> Option<DataBits> {
+    match value {
+        5 => Some(DataBits::Five),
+        6 => Some(DataBits::Six),
+        7 => Some(DataBits::Seven),
+        8 => Some(DataBits::Eight),
+        _ => None,
+    }
+}
+
+fn parity_from_value(value: u8) -> Option<Parity> {
+    match value {
+        1 => Some(Parity::None),
+        2 => Some(Parity::Odd),
+        3 => Some(Parity::Even),
+        _ => None,
+    }
+}
+
+fn stop_bits_from_value(value: u8) -> Option<StopBits> {
+    match value {
+        1 => Some(StopBits::One),
+        2 => Some(StopBits::Two),
+        _ => None,
+    }
+}
+
+fn flow_control_from_value(value: u8) -> Option<FlowControl> {
+    match value {
+        FLOW_NONE => Some(FlowControl::None),
+        FLOW_SOFTWARE => Some(FlowControl::Software),
+        FLOW_HARDWARE => Some(FlowControl::Hardware),
+        _ => None,
+    }
+}
+
+fn timed_out(description: &str) -> Error {
+    Error::new(ErrorKind::Io(io::ErrorKind::TimedOut), description)
+}
+
+/// A serial port of an RFC 2217 server
+///
+/// Setters of [`SerialPort`] cannot wait for the server: they queue the request,
+/// which is sent with the next read or write, and the getters return the settings
+/// requested until the server confirms the ones it actually uses.  Use
+/// [`apply_settings`](Rfc2217Stream::apply_settings) to wait for the server to
+/// apply new settings, and [`lines_applied`](Rfc2217Stream::lines_applied) for
+/// changes of DTR, RTS and break.  The modem lines are the ones last notified by
+/// the server.
+///
+/// The [timeout](SerialPort::timeout) is how long to wait for the server to
+/// confirm settings, 3 seconds by default.  Requests of the server to suspend
+/// the data flow are not honored.
+#[derive(Debug)]
+pub struct Rfc2217Stream {
+    stream: TcpStream,
+    name: String,
+    telnet: Telnet,
+    // Data received, and bytes queued for the network
+    rx: Mutex<VecDeque<u8>>,
+    tx: Mutex<Vec<u8>>,
+    // Whether the server agreed to the COM Port Control option
+    accepted: Option<bool>,
+    settings: SerialSettings,
+    // Commands sent whose confirmation did not come yet, as bits
+    unconfirmed: u16,
+    // Changes of DTR, RTS and break not confirmed yet, which share SET-CONTROL with
+    // the flow control
+    unconfirmed_lines: AtomicUsize,
+    modem_state: u8,
+    timeout: Duration,
+}
+
+impl Rfc2217Stream {
+    /// Connect to the server at `addr` and apply `settings` to its port.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io(Unsupported)` if the server refuses the COM Port Control option.
+    /// * `Io(TimedOut)` if the server does not answer the negotiation or confirm
+    ///   the settings within 3 seconds.
+    /// * `InvalidInput` if the server does not use the settings requested.
+    pub async fn connect<A: ToSocketAddrs>(
+        addr: A,
+        settings: &SerialSettings,
+    ) -> crate::Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        let _ = stream.set_nodelay(true);
+        let name = format!("rfc2217://{}", stream.peer_addr()?);
+        let mut port = Self {
+            stream,
+            name,
+            telnet: Telnet::default(),
+            rx: Mutex::default(),
+            tx: Mutex::default(),
+            accepted: None,
+            settings: *settings,
+            unconfirmed: 0,
+            unconfirmed_lines: AtomicUsize::new(0),
+            modem_state: 0,
+            timeout: Duration::from_secs(3),
+        };
+
+        port.tx.get_mut().unwrap().extend_from_slice(&[
+            IAC,
+            WILL,
+            BINARY,
+            IAC,
+            DO,
+            BINARY,
+            IAC,
+            WILL,
+            SUPPRESS_GO_AHEAD,
+            IAC,
+            DO,
+            SUPPRESS_GO_AHEAD,
+            IAC,
+            WILL,
+            COM_PORT_OPTION,
+        ]);
+        if !port.wait(|port| port.accepted.is_some()).await? {
+            return Err(timed_out("the server did not answer the negotiation"));
+        }
+        if port.accepted != Some(true) {
+            return Err(Error::new(
+                ErrorKind::Io(io::ErrorKind::Unsupported),
+                "the server does not support RFC 2217",
+            ));
+        }
+        log::debug!("connected to {}", port.name);
+        port.apply_settings(settings).await?;
+        Ok(port)
+    }
+
+    /// Apply `settings` to the remote port, waiting for the server to confirm them.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io(TimedOut)` if the server does not confirm the settings in time.
+    /// * `InvalidInput` if the server does not use the settings requested.
+    pub async fn apply_settings(&mut self, settings: &SerialSettings) -> crate::Result<()> {
+        self.set_baud_rate(settings.baud_rate)?;
+        self.set_data_bits(settings.data_bits)?;
+        self.set_parity(settings.parity)?;
+        self.set_stop_bits(settings.stop_bits)?;
+        self.set_flow_control(settings.flow_control)?;
+        if !self.wait(|port| port.unconfirmed == 0).await? {
+            return Err(timed_out("the server did not confirm the settings"));
+        }
+        if self.settings != *settings {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("the server uses {:?} instead", self.settings),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Wait for the server to confirm the changes of DTR, RTS and break made so far.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io(TimedOut)` if the server does not confirm them in time.
+    pub async fn lines_applied(&mut self) -> crate::Result<()> {
+        if !self
+            .wait(|port| port.unconfirmed_lines.load(Ordering::Relaxed) == 0)
+            .await?
+        {
+            return Err(timed_out("the server did not confirm the control lines"));
+        }
+        Ok(())
+    }
+
+    /// Returns the settings of the remote port.
+    pub fn settings(&self) -> SerialSettings {
+        self.settings
+    }
+
+    // Process what the server sent.
+    fn input(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            match self.telnet.push(byte) {
+                None => {}
+                Some(TelnetEvent::Data(byte)) => self.rx.get_mut().unwrap().push_back(byte),
+                Some(TelnetEvent::Negotiation(verb, option)) => self.negotiation(verb, option),
+                Some(TelnetEvent::Subnegotiation(sub)) => {
+                    if let [COM_PORT_OPTION, command, value @ ..] = &sub[..] {
+                        self.confirmation(*command, value);
+                    }
+                }
+            }
+        }
+    }
+
+    fn negotiation(&mut self, verb: u8, option: u8) {
+        let refusal = match (verb, option) {
+            (DO, COM_PORT_OPTION) => {
+                self.accepted = Some(true);
+                None
+            }
+            (DONT, COM_PORT_OPTION) => {
+                self.accepted = Some(false);
+                None
+            }
+            // Asked for when connecting
+            (DO | WILL, BINARY | SUPPRESS_GO_AHEAD) | (DONT | WONT, _) => None,
+            (DO, option) => Some([IAC, WONT, option]),
+            (_, option) => Some([IAC, DONT, option]),
+        };
+        if let Some(refusal) = refusal {
+            self.tx.get_mut().unwrap().extend_from_slice(&refusal);
+        }
+    }
+
+    fn confirmation(&mut self, command: u8, value: &[u8]) {
+        let command = command.wrapping_sub(SERVER_OFFSET);
+        let line = match (command, value) {
+            (SET_CONTROL, &[control]) => flow_control_from_value(control).is_none(),
+            _ => false,
+        };
+        if line {
+            let unconfirmed = self.unconfirmed_lines.get_mut();
+            *unconfirmed = unconfirmed.saturating_sub(1);
+        } else if command < 16 {
+            self.unconfirmed &= !(1 << command);
+        }
+        let settings = &mut self.settings;
+        match (command, value) {
+            (SET_BAUDRATE, &[a, b, c, d]) => settings.baud_rate = u32::from_be_bytes([a, b, c, d]),
+            (SET_DATASIZE, &[bits]) => {
+                settings.data_bits = data_bits_from_value(bits).unwrap_or(settings.data_bits)
+            }
+            (SET_PARITY, &[parity]) => {
+                settings.parity = parity_from_value(parity).unwrap_or(settings.parity)
+            }
+            (SET_STOPSIZE, &[stop_bits]) => {
+                settings.stop_bits = stop_bits_from_value(stop_bits).unwrap_or(settings.stop_bits)
+            }
+            (SET_CONTROL, &[control]) => {
+                if let Some(flow_control) = flow_control_from_value(control) {
+                    settings.flow_control = flow_control;
+                }
+            }
+            (NOTIFY_MODEMSTATE, &[state]) => self.modem_state = state,
+            (NOTIFY_LINESTATE, _) | (PURGE_DATA, _) => {}
+            (command, value) => log::debug!("unexpected command {} {:?}", command, value),
+        }
+    }
+
+    // Queue a command for the server, expecting a confirmation.
+    fn command(&self, command: u8, value: &[u8]) {
+        com_port_command(&mut self.tx.lock().unwrap(), command, value);
+    }
+
+    // Queue a change of DTR, RTS or break.
+    fn line(&self, value: u8) {
+        self.unconfirmed_lines.fetch_add(1, Ordering::Relaxed);
+        self.command(SET_CONTROL, &[value]);
+    }
+
+    fn set(&mut self, command: u8, value: &[u8]) {
+        self.unconfirmed |= 1 << command;
+        com_port_command(self.tx.get_mut().unwrap(), command, value);
+    }
+
+    // Send the bytes queued for the network.
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let tx = self.tx.get_mut().unwrap();
+        while !tx.is_empty() {
+            let n = ready!(Pin::new(&mut self.stream).poll_write(cx, tx))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            tx.drain(..n);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    // Read from the network once, returns `false` at end of file.
+    fn poll_receive(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<bool>> {
+        let mut buf = [0u8; 1024];
+        let mut read = ReadBuf::new(&mut buf);
+        ready!(Pin::new(&mut self.stream).poll_read(cx, &mut read))?;
+        let n = read.filled().len();
+        self.input(&buf[..n]);
+        Poll::Ready(Ok(n > 0))
+    }
+
+    // Exchange with the server until `done`, returns `false` on timeout.
+    async fn wait<F: Fn(&Self) -> bool>(&mut self, done: F) -> crate::Result<bool> {
+        let timeout = self.timeout;
+        let exchange = futures::future::poll_fn(|cx| loop {
+            if done(self) {
+                return Poll::Ready(Ok(()));
+            }
+            if let Poll::Ready(Err(e)) = self.poll_send(cx) {
+                return Poll::Ready(Err(e));
+            }
+            if !ready!(self.poll_receive(cx))? {
+                return Poll::Ready(Err(io::Error::from(io::ErrorKind::UnexpectedEof)));
+            }
+        });
+        match tokio::time::timeout(timeout, exchange).await {
+            Ok(result) => result.map(|()| true).map_err(Into::into),
+            Err(_) => Ok(false),
+        }
+    }
+
+    fn take_received(&mut self, buf: &mut [u8]) -> usize {
+        let rx = self.rx.get_mut().unwrap();
+        let n = rx.len().min(buf.len());
+        for (dst, src) in buf.iter_mut().zip(rx.drain(..n)) {
+            *dst = src;
+        }
+        n
+    }
+}
+
+impl AsyncRead for Rfc2217Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if !this.rx.get_mut().unwrap().is_empty() {
+                let n = this.take_received(buf.initialize_unfilled());
+                buf.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+            // Requests of the setters and answers to negotiations go along.
+            if let Poll::Ready(Err(e)) = this.poll_send(cx) {
+                return Poll::Ready(Err(e));
+            }
+            if !ready!(this.poll_receive(cx))? {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl AsyncWrite for Rfc2217Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.tx.get_mut().unwrap().len() >= MAX_QUEUED {
+            ready!(this.poll_send(cx))?;
+        }
+        escape(buf, this.tx.get_mut().unwrap());
+        if let Poll::Ready(Err(e)) = this.poll_send(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.stream).poll_shutdown(cx)
+    }
+}
+
+impl Read for Rfc2217Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if !self.rx.get_mut().unwrap().is_empty() {
+                return Ok(self.take_received(buf));
+            }
+            let mut received = [0u8; 1024];
+            let n = self.stream.try_read(&mut received)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            self.input(&received[..n]);
+        }
+    }
+}
+
+impl Write for Rfc2217Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.tx.get_mut().unwrap().len() >= MAX_QUEUED {
+            // Nothing more is accepted until the server took some of the queue.
+            if let Err(e) = self.flush() {
+                if e.kind() != io::ErrorKind::WouldBlock
+                    || self.tx.get_mut().unwrap().len() >= MAX_QUEUED
+                {
+                    return Err(e);
+                }
+            }
+        }
+        escape(buf, self.tx.get_mut().unwrap());
+        match self.flush() {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(buf.len()),
+            result => result.map(|()| buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let tx = self.tx.get_mut().unwrap();
+        while !tx.is_empty() {
+            let n = self.stream.try_write(tx)?;
+            tx.drain(..n);
+        }
+        Ok(())
+    }
+}
+
+impl SerialPort for Rfc2217Stream {
+    fn name(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+
+    fn baud_rate(&self) -> crate::Result<u32> {
+        Ok(self.settings.baud_rate)
+    }
+
+    fn data_bits(&self) -> crate::Result<DataBits> {
+        Ok(self.settings.data_bits)
+    }
+
+    fn flow_control(&self) -> crate::Result<FlowControl> {
+        Ok(self.settings.flow_control)
+    }
+
+    fn parity(&self) -> crate::Result<Parity> {
+        Ok(self.settings.parity)
+    }
+
+    fn stop_bits(&self) -> crate::Result<StopBits> {
+        Ok(self.settings.stop_bits)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> crate::Result<()> {
+        self.settings.baud_rate = baud_rate;
+        self.set(SET_BAUDRATE, &baud_rate.to_be_bytes());
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> crate::Result<()> {
+        self.settings.data_bits = data_bits;
+        self.set(SET_DATASIZE, &[data_bits_value(data_bits)]);
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> crate::Result<()> {
+        self.settings.flow_control = flow_control;
+        self.set(SET_CONTROL, &[flow_control_value(flow_control)]);
+        Ok(())
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> crate::Result<()> {
+        self.settings.parity = parity;
+        self.set(SET_PARITY, &[parity_value(parity)]);
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> crate::Result<()> {
+        self.settings.stop_bits = stop_bits;
+        self.set(SET_STOPSIZE, &[stop_bits_value(stop_bits)]);
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> crate::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, level: bool) -> crate::Result<()> {
+        self.line(if level { RTS_ON } else { RTS_OFF });
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, level: bool) -> crate::Result<()> {
+        self.line(if level { DTR_ON } else { DTR_OFF });
+        Ok(())
+    }
+
+    fn read_clear_to_send(&mut self) -> crate::Result<bool> {
+        Ok(self.modem_state & CTS != 0)
+    }
+
+    fn read_data_set_ready(&mut self) -> crate::Result<bool> {
+        Ok(self.modem_state & DSR != 0)
+    }
+
+    fn read_ring_indicator(&mut self) -> crate::Result<bool> {
+        Ok(self.modem_state & RI != 0)
+    }
+
+    fn read_carrier_detect(&mut self) -> crate::Result<bool> {
+        Ok(self.modem_state & CD != 0)
+    }
+
+    /// Returns the number of bytes received from the server and not read yet.
+    fn bytes_to_read(&self) -> crate::Result<u32> {
+        Ok(self.rx.lock().unwrap().len() as u32)
+    }
+
+    /// Returns the number of bytes not sent to the server yet.
+    fn bytes_to_write(&self) -> crate::Result<u32> {
+        Ok(self.tx.lock().unwrap().len() as u32)
+    }
+
+    /// Discard the data received, or ask the server to discard the data it did not
+    /// send yet.
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> crate::Result<()> {
+        let purge = match buffer_to_clear {
+            ClearBuffer::Input => 1,
+            ClearBuffer::Output => 2,
+            ClearBuffer::All => 3,
+        };
+        if purge & 1 != 0 {
+            self.rx.lock().unwrap().clear();
+        }
+        self.command(PURGE_DATA, &[purge]);
+        Ok(())
+    }
+
+    /// Cloning an `Rfc2217Stream` is not supported.
+    fn try_clone(&self) -> crate::Result<Box<dyn SerialPort>> {
+        Err(Error::new(
+            ErrorKind::Io(io::ErrorKind::Unsupported),
+            "Cannot clone RFC 2217 connections",
+        ))
+    }
+
+    fn set_break(&self) -> crate::Result<()> {
+        self.line(BREAK_ON);
+        Ok(())
+    }
+
+    fn clear_break(&self) -> crate::Result<()> {
+        self.line(BREAK_OFF);
+        Ok(())
+    }
+}
+
+/// The server side of an RFC 2217 connection
+///
+/// Answers the COM Port Control requests of a client by changing the settings of
+/// the local port, see [`BridgeServer::serve_rfc2217`](crate::bridge::BridgeServer::serve_rfc2217).
+#[derive(Debug, Default)]
+pub(crate) struct ServerSession {
+    telnet: Telnet,
+    // States of the lines the port cannot be asked about
+    dtr: bool,
+    rts: bool,
+    break_state: bool,
+    modem_mask: u8,
+    // The modem lines last notified
+    modem_state: Option<u8>,
+}
+
+impl ServerSession {
+    pub(crate) fn new() -> Self {
+        Self {
+            dtr: true,
+            rts: true,
+            modem_mask: 0xff,
+            ..Self::default()
+        }
+    }
+
+    fn negotiation(&mut self, verb: u8, option: u8, reply: &mut Vec<u8>) {
+        match (verb, option) {
+            // Offered or asked for in the greeting
+            (DO | WILL, BINARY | SUPPRESS_GO_AHEAD)
+            | (WILL, COM_PORT_OPTION)
+            | (DONT | WONT, _) => {}
+            (DO, option) => reply.extend_from_slice(&[IAC, WONT, option]),
+            (_, option) => reply.extend_from_slice(&[IAC, DONT, option]),
+        }
+    }
+
+    fn command<S: SerialPort>(
+        &mut self,
+        port: &mut S,
+        command: u8,
+        value: &[u8],
+        reply: &mut Vec<u8>,
+    ) {
+        let log_error = |what: &str, result: crate::Result<()>| {
+            if let Err(e) = result {
+                log::warn!("unable to {}: {}", what, e);
+            }
+        };
+        let answer: Vec<u8> = match (command, value) {
+            (SET_BAUDRATE, &[a, b, c, d]) => {
+                let baud_rate = u32::from_be_bytes([a, b, c, d]);
+                if baud_rate != 0 {
+                    log_error("set the baud rate", port.set_baud_rate(baud_rate));
+                }
+                port.baud_rate().unwrap_or(baud_rate).to_be_bytes().to_vec()
+            }
+            (SET_DATASIZE, &[value]) => {
+                if let Some(data_bits) = data_bits_from_value(value) {
+                    log_error("set the data bits", port.set_data_bits(data_bits));
+                }
+                vec![port.data_bits().map_or(value, data_bits_value)]
+            }
+            (SET_PARITY, &[value]) => {
+                if let Some(parity) = parity_from_value(value) {
+                    log_error("set the parity", port.set_parity(parity));
+                }
+                vec![port.parity().map_or(value, parity_value)]
+            }
+            (SET_STOPSIZE, &[value]) => {
+                if let Some(stop_bits) = stop_bits_from_value(value) {
+                    log_error("set the stop bits", port.set_stop_bits(stop_bits));
+                }
+                vec![port.stop_bits().map_or(value, stop_bits_value)]
+            }
+            (SET_CONTROL, &[value]) => vec![self.control(port, value)],
+            (NOTIFY_MODEMSTATE, _) => vec![self.read_modem_state(port)],
+            (NOTIFY_LINESTATE, _) => vec![0],
+            (SET_MODEMSTATE_MASK, &[mask]) => {
+                self.modem_mask = mask;
+                vec![mask]
+            }
+            (SET_LINESTATE_MASK, &[mask]) => vec![mask],
+            (PURGE_DATA, &[purge]) => {
+                let buffer = match purge {
+                    1 => Some(ClearBuffer::Input),
+                    2 => Some(ClearBuffer::Output),
+                    3 => Some(ClearBuffer::All),
+                    _ => None,
+                };
+                if let Some(buffer) = buffer {
+                    log_error("purge the buffers", port.clear(buffer));
+                }
+                vec![purge]
+            }
+            (FLOWCONTROL_SUSPEND | FLOWCONTROL_RESUME, _) => {
+                log::debug!("ignoring a request to suspend or resume sending");
+                return;
+            }
+            (command, value) => {
+                log::debug!("unexpected command {} {:?}", command, value);
+                return;
+            }
+        };
+        com_port_command(reply, command + SERVER_OFFSET, &answer);
+    }
+
+    // Apply a SET-CONTROL request, returns the answer.
+    fn control<S: SerialPort>(&mut self, port: &mut S, value: u8) -> u8 {
+        let result = match value {
+            FLOW_NONE | FLOW_SOFTWARE | FLOW_HARDWARE => {
+                let flow_control = flow_control_from_value(value).unwrap();
+                port.set_flow_control(flow_control)
+            }
+            BREAK_ON | BREAK_OFF => {
+                self.break_state = value == BREAK_ON;
+                if self.break_state {
+                    port.set_break()
+                } else {
+                    port.clear_break()
+                }
+            }
+            DTR_ON | DTR_OFF => {
+                self.dtr = value == DTR_ON;
+                port.write_data_terminal_ready(self.dtr)
+            }
+            RTS_ON | RTS_OFF => {
+                self.rts = value == RTS_ON;
+                port.write_request_to_send(self.rts)
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            log::warn!("unable to apply control {}: {}", value, e);
+        }
+        match value {
+            0 | FLOW_NONE | FLOW_SOFTWARE | FLOW_HARDWARE => {
+                port.flow_control().map_or(value, flow_control_value)
+            }
+            BREAK_STATE | BREAK_ON | BREAK_OFF => {
+                if self.break_state {
+                    BREAK_ON
+                } else {
+                    BREAK_OFF
+                }
+            }
+            DTR_STATE | DTR_ON | DTR_OFF => {
+                if self.dtr {
+                    DTR_ON
+                } else {
+                    DTR_OFF
+                }
+            }
+            RTS_STATE | RTS_ON | RTS_OFF => {
+                if self.rts {
+                    RTS_ON
+                } else {
+                    RTS_OFF
+                }
+            }
+            value => value,
+        }
+    }
+
+    // Read the modem lines, with the changes since they were last notified.
+    fn read_modem_state<S: SerialPort>(&mut self, port: &mut S) -> u8 {
+        let mut state = 0;
+        for (line, bit) in [
+            (port.read_clear_to_send(), CTS),
+            (port.read_data_set_ready(), DSR),
+            (port.read_ring_indicator(), RI),
+            (port.read_carrier_detect(), CD),
+        ] {
+            if line.unwrap_or(false) {
+                state |= bit;
+            }
+        }
+        let previous = self.modem_state.unwrap_or(state);
+        let changed = previous ^ state;
+        for (line, delta) in [(CTS, DELTA_CTS), (DSR, DELTA_DSR), (CD, DELTA_CD)] {
+            if changed & line != 0 {
+                state |= delta;
+            }
+        }
+        if previous & RI != 0 && state & RI == 0 {
+            state |= TRAILING_EDGE_RI;
+        }
+        self.modem_state = Some(state & 0xf0);
+        state
+    }
+}
+
+impl<S: SerialPort> crate::bridge::Session<S> for ServerSession {
+    fn greeting(&mut self) -> Vec<u8> {
+        vec![
+            IAC,
+            WILL,
+            BINARY,
+            IAC,
+            DO,
+            BINARY,
+            IAC,
+            WILL,
+            SUPPRESS_GO_AHEAD,
+            IAC,
+            DO,
+            SUPPRESS_GO_AHEAD,
+            IAC,
+            DO,
+            COM_PORT_OPTION,
+        ]
+    }
+
+    fn client_bytes(
+        &mut self,
+        bytes: &[u8],
+        port: &Mutex<S>,
+        data: &mut Vec<u8>,
+        reply: &mut Vec<u8>,
+    ) {
+        for &byte in bytes {
+            match self.telnet.push(byte) {
+                None => {}
+                Some(TelnetEvent::Data(byte)) => data.push(byte),
+                Some(TelnetEvent::Negotiation(verb, option)) => {
+                    self.negotiation(verb, option, reply)
+                }
+                Some(TelnetEvent::Subnegotiation(sub)) => {
+                    if let [COM_PORT_OPTION, command, value @ ..] = &sub[..] {
+                        let mut port = port.lock().unwrap();
+                        self.command(&mut *port, *command, value, reply);
+                    }
+                }
+            }
+        }
+    }
+
+    fn port_bytes<'a>(&mut self, data: &'a [u8]) -> std::borrow::Cow<'a, [u8]> {
+        if data.contains(&IAC) {
+            let mut escaped = Vec::with_capacity(data.len() + 16);
+            escape(data, &mut escaped);
+            escaped.into()
+        } else {
+            data.into()
+        }
+    }
+
+    fn poll_interval(&self) -> Option<Duration> {
+        Some(MODEM_POLL_INTERVAL)
+    }
+
+    fn poll(&mut self, port: &Mutex<S>, reply: &mut Vec<u8>) {
+        let previous = self.modem_state;
+        let state = self.read_modem_state(&mut *port.lock().unwrap());
+        if previous.is_some() && state & 0x0f & self.modem_mask != 0 {
+            com_port_command(
+                reply,
+                NOTIFY_MODEMSTATE + SERVER_OFFSET,
+                &[state & self.modem_mask],
+            );
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! A snapshot of the line settings of a port
+use crate::{DataBits, FlowControl, Parity, SerialPortBuilder, StopBits};
+
+/// The line settings of a serial port
+///
+/// Unlike a `SerialPortBuilder` it is not tied to a device path and its fields are
+/// public, so it can be logged, compared or persisted, and turned back into a
+/// builder with [`builder`](SerialSettings::builder) to reopen a port with the same
+/// configuration.
+///
+/// `SerialStream::settings` captures the live configuration of an open port.
+///
+/// With the `serde` feature it can be serialized, see [`PortConfig`](crate::PortConfig)
+/// for the format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SerialSettings {
+    /// The baud rate in symbols-per-second
+    pub baud_rate: u32,
+    /// Number of bits used to represent a character sent on the line
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            with = "crate::config::serde_fields::data_bits",
+            default = "crate::config::serde_fields::data_bits::default"
+        )
+    )]
+    pub data_bits: DataBits,
+    /// The type of parity to use for error checking
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            with = "crate::config::serde_fields::parity",
+            default = "crate::config::serde_fields::parity::default"
+        )
+    )]
+    pub parity: Parity,
+    /// Number of bits to use to signal the end of a character
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            with = "crate::config::serde_fields::stop_bits",
+            default = "crate::config::serde_fields::stop_bits::default"
+        )
+    )]
+    pub stop_bits: StopBits,
+    /// The type of signalling to use for controlling data transfer
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            with = "crate::config::serde_fields::flow_control",
+            default = "crate::config::serde_fields::flow_control::default"
+        )
+    )]
+    pub flow_control: FlowControl,
+}
+
+impl SerialSettings {
+    /// Settings for `baud_rate` with 8 data bits, no parity, one stop bit and no flow
+    /// control, the same defaults as `tokio_serial::new`.
+    pub fn new(baud_rate: u32) -> Self {
+        Self {
+            baud_rate,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+        }
+    }
+
+    /// Returns the time taken to send one character on the line.
+    ///
+    /// This counts the start bit, the data bits, the parity bit and the stop bits.
+    pub fn char_time(&self) -> std::time::Duration {
+        let data = match self.data_bits {
+            DataBits::Five => 5,
+            DataBits::Six => 6,
+            DataBits::Seven => 7,
+            DataBits::Eight => 8,
+        };
+        let parity = match self.parity {
+            Parity::None => 0,
+            Parity::Odd | Parity::Even => 1,
+        };
+        let stop = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        let bits = 1 + data + parity + stop;
+        std::time::Duration::from_nanos(bits * 1_000_000_000 / u64::from(self.baud_rate.max(1)))
+    }
+
+    /// Create a builder opening `path` with these settings.
+    pub fn builder<'a>(&self, path: impl Into<std::borrow::Cow<'a, str>>) -> SerialPortBuilder {
+        self.apply_to(crate::new(path, self.baud_rate))
+    }
+
+    /// Change the line settings of `builder` to these, keeping its other options.
+    pub fn apply_to(&self, builder: SerialPortBuilder) -> SerialPortBuilder {
+        builder
+            .baud_rate(self.baud_rate)
+            .data_bits(self.data_bits)
+            .parity(self.parity)
+            .stop_bits(self.stop_bits)
+            .flow_control(self.flow_control)
+    }
+}
+
+#[cfg(unix)]
+pub(crate) use self::unix::apply;
+
+#[cfg(windows)]
+pub(crate) use self::windows::apply;
+
+#[cfg(unix)]
+mod unix {
+    use super::SerialSettings;
+    use crate::{DataBits, FlowControl, Parity, StopBits};
+
+    use std::io;
+    use std::mem::MaybeUninit;
+    use std::os::unix::io::RawFd;
+
+    /// Update the flags for everything but the baud rate, mirroring what
+    /// `serialport` sets on open.
+    fn set_flags(cflag: &mut libc::tcflag_t, iflag: &mut libc::tcflag_t, s: &SerialSettings) {
+        *cflag &= !libc::CSIZE;
+        *cflag |= match s.data_bits {
+            DataBits::Five => libc::CS5,
+            DataBits::Six => libc::CS6,
+            DataBits::Seven => libc::CS7,
+            DataBits::Eight => libc::CS8,
+        };
+
+        match s.parity {
+            Parity::None => {
+                *cflag &= !(libc::PARENB | libc::PARODD);
+                *iflag &= !libc::INPCK;
+                *iflag |= libc::IGNPAR;
+            }
+            Parity::Odd => {
+                *cflag |= libc::PARENB | libc::PARODD;
+                *iflag |= libc::INPCK;
+                *iflag &= !libc::IGNPAR;
+            }
+            Parity::Even => {
+                *cflag &= !libc::PARODD;
+                *cflag |= libc::PARENB;
+                *iflag |= libc::INPCK;
+                *iflag &= !libc::IGNPAR;
+            }
+        }
+
+        match s.stop_bits {
+            StopBits::One => *cflag &= !libc::CSTOPB,
+            StopBits::Two => *cflag |= libc::CSTOPB,
+        }
+
+        match s.flow_control {
+            FlowControl::None => {
+                *iflag &= !(libc::IXON | libc::IXOFF);
+                *cflag &= !libc::CRTSCTS;
+            }
+            FlowControl::Software => {
+                *iflag |= libc::IXON | libc::IXOFF;
+                *cflag &= !libc::CRTSCTS;
+            }
+            FlowControl::Hardware => {
+                *iflag &= !(libc::IXON | libc::IXOFF);
+                *cflag |= libc::CRTSCTS;
+            }
+        }
+    }
+
+    fn check(res: libc::c_int) -> crate::Result<()> {
+        if res == -1 {
+            Err(io::Error::last_os_error().into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Apply `settings` with a single `TCSETS2`, which supports arbitrary baud rates.
+    #[cfg(all(
+        any(target_os = "linux", target_os = "android"),
+        not(any(target_arch = "powerpc", target_arch = "powerpc64"))
+    ))]
+    pub(crate) fn apply(fd: RawFd, settings: &SerialSettings) -> crate::Result<()> {
+        let mut termios = MaybeUninit::<libc::termios2>::uninit();
+        check(unsafe { libc::ioctl(fd, libc::TCGETS2 as _, termios.as_mut_ptr()) })?;
+        let mut termios = unsafe { termios.assume_init() };
+
+        set_flags(&mut termios.c_cflag, &mut termios.c_iflag, settings);
+        termios.c_cflag &= !(libc::CBAUD | libc::CIBAUD);
+        termios.c_cflag |= libc::BOTHER;
+        termios.c_ispeed = settings.baud_rate;
+        termios.c_ospeed = settings.baud_rate;
+
+        check(unsafe { libc::ioctl(fd, libc::TCSETS2 as _, &termios) })
+    }
+
+    /// Apply `settings` with a single `tcsetattr`, these platforms take the baud rate
+    /// itself as speed value.
+    #[cfg(not(all(
+        any(target_os = "linux", target_os = "android"),
+        not(any(target_arch = "powerpc", target_arch = "powerpc64"))
+    )))]
+    pub(crate) fn apply(fd: RawFd, settings: &SerialSettings) -> crate::Result<()> {
+        let mut termios = MaybeUninit::<libc::termios>::uninit();
+        check(unsafe { libc::tcgetattr(fd, termios.as_mut_ptr()) })?;
+        let mut termios = unsafe { termios.assume_init() };
+
+        set_flags(&mut termios.c_cflag, &mut termios.c_iflag, settings);
+        check(unsafe { libc::cfsetspeed(&mut termios, settings.baud_rate as libc::speed_t) })?;
+
+        check(unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) })
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::SerialSettings;
+    use crate::{DataBits, FlowControl, Parity, StopBits};
+
+    use std::io;
+    use std::mem;
+    use std::os::windows::io::RawHandle;
+    use windows_sys::Win32::Devices::Communication::{
+        GetCommState, SetCommState, DCB, EVENPARITY, NOPARITY, ODDPARITY, ONESTOPBIT, TWOSTOPBITS,
+    };
+
+    // Bits of `DCB::_bitfield`
+    const F_PARITY: u32 = 1 << 1;
+    const F_OUTX_CTS_FLOW: u32 = 1 << 2;
+    const F_OUTX: u32 = 1 << 8;
+    const F_INX: u32 = 1 << 9;
+    const F_RTS_CONTROL_MASK: u32 = 0b11 << 12;
+    const F_RTS_CONTROL_ENABLE: u32 = 0b01 << 12;
+
+    /// Apply `settings` with a single `SetCommState`, mirroring what `serialport`
+    /// sets on open.
+    pub(crate) fn apply(handle: RawHandle, settings: &SerialSettings) -> crate::Result<()> {
+        let handle = handle as _;
+        let mut dcb: DCB = unsafe { mem::zeroed() };
+        dcb.DCBlength = mem::size_of::<DCB>() as u32;
+        if unsafe { GetCommState(handle, &mut dcb) } == 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        dcb.BaudRate = settings.baud_rate;
+        dcb.ByteSize = match settings.data_bits {
+            DataBits::Five => 5,
+            DataBits::Six => 6,
+            DataBits::Seven => 7,
+            DataBits::Eight => 8,
+        };
+        dcb.Parity = match settings.parity {
+            Parity::None => NOPARITY,
+            Parity::Odd => ODDPARITY,
+            Parity::Even => EVENPARITY,
+        };
+        if settings.parity == Parity::None {
+            dcb._bitfield &= !F_PARITY;
+        } else {
+            dcb._bitfield |= F_PARITY;
+        }
+        dcb.StopBits = match settings.stop_bits {
+            StopBits::One => ONESTOPBIT,
+            StopBits::Two => TWOSTOPBITS,
+        };
+
+        dcb._bitfield &= !(F_OUTX_CTS_FLOW | F_OUTX | F_INX | F_RTS_CONTROL_MASK);
+        dcb._bitfield |= match settings.flow_control {
+            FlowControl::None => 0,
+            FlowControl::Software => F_OUTX | F_INX,
+            FlowControl::Hardware => F_OUTX_CTS_FLOW | F_RTS_CONTROL_ENABLE,
+        };
+
+        if unsafe { SetCommState(handle, &dcb) } == 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+}
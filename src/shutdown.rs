@@ -0,0 +1,63 @@
+use futures::future::BoxFuture;
+
+use std::io;
+
+/// Ordered shutdown of stacked port wrappers
+///
+/// Protocol layers stacked on a port (codecs, flow control, recorders, or
+/// application layers such as encryption or ARQ) often have something to do before
+/// the link goes away: flush buffered data, send a closing frame, wait for an
+/// acknowledgement.  Shutting the stack down from the top with
+/// [`shutdown_layered`](ShutdownLayered::shutdown_layered) lets every layer
+/// [`finish`](ShutdownLayered::finish) while the layers below it are still
+/// working, from the outermost to the port itself.
+///
+/// `SerialStream` is the bottom layer: finishing it flushes it and waits for the
+/// output buffer to be transmitted, so the last bytes are not lost when the port is
+/// closed right afterwards.
+pub trait ShutdownLayered: Send {
+    /// Finish this layer, leaving the layers below it open.
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>>;
+
+    /// Returns the layer below this one, or [`None`] for the bottom layer.
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered>;
+
+    /// Finish this layer, then the layers below it in order.
+    fn shutdown_layered(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(async move {
+            self.finish().await?;
+            match self.inner_layer() {
+                Some(inner) => inner.shutdown_layered().await,
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+impl<T: ShutdownLayered + ?Sized> ShutdownLayered for &mut T {
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        (**self).finish()
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        (**self).inner_layer()
+    }
+
+    fn shutdown_layered(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        (**self).shutdown_layered()
+    }
+}
+
+impl<T: ShutdownLayered + ?Sized> ShutdownLayered for Box<T> {
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        (**self).finish()
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        (**self).inner_layer()
+    }
+
+    fn shutdown_layered(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        (**self).shutdown_layered()
+    }
+}
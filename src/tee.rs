@@ -0,0 +1,266 @@
+//! Copying the traffic of a port for live monitoring
+//!
+//! A [`TeeStream`] passes everything through to the port it wraps and gives a copy
+//! of the data read and written, tagged with its [`Direction`], to a [`TeeSink`]:
+//!
+//! * A tokio channel, [`mpsc::UnboundedSender`] or [`mpsc::Sender`], receiving one
+//!   message per chunk of data.
+//! * Any [`AsyncWrite`], such as a TCP connection to a dashboard, through a
+//!   [`TeeWriter`] writing one line of text per chunk.
+//!
+//! Sinks never slow the port down: when they cannot keep up, copies are dropped.
+//!
+//! ```no_run
+//! use tokio::sync::mpsc;
+//! use tokio_serial::tee::TeeStream;
+//!
+//! # async fn example(port: tokio_serial::SerialStream) {
+//! let (tx, mut rx) = mpsc::unbounded_channel();
+//! let port = TeeStream::new(port, tx);
+//! tokio::spawn(async move {
+//!     while let Some((direction, data)) = rx.recv().await {
+//!         println!("{:?} {:02x?}", direction, data);
+//!     }
+//! });
+//! # }
+//! ```
+use crate::transcript::Direction;
+use crate::{AsyncSerialPort, ShutdownLayered};
+
+use futures::future::{self, BoxFuture};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc;
+
+use std::fmt::Write as _;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Where a [`TeeStream`] copies the traffic
+pub trait TeeSink: Send {
+    /// Take a copy of `data`, going in `direction`.
+    ///
+    /// This must not block, sinks which cannot take the copy right away keep it
+    /// for [`poll_flush`](TeeSink::poll_flush) or drop it.
+    fn copy(&mut self, direction: Direction, data: &[u8]);
+
+    /// Make progress delivering the copies taken so far.
+    ///
+    /// Called whenever the stream is polled, and awaited when it is shut down.
+    fn poll_flush(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Copies are dropped once the receiver is closed.
+impl TeeSink for mpsc::UnboundedSender<(Direction, Vec<u8>)> {
+    fn copy(&mut self, direction: Direction, data: &[u8]) {
+        let _ = self.send((direction, data.to_vec()));
+    }
+}
+
+/// Copies are dropped while the channel is full or once the receiver is closed.
+impl TeeSink for mpsc::Sender<(Direction, Vec<u8>)> {
+    fn copy(&mut self, direction: Direction, data: &[u8]) {
+        let _ = self.try_send((direction, data.to_vec()));
+    }
+}
+
+/// A [`TeeSink`] writing the traffic as text to an [`AsyncWrite`]
+///
+/// Every chunk of data is written as one line: the direction, `RX` or `TX`,
+/// followed by the data in hex, e.g. `TX 41 54 0d`.  Lines not written yet are
+/// buffered up to a limit, lines beyond it are dropped.
+#[derive(Debug)]
+pub struct TeeWriter<W> {
+    inner: W,
+    pending: Vec<u8>,
+    limit: usize,
+    dropped: u64,
+}
+
+impl<W> TeeWriter<W> {
+    /// Write to `inner`, buffering up to 64 KiB.
+    pub fn new(inner: W) -> Self {
+        Self::with_limit(inner, 64 * 1024)
+    }
+
+    /// Write to `inner`, buffering up to `limit` bytes of text.
+    pub fn with_limit(inner: W, limit: usize) -> Self {
+        Self {
+            inner,
+            pending: Vec::new(),
+            limit,
+            dropped: 0,
+        }
+    }
+
+    /// Returns how many lines were dropped because the writer could not keep up.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Returns a reference to the wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns the wrapped writer, dropping the lines not written yet.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send> TeeSink for TeeWriter<W> {
+    fn copy(&mut self, direction: Direction, data: &[u8]) {
+        let mut line = String::with_capacity(3 + data.len() * 3);
+        line.push_str(match direction {
+            Direction::Rx => "RX",
+            Direction::Tx => "TX",
+        });
+        for byte in data {
+            let _ = write!(line, " {:02x}", byte);
+        }
+        line.push('\n');
+        if self.pending.len() + line.len() > self.limit {
+            self.dropped += 1;
+        } else {
+            self.pending.extend_from_slice(line.as_bytes());
+        }
+    }
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.pending.is_empty() {
+            let n = futures::ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending.drain(..n);
+        }
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+}
+
+/// A port wrapper copying all data read and written to a [`TeeSink`]
+#[derive(Debug)]
+pub struct TeeStream<S, K> {
+    inner: S,
+    sink: K,
+}
+
+impl<S, K: TeeSink> TeeStream<S, K> {
+    /// Copy the data going through `inner` to `sink`.
+    pub fn new(inner: S, sink: K) -> Self {
+        Self { inner, sink }
+    }
+
+    /// Returns a reference to the wrapped port.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped port.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Returns a reference to the sink.
+    pub fn sink(&self) -> &K {
+        &self.sink
+    }
+
+    /// Stop copying, returning the wrapped port and the sink.
+    pub fn into_parts(self) -> (S, K) {
+        (self.inner, self.sink)
+    }
+
+    fn copy(&mut self, cx: &mut Context<'_>, direction: Direction, data: &[u8]) {
+        if !data.is_empty() {
+            self.sink.copy(direction, data);
+        }
+        // Errors of the sink are not the port's.
+        let _ = self.sink.poll_flush(cx);
+    }
+}
+
+impl<S: AsyncRead + Unpin, K: TeeSink + Unpin> AsyncRead for TeeStream<S, K> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        let received = match result {
+            Poll::Ready(Ok(())) => &buf.filled()[before..],
+            _ => &[],
+        };
+        this.copy(cx, Direction::Rx, received);
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin, K: TeeSink + Unpin> AsyncWrite for TeeStream<S, K> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        let sent = match result {
+            Poll::Ready(Ok(n)) => &buf[..n],
+            _ => &[],
+        };
+        this.copy(cx, Direction::Tx, sent);
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.copy(cx, Direction::Tx, &[]);
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.copy(cx, Direction::Tx, &[]);
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+impl<S: AsyncSerialPort, K: TeeSink + Unpin> AsyncSerialPort for TeeStream<S, K> {
+    fn port_name(&self) -> Option<String> {
+        self.inner.port_name()
+    }
+
+    fn change_baud_rate(&mut self, baud_rate: u32) -> crate::Result<()> {
+        self.inner.change_baud_rate(baud_rate)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> crate::Result<()> {
+        self.inner.set_dtr(level)
+    }
+
+    fn set_rts(&mut self, level: bool) -> crate::Result<()> {
+        self.inner.set_rts(level)
+    }
+
+    fn set_break_condition(&mut self, asserted: bool) -> crate::Result<()> {
+        self.inner.set_break_condition(asserted)
+    }
+}
+
+/// Finishing waits for the sink to deliver the copies taken so far.
+impl<S: ShutdownLayered, K: TeeSink> ShutdownLayered for TeeStream<S, K> {
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        let sink = &mut self.sink;
+        Box::pin(future::poll_fn(move |cx| sink.poll_flush(cx)))
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        Some(&mut self.inner)
+    }
+}
@@ -0,0 +1,769 @@
+//! Recording sessions and exporting them for analysis
+//!
+//! A [`Recorder`] wraps a port and keeps a [`Transcript`] of everything read and
+//! written, with timestamps relative to the start of the recording.  Transcripts can
+//! be exported as:
+//!
+//! * CSV, one row per chunk of data, see [`Transcript::write_csv`].
+//! * A self-contained HTML report, see [`Transcript::write_html`].
+//! * Sigrok/PulseView annotations in the format of PulseView's annotation export,
+//!   see [`Transcript::write_sigrok_annotations`].
+//!
+//! For sessions too long to keep in memory, a [`Capture`] writes the data to a file
+//! in a compact binary format as it goes, see [`Transcript::write_capture`].  A
+//! capture read back with [`Transcript::read_capture`] can be played by a
+//! [`Replay`], a port receiving the recorded data with the original timing, to
+//! reproduce a field problem offline.
+use crate::{AsyncSerialPort, ShutdownLayered};
+
+use futures::future::{self, BoxFuture};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// The direction data went through the port
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Data received from the port
+    Rx,
+    /// Data sent to the port
+    Tx,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::Rx => "RX",
+            Direction::Tx => "TX",
+        }
+    }
+}
+
+/// A chunk of data read or written in one go
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Time since the start of the transcript
+    pub at: Duration,
+    /// Whether the data was received or sent
+    pub direction: Direction,
+    /// The data itself
+    pub data: Vec<u8>,
+}
+
+/// The data exchanged during a session
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcript {
+    records: Vec<Record>,
+    baud_rate: Option<u32>,
+}
+
+impl Transcript {
+    /// An empty transcript
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the baud rate the session ran at.
+    ///
+    /// It is used to place individual bytes when exporting annotations.
+    pub fn set_baud_rate(&mut self, baud_rate: u32) {
+        self.baud_rate = Some(baud_rate);
+    }
+
+    /// Returns the baud rate the session ran at, if known.
+    pub fn baud_rate(&self) -> Option<u32> {
+        self.baud_rate
+    }
+
+    /// Append a record.
+    ///
+    /// Records are expected in chronological order.
+    pub fn push(&mut self, record: Record) {
+        self.records.push(record);
+    }
+
+    /// Returns the records, in chronological order.
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Remove all records.
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    /// Export as CSV
+    ///
+    /// The columns are the time in seconds, the direction (`RX` or `TX`), the data as
+    /// hex and the data as text with non-printable bytes escaped.
+    pub fn write_csv<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "time,direction,hex,text")?;
+        for record in &self.records {
+            writeln!(
+                out,
+                "{:.6},{},{},\"{}\"",
+                record.at.as_secs_f64(),
+                record.direction.as_str(),
+                hex(&record.data, ""),
+                escape(&record.data).replace('"', "\"\""),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Export as a self-contained HTML report
+    pub fn write_html<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "<!DOCTYPE html>")?;
+        writeln!(
+            out,
+            "<html><head><meta charset=\"utf-8\"><title>Serial session</title>"
+        )?;
+        writeln!(
+            out,
+            "<style>body{{font-family:sans-serif}}td{{font-family:monospace;padding:0 1em}}\
+             .RX{{color:#1a5fb4}}.TX{{color:#c64600}}</style>"
+        )?;
+        writeln!(out, "</head><body><h1>Serial session</h1>")?;
+        if let Some(baud_rate) = self.baud_rate {
+            writeln!(out, "<p>Baud rate: {}</p>", baud_rate)?;
+        }
+        let (rx, tx) =
+            self.records
+                .iter()
+                .fold((0, 0), |(rx, tx), record| match record.direction {
+                    Direction::Rx => (rx + record.data.len(), tx),
+                    Direction::Tx => (rx, tx + record.data.len()),
+                });
+        writeln!(out, "<p>{} bytes received, {} bytes sent</p>", rx, tx)?;
+        writeln!(
+            out,
+            "<table><tr><th>Time (s)</th><th>Dir</th><th>Hex</th><th>Text</th></tr>"
+        )?;
+        for record in &self.records {
+            let direction = record.direction.as_str();
+            writeln!(
+                out,
+                "<tr class=\"{}\"><td>{:.6}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                direction,
+                record.at.as_secs_f64(),
+                direction,
+                hex(&record.data, " "),
+                html_escape(&escape(&record.data)),
+            )?;
+        }
+        writeln!(out, "</table></body></html>")
+    }
+
+    /// Export as Sigrok/PulseView annotations
+    ///
+    /// Writes one line per byte in the format of PulseView's annotation export, as the
+    /// `uart` decoder would produce them, e.g. `1200-1300 uart-1: RX data: 41`.
+    /// Sample numbers are computed at `samplerate` samples per second.  Without a
+    /// [baud rate](Transcript::set_baud_rate) every byte of a record is annotated at
+    /// the time of the record.
+    pub fn write_sigrok_annotations<W: Write>(
+        &self,
+        mut out: W,
+        samplerate: u64,
+    ) -> io::Result<()> {
+        // One start bit, eight data bits and one stop bit.
+        let byte_samples = self
+            .baud_rate
+            .map_or(0, |baud_rate| samplerate * 10 / u64::from(baud_rate.max(1)));
+        for record in &self.records {
+            let mut start = (record.at.as_secs_f64() * samplerate as f64) as u64;
+            for byte in &record.data {
+                writeln!(
+                    out,
+                    "{}-{} uart-1: {} data: {:02X}",
+                    start,
+                    start + byte_samples,
+                    record.direction.as_str(),
+                    byte
+                )?;
+                start += byte_samples;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The header of capture files, followed by the format version
+const CAPTURE_MAGIC: &[u8; 5] = b"TSCAP";
+const CAPTURE_VERSION: u8 = 1;
+
+// The kinds of entries of capture files
+const ENTRY_RX: u64 = 0;
+const ENTRY_TX: u64 = 1;
+const ENTRY_BAUD_RATE: u64 = 2;
+
+impl Transcript {
+    /// Export as a capture file
+    ///
+    /// The format is compact: after a 6 bytes header, every record takes its data
+    /// and a few bytes for its time and length.  Captures keep the full resolution
+    /// of the timestamps down to the microsecond.
+    pub fn write_capture<W: Write>(&self, out: W) -> io::Result<()> {
+        let mut writer = CaptureWriter::new(out)?;
+        if let Some(baud_rate) = self.baud_rate {
+            writer.baud_rate(Duration::ZERO, baud_rate)?;
+        }
+        for record in &self.records {
+            writer.record(record.at, record.direction, &record.data)?;
+        }
+        writer.out.flush()
+    }
+
+    /// Import a capture file written by [`write_capture`](Transcript::write_capture)
+    /// or a [`Capture`].
+    ///
+    /// ## Errors
+    ///
+    /// * `InvalidData` if `input` is not a capture.
+    /// * `UnexpectedEof` if the capture is truncated.
+    pub fn read_capture<R: Read>(mut input: R) -> io::Result<Self> {
+        let mut header = [0u8; 6];
+        input.read_exact(&mut header)?;
+        if &header[..5] != CAPTURE_MAGIC || header[5] != CAPTURE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a capture file",
+            ));
+        }
+
+        let mut transcript = Transcript::new();
+        let mut at = Duration::ZERO;
+        while let Some(delta) = read_varint(&mut input, true)? {
+            at += Duration::from_micros(delta);
+            let entry = read_varint(&mut input, false)?.unwrap_or_default();
+            let value = entry >> 2;
+            let direction = match entry & 3 {
+                ENTRY_RX => Direction::Rx,
+                ENTRY_TX => Direction::Tx,
+                ENTRY_BAUD_RATE => {
+                    transcript.set_baud_rate(value.try_into().map_err(|_| invalid_capture())?);
+                    continue;
+                }
+                _ => return Err(invalid_capture()),
+            };
+            // A corrupted length must not allocate more than the file holds.
+            let mut data = Vec::new();
+            input.by_ref().take(value).read_to_end(&mut data)?;
+            if (data.len() as u64) < value {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            transcript.push(Record {
+                at,
+                direction,
+                data,
+            });
+        }
+        Ok(transcript)
+    }
+}
+
+fn invalid_capture() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "corrupted capture file")
+}
+
+// Write `value` 7 bits at a time, least significant first.
+fn write_varint<W: Write>(out: &mut W, mut value: u64) -> io::Result<()> {
+    let mut buf = [0u8; 10];
+    let mut n = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf[n] = byte;
+            n += 1;
+            break;
+        }
+        buf[n] = byte | 0x80;
+        n += 1;
+    }
+    out.write_all(&buf[..n])
+}
+
+// Returns `None` if `input` ends before the first byte and `eof_ok` is set.
+fn read_varint<R: Read>(input: &mut R, eof_ok: bool) -> io::Result<Option<u64>> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8; 1];
+        if input.read(&mut byte)? == 0 {
+            if shift == 0 && eof_ok {
+                return Ok(None);
+            }
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(invalid_capture())
+}
+
+// Writes the entries of a capture file
+#[derive(Debug)]
+struct CaptureWriter<W> {
+    out: W,
+    last: Duration,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(CAPTURE_MAGIC)?;
+        out.write_all(&[CAPTURE_VERSION])?;
+        Ok(Self {
+            out,
+            last: Duration::ZERO,
+        })
+    }
+
+    fn entry(&mut self, at: Duration, kind: u64, value: u64) -> io::Result<()> {
+        // Records are in chronological order, an earlier one still gets written.
+        let delta = at.saturating_sub(self.last);
+        self.last = self.last.max(at);
+        write_varint(&mut self.out, delta.as_micros() as u64)?;
+        write_varint(&mut self.out, value << 2 | kind)
+    }
+
+    fn baud_rate(&mut self, at: Duration, baud_rate: u32) -> io::Result<()> {
+        self.entry(at, ENTRY_BAUD_RATE, u64::from(baud_rate))
+    }
+
+    fn record(&mut self, at: Duration, direction: Direction, data: &[u8]) -> io::Result<()> {
+        let kind = match direction {
+            Direction::Rx => ENTRY_RX,
+            Direction::Tx => ENTRY_TX,
+        };
+        self.entry(at, kind, data.len() as u64)?;
+        self.out.write_all(data)
+    }
+}
+
+fn hex(data: &[u8], separator: &str) -> String {
+    data.iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn escape(data: &[u8]) -> String {
+    data.iter()
+        .flat_map(|&byte| std::ascii::escape_default(byte))
+        .map(char::from)
+        .collect()
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A port wrapper recording all data read and written into a [`Transcript`]
+#[derive(Debug)]
+pub struct Recorder<S> {
+    inner: S,
+    start: Instant,
+    transcript: Transcript,
+}
+
+impl<S> Recorder<S> {
+    /// Start recording the data going through `inner`.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            start: Instant::now(),
+            transcript: Transcript::new(),
+        }
+    }
+
+    /// Returns the transcript recorded so far.
+    pub fn transcript(&self) -> &Transcript {
+        &self.transcript
+    }
+
+    /// Returns a mutable reference to the transcript recorded so far.
+    pub fn transcript_mut(&mut self) -> &mut Transcript {
+        &mut self.transcript
+    }
+
+    /// Returns a reference to the wrapped port.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped port.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Stop recording, returning the wrapped port and the transcript.
+    pub fn into_parts(self) -> (S, Transcript) {
+        (self.inner, self.transcript)
+    }
+
+    fn record(&mut self, direction: Direction, data: &[u8]) {
+        if !data.is_empty() {
+            self.transcript.push(Record {
+                at: self.start.elapsed(),
+                direction,
+                data: data.to_vec(),
+            });
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Recorder<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            this.record(Direction::Rx, &buf.filled()[before..]);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Recorder<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            this.record(Direction::Tx, &buf[..n]);
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl<S: AsyncSerialPort> AsyncSerialPort for Recorder<S> {
+    fn port_name(&self) -> Option<String> {
+        self.inner.port_name()
+    }
+
+    fn change_baud_rate(&mut self, baud_rate: u32) -> crate::Result<()> {
+        self.inner.change_baud_rate(baud_rate)?;
+        self.transcript.set_baud_rate(baud_rate);
+        Ok(())
+    }
+
+    fn set_dtr(&mut self, level: bool) -> crate::Result<()> {
+        self.inner.set_dtr(level)
+    }
+
+    fn set_rts(&mut self, level: bool) -> crate::Result<()> {
+        self.inner.set_rts(level)
+    }
+
+    fn set_break_condition(&mut self, asserted: bool) -> crate::Result<()> {
+        self.inner.set_break_condition(asserted)
+    }
+}
+
+impl<S: ShutdownLayered> ShutdownLayered for Recorder<S> {
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(future::ready(Ok(())))
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        Some(&mut self.inner)
+    }
+}
+
+/// A port wrapper writing all data read and written to a capture file
+///
+/// Unlike a [`Recorder`], nothing is kept in memory.  Writing the capture must not
+/// disturb the session: if it fails, capturing stops and the error is returned by
+/// [`into_parts`](Capture::into_parts).  Wrap files in a [`BufWriter`](io::BufWriter).
+#[derive(Debug)]
+pub struct Capture<S, W> {
+    inner: S,
+    start: Instant,
+    writer: Option<CaptureWriter<W>>,
+    error: Option<io::Error>,
+}
+
+impl<S, W: Write> Capture<S, W> {
+    /// Start capturing the data going through `inner` to `out`.
+    ///
+    /// ## Errors
+    ///
+    /// The errors of writing the header of the capture to `out`.
+    pub fn new(inner: S, out: W) -> io::Result<Self> {
+        Ok(Self {
+            inner,
+            start: Instant::now(),
+            writer: Some(CaptureWriter::new(out)?),
+            error: None,
+        })
+    }
+
+    /// Returns a reference to the wrapped port.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped port.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Stop capturing, returning the wrapped port and the flushed output.
+    ///
+    /// The output is the first error writing the capture, if any.
+    pub fn into_parts(mut self) -> (S, io::Result<W>) {
+        let out = match (self.error.take(), self.writer.take()) {
+            (Some(e), _) => Err(e),
+            (None, Some(mut writer)) => writer.out.flush().map(|()| writer.out),
+            (None, None) => unreachable!("capture stopped without an error"),
+        };
+        (self.inner, out)
+    }
+
+    fn write(&mut self, write: impl FnOnce(&mut CaptureWriter<W>, Duration) -> io::Result<()>) {
+        let at = self.start.elapsed();
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(e) = write(writer, at) {
+                self.error = Some(e);
+                self.writer = None;
+            }
+        }
+    }
+
+    fn record(&mut self, direction: Direction, data: &[u8]) {
+        if !data.is_empty() {
+            self.write(|writer, at| writer.record(at, direction, data));
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin, W: Write + Unpin> AsyncRead for Capture<S, W> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            this.record(Direction::Rx, &buf.filled()[before..]);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin, W: Write + Unpin> AsyncWrite for Capture<S, W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            this.record(Direction::Tx, &buf[..n]);
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl<S: AsyncSerialPort, W: Write + Unpin> AsyncSerialPort for Capture<S, W> {
+    fn port_name(&self) -> Option<String> {
+        self.inner.port_name()
+    }
+
+    fn change_baud_rate(&mut self, baud_rate: u32) -> crate::Result<()> {
+        self.inner.change_baud_rate(baud_rate)?;
+        self.write(|writer, at| writer.baud_rate(at, baud_rate));
+        Ok(())
+    }
+
+    fn set_dtr(&mut self, level: bool) -> crate::Result<()> {
+        self.inner.set_dtr(level)
+    }
+
+    fn set_rts(&mut self, level: bool) -> crate::Result<()> {
+        self.inner.set_rts(level)
+    }
+
+    fn set_break_condition(&mut self, asserted: bool) -> crate::Result<()> {
+        self.inner.set_break_condition(asserted)
+    }
+}
+
+impl<S: ShutdownLayered, W: Write + Send> ShutdownLayered for Capture<S, W> {
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        let result = match self.writer.as_mut() {
+            Some(writer) => writer.out.flush(),
+            None => Ok(()),
+        };
+        Box::pin(future::ready(result))
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        Some(&mut self.inner)
+    }
+}
+
+/// A port playing back the data received in a [`Transcript`]
+///
+/// Each received record becomes readable at the time it was received, counted from
+/// the creation of the `Replay`, then reads return end of file.  Writes are
+/// accepted and kept, see [`written`](Replay::written), control lines and baud
+/// rate changes are ignored.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub struct Replay {
+    records: std::collections::VecDeque<Record>,
+    start: tokio::time::Instant,
+    sleep: Option<Pin<Box<tokio::time::Sleep>>>,
+    written: Vec<u8>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Replay {
+    /// Start playing back `transcript`.
+    pub fn new(transcript: &Transcript) -> Self {
+        Self {
+            records: transcript
+                .records()
+                .iter()
+                .filter(|record| record.direction == Direction::Rx)
+                .cloned()
+                .collect(),
+            start: tokio::time::Instant::now(),
+            sleep: None,
+            written: Vec::new(),
+        }
+    }
+
+    /// Returns the number of received records not played back yet.
+    pub fn remaining(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns all the data written so far.
+    pub fn written(&self) -> &[u8] {
+        &self.written
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl AsyncRead for Replay {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        use std::future::Future;
+
+        let this = self.get_mut();
+        let record = match this.records.front_mut() {
+            Some(record) => record,
+            None => return Poll::Ready(Ok(())),
+        };
+        let due = this.start + record.at;
+        if tokio::time::Instant::now() < due {
+            let sleep = this
+                .sleep
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(due)));
+            sleep.as_mut().reset(due);
+            futures::ready!(sleep.as_mut().poll(cx));
+        }
+        let n = record.data.len().min(buf.remaining());
+        buf.put_slice(&record.data[..n]);
+        record.data.drain(..n);
+        if record.data.is_empty() {
+            this.records.pop_front();
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl AsyncWrite for Replay {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().written.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl AsyncSerialPort for Replay {
+    fn port_name(&self) -> Option<String> {
+        Some("replay".to_string())
+    }
+
+    fn change_baud_rate(&mut self, _baud_rate: u32) -> crate::Result<()> {
+        Ok(())
+    }
+
+    fn set_dtr(&mut self, _level: bool) -> crate::Result<()> {
+        Ok(())
+    }
+
+    fn set_rts(&mut self, _level: bool) -> crate::Result<()> {
+        Ok(())
+    }
+
+    fn set_break_condition(&mut self, _asserted: bool) -> crate::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ShutdownLayered for Replay {
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(future::ready(Ok(())))
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        None
+    }
+}
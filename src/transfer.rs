@@ -0,0 +1,300 @@
+//! File transfer protocols
+//!
+//! Bootloaders, recovery consoles and legacy equipment often only accept files
+//! through one of the classic serial file transfer protocols.  They all run on any
+//! [`AsyncSerialPort`](crate::AsyncSerialPort), report their [`Progress`] to a
+//! callback and can be cancelled with a [`CancellationToken`], in which case the
+//! peer is told so as the protocol specifies.
+//!
+//! * [`xmodem`]: XMODEM, with checksums, CRCs or 1 KiB blocks, and YMODEM batches.
+//! * [`zmodem`]: ZMODEM, streaming batches of files with error recovery.
+//! * [`kermit`]: Kermit, in printable packets for links which are not 8 bit clean.
+//!
+//! Firmware goes through the protocols of the bootloaders themselves:
+//!
+//! * [`stk500`]: STK500 versions 1 and 2, for AVR and Arduino boards.
+//! * [`stm32`]: the UART protocol of the STM32 system memory bootloader.
+//! * [`esp`]: the serial ROM loader of the ESP8266 and ESP32 chips.
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use futures::future::Either;
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{error, fmt, io};
+
+pub use tokio_util::sync::CancellationToken;
+
+pub mod esp;
+pub mod kermit;
+pub mod stk500;
+pub mod stm32;
+pub mod xmodem;
+pub mod zmodem;
+
+/// Errors of file transfers
+#[derive(Debug)]
+pub enum TransferError {
+    /// The port or the file failed
+    Io(io::Error),
+    /// The transfer was cancelled through its [`CancellationToken`]
+    Cancelled,
+    /// The peer cancelled the transfer
+    CancelledByPeer,
+    /// The peer stopped answering
+    Timeout,
+    /// Too many packets were corrupted or rejected in a row
+    TooManyErrors,
+    /// The peer broke the protocol
+    Protocol(String),
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransferError::Io(err) => err.fmt(f),
+            TransferError::Cancelled => f.write_str("transfer cancelled"),
+            TransferError::CancelledByPeer => f.write_str("transfer cancelled by the peer"),
+            TransferError::Timeout => f.write_str("the peer stopped answering"),
+            TransferError::TooManyErrors => f.write_str("too many errors"),
+            TransferError::Protocol(msg) => write!(f, "protocol error: {}", msg),
+        }
+    }
+}
+
+impl error::Error for TransferError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            TransferError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TransferError {
+    fn from(err: io::Error) -> Self {
+        TransferError::Io(err)
+    }
+}
+
+impl From<crate::Error> for TransferError {
+    fn from(err: crate::Error) -> Self {
+        TransferError::Io(err.into())
+    }
+}
+
+/// How far a transfer went, reported after each acknowledged packet
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Progress {
+    /// The name of the file being transferred, if the protocol sends names
+    pub file: Option<String>,
+    /// The number of bytes of the file transferred so far
+    pub transferred: u64,
+    /// The size of the file, if known
+    pub total: Option<u64>,
+}
+
+/// What YMODEM and ZMODEM tell about a file
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileInfo {
+    /// The name of the file, without directories
+    pub name: String,
+    /// The size of the file in bytes, which tells receivers where the data ends
+    pub size: Option<u64>,
+    /// The modification time, in seconds since the Unix epoch
+    pub modified: Option<u64>,
+    /// The Unix mode of the file
+    pub mode: Option<u32>,
+}
+
+impl FileInfo {
+    /// A file named `name` of `size` bytes.
+    pub fn new(name: impl Into<String>, size: u64) -> Self {
+        Self {
+            name: name.into(),
+            size: Some(size),
+            modified: None,
+            mode: None,
+        }
+    }
+
+    /// Set the modification time.
+    pub fn modified(mut self, modified: SystemTime) -> Self {
+        self.modified = modified
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|since| since.as_secs());
+        self
+    }
+
+    // The name, then the size, time and mode, as in the YMODEM block 0 and the
+    // ZMODEM ZFILE subpacket.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut header = self.name.as_bytes().to_vec();
+        header.push(0);
+        let mut fields = Vec::new();
+        if let Some(size) = self.size {
+            fields.push(size.to_string());
+            if let Some(modified) = self.modified {
+                fields.push(format!("{:o}", modified));
+                if let Some(mode) = self.mode {
+                    fields.push(format!("{:o}", mode));
+                }
+            }
+        }
+        header.extend_from_slice(fields.join(" ").as_bytes());
+        header.push(0);
+        header
+    }
+
+    pub(crate) fn decode(block: &[u8]) -> Option<Self> {
+        let mut parts = block.splitn(2, |&b| b == 0);
+        let name = String::from_utf8_lossy(parts.next()?).into_owned();
+        let fields = parts.next().unwrap_or_default();
+        let fields = &fields[..fields.iter().position(|&b| b == 0).unwrap_or(fields.len())];
+        let fields = String::from_utf8_lossy(fields);
+        let mut fields = fields.split_whitespace();
+        Some(Self {
+            name,
+            size: fields.next().and_then(|size| size.parse().ok()),
+            modified: fields
+                .next()
+                .and_then(|time| u64::from_str_radix(time, 8).ok()),
+            mode: fields
+                .next()
+                .and_then(|mode| u32::from_str_radix(mode, 8).ok()),
+        })
+    }
+}
+
+type ProgressFn = Box<dyn FnMut(&Progress) + Send>;
+
+// Progress reporting and cancellation, shared by the protocols.
+#[derive(Default)]
+pub(crate) struct Control {
+    progress: Option<ProgressFn>,
+    cancel: Option<CancellationToken>,
+}
+
+impl fmt::Debug for Control {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Control")
+            .field("progress", &self.progress.as_ref().map(|_| ".."))
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+impl Control {
+    pub(crate) fn set_progress<F>(&mut self, progress: F)
+    where
+        F: FnMut(&Progress) + Send + 'static,
+    {
+        self.progress = Some(Box::new(progress));
+    }
+
+    pub(crate) fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancel = Some(token);
+    }
+
+    pub(crate) fn report(&mut self, progress: Progress) {
+        if let Some(report) = &mut self.progress {
+            report(&progress);
+        }
+    }
+
+    /// Run `fut` unless the transfer is cancelled first.
+    pub(crate) async fn guard<F: Future>(&self, fut: F) -> Result<F::Output, TransferError> {
+        let token = match &self.cancel {
+            Some(token) => token,
+            None => return Ok(fut.await),
+        };
+        if token.is_cancelled() {
+            return Err(TransferError::Cancelled);
+        }
+        futures::pin_mut!(fut);
+        let cancelled = token.cancelled();
+        futures::pin_mut!(cancelled);
+        match futures::future::select(fut, cancelled).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right(_) => Err(TransferError::Cancelled),
+        }
+    }
+
+    /// Read a byte, `None` if none arrives within `timeout`.
+    pub(crate) async fn read_byte<P: AsyncRead + Unpin>(
+        &self,
+        port: &mut P,
+        timeout: Duration,
+    ) -> Result<Option<u8>, TransferError> {
+        match self
+            .guard(tokio::time::timeout(timeout, port.read_u8()))
+            .await?
+        {
+            Ok(byte) => Ok(Some(byte?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Fill `buf`, `false` if the data does not arrive within `timeout`.
+    pub(crate) async fn read_exact<P: AsyncRead + Unpin>(
+        &self,
+        port: &mut P,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<bool, TransferError> {
+        match self
+            .guard(tokio::time::timeout(timeout, port.read_exact(buf)))
+            .await?
+        {
+            Ok(read) => read.map(|_| true).map_err(Into::into),
+            Err(_) => Ok(false),
+        }
+    }
+
+    pub(crate) async fn write<P: AsyncWrite + Unpin>(
+        &self,
+        port: &mut P,
+        data: &[u8],
+    ) -> Result<(), TransferError> {
+        self.guard(async {
+            port.write_all(data).await?;
+            port.flush().await
+        })
+        .await?
+        .map_err(Into::into)
+    }
+
+    /// Discard input until the line is silent for `silence`.
+    pub(crate) async fn purge<P: AsyncRead + Unpin>(
+        &self,
+        port: &mut P,
+        silence: Duration,
+    ) -> Result<(), TransferError> {
+        let mut buf = [0u8; 256];
+        loop {
+            match self
+                .guard(tokio::time::timeout(silence, port.read(&mut buf)))
+                .await?
+            {
+                Ok(Ok(0)) | Err(_) => return Ok(()),
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+/// Read from `data` until `buf` is full or the end of the data.
+pub(crate) async fn read_full<R: AsyncRead + Unpin>(
+    data: &mut R,
+    buf: &mut [u8],
+) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match data.read(&mut buf[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
@@ -0,0 +1,446 @@
+//! The Espressif serial ROM loader
+//!
+//! ESP8266 and ESP32 chips booted with `GPIO0` low start a loader in ROM, the one
+//! `esptool` talks to.  Commands and their answers are SLIP frames:
+//!
+//! | field | |
+//! |-------|-|
+//! | direction | `0x00` for commands, `0x01` for answers |
+//! | command | the command answered |
+//! | size | the size of the data, 16 bit little endian |
+//! | checksum or value | the checksum of the data sent with `FLASH_DATA`, the value read by `READ_REG` |
+//! | data | the arguments, 32 bit little endian words, or the status of the answer |
+//!
+//! Development boards wire DTR and RTS to `GPIO0` and `EN` so that
+//! [`reset_into_bootloader`] can start the loader without touching the board.
+//!
+//! ```no_run
+//! # async fn example(port: tokio_serial::SerialStream) -> Result<(), tokio_serial::transfer::TransferError> {
+//! use tokio_serial::transfer::esp::EspLoader;
+//!
+//! let firmware = std::fs::read("app.bin")?;
+//! let mut esp = EspLoader::new(port);
+//! let chip = esp.connect().await?;
+//! println!("found an {:?}", chip);
+//! esp.write_flash(0x10000, &firmware).await?;
+//! esp.flash_end(true).await?;
+//! # Ok(())
+//! # }
+//! ```
+use super::{CancellationToken, Control, Progress, TransferError};
+use crate::AsyncSerialPort;
+
+use tokio_serial_core::slip::Slip;
+use tokio_serial_core::{Deframer, Framer};
+
+use std::time::Duration;
+
+/// The baud rate the ROM loader starts at
+pub const BAUD_RATE: u32 = 115_200;
+
+/// The size of the blocks of `FLASH_DATA` commands
+pub const FLASH_BLOCK_SIZE: usize = 0x400;
+
+const FLASH_SECTOR_SIZE: usize = 0x1000;
+
+// The register identifying the chip by its value.
+const CHIP_DETECT_MAGIC_REG: u32 = 0x4000_1000;
+
+const CHECKSUM_SEED: u8 = 0xef;
+
+/// ROM loader commands
+pub mod command {
+    /// Start writing the flash, erasing the region written
+    pub const FLASH_BEGIN: u8 = 0x02;
+    /// Write a block of flash
+    pub const FLASH_DATA: u8 = 0x03;
+    /// Stop writing the flash, optionally running the application
+    pub const FLASH_END: u8 = 0x04;
+    /// Check that the loader listens
+    pub const SYNC: u8 = 0x08;
+    /// Write a register
+    pub const WRITE_REG: u8 = 0x09;
+    /// Read a register
+    pub const READ_REG: u8 = 0x0a;
+    /// Attach the SPI flash, needed before flashing ESP32 chips
+    pub const SPI_ATTACH: u8 = 0x0d;
+    /// Change the baud rate
+    pub const CHANGE_BAUDRATE: u8 = 0x0f;
+}
+
+/// The chips of the Espressif family
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Chip {
+    /// ESP8266
+    Esp8266,
+    /// ESP32
+    Esp32,
+    /// ESP32-S2
+    Esp32S2,
+    /// ESP32-S3
+    Esp32S3,
+    /// ESP32-C2
+    Esp32C2,
+    /// ESP32-C3
+    Esp32C3,
+    /// ESP32-C6
+    Esp32C6,
+    /// ESP32-H2
+    Esp32H2,
+    /// A chip this module does not know, with the value of its detection register
+    Unknown(u32),
+}
+
+impl Chip {
+    /// The chip whose detection register, at `0x40001000`, reads `magic`.
+    pub fn from_magic(magic: u32) -> Self {
+        match magic {
+            0xfff0_c101 => Chip::Esp8266,
+            0x00f0_1d83 => Chip::Esp32,
+            0x0000_07c6 => Chip::Esp32S2,
+            0x0000_0009 => Chip::Esp32S3,
+            0x6f51_306f | 0x7c41_a06f => Chip::Esp32C2,
+            0x6921_506f | 0x1b31_506f | 0x4881_606f | 0x4361_606f => Chip::Esp32C3,
+            0x2ce0_806f => Chip::Esp32C6,
+            0xd7b7_3e80 => Chip::Esp32H2,
+            magic => Chip::Unknown(magic),
+        }
+    }
+
+    // Whether `FLASH_BEGIN` takes the encryption flag, as on the chips after
+    // the ESP32.
+    fn flash_begin_encrypted(self) -> bool {
+        !matches!(self, Chip::Esp8266 | Chip::Esp32)
+    }
+}
+
+/// Reset the chip into its ROM loader, with the DTR and RTS wiring of
+/// development boards.
+///
+/// RTS drives `EN` and DTR `GPIO0`, both inverted: the chip is held in reset for
+/// 100ms, released with `GPIO0` low for 50ms, then `GPIO0` is released too.
+pub async fn reset_into_bootloader<P: AsyncSerialPort>(port: &mut P) -> Result<(), TransferError> {
+    port.set_dtr(false)?;
+    port.set_rts(true)?;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    port.set_dtr(true)?;
+    port.set_rts(false)?;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    port.set_dtr(false)?;
+    Ok(())
+}
+
+/// Reset the chip out of the ROM loader, into its application.
+pub async fn hard_reset<P: AsyncSerialPort>(port: &mut P) -> Result<(), TransferError> {
+    port.set_rts(true)?;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    port.set_rts(false)?;
+    Ok(())
+}
+
+// The size the ROM of the ESP8266 has to be told to erase `size` bytes at
+// `offset`: it erases up to the next 64KiB boundary twice, as esptool works
+// around.
+fn esp8266_erase_size(offset: usize, size: usize) -> usize {
+    let sectors_per_block = 16;
+    let sectors = size.div_ceil(FLASH_SECTOR_SIZE);
+    let start = offset / FLASH_SECTOR_SIZE;
+    let head = (sectors_per_block - start % sectors_per_block).min(sectors);
+    if sectors < 2 * head {
+        sectors.div_ceil(2) * FLASH_SECTOR_SIZE
+    } else {
+        (sectors - head) * FLASH_SECTOR_SIZE
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(CHECKSUM_SEED, |sum, &byte| sum ^ byte)
+}
+
+fn words(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// A client of the Espressif ROM loader
+///
+/// Commands wait 3 seconds for their answer by default, and
+/// [`flash_begin`](EspLoader::flash_begin) 30 seconds per MiB more as it erases
+/// the region first.  [`sync`](EspLoader::sync) tries 7 times.
+/// [`write_flash`](EspLoader::write_flash) reports its progress after each
+/// block.
+#[derive(Debug)]
+pub struct EspLoader<P> {
+    port: P,
+    timeout: Duration,
+    erase_timeout_per_mb: Duration,
+    retries: u32,
+    chip: Option<Chip>,
+    spi_attached: bool,
+    slip: Slip<Vec<u8>>,
+    control: Control,
+}
+
+impl<P: AsyncSerialPort> EspLoader<P> {
+    /// Talk to the ROM loader through `port`, at [`BAUD_RATE`].
+    pub fn new(port: P) -> Self {
+        Self {
+            port,
+            timeout: Duration::from_secs(3),
+            erase_timeout_per_mb: Duration::from_secs(30),
+            retries: 7,
+            chip: None,
+            spi_attached: false,
+            slip: Slip::new(vec![0; 1024]),
+            control: Control::default(),
+        }
+    }
+
+    /// Wait at most `timeout` for each answer.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Wait `timeout` more per MiB erased by [`flash_begin`](EspLoader::flash_begin).
+    pub fn erase_timeout_per_mb(mut self, timeout: Duration) -> Self {
+        self.erase_timeout_per_mb = timeout;
+        self
+    }
+
+    /// Try to get in sync with the loader `retries` times.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Report the progress of [`write_flash`](EspLoader::write_flash) after each
+    /// block.
+    pub fn progress<F>(mut self, progress: F) -> Self
+    where
+        F: FnMut(&Progress) + Send + 'static,
+    {
+        self.control.set_progress(progress);
+        self
+    }
+
+    /// Cancel commands when `token` is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.control.set_cancellation(token);
+        self
+    }
+
+    /// Returns a reference to the underlying port.
+    pub fn get_ref(&self) -> &P {
+        &self.port
+    }
+
+    /// Returns a mutable reference to the underlying port.
+    pub fn get_mut(&mut self) -> &mut P {
+        &mut self.port
+    }
+
+    /// Consumes the loader, returning the underlying port.
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    /// Reset the chip into the loader, see [`reset_into_bootloader`], get in
+    /// sync with it and detect the chip.
+    pub async fn connect(&mut self) -> Result<Chip, TransferError> {
+        reset_into_bootloader(&mut self.port).await?;
+        self.sync().await?;
+        self.detect_chip().await
+    }
+
+    /// Get in sync with the loader.
+    ///
+    /// Whatever the chip sent before, such as its boot messages, is discarded.
+    ///
+    /// ## Errors
+    ///
+    /// * `TooManyErrors` if the loader does not answer any of the retries.
+    pub async fn sync(&mut self) -> Result<(), TransferError> {
+        let mut data = vec![0x07, 0x07, 0x12, 0x20];
+        data.resize(36, 0x55);
+        let timeout = self.timeout.min(Duration::from_millis(100));
+        for _ in 0..self.retries {
+            self.control
+                .purge(&mut self.port, Duration::from_millis(20))
+                .await?;
+            self.slip.reset();
+            match self.command(command::SYNC, &data, 0, timeout).await {
+                // The loader answers several times, the other answers are
+                // skipped along with the answers to other commands.
+                Ok(_) => return Ok(()),
+                Err(TransferError::Timeout) | Err(TransferError::Protocol(_)) => {
+                    log::debug!("no ESP ROM sync, retrying");
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(TransferError::TooManyErrors)
+    }
+
+    /// Detect the chip from its identification register.
+    pub async fn detect_chip(&mut self) -> Result<Chip, TransferError> {
+        let chip = Chip::from_magic(self.read_reg(CHIP_DETECT_MAGIC_REG).await?);
+        self.chip = Some(chip);
+        Ok(chip)
+    }
+
+    /// Read the register at `address`.
+    pub async fn read_reg(&mut self, address: u32) -> Result<u32, TransferError> {
+        let timeout = self.timeout;
+        self.command(command::READ_REG, &words(&[address]), 0, timeout)
+            .await
+    }
+
+    /// Write `value` to the register at `address`.
+    pub async fn write_reg(&mut self, address: u32, value: u32) -> Result<(), TransferError> {
+        let timeout = self.timeout;
+        let data = words(&[address, value, 0xffff_ffff, 0]);
+        self.command(command::WRITE_REG, &data, 0, timeout).await?;
+        Ok(())
+    }
+
+    /// Switch the loader and the port to `baud_rate`.
+    pub async fn change_baud_rate(&mut self, baud_rate: u32) -> Result<(), TransferError> {
+        let timeout = self.timeout;
+        // The current rate is only needed by the stub loader.
+        let data = words(&[baud_rate, 0]);
+        self.command(command::CHANGE_BAUDRATE, &data, 0, timeout)
+            .await?;
+        self.port.change_baud_rate(baud_rate)?;
+        // Let the loader switch, and drop what arrived in between.
+        self.control
+            .purge(&mut self.port, Duration::from_millis(50))
+            .await
+    }
+
+    /// Start writing `size` bytes of flash at `offset`, erasing them first.
+    ///
+    /// Returns the number of blocks of [`FLASH_BLOCK_SIZE`] bytes to send.
+    pub async fn flash_begin(&mut self, offset: u32, size: usize) -> Result<u32, TransferError> {
+        let chip = match self.chip {
+            Some(chip) => chip,
+            None => self.detect_chip().await?,
+        };
+        if chip != Chip::Esp8266 && !self.spi_attached {
+            let timeout = self.timeout;
+            self.command(command::SPI_ATTACH, &[0; 8], 0, timeout)
+                .await?;
+            self.spi_attached = true;
+        }
+
+        let blocks = size.div_ceil(FLASH_BLOCK_SIZE) as u32;
+        let erase_size = match chip {
+            Chip::Esp8266 => esp8266_erase_size(offset as usize, size),
+            _ => size,
+        };
+        let mut args = vec![erase_size as u32, blocks, FLASH_BLOCK_SIZE as u32, offset];
+        if chip.flash_begin_encrypted() {
+            args.push(0);
+        }
+        let erase_time = self.erase_timeout_per_mb.as_secs_f64() * size as f64 / (1 << 20) as f64;
+        let timeout = self.timeout + Duration::from_secs_f64(erase_time);
+        self.command(command::FLASH_BEGIN, &words(&args), 0, timeout)
+            .await?;
+        Ok(blocks)
+    }
+
+    /// Write block `seq` of flash, padded with `0xff` to [`FLASH_BLOCK_SIZE`].
+    pub async fn flash_data(&mut self, seq: u32, block: &[u8]) -> Result<(), TransferError> {
+        let mut data = words(&[FLASH_BLOCK_SIZE as u32, seq, 0, 0]);
+        let start = data.len();
+        data.extend_from_slice(block);
+        data.resize(start + FLASH_BLOCK_SIZE, 0xff);
+        let checksum = checksum(&data[start..]);
+        let timeout = self.timeout;
+        self.command(command::FLASH_DATA, &data, u32::from(checksum), timeout)
+            .await?;
+        Ok(())
+    }
+
+    /// Stop writing the flash, and run the application if `run` is set.
+    pub async fn flash_end(&mut self, run: bool) -> Result<(), TransferError> {
+        let timeout = self.timeout;
+        let data = words(&[u32::from(!run)]);
+        self.command(command::FLASH_END, &data, 0, timeout).await?;
+        Ok(())
+    }
+
+    /// Write `image` to the flash at `offset`, reporting the progress after each
+    /// block.
+    ///
+    /// Call [`flash_end`](EspLoader::flash_end) once all the images are written.
+    pub async fn write_flash(&mut self, offset: u32, image: &[u8]) -> Result<(), TransferError> {
+        self.flash_begin(offset, image.len()).await?;
+        let mut written = 0;
+        for (seq, block) in image.chunks(FLASH_BLOCK_SIZE).enumerate() {
+            self.flash_data(seq as u32, block).await?;
+            written += block.len();
+            self.control.report(Progress {
+                file: None,
+                transferred: written as u64,
+                total: Some(image.len() as u64),
+            });
+        }
+        Ok(())
+    }
+
+    // Send `command` and return the value of its answer.
+    async fn command(
+        &mut self,
+        command: u8,
+        data: &[u8],
+        checksum: u32,
+        timeout: Duration,
+    ) -> Result<u32, TransferError> {
+        let mut packet = vec![0x00, command];
+        packet.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        packet.extend_from_slice(&checksum.to_le_bytes());
+        packet.extend_from_slice(data);
+        let mut frame = Vec::with_capacity(packet.len() + 8);
+        let _ = self
+            .slip
+            .frame(&packet, |chunk| frame.extend_from_slice(chunk));
+        self.control.write(&mut self.port, &frame).await?;
+
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let answer = self.read_answer(deadline).await?;
+            if answer.len() < 8 || answer[0] != 0x01 || answer[1] != command {
+                continue;
+            }
+            let value = u32::from_le_bytes([answer[4], answer[5], answer[6], answer[7]]);
+            // The status comes first: 2 bytes on the ESP8266, 4 on the others.
+            return match answer[8..] {
+                [0, ..] => Ok(value),
+                [_, error, ..] => Err(TransferError::Protocol(format!(
+                    "ESP ROM command {:#04x} failed with error {:#04x}",
+                    command, error
+                ))),
+                _ => Err(TransferError::Protocol("short ESP ROM answer".into())),
+            };
+        }
+    }
+
+    // The next frame, before `deadline`.
+    async fn read_answer(
+        &mut self,
+        deadline: tokio::time::Instant,
+    ) -> Result<Vec<u8>, TransferError> {
+        loop {
+            let left = deadline.saturating_duration_since(tokio::time::Instant::now());
+            let byte = match self.control.read_byte(&mut self.port, left).await? {
+                Some(byte) => byte,
+                None => return Err(TransferError::Timeout),
+            };
+            match self.slip.push(byte) {
+                Ok(Some(frame)) => return Ok(frame.to_vec()),
+                Ok(None) => {}
+                Err(err) => log::debug!("skipping ESP ROM frame: {}", err),
+            }
+        }
+    }
+}
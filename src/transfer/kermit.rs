@@ -0,0 +1,818 @@
+//! Kermit
+//!
+//! Kermit sends printable packets, each acknowledged before the next one is sent:
+//!
+//! | field | |
+//! |-------|-|
+//! | `MARK` | `SOH` |
+//! | `LEN` | the number of bytes following, plus 32 |
+//! | `SEQ` | the packet number modulo 64, plus 32 |
+//! | `TYPE` | a letter telling what the packet is |
+//! | `DATA` | control characters are prefixed with `#` and sent printable |
+//! | `CHECK` | a 6 or 12 bit sum, or a CRC-16, in 1 to 3 printable bytes |
+//! | `EOL` | a carriage return |
+//!
+//! A transfer starts with a `S` packet and its acknowledgement, in which both ends
+//! tell their parameters.  Each file is then sent as a `F` packet with its name,
+//! an optional `A` packet with its attributes, `D` packets with the data and a `Z`
+//! packet ending it.  A `B` packet ends the batch, an `E` packet the transfer
+//! when something goes wrong.
+//!
+//! Only the basic protocol is implemented: packets are at most 94 bytes long and
+//! the sliding window is off.
+//!
+//! ```no_run
+//! # async fn example(mut port: tokio_serial::SerialStream) -> Result<(), tokio_serial::transfer::TransferError> {
+//! use tokio_serial::transfer::kermit::Kermit;
+//! use tokio_serial::transfer::FileInfo;
+//!
+//! let image = tokio::fs::File::open("boot.img").await?;
+//! let size = image.metadata().await?.len();
+//! Kermit::new()
+//!     .send(&mut port, vec![(FileInfo::new("boot.img", size), image)])
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+use super::{CancellationToken, Control, FileInfo, Progress, TransferError};
+use crate::AsyncSerialPort;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use std::io;
+use std::time::Duration;
+use tokio_serial_core::crc::CRC16_KERMIT;
+
+/// Start of packets
+pub const MARK: u8 = 0x01;
+
+// The longest packet of the basic protocol, from `SEQ` to `CHECK`.
+const MAX_LEN: usize = 94;
+const CR: u8 = b'\r';
+const QCTL: u8 = b'#';
+const QBIN: u8 = b'&';
+const REPT: u8 = b'~';
+// The peer accepts `A` packets.
+const CAPAS_ATTRIBUTES: u8 = 0x08;
+
+fn tochar(value: u8) -> u8 {
+    value + 32
+}
+
+fn unchar(value: u8) -> u8 {
+    value.wrapping_sub(32)
+}
+
+fn ctl(value: u8) -> u8 {
+    value ^ 0x40
+}
+
+/// How packets are checked
+///
+/// The `S` packet and its acknowledgement are always checked with a 6 bit sum,
+/// the other packets as both ends agree on in them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockCheck {
+    /// Type 1, a 6 bit sum of the packet
+    Sum6,
+    /// Type 2, a 12 bit sum of the packet
+    Sum12,
+    /// Type 3, CRC-16/KERMIT
+    Crc16,
+}
+
+impl BlockCheck {
+    fn len(self) -> usize {
+        match self {
+            BlockCheck::Sum6 => 1,
+            BlockCheck::Sum12 => 2,
+            BlockCheck::Crc16 => 3,
+        }
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            BlockCheck::Sum6 => b'1',
+            BlockCheck::Sum12 => b'2',
+            BlockCheck::Crc16 => b'3',
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            b'1' => Some(BlockCheck::Sum6),
+            b'2' => Some(BlockCheck::Sum12),
+            b'3' => Some(BlockCheck::Crc16),
+            _ => None,
+        }
+    }
+
+    /// Append the check of `packet`, from `LEN` to `DATA`.
+    fn append(self, packet: &[u8], out: &mut Vec<u8>) {
+        let sum = packet.iter().map(|&b| u32::from(b)).sum::<u32>();
+        match self {
+            BlockCheck::Sum6 => out.push(tochar(((sum + ((sum >> 6) & 3)) & 0x3f) as u8)),
+            BlockCheck::Sum12 => {
+                out.push(tochar(((sum >> 6) & 0x3f) as u8));
+                out.push(tochar((sum & 0x3f) as u8));
+            }
+            BlockCheck::Crc16 => {
+                let crc = CRC16_KERMIT.checksum(packet);
+                out.push(tochar(((crc >> 12) & 0x0f) as u8));
+                out.push(tochar(((crc >> 6) & 0x3f) as u8));
+                out.push(tochar((crc & 0x3f) as u8));
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Packet {
+    seq: u8,
+    kind: u8,
+    data: Vec<u8>,
+}
+
+// How the data of packets is prefixed, as agreed on during the `S` exchange.
+#[derive(Debug, Clone, Copy)]
+struct Quoting {
+    qctl: u8,
+    qbin: Option<u8>,
+    rept: Option<u8>,
+}
+
+impl Quoting {
+    fn push(&self, byte: u8, out: &mut Vec<u8>) {
+        let mut byte = byte;
+        if let Some(qbin) = self.qbin {
+            if byte & 0x80 != 0 {
+                out.push(qbin);
+                byte &= 0x7f;
+            }
+        }
+        let low = byte & 0x7f;
+        if low < 32 || low == 127 {
+            out.push(self.qctl);
+            byte = ctl(byte);
+        } else if low == self.qctl || Some(low) == self.qbin || Some(low) == self.rept {
+            out.push(self.qctl);
+        }
+        out.push(byte);
+    }
+
+    /// Encode the start of `data` into at most `room` bytes, returning how many
+    /// bytes of `data` were encoded.
+    fn encode(&self, data: &[u8], room: usize, out: &mut Vec<u8>) -> usize {
+        let mut used = 0;
+        let mut unit = Vec::with_capacity(5);
+        while used < data.len() {
+            let byte = data[used];
+            let run = data[used..]
+                .iter()
+                .take(MAX_LEN)
+                .take_while(|&&b| b == byte)
+                .count();
+            unit.clear();
+            let run = match self.rept {
+                Some(rept) if run >= 3 => {
+                    unit.push(rept);
+                    unit.push(tochar(run as u8));
+                    run
+                }
+                _ => 1,
+            };
+            self.push(byte, &mut unit);
+            if out.len() + unit.len() > room {
+                break;
+            }
+            out.extend_from_slice(&unit);
+            used += run;
+        }
+        used
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>, TransferError> {
+        let invalid = || TransferError::Protocol("invalid packet data".into());
+        let mut out = Vec::with_capacity(data.len());
+        let mut data = data.iter().copied();
+        while let Some(mut byte) = data.next() {
+            let mut count = 1;
+            if Some(byte) == self.rept {
+                count = unchar(data.next().ok_or_else(invalid)?);
+                byte = data.next().ok_or_else(invalid)?;
+            }
+            let mut high = 0;
+            if Some(byte) == self.qbin {
+                high = 0x80;
+                byte = data.next().ok_or_else(invalid)?;
+            }
+            if byte == self.qctl {
+                byte = data.next().ok_or_else(invalid)?;
+                let low = byte & 0x7f;
+                if (0x3f..=0x5f).contains(&low) {
+                    byte = ctl(byte);
+                }
+            }
+            out.resize(out.len() + usize::from(count), byte | high);
+        }
+        Ok(out)
+    }
+}
+
+// What an end tells in the `S` packet or its acknowledgement.
+#[derive(Debug, Clone, Copy)]
+struct Params {
+    maxl: usize,
+    npad: u8,
+    padc: u8,
+    eol: u8,
+    qctl: u8,
+    // `Y` if it would prefix 8 bit bytes, `N` if it will not, or the prefix.
+    qbin: u8,
+    chkt: u8,
+    // A space if it does not compress repeats.
+    rept: u8,
+    capas: u8,
+}
+
+impl Params {
+    fn ours(check: BlockCheck, eight_bit: bool) -> Self {
+        Self {
+            maxl: MAX_LEN,
+            npad: 0,
+            padc: 0,
+            eol: CR,
+            qctl: QCTL,
+            qbin: if eight_bit { QBIN } else { b'Y' },
+            chkt: check.code(),
+            rept: REPT,
+            capas: CAPAS_ATTRIBUTES,
+        }
+    }
+
+    fn encode(&self, timeout: Duration) -> Vec<u8> {
+        vec![
+            tochar(self.maxl as u8),
+            tochar(timeout.as_secs().clamp(1, 94) as u8),
+            tochar(self.npad),
+            ctl(self.padc),
+            tochar(self.eol),
+            self.qctl,
+            self.qbin,
+            self.chkt,
+            self.rept,
+            tochar(self.capas),
+        ]
+    }
+
+    /// The parameters in `data`, with the defaults of the protocol for those
+    /// missing.
+    fn decode(data: &[u8]) -> Self {
+        let field = |at: usize| data.get(at).copied().filter(|&b| b != b' ');
+        Self {
+            maxl: field(0)
+                .map(|b| usize::from(unchar(b)))
+                .filter(|&maxl| maxl >= 10)
+                .unwrap_or(80)
+                .min(MAX_LEN),
+            npad: field(2).map(unchar).unwrap_or(0),
+            padc: field(3).map(ctl).unwrap_or(0),
+            eol: field(4).map(unchar).unwrap_or(CR),
+            qctl: field(5).unwrap_or(QCTL),
+            qbin: field(6).unwrap_or(b'N'),
+            chkt: field(7).unwrap_or(b'1'),
+            rept: field(8).unwrap_or(b' '),
+            capas: field(9).map(unchar).unwrap_or(0),
+        }
+    }
+}
+
+// The 8 bit prefix both ends agree on, if any.
+fn agree_qbin(ours: u8, theirs: u8) -> Option<u8> {
+    let prefix = |b: u8| (33..=62).contains(&b) || (96..=126).contains(&b);
+    match (ours, theirs) {
+        (b'Y', prefix_) if prefix(prefix_) => Some(prefix_),
+        (prefix_, b'Y') if prefix(prefix_) => Some(prefix_),
+        (a, b) if a == b && prefix(a) => Some(a),
+        _ => None,
+    }
+}
+
+// One end of a transfer.
+struct Link<'a, P> {
+    port: &'a mut P,
+    control: &'a mut Control,
+    timeout: Duration,
+    retries: u32,
+    check: BlockCheck,
+    ours: Params,
+    // The encoding of the data we send and of the data the peer sends.
+    send: Quoting,
+    recv: Quoting,
+    peer: Params,
+    seq: u8,
+}
+
+impl<'a, P: AsyncSerialPort> Link<'a, P> {
+    async fn read_byte(&mut self) -> Result<Option<u8>, TransferError> {
+        self.control.read_byte(self.port, self.timeout).await
+    }
+
+    async fn write_packet(&mut self, seq: u8, kind: u8, data: &[u8]) -> Result<(), TransferError> {
+        let frame = self.frame(seq, kind, data);
+        self.control.write(self.port, &frame).await
+    }
+
+    fn frame(&self, seq: u8, kind: u8, data: &[u8]) -> Vec<u8> {
+        let check = if kind == b'S' {
+            BlockCheck::Sum6
+        } else {
+            self.check
+        };
+        let mut frame = vec![self.peer.padc; usize::from(self.peer.npad)];
+        frame.push(MARK);
+        let start = frame.len();
+        frame.push(tochar((2 + data.len() + check.len()) as u8));
+        frame.push(tochar(seq));
+        frame.push(kind);
+        frame.extend_from_slice(data);
+        let body = frame[start..].to_vec();
+        check.append(&body, &mut frame);
+        frame.push(self.peer.eol);
+        frame
+    }
+
+    /// Tell the peer the transfer is over, the error is returned.
+    async fn abort(&mut self, err: TransferError) -> TransferError {
+        if !matches!(err, TransferError::CancelledByPeer | TransferError::Io(_)) {
+            let mut message = Vec::new();
+            let room = self.peer.maxl - 2 - self.check.len();
+            self.send
+                .encode(err.to_string().as_bytes(), room, &mut message);
+            let frame = self.frame(self.seq, b'E', &message);
+            let _ = self.port.write_all(&frame).await;
+            let _ = self.port.flush().await;
+        }
+        err
+    }
+
+    /// The next packet, `None` if it is corrupted or does not arrive in time.
+    async fn read_packet(&mut self) -> Result<Option<Packet>, TransferError> {
+        'packet: loop {
+            match self.read_byte().await? {
+                Some(MARK) => {}
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+            let mut body = Vec::with_capacity(MAX_LEN + 1);
+            loop {
+                match self.read_byte().await? {
+                    // A new packet starts, the previous one was truncated.
+                    Some(MARK) => continue 'packet,
+                    Some(byte) => body.push(byte),
+                    None => return Ok(None),
+                }
+                let len = usize::from(unchar(body[0]));
+                if !(3..=MAX_LEN).contains(&len) {
+                    // Extended packets are not supported.
+                    log::debug!("invalid Kermit packet length {}", len);
+                    continue 'packet;
+                }
+                if body.len() == 1 + len {
+                    break;
+                }
+            }
+            let kind = body[2];
+            // The check agreed on applies once the parameters are exchanged.
+            let check = if kind == b'S' {
+                BlockCheck::Sum6
+            } else {
+                self.check
+            };
+            if body.len() < 3 + check.len() {
+                return Ok(None);
+            }
+            let (packet, sent) = body.split_at(body.len() - check.len());
+            let mut expected = Vec::with_capacity(3);
+            check.append(packet, &mut expected);
+            if expected != sent {
+                log::debug!("Kermit packet check mismatch");
+                return Ok(None);
+            }
+            return Ok(Some(Packet {
+                seq: unchar(body[1]) & 0x3f,
+                kind,
+                data: packet[3..].to_vec(),
+            }));
+        }
+    }
+
+    fn peer_error(&self, data: &[u8]) {
+        let message = self.recv.decode(data).unwrap_or_else(|_| data.to_vec());
+        log::debug!("Kermit peer error: {}", String::from_utf8_lossy(&message));
+    }
+
+    /// Send a packet until the peer acknowledges it, returning the data of the
+    /// acknowledgement.
+    async fn exchange(&mut self, kind: u8, data: &[u8]) -> Result<Vec<u8>, TransferError> {
+        let mut rejected = false;
+        for _ in 0..=self.retries {
+            self.write_packet(self.seq, kind, data).await?;
+            loop {
+                match self.read_packet().await? {
+                    Some(ack) if ack.kind == b'Y' && ack.seq == self.seq => {
+                        self.seq = (self.seq + 1) % 64;
+                        return Ok(ack.data);
+                    }
+                    // Asking for the next packet acknowledges this one.
+                    Some(nak) if nak.kind == b'N' && nak.seq == (self.seq + 1) % 64 => {
+                        self.seq = (self.seq + 1) % 64;
+                        return Ok(Vec::new());
+                    }
+                    Some(err) if err.kind == b'E' => {
+                        self.peer_error(&err.data);
+                        return Err(TransferError::CancelledByPeer);
+                    }
+                    Some(nak) if nak.kind == b'N' => {
+                        rejected = true;
+                        break;
+                    }
+                    // An acknowledgement of an earlier packet
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+            log::debug!("Kermit packet {} not acknowledged", self.seq);
+        }
+        Err(if rejected {
+            TransferError::TooManyErrors
+        } else {
+            TransferError::Timeout
+        })
+    }
+
+    /// Exchange parameters with the receiver.
+    async fn send_init(&mut self) -> Result<(), TransferError> {
+        let ours = self.ours;
+        let ack = self.exchange(b'S', &ours.encode(self.timeout)).await?;
+        self.negotiate(&ours, Params::decode(&ack));
+        Ok(())
+    }
+
+    fn negotiate(&mut self, ours: &Params, theirs: Params) {
+        self.check = match BlockCheck::from_code(theirs.chkt) {
+            Some(check) if theirs.chkt == ours.chkt => check,
+            _ => BlockCheck::Sum6,
+        };
+        let qbin = agree_qbin(ours.qbin, theirs.qbin);
+        let rept = Some(REPT).filter(|&rept| theirs.rept == rept);
+        self.send = Quoting {
+            qctl: ours.qctl,
+            qbin,
+            rept,
+        };
+        self.recv = Quoting {
+            qctl: theirs.qctl,
+            qbin,
+            rept,
+        };
+        self.peer = theirs;
+    }
+
+    async fn send_file<R: AsyncRead + Unpin>(
+        &mut self,
+        info: &FileInfo,
+        data: &mut R,
+    ) -> Result<(), TransferError> {
+        // Room for the data in packets, between `TYPE` and `CHECK`.
+        let room = self.peer.maxl - 2 - self.check.len();
+        let mut name = Vec::new();
+        self.send.encode(info.name.as_bytes(), room, &mut name);
+        self.exchange(b'F', &name).await?;
+
+        if let Some(size) = info.size {
+            if self.peer.capas & CAPAS_ATTRIBUTES != 0 {
+                let size = size.to_string();
+                let mut attributes = vec![b'1', tochar(size.len() as u8)];
+                attributes.extend_from_slice(size.as_bytes());
+                if self.exchange(b'A', &attributes).await?.first() == Some(&b'N') {
+                    // The receiver refuses the file.
+                    self.exchange(b'Z', b"D").await?;
+                    return Ok(());
+                }
+            }
+        }
+
+        let mut progress = Progress {
+            file: Some(info.name.clone()),
+            transferred: 0,
+            total: info.size,
+        };
+        let mut buf = Vec::with_capacity(2 * MAX_LEN);
+        let mut chunk = [0u8; 1024];
+        let mut end = false;
+        loop {
+            if !end && buf.len() < MAX_LEN {
+                let read = self.control.guard(data.read(&mut chunk)).await??;
+                end = read == 0;
+                buf.extend_from_slice(&chunk[..read]);
+                continue;
+            }
+            if buf.is_empty() {
+                break;
+            }
+            let mut packet = Vec::with_capacity(room);
+            let used = self.send.encode(&buf, room, &mut packet);
+            let ack = self.exchange(b'D', &packet).await?;
+            buf.drain(..used);
+            progress.transferred += used as u64;
+            self.control.report(progress.clone());
+            // The receiver asks to skip the file, or the rest of the batch.
+            if matches!(ack.first(), Some(b'X') | Some(b'Z')) {
+                self.exchange(b'Z', b"D").await?;
+                return Ok(());
+            }
+        }
+        self.exchange(b'Z', &[]).await?;
+        Ok(())
+    }
+
+    async fn receive<W, F>(&mut self, open: &mut F) -> Result<Vec<FileInfo>, TransferError>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(&FileInfo) -> io::Result<W>,
+    {
+        let mut files = Vec::new();
+        let mut current: Option<(FileInfo, Option<W>, Progress)> = None;
+        let mut last_ack: Option<Vec<u8>> = None;
+        let mut errors = 0;
+        let mut started = false;
+        loop {
+            let packet = match self.read_packet().await? {
+                Some(packet) => packet,
+                None => {
+                    errors += 1;
+                    if errors > self.retries {
+                        return Err(if started {
+                            TransferError::TooManyErrors
+                        } else {
+                            TransferError::Timeout
+                        });
+                    }
+                    self.write_packet(self.seq, b'N', &[]).await?;
+                    continue;
+                }
+            };
+            if packet.kind == b'E' {
+                self.peer_error(&packet.data);
+                return Err(TransferError::CancelledByPeer);
+            }
+            if packet.seq != self.seq {
+                // Our acknowledgement was lost.
+                if let Some(ack) = &last_ack {
+                    if packet.seq == (self.seq + 63) % 64 {
+                        self.control.write(self.port, ack).await?;
+                    }
+                }
+                continue;
+            }
+            errors = 0;
+            let mut reply = Vec::new();
+            match packet.kind {
+                b'S' if !started => {
+                    let theirs = Params::decode(&packet.data);
+                    let ours = self.ours;
+                    // The acknowledgement is sent as the sender asks, and still
+                    // checked with a sum.
+                    self.peer = theirs;
+                    let ack = self.frame(0, b'Y', &ours.encode(self.timeout));
+                    self.negotiate(&ours, theirs);
+                    self.control.write(self.port, &ack).await?;
+                    last_ack = Some(ack);
+                    self.seq = 1;
+                    started = true;
+                    continue;
+                }
+                b'F' if started => {
+                    let name =
+                        String::from_utf8_lossy(&self.recv.decode(&packet.data)?).into_owned();
+                    let progress = Progress {
+                        file: Some(name.clone()),
+                        transferred: 0,
+                        total: None,
+                    };
+                    current = Some((
+                        FileInfo {
+                            name,
+                            size: None,
+                            modified: None,
+                            mode: None,
+                        },
+                        None,
+                        progress,
+                    ));
+                }
+                b'A' => {
+                    if let Some((info, _, progress)) = &mut current {
+                        let mut attributes = &packet.data[..];
+                        while attributes.len() >= 2 {
+                            let len = usize::from(unchar(attributes[1])).min(attributes.len() - 2);
+                            let value = &attributes[2..2 + len];
+                            if attributes[0] == b'1' {
+                                info.size = std::str::from_utf8(value)
+                                    .ok()
+                                    .and_then(|s| s.trim().parse().ok());
+                                progress.total = info.size;
+                            }
+                            attributes = &attributes[2 + len..];
+                        }
+                    }
+                    reply.push(b'Y');
+                }
+                b'D' => {
+                    let (info, out, progress) = current.as_mut().ok_or_else(|| {
+                        TransferError::Protocol("data received before a file header".into())
+                    })?;
+                    let data = self.recv.decode(&packet.data)?;
+                    if out.is_none() {
+                        *out = Some(open(info)?);
+                    }
+                    if let Some(out) = out {
+                        self.control.guard(out.write_all(&data)).await??;
+                    }
+                    progress.transferred += data.len() as u64;
+                    self.control.report(progress.clone());
+                }
+                b'Z' => {
+                    if let Some((info, out, _)) = current.take() {
+                        // `D` if the sender discarded the file
+                        if packet.data.first() != Some(&b'D') {
+                            let mut out = match out {
+                                Some(out) => out,
+                                None => open(&info)?,
+                            };
+                            self.control.guard(out.flush()).await??;
+                            files.push(info);
+                        }
+                    }
+                }
+                b'B' => {
+                    self.write_packet(self.seq, b'Y', &[]).await?;
+                    return Ok(files);
+                }
+                kind => {
+                    return Err(TransferError::Protocol(format!(
+                        "unexpected Kermit packet {}",
+                        char::from(kind)
+                    )));
+                }
+            }
+            let ack = self.frame(self.seq, b'Y', &reply);
+            self.control.write(self.port, &ack).await?;
+            last_ack = Some(ack);
+            self.seq = (self.seq + 1) % 64;
+        }
+    }
+}
+
+/// A Kermit sender or receiver
+///
+/// Packets are sent once the peer acknowledged the previous one, or again after
+/// it rejected them or did not answer within the timeout, 10 seconds by default,
+/// up to 10 times.  Both ends compress repeated bytes and prefix 8 bit bytes when
+/// the peer asks to, file sizes are sent in attribute packets when the receiver
+/// accepts them.
+#[derive(Debug)]
+pub struct Kermit {
+    check: BlockCheck,
+    eight_bit: bool,
+    timeout: Duration,
+    retries: u32,
+    control: Control,
+}
+
+impl Default for Kermit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Kermit {
+    /// A Kermit sender or receiver, asking for CRCs.
+    pub fn new() -> Self {
+        Self {
+            check: BlockCheck::Crc16,
+            eight_bit: false,
+            timeout: Duration::from_secs(10),
+            retries: 10,
+            control: Control::default(),
+        }
+    }
+
+    /// Set how packets are checked.
+    ///
+    /// Both ends fall back to the 6 bit sum when they do not ask for the same
+    /// check.
+    pub fn block_check(mut self, check: BlockCheck) -> Self {
+        self.check = check;
+        self
+    }
+
+    /// Ask the peer to prefix bytes with their 8th bit set, for links which
+    /// only carry 7 data bits.
+    ///
+    /// Otherwise bytes are only prefixed if the peer asks to.
+    pub fn eight_bit_prefixing(mut self, eight_bit: bool) -> Self {
+        self.eight_bit = eight_bit;
+        self
+    }
+
+    /// Set how long to wait for the peer to answer.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how many times a packet is sent or requested again before giving up.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Report the progress of transfers to `progress`.
+    pub fn progress<F>(mut self, progress: F) -> Self
+    where
+        F: FnMut(&Progress) + Send + 'static,
+    {
+        self.control.set_progress(progress);
+        self
+    }
+
+    /// Cancel transfers once `token` is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.control.set_cancellation(token);
+        self
+    }
+
+    fn link<'a, P>(&'a mut self, port: &'a mut P) -> Link<'a, P> {
+        let quoting = Quoting {
+            qctl: QCTL,
+            qbin: None,
+            rept: None,
+        };
+        Link {
+            port,
+            control: &mut self.control,
+            timeout: self.timeout,
+            retries: self.retries,
+            check: BlockCheck::Sum6,
+            ours: Params::ours(self.check, self.eight_bit),
+            send: quoting,
+            recv: quoting,
+            peer: Params::decode(&[]),
+            seq: 0,
+        }
+    }
+
+    /// Send files, each with its [`FileInfo`].
+    pub async fn send<P, R, I>(&mut self, port: &mut P, files: I) -> Result<(), TransferError>
+    where
+        P: AsyncSerialPort,
+        R: AsyncRead + Unpin,
+        I: IntoIterator<Item = (FileInfo, R)>,
+    {
+        let mut link = self.link(port);
+        let result = async {
+            link.send_init().await?;
+            for (info, mut data) in files {
+                link.send_file(&info, &mut data).await?;
+            }
+            link.exchange(b'B', &[]).await.map(|_| ())
+        }
+        .await;
+        match result {
+            Ok(()) => Ok(()),
+            Err(err) => Err(link.abort(err).await),
+        }
+    }
+
+    /// Receive files, writing each to the writer `open` returns for it.
+    ///
+    /// Returns what the sender told about the files received.
+    pub async fn receive<P, W, F>(
+        &mut self,
+        port: &mut P,
+        mut open: F,
+    ) -> Result<Vec<FileInfo>, TransferError>
+    where
+        P: AsyncSerialPort,
+        W: AsyncWrite + Unpin,
+        F: FnMut(&FileInfo) -> io::Result<W>,
+    {
+        let mut link = self.link(port);
+        match link.receive(&mut open).await {
+            Ok(files) => Ok(files),
+            Err(err) => Err(link.abort(err).await),
+        }
+    }
+}
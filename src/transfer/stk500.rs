@@ -0,0 +1,585 @@
+//! STK500 AVR programming
+//!
+//! The bootloaders of most AVR boards speak a subset of the protocol of the
+//! Atmel STK500 programmer: Optiboot on the Arduino Uno and Nano speaks version 1,
+//! the bootloader of the Arduino Mega 2560 version 2.  [`Stk500`] drives either,
+//! from resetting the board into its bootloader to writing and reading back
+//! pages of flash or EEPROM.
+//!
+//! Version 1 commands are single bytes followed by their arguments and `0x20`,
+//! answered with `0x14`, the result and `0x10`.  Version 2 wraps commands into
+//! messages:
+//!
+//! | field | |
+//! |-------|-|
+//! | `MESSAGE_START` | `0x1b` |
+//! | `SEQUENCE_NUMBER` | incremented with each command, echoed in its answer |
+//! | `MESSAGE_SIZE` | the size of the body, big endian |
+//! | `TOKEN` | `0x0e` |
+//! | `MESSAGE_BODY` | the command, or the answer, and its arguments |
+//! | `CHECKSUM` | the exclusive or of all the previous bytes |
+//!
+//! ```no_run
+//! # async fn example(port: tokio_serial::SerialStream) -> Result<(), tokio_serial::transfer::TransferError> {
+//! use tokio_serial::transfer::stk500::{Memory, Stk500};
+//!
+//! let firmware = std::fs::read("blink.bin")?;
+//! let mut avr = Stk500::new(port);
+//! avr.reset().await?;
+//! avr.enter_programming().await?;
+//! avr.flash(Memory::Flash, 0, &firmware, 128).await?;
+//! avr.leave_programming().await?;
+//! # Ok(())
+//! # }
+//! ```
+use super::{CancellationToken, Control, Progress, TransferError};
+use crate::AsyncSerialPort;
+
+use std::time::Duration;
+
+/// STK500 version 1 constants
+pub mod v1 {
+    /// End of commands
+    pub const CRC_EOP: u8 = 0x20;
+    /// Start of answers
+    pub const INSYNC: u8 = 0x14;
+    /// End of successful answers
+    pub const OK: u8 = 0x10;
+    /// End of failed answers
+    pub const FAILED: u8 = 0x11;
+    /// Answer to commands not ended with [`CRC_EOP`]
+    pub const NOSYNC: u8 = 0x15;
+
+    /// Check that the bootloader listens
+    pub const GET_SYNC: u8 = 0x30;
+    /// Read a parameter
+    pub const GET_PARAMETER: u8 = 0x41;
+    /// Enter programming mode
+    pub const ENTER_PROGMODE: u8 = 0x50;
+    /// Leave programming mode, Optiboot then starts the application
+    pub const LEAVE_PROGMODE: u8 = 0x51;
+    /// Set the address of the next page
+    pub const LOAD_ADDRESS: u8 = 0x55;
+    /// Write a page
+    pub const PROG_PAGE: u8 = 0x64;
+    /// Read a page
+    pub const READ_PAGE: u8 = 0x74;
+    /// Read the signature of the chip
+    pub const READ_SIGN: u8 = 0x75;
+
+    /// The major version of the bootloader
+    pub const SW_MAJOR: u8 = 0x81;
+    /// The minor version of the bootloader
+    pub const SW_MINOR: u8 = 0x82;
+}
+
+/// STK500 version 2 constants
+pub mod v2 {
+    /// Start of messages
+    pub const MESSAGE_START: u8 = 0x1b;
+    /// Token before the body of messages
+    pub const TOKEN: u8 = 0x0e;
+    /// Status of successful answers
+    pub const STATUS_CMD_OK: u8 = 0x00;
+
+    /// Check that the bootloader listens, answered with its name
+    pub const CMD_SIGN_ON: u8 = 0x01;
+    /// Read a parameter
+    pub const CMD_GET_PARAMETER: u8 = 0x03;
+    /// Set the address of the next page
+    pub const CMD_LOAD_ADDRESS: u8 = 0x06;
+    /// Enter programming mode
+    pub const CMD_ENTER_PROGMODE_ISP: u8 = 0x10;
+    /// Leave programming mode
+    pub const CMD_LEAVE_PROGMODE_ISP: u8 = 0x11;
+    /// Write a page of flash
+    pub const CMD_PROGRAM_FLASH_ISP: u8 = 0x13;
+    /// Read a page of flash
+    pub const CMD_READ_FLASH_ISP: u8 = 0x14;
+    /// Write a page of EEPROM
+    pub const CMD_PROGRAM_EEPROM_ISP: u8 = 0x15;
+    /// Read a page of EEPROM
+    pub const CMD_READ_EEPROM_ISP: u8 = 0x16;
+    /// Read a byte of the signature of the chip
+    pub const CMD_READ_SIGNATURE_ISP: u8 = 0x1b;
+
+    /// The major version of the bootloader
+    pub const PARAM_SW_MAJOR: u8 = 0x91;
+    /// The minor version of the bootloader
+    pub const PARAM_SW_MINOR: u8 = 0x92;
+}
+
+/// The version of the STK500 protocol a bootloader speaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Version {
+    /// Version 1, Optiboot and the older Arduino bootloaders
+    V1,
+    /// Version 2, the bootloader of the Arduino Mega 2560
+    V2,
+}
+
+/// The memories of AVR chips
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Memory {
+    /// The program memory, addressed in words by the protocol
+    Flash,
+    /// The data EEPROM, addressed in words by STK500v1 bootloaders and in bytes by STK500v2
+    Eeprom,
+}
+
+/// Reset an Arduino style board into its bootloader.
+///
+/// Boards reset on the falling edge of DTR or RTS, through a capacitor: both are
+/// released for 250ms, then asserted again and the bootloader is given 50ms to
+/// start.
+pub async fn reset<P: AsyncSerialPort>(port: &mut P) -> Result<(), TransferError> {
+    port.set_dtr(false)?;
+    port.set_rts(false)?;
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    port.set_dtr(true)?;
+    port.set_rts(true)?;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    Ok(())
+}
+
+/// An STK500 bootloader on the other end of a port
+///
+/// Commands wait 500ms for their answer by default.  [`sync`](Stk500::sync)
+/// tries 10 times, the bootloader may still be starting or the board may have
+/// sent garbage before.  Protocol version 1 is the default.
+#[derive(Debug)]
+pub struct Stk500<P> {
+    port: P,
+    version: Version,
+    timeout: Duration,
+    retries: u32,
+    // The sequence number of the next version 2 message.
+    seq: u8,
+    control: Control,
+}
+
+impl<P: AsyncSerialPort> Stk500<P> {
+    /// Program through `port`, with protocol version 1.
+    pub fn new(port: P) -> Self {
+        Self {
+            port,
+            version: Version::V1,
+            timeout: Duration::from_millis(500),
+            retries: 10,
+            seq: 0,
+            control: Control::default(),
+        }
+    }
+
+    /// Speak protocol `version`.
+    pub fn version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Wait at most `timeout` for each answer.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Try to get in sync with the bootloader `retries` times.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Report the progress of [`flash`](Stk500::flash) after each page.
+    pub fn progress<F>(mut self, progress: F) -> Self
+    where
+        F: FnMut(&Progress) + Send + 'static,
+    {
+        self.control.set_progress(progress);
+        self
+    }
+
+    /// Cancel commands when `token` is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.control.set_cancellation(token);
+        self
+    }
+
+    /// Returns a reference to the underlying port.
+    pub fn get_ref(&self) -> &P {
+        &self.port
+    }
+
+    /// Returns a mutable reference to the underlying port.
+    pub fn get_mut(&mut self) -> &mut P {
+        &mut self.port
+    }
+
+    /// Consumes the programmer, returning the underlying port.
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    /// Reset the board into its bootloader, see [`reset`], and get in sync
+    /// with it.
+    pub async fn reset(&mut self) -> Result<(), TransferError> {
+        reset(&mut self.port).await?;
+        self.sync().await
+    }
+
+    /// Get in sync with the bootloader.
+    ///
+    /// Whatever the board sent before is discarded.
+    ///
+    /// ## Errors
+    ///
+    /// * `TooManyErrors` if the bootloader does not answer any of the retries.
+    pub async fn sync(&mut self) -> Result<(), TransferError> {
+        for _ in 0..self.retries {
+            self.control
+                .purge(&mut self.port, Duration::from_millis(20))
+                .await?;
+            let synced = match self.version {
+                Version::V1 => self.command_v1(&[v1::GET_SYNC], 0).await,
+                Version::V2 => self.command_v2(&[v2::CMD_SIGN_ON]).await,
+            };
+            match synced {
+                Ok(_) => return Ok(()),
+                Err(TransferError::Timeout) | Err(TransferError::Protocol(_)) => {
+                    log::debug!("no STK500 sync, retrying");
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(TransferError::TooManyErrors)
+    }
+
+    /// Read the software version of the bootloader, as major and minor.
+    pub async fn software_version(&mut self) -> Result<(u8, u8), TransferError> {
+        let (major, minor) = match self.version {
+            Version::V1 => (v1::SW_MAJOR, v1::SW_MINOR),
+            Version::V2 => (v2::PARAM_SW_MAJOR, v2::PARAM_SW_MINOR),
+        };
+        Ok((self.parameter(major).await?, self.parameter(minor).await?))
+    }
+
+    /// Read parameter `param` of the bootloader.
+    pub async fn parameter(&mut self, param: u8) -> Result<u8, TransferError> {
+        let value = match self.version {
+            Version::V1 => self.command_v1(&[v1::GET_PARAMETER, param], 1).await?,
+            Version::V2 => {
+                let answer = self.command_v2(&[v2::CMD_GET_PARAMETER, param]).await?;
+                answer.get(2..3).map(<[u8]>::to_vec).unwrap_or_default()
+            }
+        };
+        value
+            .first()
+            .copied()
+            .ok_or_else(|| TransferError::Protocol("short STK500 answer".into()))
+    }
+
+    /// Read the three bytes of the signature of the chip.
+    pub async fn signature(&mut self) -> Result<[u8; 3], TransferError> {
+        let mut signature = [0u8; 3];
+        match self.version {
+            Version::V1 => {
+                let answer = self.command_v1(&[v1::READ_SIGN], 3).await?;
+                signature.copy_from_slice(&answer);
+            }
+            Version::V2 => {
+                for (i, byte) in signature.iter_mut().enumerate() {
+                    let answer = self
+                        .command_v2(&[v2::CMD_READ_SIGNATURE_ISP, 0, 0x30, 0, i as u8, 0])
+                        .await?;
+                    *byte = *answer
+                        .get(2)
+                        .ok_or_else(|| TransferError::Protocol("short STK500 answer".into()))?;
+                }
+            }
+        }
+        Ok(signature)
+    }
+
+    /// Enter programming mode.
+    pub async fn enter_programming(&mut self) -> Result<(), TransferError> {
+        match self.version {
+            Version::V1 => self.command_v1(&[v1::ENTER_PROGMODE], 0).await?,
+            // The ISP timings and commands of the ATmega2560, which bootloaders
+            // ignore.
+            Version::V2 => {
+                let command = [
+                    v2::CMD_ENTER_PROGMODE_ISP,
+                    200,
+                    100,
+                    25,
+                    32,
+                    0,
+                    0x53,
+                    3,
+                    0xac,
+                    0x53,
+                    0,
+                    0,
+                ];
+                self.command_v2(&command).await?
+            }
+        };
+        Ok(())
+    }
+
+    /// Leave programming mode, which starts the application on most bootloaders.
+    pub async fn leave_programming(&mut self) -> Result<(), TransferError> {
+        match self.version {
+            Version::V1 => self.command_v1(&[v1::LEAVE_PROGMODE], 0).await?,
+            Version::V2 => self.command_v2(&[v2::CMD_LEAVE_PROGMODE_ISP, 1, 1]).await?,
+        };
+        Ok(())
+    }
+
+    /// Set the address of the next page, in bytes.
+    ///
+    /// The protocol addresses flash in words, `address` is halved for it. STK500v1
+    /// bootloaders such as optiboot also take EEPROM addresses in words, so `address` must be
+    /// even there.
+    pub async fn load_address(
+        &mut self,
+        memory: Memory,
+        address: u32,
+    ) -> Result<(), TransferError> {
+        let address = match (memory, self.version) {
+            (Memory::Flash, _) | (Memory::Eeprom, Version::V1) => address / 2,
+            (Memory::Eeprom, Version::V2) => address,
+        };
+        match self.version {
+            Version::V1 => {
+                let [low, high, ..] = (address as u16).to_le_bytes();
+                self.command_v1(&[v1::LOAD_ADDRESS, low, high], 0).await?
+            }
+            Version::V2 => {
+                let mut address = address;
+                // Flash past 128KiB is selected with the top bit.
+                if memory == Memory::Flash && address >= 0x1_0000 {
+                    address |= 0x8000_0000;
+                }
+                let mut command = vec![v2::CMD_LOAD_ADDRESS];
+                command.extend_from_slice(&address.to_be_bytes());
+                self.command_v2(&command).await?
+            }
+        };
+        Ok(())
+    }
+
+    /// Write `data` as a page at the address loaded.
+    pub async fn program_page(&mut self, memory: Memory, data: &[u8]) -> Result<(), TransferError> {
+        let [high, low] = (data.len() as u16).to_be_bytes();
+        match self.version {
+            Version::V1 => {
+                let mut command = vec![v1::PROG_PAGE, high, low, memory_type(memory)];
+                command.extend_from_slice(data);
+                self.command_v1(&command, 0).await?
+            }
+            Version::V2 => {
+                // Page mode, the delay and the ISP commands, ignored by bootloaders.
+                let mut command = match memory {
+                    Memory::Flash => vec![
+                        v2::CMD_PROGRAM_FLASH_ISP,
+                        high,
+                        low,
+                        0xc1,
+                        10,
+                        0x40,
+                        0x4c,
+                        0x20,
+                        0,
+                        0,
+                    ],
+                    Memory::Eeprom => vec![
+                        v2::CMD_PROGRAM_EEPROM_ISP,
+                        high,
+                        low,
+                        0xc1,
+                        10,
+                        0xc1,
+                        0xc2,
+                        0xa0,
+                        0xff,
+                        0xff,
+                    ],
+                };
+                command.extend_from_slice(data);
+                self.command_v2(&command).await?
+            }
+        };
+        Ok(())
+    }
+
+    /// Read a page of `len` bytes at the address loaded.
+    pub async fn read_page(
+        &mut self,
+        memory: Memory,
+        len: usize,
+    ) -> Result<Vec<u8>, TransferError> {
+        let [high, low] = (len as u16).to_be_bytes();
+        match self.version {
+            Version::V1 => {
+                self.command_v1(&[v1::READ_PAGE, high, low, memory_type(memory)], len)
+                    .await
+            }
+            Version::V2 => {
+                let command = match memory {
+                    Memory::Flash => [v2::CMD_READ_FLASH_ISP, high, low, 0x20],
+                    Memory::Eeprom => [v2::CMD_READ_EEPROM_ISP, high, low, 0xa0],
+                };
+                let answer = self.command_v2(&command).await?;
+                // The command, its status, the data and the status again.
+                if answer.len() != len + 3 {
+                    return Err(TransferError::Protocol("short STK500 answer".into()));
+                }
+                Ok(answer[2..2 + len].to_vec())
+            }
+        }
+    }
+
+    /// Write `image` at `address`, in pages of `page_size` bytes, reporting the
+    /// progress after each page.
+    ///
+    /// `address` and `page_size` should be multiples of the page size of the
+    /// chip, the last page is written short.
+    ///
+    /// # Panics
+    ///
+    /// If `page_size` is 0.
+    pub async fn flash(
+        &mut self,
+        memory: Memory,
+        address: u32,
+        image: &[u8],
+        page_size: usize,
+    ) -> Result<(), TransferError> {
+        assert!(page_size > 0, "STK500 pages cannot be empty");
+        let mut written = 0;
+        for page in image.chunks(page_size) {
+            self.load_address(memory, address + written as u32).await?;
+            self.program_page(memory, page).await?;
+            written += page.len();
+            self.control.report(Progress {
+                file: None,
+                transferred: written as u64,
+                total: Some(image.len() as u64),
+            });
+        }
+        Ok(())
+    }
+
+    // Send a version 1 command, returning the `len` bytes of its result.
+    async fn command_v1(&mut self, command: &[u8], len: usize) -> Result<Vec<u8>, TransferError> {
+        let mut frame = command.to_vec();
+        frame.push(v1::CRC_EOP);
+        self.control.write(&mut self.port, &frame).await?;
+
+        match self.control.read_byte(&mut self.port, self.timeout).await? {
+            Some(v1::INSYNC) => {}
+            Some(v1::NOSYNC) => return Err(TransferError::Protocol("STK500 not in sync".into())),
+            Some(byte) => {
+                return Err(TransferError::Protocol(format!(
+                    "unexpected STK500 answer {:#04x}",
+                    byte
+                )))
+            }
+            None => return Err(TransferError::Timeout),
+        }
+        let mut answer = vec![0u8; len + 1];
+        if !self
+            .control
+            .read_exact(&mut self.port, &mut answer, self.timeout)
+            .await?
+        {
+            return Err(TransferError::Timeout);
+        }
+        match answer.pop() {
+            Some(v1::OK) => Ok(answer),
+            Some(v1::FAILED) => Err(TransferError::Protocol(format!(
+                "STK500 command {:#04x} failed",
+                command[0]
+            ))),
+            _ => Err(TransferError::Protocol("STK500 answer out of sync".into())),
+        }
+    }
+
+    // Send a version 2 command, returning its answer from the command byte.
+    async fn command_v2(&mut self, command: &[u8]) -> Result<Vec<u8>, TransferError> {
+        let seq = self.seq;
+        self.seq = self.seq.wrapping_add(1);
+        let mut frame = vec![v2::MESSAGE_START, seq];
+        frame.extend_from_slice(&(command.len() as u16).to_be_bytes());
+        frame.push(v2::TOKEN);
+        frame.extend_from_slice(command);
+        frame.push(xor(&frame));
+        self.control.write(&mut self.port, &frame).await?;
+
+        let answer = loop {
+            let (answer_seq, answer) = self.read_message().await?;
+            if answer_seq == seq {
+                break answer;
+            }
+            log::debug!(
+                "skipping STK500 answer {} to an earlier command",
+                answer_seq
+            );
+        };
+        match answer.get(..2) {
+            Some([cmd, v2::STATUS_CMD_OK]) if *cmd == command[0] => Ok(answer),
+            Some([cmd, status]) if *cmd == command[0] => Err(TransferError::Protocol(format!(
+                "STK500 command {:#04x} failed with status {:#04x}",
+                cmd, status
+            ))),
+            _ => Err(TransferError::Protocol("STK500 answer out of sync".into())),
+        }
+    }
+
+    // The next version 2 message, its sequence number and its body.
+    async fn read_message(&mut self) -> Result<(u8, Vec<u8>), TransferError> {
+        loop {
+            match self.control.read_byte(&mut self.port, self.timeout).await? {
+                Some(v2::MESSAGE_START) => {}
+                Some(_) => continue,
+                None => return Err(TransferError::Timeout),
+            }
+            let mut header = [0u8; 4];
+            if !self
+                .control
+                .read_exact(&mut self.port, &mut header, self.timeout)
+                .await?
+            {
+                return Err(TransferError::Timeout);
+            }
+            if header[3] != v2::TOKEN {
+                continue;
+            }
+            let len = usize::from(u16::from_be_bytes([header[1], header[2]]));
+            let mut body = vec![0u8; len + 1];
+            if !self
+                .control
+                .read_exact(&mut self.port, &mut body, self.timeout)
+                .await?
+            {
+                return Err(TransferError::Timeout);
+            }
+            let checksum = body.pop().unwrap_or_default();
+            if v2::MESSAGE_START ^ xor(&header) ^ xor(&body) != checksum {
+                return Err(TransferError::Protocol("STK500 checksum mismatch".into()));
+            }
+            return Ok((header[0], body));
+        }
+    }
+}
+
+fn memory_type(memory: Memory) -> u8 {
+    match memory {
+        Memory::Flash => b'F',
+        Memory::Eeprom => b'E',
+    }
+}
+
+fn xor(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, &byte| sum ^ byte)
+}
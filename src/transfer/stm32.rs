@@ -0,0 +1,377 @@
+//! The STM32 system memory bootloader
+//!
+//! Every STM32 has a bootloader in ROM, started by booting with `BOOT0` high,
+//! which speaks the protocol of ST application note AN3155 on its UARTs.  The
+//! line is 8 data bits, even parity and one stop bit, see
+//! [`presets::stm32_bootloader`](crate::presets::stm32_bootloader); the
+//! bootloader measures the baud rate on the first byte, `0x7f`.
+//!
+//! Commands are a byte and its complement.  The bootloader answers each command,
+//! and each block of arguments, with `ACK` (`0x79`) or `NACK` (`0x1f`).
+//! Arguments end with the exclusive or of their bytes.
+//!
+//! ```no_run
+//! # async fn example(port: tokio_serial::SerialStream) -> Result<(), tokio_serial::transfer::TransferError> {
+//! use tokio_serial::transfer::stm32::{Erase, Stm32, FLASH_START};
+//!
+//! let firmware = std::fs::read("app.bin")?;
+//! let mut stm32 = Stm32::new(port).progress(|progress| {
+//!     println!("{}/{:?}", progress.transferred, progress.total);
+//! });
+//! stm32.connect().await?;
+//! stm32.erase(Erase::Mass).await?;
+//! stm32.write_memory(FLASH_START, &firmware).await?;
+//! stm32.go(FLASH_START).await?;
+//! # Ok(())
+//! # }
+//! ```
+use super::{CancellationToken, Control, Progress, TransferError};
+use crate::AsyncSerialPort;
+
+use std::time::Duration;
+
+/// The byte the bootloader measures the baud rate on
+pub const SYNC: u8 = 0x7f;
+/// Positive answer
+pub const ACK: u8 = 0x79;
+/// Negative answer
+pub const NACK: u8 = 0x1f;
+
+/// The start of the flash memory of STM32s, where applications live
+pub const FLASH_START: u32 = 0x0800_0000;
+
+/// The most bytes read or written by a command
+pub const MAX_BLOCK: usize = 256;
+
+/// Bootloader commands
+pub mod command {
+    /// The version of the bootloader and the commands it supports
+    pub const GET: u8 = 0x00;
+    /// The version of the bootloader and the option bytes
+    pub const GET_VERSION: u8 = 0x01;
+    /// The product id of the chip
+    pub const GET_ID: u8 = 0x02;
+    /// Read up to 256 bytes of memory
+    pub const READ_MEMORY: u8 = 0x11;
+    /// Jump to an application
+    pub const GO: u8 = 0x21;
+    /// Write up to 256 bytes of memory
+    pub const WRITE_MEMORY: u8 = 0x31;
+    /// Erase pages of flash, with 8 bit page numbers
+    pub const ERASE: u8 = 0x43;
+    /// Erase pages of flash, with 16 bit page numbers
+    pub const EXTENDED_ERASE: u8 = 0x44;
+}
+
+/// What the `GET` command tells about the bootloader
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BootloaderInfo {
+    /// The version of the protocol, `0x31` for 3.1
+    pub version: u8,
+    /// The commands the bootloader supports
+    pub commands: Vec<u8>,
+}
+
+impl BootloaderInfo {
+    /// Returns whether the bootloader supports `command`.
+    pub fn supports(&self, command: u8) -> bool {
+        self.commands.contains(&command)
+    }
+}
+
+/// What to erase
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Erase {
+    /// The whole flash
+    Mass,
+    /// These pages
+    Pages(Vec<u16>),
+}
+
+/// A client of the STM32 bootloader
+///
+/// Commands wait a second for each answer by default, erasing 30 seconds: a
+/// mass erase of large chips takes that long.
+/// [`write_memory`](Stm32::write_memory) reports its progress after each block.
+#[derive(Debug)]
+pub struct Stm32<P> {
+    port: P,
+    timeout: Duration,
+    erase_timeout: Duration,
+    retries: u32,
+    info: Option<BootloaderInfo>,
+    control: Control,
+}
+
+impl<P: AsyncSerialPort> Stm32<P> {
+    /// Talk to the bootloader through `port`.
+    pub fn new(port: P) -> Self {
+        Self {
+            port,
+            timeout: Duration::from_secs(1),
+            erase_timeout: Duration::from_secs(30),
+            retries: 5,
+            info: None,
+            control: Control::default(),
+        }
+    }
+
+    /// Wait at most `timeout` for each answer.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Wait at most `timeout` for erasing to complete.
+    pub fn erase_timeout(mut self, timeout: Duration) -> Self {
+        self.erase_timeout = timeout;
+        self
+    }
+
+    /// Send the autobaud byte `retries` times before giving up.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Report the progress of [`write_memory`](Stm32::write_memory) after each
+    /// block.
+    pub fn progress<F>(mut self, progress: F) -> Self
+    where
+        F: FnMut(&Progress) + Send + 'static,
+    {
+        self.control.set_progress(progress);
+        self
+    }
+
+    /// Cancel commands when `token` is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.control.set_cancellation(token);
+        self
+    }
+
+    /// Returns a reference to the underlying port.
+    pub fn get_ref(&self) -> &P {
+        &self.port
+    }
+
+    /// Returns a mutable reference to the underlying port.
+    pub fn get_mut(&mut self) -> &mut P {
+        &mut self.port
+    }
+
+    /// Consumes the client, returning the underlying port.
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    /// Have the bootloader measure the baud rate, then ask for its version and
+    /// commands.
+    ///
+    /// A bootloader which measured the rate already answers the autobaud byte
+    /// with `NACK`, which is accepted as well.
+    ///
+    /// ## Errors
+    ///
+    /// * `TooManyErrors` if the bootloader does not answer any of the retries.
+    pub async fn connect(&mut self) -> Result<BootloaderInfo, TransferError> {
+        let mut connected = false;
+        for _ in 0..self.retries {
+            self.control
+                .purge(&mut self.port, Duration::from_millis(20))
+                .await?;
+            self.control.write(&mut self.port, &[SYNC]).await?;
+            match self.control.read_byte(&mut self.port, self.timeout).await? {
+                Some(ACK) | Some(NACK) => {
+                    connected = true;
+                    break;
+                }
+                Some(byte) => log::debug!("unexpected {:#04x} from the STM32 bootloader", byte),
+                None => log::debug!("no answer from the STM32 bootloader, retrying"),
+            }
+        }
+        if !connected {
+            return Err(TransferError::TooManyErrors);
+        }
+        self.get().await
+    }
+
+    /// Ask for the version of the bootloader and the commands it supports.
+    pub async fn get(&mut self) -> Result<BootloaderInfo, TransferError> {
+        self.command(command::GET).await?;
+        let answer = self.read_counted().await?;
+        self.expect_ack(self.timeout).await?;
+        let info = BootloaderInfo {
+            version: answer[0],
+            commands: answer[1..].to_vec(),
+        };
+        self.info = Some(info.clone());
+        Ok(info)
+    }
+
+    /// Ask for the product id of the chip, `0x0413` for the STM32F405/407 for
+    /// example.
+    pub async fn get_id(&mut self) -> Result<u16, TransferError> {
+        self.command(command::GET_ID).await?;
+        let answer = self.read_counted().await?;
+        self.expect_ack(self.timeout).await?;
+        match answer[..] {
+            [high, low] => Ok(u16::from_be_bytes([high, low])),
+            _ => Err(TransferError::Protocol("invalid STM32 product id".into())),
+        }
+    }
+
+    /// Read `len` bytes of memory at `address`, in blocks of up to 256 bytes.
+    pub async fn read_memory(
+        &mut self,
+        address: u32,
+        len: usize,
+    ) -> Result<Vec<u8>, TransferError> {
+        let mut data = vec![0u8; len];
+        for (i, block) in data.chunks_mut(MAX_BLOCK).enumerate() {
+            self.command(command::READ_MEMORY).await?;
+            self.send_address(address + (i * MAX_BLOCK) as u32).await?;
+            let count = (block.len() - 1) as u8;
+            self.send_checked(&[count, !count], self.timeout).await?;
+            if !self
+                .control
+                .read_exact(&mut self.port, block, self.timeout)
+                .await?
+            {
+                return Err(TransferError::Timeout);
+            }
+        }
+        Ok(data)
+    }
+
+    /// Write `data` to memory at `address`, in blocks of up to 256 bytes, and
+    /// report the progress after each block.
+    ///
+    /// The flash is written in words: the last block is padded with `0xff` to a
+    /// multiple of 4 bytes.  It should be erased first.
+    pub async fn write_memory(&mut self, address: u32, data: &[u8]) -> Result<(), TransferError> {
+        let mut written = 0;
+        for block in data.chunks(MAX_BLOCK) {
+            self.command(command::WRITE_MEMORY).await?;
+            self.send_address(address + written as u32).await?;
+            let mut args = Vec::with_capacity(1 + MAX_BLOCK + 1);
+            args.push(0);
+            args.extend_from_slice(block);
+            args.resize(1 + block.len().div_ceil(4) * 4, 0xff);
+            args[0] = (args.len() - 2) as u8;
+            args.push(xor(&args));
+            self.send_checked(&args, self.timeout).await?;
+            written += block.len();
+            self.control.report(Progress {
+                file: None,
+                transferred: written as u64,
+                total: Some(data.len() as u64),
+            });
+        }
+        Ok(())
+    }
+
+    /// Erase the whole flash or some of its pages.
+    ///
+    /// Uses the extended erase command when the bootloader supports it, as
+    /// found by [`connect`](Stm32::connect) or [`get`](Stm32::get).
+    pub async fn erase(&mut self, erase: Erase) -> Result<(), TransferError> {
+        let info = match &self.info {
+            Some(info) => info.clone(),
+            None => self.get().await?,
+        };
+        let extended = info.supports(command::EXTENDED_ERASE);
+        let mut args = Vec::new();
+        match (&erase, extended) {
+            (Erase::Mass, true) => args.extend_from_slice(&[0xff, 0xff]),
+            (Erase::Mass, false) => args.push(0xff),
+            (Erase::Pages(pages), _) if pages.is_empty() => return Ok(()),
+            (Erase::Pages(pages), true) => {
+                args.extend_from_slice(&((pages.len() - 1) as u16).to_be_bytes());
+                for page in pages {
+                    args.extend_from_slice(&page.to_be_bytes());
+                }
+            }
+            (Erase::Pages(pages), false) => {
+                if pages.len() > 256 || pages.iter().any(|&page| page > 0xff) {
+                    return Err(TransferError::Protocol(
+                        "the STM32 bootloader cannot erase pages past 255".into(),
+                    ));
+                }
+                args.push((pages.len() - 1) as u8);
+                args.extend(pages.iter().map(|&page| page as u8));
+            }
+        }
+        // The global erase of the basic command is checked with its complement.
+        args.push(match (&erase, extended) {
+            (Erase::Mass, false) => 0x00,
+            _ => xor(&args),
+        });
+        self.command(if extended {
+            command::EXTENDED_ERASE
+        } else {
+            command::ERASE
+        })
+        .await?;
+        self.send_checked(&args, self.erase_timeout).await
+    }
+
+    /// Jump to the application at `address`.
+    ///
+    /// The bootloader stops answering afterwards.
+    pub async fn go(&mut self, address: u32) -> Result<(), TransferError> {
+        self.command(command::GO).await?;
+        self.send_address(address).await
+    }
+
+    async fn command(&mut self, command: u8) -> Result<(), TransferError> {
+        self.send_checked(&[command, !command], self.timeout).await
+    }
+
+    async fn send_address(&mut self, address: u32) -> Result<(), TransferError> {
+        let mut args = address.to_be_bytes().to_vec();
+        args.push(xor(&args));
+        self.send_checked(&args, self.timeout).await
+    }
+
+    // Send `data` and wait for it to be acknowledged.
+    async fn send_checked(&mut self, data: &[u8], timeout: Duration) -> Result<(), TransferError> {
+        self.control.write(&mut self.port, data).await?;
+        self.expect_ack(timeout).await
+    }
+
+    async fn expect_ack(&mut self, timeout: Duration) -> Result<(), TransferError> {
+        match self.control.read_byte(&mut self.port, timeout).await? {
+            Some(ACK) => Ok(()),
+            Some(NACK) => Err(TransferError::Protocol(
+                "the STM32 bootloader refused the command".into(),
+            )),
+            Some(byte) => Err(TransferError::Protocol(format!(
+                "unexpected {:#04x} from the STM32 bootloader",
+                byte
+            ))),
+            None => Err(TransferError::Timeout),
+        }
+    }
+
+    // An answer starting with its length minus one.
+    async fn read_counted(&mut self) -> Result<Vec<u8>, TransferError> {
+        let count = match self.control.read_byte(&mut self.port, self.timeout).await? {
+            Some(count) => count,
+            None => return Err(TransferError::Timeout),
+        };
+        let mut answer = vec![0u8; usize::from(count) + 1];
+        if !self
+            .control
+            .read_exact(&mut self.port, &mut answer, self.timeout)
+            .await?
+        {
+            return Err(TransferError::Timeout);
+        }
+        Ok(answer)
+    }
+}
+
+fn xor(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, &byte| sum ^ byte)
+}
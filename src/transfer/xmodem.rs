@@ -0,0 +1,622 @@
+//! XMODEM and YMODEM
+//!
+//! XMODEM sends a single file in numbered blocks of 128 bytes, each acknowledged
+//! by the receiver before the next one is sent.  Blocks are checked with an 8 bit
+//! checksum in the original protocol, or a CRC-16 when the receiver asks for it by
+//! starting the transfer with `C` instead of `NAK`.  XMODEM-1K adds 1 KiB blocks.
+//!
+//! YMODEM sends batches of files as XMODEM-1K transfers, each preceded by a block
+//! giving the name and size of the file.
+//!
+//! ```no_run
+//! # async fn example(mut port: tokio_serial::SerialStream) -> Result<(), tokio_serial::transfer::TransferError> {
+//! use tokio_serial::transfer::xmodem::Xmodem;
+//!
+//! let firmware = tokio::fs::File::open("firmware.bin").await?;
+//! Xmodem::new()
+//!     .one_k(true)
+//!     .progress(|progress| println!("{} bytes sent", progress.transferred))
+//!     .send(&mut port, firmware)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+use super::{read_full, CancellationToken, Control, FileInfo, Progress, TransferError};
+use crate::AsyncSerialPort;
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio_serial_core::crc::CRC16_XMODEM;
+
+use std::io;
+use std::time::Duration;
+
+/// Starts a 128 bytes block
+pub const SOH: u8 = 0x01;
+/// Starts a 1024 bytes block
+pub const STX: u8 = 0x02;
+/// Ends a file
+pub const EOT: u8 = 0x04;
+/// Acknowledges a block
+pub const ACK: u8 = 0x06;
+/// Rejects a block, or starts a transfer with checksums
+pub const NAK: u8 = 0x15;
+/// Cancels the transfer, when sent twice
+pub const CAN: u8 = 0x18;
+/// Pads the last block of an XMODEM file
+pub const SUB: u8 = 0x1a;
+/// Starts a transfer with CRCs
+pub const CRC_REQUEST: u8 = b'C';
+
+const BLOCK: usize = 128;
+const BLOCK_1K: usize = 1024;
+// Requests sent by a receiver asking for CRCs before falling back to checksums.
+const CRC_ATTEMPTS: u32 = 3;
+
+/// How blocks are checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Checksum {
+    /// The arithmetic sum of the data bytes, of the original XMODEM
+    Sum,
+    /// CRC-16/XMODEM, sent high byte first
+    Crc,
+}
+
+impl Checksum {
+    fn len(self) -> usize {
+        match self {
+            Checksum::Sum => 1,
+            Checksum::Crc => 2,
+        }
+    }
+
+    fn append(self, data: &[u8], packet: &mut Vec<u8>) {
+        match self {
+            Checksum::Sum => packet.push(data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))),
+            Checksum::Crc => packet.extend_from_slice(&CRC16_XMODEM.checksum(data).to_be_bytes()),
+        }
+    }
+
+    fn verify(self, data: &[u8], check: &[u8]) -> bool {
+        let mut expected = Vec::with_capacity(2);
+        self.append(data, &mut expected);
+        expected == check
+    }
+}
+
+#[derive(Debug)]
+enum Packet {
+    Block(u8, Vec<u8>),
+    Eot,
+    Corrupt,
+    Timeout,
+}
+
+// One end of a transfer.
+struct Link<'a, P> {
+    port: &'a mut P,
+    control: &'a mut Control,
+    checksum: Checksum,
+    timeout: Duration,
+    retries: u32,
+}
+
+impl<'a, P: AsyncSerialPort> Link<'a, P> {
+    async fn read_byte(&mut self) -> Result<Option<u8>, TransferError> {
+        self.control.read_byte(self.port, self.timeout).await
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), TransferError> {
+        self.control.write(self.port, data).await
+    }
+
+    /// Tell the peer the transfer is over, the error is returned.
+    async fn abort(&mut self, err: TransferError) -> TransferError {
+        if !matches!(err, TransferError::CancelledByPeer | TransferError::Io(_)) {
+            // Backspaces erase the cancels if the peer turns out to be a shell.
+            let _ = self.port.write_all(&[CAN; 8]).await;
+            let _ = self.port.write_all(&[0x08; 8]).await;
+            let _ = self.port.flush().await;
+        }
+        err
+    }
+
+    // A CAN was received, cancellations are two of them in a row.
+    async fn cancelled(&mut self) -> Result<bool, TransferError> {
+        Ok(self.read_byte().await? == Some(CAN))
+    }
+
+    /// Wait for the receiver to start, returning how it wants blocks checked.
+    async fn wait_start(&mut self) -> Result<Checksum, TransferError> {
+        for _ in 0..=self.retries {
+            match self.read_byte().await? {
+                Some(CRC_REQUEST) => return Ok(Checksum::Crc),
+                Some(NAK) => return Ok(Checksum::Sum),
+                Some(CAN) if self.cancelled().await? => return Err(TransferError::CancelledByPeer),
+                Some(_) | None => {}
+            }
+        }
+        Err(TransferError::Timeout)
+    }
+
+    async fn send_block(
+        &mut self,
+        num: u8,
+        data: &[u8],
+        size: usize,
+        pad: u8,
+    ) -> Result<(), TransferError> {
+        let mut packet = Vec::with_capacity(3 + size + 2);
+        packet.push(if size == BLOCK_1K { STX } else { SOH });
+        packet.push(num);
+        packet.push(!num);
+        packet.extend_from_slice(data);
+        packet.resize(3 + size, pad);
+        let block = packet[3..].to_vec();
+        self.checksum.append(&block, &mut packet);
+
+        for _ in 0..=self.retries {
+            self.write(&packet).await?;
+            loop {
+                match self.read_byte().await? {
+                    Some(ACK) => return Ok(()),
+                    Some(NAK) | None => break,
+                    Some(CAN) if self.cancelled().await? => {
+                        return Err(TransferError::CancelledByPeer)
+                    }
+                    // Noise, or the receiver still asking to start
+                    Some(_) => {}
+                }
+            }
+            log::debug!("block {} rejected", num);
+        }
+        Err(TransferError::TooManyErrors)
+    }
+
+    async fn send_data<R: AsyncRead + Unpin>(
+        &mut self,
+        data: &mut R,
+        one_k: bool,
+        mut progress: Progress,
+    ) -> Result<u64, TransferError> {
+        let mut buf = vec![0u8; if one_k { BLOCK_1K } else { BLOCK }];
+        let mut num = 1u8;
+        loop {
+            let len = self.control.guard(read_full(data, &mut buf)).await??;
+            if len == 0 {
+                break;
+            }
+            let size = if len > BLOCK { BLOCK_1K } else { BLOCK };
+            self.send_block(num, &buf[..len], size, SUB).await?;
+            num = num.wrapping_add(1);
+            progress.transferred += len as u64;
+            self.control.report(progress.clone());
+        }
+        // Receivers often reject the first EOT to make sure it is not noise.
+        for _ in 0..=self.retries {
+            self.write(&[EOT]).await?;
+            match self.read_byte().await? {
+                Some(ACK) => return Ok(progress.transferred),
+                Some(CAN) if self.cancelled().await? => return Err(TransferError::CancelledByPeer),
+                _ => {}
+            }
+        }
+        Err(TransferError::TooManyErrors)
+    }
+
+    async fn recv_packet(&mut self) -> Result<Packet, TransferError> {
+        loop {
+            let size = match self.read_byte().await? {
+                None => return Ok(Packet::Timeout),
+                Some(SOH) => BLOCK,
+                Some(STX) => BLOCK_1K,
+                Some(EOT) => return Ok(Packet::Eot),
+                Some(CAN) if self.cancelled().await? => return Err(TransferError::CancelledByPeer),
+                Some(_) => continue,
+            };
+            let mut packet = vec![0u8; 2 + size + self.checksum.len()];
+            if !self
+                .control
+                .read_exact(self.port, &mut packet, self.timeout)
+                .await?
+            {
+                return Ok(Packet::Corrupt);
+            }
+            let (num, inverse) = (packet[0], packet[1]);
+            let (data, check) = packet[2..].split_at(size);
+            if num != !inverse || !self.checksum.verify(data, check) {
+                return Ok(Packet::Corrupt);
+            }
+            return Ok(Packet::Block(num, data.to_vec()));
+        }
+    }
+
+    /// Receive the blocks of a file, after asking for them with `request`.
+    async fn recv_data<W: AsyncWrite + Unpin>(
+        &mut self,
+        out: &mut W,
+        mut request: u8,
+        batch: bool,
+        mut progress: Progress,
+    ) -> Result<u64, TransferError> {
+        let mut expected = 1u8;
+        let mut errors = 0;
+        let mut started = false;
+        let mut eots = 0;
+        self.write(&[request]).await?;
+        loop {
+            match self.recv_packet().await? {
+                Packet::Block(num, data) if num == expected => {
+                    let len = match progress.total {
+                        Some(total) => (total - progress.transferred).min(data.len() as u64),
+                        None => data.len() as u64,
+                    };
+                    self.control
+                        .guard(out.write_all(&data[..len as usize]))
+                        .await??;
+                    started = true;
+                    errors = 0;
+                    expected = expected.wrapping_add(1);
+                    self.write(&[ACK]).await?;
+                    progress.transferred += len;
+                    self.control.report(progress.clone());
+                    continue;
+                }
+                // Our ACK was lost
+                Packet::Block(num, _) if num == expected.wrapping_sub(1) && started => {
+                    self.write(&[ACK]).await?;
+                    continue;
+                }
+                Packet::Block(num, _) => {
+                    return Err(TransferError::Protocol(format!(
+                        "received block {} instead of {}",
+                        num, expected
+                    )));
+                }
+                Packet::Eot if batch && eots == 0 => {
+                    eots += 1;
+                    self.write(&[NAK]).await?;
+                    continue;
+                }
+                Packet::Eot => {
+                    self.write(&[ACK]).await?;
+                    self.control.guard(out.flush()).await??;
+                    return Ok(progress.transferred);
+                }
+                Packet::Corrupt => {
+                    self.control
+                        .purge(self.port, Duration::from_secs(1))
+                        .await?;
+                }
+                Packet::Timeout => {}
+            }
+            errors += 1;
+            if errors > self.retries {
+                return Err(if started {
+                    TransferError::TooManyErrors
+                } else {
+                    TransferError::Timeout
+                });
+            }
+            if !started && request == CRC_REQUEST && !batch && errors >= CRC_ATTEMPTS {
+                // The sender may only know checksums.
+                request = NAK;
+                self.checksum = Checksum::Sum;
+            }
+            self.write(&[if started { NAK } else { request }]).await?;
+        }
+    }
+
+    /// Receive the block 0 of a YMODEM file, `None` at the end of the batch.
+    async fn recv_header(&mut self) -> Result<Option<FileInfo>, TransferError> {
+        for _ in 0..=self.retries {
+            self.write(&[CRC_REQUEST]).await?;
+            match self.recv_packet().await? {
+                Packet::Block(0, data) => {
+                    self.write(&[ACK]).await?;
+                    if data[0] == 0 {
+                        return Ok(None);
+                    }
+                    return FileInfo::decode(&data)
+                        .map(Some)
+                        .ok_or_else(|| TransferError::Protocol("invalid file header".into()));
+                }
+                Packet::Corrupt => {
+                    self.control
+                        .purge(self.port, Duration::from_secs(1))
+                        .await?;
+                }
+                // The end of the previous file, our ACK to it was lost
+                Packet::Eot => self.write(&[ACK]).await?,
+                _ => {}
+            }
+        }
+        Err(TransferError::Timeout)
+    }
+}
+
+/// An XMODEM sender or receiver
+///
+/// Blocks are sent once the receiver acknowledged the previous one, or again after
+/// it rejected them or did not answer within the timeout, 10 seconds by default,
+/// up to 10 times.
+///
+/// XMODEM does not send the size of files: received files are padded with `SUB`
+/// bytes (`0x1A`) to a multiple of the block size.
+#[derive(Debug)]
+pub struct Xmodem {
+    checksum: Checksum,
+    one_k: bool,
+    timeout: Duration,
+    retries: u32,
+    control: Control,
+}
+
+impl Default for Xmodem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Xmodem {
+    /// XMODEM with CRCs and 128 bytes blocks.
+    pub fn new() -> Self {
+        Self {
+            checksum: Checksum::Crc,
+            one_k: false,
+            timeout: Duration::from_secs(10),
+            retries: 10,
+            control: Control::default(),
+        }
+    }
+
+    /// Set how received blocks are checked.
+    ///
+    /// Receivers asking for CRCs fall back to checksums when the sender does not
+    /// answer, senders use whatever the receiver asks for.
+    pub fn checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Send 1 KiB blocks, XMODEM-1K, if the receiver asks for CRCs.
+    pub fn one_k(mut self, one_k: bool) -> Self {
+        self.one_k = one_k;
+        self
+    }
+
+    /// Set how long to wait for the peer to answer.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how many times a block is sent or requested again before giving up.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Report the progress of transfers to `progress`.
+    pub fn progress<F>(mut self, progress: F) -> Self
+    where
+        F: FnMut(&Progress) + Send + 'static,
+    {
+        self.control.set_progress(progress);
+        self
+    }
+
+    /// Cancel transfers once `token` is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.control.set_cancellation(token);
+        self
+    }
+
+    fn link<'a, P>(&'a mut self, port: &'a mut P) -> Link<'a, P> {
+        Link {
+            port,
+            control: &mut self.control,
+            checksum: self.checksum,
+            timeout: self.timeout,
+            retries: self.retries,
+        }
+    }
+
+    /// Send `data`, returning the number of bytes sent.
+    pub async fn send<P, R>(&mut self, port: &mut P, mut data: R) -> Result<u64, TransferError>
+    where
+        P: AsyncSerialPort,
+        R: AsyncRead + Unpin,
+    {
+        let one_k = self.one_k;
+        let mut link = self.link(port);
+        let progress = Progress {
+            file: None,
+            transferred: 0,
+            total: None,
+        };
+        let result = async {
+            link.checksum = link.wait_start().await?;
+            let one_k = one_k && link.checksum == Checksum::Crc;
+            link.send_data(&mut data, one_k, progress).await
+        }
+        .await;
+        match result {
+            Ok(sent) => Ok(sent),
+            Err(err) => Err(link.abort(err).await),
+        }
+    }
+
+    /// Receive a file into `out`, returning the number of bytes received.
+    pub async fn receive<P, W>(&mut self, port: &mut P, mut out: W) -> Result<u64, TransferError>
+    where
+        P: AsyncSerialPort,
+        W: AsyncWrite + Unpin,
+    {
+        let mut link = self.link(port);
+        let request = match link.checksum {
+            Checksum::Crc => CRC_REQUEST,
+            Checksum::Sum => NAK,
+        };
+        let progress = Progress {
+            file: None,
+            transferred: 0,
+            total: None,
+        };
+        match link.recv_data(&mut out, request, false, progress).await {
+            Ok(received) => Ok(received),
+            Err(err) => Err(link.abort(err).await),
+        }
+    }
+}
+
+/// A YMODEM batch sender or receiver
+///
+/// Files are sent in 1 KiB blocks with CRCs, retried as with [`Xmodem`].
+#[derive(Debug)]
+pub struct Ymodem {
+    timeout: Duration,
+    retries: u32,
+    control: Control,
+}
+
+impl Default for Ymodem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ymodem {
+    /// A YMODEM sender or receiver.
+    pub fn new() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            retries: 10,
+            control: Control::default(),
+        }
+    }
+
+    /// Set how long to wait for the peer to answer.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how many times a block is sent or requested again before giving up.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Report the progress of transfers to `progress`.
+    pub fn progress<F>(mut self, progress: F) -> Self
+    where
+        F: FnMut(&Progress) + Send + 'static,
+    {
+        self.control.set_progress(progress);
+        self
+    }
+
+    /// Cancel transfers once `token` is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.control.set_cancellation(token);
+        self
+    }
+
+    fn link<'a, P>(&'a mut self, port: &'a mut P) -> Link<'a, P> {
+        Link {
+            port,
+            control: &mut self.control,
+            checksum: Checksum::Crc,
+            timeout: self.timeout,
+            retries: self.retries,
+        }
+    }
+
+    /// Send a batch of files, each with its [`FileInfo`].
+    ///
+    /// The data of each file is sent up to its size, or up to its end if the size
+    /// is unknown.
+    pub async fn send<P, R, I>(&mut self, port: &mut P, files: I) -> Result<(), TransferError>
+    where
+        P: AsyncSerialPort,
+        R: AsyncRead + Unpin,
+        I: IntoIterator<Item = (FileInfo, R)>,
+    {
+        let mut link = self.link(port);
+        let result = async {
+            for (info, data) in files {
+                let header = info.encode();
+                if link.wait_start().await? != Checksum::Crc {
+                    return Err(TransferError::Protocol("receiver does not use CRCs".into()));
+                }
+                let size = if header.len() > BLOCK {
+                    BLOCK_1K
+                } else {
+                    BLOCK
+                };
+                link.send_block(0, &header, size, 0).await?;
+                link.wait_start().await?;
+
+                let progress = Progress {
+                    file: Some(info.name.clone()),
+                    transferred: 0,
+                    total: info.size,
+                };
+                match info.size {
+                    Some(size) => {
+                        let mut data = tokio::io::AsyncReadExt::take(data, size);
+                        link.send_data(&mut data, true, progress).await?
+                    }
+                    None => {
+                        let mut data = data;
+                        link.send_data(&mut data, true, progress).await?
+                    }
+                };
+            }
+            // An empty block 0 ends the batch.
+            link.wait_start().await?;
+            link.send_block(0, &[], BLOCK, 0).await
+        }
+        .await;
+        match result {
+            Ok(()) => Ok(()),
+            Err(err) => Err(link.abort(err).await),
+        }
+    }
+
+    /// Receive a batch of files, writing each to the writer `open` returns for it.
+    ///
+    /// Returns what the sender told about the files received.
+    pub async fn receive<P, W, F>(
+        &mut self,
+        port: &mut P,
+        mut open: F,
+    ) -> Result<Vec<FileInfo>, TransferError>
+    where
+        P: AsyncSerialPort,
+        W: AsyncWrite + Unpin,
+        F: FnMut(&FileInfo) -> io::Result<W>,
+    {
+        let mut link = self.link(port);
+        let result = async {
+            let mut files = Vec::new();
+            while let Some(info) = link.recv_header().await? {
+                let mut out = open(&info)?;
+                let progress = Progress {
+                    file: Some(info.name.clone()),
+                    transferred: 0,
+                    total: info.size,
+                };
+                link.recv_data(&mut out, CRC_REQUEST, true, progress)
+                    .await?;
+                files.push(info);
+            }
+            Ok(files)
+        }
+        .await;
+        match result {
+            Ok(files) => Ok(files),
+            Err(err) => Err(link.abort(err).await),
+        }
+    }
+}
@@ -0,0 +1,834 @@
+//! ZMODEM
+//!
+//! ZMODEM streams files without waiting for acknowledgements: the receiver only
+//! answers when something went wrong, with a `ZRPOS` header giving the offset to
+//! resume from.  Frames start with a header, either in hex or ZDLE-escaped binary,
+//! and data follows in subpackets checked by a CRC-16 or, when both ends support
+//! it, a CRC-32.
+//!
+//! A session looks like:
+//!
+//! | sender | receiver |
+//! |--------|----------|
+//! | `ZRQINIT` | |
+//! | | `ZRINIT`, with the receiver capabilities |
+//! | `ZFILE` and a subpacket with the name and size | |
+//! | | `ZRPOS`, the offset to start from |
+//! | `ZDATA` and subpackets of data, then `ZEOF` | |
+//! | | `ZRINIT`, ready for the next file |
+//! | `ZFIN` | |
+//! | | `ZFIN` |
+//! | `OO` | |
+//!
+//! ```no_run
+//! # async fn example(mut port: tokio_serial::SerialStream) -> Result<(), tokio_serial::transfer::TransferError> {
+//! use tokio_serial::transfer::zmodem::Zmodem;
+//! use tokio_serial::transfer::FileInfo;
+//!
+//! let config = tokio::fs::File::open("startup-config").await?;
+//! let size = config.metadata().await?.len();
+//! Zmodem::new()
+//!     .send(&mut port, vec![(FileInfo::new("startup-config", size), config)])
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+use super::{CancellationToken, Control, FileInfo, Progress, TransferError};
+use crate::AsyncSerialPort;
+
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio_serial_core::crc::{CRC16_XMODEM, CRC32_ISO_HDLC};
+
+use futures::FutureExt;
+use std::io::{self, SeekFrom};
+use std::time::Duration;
+
+/// Starts headers
+pub const ZPAD: u8 = b'*';
+/// Escapes the next byte, and is CAN: five in a row cancel the session
+pub const ZDLE: u8 = 0x18;
+const ZBIN: u8 = b'A';
+const ZHEX: u8 = b'B';
+const ZBIN32: u8 = b'C';
+
+/// Ends a subpacket: the frame ends, a header follows
+pub const ZCRCE: u8 = b'h';
+/// Ends a subpacket: the frame continues, no answer expected
+pub const ZCRCG: u8 = b'i';
+/// Ends a subpacket: the frame continues, answer with `ZACK`
+pub const ZCRCQ: u8 = b'j';
+/// Ends a subpacket: the frame ends, answer with `ZACK`
+pub const ZCRCW: u8 = b'k';
+const ZRUB0: u8 = b'l';
+const ZRUB1: u8 = b'm';
+
+/// The sender asks the receiver to start
+pub const ZRQINIT: u8 = 0;
+/// The receiver is ready for a file
+pub const ZRINIT: u8 = 1;
+/// The sender sends session parameters
+pub const ZSINIT: u8 = 2;
+/// Acknowledges, with the current offset
+pub const ZACK: u8 = 3;
+/// The sender offers a file
+pub const ZFILE: u8 = 4;
+/// The receiver refuses the file offered
+pub const ZSKIP: u8 = 5;
+/// The last header was corrupted
+pub const ZNAK: u8 = 6;
+/// The receiver aborts the session
+pub const ZABORT: u8 = 7;
+/// Ends the session
+pub const ZFIN: u8 = 8;
+/// The receiver asks for data from the offset given
+pub const ZRPOS: u8 = 9;
+/// Data subpackets follow, starting at the offset given
+pub const ZDATA: u8 = 10;
+/// The file ends at the offset given
+pub const ZEOF: u8 = 11;
+/// The receiver failed to write the file
+pub const ZFERR: u8 = 12;
+/// The receiver checks the sender answers by echoing this header
+pub const ZCHALLENGE: u8 = 14;
+
+// ZRINIT capabilities, in ZF0
+const CANFDX: u8 = 0x01;
+const CANOVIO: u8 = 0x02;
+const CANFC32: u8 = 0x20;
+// ZFILE conversion option: binary transfer
+const ZCBIN: u8 = 1;
+
+const SUBPACKET: usize = 1024;
+// Longest subpacket accepted, as lrzsz accepts.
+const MAX_SUBPACKET: usize = 8192;
+// Garbage skipped looking for a header before reporting a timeout.
+const MAX_GARBAGE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Header {
+    kind: u8,
+    // ZP0 to ZP3, or ZF3 to ZF0.
+    data: [u8; 4],
+}
+
+impl Header {
+    fn new(kind: u8) -> Self {
+        Self { kind, data: [0; 4] }
+    }
+
+    fn at(kind: u8, position: u64) -> Self {
+        // Offsets wrap past 4 GiB, as they do in every implementation.
+        Self {
+            kind,
+            data: (position as u32).to_le_bytes(),
+        }
+    }
+
+    fn flags(kind: u8, zf0: u8) -> Self {
+        Self {
+            kind,
+            data: [0, 0, 0, zf0],
+        }
+    }
+
+    fn position(&self) -> u64 {
+        u64::from(u32::from_le_bytes(self.data))
+    }
+
+    fn zf0(&self) -> u8 {
+        self.data[3]
+    }
+
+    fn bytes(&self) -> [u8; 5] {
+        let d = self.data;
+        [self.kind, d[0], d[1], d[2], d[3]]
+    }
+
+    fn hex(&self) -> Vec<u8> {
+        let mut out = vec![ZPAD, ZPAD, ZDLE, ZHEX];
+        let bytes = self.bytes();
+        let crc = CRC16_XMODEM.checksum(&bytes).to_be_bytes();
+        for byte in bytes.iter().chain(crc.iter()) {
+            out.extend_from_slice(format!("{:02x}", byte).as_bytes());
+        }
+        out.extend_from_slice(b"\r\x8a");
+        if self.kind != ZACK && self.kind != ZFIN {
+            out.push(0x11);
+        }
+        out
+    }
+
+    fn binary(&self, crc32: bool) -> Vec<u8> {
+        let mut out = vec![ZPAD, ZDLE, if crc32 { ZBIN32 } else { ZBIN }];
+        let bytes = self.bytes();
+        escape(&bytes, &mut out);
+        if crc32 {
+            escape(&CRC32_ISO_HDLC.checksum(&bytes).to_le_bytes(), &mut out);
+        } else {
+            escape(&CRC16_XMODEM.checksum(&bytes).to_be_bytes(), &mut out);
+        }
+        out
+    }
+}
+
+fn escape(data: &[u8], out: &mut Vec<u8>) {
+    for &byte in data {
+        match byte {
+            ZDLE | 0x10 | 0x11 | 0x13 | 0x90 | 0x91 | 0x93 | 0x98 => {
+                out.push(ZDLE);
+                out.push(byte ^ 0x40);
+            }
+            _ => out.push(byte),
+        }
+    }
+}
+
+fn subpacket(data: &[u8], end: u8, crc32: bool, out: &mut Vec<u8>) {
+    escape(data, out);
+    out.push(ZDLE);
+    out.push(end);
+    if crc32 {
+        let mut digest = CRC32_ISO_HDLC.digest();
+        digest.update(data);
+        digest.update(&[end]);
+        escape(&digest.finish().to_le_bytes(), out);
+    } else {
+        let mut digest = CRC16_XMODEM.digest();
+        digest.update(data);
+        digest.update(&[end]);
+        escape(&digest.finish().to_be_bytes(), out);
+    }
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+enum Escaped {
+    Byte(u8),
+    End(u8),
+    Invalid,
+    Timeout,
+}
+
+#[derive(Debug)]
+enum Subpacket {
+    Data(Vec<u8>, u8),
+    Corrupt,
+    Timeout,
+}
+
+// One end of a session.
+struct Link<'a, P> {
+    port: BufReader<&'a mut P>,
+    control: &'a mut Control,
+    timeout: Duration,
+    retries: u32,
+    // Whether the subpackets following the last header carry CRC-32s.
+    crc32: bool,
+}
+
+impl<'a, P: AsyncSerialPort> Link<'a, P> {
+    async fn write(&mut self, data: &[u8]) -> Result<(), TransferError> {
+        self.control.write(&mut self.port, data).await
+    }
+
+    async fn abort(&mut self, err: TransferError) -> TransferError {
+        if !matches!(err, TransferError::CancelledByPeer | TransferError::Io(_)) {
+            let _ = self.port.write_all(&[ZDLE; 8]).await;
+            let _ = self.port.write_all(&[0x08; 8]).await;
+            let _ = self.port.flush().await;
+        }
+        err
+    }
+
+    async fn read_raw(&mut self) -> Result<Option<u8>, TransferError> {
+        loop {
+            match self.control.read_byte(&mut self.port, self.timeout).await? {
+                // Flow control, inserted by the link
+                Some(0x11) | Some(0x13) | Some(0x91) | Some(0x93) => {}
+                byte => return Ok(byte),
+            }
+        }
+    }
+
+    async fn read_escaped(&mut self) -> Result<Escaped, TransferError> {
+        match self.read_raw().await? {
+            Some(ZDLE) => {}
+            Some(byte) => return Ok(Escaped::Byte(byte)),
+            None => return Ok(Escaped::Timeout),
+        }
+        let mut cans = 1;
+        loop {
+            let byte = match self.read_raw().await? {
+                Some(byte) => byte,
+                None => return Ok(Escaped::Timeout),
+            };
+            if byte == ZDLE {
+                cans += 1;
+                if cans == 5 {
+                    return Err(TransferError::CancelledByPeer);
+                }
+                continue;
+            }
+            return Ok(match byte {
+                _ if cans > 1 => Escaped::Invalid,
+                ZCRCE | ZCRCG | ZCRCQ | ZCRCW => Escaped::End(byte),
+                ZRUB0 => Escaped::Byte(0x7f),
+                ZRUB1 => Escaped::Byte(0xff),
+                _ if byte & 0x60 == 0x40 => Escaped::Byte(byte ^ 0x40),
+                _ => Escaped::Invalid,
+            });
+        }
+    }
+
+    async fn read_escaped_bytes(&mut self, buf: &mut [u8]) -> Result<bool, TransferError> {
+        for slot in buf.iter_mut() {
+            match self.read_escaped().await? {
+                Escaped::Byte(byte) => *slot = byte,
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
+    /// The next valid header, `None` if none arrives in time.
+    async fn read_header(&mut self) -> Result<Option<Header>, TransferError> {
+        let mut skipped = 0;
+        let mut cans = 0;
+        'scan: while skipped < MAX_GARBAGE {
+            let byte = match self.read_raw().await? {
+                Some(byte) => byte,
+                None => return Ok(None),
+            };
+            skipped += 1;
+            if byte == ZDLE {
+                cans += 1;
+                if cans == 5 {
+                    return Err(TransferError::CancelledByPeer);
+                }
+            } else {
+                cans = 0;
+            }
+            if byte != ZPAD {
+                continue;
+            }
+            let format = loop {
+                match self.read_raw().await? {
+                    Some(ZPAD) => continue,
+                    Some(ZDLE) => match self.read_raw().await? {
+                        Some(format) => break format,
+                        None => return Ok(None),
+                    },
+                    Some(_) => continue 'scan,
+                    None => return Ok(None),
+                }
+            };
+            let header = match format {
+                ZHEX => self.read_hex_header().await?,
+                ZBIN => self.read_binary_header(false).await?,
+                ZBIN32 => self.read_binary_header(true).await?,
+                _ => None,
+            };
+            match header {
+                Some(header) => return Ok(Some(header)),
+                None => log::debug!("corrupted ZMODEM header"),
+            }
+        }
+        Ok(None)
+    }
+
+    async fn read_hex_header(&mut self) -> Result<Option<Header>, TransferError> {
+        let mut bytes = [0u8; 7];
+        for slot in bytes.iter_mut() {
+            let (high, low) = match (self.read_raw().await?, self.read_raw().await?) {
+                (Some(high), Some(low)) => (hex_value(high), hex_value(low)),
+                _ => return Ok(None),
+            };
+            match (high, low) {
+                (Some(high), Some(low)) => *slot = high << 4 | low,
+                _ => return Ok(None),
+            }
+        }
+        if CRC16_XMODEM.checksum(&bytes[..5]).to_be_bytes() != bytes[5..] {
+            return Ok(None);
+        }
+        self.crc32 = false;
+        Ok(Some(Header {
+            kind: bytes[0],
+            data: [bytes[1], bytes[2], bytes[3], bytes[4]],
+        }))
+    }
+
+    async fn read_binary_header(&mut self, crc32: bool) -> Result<Option<Header>, TransferError> {
+        let mut bytes = [0u8; 9];
+        let len = if crc32 { 9 } else { 7 };
+        if !self.read_escaped_bytes(&mut bytes[..len]).await? {
+            return Ok(None);
+        }
+        let valid = if crc32 {
+            CRC32_ISO_HDLC.checksum(&bytes[..5]).to_le_bytes() == bytes[5..9]
+        } else {
+            CRC16_XMODEM.checksum(&bytes[..5]).to_be_bytes() == bytes[5..7]
+        };
+        if !valid {
+            return Ok(None);
+        }
+        self.crc32 = crc32;
+        Ok(Some(Header {
+            kind: bytes[0],
+            data: [bytes[1], bytes[2], bytes[3], bytes[4]],
+        }))
+    }
+
+    async fn read_subpacket(&mut self) -> Result<Subpacket, TransferError> {
+        let mut data = Vec::with_capacity(SUBPACKET);
+        let end = loop {
+            match self.read_escaped().await? {
+                Escaped::Byte(byte) if data.len() < MAX_SUBPACKET => data.push(byte),
+                Escaped::End(end) => break end,
+                Escaped::Timeout => return Ok(Subpacket::Timeout),
+                Escaped::Byte(_) | Escaped::Invalid => return Ok(Subpacket::Corrupt),
+            }
+        };
+        let mut crc = [0u8; 4];
+        let crc = &mut crc[..if self.crc32 { 4 } else { 2 }];
+        if !self.read_escaped_bytes(crc).await? {
+            return Ok(Subpacket::Corrupt);
+        }
+        let valid = if self.crc32 {
+            let mut digest = CRC32_ISO_HDLC.digest();
+            digest.update(&data);
+            digest.update(&[end]);
+            digest.finish().to_le_bytes() == *crc
+        } else {
+            let mut digest = CRC16_XMODEM.digest();
+            digest.update(&data);
+            digest.update(&[end]);
+            digest.finish().to_be_bytes() == *crc
+        };
+        Ok(if valid {
+            Subpacket::Data(data, end)
+        } else {
+            Subpacket::Corrupt
+        })
+    }
+
+    /// Whether the receiver sent something, flow control and the ends of hex
+    /// headers aside.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        loop {
+            let pending = match self.port.fill_buf().now_or_never() {
+                Some(buf) => buf?,
+                None => return Ok(false),
+            };
+            match pending
+                .iter()
+                .position(|b| ![0x11, 0x13, 0x91, 0x93, b'\r', b'\n', 0x8a].contains(b))
+            {
+                Some(at) => {
+                    self.port.consume(at);
+                    return Ok(true);
+                }
+                None if pending.is_empty() => return Ok(false),
+                None => {
+                    let len = pending.len();
+                    self.port.consume(len);
+                }
+            }
+        }
+    }
+
+    async fn handshake(&mut self) -> Result<Header, TransferError> {
+        for _ in 0..=self.retries {
+            self.write(&Header::new(ZRQINIT).hex()).await?;
+            match self.read_header().await? {
+                Some(header) if header.kind == ZRINIT => return Ok(header),
+                Some(header) if header.kind == ZCHALLENGE => {
+                    let mut echo = Header::new(ZACK);
+                    echo.data = header.data;
+                    self.write(&echo.hex()).await?;
+                }
+                _ => {}
+            }
+        }
+        Err(TransferError::Timeout)
+    }
+
+    /// Offer a file, returning the offset to start from, `None` if it is skipped.
+    async fn offer(&mut self, info: &FileInfo, crc32: bool) -> Result<Option<u64>, TransferError> {
+        let mut frame = Header::flags(ZFILE, ZCBIN).binary(crc32);
+        subpacket(&info.encode(), ZCRCW, crc32, &mut frame);
+        for _ in 0..=self.retries {
+            self.write(&frame).await?;
+            // The receiver may still be answering our ZRQINIT, or the last ZEOF.
+            let mut stale = 1;
+            loop {
+                match self.read_header().await? {
+                    Some(header) if header.kind == ZRPOS => return Ok(Some(header.position())),
+                    Some(header) if header.kind == ZSKIP => return Ok(None),
+                    Some(header) if header.kind == ZABORT || header.kind == ZFERR => {
+                        return Err(TransferError::CancelledByPeer)
+                    }
+                    Some(header) if header.kind == ZRINIT && stale > 0 => stale -= 1,
+                    _ => break,
+                }
+            }
+        }
+        Err(TransferError::Timeout)
+    }
+
+    async fn send_file<R>(
+        &mut self,
+        data: &mut R,
+        mut position: u64,
+        crc32: bool,
+        mut progress: Progress,
+    ) -> Result<(), TransferError>
+    where
+        R: AsyncRead + AsyncSeek + Unpin,
+    {
+        let mut errors = 0;
+        let mut buf = vec![0u8; SUBPACKET];
+        'frame: loop {
+            self.control
+                .guard(data.seek(SeekFrom::Start(position)))
+                .await??;
+            let mut len = self
+                .control
+                .guard(super::read_full(data, &mut buf))
+                .await??;
+            if len > 0 {
+                self.write(&Header::at(ZDATA, position).binary(crc32))
+                    .await?;
+            }
+            while len > 0 {
+                let chunk = buf[..len].to_vec();
+                len = self
+                    .control
+                    .guard(super::read_full(data, &mut buf))
+                    .await??;
+                let end = if len == 0 { ZCRCE } else { ZCRCG };
+                let mut packet = Vec::with_capacity(chunk.len() + 16);
+                subpacket(&chunk, end, crc32, &mut packet);
+                self.write(&packet).await?;
+                position += chunk.len() as u64;
+                progress.transferred = position;
+                self.control.report(progress.clone());
+
+                if end == ZCRCG && self.interrupted()? {
+                    // The receiver only interrupts the stream for errors, it
+                    // ignores the subpackets in flight until our next header.
+                    match self.read_header().await? {
+                        Some(header) if header.kind == ZRPOS => {
+                            errors += 1;
+                            if errors > self.retries {
+                                return Err(TransferError::TooManyErrors);
+                            }
+                            log::debug!("resending from {}", header.position());
+                            position = header.position();
+                            continue 'frame;
+                        }
+                        Some(header) if header.kind == ZSKIP => return Ok(()),
+                        Some(header) if header.kind == ZABORT || header.kind == ZFERR => {
+                            return Err(TransferError::CancelledByPeer)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            for _ in 0..=self.retries {
+                self.write(&Header::at(ZEOF, position).binary(crc32))
+                    .await?;
+                match self.read_header().await? {
+                    Some(header) if header.kind == ZRINIT || header.kind == ZSKIP => return Ok(()),
+                    Some(header) if header.kind == ZRPOS => {
+                        errors += 1;
+                        if errors > self.retries {
+                            return Err(TransferError::TooManyErrors);
+                        }
+                        position = header.position();
+                        continue 'frame;
+                    }
+                    Some(header) if header.kind == ZABORT || header.kind == ZFERR => {
+                        return Err(TransferError::CancelledByPeer)
+                    }
+                    _ => {}
+                }
+            }
+            return Err(TransferError::Timeout);
+        }
+    }
+
+    async fn finish(&mut self) -> Result<(), TransferError> {
+        for _ in 0..=self.retries {
+            self.write(&Header::new(ZFIN).hex()).await?;
+            if let Some(header) = self.read_header().await? {
+                if header.kind == ZFIN {
+                    return self.write(b"OO").await;
+                }
+            }
+        }
+        Err(TransferError::Timeout)
+    }
+
+    async fn receive<W, F>(&mut self, open: &mut F) -> Result<Vec<FileInfo>, TransferError>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(&FileInfo) -> io::Result<W>,
+    {
+        let init = Header {
+            kind: ZRINIT,
+            data: [0, 0, 0, CANFDX | CANOVIO | CANFC32],
+        };
+        let mut files = Vec::new();
+        let mut current: Option<(FileInfo, W, Progress)> = None;
+        let mut errors = 0;
+
+        self.write(&init.hex()).await?;
+        loop {
+            let header = match self.read_header().await? {
+                Some(header) => header,
+                None => {
+                    errors += 1;
+                    if errors > self.retries {
+                        return Err(TransferError::Timeout);
+                    }
+                    match &current {
+                        Some((_, _, progress)) => {
+                            let position = progress.transferred;
+                            self.write(&Header::at(ZRPOS, position).hex()).await?
+                        }
+                        None => self.write(&init.hex()).await?,
+                    }
+                    continue;
+                }
+            };
+            match header.kind {
+                ZRQINIT => self.write(&init.hex()).await?,
+                ZSINIT => {
+                    let _ = self.read_subpacket().await?;
+                    self.write(&Header::new(ZACK).hex()).await?;
+                }
+                ZFILE => match self.read_subpacket().await? {
+                    // The sender did not get our ZRPOS
+                    Subpacket::Data(_, _) if matches!(&current, Some((_, _, progress)) if progress.transferred == 0) =>
+                    {
+                        self.write(&Header::at(ZRPOS, 0).hex()).await?;
+                    }
+                    Subpacket::Data(data, _) => {
+                        let info = FileInfo::decode(&data)
+                            .ok_or_else(|| TransferError::Protocol("invalid file header".into()))?;
+                        let out = open(&info)?;
+                        let progress = Progress {
+                            file: Some(info.name.clone()),
+                            transferred: 0,
+                            total: info.size,
+                        };
+                        current = Some((info, out, progress));
+                        self.write(&Header::at(ZRPOS, 0).hex()).await?;
+                    }
+                    _ => self.write(&Header::new(ZNAK).hex()).await?,
+                },
+                ZDATA => {
+                    let (_, out, progress) = match &mut current {
+                        Some(current) => current,
+                        None => {
+                            self.write(&init.hex()).await?;
+                            continue;
+                        }
+                    };
+                    if header.position() != progress.transferred {
+                        let position = progress.transferred;
+                        self.write(&Header::at(ZRPOS, position).hex()).await?;
+                        continue;
+                    }
+                    loop {
+                        match self.read_subpacket().await? {
+                            Subpacket::Data(data, end) => {
+                                self.control.guard(out.write_all(&data)).await??;
+                                progress.transferred += data.len() as u64;
+                                self.control.report(progress.clone());
+                                errors = 0;
+                                let position = progress.transferred;
+                                match end {
+                                    ZCRCW => {
+                                        self.write(&Header::at(ZACK, position).hex()).await?;
+                                        break;
+                                    }
+                                    ZCRCQ => self.write(&Header::at(ZACK, position).hex()).await?,
+                                    ZCRCG => {}
+                                    _ => break,
+                                }
+                            }
+                            _ => {
+                                errors += 1;
+                                if errors > self.retries {
+                                    return Err(TransferError::TooManyErrors);
+                                }
+                                let position = progress.transferred;
+                                log::debug!("corrupted subpacket, asking for {}", position);
+                                self.write(&Header::at(ZRPOS, position).hex()).await?;
+                                break;
+                            }
+                        }
+                    }
+                }
+                ZEOF => match current.take() {
+                    Some((info, mut out, progress))
+                        if header.position() == progress.transferred =>
+                    {
+                        self.control.guard(out.flush()).await??;
+                        files.push(info);
+                        self.write(&init.hex()).await?;
+                    }
+                    // A ZEOF for data we asked again
+                    other => current = other,
+                },
+                ZFIN => {
+                    self.write(&Header::new(ZFIN).hex()).await?;
+                    // The sender ends with "OO", which may never come.
+                    let mut over = [0u8; 2];
+                    let _ = self
+                        .control
+                        .read_exact(&mut self.port, &mut over, Duration::from_secs(1))
+                        .await;
+                    return Ok(files);
+                }
+                kind => log::debug!("ignoring ZMODEM header {}", kind),
+            }
+        }
+    }
+}
+
+/// A ZMODEM sender or receiver
+///
+/// The receiver asks again for data from the last good offset when a subpacket
+/// is corrupted or data stops flowing for the timeout, 10 seconds by default, up
+/// to 10 times in a row.  Files are sent and received in binary, sizes are sent
+/// along, and CRC-32s are used when the receiver supports them.
+#[derive(Debug)]
+pub struct Zmodem {
+    timeout: Duration,
+    retries: u32,
+    control: Control,
+}
+
+impl Default for Zmodem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Zmodem {
+    /// A ZMODEM sender or receiver.
+    pub fn new() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            retries: 10,
+            control: Control::default(),
+        }
+    }
+
+    /// Set how long to wait for the peer.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how many errors in a row are recovered from before giving up.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Report the progress of transfers to `progress`.
+    pub fn progress<F>(mut self, progress: F) -> Self
+    where
+        F: FnMut(&Progress) + Send + 'static,
+    {
+        self.control.set_progress(progress);
+        self
+    }
+
+    /// Cancel transfers once `token` is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.control.set_cancellation(token);
+        self
+    }
+
+    fn link<'a, P: AsyncSerialPort>(&'a mut self, port: &'a mut P) -> Link<'a, P> {
+        Link {
+            port: BufReader::new(port),
+            control: &mut self.control,
+            timeout: self.timeout,
+            retries: self.retries,
+            crc32: false,
+        }
+    }
+
+    /// Send files, each with its [`FileInfo`].
+    ///
+    /// The data is seekable so that it can be sent again from where the receiver
+    /// asks, after errors or to resume a transfer.  Files the receiver skips are
+    /// not reported as errors.
+    pub async fn send<P, R, I>(&mut self, port: &mut P, files: I) -> Result<(), TransferError>
+    where
+        P: AsyncSerialPort,
+        R: AsyncRead + AsyncSeek + Unpin,
+        I: IntoIterator<Item = (FileInfo, R)>,
+    {
+        let mut link = self.link(port);
+        let result = async {
+            let init = link.handshake().await?;
+            let crc32 = init.zf0() & CANFC32 != 0;
+            for (info, mut data) in files {
+                let position = match link.offer(&info, crc32).await? {
+                    Some(position) => position,
+                    None => continue,
+                };
+                let progress = Progress {
+                    file: Some(info.name.clone()),
+                    transferred: position,
+                    total: info.size,
+                };
+                link.send_file(&mut data, position, crc32, progress).await?;
+            }
+            link.finish().await
+        }
+        .await;
+        match result {
+            Ok(()) => Ok(()),
+            Err(err) => Err(link.abort(err).await),
+        }
+    }
+
+    /// Receive files, writing each to the writer `open` returns for it.
+    ///
+    /// Returns what the sender told about the files received.
+    pub async fn receive<P, W, F>(
+        &mut self,
+        port: &mut P,
+        mut open: F,
+    ) -> Result<Vec<FileInfo>, TransferError>
+    where
+        P: AsyncSerialPort,
+        W: AsyncWrite + Unpin,
+        F: FnMut(&FileInfo) -> io::Result<W>,
+    {
+        let mut link = self.link(port);
+        match link.receive(&mut open).await {
+            Ok(files) => Ok(files),
+            Err(err) => Err(link.abort(err).await),
+        }
+    }
+}
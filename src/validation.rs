@@ -0,0 +1,315 @@
+//! Checking settings against what a device supports before opening it
+//!
+//! [`PortConfig::validate`](crate::PortConfig::validate), or
+//! [`SerialPortBuilderExt::validate`](crate::SerialPortBuilderExt::validate), tells
+//! which of the requested settings a device cannot do, instead of an open failing
+//! with an OS error, or worse succeeding and garbling the line.
+//!
+//! What can be known depends on the platform:
+//!
+//! * On Windows the driver reports its capabilities (`COMMPROP`): maximum and
+//!   settable baud rates, data bits, stop bits, parity and flow control.
+//! * On Linux the baud rate is checked against the UART clock of on-board ports.
+//!   termios can set any other combination, USB adapters report nothing.
+//! * Elsewhere only settings that are never valid are reported.
+use crate::config::{data_bits_number, flow_control_name, parity_name, stop_bits_number};
+use crate::{DataBits, FlowControl, Parity, PortConfig, SerialSettings, StopBits};
+
+use std::fmt;
+
+/// A setting the device does not support
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// The baud rate cannot be set, `max` is the highest the device supports if known
+    BaudRate {
+        /// The baud rate of the settings
+        requested: u32,
+        /// The maximum baud rate of the device
+        max: Option<u32>,
+    },
+    /// The number of data bits cannot be set
+    DataBits(DataBits),
+    /// The parity cannot be set
+    Parity(Parity),
+    /// The number of stop bits cannot be set
+    StopBits(StopBits),
+    /// The stop bits are not supported with this number of data bits
+    StopBitsForDataBits(StopBits, DataBits),
+    /// The flow control cannot be set
+    FlowControl(FlowControl),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::BaudRate {
+                requested,
+                max: Some(max),
+            } => write!(
+                f,
+                "baud rate {} is not supported (at most {})",
+                requested, max
+            ),
+            Problem::BaudRate {
+                requested,
+                max: None,
+            } => write!(f, "baud rate {} is not supported", requested),
+            Problem::DataBits(data_bits) => write!(
+                f,
+                "{} data bits are not supported",
+                data_bits_number(*data_bits)
+            ),
+            Problem::Parity(parity) => {
+                write!(f, "{} parity is not supported", parity_name(*parity))
+            }
+            Problem::StopBits(stop_bits) => write!(
+                f,
+                "{} stop bits are not supported",
+                stop_bits_number(*stop_bits)
+            ),
+            Problem::StopBitsForDataBits(stop_bits, data_bits) => write!(
+                f,
+                "{} stop bits are not supported with {} data bits",
+                stop_bits_number(*stop_bits),
+                data_bits_number(*data_bits)
+            ),
+            Problem::FlowControl(flow_control) => write!(
+                f,
+                "{} flow control is not supported",
+                flow_control_name(*flow_control)
+            ),
+        }
+    }
+}
+
+/// What a device supports of the settings of a configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// The settings which were checked
+    pub settings: SerialSettings,
+    /// The maximum baud rate of the device, if it reports one
+    pub max_baud_rate: Option<u32>,
+    /// The settings the device does not support, empty if all are
+    pub problems: Vec<Problem>,
+}
+
+impl ValidationReport {
+    /// Whether the device supports all the settings.
+    pub fn is_supported(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Turn the report into an `InvalidInput` error listing the problems, if any.
+    pub fn into_result(self) -> crate::Result<SerialSettings> {
+        if self.is_supported() {
+            return Ok(self.settings);
+        }
+        let problems: Vec<_> = self.problems.iter().map(Problem::to_string).collect();
+        Err(crate::Error::new(
+            crate::ErrorKind::InvalidInput,
+            problems.join(", "),
+        ))
+    }
+}
+
+pub(crate) fn validate(config: &PortConfig) -> crate::Result<ValidationReport> {
+    let mut report = ValidationReport {
+        settings: config.settings,
+        max_baud_rate: None,
+        problems: Vec::new(),
+    };
+    platform::check(&config.path, &mut report)?;
+
+    if config.settings.baud_rate == 0 {
+        // The platform may have reported it already, as not settable.
+        report
+            .problems
+            .retain(|problem| !matches!(problem, Problem::BaudRate { .. }));
+        report.problems.insert(
+            0,
+            Problem::BaudRate {
+                requested: 0,
+                max: report.max_baud_rate,
+            },
+        );
+    }
+    Ok(report)
+}
+
+#[cfg(unix)]
+mod platform {
+    use super::{Problem, ValidationReport};
+
+    use std::path::Path;
+
+    pub(super) fn check(path: &str, report: &mut ValidationReport) -> crate::Result<()> {
+        let path = Path::new(path).canonicalize()?;
+        report.max_baud_rate = max_baud_rate(&path);
+        let requested = report.settings.baud_rate;
+        if report.max_baud_rate.is_some_and(|max| requested > max) {
+            report.problems.push(Problem::BaudRate {
+                requested,
+                max: report.max_baud_rate,
+            });
+        }
+        Ok(())
+    }
+
+    // 16550 compatible UARTs divide their clock by 16 at least.
+    #[cfg(target_os = "linux")]
+    fn max_baud_rate(path: &Path) -> Option<u32> {
+        let name = path.file_name()?.to_str()?;
+        let clock = std::fs::read_to_string(format!("/sys/class/tty/{}/uartclk", name)).ok()?;
+        Some(clock.trim().parse::<u32>().ok()? / 16).filter(|&max| max > 0)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn max_baud_rate(_path: &Path) -> Option<u32> {
+        None
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::{Problem, ValidationReport};
+    use crate::{DataBits, FlowControl, Parity, StopBits};
+
+    use std::io;
+    use windows_sys::Win32::Devices::Communication::{
+        GetCommProperties, COMMPROP, PARITY_EVEN, PARITY_NONE, PARITY_ODD, STOPBITS_10, STOPBITS_20,
+    };
+    use windows_sys::Win32::Foundation::{
+        CloseHandle, GENERIC_READ, GENERIC_WRITE, INVALID_HANDLE_VALUE,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{CreateFileW, OPEN_EXISTING};
+
+    // `dwSettableBaud` and `dwMaxBaud` bits from <winbase.h>
+    const BAUD_USER: u32 = 0x1000_0000;
+    const BAUD_RATES: &[(u32, u32)] = &[
+        (0x0000_0001, 75),
+        (0x0000_0002, 110),
+        (0x0000_0004, 134),
+        (0x0000_0008, 150),
+        (0x0000_0010, 300),
+        (0x0000_0020, 600),
+        (0x0000_0040, 1200),
+        (0x0000_0080, 1800),
+        (0x0000_0100, 2400),
+        (0x0000_0200, 4800),
+        (0x0000_0400, 7200),
+        (0x0000_0800, 9600),
+        (0x0000_1000, 14400),
+        (0x0000_2000, 19200),
+        (0x0000_4000, 38400),
+        (0x0000_8000, 56000),
+        (0x0004_0000, 57600),
+        (0x0002_0000, 115_200),
+        (0x0001_0000, 128_000),
+    ];
+    // `wSettableData` bits
+    const DATABITS_5: u16 = 0x0001;
+    const DATABITS_6: u16 = 0x0002;
+    const DATABITS_7: u16 = 0x0004;
+    const DATABITS_8: u16 = 0x0008;
+    // `dwProvCapabilities` bits
+    const PCF_RTSCTS: u32 = 0x0002;
+    const PCF_XONXOFF: u32 = 0x0010;
+
+    // Read the capabilities of the driver, the device is opened without changing
+    // any of its settings.
+    fn properties(path: &str) -> io::Result<COMMPROP> {
+        // Same as `serialport`, which opens `COM3` as `\\.\COM3`.
+        let prefix = if path.starts_with('\\') { "" } else { r"\\.\" };
+        let name: Vec<u16> = prefix
+            .encode_utf16()
+            .chain(path.encode_utf16())
+            .chain(Some(0))
+            .collect();
+        let handle = unsafe {
+            CreateFileW(
+                name.as_ptr(),
+                GENERIC_READ | GENERIC_WRITE,
+                0,
+                std::ptr::null(),
+                OPEN_EXISTING,
+                0,
+                std::ptr::null_mut(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        let mut properties = COMMPROP::default();
+        let ret = unsafe { GetCommProperties(handle, &mut properties) };
+        let err = io::Error::last_os_error();
+        unsafe { CloseHandle(handle) };
+        if ret == 0 {
+            return Err(err);
+        }
+        Ok(properties)
+    }
+
+    pub(super) fn check(path: &str, report: &mut ValidationReport) -> crate::Result<()> {
+        let properties = properties(path)?;
+        let s = report.settings;
+
+        if properties.dwMaxBaud != BAUD_USER {
+            report.max_baud_rate = BAUD_RATES
+                .iter()
+                .find(|&&(bit, _)| bit == properties.dwMaxBaud)
+                .map(|&(_, rate)| rate);
+        }
+        let settable = properties.dwSettableBaud & BAUD_USER != 0
+            || BAUD_RATES
+                .iter()
+                .any(|&(bit, rate)| rate == s.baud_rate && properties.dwSettableBaud & bit != 0);
+        if !settable || report.max_baud_rate.is_some_and(|max| s.baud_rate > max) {
+            report.problems.push(Problem::BaudRate {
+                requested: s.baud_rate,
+                max: report.max_baud_rate,
+            });
+        }
+
+        let data_bits = match s.data_bits {
+            DataBits::Five => DATABITS_5,
+            DataBits::Six => DATABITS_6,
+            DataBits::Seven => DATABITS_7,
+            DataBits::Eight => DATABITS_8,
+        };
+        if properties.wSettableData & data_bits == 0 {
+            report.problems.push(Problem::DataBits(s.data_bits));
+        }
+
+        let parity = match s.parity {
+            Parity::None => PARITY_NONE,
+            Parity::Odd => PARITY_ODD,
+            Parity::Even => PARITY_EVEN,
+        };
+        if properties.wSettableStopParity & parity == 0 {
+            report.problems.push(Problem::Parity(s.parity));
+        }
+
+        let stop_bits = match s.stop_bits {
+            StopBits::One => STOPBITS_10,
+            StopBits::Two => STOPBITS_20,
+        };
+        if properties.wSettableStopParity & stop_bits == 0 {
+            report.problems.push(Problem::StopBits(s.stop_bits));
+        } else if s.stop_bits == StopBits::Two && s.data_bits == DataBits::Five {
+            // `SetCommState` only takes 1.5 stop bits with 5 data bits.
+            report
+                .problems
+                .push(Problem::StopBitsForDataBits(s.stop_bits, s.data_bits));
+        }
+
+        let flow_control = match s.flow_control {
+            FlowControl::None => None,
+            FlowControl::Software => Some(PCF_XONXOFF),
+            FlowControl::Hardware => Some(PCF_RTSCTS),
+        };
+        if flow_control.is_some_and(|bit| properties.dwProvCapabilities & bit == 0) {
+            report.problems.push(Problem::FlowControl(s.flow_control));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+//! Applying line settings published by a configuration service
+use crate::{SerialSettings, SerialStream};
+
+use tokio::sync::watch;
+
+/// What became of settings received by a [`SettingsWatcher`]
+#[derive(Debug)]
+pub enum SettingsEvent {
+    /// The port now uses these settings
+    Applied(SerialSettings),
+    /// The port rejected these settings and kept its previous ones
+    Failed(SerialSettings, crate::Error),
+}
+
+/// Applies the settings of a `watch` channel to an open port as they change
+///
+/// Each new value waits for everything written so far to be transmitted, so bytes
+/// queued with the old settings are not garbled, then goes through
+/// [`SerialStream::apply_settings`], which changes all of the settings at once.
+/// Values equal to the current settings of the port are reported as applied without
+/// reconfiguring it.  Failures are returned, not logged, for the caller to report.
+///
+/// [`apply_next`](SettingsWatcher::apply_next) does both when the port has nothing
+/// else to do.  To keep reading the port meanwhile, wait for the settings with
+/// [`next`](SettingsWatcher::next) and [`apply`](SettingsWatcher::apply) them
+/// once nothing else borrows the port:
+///
+/// ```no_run
+/// use tokio::io::AsyncReadExt;
+/// use tokio::sync::watch;
+/// use tokio_serial::{SerialSettings, SerialStream, SettingsEvent, SettingsWatcher};
+///
+/// # async fn example(mut port: SerialStream, settings: watch::Receiver<SerialSettings>) {
+/// let mut watcher = SettingsWatcher::new(settings);
+/// let mut buf = [0u8; 256];
+/// loop {
+///     tokio::select! {
+///         Some(settings) = watcher.next() => {
+///             if let SettingsEvent::Failed(settings, e) = watcher.apply(&mut port, settings).await {
+///                 log::warn!("unable to apply {:?}: {}", settings, e);
+///             }
+///         }
+///         n = port.read(&mut buf) => { /* ... */ }
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SettingsWatcher {
+    settings: watch::Receiver<SerialSettings>,
+}
+
+impl SettingsWatcher {
+    /// Watch `settings`, the current value is returned by the first call to
+    /// [`next`](SettingsWatcher::next) or applied by the first call to
+    /// [`apply_next`](SettingsWatcher::apply_next).
+    pub fn new(mut settings: watch::Receiver<SerialSettings>) -> Self {
+        settings.mark_changed();
+        Self { settings }
+    }
+
+    /// Wait for new settings, without touching the port.
+    ///
+    /// Returns `None` once the sender is dropped.  Cancelling this future before it
+    /// completes loses nothing: the settings are only marked as seen when returned.
+    pub async fn next(&mut self) -> Option<SerialSettings> {
+        self.settings.changed().await.ok()?;
+        Some(*self.settings.borrow_and_update())
+    }
+
+    /// Apply `settings` to `port` once everything written so far is transmitted.
+    ///
+    /// Settings equal to the current ones of the port are reported as applied
+    /// without reconfiguring it.
+    pub async fn apply(&self, port: &mut SerialStream, settings: SerialSettings) -> SettingsEvent {
+        if port.settings().ok() == Some(settings) {
+            return SettingsEvent::Applied(settings);
+        }
+        let drained = port.wait_transmitted().await;
+        match drained.and_then(|_| port.apply_settings(&settings)) {
+            Ok(()) => {
+                log::debug!("applied {:?}", settings);
+                SettingsEvent::Applied(settings)
+            }
+            Err(e) => SettingsEvent::Failed(settings, e),
+        }
+    }
+
+    /// Wait for new settings and apply them to `port`.
+    ///
+    /// Returns `None` once the sender is dropped.  The port is borrowed until then,
+    /// use [`next`](SettingsWatcher::next) and [`apply`](SettingsWatcher::apply) to
+    /// use it meanwhile.
+    pub async fn apply_next(&mut self, port: &mut SerialStream) -> Option<SettingsEvent> {
+        let settings = self.next().await?;
+        Some(self.apply(port, settings).await)
+    }
+}
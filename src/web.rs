@@ -0,0 +1,340 @@
+//! WebSerial backend for `wasm32` targets
+//!
+//! Wraps a browser [`SerialPort`](web_sys::SerialPort) so it implements
+//! [`AsyncSerialPort`](crate::AsyncSerialPort).  Protocol and codec code written
+//! against the trait can then be shared between native applications and browser
+//! based tools.
+//!
+//! WebSerial is still an unstable API in `web-sys`, so building this backend
+//! requires passing `--cfg=web_sys_unstable_apis` to rustc, e.g. through
+//! `RUSTFLAGS`.
+//!
+//! Asynchronous browser APIs map onto the synchronous control methods of
+//! [`AsyncSerialPort`] as follows: control line changes are handed to the browser
+//! right away, their failures are reported by the next write, flush or
+//! [`WebSerialPort::signals_applied`], while changing the baud rate requires
+//! reopening the port with [`WebSerialPort::reopen`].
+use crate::{AsyncSerialPort, DataBits, FlowControl, Parity, StopBits};
+
+use futures::{future, ready};
+use js_sys::{Reflect, Uint8Array};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{ReadableStreamDefaultReader, SerialOptions, WritableStreamDefaultWriter};
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+fn js_error(err: JsValue) -> io::Error {
+    io::Error::other(format!("{:?}", err))
+}
+
+fn unsupported(description: &str) -> crate::Error {
+    crate::Error::new(
+        crate::ErrorKind::Io(io::ErrorKind::Unsupported),
+        description,
+    )
+}
+
+/// Ask the user to select a serial port.
+///
+/// Browsers only allow this in response to a user gesture, such as a click.
+pub async fn request_port() -> crate::Result<web_sys::SerialPort> {
+    let window = web_sys::window().ok_or_else(|| unsupported("No window available"))?;
+    let promise = window.navigator().serial().request_port();
+    JsFuture::from(promise)
+        .await
+        .map_err(|err| js_error(err).into())
+}
+
+/// Settings used to open a [`WebSerialPort`]
+///
+/// Mirrors `SerialPortBuilder` for the settings WebSerial supports.
+#[derive(Debug, Clone)]
+pub struct WebSerialBuilder {
+    port: web_sys::SerialPort,
+    baud_rate: u32,
+    data_bits: DataBits,
+    parity: Parity,
+    stop_bits: StopBits,
+    flow_control: FlowControl,
+}
+
+impl WebSerialBuilder {
+    /// Set the number of bits used to represent a character sent on the line
+    pub fn data_bits(mut self, data_bits: DataBits) -> Self {
+        self.data_bits = data_bits;
+        self
+    }
+
+    /// Set the type of parity to use for error checking
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    /// Set the number of bits to use to signal the end of a character
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    /// Set the type of signalling to use for controlling data transfer
+    ///
+    /// WebSerial only supports hardware flow control, `FlowControl::Software` fails
+    /// to open.
+    pub fn flow_control(mut self, flow_control: FlowControl) -> Self {
+        self.flow_control = flow_control;
+        self
+    }
+
+    fn options(&self) -> crate::Result<SerialOptions> {
+        let options = SerialOptions::new(self.baud_rate);
+        options.set_data_bits(u8::from(self.data_bits));
+        options.set_stop_bits(u8::from(self.stop_bits));
+        options.set_parity(match self.parity {
+            Parity::None => web_sys::ParityType::None,
+            Parity::Odd => web_sys::ParityType::Odd,
+            Parity::Even => web_sys::ParityType::Even,
+        });
+        options.set_flow_control(match self.flow_control {
+            FlowControl::None => web_sys::FlowControlType::None,
+            FlowControl::Hardware => web_sys::FlowControlType::Hardware,
+            FlowControl::Software => {
+                return Err(unsupported(
+                    "WebSerial does not support software flow control",
+                ))
+            }
+        });
+        Ok(options)
+    }
+
+    /// Open the port with the specified settings
+    pub async fn open(self) -> crate::Result<WebSerialPort> {
+        let options = self.options()?;
+        JsFuture::from(self.port.open(&options))
+            .await
+            .map_err(js_error)?;
+
+        let reader = ReadableStreamDefaultReader::new(&self.port.readable()).map_err(js_error)?;
+        let writer = WritableStreamDefaultWriter::new(&self.port.writable()).map_err(js_error)?;
+
+        Ok(WebSerialPort {
+            builder: self,
+            reader,
+            writer,
+            read: None,
+            rd: Vec::new(),
+            rd_pos: 0,
+            write: None,
+            signals: VecDeque::new(),
+            close: None,
+        })
+    }
+}
+
+/// A serial port opened through the browser's WebSerial API
+#[derive(Debug)]
+pub struct WebSerialPort {
+    builder: WebSerialBuilder,
+    reader: ReadableStreamDefaultReader,
+    writer: WritableStreamDefaultWriter,
+    // Pending `reader.read()`
+    read: Option<JsFuture>,
+    // Data of the last chunk not yet handed out
+    rd: Vec<u8>,
+    rd_pos: usize,
+    // Pending `writer.write()`
+    write: Option<JsFuture>,
+    // Pending `port.setSignals()`, in the order they were made
+    signals: VecDeque<JsFuture<js_sys::Undefined>>,
+    // Pending `writer.close()`
+    close: Option<JsFuture>,
+}
+
+impl WebSerialPort {
+    /// Start opening `port` at `baud_rate`, other settings default to 8N1.
+    pub fn builder(port: web_sys::SerialPort, baud_rate: u32) -> WebSerialBuilder {
+        WebSerialBuilder {
+            port,
+            baud_rate,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+        }
+    }
+
+    /// Returns the underlying browser port.
+    pub fn port(&self) -> &web_sys::SerialPort {
+        &self.builder.port
+    }
+
+    /// Close the port and open it again at `baud_rate`.
+    ///
+    /// Data still in flight is discarded.
+    pub async fn reopen(&mut self, baud_rate: u32) -> crate::Result<()> {
+        let _ = JsFuture::from(self.reader.cancel()).await;
+        self.reader.release_lock();
+        let _ = JsFuture::from(self.writer.close()).await;
+        self.writer.release_lock();
+        JsFuture::from(self.builder.port.close())
+            .await
+            .map_err(js_error)?;
+
+        let mut builder = self.builder.clone();
+        builder.baud_rate = baud_rate;
+        *self = builder.open().await?;
+        Ok(())
+    }
+
+    /// Wait until the browser applied the control line changes made so far.
+    ///
+    /// ## Errors
+    ///
+    /// * `Io` with the first change the browser rejected.
+    pub async fn signals_applied(&mut self) -> crate::Result<()> {
+        future::poll_fn(|cx| self.poll_signals(cx)).await?;
+        Ok(())
+    }
+
+    fn set_signals<F>(&mut self, set: F)
+    where
+        F: FnOnce(&web_sys::SerialOutputSignals),
+    {
+        let signals = web_sys::SerialOutputSignals::new();
+        set(&signals);
+        let promise = self.builder.port.set_signals_with_signals(&signals);
+        self.signals.push_back(JsFuture::from(promise));
+    }
+
+    /// Collect the results of control line changes, in order.
+    fn poll_signals(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while let Some(pending) = self.signals.front_mut() {
+            let result = ready!(Pin::new(pending).poll(cx));
+            self.signals.pop_front();
+            result.map_err(js_error)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_pending_write(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_signals(cx))?;
+        if let Some(write) = self.write.as_mut() {
+            let result = ready!(Pin::new(write).poll(cx));
+            self.write = None;
+            result.map_err(js_error)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for WebSerialPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.rd_pos < this.rd.len() {
+                let n = buf.remaining().min(this.rd.len() - this.rd_pos);
+                buf.put_slice(&this.rd[this.rd_pos..this.rd_pos + n]);
+                this.rd_pos += n;
+                return Poll::Ready(Ok(()));
+            }
+
+            let reader = &this.reader;
+            let read = this
+                .read
+                .get_or_insert_with(|| JsFuture::from(reader.read()));
+            let result = ready!(Pin::new(read).poll(cx));
+            this.read = None;
+
+            let chunk = result.map_err(js_error)?;
+            let done = Reflect::get(&chunk, &JsValue::from_str("done"))
+                .map_err(js_error)?
+                .as_bool()
+                .unwrap_or(false);
+            if done {
+                // The stream was closed, signal EOF.
+                return Poll::Ready(Ok(()));
+            }
+
+            let value = Reflect::get(&chunk, &JsValue::from_str("value")).map_err(js_error)?;
+            this.rd = value.dyn_into::<Uint8Array>().map_err(js_error)?.to_vec();
+            this.rd_pos = 0;
+        }
+    }
+}
+
+impl AsyncWrite for WebSerialPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending_write(cx))?;
+
+        let chunk = Uint8Array::from(buf);
+        this.write = Some(JsFuture::from(this.writer.write_with_chunk(&chunk)));
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending_write(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending_write(cx))?;
+
+        let writer = &this.writer;
+        let close = this
+            .close
+            .get_or_insert_with(|| JsFuture::from(writer.close()));
+        let result = ready!(Pin::new(close).poll(cx));
+        this.close = None;
+        Poll::Ready(result.map(|_| ()).map_err(js_error))
+    }
+}
+
+impl AsyncSerialPort for WebSerialPort {
+    /// WebSerial ports have no name, this returns the USB vendor and product ID
+    /// (`"vid:pid"` in hex) if the port has them.
+    fn port_name(&self) -> Option<String> {
+        let info = self.builder.port.get_info();
+        match (info.get_usb_vendor_id(), info.get_usb_product_id()) {
+            (Some(vid), Some(pid)) => Some(format!("{:04x}:{:04x}", vid, pid)),
+            _ => None,
+        }
+    }
+
+    /// Always fails, use [`WebSerialPort::reopen`] instead.
+    fn change_baud_rate(&mut self, _: u32) -> crate::Result<()> {
+        Err(unsupported(
+            "WebSerial ports have to be reopened to change the baud rate",
+        ))
+    }
+
+    fn set_dtr(&mut self, level: bool) -> crate::Result<()> {
+        self.set_signals(|signals| signals.set_data_terminal_ready(level));
+        Ok(())
+    }
+
+    fn set_rts(&mut self, level: bool) -> crate::Result<()> {
+        self.set_signals(|signals| signals.set_request_to_send(level));
+        Ok(())
+    }
+
+    fn set_break_condition(&mut self, asserted: bool) -> crate::Result<()> {
+        self.set_signals(|signals| signals.set_break(asserted));
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use bytes::BytesMut;
+use tokio_serial::codec::at::{AtCodec, AtEvent, AtResponse, AtResult};
+use tokio_util::codec::{Decoder, Encoder};
+
+fn decode_all(codec: &mut AtCodec, data: &[u8]) -> Vec<AtEvent> {
+    let mut src = BytesMut::from(data);
+    let mut events = Vec::new();
+    while let Some(event) = codec.decode(&mut src).unwrap() {
+        events.push(event);
+    }
+    events
+}
+
+fn urc(line: &str) -> AtEvent {
+    AtEvent::Urc(line.to_string())
+}
+
+#[test]
+fn responses_collect_lines_until_the_final_result() {
+    let mut codec = AtCodec::new();
+    let mut dst = BytesMut::new();
+    codec.encode("AT+CSQ", &mut dst).unwrap();
+    assert_eq!(&dst[..], b"AT+CSQ\r");
+    assert!(codec.is_pending());
+
+    assert_eq!(
+        decode_all(
+            &mut codec,
+            b"AT+CSQ\r\r\n+CSQ: 20,99\r\n\r\nRING\r\n\r\nOK\r\n"
+        ),
+        [
+            urc("RING"),
+            AtEvent::Response(AtResponse {
+                lines: vec!["+CSQ: 20,99".to_string()],
+                result: AtResult::Ok
+            })
+        ]
+    );
+    assert!(!codec.is_pending());
+
+    codec.encode("AT+CPIN?", &mut dst).unwrap();
+    assert_eq!(
+        decode_all(&mut codec, b"\r\n+CME ERROR: SIM not inserted\r\n"),
+        [AtEvent::Response(AtResponse {
+            lines: vec![],
+            result: AtResult::CmeError("SIM not inserted".to_string())
+        })]
+    );
+}
+
+#[test]
+fn urcs_are_told_apart_from_responses() {
+    let mut codec = AtCodec::new().urc("+QIURC:");
+    let mut dst = BytesMut::new();
+
+    // The response to the query shares the prefix of the URC.
+    codec.encode("AT+CREG?", &mut dst).unwrap();
+    assert_eq!(
+        decode_all(
+            &mut codec,
+            b"\r\n+CMTI: \"SM\",3\r\n+CREG: 0,1\r\n+QIURC: \"closed\",0\r\nOK\r\n"
+        ),
+        [
+            urc("+CMTI: \"SM\",3"),
+            urc("+QIURC: \"closed\",0"),
+            AtEvent::Response(AtResponse {
+                lines: vec!["+CREG: 0,1".to_string()],
+                result: AtResult::Ok
+            })
+        ]
+    );
+
+    // Without a command everything is unsolicited, but late results.
+    assert_eq!(
+        decode_all(&mut codec, b"\r\n+CREG: 5\r\nOK\r\nNO CARRIER\r\n"),
+        [urc("+CREG: 5"), urc("NO CARRIER")]
+    );
+
+    codec.encode("ATD+123456789;", &mut dst).unwrap();
+    codec.cancel();
+    assert_eq!(decode_all(&mut codec, b"\r\nBUSY\r\n"), [urc("BUSY")]);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn client_runs_commands_and_forwards_urcs() {
+    use futures::StreamExt;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_serial::codec::at::{AtClient, AtError};
+    use tokio_serial::SerialStream;
+
+    let (mut modem, port) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut client, mut urcs) = AtClient::new(port);
+    client.set_timeout(Duration::from_millis(200));
+
+    let device = tokio::spawn(async move {
+        let mut buf = [0u8; 7];
+        modem.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"AT+CSQ\r");
+        modem
+            .write_all(b"\r\n+CMTI: \"SM\",1\r\n+CSQ: 31,0\r\n\r\nOK\r\n")
+            .await
+            .unwrap();
+        modem
+    });
+
+    let response = client.command("AT+CSQ").await.unwrap();
+    assert_eq!(response.lines, ["+CSQ: 31,0"]);
+    assert!(response.is_ok());
+    assert_eq!(urcs.next().await.unwrap(), "+CMTI: \"SM\",1");
+
+    let mut modem = device.await.unwrap();
+    assert!(matches!(client.command("AT").await, Err(AtError::Timeout)));
+
+    modem.write_all(b"\r\nRING\r\n").await.unwrap();
+    tokio::select! {
+        closed = client.listen() => panic!("listen ended: {:?}", closed),
+        urc = urcs.next() => assert_eq!(urc.unwrap(), "RING"),
+    }
+}
+
+#[test]
+fn prompts_are_decoded_before_the_response() {
+    let mut codec = AtCodec::new();
+    let mut dst = BytesMut::new();
+    codec.encode("AT+CMGS=\"+123\"", &mut dst).unwrap();
+    assert_eq!(decode_all(&mut codec, b"\r\n> "), [AtEvent::Prompt]);
+    assert_eq!(
+        decode_all(&mut codec, b"\r\n+CMGS: 4\r\n\r\nOK\r\n"),
+        [AtEvent::Response(AtResponse {
+            lines: vec!["+CMGS: 4".to_string()],
+            result: AtResult::Ok
+        })]
+    );
+}
@@ -0,0 +1,70 @@
+#![cfg(unix)]
+
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::autobaud::AutobaudProbe;
+use tokio_serial::identify::Signature;
+use tokio_serial::{SerialPort, SerialStream};
+
+#[tokio::test]
+async fn settles_on_rate_answering_probe() {
+    let (mut device, mut port) = SerialStream::pair().expect("unable to open pty pair");
+    port.set_baud_rate(115_200).unwrap();
+
+    // The device answers garbage to the first probe, as if talked to at the wrong
+    // rate, and properly to the second.
+    let device = async move {
+        let mut buf = [0u8; 3];
+        device.read_exact(&mut buf).await.unwrap();
+        device.write_all(b"\xe3\x1c\x80\x07").await.unwrap();
+        device.read_exact(&mut buf).await.unwrap();
+        device.write_all(b"\r\nOK\r\n").await.unwrap();
+        device
+    };
+    let probe = AutobaudProbe::pattern(Signature::contains(&b"OK"[..]))
+        .command(&b"AT\r"[..])
+        .listen(Duration::from_millis(200));
+    let (detected, _device) =
+        futures::join!(port.detect_baud(&[9600, 19_200, 38_400], probe), device);
+
+    assert_eq!(detected.unwrap(), Some(19_200));
+    assert_eq!(port.baud_rate().unwrap(), 19_200);
+}
+
+#[tokio::test]
+async fn restores_rate_without_traffic() {
+    let (_device, mut port) = SerialStream::pair().expect("unable to open pty pair");
+    port.set_baud_rate(115_200).unwrap();
+
+    let probe = AutobaudProbe::printable(0.9).listen(Duration::from_millis(50));
+    assert_eq!(
+        port.detect_baud(&[9600, 19_200], probe).await.unwrap(),
+        None
+    );
+    assert_eq!(port.baud_rate().unwrap(), 115_200);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn restores_rate_on_errors() {
+    let (_device, mut port) = SerialStream::pair().expect("unable to open pty pair");
+    port.set_baud_rate(115_200).unwrap();
+
+    // Ptys keep no error counters.
+    let probe = AutobaudProbe::error_rate(0.1).listen(Duration::from_millis(50));
+    assert!(port.detect_baud(&[9600, 19_200], probe).await.is_err());
+    assert_eq!(port.baud_rate().unwrap(), 115_200);
+}
+
+#[tokio::test]
+async fn restores_rate_when_cancelled() {
+    let (_device, mut port) = SerialStream::pair().expect("unable to open pty pair");
+    port.set_baud_rate(115_200).unwrap();
+
+    let probe = AutobaudProbe::printable(0.9).listen(Duration::from_secs(10));
+    let detection = port.detect_baud(&[9600, 19_200], probe);
+    assert!(tokio::time::timeout(Duration::from_millis(50), detection)
+        .await
+        .is_err());
+    assert_eq!(port.baud_rate().unwrap(), 115_200);
+}
@@ -0,0 +1,99 @@
+#![cfg(unix)]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_serial::bridge::{BridgeServer, ClientPolicy};
+use tokio_serial::SerialStream;
+
+async fn serve(port: SerialStream, configure: impl FnOnce(&mut BridgeServer)) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut server = BridgeServer::from_listener(listener);
+    configure(&mut server);
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.serve(port));
+    addr
+}
+
+async fn closed(client: &mut TcpStream) -> bool {
+    let mut buf = [0u8; 1];
+    matches!(
+        tokio::time::timeout(Duration::from_secs(1), client.read(&mut buf)).await,
+        Ok(Ok(0))
+    )
+}
+
+#[tokio::test]
+async fn passes_data_both_ways() {
+    let (a, mut device) = SerialStream::pair().expect("unable to open pty pair");
+    let addr = serve(a, |_| {}).await;
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(b"hello").await.unwrap();
+    let mut buf = [0u8; 5];
+    device.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello");
+
+    device.write_all(b"world").await.unwrap();
+    client.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"world");
+
+    // The port is in use.
+    let mut other = TcpStream::connect(addr).await.unwrap();
+    assert!(closed(&mut other).await);
+}
+
+#[tokio::test]
+async fn new_clients_take_the_port_over() {
+    let (a, mut device) = SerialStream::pair().expect("unable to open pty pair");
+    let addr = serve(a, |server| server.set_policy(ClientPolicy::Takeover)).await;
+
+    let mut first = TcpStream::connect(addr).await.unwrap();
+    first.write_all(b"1").await.unwrap();
+    let mut buf = [0u8; 1];
+    device.read_exact(&mut buf).await.unwrap();
+
+    let mut second = TcpStream::connect(addr).await.unwrap();
+    assert!(closed(&mut first).await);
+    device.write_all(b"2").await.unwrap();
+    second.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"2");
+}
+
+#[tokio::test]
+async fn shared_clients_all_receive_the_data() {
+    let (a, mut device) = SerialStream::pair().expect("unable to open pty pair");
+    let addr = serve(a, |server| server.set_policy(ClientPolicy::Shared)).await;
+
+    let mut first = TcpStream::connect(addr).await.unwrap();
+    let mut second = TcpStream::connect(addr).await.unwrap();
+    second.write_all(b"x").await.unwrap();
+    let mut buf = [0u8; 1];
+    device.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"x");
+
+    device.write_all(b"y").await.unwrap();
+    first.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"y");
+    second.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"y");
+}
+
+#[tokio::test]
+async fn idle_clients_are_disconnected() {
+    let (a, mut device) = SerialStream::pair().expect("unable to open pty pair");
+    let addr = serve(a, |server| {
+        server.set_idle_timeout(Some(Duration::from_millis(100)))
+    })
+    .await;
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    assert!(closed(&mut client).await);
+    // The port is free again.
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(b"z").await.unwrap();
+    let mut buf = [0u8; 1];
+    device.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"z");
+}
@@ -0,0 +1,157 @@
+#![cfg(unix)]
+
+use futures::future::BoxFuture;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::bus::SharedBus;
+use tokio_serial::SerialStream;
+
+#[tokio::test]
+async fn exchanges_do_not_interleave() {
+    let (a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let device = tokio::spawn(async move {
+        for _ in 0..2 {
+            let mut request = [0u8; 4];
+            b.read_exact(&mut request).await.unwrap();
+            assert!(&request == b"AAAA" || &request == b"BBBB");
+            b.write_all(&request[..1]).await.unwrap();
+        }
+    });
+
+    let bus = SharedBus::new(a);
+    let drivers: Vec<_> = [b'A', b'B']
+        .iter()
+        .map(|&id| {
+            let bus = bus.clone();
+            tokio::spawn(async move {
+                bus.exchange(move |port| {
+                    Box::pin(async move {
+                        // Written in two halves, another exchange could slip in between.
+                        port.write_all(&[id; 2]).await?;
+                        tokio::task::yield_now().await;
+                        port.write_all(&[id; 2]).await?;
+                        let mut response = [0u8; 1];
+                        port.read_exact(&mut response).await?;
+                        Ok(response[0])
+                    })
+                })
+                .await
+            })
+        })
+        .collect();
+    for (driver, id) in drivers.into_iter().zip([b'A', b'B']) {
+        assert_eq!(driver.await.unwrap().unwrap(), id);
+    }
+    device.await.unwrap();
+}
+
+fn read_one(port: &mut SerialStream) -> BoxFuture<'_, std::io::Result<u8>> {
+    Box::pin(async move {
+        let mut response = [0u8; 1];
+        port.read_exact(&mut response).await?;
+        Ok(response[0])
+    })
+}
+
+#[tokio::test]
+async fn late_responses_are_discarded() {
+    let (a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let mut bus = SharedBus::new(a);
+    bus.set_timeout(Duration::from_millis(50));
+
+    let err = bus.exchange(read_one).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+
+    // The answer to the exchange which timed out
+    b.write_all(b"1").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let answer = bus.exchange(move |port| {
+        Box::pin(async move {
+            port.write_all(b"?").await?;
+            read_one(port).await
+        })
+    });
+    let device = async {
+        let mut request = [0u8; 1];
+        b.read_exact(&mut request).await.unwrap();
+        b.write_all(b"2").await.unwrap();
+    };
+    let (answer, ()) = futures::join!(answer, device);
+    assert_eq!(answer.unwrap(), b'2');
+}
+
+mod master {
+    use super::*;
+    use std::io;
+    use tokio_serial::bus::{BusMaster, BusProtocol, SlaveConfig, SlaveHealth};
+
+    // Requests are the address and a register, responses the address and its value.
+    struct Registers;
+
+    impl BusProtocol for Registers {
+        type Request = u8;
+        type Response = u8;
+
+        fn encode(&self, address: u8, register: &u8, out: &mut Vec<u8>) {
+            out.extend_from_slice(&[address, *register]);
+        }
+
+        fn decode(&self, address: u8, received: &[u8]) -> io::Result<Option<u8>> {
+            match received {
+                [from, ..] if *from != address => Err(io::ErrorKind::InvalidData.into()),
+                [_, value, ..] => Ok(Some(*value)),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn slaves_are_polled_by_address() {
+        let (a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+        // Slave 1 answers the register plus one, slave 2 is missing, slave 3
+        // answers as slave 4.
+        tokio::spawn(async move {
+            let mut request = [0u8; 2];
+            while b.read_exact(&mut request).await.is_ok() {
+                match request[0] {
+                    1 => b.write_all(&[1, request[1] + 1]).await.unwrap(),
+                    3 => b.write_all(&[4, 0]).await.unwrap(),
+                    _ => {}
+                }
+            }
+        });
+
+        let mut master = BusMaster::new(SharedBus::new(a), Registers);
+        master.set_offline_after(2);
+        master.configure_slave(
+            2,
+            SlaveConfig {
+                timeout: Duration::from_millis(50),
+                turnaround: Duration::from_millis(5),
+            },
+        );
+
+        assert_eq!(master.poll_slave(1, &41).await.unwrap(), 42);
+        assert_eq!(master.health(1), SlaveHealth::Online);
+        let stats = master.stats(1);
+        assert_eq!((stats.requests, stats.responses), (1, 1));
+        assert!(stats.last_latency.is_some());
+
+        assert_eq!(master.health(2), SlaveHealth::Unknown);
+        for _ in 0..2 {
+            let error = master.poll_slave(2, &0).await.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        }
+        assert_eq!(master.health(2), SlaveHealth::Offline);
+        assert_eq!(master.stats(2).timeouts, 2);
+
+        let error = master.poll_slave(3, &0).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(master.stats(3).errors, 1);
+        assert_eq!(master.health(3), SlaveHealth::Online);
+
+        // Slave 1 still answers after the others failed.
+        assert_eq!(master.poll_slave(1, &7).await.unwrap(), 8);
+        assert_eq!(master.all_stats().len(), 3);
+    }
+}
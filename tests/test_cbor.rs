@@ -0,0 +1,123 @@
+use bytes::BytesMut;
+use serde::{Deserialize, Serialize};
+use tokio_serial::codec::{CborCodec, CborError, LengthDelimitedCodec};
+use tokio_util::codec::{Decoder, Encoder};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Reading {
+    channel: u8,
+    value: i32,
+}
+
+#[test]
+fn values_are_self_delimited() {
+    let mut codec = CborCodec::<Reading>::new();
+    let mut dst = BytesMut::new();
+    codec
+        .encode(
+            Reading {
+                channel: 1,
+                value: -2,
+            },
+            &mut dst,
+        )
+        .unwrap();
+    assert_eq!(&dst[..], b"\xa2\x67channel\x01\x65value\x21");
+    codec
+        .encode(
+            Reading {
+                channel: 2,
+                value: 1000,
+            },
+            &mut dst,
+        )
+        .unwrap();
+
+    // Values are decoded once complete.
+    let mut src = BytesMut::new();
+    src.extend_from_slice(&dst[..10]);
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+    src.extend_from_slice(&dst[10..]);
+    assert_eq!(
+        codec.decode(&mut src).unwrap(),
+        Some(Reading {
+            channel: 1,
+            value: -2
+        })
+    );
+    assert_eq!(
+        codec.decode(&mut src).unwrap(),
+        Some(Reading {
+            channel: 2,
+            value: 1000
+        })
+    );
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+    assert!(src.is_empty());
+}
+
+#[test]
+fn bad_values_are_skipped() {
+    let mut codec = CborCodec::<Reading>::new();
+    let mut src = BytesMut::new();
+    // A well-formed value of the wrong type, then a reserved initial byte.
+    codec.encode("hello", &mut src).unwrap();
+    src.extend_from_slice(b"\xfc");
+    codec
+        .encode(
+            Reading {
+                channel: 3,
+                value: 0,
+            },
+            &mut src,
+        )
+        .unwrap();
+
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(CborError::Deserialize(_))
+    ));
+    assert!(matches!(codec.decode(&mut src), Err(CborError::Malformed)));
+    assert_eq!(
+        codec.decode(&mut src).unwrap(),
+        Some(Reading {
+            channel: 3,
+            value: 0
+        })
+    );
+
+    // An incomplete value over the limit.
+    let mut codec = CborCodec::<Vec<u8>>::new().max_length(8);
+    let mut src = BytesMut::from(&b"\x58\x20\x00\x00\x00\x00\x00\x00"[..]);
+    assert!(matches!(codec.decode(&mut src), Err(CborError::TooLong)));
+    assert!(src.is_empty());
+}
+
+#[test]
+fn values_can_be_length_delimited() {
+    let mut codec = CborCodec::<Reading>::new()
+        .length_delimited(LengthDelimitedCodec::new().length_field_length(1));
+    let mut dst = BytesMut::new();
+    codec
+        .encode(
+            &Reading {
+                channel: 1,
+                value: -2,
+            },
+            &mut dst,
+        )
+        .unwrap();
+    assert_eq!(&dst[..], b"\x11\xa2\x67channel\x01\x65value\x21");
+
+    // A frame with trailing garbage after its value only costs that frame.
+    let mut src = BytesMut::from(&b"\x02\x01\x01"[..]);
+    src.extend_from_slice(&dst);
+    assert!(matches!(codec.decode(&mut src), Err(CborError::Malformed)));
+    assert_eq!(
+        codec.decode(&mut src).unwrap(),
+        Some(Reading {
+            channel: 1,
+            value: -2
+        })
+    );
+}
@@ -0,0 +1,86 @@
+use bytes::BytesMut;
+use tokio_serial::codec::crc::{Crc8, CRC16_MODBUS, CRC32_ISO_HDLC};
+use tokio_serial::codec::slip::Slip;
+use tokio_serial::codec::{
+    Checksum, ChecksumCodec, ChecksumError, FrameCodec, FrameError, LengthDelimitedCodec,
+};
+use tokio_util::codec::{Decoder, Encoder};
+
+#[test]
+fn checksums_are_appended_and_checked() {
+    let mut codec = ChecksumCodec::new(LengthDelimitedCodec::new(), Checksum::Crc16(CRC16_MODBUS));
+    let mut dst = BytesMut::new();
+    codec.encode(&b"123456789"[..], &mut dst).unwrap();
+    // CRC-16/MODBUS of the check string is 0x4b37, sent little endian.
+    assert_eq!(&dst[..], b"\x00\x0b123456789\x37\x4b");
+
+    let mut src = dst.clone();
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), &b"123456789"[..]);
+
+    let mut codec = codec.big_endian();
+    let mut dst = BytesMut::new();
+    codec.encode(&b"123456789"[..], &mut dst).unwrap();
+    assert_eq!(&dst[11..], b"\x4b\x37");
+}
+
+#[test]
+fn corrupt_frames_are_reported() {
+    let mut codec = ChecksumCodec::new(
+        FrameCodec::new(Slip::new(vec![0; 64])),
+        Checksum::Crc32(CRC32_ISO_HDLC),
+    );
+    let mut src = BytesMut::new();
+    codec.encode(&b"first"[..], &mut src).unwrap();
+    codec.encode(&b"second"[..], &mut src).unwrap();
+    codec.encode(&b"third"[..], &mut src).unwrap();
+    // Corrupt the payload of the second frame.
+    let at = src.windows(6).position(|w| w == b"second").unwrap();
+    src[at] = b'S';
+
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), &b"first"[..]);
+    match codec.decode(&mut src) {
+        Err(FrameError::Frame(ChecksumError::Mismatch { expected, found })) => {
+            assert_eq!(found, CRC32_ISO_HDLC.checksum(b"second"));
+            assert_eq!(expected, CRC32_ISO_HDLC.checksum(b"Second"));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), &b"third"[..]);
+
+    // A frame shorter than the checksum.
+    let mut src = BytesMut::from(&b"\xc0ab\xc0"[..]);
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(FrameError::Frame(ChecksumError::TooShort(2)))
+    ));
+}
+
+#[test]
+fn simple_checksums() {
+    let data = b"\x01\x02\xff";
+    assert_eq!(Checksum::Sum8.compute(data), 0x02);
+    assert_eq!(Checksum::Sum8Complement.compute(data), 0xfe);
+    assert_eq!(Checksum::Xor8.compute(data), 0xfc);
+    // CRC-8/AUTOSAR, a polynomial without a predefined constant.
+    let autosar = Crc8 {
+        poly: 0x2f,
+        init: 0xff,
+        reflected: false,
+        xorout: 0xff,
+    };
+    assert_eq!(Checksum::Crc8(autosar).compute(b"123456789"), 0xdf);
+    assert_eq!(Checksum::Crc8(autosar).size(), 1);
+
+    let mut codec = ChecksumCodec::new(LengthDelimitedCodec::new(), Checksum::Sum8Complement);
+    let mut dst = BytesMut::new();
+    codec.encode(&data[..], &mut dst).unwrap();
+    assert_eq!(&dst[..], b"\x00\x04\x01\x02\xff\xfe");
+    dst[2] = 0;
+    assert!(matches!(
+        codec.decode(&mut dst),
+        Err(FrameError::Frame(ChecksumError::Mismatch {
+            expected: 0xff,
+            found: 0xfe
+        }))
+    ));
+}
@@ -0,0 +1,94 @@
+use bytes::BytesMut;
+use tokio_serial::codec::{Deframer, FrameCodec, FrameError, Framer};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Newline terminated frames of at most 8 bytes
+#[derive(Default)]
+struct Lines {
+    buf: [u8; 8],
+    len: usize,
+    overflow: bool,
+}
+
+#[derive(Debug, PartialEq)]
+struct TooLong;
+
+impl Deframer for Lines {
+    type Error = TooLong;
+
+    fn push(&mut self, byte: u8) -> Result<Option<&[u8]>, TooLong> {
+        if byte == b'\n' {
+            let (len, overflow) = (self.len, self.overflow);
+            self.reset();
+            return if overflow {
+                Err(TooLong)
+            } else {
+                Ok(Some(&self.buf[..len]))
+            };
+        }
+        if self.len == self.buf.len() {
+            self.overflow = true;
+        } else {
+            self.buf[self.len] = byte;
+            self.len += 1;
+        }
+        Ok(None)
+    }
+
+    fn reset(&mut self) {
+        self.len = 0;
+        self.overflow = false;
+    }
+}
+
+impl Framer for Lines {
+    type Error = TooLong;
+
+    fn frame<W: FnMut(&[u8])>(&mut self, payload: &[u8], mut write: W) -> Result<(), TooLong> {
+        if payload.len() > self.buf.len() {
+            return Err(TooLong);
+        }
+        write(payload);
+        write(b"\n");
+        Ok(())
+    }
+}
+
+#[test]
+fn decodes_frames_one_at_a_time() {
+    let mut codec = FrameCodec::new(Lines::default());
+    let mut src = BytesMut::from(&b"one\ntwo\nthr"[..]);
+
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), &b"one"[..]);
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), &b"two"[..]);
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+    assert!(src.is_empty());
+
+    src.extend_from_slice(b"ee\n");
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), &b"three"[..]);
+}
+
+#[test]
+fn frame_errors_resynchronize() {
+    let mut codec = FrameCodec::new(Lines::default());
+    let mut src = BytesMut::from(&b"much too long\nok\n"[..]);
+
+    match codec.decode(&mut src) {
+        Err(FrameError::Frame(TooLong)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), &b"ok"[..]);
+}
+
+#[test]
+fn encodes_payloads() {
+    let mut codec = FrameCodec::new(Lines::default());
+    let mut dst = BytesMut::new();
+
+    codec.encode(&b"ping"[..], &mut dst).unwrap();
+    assert_eq!(dst, &b"ping\n"[..]);
+    assert!(matches!(
+        codec.encode(&b"far too long"[..], &mut dst),
+        Err(FrameError::Frame(TooLong))
+    ));
+}
@@ -0,0 +1,134 @@
+use tokio_serial::{
+    DataBits, ErrorKind, FlowControl, Parity, PortConfig, SerialPortBuilder, SerialPortBuilderExt,
+    SerialSettings, StopBits,
+};
+
+#[test]
+fn urls_give_path_and_settings() {
+    let url: PortConfig = "serial:///dev/ttyUSB0?baud=115200&parity=even&flow=hardware"
+        .parse()
+        .unwrap();
+    assert_eq!(url.path, "/dev/ttyUSB0");
+    assert_eq!(
+        url.settings,
+        SerialSettings {
+            baud_rate: 115_200,
+            data_bits: DataBits::Eight,
+            parity: Parity::Even,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::Hardware,
+        }
+    );
+
+    let url: PortConfig = "serial://COM3?mode=7O2".parse().unwrap();
+    assert_eq!(url.path, "COM3");
+    assert_eq!(url.settings.baud_rate, 9600);
+    assert_eq!(url.settings.data_bits, DataBits::Seven);
+    assert_eq!(url.settings.parity, Parity::Odd);
+    assert_eq!(url.settings.stop_bits, StopBits::Two);
+}
+
+#[test]
+fn urls_round_trip_through_display() {
+    let url: PortConfig = "serial:///dev/ttyS0?baud=4800&data_bits=7&stop_bits=2&flow=xonxoff"
+        .parse()
+        .unwrap();
+    let text = url.to_string();
+    assert_eq!(text, "serial:///dev/ttyS0?baud=4800&mode=7N2&flow=software");
+    assert_eq!(text.parse::<PortConfig>().unwrap(), url);
+
+    assert_eq!(url.builder(), url.settings.builder("/dev/ttyS0"));
+}
+
+#[test]
+fn urls_are_percent_decoded() {
+    let url: PortConfig =
+        "serial:///dev/serial/by-id/usb-My%20Adapter%3Fv2-if00?baud=%31%32%30%30&mode=8n1"
+            .parse()
+            .unwrap();
+    assert_eq!(url.path, "/dev/serial/by-id/usb-My Adapter?v2-if00");
+    assert_eq!(url.settings, SerialSettings::new(1200));
+
+    let text = url.to_string();
+    assert_eq!(
+        text,
+        "serial:///dev/serial/by-id/usb-My%20Adapter%3Fv2-if00?baud=1200&mode=8N1&flow=none"
+    );
+    assert_eq!(text.parse::<PortConfig>().unwrap(), url);
+
+    let url: PortConfig = "serial://COM%C3%A9?baud=9600".parse().unwrap();
+    assert_eq!(url.path, "COM\u{e9}");
+    assert_eq!(url.to_string().parse::<PortConfig>().unwrap(), url);
+}
+
+#[test]
+fn invalid_urls_are_rejected() {
+    for url in [
+        "/dev/ttyUSB0",
+        "serial://",
+        "serial:///dev/ttyUSB0?baud=fast",
+        "serial:///dev/ttyUSB0?parity=mark",
+        "serial:///dev/ttyUSB0?mode=9N1",
+        "serial:///dev/ttyUSB0?speed=9600",
+        "serial:///dev/ttyUSB0?baud",
+        "serial:///dev/tty%",
+        "serial:///dev/tty%4",
+        "serial:///dev/tty%zz",
+        "serial:///dev/tty%FF",
+        "serial:///dev/ttyUSB0?baud=96%0",
+    ] {
+        let err = url.parse::<PortConfig>().expect_err(url);
+        assert_eq!(err.kind(), ErrorKind::InvalidInput, "{}", url);
+    }
+}
+
+#[test]
+fn configs_make_builders_of_their_settings() {
+    let config: PortConfig = r"serial://\\.\COM3?baud=4800&mode=7E2".parse().unwrap();
+    let builder = tokio_serial::new(r"\\.\COM3", 4800)
+        .data_bits(DataBits::Seven)
+        .parity(Parity::Even)
+        .stop_bits(StopBits::Two);
+    assert_eq!(config.builder(), builder);
+    assert_eq!(SerialPortBuilder::from(config), builder);
+    assert_eq!(
+        SerialPortBuilder::from_url(r"serial://\\.\COM3?baud=4800&mode=7E2").unwrap(),
+        builder
+    );
+    let err = SerialPortBuilder::from_url("serial:///dev/ttyUSB0?baud=fast").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn configs_are_read_from_the_environment() {
+    std::env::set_var("TEST_ENV_SERIAL_PORT", "/dev/ttyUSB3");
+    std::env::set_var("TEST_ENV_SERIAL_BAUD", "57600");
+    std::env::set_var("TEST_ENV_SERIAL_MODE", "7E1");
+    std::env::set_var("TEST_ENV_SERIAL_STOP_BITS", "2");
+    std::env::set_var("TEST_ENV_SERIAL_FLOW", "rtscts");
+    let config = PortConfig::from_env("TEST_ENV").unwrap();
+    assert_eq!(config.path, "/dev/ttyUSB3");
+    assert_eq!(
+        config.settings,
+        SerialSettings {
+            baud_rate: 57600,
+            data_bits: DataBits::Seven,
+            parity: Parity::Even,
+            stop_bits: StopBits::Two,
+            flow_control: FlowControl::Hardware,
+        }
+    );
+
+    let builder = SerialPortBuilder::from_env("TEST_ENV");
+    assert_eq!(builder.unwrap(), config.builder());
+
+    let err = PortConfig::from_env("TEST_ENV_UNSET").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(err.description, "TEST_ENV_UNSET_SERIAL_PORT is not set");
+
+    std::env::set_var("TEST_ENV_BAD_SERIAL_PORT", "/dev/ttyUSB3");
+    std::env::set_var("TEST_ENV_BAD_SERIAL_PARITY", "mark");
+    let err = PortConfig::from_env("TEST_ENV_BAD").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(err.description, "invalid TEST_ENV_BAD_SERIAL_PARITY `mark`");
+}
@@ -0,0 +1,55 @@
+use tokio_serial::{DataBits, FlowControl, Parity, PortConfig, SerialSettings, StopBits};
+
+#[test]
+fn configs_load_with_defaults() {
+    let config: PortConfig =
+        serde_json::from_str(r#"{"path": "/dev/ttyUSB0", "baud_rate": 115200, "parity": "even"}"#)
+            .unwrap();
+    assert_eq!(config.path, "/dev/ttyUSB0");
+    assert_eq!(
+        config.settings,
+        SerialSettings {
+            baud_rate: 115_200,
+            data_bits: DataBits::Eight,
+            parity: Parity::Even,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+        }
+    );
+}
+
+#[test]
+fn configs_round_trip() {
+    let config: PortConfig = "serial://COM3?baud=4800&mode=7O2&flow=hardware"
+        .parse()
+        .unwrap();
+    let json = serde_json::to_value(&config).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "path": "COM3",
+            "baud_rate": 4800,
+            "data_bits": 7,
+            "parity": "odd",
+            "stop_bits": 2,
+            "flow_control": "hardware",
+        })
+    );
+    assert_eq!(serde_json::from_value::<PortConfig>(json).unwrap(), config);
+}
+
+#[test]
+fn invalid_settings_are_rejected() {
+    for json in [
+        r#"{"path": "/dev/ttyS0"}"#,
+        r#"{"path": "/dev/ttyS0", "baud_rate": 9600, "data_bits": 9}"#,
+        r#"{"path": "/dev/ttyS0", "baud_rate": 9600, "parity": "mark"}"#,
+        r#"{"path": "/dev/ttyS0", "baud_rate": 9600, "flow_control": "dtr"}"#,
+    ] {
+        assert!(
+            serde_json::from_str::<PortConfig>(json).is_err(),
+            "{}",
+            json
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio_serial::console::{attach, Console, Exit, Newline};
+
+async fn read(stream: &mut DuplexStream, len: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; len];
+    tokio::time::timeout(Duration::from_secs(1), stream.read_exact(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    buf
+}
+
+#[tokio::test]
+async fn line_endings_are_translated() {
+    let (port, mut device) = duplex(64);
+    let (mut keyboard, input) = duplex(64);
+    let (output, mut screen) = duplex(64);
+    let console = Console::new()
+        .input_newline(Newline::CrLf)
+        .output_newline(Newline::CrLf);
+    let session = tokio::spawn(async move { console.attach(port, input, output).await });
+
+    keyboard.write_all(b"AT\r").await.unwrap();
+    assert_eq!(read(&mut device, 4).await, b"AT\r\n");
+    device.write_all(b"OK\n\r\nx").await.unwrap();
+    assert_eq!(read(&mut screen, 7).await, b"OK\r\n\r\nx");
+
+    keyboard.write_all(b"~.").await.unwrap();
+    assert_eq!(session.await.unwrap().unwrap(), Exit::Escape);
+}
+
+#[tokio::test]
+async fn escape_sequences_start_lines() {
+    let (port, mut device) = duplex(64);
+    let (mut keyboard, input) = duplex(64);
+    let (output, mut screen) = duplex(64);
+    let session = tokio::spawn(attach(port, input, output));
+
+    // Not at the start of a line, and not a command
+    keyboard.write_all(b"a~.\r~~\r~x").await.unwrap();
+    assert_eq!(read(&mut device, 8).await, b"a~.\r~\r~x");
+
+    // Local echo
+    keyboard.write_all(b"\r~e").await.unwrap();
+    keyboard.write_all(b"hi").await.unwrap();
+    assert_eq!(read(&mut device, 3).await, b"\rhi");
+    assert_eq!(read(&mut screen, 2).await, b"hi");
+
+    drop(keyboard);
+    assert_eq!(session.await.unwrap().unwrap(), Exit::InputClosed);
+}
+
+#[tokio::test]
+async fn sessions_end_with_the_port() {
+    let (port, device) = duplex(64);
+    let (_keyboard, input) = duplex(64);
+    let (output, _screen) = duplex(64);
+    let console = Console::new().escape(None);
+    let session = tokio::spawn(async move { console.attach(port, input, output).await });
+    drop(device);
+    assert_eq!(session.await.unwrap().unwrap(), Exit::PortClosed);
+}
@@ -0,0 +1,53 @@
+use tokio_serial::LineCounters;
+
+fn reading(rx: u32, frame: u32, parity: u32, breaks: u32) -> LineCounters {
+    LineCounters {
+        rx: Some(rx),
+        tx: None,
+        frame,
+        parity,
+        breaks,
+        ..LineCounters::default()
+    }
+}
+
+#[cfg(not(windows))]
+#[test]
+fn counters_since_an_earlier_reading_are_deltas() {
+    let earlier = reading(100, 2, 1, 0);
+    let now = reading(180, 5, 1, 3);
+
+    let delta = now.since(&earlier);
+    assert_eq!(delta, reading(80, 3, 0, 3));
+    assert_eq!(delta.errors(), 3);
+    // Totals the driver does not report stay unknown.
+    assert_eq!(delta.tx, None);
+    assert_eq!(now.since(&now), reading(0, 0, 0, 0));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn counter_deltas_survive_wrap_around() {
+    let earlier = reading(u32::MAX - 9, u32::MAX, 0, 0);
+    let now = reading(10, 1, 0, 0);
+    assert_eq!(now.since(&earlier), reading(20, 2, 0, 0));
+}
+
+#[cfg(windows)]
+#[test]
+fn windows_counters_are_already_deltas() {
+    let now = reading(0, 1, 0, 1);
+    assert_eq!(now.since(&reading(0, 1, 1, 0)), now);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[tokio::test]
+async fn ptys_keep_no_counters() {
+    let (_master, slave) = tokio_serial::SerialStream::pair().expect("unable to open pty pair");
+    let err = slave.counters().expect_err("a pty reported counters");
+    assert!(
+        matches!(err.kind(), tokio_serial::ErrorKind::Io(_)),
+        "unexpected error {:?}",
+        err
+    );
+}
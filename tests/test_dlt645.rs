@@ -0,0 +1,109 @@
+use bytes::{Bytes, BytesMut};
+use tokio_serial::codec::dlt645::{control, Address, Dlt645Error, Dlt645Frame};
+use tokio_serial::codec::Dlt645Codec;
+use tokio_util::codec::{Decoder, Encoder};
+
+#[test]
+fn addresses_are_bcd() {
+    let address = Address::from_number(123_456_789_012).unwrap();
+    assert_eq!(address.0, [0x12, 0x90, 0x78, 0x56, 0x34, 0x12]);
+    assert_eq!(address.to_string(), "123456789012");
+    assert_eq!(Address::from_number(1_000_000_000_000), None);
+
+    let abbreviated = Address([0x12, 0x90, 0xaa, 0xaa, 0xaa, 0xaa]);
+    assert!(abbreviated.matches(&address));
+    assert!(Address::WILDCARD.matches(&address));
+    assert!(!Address::from_number(1).unwrap().matches(&address));
+}
+
+#[test]
+fn frames_round_trip() {
+    let address = Address::from_number(123_456_789_012).unwrap();
+    let response = Dlt645Frame {
+        address,
+        control: control::READ_DATA | control::RESPONSE,
+        data: Bytes::from_static(&[0x00, 0x00, 0x01, 0x00, 0x45, 0x23, 0x01, 0x00]),
+    };
+    let mut codec = Dlt645Codec::new();
+    let mut buf = BytesMut::from(&b"\x00\x68\x12"[..]);
+    codec.encode(&response, &mut buf).unwrap();
+    assert_eq!(&buf[3..7], &[0xfe; 4]);
+    // The offset is added on the wire: 0x45 is sent as 0x78.
+    assert_eq!(buf[buf.len() - 6], 0x78);
+
+    // Noise and the preamble are skipped.
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(response));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn checksum_mismatches_are_reported() {
+    let address = Address::from_number(42).unwrap();
+    let mut codec = Dlt645Codec::new().preamble(0);
+    let mut buf = BytesMut::new();
+    codec
+        .encode(Dlt645Frame::read_data(address, 0x0201_0100), &mut buf)
+        .unwrap();
+    let sum = buf.len() - 2;
+    buf[sum] ^= 0x01;
+    codec
+        .encode(Dlt645Frame::read_data(address, 0x0201_0100), &mut buf)
+        .unwrap();
+
+    match codec.decode(&mut buf) {
+        Err(Dlt645Error::Checksum { .. }) => {}
+        other => panic!("expected a checksum mismatch, got {:?}", other),
+    }
+    let frame = codec.decode(&mut buf).unwrap().unwrap();
+    assert_eq!(frame.data, &[0x00, 0x01, 0x01, 0x02][..]);
+}
+
+#[cfg(unix)]
+mod client {
+    use super::*;
+    use futures::{SinkExt, StreamExt};
+    use tokio_serial::codec::dlt645::Dlt645Client;
+    use tokio_serial::{SerialFramed, SerialStream};
+
+    #[tokio::test]
+    async fn reads_data_of_an_abbreviated_address() {
+        let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+        let meter = tokio::spawn(async move {
+            let mut port = SerialFramed::new(b, Dlt645Codec::new().preamble(0));
+            let address = Address::from_number(123_456_789_012).unwrap();
+            for _ in 0..2 {
+                let request = port.next().await.unwrap().unwrap();
+                assert_eq!(request.control, control::READ_DATA);
+                assert!(request.address.matches(&address));
+                let data = if request.data[..] == [0x00, 0x00, 0x01, 0x00] {
+                    (
+                        control::READ_DATA | control::RESPONSE,
+                        &b"\x00\x00\x01\x00\x45\x23\x01\x00"[..],
+                    )
+                } else {
+                    // No such data
+                    (
+                        control::READ_DATA | control::RESPONSE | control::ABNORMAL,
+                        &b"\x02"[..],
+                    )
+                };
+                let response = Dlt645Frame {
+                    address,
+                    control: data.0,
+                    data: Bytes::from_static(data.1),
+                };
+                port.send(&response).await.unwrap();
+            }
+        });
+
+        let mut client = Dlt645Client::new(a);
+        let address = Address([0x12, 0x90, 0x78, 0xaa, 0xaa, 0xaa]);
+        let energy = client.read_data(address, 0x0001_0000).await.unwrap();
+        assert_eq!(energy, &[0x45, 0x23, 0x01, 0x00][..]);
+        match client.read_data(address, 0x0401_0001).await {
+            Err(Dlt645Error::Abnormal(0x02)) => {}
+            other => panic!("expected an abnormal response, got {:?}", other),
+        }
+        meter.await.unwrap();
+    }
+}
@@ -0,0 +1,57 @@
+#![cfg(unix)]
+
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio_serial::dmx::{DmxSender, NULL_START_CODE};
+use tokio_serial::{SerialStream, StopBits};
+
+#[tokio::test]
+async fn frames_carry_the_universe() {
+    let (a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let mut dmx = DmxSender::new(a).unwrap();
+    let settings = dmx.get_ref().settings().unwrap();
+    assert_eq!(settings.baud_rate, 250_000);
+    assert_eq!(settings.stop_bits, StopBits::Two);
+
+    dmx.set_channel(1, 255);
+    dmx.channels_mut()[511] = 0x80;
+    assert_eq!(dmx.channel(1), Some(255));
+    assert_eq!(dmx.channel(0), None);
+    dmx.send().await.unwrap();
+
+    let mut frame = [0u8; 513];
+    b.read_exact(&mut frame).await.unwrap();
+    assert_eq!(frame[0], NULL_START_CODE);
+    assert_eq!(frame[1], 255);
+    assert!(frame[2..512].iter().all(|&level| level == 0));
+    assert_eq!(frame[512], 0x80);
+}
+
+#[tokio::test]
+async fn frames_are_sent_at_the_frame_rate() {
+    let (a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let mut dmx = DmxSender::new(a).unwrap();
+    dmx.set_slots(4);
+    dmx.set_frame_rate(100.0);
+
+    let start = Instant::now();
+    for level in 1..=3 {
+        dmx.set_channel(4, level);
+        dmx.tick().await.unwrap();
+    }
+    // The first frame is sent at once, the next ones 10ms apart.
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    let mut frames = [0u8; 15];
+    b.read_exact(&mut frames).await.unwrap();
+    assert_eq!(frames, [0, 0, 0, 0, 1, 0, 0, 0, 0, 2, 0, 0, 0, 0, 3]);
+}
+
+#[tokio::test]
+#[should_panic(expected = "invalid DMX channel 5")]
+async fn channels_are_checked() {
+    let (a, _b) = SerialStream::pair().expect("unable to open pty pair");
+    let mut dmx = DmxSender::new(a).unwrap();
+    dmx.set_slots(4);
+    dmx.set_channel(5, 1);
+}
@@ -0,0 +1,74 @@
+#![cfg(unix)]
+
+use futures::SinkExt;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::escpos::{Align, Commands, Cut, Printer, PrinterError, Status};
+use tokio_serial::SerialStream;
+
+#[test]
+fn commands_encode() {
+    let commands = Commands::new()
+        .initialize()
+        .align(Align::Center)
+        .bold(true)
+        .line("Café")
+        .size(2, 1)
+        .feed(2)
+        .cut(Cut::Partial);
+    assert_eq!(
+        commands.as_bytes(),
+        b"\x1b@\x1ba\x01\x1bE\x01Caf?\n\x1d!\x10\x1bd\x02\x1dV\x01"
+    );
+}
+
+#[tokio::test]
+async fn status_requests_skip_automatic_status() {
+    let (a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let device = tokio::spawn(async move {
+        let mut requests = [0u8; 12];
+        b.read_exact(&mut requests).await.unwrap();
+        assert_eq!(
+            &requests,
+            b"\x10\x04\x01\x10\x04\x02\x10\x04\x03\x10\x04\x04"
+        );
+        // An automatic status back, then the answers: offline, cover open, out of
+        // paper.
+        b.write_all(&[0x10, 0x00, 0x00, 0x00, 0x1a, 0x36, 0x12, 0x72])
+            .await
+            .unwrap();
+        b
+    });
+
+    let mut printer = Printer::new(a);
+    let status = printer.status().await.unwrap();
+    device.await.unwrap();
+    assert!(status.cover_open);
+    assert!(status.paper_end);
+    assert!(!status.is_ready());
+    assert_eq!(printer.last_status(), Some(status));
+    assert_eq!(status.to_string(), "cover open, paper out, offline");
+}
+
+#[tokio::test]
+async fn printing_stops_while_out_of_paper() {
+    let (a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let mut printer = Printer::new(a);
+
+    // Offline, out of paper
+    b.write_all(&[0x18, 0x00, 0x0c, 0x00]).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    match printer.send(&b"receipt\n"[..]).await {
+        Err(PrinterError::NotReady(status)) => assert!(status.paper_end),
+        other => panic!("expected the printer not to be ready, got {:?}", other),
+    }
+
+    // Paper loaded
+    b.write_all(&[0x10, 0x00, 0x00, 0x00]).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    printer.flush().await.unwrap();
+    assert_eq!(printer.last_status(), Some(Status::default()));
+    let mut received = [0u8; 8];
+    b.read_exact(&mut received).await.unwrap();
+    assert_eq!(&received, b"receipt\n");
+}
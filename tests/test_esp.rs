@@ -0,0 +1,258 @@
+#![cfg(unix)]
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio_serial::transfer::esp::{command, Chip, EspLoader, FLASH_BLOCK_SIZE};
+use tokio_serial::transfer::TransferError;
+use tokio_serial::{AsyncSerialPort, SerialStream};
+
+// Records the control lines instead of setting them, ptys have none.
+struct Board {
+    port: SerialStream,
+    lines: Arc<Mutex<Vec<(&'static str, bool)>>>,
+}
+
+impl AsyncRead for Board {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.port).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Board {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.port).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.port).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.port).poll_shutdown(cx)
+    }
+}
+
+impl AsyncSerialPort for Board {
+    fn port_name(&self) -> Option<String> {
+        None
+    }
+
+    fn change_baud_rate(&mut self, baud_rate: u32) -> tokio_serial::Result<()> {
+        self.port.change_baud_rate(baud_rate)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> tokio_serial::Result<()> {
+        self.lines.lock().unwrap().push(("dtr", level));
+        Ok(())
+    }
+
+    fn set_rts(&mut self, level: bool) -> tokio_serial::Result<()> {
+        self.lines.lock().unwrap().push(("rts", level));
+        Ok(())
+    }
+
+    fn set_break_condition(&mut self, asserted: bool) -> tokio_serial::Result<()> {
+        self.port.set_break_condition(asserted)
+    }
+}
+
+async fn read_frame(port: &mut SerialStream) -> Option<Vec<u8>> {
+    let mut frame = Vec::new();
+    loop {
+        match port.read_u8().await.ok()? {
+            0xc0 if frame.is_empty() => {}
+            0xc0 => return Some(frame),
+            0xdb => match port.read_u8().await.ok()? {
+                0xdc => frame.push(0xc0),
+                0xdd => frame.push(0xdb),
+                other => panic!("invalid escape {:#04x}", other),
+            },
+            byte => frame.push(byte),
+        }
+    }
+}
+
+async fn answer(port: &mut SerialStream, command: u8, value: u32, status: &[u8]) {
+    let mut packet = vec![0x01, command];
+    packet.extend_from_slice(&(status.len() as u16).to_le_bytes());
+    packet.extend_from_slice(&value.to_le_bytes());
+    packet.extend_from_slice(status);
+    let mut frame = vec![0xc0];
+    for byte in packet {
+        match byte {
+            0xc0 => frame.extend_from_slice(&[0xdb, 0xdc]),
+            0xdb => frame.extend_from_slice(&[0xdb, 0xdd]),
+            byte => frame.push(byte),
+        }
+    }
+    frame.push(0xc0);
+    port.write_all(&frame).await.unwrap();
+}
+
+fn word(data: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([
+        data[4 * i],
+        data[4 * i + 1],
+        data[4 * i + 2],
+        data[4 * i + 3],
+    ])
+}
+
+#[derive(Debug, Default)]
+struct Flash {
+    data: Vec<u8>,
+    begins: Vec<Vec<u32>>,
+    attached: bool,
+    ended: Option<u32>,
+}
+
+// The ROM loader of an ESP32, or of an ESP8266 with its 2 status bytes.
+async fn rom(mut port: SerialStream, magic: u32, status: &'static [u8]) -> Flash {
+    let mut flash = Flash::default();
+    let mut offset = 0;
+    // Boot messages, at another baud rate on real chips.
+    port.write_all(b"ets Jun  8 2016 00:22:57\r\n\xc0garbage")
+        .await
+        .unwrap();
+    while let Some(packet) = read_frame(&mut port).await {
+        assert_eq!(packet[0], 0x00);
+        let (cmd, data) = (packet[1], &packet[8..]);
+        assert_eq!(
+            usize::from(u16::from_le_bytes([packet[2], packet[3]])),
+            data.len()
+        );
+        let mut value = 0;
+        match cmd {
+            command::SYNC => {
+                assert_eq!(&data[..4], [0x07, 0x07, 0x12, 0x20]);
+                for _ in 0..7 {
+                    answer(&mut port, cmd, 0, status).await;
+                }
+            }
+            command::READ_REG => {
+                assert_eq!(word(data, 0), 0x4000_1000);
+                value = magic;
+            }
+            command::SPI_ATTACH => flash.attached = true,
+            command::FLASH_BEGIN => {
+                let args: Vec<u32> = (0..data.len() / 4).map(|i| word(data, i)).collect();
+                offset = args[3] as usize;
+                flash.begins.push(args);
+            }
+            command::FLASH_DATA => {
+                let (size, seq) = (word(data, 0) as usize, word(data, 1) as usize);
+                let block = &data[16..];
+                assert_eq!(block.len(), size);
+                let checksum = block.iter().fold(0xef, |sum, &b| sum ^ b);
+                if u32::from(checksum) != word(&packet[4..8], 0) {
+                    answer(&mut port, cmd, 0, &[1, 0x07]).await;
+                    continue;
+                }
+                let at = offset + seq * size;
+                if flash.data.len() < at + size {
+                    flash.data.resize(at + size, 0xff);
+                }
+                flash.data[at..at + size].copy_from_slice(block);
+            }
+            command::FLASH_END => {
+                flash.ended = Some(word(data, 0));
+                answer(&mut port, cmd, 0, status).await;
+                return flash;
+            }
+            other => panic!("unexpected command {:#04x}", other),
+        }
+        if cmd != command::SYNC {
+            answer(&mut port, cmd, value, status).await;
+        }
+    }
+    flash
+}
+
+fn firmware(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 256) as u8 | 0xc0).collect()
+}
+
+#[tokio::test]
+async fn esp32_is_flashed() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let board = Board {
+        port: a,
+        lines: lines.clone(),
+    };
+    let chip = tokio::spawn(rom(b, 0x00f0_1d83, &[0, 0, 0, 0]));
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let recorder = progress.clone();
+    let mut esp = EspLoader::new(board)
+        .timeout(Duration::from_millis(500))
+        .progress(move |progress| recorder.lock().unwrap().push(progress.transferred));
+    assert_eq!(esp.connect().await.unwrap(), Chip::Esp32);
+    assert_eq!(
+        *lines.lock().unwrap(),
+        [
+            ("dtr", false),
+            ("rts", true),
+            ("dtr", true),
+            ("rts", false),
+            ("dtr", false)
+        ]
+    );
+
+    let image = firmware(2500);
+    esp.write_flash(0x1000, &image).await.unwrap();
+    esp.flash_end(true).await.unwrap();
+
+    let flash = chip.await.unwrap();
+    assert!(flash.attached);
+    assert_eq!(
+        flash.begins,
+        [vec![2500, 3, FLASH_BLOCK_SIZE as u32, 0x1000]]
+    );
+    assert_eq!(&flash.data[0x1000..0x1000 + 2500], &image[..]);
+    assert!(flash.data[0x1000 + 2500..].iter().all(|&b| b == 0xff));
+    assert_eq!(flash.ended, Some(0));
+    assert_eq!(*progress.lock().unwrap(), [1024, 2048, 2500]);
+}
+
+#[tokio::test]
+async fn esp8266_erase_size_is_worked_around() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let chip = tokio::spawn(rom(b, 0xfff0_c101, &[0, 0]));
+
+    let mut esp = EspLoader::new(a).timeout(Duration::from_millis(500));
+    esp.sync().await.unwrap();
+    assert_eq!(esp.detect_chip().await.unwrap(), Chip::Esp8266);
+    // 3 sectors from the start of a 64KiB block are erased twice by the ROM.
+    esp.write_flash(0, &firmware(3 * 4096)).await.unwrap();
+    esp.flash_end(false).await.unwrap();
+
+    let flash = chip.await.unwrap();
+    assert!(!flash.attached);
+    assert_eq!(
+        flash.begins,
+        [vec![2 * 4096, 12, FLASH_BLOCK_SIZE as u32, 0]]
+    );
+    assert_eq!(flash.ended, Some(1));
+}
+
+#[tokio::test]
+async fn silent_chips_fail_to_sync() {
+    let (a, _b) = SerialStream::pair().expect("unable to open pty pair");
+    let mut esp = EspLoader::new(a)
+        .timeout(Duration::from_millis(20))
+        .retries(2);
+    let err = esp.sync().await.unwrap_err();
+    assert!(matches!(err, TransferError::TooManyErrors), "{:?}", err);
+}
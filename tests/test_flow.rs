@@ -0,0 +1,52 @@
+#![cfg(unix)]
+
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::flow::{SoftwareFlowControl, XOFF, XON};
+use tokio_serial::{FlowControl, SerialPort, SerialStream};
+
+#[tokio::test]
+async fn xoff_holds_writes_until_xon() {
+    let (mut master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    // Keep the line discipline from acting on XON/XOFF itself.
+    slave.set_flow_control(FlowControl::None).unwrap();
+    master.set_flow_control(FlowControl::None).unwrap();
+    let mut port = SoftwareFlowControl::new(slave);
+    port.set_rx_watermarks(1, 4);
+
+    master.write_all(&[XOFF]).await.unwrap();
+    // Give the pty time to deliver it before writing.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let mut buf = [0u8; 3];
+    let write = async {
+        port.write_all(b"hi").await.unwrap();
+        port.flush().await.unwrap();
+    };
+    let remote = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), master.read(&mut buf))
+                .await
+                .is_err(),
+            "data written while paused"
+        );
+        // Overflowing the receive buffer makes the port send XOFF itself.
+        master.write_all(b"abcdef").await.unwrap();
+        master.write_all(&[XON]).await.unwrap();
+        master.read_exact(&mut buf).await.unwrap();
+    };
+    futures::join!(write, remote);
+
+    assert_eq!(buf, [XOFF, b'h', b'i']);
+    assert!(!port.tx_paused());
+    assert!(port.remote_paused());
+
+    let mut data = [0u8; 6];
+    port.read_exact(&mut data).await.unwrap();
+    assert_eq!(&data, b"abcdef");
+    assert!(!port.remote_paused());
+
+    let mut control = [0u8; 1];
+    master.read_exact(&mut control).await.unwrap();
+    assert_eq!(control, [XON]);
+}
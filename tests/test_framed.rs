@@ -0,0 +1,60 @@
+#![cfg(unix)]
+
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::{SerialFramed, SerialStream};
+use tokio_util::codec::LinesCodec;
+
+#[tokio::test]
+async fn frames_span_reads() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut framed = SerialFramed::new(slave, LinesCodec::new());
+
+    master.write_all(b"hel").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    master.write_all(b"lo\nworld\n").await.unwrap();
+
+    assert_eq!(framed.next().await.unwrap().unwrap(), "hello");
+    assert_eq!(framed.next().await.unwrap().unwrap(), "world");
+}
+
+#[tokio::test]
+async fn congestion_follows_watermarks() {
+    let (master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut framed = SerialFramed::new(master, LinesCodec::new());
+    framed.set_write_watermarks(4, 16);
+    let congestion = framed.congestion();
+
+    framed.feed("short".to_string()).await.unwrap();
+    assert!(!*congestion.borrow());
+
+    framed.feed("x".repeat(32)).await.unwrap();
+    assert!(*congestion.borrow());
+
+    // Readiness for the next frame drains the buffer to the low watermark.
+    framed.feed("end".to_string()).await.unwrap();
+    assert!(!*congestion.borrow());
+    SinkExt::<String>::flush(&mut framed).await.unwrap();
+    assert_eq!(framed.write_buffer_len(), 0);
+
+    let mut buf = vec![0u8; 6 + 33 + 4];
+    slave.read_exact(&mut buf).await.unwrap();
+    assert!(buf.starts_with(b"short\nxxx"));
+    assert!(buf.ends_with(b"x\nend\n"));
+}
+
+#[tokio::test]
+async fn ports_frame_themselves() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut lines = slave.lines();
+    let mut framed = master.framed_with_capacity(tokio_serial::codec::LinesCodec::new(16), 4);
+
+    framed.send("a longer line than the buffer").await.unwrap();
+    assert_eq!(
+        lines.next().await.unwrap().unwrap(),
+        "a longer line than the buffer"
+    );
+    lines.send("back").await.unwrap();
+    assert_eq!(framed.next().await.unwrap().unwrap(), "back");
+}
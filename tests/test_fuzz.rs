@@ -0,0 +1,117 @@
+use tokio::io::AsyncReadExt;
+use tokio_serial::codec::{Deframer, FrameCodec, Framer};
+use tokio_serial::fuzz::{self, Corpus, StreamFuzzer, SEEDS};
+
+/// Newline terminated frames of at most 8 bytes
+#[derive(Default)]
+struct Lines {
+    buf: [u8; 8],
+    len: usize,
+}
+
+#[derive(Debug)]
+struct Invalid;
+
+impl Deframer for Lines {
+    type Error = Invalid;
+
+    fn push(&mut self, byte: u8) -> Result<Option<&[u8]>, Invalid> {
+        if byte == b'\n' {
+            let len = self.len;
+            self.len = 0;
+            return Ok(Some(&self.buf[..len]));
+        }
+        if self.len == self.buf.len() {
+            self.len = 0;
+            return Err(Invalid);
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+        Ok(None)
+    }
+
+    fn reset(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Framer for Lines {
+    type Error = Invalid;
+
+    fn frame<W: FnMut(&[u8])>(&mut self, payload: &[u8], mut write: W) -> Result<(), Invalid> {
+        if payload.len() > self.buf.len() || payload.contains(&b'\n') {
+            return Err(Invalid);
+        }
+        write(payload);
+        write(b"\n");
+        Ok(())
+    }
+}
+
+#[test]
+fn decode_and_round_trip_seeds() {
+    let mut fuzzer = StreamFuzzer::new(7);
+    for seed in SEEDS {
+        fuzz::decode(&mut FrameCodec::new(Lines::default()), seed);
+        fuzz::round_trip(&mut Lines::default(), seed);
+        for _ in 0..50 {
+            let input = fuzzer.mutate(seed);
+            fuzz::decode(&mut FrameCodec::new(Lines::default()), &input);
+            fuzz::round_trip(&mut Lines::default(), &input);
+        }
+    }
+}
+
+#[test]
+fn seeds_are_frames_of_the_codecs() {
+    use bytes::BytesMut;
+    use tokio_serial::codec::{NmeaCodec, SlcanCodec, XbeeCodec};
+    use tokio_util::codec::Decoder;
+
+    let seed = |start: &[u8]| {
+        let seed = SEEDS.iter().find(|seed| seed.starts_with(start)).unwrap();
+        BytesMut::from(*seed)
+    };
+    assert!(NmeaCodec::new()
+        .decode(&mut seed(b"$GP"))
+        .unwrap()
+        .is_some());
+    assert!(XbeeCodec::new()
+        .decode(&mut seed(b"\x7e\x00"))
+        .unwrap()
+        .is_some());
+    assert!(SlcanCodec::new().decode(&mut seed(b"t")).unwrap().is_some());
+}
+
+#[test]
+fn mutations_are_reproducible() {
+    let (mut a, mut b) = (StreamFuzzer::new(42), StreamFuzzer::new(42));
+    for _ in 0..20 {
+        assert_eq!(a.mutate(b"hello\n"), b.mutate(b"hello\n"));
+    }
+}
+
+#[test]
+fn corpus_is_written_once_per_input() {
+    let dir = std::env::temp_dir().join(format!("tokio-serial-corpus-{}", std::process::id()));
+    let corpus = Corpus::new().seed(&b"extra"[..]).seed(&b"extra"[..]);
+    assert_eq!(corpus.inputs().len(), SEEDS.len() + 2);
+
+    corpus.write_to(&dir).unwrap();
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), SEEDS.len() + 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn stream_fuzzer_drives_handler() {
+    let mut fuzzer = StreamFuzzer::new(1).timeout(std::time::Duration::from_millis(10));
+    let report = fuzzer
+        .run(&[&b"one\ntwo\n"[..]], 20, |mut port| async move {
+            let mut buf = [0u8; 64];
+            // Never finishes, every iteration ends with the timeout.
+            while port.read(&mut buf).await.is_ok() {}
+        })
+        .await;
+    assert_eq!(report.iterations, 20);
+    assert_eq!(report.timeouts, 20);
+}
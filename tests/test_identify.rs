@@ -0,0 +1,54 @@
+#![cfg(unix)]
+
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::identify::{Identifier, Probe, Signature};
+use tokio_serial::SerialStream;
+
+/// Answer `ATI` like a modem would, ignore everything else.
+async fn fake_modem(mut port: SerialStream) {
+    let mut buf = [0u8; 64];
+    loop {
+        let n = match port.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        if buf[..n].starts_with(b"ATI") {
+            port.write_all(b"\r\nQuectel EC25\r\nOK\r\n").await.unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn identifies_first_matching_device() {
+    let (mut host, device) = SerialStream::pair().expect("unable to open pty pair");
+    tokio::spawn(fake_modem(device));
+
+    let timeout = Duration::from_millis(200);
+    let identifier = Identifier::new()
+        .device(
+            "sim800",
+            vec![
+                Probe::new(&b"AT+CGMM\r"[..], Signature::contains(&b"SIM800"[..])).timeout(timeout),
+            ],
+        )
+        .device(
+            "ec25",
+            vec![Probe::new(&b"ATI\r"[..], Signature::contains(&b"EC25"[..])).timeout(timeout)],
+        );
+
+    assert_eq!(identifier.identify(&mut host).await.unwrap(), Some(&"ec25"));
+}
+
+#[tokio::test]
+async fn unknown_device_is_none() {
+    let (mut host, _device) = SerialStream::pair().expect("unable to open pty pair");
+
+    let identifier = Identifier::new().device(
+        "silent",
+        vec![Probe::new(&b"?"[..], Signature::starts_with(&b"!"[..]))
+            .timeout(Duration::from_millis(100))],
+    );
+
+    assert_eq!(identifier.identify(&mut host).await.unwrap(), None);
+}
@@ -0,0 +1,101 @@
+#![cfg(unix)]
+
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::iec62056::{self, DataSet, Meter};
+use tokio_serial::{ErrorKind, SerialPort, SerialStream};
+
+const DATA: &[u8] = b"0.0.0(12345678)\r\n1.8.0(001234.5*kWh)\r\n0.9.1(123000)(0.5*h)\r\n!\r\n";
+
+// A meter answering one sign-on, the data message followed by `bcc`.
+async fn meter(mut port: SerialStream, baud_rate: u8, ack: u8, bcc: Option<u8>) {
+    let mut request = [0u8; 5];
+    port.read_exact(&mut request).await.unwrap();
+    assert_eq!(&request, b"/?!\r\n");
+    let mut identification = b"/ISK".to_vec();
+    identification.push(baud_rate);
+    identification.extend_from_slice(b"MT174-0001\r\n");
+    port.write_all(&identification).await.unwrap();
+
+    let mut acknowledgement = [0u8; 6];
+    port.read_exact(&mut acknowledgement).await.unwrap();
+    assert_eq!(
+        acknowledgement,
+        [iec62056::ACK, b'0', ack, b'0', b'\r', b'\n']
+    );
+
+    let mut message = vec![iec62056::STX];
+    message.extend_from_slice(DATA);
+    message.push(iec62056::ETX);
+    message.push(bcc.unwrap_or_else(|| iec62056::bcc(&message[1..])));
+    port.write_all(&message).await.unwrap();
+}
+
+fn data_set(address: &str, value: &str, unit: Option<&str>) -> DataSet {
+    DataSet {
+        address: address.to_owned(),
+        value: value.to_owned(),
+        unit: unit.map(str::to_owned),
+    }
+}
+
+#[test]
+fn baud_rate_codes() {
+    assert_eq!(iec62056::baud_rate(b'0'), Some(300));
+    assert_eq!(iec62056::baud_rate(b'5'), Some(9600));
+    assert_eq!(iec62056::baud_rate(b'6'), Some(19200));
+    assert_eq!(iec62056::baud_rate(b'7'), None);
+    // Mode B codes
+    assert_eq!(iec62056::baud_rate(b'E'), None);
+}
+
+#[tokio::test]
+async fn reads_out_at_the_offered_rate() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let device = tokio::spawn(meter(b, b'5', b'5', None));
+    let mut meter = Meter::new(a);
+    meter.set_reaction_time(Duration::from_millis(10));
+
+    let readout = meter.read_out().await.unwrap();
+    device.await.unwrap();
+    assert_eq!(readout.identification.manufacturer, "ISK");
+    assert_eq!(readout.identification.baud_rate, 9600);
+    assert_eq!(readout.identification.identification, "MT174-0001");
+    assert_eq!(
+        readout.data,
+        [
+            data_set("0.0.0", "12345678", None),
+            data_set("1.8.0", "001234.5", Some("kWh")),
+            data_set("0.9.1", "123000", None),
+            data_set("", "0.5", Some("h")),
+        ]
+    );
+    assert_eq!(meter.get_ref().baud_rate().unwrap(), 9600);
+}
+
+#[tokio::test]
+async fn caps_the_baud_rate() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let device = tokio::spawn(meter(b, b'6', b'3', None));
+    let mut meter = Meter::new(a);
+    meter.set_reaction_time(Duration::from_millis(10));
+    meter.set_max_baud_rate(4000);
+
+    meter.read_out().await.unwrap();
+    device.await.unwrap();
+    assert_eq!(meter.get_ref().baud_rate().unwrap(), 2400);
+}
+
+#[tokio::test]
+async fn rejects_block_check_mismatches() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let bcc = iec62056::bcc(DATA) ^ iec62056::ETX ^ 0x01;
+    let device = tokio::spawn(meter(b, b'5', b'5', Some(bcc)));
+    let mut meter = Meter::new(a);
+    meter.set_reaction_time(Duration::from_millis(10));
+
+    let err = meter.read_out().await.unwrap_err();
+    device.await.unwrap();
+    assert_eq!(err.kind(), ErrorKind::Io(io::ErrorKind::InvalidData));
+}
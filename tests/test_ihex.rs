@@ -0,0 +1,122 @@
+use bytes::{Bytes, BytesMut};
+use tokio_serial::codec::ihex::{IhexError, Record};
+use tokio_serial::codec::{FrameError, IhexCodec};
+use tokio_util::codec::{Decoder, Encoder};
+
+fn decode_all(codec: &mut IhexCodec, src: &mut BytesMut) -> Vec<Result<Record, IhexError>> {
+    let mut records = Vec::new();
+    loop {
+        match codec.decode(src) {
+            Ok(Some(record)) => records.push(Ok(record)),
+            Ok(None) => return records,
+            Err(FrameError::Frame(err)) => records.push(Err(err)),
+            Err(err) => panic!("unexpected {:?}", err),
+        }
+    }
+}
+
+fn data(address: u32, data: &[u8]) -> Record {
+    Record::Data {
+        address,
+        data: Bytes::copy_from_slice(data),
+    }
+}
+
+#[test]
+fn records_are_resolved_against_extended_addresses() {
+    let mut codec = IhexCodec::new();
+    let mut src = BytesMut::from(
+        &b":0300300002337A1E\r\n\
+           :020000021200EA\r\n\
+           :0100000055AA\n\
+           :020000040800F2\r\n\
+           :0100100055\
+           9A\r\n\
+           :0400000508000135B9\r\n\
+           :00000001FF\r\n\
+           \x79\x00"[..],
+    );
+    // Records whose lines break in the middle arrive whole.
+    let mut tail = src.split_off(80);
+    let mut records = decode_all(&mut codec, &mut src);
+    src.unsplit(tail.split());
+    records.extend(decode_all(&mut codec, &mut src));
+
+    assert_eq!(
+        records,
+        [
+            Ok(data(0x0030, &[0x02, 0x33, 0x7a])),
+            Ok(Record::ExtendedSegmentAddress(0x1200)),
+            Ok(data(0x12000, &[0x55])),
+            Ok(Record::ExtendedLinearAddress(0x0800)),
+            Ok(data(0x0800_0010, &[0x55])),
+            Ok(Record::StartLinearAddress(0x0800_0135)),
+            Ok(Record::EndOfFile),
+        ]
+    );
+    // What follows the file is left to the next codec.
+    assert!(codec.finished());
+    assert_eq!(&src[..], b"\x79\x00");
+}
+
+#[test]
+fn invalid_records_are_skipped() {
+    let mut codec = IhexCodec::new();
+    let mut src = BytesMut::from(&b"junk:0100000055AB\r\n:01000000GGAA\r\n:0100000055AA\r\n"[..]);
+    let records = decode_all(&mut codec, &mut src);
+    assert_eq!(
+        records,
+        [
+            Err(IhexError::InvalidCharacter(b'j')),
+            Err(IhexError::Checksum),
+            Err(IhexError::InvalidCharacter(b'G')),
+            Ok(data(0, &[0x55])),
+        ]
+    );
+
+    let mut src = BytesMut::from(&b":0100000655A4\r\n"[..]);
+    assert_eq!(
+        decode_all(&mut codec, &mut src),
+        [Err(IhexError::InvalidRecord(0x06))]
+    );
+}
+
+#[test]
+fn data_is_split_into_records() {
+    let mut codec = IhexCodec::new().bytes_per_record(4);
+    let mut dst = BytesMut::new();
+    let image: Vec<u8> = (1..=6).collect();
+    codec.encode(data(0x0001_fffe, &image), &mut dst).unwrap();
+    codec.encode(data(0x0002_0004, &[7]), &mut dst).unwrap();
+    codec.encode(Record::EndOfFile, &mut dst).unwrap();
+    assert_eq!(
+        &dst[..],
+        &b":020000040001F9\r\n\
+           :02FFFE000102FE\r\n\
+           :020000040002F8\r\n\
+           :0400000003040506EA\r\n\
+           :0100040007F4\r\n\
+           :00000001FF\r\n"[..]
+    );
+
+    let mut codec = IhexCodec::new();
+    let records = decode_all(&mut codec, &mut dst);
+    assert_eq!(
+        records,
+        [
+            Ok(Record::ExtendedLinearAddress(1)),
+            Ok(data(0x0001_fffe, &[1, 2])),
+            Ok(Record::ExtendedLinearAddress(2)),
+            Ok(data(0x0002_0000, &[3, 4, 5, 6])),
+            Ok(data(0x0002_0004, &[7])),
+            Ok(Record::EndOfFile),
+        ]
+    );
+
+    let mut codec = IhexCodec::new().bytes_per_record(0);
+    let err = codec.encode(data(0, &[1]), &mut dst).unwrap_err();
+    assert!(matches!(
+        err,
+        FrameError::Frame(IhexError::InvalidRecordLength(0))
+    ));
+}
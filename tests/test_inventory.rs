@@ -0,0 +1,454 @@
+use tokio_serial::inventory::{
+    EnumerationOptions, PortChange, PortDetails, PortInventory, PortKind, SortOrder, UsbFilter,
+};
+use tokio_serial::{SerialPortInfo, SerialPortType, UsbPortInfo};
+
+fn port(name: &str, port_type: SerialPortType) -> SerialPortInfo {
+    SerialPortInfo {
+        port_name: name.to_string(),
+        port_type,
+    }
+}
+
+#[test]
+fn first_update_adds_everything() {
+    let mut inventory = PortInventory::new();
+    assert!(inventory.last_refresh().is_none());
+
+    let ports = vec![
+        port("/dev/rfcomm0", SerialPortType::BluetoothPort),
+        port("/dev/ttyS0", SerialPortType::PciPort),
+    ];
+    let changes = inventory.update(ports.clone());
+    assert_eq!(changes.added, ports);
+    assert!(changes.removed.is_empty() && changes.changed.is_empty());
+    assert_eq!(inventory.ports(), &ports[..]);
+    assert!(inventory.last_refresh().is_some());
+}
+
+#[test]
+fn update_reports_differences() {
+    let mut inventory = PortInventory::new();
+    inventory.update(vec![
+        port("/dev/ttyS0", SerialPortType::PciPort),
+        port("/dev/ttyS1", SerialPortType::Unknown),
+        port("/dev/rfcomm0", SerialPortType::BluetoothPort),
+    ]);
+
+    let changes = inventory.update(vec![
+        port("/dev/ttyS0", SerialPortType::PciPort),
+        port("/dev/ttyS1", SerialPortType::PciPort),
+        port("/dev/ttyS2", SerialPortType::PciPort),
+    ]);
+    assert_eq!(
+        changes.added,
+        vec![port("/dev/ttyS2", SerialPortType::PciPort)]
+    );
+    assert_eq!(
+        changes.removed,
+        vec![port("/dev/rfcomm0", SerialPortType::BluetoothPort)]
+    );
+    assert_eq!(
+        changes.changed,
+        vec![PortChange {
+            old: port("/dev/ttyS1", SerialPortType::Unknown),
+            new: port("/dev/ttyS1", SerialPortType::PciPort),
+        }]
+    );
+
+    let unchanged = inventory.ports().to_vec();
+    assert!(inventory.update(unchanged).is_empty());
+    assert_eq!(
+        inventory.get("/dev/ttyS1").map(|p| &p.port_type),
+        Some(&SerialPortType::PciPort)
+    );
+}
+
+fn usb(name: &str, vid: u16, pid: u16) -> SerialPortInfo {
+    port(
+        name,
+        SerialPortType::UsbPort(UsbPortInfo {
+            vid,
+            pid,
+            serial_number: None,
+            manufacturer: None,
+            product: None,
+            interface: None,
+        }),
+    )
+}
+
+#[test]
+fn ports_are_sorted_by_name_by_default() {
+    let ports = vec![
+        port("/dev/ttyUSB10", SerialPortType::Unknown),
+        port("/dev/ttyS1", SerialPortType::PciPort),
+        port("/dev/ttyUSB2", SerialPortType::Unknown),
+    ];
+    let names: Vec<_> = EnumerationOptions::new()
+        .apply(ports.clone())
+        .into_iter()
+        .map(|p| p.port_name)
+        .collect();
+    assert_eq!(names, ["/dev/ttyS1", "/dev/ttyUSB2", "/dev/ttyUSB10"]);
+
+    let unsorted = EnumerationOptions::new()
+        .sort_by(SortOrder::Enumeration)
+        .apply(ports.clone());
+    assert_eq!(unsorted, ports);
+}
+
+#[test]
+fn options_filter_ports() {
+    let ports = vec![
+        usb("/dev/ttyUSB0", 0x0403, 0x6001),
+        usb("/dev/ttyUSB1", 0x10c4, 0xea60),
+        usb("/dev/ttyACM0", 0x0403, 0x6015),
+        port("/dev/ttyS0", SerialPortType::PciPort),
+        port("/dev/rfcomm0", SerialPortType::BluetoothPort),
+    ];
+
+    let ftdi = EnumerationOptions::new()
+        .usb_vendor(0x0403)
+        .sort_by(SortOrder::UsbId)
+        .apply(ports.clone());
+    assert_eq!(
+        ftdi,
+        vec![
+            usb("/dev/ttyUSB0", 0x0403, 0x6001),
+            usb("/dev/ttyACM0", 0x0403, 0x6015),
+        ]
+    );
+
+    let cp210x = EnumerationOptions::new()
+        .usb_id(0x10c4, 0xea60)
+        .apply(ports.clone());
+    assert_eq!(cp210x, vec![usb("/dev/ttyUSB1", 0x10c4, 0xea60)]);
+
+    let kinds: Vec<_> = EnumerationOptions::new()
+        .kind(PortKind::Bluetooth)
+        .kind(PortKind::Pci)
+        .sort_by(SortOrder::Kind)
+        .apply(ports.clone())
+        .into_iter()
+        .map(|p| p.port_name)
+        .collect();
+    assert_eq!(kinds, ["/dev/ttyS0", "/dev/rfcomm0"]);
+
+    let mut inventory = PortInventory::with_options(EnumerationOptions::new().kind(PortKind::Usb));
+    assert_eq!(inventory.update(ports).added.len(), 3);
+}
+
+#[cfg(feature = "rt")]
+#[tokio::test]
+async fn async_enumeration_matches_blocking() {
+    let ports = tokio_serial::available_ports_async().await.unwrap();
+    let mut expected = tokio_serial::available_ports().unwrap();
+    let mut names: Vec<_> = ports.into_iter().map(|p| p.port_name).collect();
+    names.sort();
+    expected.sort_by(|a, b| a.port_name.cmp(&b.port_name));
+    let expected: Vec<_> = expected.into_iter().map(|p| p.port_name).collect();
+    assert_eq!(names, expected);
+}
+
+#[cfg(any(target_os = "linux", windows))]
+#[tokio::test]
+async fn watching_ports_reports_nothing_without_hotplug() {
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    let mut events = tokio_serial::inventory::watch_ports().expect("unable to watch ports");
+    let event = tokio::time::timeout(Duration::from_millis(100), events.next()).await;
+    assert!(event.is_err(), "unexpected event {:?}", event);
+}
+
+// A message as sent by udev: `struct udev_monitor_netlink_header` followed by the
+// NUL separated properties.
+#[cfg(target_os = "linux")]
+fn udev_message(properties: &[&str]) -> Vec<u8> {
+    let properties: Vec<u8> = properties
+        .iter()
+        .flat_map(|property| property.bytes().chain(Some(0)))
+        .collect();
+    let mut message = b"libudev\0".to_vec();
+    message.extend_from_slice(&0xfeed_cafeu32.to_be_bytes());
+    // Header size, then the offset and length of the properties.
+    message.extend_from_slice(&40u32.to_ne_bytes());
+    message.extend_from_slice(&40u32.to_ne_bytes());
+    message.extend_from_slice(&(properties.len() as u32).to_ne_bytes());
+    // Filter hashes and tag bloom filter.
+    message.extend_from_slice(&[0; 16]);
+    message.extend_from_slice(&properties);
+    message
+}
+
+#[cfg(target_os = "linux")]
+const FTDI_ADDED: &[&str] = &[
+    "ACTION=add",
+    "DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/ttyUSB0/tty/ttyUSB0",
+    "SUBSYSTEM=tty",
+    "DEVNAME=/dev/ttyUSB0",
+    "ID_BUS=usb",
+    "ID_VENDOR_ID=0403",
+    "ID_MODEL_ID=6001",
+    "ID_SERIAL_SHORT=A601EXYZ",
+    "ID_VENDOR=FTDI",
+    "ID_MODEL=FT232R_USB_UART",
+    "ID_USB_INTERFACE_NUM=00",
+];
+
+#[cfg(target_os = "linux")]
+#[test]
+fn udev_messages_are_decoded() {
+    use tokio_serial::inventory::PortEvent;
+
+    let event = PortEvent::from_udev_message(&udev_message(FTDI_ADDED));
+    let expected = SerialPortInfo {
+        port_name: "/dev/ttyUSB0".into(),
+        port_type: SerialPortType::UsbPort(UsbPortInfo {
+            vid: 0x0403,
+            pid: 0x6001,
+            serial_number: Some("A601EXYZ".into()),
+            manufacturer: Some("FTDI".into()),
+            product: Some("FT232R USB UART".into()),
+            interface: Some(0),
+        }),
+    };
+    assert_eq!(event, Some(PortEvent::Added(expected.clone())));
+
+    let mut removed = FTDI_ADDED.to_vec();
+    removed[0] = "ACTION=remove";
+    let event = PortEvent::from_udev_message(&udev_message(&removed));
+    assert_eq!(event, Some(PortEvent::Removed(expected)));
+
+    // Changes of other devices, and of virtual terminals, are no port events.
+    let mut block = FTDI_ADDED.to_vec();
+    block[2] = "SUBSYSTEM=block";
+    assert_eq!(PortEvent::from_udev_message(&udev_message(&block)), None);
+    let console = [
+        "ACTION=add",
+        "DEVPATH=/devices/virtual/tty/tty1",
+        "SUBSYSTEM=tty",
+        "DEVNAME=/dev/tty1",
+    ];
+    assert_eq!(PortEvent::from_udev_message(&udev_message(&console)), None);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn malformed_udev_messages_are_ignored() {
+    use tokio_serial::inventory::PortEvent;
+
+    let message = udev_message(FTDI_ADDED);
+    for len in [0, 7, 12, 20, 24, message.len() - 1] {
+        assert_eq!(
+            PortEvent::from_udev_message(&message[..len]),
+            None,
+            "decoded a message truncated to {} bytes",
+            len
+        );
+    }
+
+    let mut bad_magic = message.clone();
+    bad_magic[8..12].copy_from_slice(&0xcafe_feedu32.to_be_bytes());
+    assert_eq!(PortEvent::from_udev_message(&bad_magic), None);
+
+    // The kernel's own messages, sent before udev ran its rules.
+    let mut kernel = b"add@/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/ttyUSB0\0".to_vec();
+    kernel.extend_from_slice(&message[40..]);
+    assert_eq!(PortEvent::from_udev_message(&kernel), None);
+
+    // Properties reaching past the end of the message.
+    let mut overlong = message;
+    overlong[20..24].copy_from_slice(&u32::MAX.to_ne_bytes());
+    assert_eq!(PortEvent::from_udev_message(&overlong), None);
+}
+
+#[test]
+fn usb_filter_matches_ids_serial_and_names() {
+    let mut adapter = usb("/dev/ttyUSB0", 0x0403, 0x6001);
+    if let SerialPortType::UsbPort(info) = &mut adapter.port_type {
+        info.serial_number = Some("A601EXYZ".into());
+        info.manufacturer = Some("FTDI".into());
+        info.product = Some("FT232R USB UART".into());
+    }
+
+    assert!(UsbFilter::default().matches(&adapter));
+    assert!(!UsbFilter::default().matches(&port("/dev/ttyS0", SerialPortType::PciPort)));
+
+    let filter = UsbFilter {
+        vid: Some(0x0403),
+        pid: Some(0x6001),
+        serial_number: Some("A601EXYZ".into()),
+        ..UsbFilter::default()
+    };
+    assert!(filter.matches(&adapter));
+    assert!(!UsbFilter {
+        serial_number: Some("A601".into()),
+        ..filter.clone()
+    }
+    .matches(&adapter));
+    assert!(!UsbFilter {
+        pid: Some(0x6015),
+        ..filter
+    }
+    .matches(&adapter));
+
+    let by_name = UsbFilter {
+        manufacturer: Some("ftdi".into()),
+        product: Some("uart".into()),
+        ..UsbFilter::default()
+    };
+    assert!(by_name.matches(&adapter));
+    assert!(!by_name.matches(&usb("/dev/ttyUSB1", 0x0403, 0x6001)));
+}
+
+#[test]
+fn usb_filter_selects_interfaces_of_composite_devices() {
+    let probe = |interface: u8, name: &str| PortDetails {
+        info: usb(&format!("/dev/ttyACM{}", interface), 0x1366, 0x1051),
+        driver: Some("cdc_acm".into()),
+        interface: Some(interface),
+        interface_name: Some(name.into()),
+        location: Some("1-1.2".into()),
+        uucp_locked: Some(false),
+    };
+    let console = probe(0, "J-Link CDC UART Port");
+    let data = probe(2, "J-Link Data Channel");
+
+    let by_number = UsbFilter {
+        vid: Some(0x1366),
+        pid: Some(0x1051),
+        interface: Some(2),
+        ..UsbFilter::default()
+    };
+    assert!(!by_number.matches_details(&console));
+    assert!(by_number.matches_details(&data));
+    // The interface is only known from the details.
+    assert!(by_number.matches(&console.info));
+
+    // Or it is enumerated along with the port.
+    let enumerated = |interface: u8| {
+        let mut port = console.info.clone();
+        if let SerialPortType::UsbPort(usb) = &mut port.port_type {
+            usb.interface = Some(interface);
+        }
+        port
+    };
+    assert!(!by_number.matches(&enumerated(0)));
+    assert!(by_number.matches(&enumerated(2)));
+    assert!(by_number.matches_details(&tokio_serial::inventory::details(enumerated(2))));
+
+    let by_name = UsbFilter {
+        interface_name: Some("uart".into()),
+        ..UsbFilter::default()
+    };
+    assert!(by_name.matches_details(&console));
+    assert!(!by_name.matches_details(&data));
+}
+
+#[cfg(feature = "rt")]
+#[tokio::test]
+async fn refreshing_off_the_runtime_finds_the_same_ports() {
+    let mut blocking = PortInventory::new();
+    let mut off_runtime = PortInventory::new();
+    let expected = blocking.refresh().expect("unable to enumerate ports");
+    let changes = off_runtime
+        .refresh_async()
+        .await
+        .expect("unable to enumerate ports");
+    assert_eq!(changes, expected);
+    assert_eq!(off_runtime.ports(), blocking.ports());
+    assert!(off_runtime.last_refresh().is_some());
+}
+
+#[cfg(feature = "rt")]
+#[tokio::test]
+async fn opening_without_a_match_fails_with_no_device() {
+    let result =
+        tokio_serial::inventory::open_first_matching(|_| false, &tokio_serial::new("", 9600)).await;
+    let err = result.expect_err("opened a port although none matches");
+    assert_eq!(err.kind(), tokio_serial::ErrorKind::NoDevice);
+}
+
+#[tokio::test]
+async fn opening_a_missing_usb_device_fails_with_no_device() {
+    use tokio_serial::SerialPortBuilderExt;
+
+    // No device uses the reserved vendor id 0.
+    let filter = UsbFilter {
+        vid: Some(0),
+        serial_number: Some("no such device".into()),
+        ..UsbFilter::default()
+    };
+    let result = tokio_serial::new("", 9600).open_usb_async(&filter).await;
+    let err = result.expect_err("opened a port although none matches");
+    assert_eq!(err.kind(), tokio_serial::ErrorKind::NoDevice);
+}
+
+// A sysfs with an FTDI adapter, the second interface of a CDC-ACM device and an
+// on-board UART.
+#[cfg(target_os = "linux")]
+fn sysfs_fixture() -> std::path::PathBuf {
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    let root = std::env::temp_dir().join(format!("tokio-serial-sysfs-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let usb = root.join("devices/pci0000:00/0000:00:14.0/usb1");
+    let drivers = root.join("bus/drivers");
+    for driver in ["ftdi_sio", "cdc_acm", "serial"].iter() {
+        fs::create_dir_all(drivers.join(driver)).unwrap();
+    }
+    let port = |name: &str, device: &std::path::Path, driver: &str| {
+        fs::create_dir_all(device).unwrap();
+        symlink(drivers.join(driver), device.join("driver")).unwrap();
+        let class = root.join("class/tty").join(name);
+        fs::create_dir_all(&class).unwrap();
+        symlink(device, class.join("device")).unwrap();
+    };
+
+    let ftdi = usb.join("1-1/1-1.2/1-1.2:1.0");
+    port("ttyUSB0", &ftdi.join("ttyUSB0"), "ftdi_sio");
+    fs::write(ftdi.join("bInterfaceNumber"), "00\n").unwrap();
+
+    let acm = usb.join("1-1/1-1.3/1-1.3:1.2");
+    port("ttyACM0", &acm, "cdc_acm");
+    fs::write(acm.join("bInterfaceNumber"), "02\n").unwrap();
+    fs::write(acm.join("interface"), "Data Channel\n").unwrap();
+
+    port("ttyS0", &root.join("devices/pnp0/00:01"), "serial");
+    root
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn details_are_read_from_sysfs() {
+    use tokio_serial::inventory::details_from;
+
+    let root = sysfs_fixture();
+    let details = |name: &str| details_from(port(name, SerialPortType::Unknown), &root);
+
+    let ftdi = details("/dev/ttyUSB0");
+    assert_eq!(ftdi.driver.as_deref(), Some("ftdi_sio"));
+    assert_eq!(ftdi.interface, Some(0));
+    assert_eq!(ftdi.interface_name, None);
+    assert_eq!(ftdi.location.as_deref(), Some("1-1.2"));
+
+    let acm = details("/dev/ttyACM0");
+    assert_eq!(acm.driver.as_deref(), Some("cdc_acm"));
+    assert_eq!(acm.interface, Some(2));
+    assert_eq!(acm.interface_name.as_deref(), Some("Data Channel"));
+    assert_eq!(acm.location.as_deref(), Some("1-1.3"));
+
+    let uart = details("/dev/ttyS0");
+    assert_eq!(uart.driver.as_deref(), Some("serial"));
+    assert_eq!(uart.interface, None);
+    assert_eq!(uart.location.as_deref(), Some("pnp0/00:01"));
+
+    let missing = details("/dev/ttyUSB9");
+    assert_eq!(missing.driver, None);
+    assert_eq!(missing.location, None);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,73 @@
+use bytes::BytesMut;
+use serde::{Deserialize, Serialize};
+use tokio_serial::codec::{JsonLinesCodec, JsonLinesError};
+use tokio_util::codec::{Decoder, Encoder};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Reading {
+    sensor: String,
+    value: f64,
+}
+
+fn reading(sensor: &str, value: f64) -> Reading {
+    Reading {
+        sensor: sensor.to_string(),
+        value,
+    }
+}
+
+#[test]
+fn values_are_encoded_one_per_line() {
+    let mut codec = JsonLinesCodec::new(256);
+    let mut dst = BytesMut::new();
+    codec.encode(reading("t0", 21.5), &mut dst).unwrap();
+    codec.encode(&reading("t1", -3.0), &mut dst).unwrap();
+    assert_eq!(
+        &dst[..],
+        &b"{\"sensor\":\"t0\",\"value\":21.5}\n{\"sensor\":\"t1\",\"value\":-3.0}\n"[..]
+    );
+
+    assert_eq!(codec.decode(&mut dst).unwrap(), Some(reading("t0", 21.5)));
+    assert_eq!(codec.decode(&mut dst).unwrap(), Some(reading("t1", -3.0)));
+    assert_eq!(codec.decode(&mut dst).unwrap(), None);
+}
+
+#[test]
+fn bad_lines_are_reported_and_skipped() {
+    let mut codec = JsonLinesCodec::<Reading>::new(64);
+    let mut src = BytesMut::from(
+        &b"{\"sensor\":\"t0\",\"val\r\n\r\n{\"sensor\":\"t0\",\"value\":1}\r\n\
+           {\"sensor\":\"t0\",\"value\":\"hot\"}\n"[..],
+    );
+
+    match codec.decode(&mut src) {
+        Err(JsonLinesError::Json { line, .. }) => {
+            assert_eq!(line, "{\"sensor\":\"t0\",\"val\r")
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(codec.decode(&mut src).unwrap(), Some(reading("t0", 1.0)));
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(JsonLinesError::Json { .. })
+    ));
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+}
+
+#[test]
+fn long_lines_and_the_last_line() {
+    let mut codec = JsonLinesCodec::<Reading>::new(32);
+    let mut src = BytesMut::from(&b"[0000000000000000000000000000000000000]\n"[..]);
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(JsonLinesError::TooLong)
+    ));
+
+    // The stream ends without a newline after the last value.
+    src.extend_from_slice(b"{\"sensor\":\"t2\",\"value\":0.5}");
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+    assert_eq!(
+        codec.decode_eof(&mut src).unwrap(),
+        Some(reading("t2", 0.5))
+    );
+}
@@ -0,0 +1,123 @@
+#![cfg(unix)]
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio_serial::transfer::kermit::{BlockCheck, Kermit};
+use tokio_serial::transfer::{CancellationToken, FileInfo, TransferError};
+use tokio_serial::SerialStream;
+
+// Every byte value, then runs to compress.
+fn data() -> Vec<u8> {
+    let mut data: Vec<u8> = (0..=255).collect();
+    data.extend_from_slice(&[0; 300]);
+    data.extend((0..1000).map(|i| (i * 7) as u8));
+    data.extend_from_slice(b"~~~~~");
+    data
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tokio-serial-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn create(dir: &Path, info: &FileInfo) -> std::io::Result<tokio::fs::File> {
+    std::fs::File::create(dir.join(&info.name)).map(tokio::fs::File::from_std)
+}
+
+#[tokio::test]
+async fn files_are_sent_in_batches() {
+    let (mut a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let dir = temp_dir("kermit");
+    let data = data();
+    let len = data.len() as u64;
+    let files = vec![
+        (FileInfo::new("data.bin", len), &data[..]),
+        (FileInfo::new("empty", 0), &[][..]),
+    ];
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let mut sender = Kermit::new().progress({
+        let progress = progress.clone();
+        move |p| progress.lock().unwrap().push((p.transferred, p.total))
+    });
+    let mut receiver = Kermit::new();
+    let (sent, received) = tokio::join!(
+        sender.send(&mut a, files),
+        receiver.receive(&mut b, |info| create(&dir, info))
+    );
+    sent.unwrap();
+    assert_eq!(
+        received.unwrap(),
+        [FileInfo::new("data.bin", len), FileInfo::new("empty", 0)]
+    );
+    assert_eq!(std::fs::read(dir.join("data.bin")).unwrap(), data);
+    assert!(std::fs::read(dir.join("empty")).unwrap().is_empty());
+    assert_eq!(progress.lock().unwrap().last(), Some(&(len, Some(len))));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn parameters_are_negotiated() {
+    let (mut a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let data = data();
+
+    // The checks differ, both ends fall back to the 6 bit sum.
+    let mut sender = Kermit::new()
+        .block_check(BlockCheck::Sum12)
+        .eight_bit_prefixing(true);
+    let mut receiver = Kermit::new();
+    let out = Arc::new(Mutex::new(Vec::new()));
+    let (sent, received) = tokio::join!(
+        sender.send(&mut a, vec![(FileInfo::new("data.bin", 0), &data[..])]),
+        receiver.receive(&mut b, |_| Ok(SharedWriter(out.clone())))
+    );
+    sent.unwrap();
+    assert_eq!(received.unwrap().len(), 1);
+    assert_eq!(*out.lock().unwrap(), data);
+}
+
+struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+impl tokio::io::AsyncWrite for SharedWriter {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        std::task::Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test]
+async fn cancelled_transfers_notify_the_peer() {
+    let (mut a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let token = CancellationToken::new();
+    let mut sender = Kermit::new();
+    let mut receiver = Kermit::new()
+        .cancellation(token.clone())
+        .progress(move |_| token.cancel());
+
+    let data = vec![0x55; 10_000];
+    let (sent, received) = tokio::join!(
+        sender.send(&mut a, vec![(FileInfo::new("big", 10_000), &data[..])]),
+        receiver.receive(&mut b, |_| Ok(tokio::io::sink()))
+    );
+    assert!(matches!(received, Err(TransferError::Cancelled)));
+    assert!(matches!(sent, Err(TransferError::CancelledByPeer)));
+}
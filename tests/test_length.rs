@@ -0,0 +1,68 @@
+use bytes::BytesMut;
+use tokio_serial::codec::{FrameError, LengthDelimitedCodec, LengthError};
+use tokio_util::codec::{Decoder, Encoder};
+
+#[test]
+fn payloads_follow_their_length() {
+    let mut codec = LengthDelimitedCodec::new();
+    let mut src = BytesMut::from(&b"\x00\x03abc\x00"[..]);
+
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), &b"abc"[..]);
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+    src.extend_from_slice(b"\x02d");
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+    src.extend_from_slice(b"e");
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), &b"de"[..]);
+
+    let mut dst = BytesMut::new();
+    codec.encode(&b"abc"[..], &mut dst).unwrap();
+    assert_eq!(dst, &b"\x00\x03abc"[..]);
+}
+
+#[test]
+fn headers_and_adjustments() {
+    // An address byte, then a little endian u16 length which counts itself.
+    let mut codec = LengthDelimitedCodec::new()
+        .length_field_offset(1)
+        .little_endian()
+        .length_adjustment(-2)
+        .strip_header(false);
+
+    let mut dst = BytesMut::new();
+    codec.encode(&b"\x11ping"[..], &mut dst).unwrap();
+    assert_eq!(dst, &b"\x11\x06\x00ping"[..]);
+    assert_eq!(
+        codec.decode(&mut dst).unwrap().unwrap(),
+        &b"\x11\x06\x00ping"[..]
+    );
+
+    assert!(matches!(
+        codec.encode(&b""[..], &mut dst),
+        Err(FrameError::Frame(LengthError::MissingHeader))
+    ));
+    let mut src = BytesMut::from(&b"\x11\x01\x00"[..]);
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(FrameError::Frame(LengthError::Invalid(1)))
+    ));
+
+    let mut codec = LengthDelimitedCodec::new().length_field_length(1);
+    assert!(matches!(
+        codec.encode(&[0u8; 256][..], &mut dst),
+        Err(FrameError::Frame(LengthError::Unrepresentable(256)))
+    ));
+}
+
+#[test]
+fn long_frames_are_skipped() {
+    let mut codec = LengthDelimitedCodec::new().max_frame_length(4);
+    let mut src = BytesMut::from(&b"\x00\x08abc"[..]);
+
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(FrameError::Frame(LengthError::TooLong(8)))
+    ));
+    assert!(src.is_empty());
+    src.extend_from_slice(b"defgh\x00\x02ok");
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), &b"ok"[..]);
+}
@@ -0,0 +1,139 @@
+#![cfg(unix)]
+
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::lin::{self, Checksum, LinMaster, LinSlave};
+use tokio_serial::{ErrorKind, SerialStream};
+
+#[test]
+fn protected_ids_carry_parity() {
+    let cases = [
+        (0x00, 0x80),
+        (0x01, 0xc1),
+        (0x10, 0x50),
+        (0x3c, 0x3c),
+        (0x3d, 0x7d),
+    ];
+    for &(id, pid) in &cases {
+        assert_eq!(lin::protected_id(id), pid);
+        assert_eq!(lin::frame_id(pid), Some(id));
+    }
+    assert_eq!(lin::frame_id(0x00), None);
+    assert_eq!(lin::frame_id(0x3c | 0x40), None);
+}
+
+#[test]
+fn checksums_add_with_carry() {
+    // The example of the LIN specification
+    let data = [0x4a, 0x55, 0x93, 0xe5];
+    assert_eq!(
+        Checksum::Classic.compute(lin::protected_id(0x10), &data),
+        0xe6
+    );
+    // The data adds up to 0x19, plus the protected identifier.
+    assert_eq!(Checksum::Enhanced.compute(0x50, &data), !0x69);
+    // Diagnostic frames keep the classic checksum.
+    assert_eq!(Checksum::Enhanced.compute(0x3c, &data), 0xe6);
+}
+
+#[tokio::test]
+async fn master_sends_headers_and_responses() {
+    let (a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let mut master = LinMaster::new(a).unwrap();
+    master.send_frame(0x10, &[0x01, 0x02]).await.unwrap();
+
+    let mut frame = [0u8; 5];
+    b.read_exact(&mut frame).await.unwrap();
+    let checksum = Checksum::Enhanced.compute(0x50, &[0x01, 0x02]);
+    assert_eq!(frame, [lin::SYNC, 0x50, 0x01, 0x02, checksum]);
+}
+
+#[tokio::test]
+async fn master_reads_slave_responses() {
+    let (a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let mut master = LinMaster::new(a).unwrap();
+
+    let slave = tokio::spawn(async move {
+        let mut header = [0u8; 2];
+        b.read_exact(&mut header).await.unwrap();
+        assert_eq!(header, [lin::SYNC, lin::protected_id(0x21)]);
+        let checksum = Checksum::Enhanced.compute(header[1], &[1, 2, 3]);
+        b.write_all(&[1, 2, 3, checksum]).await.unwrap();
+        // Then a response with a wrong checksum.
+        b.read_exact(&mut header).await.unwrap();
+        b.write_all(&[1, 2, 3, !checksum]).await.unwrap();
+        b
+    });
+    assert_eq!(master.request(0x21, 3).await.unwrap(), [1, 2, 3]);
+    let err = master.request(0x21, 3).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Io(io::ErrorKind::InvalidData));
+    let _b = slave.await.unwrap();
+
+    master.set_response_timeout(Duration::from_millis(20));
+    let err = master.request(0x21, 3).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Io(io::ErrorKind::TimedOut));
+}
+
+#[tokio::test]
+async fn master_checks_the_echo() {
+    let (a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let mut master = LinMaster::new(a).unwrap();
+    master.set_echo(true);
+
+    let bus = tokio::spawn(async move {
+        for corrupt in [false, true] {
+            let mut header = [0u8; 2];
+            b.read_exact(&mut header).await.unwrap();
+            // The break reads back as a NUL byte.
+            b.write_all(&[0]).await.unwrap();
+            b.write_all(&header).await.unwrap();
+            let mut response = [0u8; 3];
+            b.read_exact(&mut response).await.unwrap();
+            if corrupt {
+                response[0] ^= 0x01;
+            }
+            b.write_all(&response).await.unwrap();
+        }
+        b
+    });
+    master.send_frame(0x01, &[0xaa, 0x55]).await.unwrap();
+    let err = master.send_frame(0x01, &[0xaa, 0x55]).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Io(io::ErrorKind::InvalidData));
+    bus.await.unwrap();
+}
+
+#[tokio::test]
+async fn slave_aligns_on_breaks() {
+    let (a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let mut slave = LinSlave::new(a);
+
+    // Noise, a header with bad parity, then headers, breaks reading as NUL bytes.
+    b.write_all(&[lin::SYNC, 0x10, 0x00, lin::SYNC, 0x10])
+        .await
+        .unwrap();
+    b.write_all(&[0x00, lin::SYNC, 0x50, 0x00, lin::SYNC, 0x61])
+        .await
+        .unwrap();
+    assert_eq!(slave.next_header().await.unwrap(), 0x10);
+    assert_eq!(slave.next_header().await.unwrap(), 0x21);
+
+    slave.respond(0x21, &[7, 8]).await.unwrap();
+    let mut response = [0u8; 3];
+    b.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        response,
+        [
+            7,
+            8,
+            Checksum::Enhanced.compute(lin::protected_id(0x21), &[7, 8])
+        ]
+    );
+
+    let checksum = Checksum::Enhanced.compute(0x50, &[9]);
+    b.write_all(&[0x00, lin::SYNC, 0x50, 9, checksum])
+        .await
+        .unwrap();
+    assert_eq!(slave.next_header().await.unwrap(), 0x10);
+    assert_eq!(slave.read_response(0x10, 1).await.unwrap(), [9]);
+}
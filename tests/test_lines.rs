@@ -0,0 +1,60 @@
+use bytes::BytesMut;
+use tokio_serial::codec::{Delimiter, LinesCodec, LinesCodecError};
+use tokio_util::codec::{Decoder, Encoder};
+
+#[test]
+fn lines_split_on_the_delimiter() {
+    let mut codec = LinesCodec::new(16).delimiter(Delimiter::CrLf);
+    let mut src = BytesMut::from(&b"OK\r\nERROR\r"[..]);
+
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), "OK");
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+    // The delimiter is split across reads.
+    src.extend_from_slice(b"\nlone\rcr\r\n");
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), "ERROR");
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), "lone\rcr");
+    assert!(src.is_empty());
+
+    let mut codec = LinesCodec::new(16)
+        .delimiter(Delimiter::Bytes(b"> ".to_vec()))
+        .keep_delimiter(true);
+    let mut src = BytesMut::from(&b"login> pass"[..]);
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), "login> ");
+    assert_eq!(codec.decode_eof(&mut src).unwrap().unwrap(), "pass");
+    assert_eq!(codec.decode_eof(&mut src).unwrap(), None);
+}
+
+#[test]
+fn long_lines_are_skipped() {
+    let mut codec = LinesCodec::new(4);
+    let mut src = BytesMut::from(&b"garbage"[..]);
+
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(LinesCodecError::TooLong)
+    ));
+    assert!(src.len() <= 4, "the buffer keeps growing");
+    src.extend_from_slice(b"more garbage\nfour\n");
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), "four");
+
+    let mut src = BytesMut::from(&b"fives\nok\n\xff\xfe\nnext\n"[..]);
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(LinesCodecError::TooLong)
+    ));
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), "ok");
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(LinesCodecError::InvalidUtf8(_))
+    ));
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), "next");
+}
+
+#[test]
+fn lines_are_encoded_with_the_delimiter() {
+    let mut codec = LinesCodec::new(64).delimiter(Delimiter::Cr);
+    let mut dst = BytesMut::new();
+    codec.encode("AT", &mut dst).unwrap();
+    codec.encode(String::from("ATI"), &mut dst).unwrap();
+    assert_eq!(dst, &b"AT\rATI\r"[..]);
+}
@@ -0,0 +1,60 @@
+#![cfg(unix)]
+
+use tokio_serial::{SerialPort, SerialStream};
+
+fn lock_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::create_dir_all(&dir).expect("unable to create lock directory");
+    dir
+}
+
+#[tokio::test]
+async fn lock_is_exclusive_and_released_on_drop() {
+    let dir = lock_dir("lock_exclusive");
+    let (_master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    let name = slave.name().expect("slave has no name");
+
+    let mut other = SerialStream::open(&tokio_serial::new(&name, 9600).exclusive(false))
+        .expect("unable to reopen port");
+    other.lock_in(&dir).expect("unable to lock port");
+    let lock_file = other.lock_file().expect("no lock file").to_path_buf();
+    let contents = std::fs::read_to_string(&lock_file).expect("unable to read lock file");
+    assert_eq!(contents, format!("{:>10}\n", std::process::id()));
+
+    assert!(slave.lock_in(&dir).is_err());
+    assert!(slave.lock_file().is_none());
+
+    drop(other);
+    assert!(!lock_file.exists());
+    slave.lock_in(&dir).expect("unable to lock released port");
+}
+
+#[tokio::test]
+async fn lock_file_of_live_process_is_honored() {
+    let dir = lock_dir("lock_live");
+    let (_master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+
+    slave.lock_in(&dir).expect("unable to lock port");
+    let lock_file = slave.lock_file().expect("no lock file").to_path_buf();
+    slave.unlock();
+    assert!(!lock_file.exists());
+
+    std::fs::write(&lock_file, format!("{:>10}\n", std::process::id())).unwrap();
+    assert!(slave.lock_in(&dir).is_err());
+    std::fs::remove_file(&lock_file).unwrap();
+}
+
+#[tokio::test]
+async fn stale_lock_is_replaced() {
+    let dir = lock_dir("lock_stale");
+    let (_master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+
+    slave.lock_in(&dir).expect("unable to lock port");
+    let lock_file = slave.lock_file().expect("no lock file").to_path_buf();
+    slave.unlock();
+
+    // PIDs are bounded well below i32::MAX, so this one can't be running.
+    std::fs::write(&lock_file, format!("{:>10}\n", i32::MAX)).unwrap();
+    slave.lock_in(&dir).expect("unable to replace stale lock");
+    assert_eq!(slave.lock_file(), Some(lock_file.as_path()));
+}
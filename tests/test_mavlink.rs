@@ -0,0 +1,122 @@
+use bytes::BytesMut;
+use tokio_serial::codec::mavlink::{MavlinkError, MavlinkFrame, MavlinkVersion, IFLAG_SIGNED};
+use tokio_serial::codec::{FrameError, MavlinkCodec};
+use tokio_util::codec::{Decoder, Encoder};
+
+// HEARTBEAT and COMMAND_ACK
+fn codec() -> MavlinkCodec {
+    MavlinkCodec::new().crc_extra(|msgid| match msgid {
+        0 => Some(50),
+        77 => Some(143),
+        _ => None,
+    })
+}
+
+const HEARTBEAT: &[u8] = b"\x00\x00\x00\x00\x02\x03\x51\x04\x03";
+const HEARTBEAT_V1: &[u8] = b"\xfe\x09\x07\x01\x01\x00\x00\x00\x00\x00\x02\x03\x51\x04\x03\xfa\xad";
+const HEARTBEAT_V2: &[u8] =
+    b"\xfd\x09\x00\x00\x07\x01\x01\x00\x00\x00\x00\x00\x00\x00\x02\x03\x51\x04\x03\xa4\xac";
+
+#[test]
+fn frames_of_both_versions_are_decoded() {
+    let mut codec = codec();
+    let mut src = BytesMut::from(&b"noise"[..]);
+    src.extend_from_slice(HEARTBEAT_V1);
+    src.extend_from_slice(&HEARTBEAT_V2[..8]);
+
+    let frame = codec.decode(&mut src).unwrap().unwrap();
+    assert_eq!(frame.version, MavlinkVersion::V1);
+    assert_eq!((frame.seq, frame.sysid, frame.compid), (7, 1, 1));
+    assert_eq!(frame.msgid, 0);
+    assert_eq!(frame.payload, HEARTBEAT);
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+
+    src.extend_from_slice(&HEARTBEAT_V2[8..]);
+    let frame = codec.decode(&mut src).unwrap().unwrap();
+    assert_eq!(frame.version, MavlinkVersion::V2);
+    assert_eq!(frame.payload, HEARTBEAT);
+    assert_eq!(frame.signature, None);
+    assert!(src.is_empty());
+}
+
+#[test]
+fn frames_are_encoded_with_their_crc() {
+    let mut codec = codec();
+    let mut dst = BytesMut::new();
+    let mut frame = MavlinkFrame::new(7, 1, 1, 0, HEARTBEAT);
+    codec.encode(&frame, &mut dst).unwrap();
+    assert_eq!(dst, HEARTBEAT_V2);
+
+    dst.clear();
+    frame.version = MavlinkVersion::V1;
+    codec.encode(frame, &mut dst).unwrap();
+    assert_eq!(dst, HEARTBEAT_V1);
+
+    // The trailing zero of the result is truncated.
+    dst.clear();
+    let ack = MavlinkFrame::new(8, 255, 190, 77, &b"\x90\x01\x00"[..]);
+    codec.encode(&ack, &mut dst).unwrap();
+    assert_eq!(
+        dst,
+        &b"\xfd\x02\x00\x00\x08\xff\xbe\x4d\x00\x00\x90\x01\x5f\x1d"[..]
+    );
+    assert_eq!(
+        codec.decode(&mut dst).unwrap().unwrap().payload,
+        &b"\x90\x01"[..]
+    );
+
+    assert!(matches!(
+        codec.encode(MavlinkFrame::new(0, 1, 1, 1, &b"\x01"[..]), &mut dst),
+        Err(FrameError::Frame(MavlinkError::UnknownMessage(1)))
+    ));
+    let mut long = MavlinkFrame::new(0, 1, 1, 300, &b"\x01"[..]);
+    long.version = MavlinkVersion::V1;
+    assert!(matches!(
+        MavlinkCodec::new()
+            .crc_extra(|_| Some(0))
+            .encode(long, &mut dst),
+        Err(FrameError::Frame(MavlinkError::MessageIdTooLarge(300)))
+    ));
+}
+
+#[test]
+fn signatures_are_carried() {
+    let mut codec = codec();
+    let mut frame = MavlinkFrame::new(1, 1, 1, 0, HEARTBEAT);
+    frame.signature = Some([0x42; 13]);
+    let mut dst = BytesMut::new();
+    codec.encode(&frame, &mut dst).unwrap();
+    assert_eq!(dst.len(), HEARTBEAT_V2.len() + 13);
+
+    let decoded = codec.decode(&mut dst).unwrap().unwrap();
+    assert_eq!(decoded.incompat_flags, IFLAG_SIGNED);
+    assert_eq!(decoded.signature, Some([0x42; 13]));
+}
+
+#[test]
+fn corrupted_frames_are_skipped() {
+    let mut codec = codec();
+    let mut corrupted = HEARTBEAT_V2.to_vec();
+    corrupted[12] ^= 1;
+    let mut src = BytesMut::from(&corrupted[..]);
+    src.extend_from_slice(HEARTBEAT_V1);
+
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(FrameError::Frame(MavlinkError::Crc { msgid: 0 }))
+    ));
+    let frame = codec.decode(&mut src).unwrap().unwrap();
+    assert_eq!(frame.version, MavlinkVersion::V1);
+    assert!(src.is_empty());
+
+    // Unknown flags
+    let mut src = BytesMut::from(&b"\xfd\x01\x02"[..]);
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(FrameError::Frame(MavlinkError::Incompatible(2)))
+    ));
+
+    // Messages the dialect does not know are not checked.
+    let mut src = BytesMut::from(&b"\xfe\x01\x00\x01\x01\x05\xaa\x00\x00"[..]);
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap().msgid, 5);
+}
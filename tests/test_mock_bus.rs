@@ -0,0 +1,58 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::mock::{MockBus, COLLISION_BYTE};
+
+#[tokio::test]
+async fn endpoints_see_each_other() {
+    let bus = MockBus::new();
+    let mut master = bus.endpoint();
+    let mut slave1 = bus.endpoint();
+    let mut slave2 = bus.endpoint();
+
+    master.write_all(b"\x02ping").await.unwrap();
+    let mut buf = [0u8; 5];
+    slave1.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"\x02ping");
+    slave2.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"\x02ping");
+    assert_eq!(master.bytes_to_read(), 0);
+
+    slave2.write_all(b"pong").await.unwrap();
+    let mut buf = [0u8; 4];
+    master.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"pong");
+    slave1.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"pong");
+}
+
+#[tokio::test]
+async fn echo_returns_own_writes() {
+    let bus = MockBus::new();
+    bus.set_echo(true);
+    let mut port = bus.endpoint();
+
+    port.write_all(b"abc").await.unwrap();
+    let mut buf = [0u8; 3];
+    port.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"abc");
+}
+
+#[tokio::test]
+async fn overlapping_writes_collide() {
+    // 100 bytes at 1200 baud keep the bus busy for most of a second.
+    let bus = MockBus::with_collisions(1200);
+    let mut a = bus.endpoint();
+    let mut b = bus.endpoint();
+    let mut listener = bus.endpoint();
+
+    a.write_all(&[0x55; 100]).await.unwrap();
+    // The same endpoint writing again is not a collision.
+    a.write_all(b"z").await.unwrap();
+    assert_eq!(bus.collisions(), 0);
+    b.write_all(b"xy").await.unwrap();
+    assert_eq!(bus.collisions(), 1);
+
+    let mut buf = [0u8; 103];
+    listener.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf[..100], &[0x55; 100][..]);
+    assert_eq!(&buf[100..], &[b'z', COLLISION_BYTE, COLLISION_BYTE]);
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::mock::{MockSerialStream, Script};
+use tokio_serial::{Parity, SerialPort};
+
+#[tokio::test]
+async fn plays_the_script() {
+    let mut port = MockSerialStream::new(
+        Script::new()
+            .respond(b"READY")
+            .expect_write(b"\x01\x03")
+            .respond(b"\x01\x83"),
+    );
+
+    let mut buf = [0u8; 5];
+    port.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"READY");
+
+    // Writes may be split.
+    port.write_all(b"\x01").await.unwrap();
+    assert_eq!(port.bytes_to_read().unwrap(), 0);
+    port.write_all(b"\x03").await.unwrap();
+    let mut buf = [0u8; 2];
+    port.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"\x01\x83");
+    port.assert_done();
+
+    // Nothing comes after the end of the script.
+    let mut buf = [0u8; 1];
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), port.read(&mut buf))
+            .await
+            .is_err()
+    );
+    assert!(port.write_all(b"more").await.is_err());
+}
+
+#[tokio::test]
+async fn unexpected_writes_fail() {
+    let mut port = MockSerialStream::new(Script::new().expect_write(b"ping").respond(b"pong"));
+    let error = port.write_all(b"pint").await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(!port.remaining().is_empty());
+}
+
+#[tokio::test]
+async fn responses_are_delayed() {
+    let mut port = MockSerialStream::new(
+        Script::new()
+            .expect_write(b"?")
+            .delay(Duration::from_millis(100))
+            .respond(b"!"),
+    );
+    port.write_all(b"?").await.unwrap();
+
+    let mut buf = [0u8; 1];
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), port.read_exact(&mut buf))
+            .await
+            .is_err()
+    );
+    tokio::time::timeout(Duration::from_millis(500), port.read_exact(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(&buf, b"!");
+}
+
+#[tokio::test]
+async fn settings_and_lines_are_recorded() {
+    let mut port = MockSerialStream::new(Script::new());
+    port.set_baud_rate(115_200).unwrap();
+    port.set_parity(Parity::Even).unwrap();
+    port.write_request_to_send(true).unwrap();
+    port.set_break().unwrap();
+    port.set_clear_to_send(true);
+
+    assert_eq!(port.settings().baud_rate, 115_200);
+    assert_eq!(port.parity().unwrap(), Parity::Even);
+    assert!(port.request_to_send());
+    assert!(!port.data_terminal_ready());
+    assert!(port.break_state());
+    assert!(port.read_clear_to_send().unwrap());
+}
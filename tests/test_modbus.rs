@@ -0,0 +1,70 @@
+#![cfg(unix)]
+
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio_serial::codec::modbus::{handle_pdu, ModbusSlave, RegisterBank};
+use tokio_serial::codec::ModbusRtuFramed;
+use tokio_serial::SerialStream;
+
+#[test]
+fn pdus_are_dispatched_to_the_backend() {
+    let mut bank = RegisterBank::new(10, 0, 4, 0);
+    bank.holding_registers[1] = 0x1234;
+    bank.coils[0] = true;
+    bank.coils[9] = true;
+
+    // Read holding registers 1 and 2
+    assert_eq!(
+        handle_pdu(&mut bank, b"\x03\x00\x01\x00\x02"),
+        b"\x03\x04\x12\x34\x00\x00"
+    );
+    // Read coils 0 to 9
+    assert_eq!(
+        handle_pdu(&mut bank, b"\x01\x00\x00\x00\x0a"),
+        b"\x01\x02\x01\x02"
+    );
+    // Write registers 2 and 3
+    assert_eq!(
+        handle_pdu(&mut bank, b"\x10\x00\x02\x00\x02\x04\x00\x07\x00\x08"),
+        b"\x10\x00\x02\x00\x02"
+    );
+    assert_eq!(bank.holding_registers, [0, 0x1234, 7, 8]);
+    // Write coil 3
+    assert_eq!(
+        handle_pdu(&mut bank, b"\x05\x00\x03\xff\x00"),
+        b"\x05\x00\x03\xff\x00"
+    );
+    assert!(bank.coils[3]);
+
+    // Out of range, bad value, unsupported
+    assert_eq!(handle_pdu(&mut bank, b"\x03\x00\x03\x00\x02"), b"\x83\x02");
+    assert_eq!(handle_pdu(&mut bank, b"\x05\x00\x03\x12\x34"), b"\x85\x03");
+    assert_eq!(handle_pdu(&mut bank, b"\x04\x00\x00\x00\x01"), b"\x84\x02");
+    assert_eq!(handle_pdu(&mut bank, b"\x2b\x0e\x01\x00"), b"\xab\x01");
+}
+
+#[tokio::test]
+async fn slave_answers_its_unit_id() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let mut port = ModbusRtuFramed::new(a).unwrap();
+    port.set_silent_interval(Duration::from_millis(10));
+    let slave = tokio::spawn(async move {
+        let mut slave = ModbusSlave::new(port, 17, RegisterBank::new(0, 0, 4, 0));
+        let _ = slave.run().await;
+    });
+
+    let mut master = ModbusRtuFramed::new(b).unwrap();
+    master.set_silent_interval(Duration::from_millis(10));
+
+    // Other slaves and broadcasts are not answered, broadcast writes are executed.
+    master.send(&b"\x05\x03\x00\x00\x00\x01"[..]).await.unwrap();
+    master.send(&b"\x00\x06\x00\x02\xab\xcd"[..]).await.unwrap();
+    master.send(&b"\x11\x03\x00\x02\x00\x01"[..]).await.unwrap();
+    let response = master.next().await.unwrap().unwrap();
+    assert_eq!(&response[..5], b"\x11\x03\x02\xab\xcd");
+
+    master.send(&b"\x11\x03\x00\x04\x00\x01"[..]).await.unwrap();
+    let response = master.next().await.unwrap().unwrap();
+    assert_eq!(&response[..3], b"\x11\x83\x02");
+    slave.abort();
+}
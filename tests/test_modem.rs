@@ -0,0 +1,264 @@
+use tokio_serial::codec::modem::{decode_deliver, encode_submit, ModemError};
+
+fn unhex(text: &str) -> Vec<u8> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn pdus_are_encoded_and_decoded() {
+    assert_eq!(
+        encode_submit("+46708251358", "hellohello").unwrap(),
+        unhex("01000B916407281553F800000AE8329BFD4697D9EC37")
+    );
+    // UCS-2 for characters outside of the GSM alphabet
+    let pdu = encode_submit("12345", "привет").unwrap();
+    assert_eq!(&pdu[..8], unhex("010005812143F500"));
+    assert_eq!(&pdu[8..12], [0x08, 12, 0x04, 0x3f]);
+    // Characters of the extension table take two septets.
+    let pdu = encode_submit("1", &"€".repeat(80)).unwrap();
+    assert_eq!(pdu[7], 160);
+    assert!(matches!(
+        encode_submit("1", &"€".repeat(81)),
+        Err(ModemError::InvalidSms)
+    ));
+    assert!(matches!(
+        encode_submit("+31 6", "hi"),
+        Err(ModemError::InvalidSms)
+    ));
+
+    let sms = decode_deliver(&unhex(
+        "07917283010010F5040BC87238880900F10000993092516195800AE8329BFD4697D9EC37",
+    ))
+    .unwrap();
+    assert_eq!(sms.address, "27838890001");
+    assert_eq!(sms.timestamp.as_deref(), Some("99/03/29,15:16:59+08"));
+    assert_eq!(sms.text, "hellohello");
+
+    // A part of a concatenated UCS-2 message from an international number
+    let sms = decode_deliver(&unhex(
+        "0791448720003023440C91449703529096000850015132532240100500030A0201004F004B",
+    ))
+    .unwrap();
+    assert_eq!(sms.address, "+447930250969");
+    assert_eq!(sms.timestamp.as_deref(), Some("05/10/15,23:35:22+04"));
+    assert_eq!(sms.text, "OK");
+    assert_eq!(decode_deliver(&unhex("0001")), None);
+}
+
+#[cfg(unix)]
+mod manager {
+    use futures::StreamExt;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::mpsc;
+    use tokio_serial::codec::at::AtResult;
+    use tokio_serial::codec::modem::{
+        ModemError, ModemEvent, ModemManager, PinStatus, Registration, SignalQuality, SmsMode,
+    };
+    use tokio_serial::SerialStream;
+
+    // A modem answering the commands it receives with `answer`, reporting them.
+    // After the prompt of `AT+CMGS`, the data is reported as the command.
+    fn modem(
+        mut port: SerialStream,
+        mut answer: impl FnMut(&str) -> String + Send + 'static,
+    ) -> mpsc::UnboundedReceiver<String> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut received = Vec::new();
+            let mut buf = [0u8; 256];
+            let mut data = false;
+            loop {
+                let n = port.read(&mut buf).await.unwrap();
+                received.extend_from_slice(&buf[..n]);
+                let end = if data { 0x1a } else { b'\r' };
+                while let Some(at) = received.iter().position(|&b| b == end) {
+                    let line: Vec<u8> = received.drain(..=at).collect();
+                    let line = String::from_utf8(line[..at].to_vec()).unwrap();
+                    let reply = if data {
+                        "\r\n+CMGS: 7\r\n\r\nOK\r\n".to_string()
+                    } else if line.starts_with("AT+CMGS") {
+                        "\r\n> ".to_string()
+                    } else {
+                        answer(&line)
+                    };
+                    data = !data && line.starts_with("AT+CMGS");
+                    tx.send(line).unwrap();
+                    port.write_all(reply.as_bytes()).await.unwrap();
+                }
+            }
+        });
+        rx
+    }
+
+    fn commands(rx: &mut mpsc::UnboundedReceiver<String>) -> Vec<String> {
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    fn answer(command: &str) -> String {
+        match command {
+            "AT+CPIN?" => "\r\n+CPIN: SIM PIN\r\n\r\nOK\r\n",
+            "AT+CPIN=\"1234\"" => "\r\nOK\r\n\r\n+CMTI: \"SM\",3\r\n",
+            "AT+CSQ" => "\r\n+CSQ: 20,99\r\n\r\nOK\r\n",
+            "AT+CREG?" => "\r\n+CREG: 1,5\r\n\r\nOK\r\n",
+            "AT+CMGR=3" => concat!(
+                "\r\n+CMGR: 0,,28\r\n",
+                "07917283010010F5040BC87238880900F10000993092516195800AE8329BFD4697D9EC37\r\n",
+                "\r\nOK\r\n"
+            ),
+            "ATD123;" => "\r\nNO CARRIER\r\n",
+            _ => "\r\nOK\r\n",
+        }
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn modems_are_initialized_and_driven() {
+        let (device, port) = SerialStream::pair().expect("unable to open pty pair");
+        let mut unlocked = false;
+        let mut received = modem(device, move |command| match command {
+            "AT+CPIN?" if unlocked => "\r\n+CPIN: READY\r\n\r\nOK\r\n".to_string(),
+            _ => {
+                unlocked |= command == "AT+CPIN=\"1234\"";
+                answer(command)
+            }
+        });
+        let (mut modem, mut events) = ModemManager::new(port);
+        modem.get_mut().set_timeout(Duration::from_millis(500));
+
+        assert!(matches!(
+            modem.initialize().await,
+            Err(ModemError::Locked(PinStatus::SimPin))
+        ));
+        modem.set_pin("1234");
+        modem.initialize().await.unwrap();
+        assert_eq!(
+            commands(&mut received),
+            [
+                "AT",
+                "ATE0",
+                "AT+CMEE=1",
+                "AT+CPIN?",
+                "AT",
+                "ATE0",
+                "AT+CMEE=1",
+                "AT+CPIN?",
+                "AT+CPIN=\"1234\"",
+                "AT+CPIN?",
+                "AT+CMGF=0",
+                "AT+CNMI=2,1,0,0,0",
+                "AT+CREG=1"
+            ]
+        );
+        assert_eq!(
+            events.next().await,
+            Some(ModemEvent::SmsStored {
+                storage: "SM".to_string(),
+                index: 3
+            })
+        );
+
+        assert_eq!(
+            modem.signal_quality().await.unwrap(),
+            SignalQuality {
+                rssi: Some(-73),
+                ber: None
+            }
+        );
+        assert_eq!(modem.registration().await.unwrap(), Registration::Roaming);
+
+        let sms = modem.read_sms(3).await.unwrap();
+        assert_eq!(sms.text, "hellohello");
+
+        assert_eq!(
+            modem.send_sms("+46708251358", "hellohello").await.unwrap(),
+            7
+        );
+        let sent = commands(&mut received);
+        assert_eq!(
+            sent[sent.len() - 2..],
+            [
+                "AT+CMGS=22",
+                "0001000B916407281553F800000AE8329BFD4697D9EC37"
+            ]
+        );
+
+        modem.set_sms_mode(SmsMode::Text);
+        assert_eq!(modem.send_sms("+46708251358", "hello").await.unwrap(), 7);
+        let sent = commands(&mut received);
+        assert_eq!(
+            sent[sent.len() - 2..],
+            ["AT+CMGS=\"+46708251358\"", "hello"]
+        );
+
+        match modem.dial("123").await {
+            Err(ModemError::Command { command, result }) => {
+                assert_eq!(command, "ATD123;");
+                assert_eq!(result, AtResult::NoCarrier);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn events_are_routed() {
+        let (mut device, port) = SerialStream::pair().expect("unable to open pty pair");
+        let (mut modem, mut events) = ModemManager::new(port);
+        modem.set_signal_interval(Some(Duration::from_millis(100)));
+
+        device
+            .write_all(
+                concat!(
+                    "\r\nRING\r\n\r\n+CLIP: \"+31612345678\",145\r\n",
+                    "\r\n+CREG: 2\r\n\r\nNO CARRIER\r\n\r\n+CMT: ,28\r\n",
+                    "07917283010010F5040BC87238880900F10000993092516195800AE8329BFD4697D9EC37\r\n",
+                    "\r\n+QIND: SMS DONE\r\n",
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let device = tokio::spawn(async move {
+            // The signal quality is polled after an interval.
+            let mut buf = [0u8; 7];
+            device.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"AT+CSQ\r");
+            device
+                .write_all(b"\r\n+CSQ: 31,0\r\n\r\nOK\r\n")
+                .await
+                .unwrap();
+            device
+        });
+
+        let mut received = Vec::new();
+        while received.len() < 7 {
+            tokio::select! {
+                closed = modem.listen() => panic!("listen ended: {:?}", closed),
+                Some(event) = events.next() => received.push(event),
+            }
+        }
+        let sms = match received.remove(4) {
+            ModemEvent::Sms(sms) => sms,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(sms.address, "27838890001");
+        assert_eq!(
+            received,
+            [
+                ModemEvent::Ring,
+                ModemEvent::CallerId("+31612345678".to_string()),
+                ModemEvent::Registration(Registration::Searching),
+                ModemEvent::CallEnded,
+                ModemEvent::Other("+QIND: SMS DONE".to_string()),
+                ModemEvent::Signal(SignalQuality {
+                    rssi: Some(-51),
+                    ber: Some(0)
+                }),
+            ]
+        );
+        device.await.unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+use bytes::BytesMut;
+use serde::{Deserialize, Serialize};
+use tokio_serial::codec::{LengthDelimitedCodec, LengthError, MessagePackCodec, MessagePackError};
+use tokio_util::codec::{Decoder, Encoder};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Status {
+    uptime: u32,
+    ok: bool,
+}
+
+#[test]
+fn values_are_length_prefixed() {
+    let mut codec = MessagePackCodec::<Status>::new();
+    let mut dst = BytesMut::new();
+    codec
+        .encode(
+            Status {
+                uptime: 5,
+                ok: true,
+            },
+            &mut dst,
+        )
+        .unwrap();
+    assert_eq!(&dst[..], b"\x00\x0d\x82\xa6uptime\x05\xa2ok\xc3");
+
+    let mut compact = MessagePackCodec::<Status>::new().compact();
+    compact
+        .encode(
+            &Status {
+                uptime: 300,
+                ok: false,
+            },
+            &mut dst,
+        )
+        .unwrap();
+    assert_eq!(&dst[15..], b"\x00\x05\x92\xcd\x01\x2c\xc2");
+
+    // Both forms decode, frames split across reads are buffered.
+    let mut src = BytesMut::new();
+    let mut decoded = Vec::new();
+    for chunk in dst.chunks(3) {
+        src.extend_from_slice(chunk);
+        while let Some(status) = codec.decode(&mut src).unwrap() {
+            decoded.push(status);
+        }
+    }
+    assert_eq!(
+        decoded,
+        [
+            Status {
+                uptime: 5,
+                ok: true
+            },
+            Status {
+                uptime: 300,
+                ok: false
+            }
+        ]
+    );
+    assert!(src.is_empty());
+}
+
+#[test]
+fn bad_frames_are_skipped() {
+    let mut codec = MessagePackCodec::<Status>::new().framing(
+        LengthDelimitedCodec::new()
+            .length_field_length(1)
+            .max_frame_length(16),
+    );
+    let mut src = BytesMut::from(&b"\x02\xc1\x00\x20"[..]);
+    src.extend_from_slice(&[0; 32]);
+    src.extend_from_slice(b"\x04\x92\x07\xc3\x00\x04\x92\x07\xc2");
+
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(MessagePackError::Decode(_))
+    ));
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(MessagePackError::Length(LengthError::TooLong(32)))
+    ));
+    // A frame holding a value followed by garbage still decodes.
+    assert_eq!(
+        codec.decode(&mut src).unwrap(),
+        Some(Status {
+            uptime: 7,
+            ok: true
+        })
+    );
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+}
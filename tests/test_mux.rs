@@ -0,0 +1,90 @@
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio_serial::mux::{self, Mux};
+
+fn link() -> (Mux, Mux) {
+    let (a, b) = tokio::io::duplex(64);
+    let (host, driver) = Mux::new(a);
+    tokio::spawn(driver);
+    let (device, driver) = Mux::new(b);
+    tokio::spawn(driver);
+    (host, device)
+}
+
+#[tokio::test]
+async fn channels_carry_their_own_data() {
+    let (host, device) = link();
+    let mut console = host.channel(0).unwrap();
+    let mut telemetry = host.channel(1).unwrap();
+    let mut device_console = device.channel(0).unwrap();
+    let mut device_telemetry = device.channel(1).unwrap();
+    assert!(host.channel(1).is_err());
+
+    console.write_all(b"reboot\n").await.unwrap();
+    device_telemetry.write_all(b"temp=21\n").await.unwrap();
+
+    let mut buf = [0u8; 7];
+    device_console.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"reboot\n");
+    let mut buf = [0u8; 8];
+    telemetry.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"temp=21\n");
+}
+
+#[tokio::test]
+async fn unread_channels_do_not_block_the_others() {
+    let (host, device) = link();
+    device.set_window(16);
+    let mut bulk = host.channel(0).unwrap();
+    let mut console = host.channel(1).unwrap();
+    let mut device_bulk = device.channel(0).unwrap();
+    let mut device_console = device.channel(1).unwrap();
+
+    // The device grants 16 bytes and does not read.
+    bulk.write_all(&[0x42; 100]).await.unwrap();
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), bulk.flush())
+            .await
+            .is_err()
+    );
+
+    console.write_all(b"status").await.unwrap();
+    let mut buf = [0u8; 6];
+    device_console.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"status");
+
+    let mut buf = [0u8; 100];
+    device_bulk.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, [0x42; 100]);
+    tokio::time::timeout(Duration::from_secs(1), bulk.flush())
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn corrupted_frames_are_dropped() {
+    let (raw, port): (DuplexStream, DuplexStream) = tokio::io::duplex(64);
+    let (mux, driver) = Mux::new(port);
+    let driver = tokio::spawn(driver);
+    let mut channel = mux.channel(3).unwrap();
+
+    let mut frame = Vec::new();
+    mux::frame(3, mux::DATA, b"ok", |chunk| frame.extend_from_slice(chunk)).unwrap();
+    let mut corrupted = frame.clone();
+    corrupted[5] ^= 0xff;
+    let mut raw = raw;
+    raw.write_all(&corrupted).await.unwrap();
+    raw.write_all(&frame).await.unwrap();
+
+    let mut buf = [0u8; 2];
+    channel.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ok");
+    assert_eq!(mux.corrupted_frames(), 1);
+
+    // The driver stops at end of file, and the channels with it.
+    drop(raw);
+    driver.await.unwrap().unwrap();
+    assert_eq!(channel.read(&mut buf).await.unwrap(), 0);
+    assert!(channel.write_all(b"late").await.is_err());
+}
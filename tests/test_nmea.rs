@@ -0,0 +1,151 @@
+use bytes::BytesMut;
+use futures::StreamExt;
+use tokio_serial::codec::nmea::{FixMode, NmeaError, Satellite, Sentence, UtcTime};
+use tokio_serial::codec::{FrameError, GpsClient, NmeaCodec};
+use tokio_util::codec::{Decoder, Encoder};
+
+// A sentence with its checksum
+fn line(body: &str) -> String {
+    let sum = body.bytes().fold(0, |sum, byte| sum ^ byte);
+    format!("${}*{:02X}\r\n", body, sum)
+}
+
+async fn fixes(input: &str) -> (Vec<tokio_serial::codec::nmea::Fix>, u64) {
+    let mut client = GpsClient::from_port(input.as_bytes());
+    let mut fixes = Vec::new();
+    while let Some(fix) = client.next().await {
+        fixes.push(fix.unwrap());
+    }
+    (fixes, client.get_ref().decoder().errors())
+}
+
+#[test]
+fn sentences_round_trip() {
+    let mut codec = NmeaCodec::new();
+    let sentence = Sentence {
+        address: "GPGGA".to_owned(),
+        fields: vec!["123519".to_owned(), String::new(), "N".to_owned()],
+    };
+    let mut buf = BytesMut::new();
+    codec.encode(&sentence, &mut buf).unwrap();
+    assert_eq!(&buf[..], line("GPGGA,123519,,N").as_bytes());
+
+    buf.extend_from_slice(b"noise$PGRME,15.0,M*");
+    let decoded = codec.decode(&mut buf).unwrap().unwrap();
+    assert_eq!(decoded, sentence);
+    assert_eq!(decoded.talker(), "GP");
+    assert_eq!(decoded.kind(), "GGA");
+    assert_eq!(decoded.field(1), None);
+    assert_eq!(decoded.field(2), Some("N"));
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+    buf.extend_from_slice(b"00\r\n$PGRMZ,93,f\n");
+    assert!(matches!(
+        codec.decode(&mut buf),
+        Err(FrameError::Frame(NmeaError::Checksum { found: 0, .. }))
+    ));
+    // Sentences without checksum are accepted by default.
+    let proprietary = codec.decode(&mut buf).unwrap().unwrap();
+    assert_eq!(proprietary.talker(), "");
+    assert_eq!(proprietary.kind(), "PGRMZ");
+
+    let mut codec = NmeaCodec::new().require_checksum(true);
+    buf.extend_from_slice(b"$GPTXT,01\r\n");
+    assert!(matches!(
+        codec.decode(&mut buf),
+        Err(FrameError::Frame(NmeaError::MissingChecksum))
+    ));
+}
+
+#[test]
+fn long_sentences_are_skipped() {
+    let mut codec = NmeaCodec::new().max_length(20);
+    let mut buf = BytesMut::from(&b"$GPTXT,0123456789012345678901234567890"[..]);
+    assert!(matches!(
+        codec.decode(&mut buf),
+        Err(FrameError::Frame(NmeaError::TooLong))
+    ));
+    buf.extend_from_slice(line("GPTXT,1").as_bytes());
+    let sentence = codec.decode(&mut buf).unwrap().unwrap();
+    assert_eq!(sentence.fields, ["1"]);
+}
+
+#[tokio::test]
+async fn sentences_are_aggregated_into_fixes() {
+    let input = [
+        line("GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,"),
+        line("GPGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1"),
+        line("GPGSV,2,1,05,01,40,083,46,02,17,308,41,12,07,344,39,14,22,228,"),
+        "$GPGSV,2,2,05,17,42,270,45*00\r\n".to_owned(),
+        line("GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W"),
+        line("GPRMC,123520,V,,,,,,,230394,,"),
+        line("GPGSV,2,2,05,17,42,270,45"),
+        line("GPGSV,1,1,01,17,42,270,45"),
+    ]
+    .concat();
+    let (fixes, checksum_errors) = fixes(&input).await;
+    assert_eq!(checksum_errors, 1);
+    assert_eq!(fixes.len(), 2);
+
+    let fix = &fixes[0];
+    assert_eq!(
+        fix.time,
+        Some(UtcTime {
+            hour: 12,
+            minute: 35,
+            second: 19.0
+        })
+    );
+    assert!(fix.valid);
+    assert!((fix.latitude.unwrap() - 48.1173).abs() < 1e-6);
+    assert!((fix.longitude.unwrap() - 11.516_666).abs() < 1e-6);
+    assert_eq!(fix.altitude, Some(545.4));
+    assert!((fix.speed.unwrap() - 11.523_556).abs() < 1e-6);
+    assert_eq!(fix.course, Some(84.4));
+    assert_eq!(fix.date.unwrap().year, 1994);
+    assert_eq!(fix.quality, Some(1));
+    assert_eq!(fix.mode, Some(FixMode::Fix3D));
+    assert_eq!(fix.satellites_used, Some(8));
+    assert_eq!(
+        (fix.pdop, fix.hdop, fix.vdop),
+        (Some(2.5), Some(1.3), Some(2.1))
+    );
+    // The second part of the GSV group was corrupted.
+    assert!(fix.satellites.is_empty());
+
+    let fix = &fixes[1];
+    assert!(!fix.valid);
+    assert_eq!(fix.latitude, None);
+    assert_eq!(fix.speed, None);
+    assert_eq!(fix.mode, None);
+    assert_eq!(
+        fix.satellites,
+        [Satellite {
+            prn: 17,
+            elevation: Some(42),
+            azimuth: Some(270),
+            snr: Some(45)
+        }]
+    );
+}
+
+#[tokio::test]
+async fn satellites_of_all_constellations_are_kept() {
+    let input = [
+        line("GPGSV,2,1,05,01,40,083,46,02,17,308,41,12,07,344,39,14,22,228,"),
+        line("GPGSV,2,2,05,17,42,270,45"),
+        line("GLGSV,1,1,01,65,10,100,"),
+        line("GNRMC,000001,A,0000.000,S,00000.000,W,0,,010180,,"),
+        line("GNRMC,000002,A,0000.000,S,00000.000,W,0,,010180,,"),
+    ]
+    .concat();
+    let (fixes, _) = fixes(&input).await;
+    assert_eq!(fixes.len(), 2);
+    for fix in &fixes {
+        let prns: Vec<_> = fix.satellites.iter().map(|s| s.prn).collect();
+        assert_eq!(prns, [65, 1, 2, 12, 14, 17]);
+    }
+    assert_eq!(fixes[0].satellites[0].snr, None);
+    assert_eq!(fixes[0].satellites[4].snr, None);
+    assert_eq!(fixes[1].date.unwrap().year, 1980);
+}
@@ -0,0 +1,97 @@
+use bytes::BytesMut;
+use tokio_serial::codec::p1::{P1Error, Telegram, Value};
+use tokio_serial::codec::{FrameError, P1Codec};
+use tokio_util::codec::{Decoder, Encoder};
+
+const DATA: &str = "\r\n\
+    1-3:0.2.8(50)\r\n\
+    0-0:1.0.0(170124213128W)\r\n\
+    1-0:1.8.1(000001.234*kWh)\r\n\
+    1-0:1.7.0(00.191*kW)\r\n\
+    0-1:24.2.1(170124210000W)(00671.790*m3)\r\n";
+
+fn telegram() -> Telegram {
+    Telegram {
+        header: "ISK5\\2M550T-1012".to_owned(),
+        data: DATA.to_owned(),
+    }
+}
+
+fn value(value: &str, unit: Option<&str>) -> Value {
+    Value {
+        value: value.to_owned(),
+        unit: unit.map(str::to_owned),
+    }
+}
+
+#[test]
+fn telegrams_round_trip() {
+    let mut codec = P1Codec::new();
+    let mut buf = BytesMut::from(&b"(00.191*kW)\r\n!12AB\r\n"[..]);
+    codec.encode(&telegram(), &mut buf).unwrap();
+    assert!(buf.ends_with(b"\r\n"));
+
+    // The end of a telegram whose start was missed is skipped.
+    let decoded = codec.decode(&mut buf).unwrap().unwrap();
+    assert_eq!(decoded, telegram());
+    assert!(buf.is_empty());
+
+    let objects = decoded.objects();
+    assert_eq!(objects.len(), 5);
+    assert_eq!(objects[2].obis, "1-0:1.8.1");
+    assert_eq!(objects[2].values, [value("000001.234", Some("kWh"))]);
+    assert_eq!(
+        decoded.get("0-1:24.2.1").unwrap().values,
+        [value("170124210000W", None), value("00671.790", Some("m3"))]
+    );
+    assert_eq!(decoded.get("1-0:2.8.1"), None);
+}
+
+#[test]
+fn crc_mismatches_are_reported() {
+    let mut codec = P1Codec::new();
+    let mut buf = BytesMut::new();
+    codec.encode(&telegram(), &mut buf).unwrap();
+    let digit = buf.len() - 3;
+    buf[digit] = if buf[digit] == b'0' { b'1' } else { b'0' };
+    codec.encode(&telegram(), &mut buf).unwrap();
+
+    match codec.decode(&mut buf) {
+        Err(FrameError::Frame(P1Error::Crc { .. })) => {}
+        other => panic!("expected a CRC mismatch, got {:?}", other),
+    }
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(telegram()));
+}
+
+#[test]
+fn cut_short_telegrams_are_dropped() {
+    let mut codec = P1Codec::new();
+    let mut buf = BytesMut::from(&b"/ISK5\\2M550T-1012\r\n\r\n1-3:0.2.8(50)\r\n1-0:1.8"[..]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    buf.extend_from_slice(b"\r\n");
+    let mut next = BytesMut::new();
+    codec.encode(&telegram(), &mut next).unwrap();
+    buf.extend_from_slice(&next);
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(telegram()));
+}
+
+#[test]
+fn telegrams_without_crc() {
+    // DSMR 2.2, with the gas reading on a line of its own.
+    let text = &b"/KMP5 KA6U001585575011\r\n\r\n\
+        0-1:24.3.0(121030140000)(00)(60)(1)(0-1:24.2.1)(m3)\r\n\
+        (00001.001)\r\n\
+        !\r\n"[..];
+    let mut codec = P1Codec::new();
+    let telegram = codec.decode(&mut BytesMut::from(text)).unwrap().unwrap();
+    assert_eq!(telegram.header, "KMP5 KA6U001585575011");
+    let gas = telegram.get("0-1:24.3.0").unwrap();
+    assert_eq!(gas.values.len(), 7);
+    assert_eq!(gas.values[6], value("00001.001", None));
+
+    let mut codec = P1Codec::new().require_crc(true);
+    match codec.decode(&mut BytesMut::from(text)) {
+        Err(FrameError::Frame(P1Error::MissingCrc)) => {}
+        other => panic!("expected a missing CRC, got {:?}", other),
+    }
+}
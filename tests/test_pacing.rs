@@ -0,0 +1,43 @@
+#![cfg(unix)]
+
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::pacing::PacedWriter;
+use tokio_serial::{Parity, SerialSettings, SerialStream};
+
+#[tokio::test]
+async fn writes_take_the_line_time() {
+    let (master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    // 11 bits per character at 9600 baud.
+    let mut settings = SerialSettings::new(9600);
+    settings.parity = Parity::Even;
+    let mut port = PacedWriter::new(master, &settings);
+
+    let started = Instant::now();
+    port.write_all(&[0x55; 87]).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(95));
+
+    let mut buf = [0u8; 87];
+    slave.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, [0x55; 87]);
+}
+
+#[tokio::test]
+async fn gaps_separate_characters_and_frames() {
+    let (master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut port = PacedWriter::new(master, &SerialSettings::new(115_200));
+    port.set_char_gap(Duration::from_millis(10));
+    port.set_frame_gap(Duration::from_millis(50));
+
+    let started = Instant::now();
+    port.write_all(b"abc").await.unwrap();
+    port.flush().await.unwrap();
+    // The last character of the frame is followed by its gap.
+    assert!(started.elapsed() >= Duration::from_millis(20));
+    port.write_all(b"d").await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(80));
+
+    let mut buf = [0u8; 4];
+    slave.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"abcd");
+}
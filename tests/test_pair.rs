@@ -0,0 +1,607 @@
+#![cfg(unix)]
+
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::{
+    ClearBuffer, ControlChar, DataBits, FlowControl, LineEvent, Parity, SerialPort, SerialStream,
+    StopBits,
+};
+
+async fn wait_for_input(port: &SerialStream, n: u32) {
+    for _ in 0..100 {
+        if port.bytes_to_read().expect("unable to query input buffer") >= n {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("timed out waiting for {} bytes of input", n);
+}
+
+#[tokio::test]
+async fn clear_buffers_discards_stale_input() {
+    let (mut master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+
+    master.write_all(b"stale").await.unwrap();
+    wait_for_input(&slave, 5).await;
+    // Make sure the stream has seen the stale data as readiness before it is purged.
+    slave.readable().await.unwrap();
+
+    slave
+        .clear_buffers(ClearBuffer::Input)
+        .await
+        .expect("unable to clear buffers");
+    assert_eq!(slave.bytes_to_read().unwrap(), 0);
+
+    master.write_all(b"fresh").await.unwrap();
+    let mut buf = [0u8; 5];
+    tokio::time::timeout(Duration::from_secs(1), slave.read_exact(&mut buf))
+        .await
+        .expect("timed out reading fresh data")
+        .unwrap();
+    assert_eq!(&buf, b"fresh");
+}
+
+#[tokio::test]
+async fn readable_at_least_waits_for_whole_frame() {
+    let (mut master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+
+    let writer = tokio::spawn(async move {
+        for byte in b"frame" {
+            master.write_all(&[*byte]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        master
+    });
+
+    tokio::time::timeout(Duration::from_secs(1), slave.readable_at_least(5))
+        .await
+        .expect("timed out waiting for the frame")
+        .unwrap();
+    assert!(slave.bytes_to_read().unwrap() >= 5);
+
+    let mut buf = [0u8; 8];
+    assert_eq!(slave.try_read(&mut buf).unwrap(), 5);
+    assert_eq!(&buf[..5], b"frame");
+    writer.await.unwrap();
+}
+
+#[tokio::test]
+async fn wait_tx_below_returns_once_drained() {
+    let (mut master, _slave) = SerialStream::pair().expect("unable to open pty pair");
+
+    master.write_all(b"drained").await.unwrap();
+    tokio::time::timeout(Duration::from_secs(1), master.wait_tx_below(0))
+        .await
+        .expect("timed out waiting for the output buffer to drain")
+        .unwrap();
+    assert_eq!(master.bytes_to_write().unwrap(), 0);
+}
+
+#[tokio::test]
+async fn apply_settings_applies_all_settings() {
+    let (_master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+
+    let settings = tokio_serial::SerialSettings {
+        baud_rate: 57_600,
+        data_bits: DataBits::Seven,
+        parity: Parity::Even,
+        stop_bits: StopBits::Two,
+        flow_control: FlowControl::Hardware,
+    };
+    slave
+        .apply_settings(&settings)
+        .expect("unable to apply the settings");
+
+    // Pseudo terminals force 8 data bits without parity, only check the rest.
+    assert_eq!(slave.baud_rate().unwrap(), 57_600);
+    assert_eq!(slave.stop_bits().unwrap(), StopBits::Two);
+    assert_eq!(slave.flow_control().unwrap(), FlowControl::Hardware);
+}
+
+#[tokio::test]
+async fn reconfigure_applies_the_settings_of_a_builder() {
+    let (_master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+
+    // An odd path and a non-standard baud rate, both only readable from `Debug`.
+    let builder = tokio_serial::new("/dev/weird \"port\" \u{7f}é", 31_250)
+        .stop_bits(StopBits::Two)
+        .flow_control(FlowControl::Software)
+        .timeout(Duration::from_millis(5));
+    slave
+        .reconfigure(&builder)
+        .expect("unable to apply the builder");
+
+    assert_eq!(slave.baud_rate().unwrap(), 31_250);
+    assert_eq!(slave.stop_bits().unwrap(), StopBits::Two);
+    assert_eq!(slave.flow_control().unwrap(), FlowControl::Software);
+}
+
+#[tokio::test]
+async fn settings_round_trip_through_builder() {
+    let (_master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+
+    let settings = tokio_serial::SerialSettings {
+        stop_bits: StopBits::Two,
+        ..tokio_serial::SerialSettings::new(38_400)
+    };
+    slave.apply_settings(&settings).unwrap();
+    assert_eq!(slave.settings().unwrap(), settings);
+
+    let builder = slave.to_builder().unwrap();
+    assert_eq!(builder, settings.builder(slave.name().unwrap_or_default()));
+}
+
+#[tokio::test]
+async fn paused_reads_resume_with_pending_data() {
+    let (mut master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+
+    slave.pause_reads().unwrap();
+    assert!(slave.reads_paused());
+    master.write_all(b"ping").await.unwrap();
+    wait_for_input(&slave, 4).await;
+
+    let mut buf = [0u8; 4];
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), slave.read(&mut buf))
+            .await
+            .is_err(),
+        "read completed while paused"
+    );
+
+    slave.resume_reads().unwrap();
+    slave.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[tokio::test]
+async fn error_marking_unescapes_literal_marks() {
+    let (mut master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+
+    slave.set_error_marking(true).unwrap();
+    assert!(slave.error_marking());
+    master.write_all(&[b'a', 0xff, b'b']).await.unwrap();
+    wait_for_input(&slave, 4).await;
+
+    let events = slave.read_with_errors().await.unwrap();
+    assert_eq!(events, [LineEvent::Data(vec![b'a', 0xff, b'b'])]);
+
+    slave.set_error_marking(false).unwrap();
+    assert!(!slave.error_marking());
+}
+
+#[tokio::test]
+async fn break_reporting_keeps_the_data_intact() {
+    use futures::StreamExt;
+
+    let (mut master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut breaks = slave.break_events().unwrap();
+
+    // Marked as `\377\377` and `\377\0\0` would be a break.
+    master.write_all(&[b'a', 0xff, 0, 0, b'b']).await.unwrap();
+    wait_for_input(&slave, 6).await;
+    let mut buf = [0u8; 5];
+    slave.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, [b'a', 0xff, 0, 0, b'b']);
+
+    // Still stripped after error marking is turned off.
+    slave.set_error_marking(true).unwrap();
+    slave.set_error_marking(false).unwrap();
+    master.write_all(&[0xff]).await.unwrap();
+    wait_for_input(&slave, 2).await;
+    let mut byte = [0u8; 1];
+    slave.read_exact(&mut byte).await.unwrap();
+    assert_eq!(byte, [0xff]);
+
+    // A new stream takes over, the old one ends.
+    let _new = slave.break_events().unwrap();
+    assert!(breaks.next().await.is_none());
+}
+
+#[tokio::test]
+async fn canonical_reads_complete_on_lines() {
+    let (mut master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+
+    slave.set_control_char(ControlChar::Eol, b';').unwrap();
+    assert_eq!(slave.control_char(ControlChar::Eol).unwrap(), b';');
+    slave.set_canonical(true).unwrap();
+    assert!(slave.canonical().unwrap());
+
+    master.write_all(b"ab").await.unwrap();
+    let mut buf = [0u8; 16];
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), slave.read(&mut buf))
+            .await
+            .is_err(),
+        "read completed without a line terminator"
+    );
+
+    master.write_all(b"c;de").await.unwrap();
+    let n = slave.read(&mut buf).await.unwrap();
+    assert_eq!(&buf[..n], b"abc;");
+}
+
+#[tokio::test]
+async fn write_stats_count_accepted_bytes() {
+    let (_master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+
+    slave.write_all(b"hello").await.unwrap();
+    assert_eq!(slave.try_write(b"!").unwrap(), 1);
+    assert_eq!(slave.write_stats().unwrap().accepted, 6);
+
+    let stats = slave.wait_transmitted().await.unwrap();
+    assert_eq!(stats.queued, 0);
+    assert_eq!(stats.transmitted, 6);
+}
+
+#[tokio::test]
+async fn reads_are_timestamped_on_arrival() {
+    let (mut master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+
+    let before = std::time::Instant::now();
+    master.write_all(b"tick").await.unwrap();
+    wait_for_input(&slave, 4).await;
+
+    let mut buf = [0u8; 8];
+    let (n, at) = slave.read_timestamped(&mut buf).await.unwrap();
+    assert_eq!(&buf[..n], b"tick");
+    assert!(at >= before && at <= std::time::Instant::now());
+}
+
+#[tokio::test]
+async fn hangup_is_reported_as_disconnection() {
+    let (master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+
+    let closed = tokio::time::timeout(Duration::from_millis(100), slave.closed()).await;
+    assert!(closed.is_err(), "closed before the other end went away");
+
+    drop(master);
+    tokio::time::timeout(Duration::from_secs(2), slave.closed())
+        .await
+        .expect("hangup not noticed")
+        .unwrap();
+
+    let mut buf = [0u8; 16];
+    let err = slave.read(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotConnected);
+}
+
+#[tokio::test]
+async fn ports_open_through_symlinks() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let name = slave.name().expect("pty without a name");
+
+    let dir = std::env::temp_dir().join(format!("tokio-serial-by-id-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let link = dir.join("usb-Test_Adapter_0001-if00-port0");
+    let _ = std::fs::remove_file(&link);
+    std::os::unix::fs::symlink(&name, &link).unwrap();
+
+    let info = tokio_serial::SerialPortInfo {
+        port_name: name,
+        port_type: tokio_serial::SerialPortType::Unknown,
+    };
+    // udev does not create stable links for ptys.
+    if cfg!(target_os = "linux") {
+        assert_eq!(tokio_serial::inventory::stable_path(&info), None);
+    }
+
+    let mut port = SerialStream::open(&tokio_serial::new(link.to_str().unwrap(), 9600)).unwrap();
+    master.write_all(b"hi").await.unwrap();
+    let mut buf = [0u8; 2];
+    port.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hi");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn stable_paths_resolve_udev_style_links() {
+    use tokio_serial::inventory::stable_path_in;
+
+    let (_master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let (_master, other) = SerialStream::pair().expect("unable to open pty pair");
+    let info = tokio_serial::SerialPortInfo {
+        port_name: slave.name().expect("pty without a name"),
+        port_type: tokio_serial::SerialPortType::Unknown,
+    };
+
+    let root = std::env::temp_dir().join(format!("tokio-serial-serial-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let by_id = root.join("by-id");
+    let by_path = root.join("by-path");
+    std::fs::create_dir_all(&by_id).unwrap();
+    std::fs::create_dir_all(&by_path).unwrap();
+    let link = |dir: &std::path::Path, name: &str, target: &str| {
+        let link = dir.join(name);
+        std::os::unix::fs::symlink(target, &link).unwrap();
+        link.display().to_string()
+    };
+
+    let other_name = other.name().expect("pty without a name");
+    link(&by_id, "usb-Other_Adapter_0002-if00-port0", &other_name);
+    let path = link(
+        &by_path,
+        "pci-0000:00:14.0-usb-0:2:1.0-port0",
+        &info.port_name,
+    );
+    let dirs = [&by_id, &by_path];
+    // No link of the port in by-id, the by-path one is used.
+    assert_eq!(stable_path_in(&info, &dirs), Some(path));
+
+    link(&by_id, "usb-Test_Adapter_0001-if01-port0", &info.port_name);
+    let first = link(&by_id, "usb-Test_Adapter_0001-if00-port0", &info.port_name);
+    assert_eq!(stable_path_in(&info, &dirs), Some(first));
+
+    let missing = tokio_serial::SerialPortInfo {
+        port_name: "/dev/tokio-serial-missing".to_string(),
+        ..info.clone()
+    };
+    assert_eq!(stable_path_in(&missing, &dirs), None);
+    assert_eq!(stable_path_in(&info, &[root.join("by-uuid")]), None);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn waiting_for_a_port_opens_it_once_it_appears() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let name = slave.name().expect("pty without a name");
+    let link = std::env::temp_dir().join(format!("tokio-serial-wait-{}", std::process::id()));
+    let _ = std::fs::remove_file(&link);
+    let path = link.to_str().unwrap().to_string();
+    let builder = tokio_serial::new("", 9600);
+
+    let err =
+        tokio_serial::inventory::wait_for_port(path.as_str(), &builder, Duration::from_millis(300))
+            .await
+            .unwrap_err();
+    assert_eq!(
+        err.kind(),
+        tokio_serial::ErrorKind::Io(std::io::ErrorKind::TimedOut)
+    );
+
+    let appear = {
+        let link = link.clone();
+        async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            std::os::unix::fs::symlink(&name, &link).unwrap();
+        }
+    };
+    let (port, ()) = tokio::join!(
+        tokio_serial::inventory::wait_for_port(path, &builder, Duration::from_secs(2)),
+        appear
+    );
+    let mut port = port.unwrap();
+
+    master.write_all(b"up").await.unwrap();
+    let mut buf = [0u8; 2];
+    port.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"up");
+
+    std::fs::remove_file(&link).unwrap();
+}
+
+#[tokio::test]
+async fn pty_details_have_no_usb_interface() {
+    let (_master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let info = tokio_serial::SerialPortInfo {
+        port_name: slave.name().expect("pty without a name"),
+        port_type: tokio_serial::SerialPortType::Unknown,
+    };
+
+    let details = tokio_serial::inventory::details(info.clone());
+    assert_eq!(details.info, info);
+    assert_eq!(details.interface, None);
+    assert_eq!(details.uucp_locked, Some(false));
+}
+
+#[cfg(feature = "rt")]
+#[tokio::test]
+async fn open_async_registers_the_port_once_open() {
+    use tokio_serial::SerialPortBuilderExt;
+
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let name = slave.name().expect("pty without a name");
+
+    let mut port = tokio_serial::new(name, 9600).open_async().await.unwrap();
+    port.write_all(b"hi").await.unwrap();
+    let mut buf = [0u8; 2];
+    master.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hi");
+}
+
+#[cfg(feature = "rt")]
+#[tokio::test]
+async fn open_with_timeout_runs_on_the_blocking_pool() {
+    use tokio_serial::SerialPortBuilderExt;
+
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let name = slave.name().expect("pty without a name");
+
+    let mut port = tokio_serial::new(name, 9600)
+        .open_async_timeout(Duration::from_secs(2))
+        .await
+        .unwrap();
+    master.write_all(b"ok").await.unwrap();
+    let mut buf = [0u8; 2];
+    port.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ok");
+
+    let err = tokio_serial::new("/dev/tokio-serial-missing", 9600)
+        .open_async_timeout(Duration::from_secs(2))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.kind(),
+        tokio_serial::ErrorKind::Io(std::io::ErrorKind::NotFound)
+    );
+}
+
+#[tokio::test]
+async fn open_retries_until_the_port_appears() {
+    use tokio_serial::retry::RetryPolicy;
+    use tokio_serial::SerialPortBuilderExt;
+
+    let (_master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let name = slave.name().expect("pty without a name");
+    let link = std::env::temp_dir().join(format!("tokio-serial-retry-{}", std::process::id()));
+    let _ = std::fs::remove_file(&link);
+    let builder = tokio_serial::new(link.to_str().unwrap(), 9600);
+
+    let policy = RetryPolicy::fixed(Duration::from_millis(50)).max_attempts(3);
+    let err = builder.clone().open_async_retry(&policy).await.unwrap_err();
+    assert_eq!(
+        err.kind(),
+        tokio_serial::ErrorKind::Io(std::io::ErrorKind::NotFound)
+    );
+
+    let appear = async {
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        std::os::unix::fs::symlink(&name, &link).unwrap();
+    };
+    let policy = RetryPolicy::fixed(Duration::from_millis(50)).max_attempts(20);
+    let (port, ()) = tokio::join!(builder.open_async_retry(&policy), appear);
+    port.unwrap();
+
+    std::fs::remove_file(&link).unwrap();
+}
+
+#[tokio::test]
+async fn validation_reports_unsupported_settings() {
+    use tokio_serial::validation::Problem;
+    use tokio_serial::{PortConfig, SerialPortBuilderExt, SerialSettings};
+
+    let (_master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let name = slave.name().expect("pty without a name");
+
+    let config = |path: &str, baud_rate| PortConfig {
+        path: path.to_string(),
+        settings: SerialSettings::new(baud_rate),
+    };
+    let report = config(&name, 115_200).validate().unwrap();
+    assert!(report.is_supported(), "{:?}", report);
+    assert_eq!(report.settings.baud_rate, 115_200);
+
+    let report = config(&name, 0).validate().unwrap();
+    assert_eq!(
+        report.problems,
+        [Problem::BaudRate {
+            requested: 0,
+            max: None
+        }]
+    );
+    let err = report.into_result().unwrap_err();
+    assert_eq!(err.kind(), tokio_serial::ErrorKind::InvalidInput);
+    assert_eq!(err.description, "baud rate 0 is not supported");
+
+    // Builders are validated the same.
+    let builder = tokio_serial::new(&name, 0).parity(Parity::Odd);
+    let expected = PortConfig {
+        path: name.clone(),
+        settings: SerialSettings {
+            parity: Parity::Odd,
+            ..SerialSettings::new(0)
+        },
+    }
+    .validate()
+    .unwrap();
+    assert_eq!(builder.validate().unwrap(), expected);
+
+    let err = config("/dev/tokio-serial-missing", 9600)
+        .validate()
+        .unwrap_err();
+    assert_eq!(
+        err.kind(),
+        tokio_serial::ErrorKind::Io(std::io::ErrorKind::NotFound)
+    );
+}
+
+#[tokio::test]
+async fn native_streams_expose_termios() {
+    use tokio_serial::{SerialPortBuilderExt, UnixSerialStream};
+
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let name = slave.name().expect("pty without a name");
+
+    let mut port: UnixSerialStream = tokio_serial::new(name, 9600).open_platform_async().unwrap();
+    let mut termios = port.termios().unwrap();
+    termios.c_cflag ^= libc::HUPCL;
+    port.set_termios(&termios).unwrap();
+    assert_eq!(port.termios().unwrap().c_cflag, termios.c_cflag);
+
+    #[cfg(target_os = "linux")]
+    assert!(port.rs485().is_err(), "ptys have no RS-485 mode");
+
+    port.write_all(b"ok").await.unwrap();
+    let mut buf = [0u8; 2];
+    master.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ok");
+
+    let portable: SerialStream = port.into();
+    assert!(portable.name().is_some());
+}
+
+#[tokio::test]
+async fn settings_watcher_applies_changes() {
+    use tokio_serial::{SerialSettings, SettingsEvent, SettingsWatcher};
+
+    let (_master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    let initial = slave.settings().unwrap();
+    let (sender, receiver) = tokio::sync::watch::channel(initial);
+    let mut watcher = SettingsWatcher::new(receiver);
+
+    let changed = SerialSettings {
+        baud_rate: 19200,
+        stop_bits: StopBits::Two,
+        ..initial
+    };
+    sender.send(changed).unwrap();
+    match watcher.apply_next(&mut slave).await {
+        Some(SettingsEvent::Applied(settings)) => assert_eq!(settings, changed),
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert_eq!(slave.settings().unwrap(), changed);
+
+    // Unchanged settings are applied as they are, until the sender goes away.
+    sender.send(changed).unwrap();
+    drop(sender);
+    match watcher.apply_next(&mut slave).await {
+        Some(SettingsEvent::Applied(settings)) => assert_eq!(settings, changed),
+        event => panic!("unexpected event {:?}", event),
+    }
+    let event = watcher.apply_next(&mut slave).await;
+    assert!(event.is_none(), "{:?}", event);
+}
+
+#[tokio::test]
+async fn settings_watcher_leaves_the_port_to_the_caller() {
+    use tokio_serial::{SerialSettings, SettingsEvent, SettingsWatcher};
+
+    let (mut master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    let initial = slave.settings().unwrap();
+    let (sender, receiver) = tokio::sync::watch::channel(initial);
+    let mut watcher = SettingsWatcher::new(receiver);
+    assert_eq!(watcher.next().await, Some(initial));
+
+    master.write_all(b"hi").await.unwrap();
+    let changed = SerialSettings {
+        baud_rate: 38400,
+        ..initial
+    };
+    let mut buf = [0u8; 2];
+    let mut events = Vec::new();
+    while events.is_empty() || buf != *b"hi" {
+        tokio::select! {
+            Some(settings) = watcher.next() => events.push(watcher.apply(&mut slave, settings).await),
+            read = slave.read_exact(&mut buf) => {
+                read.unwrap();
+                sender.send(changed).unwrap();
+            }
+        }
+    }
+    match events.as_slice() {
+        [SettingsEvent::Applied(settings)] => assert_eq!(*settings, changed),
+        events => panic!("unexpected events {:?}", events),
+    }
+    assert_eq!(slave.settings().unwrap(), changed);
+}
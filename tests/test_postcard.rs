@@ -0,0 +1,56 @@
+use bytes::BytesMut;
+use serde::{Deserialize, Serialize};
+use tokio_serial::codec::cobs::CobsError;
+use tokio_serial::codec::{PostcardCodec, PostcardError};
+use tokio_util::codec::{Decoder, Encoder};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Command {
+    Ping,
+    SetLed { index: u8, on: bool },
+}
+
+#[test]
+fn values_are_sent_in_cobs_frames() {
+    let mut codec = PostcardCodec::<Command>::new(64);
+    let mut dst = BytesMut::new();
+    codec
+        .encode(Command::SetLed { index: 0, on: true }, &mut dst)
+        .unwrap();
+    codec.encode(&Command::Ping, &mut dst).unwrap();
+    // Variant 1, index 0, true; then variant 0.
+    assert_eq!(&dst[..], b"\x02\x01\x02\x01\x00\x01\x01\x00");
+    assert_eq!(
+        &dst[..5],
+        &postcard::to_stdvec_cobs(&Command::SetLed { index: 0, on: true }).unwrap()[..]
+    );
+
+    assert_eq!(
+        codec.decode(&mut dst).unwrap(),
+        Some(Command::SetLed { index: 0, on: true })
+    );
+    assert_eq!(codec.decode(&mut dst).unwrap(), Some(Command::Ping));
+    assert_eq!(codec.decode(&mut dst).unwrap(), None);
+}
+
+#[test]
+fn bad_frames_are_skipped() {
+    let mut codec = PostcardCodec::<Command>::new(4);
+    let mut src = BytesMut::from(
+        // An unknown variant, a truncated block, a frame too long, then a good one.
+        &b"\x02\x07\x00\x05\x01\x00\x06\x01\x02\x03\x04\x05\x00\x01\x01\x00"[..],
+    );
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(PostcardError::Postcard(_))
+    ));
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(PostcardError::Cobs(CobsError::Truncated))
+    ));
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(PostcardError::Cobs(CobsError::TooLong))
+    ));
+    assert_eq!(codec.decode(&mut src).unwrap(), Some(Command::Ping));
+}
@@ -0,0 +1,111 @@
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_serial::power::{LinePower, PowerControl, PowerFn, PowerLine};
+use tokio_serial::AsyncSerialPort;
+
+#[tokio::test]
+async fn power_cycle_holds_power_off() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorder = events.clone();
+    let mut power = PowerFn::new(move |on| {
+        recorder.lock().unwrap().push((on, Instant::now()));
+        async { Ok(()) }
+    });
+
+    let hold = Duration::from_millis(50);
+    power.power_cycle(hold).await.unwrap();
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 2);
+    let ((first, off_at), (second, on_at)) = (events[0], events[1]);
+    assert!(!first && second);
+    assert!(on_at - off_at >= hold);
+}
+
+// Records the control lines it is asked to set, ptys have none.
+#[derive(Default)]
+struct Lines {
+    set: Vec<(&'static str, bool)>,
+}
+
+impl AsyncRead for Lines {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        _: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Lines {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSerialPort for Lines {
+    fn port_name(&self) -> Option<String> {
+        None
+    }
+
+    fn change_baud_rate(&mut self, _: u32) -> tokio_serial::Result<()> {
+        Ok(())
+    }
+
+    fn set_dtr(&mut self, level: bool) -> tokio_serial::Result<()> {
+        self.set.push(("dtr", level));
+        Ok(())
+    }
+
+    fn set_rts(&mut self, level: bool) -> tokio_serial::Result<()> {
+        self.set.push(("rts", level));
+        Ok(())
+    }
+
+    fn set_break_condition(&mut self, _: bool) -> tokio_serial::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn line_power_switches_dtr() {
+    let mut power = LinePower::dtr(Lines::default());
+    power.power_off().await.unwrap();
+    power.power_on().await.unwrap();
+    assert_eq!(power.into_inner().set, [("dtr", false), ("dtr", true)]);
+
+    let mut power = LinePower::dtr(Lines::default()).inverted();
+    power.power_off().await.unwrap();
+    power.power_on().await.unwrap();
+    assert_eq!(power.into_inner().set, [("dtr", true), ("dtr", false)]);
+}
+
+#[tokio::test]
+async fn line_power_switches_rts_and_both_lines() {
+    let mut power = LinePower::rts(Lines::default());
+    power.power_on().await.unwrap();
+    assert_eq!(power.get_mut().set, [("rts", true)]);
+
+    let mut power = LinePower::new(Lines::default(), PowerLine::Both);
+    power.power_cycle(Duration::from_millis(1)).await.unwrap();
+    assert_eq!(
+        power.into_inner().set,
+        [("dtr", false), ("rts", false), ("dtr", true), ("rts", true)]
+    );
+}
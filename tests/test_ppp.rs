@@ -0,0 +1,82 @@
+use bytes::{Bytes, BytesMut};
+use tokio_serial::codec::hdlc::HdlcError;
+use tokio_serial::codec::ppp::{protocol, PppCodec, PppError, PppFrame};
+use tokio_serial::codec::FrameError;
+use tokio_util::codec::{Decoder, Encoder};
+
+fn frame(protocol: u16, information: &'static [u8]) -> PppFrame {
+    PppFrame {
+        protocol,
+        information: Bytes::from_static(information),
+    }
+}
+
+#[test]
+fn negotiated_options_apply_to_all_but_lcp() {
+    let mut codec = PppCodec::new();
+    codec.set_send_accm(0);
+    codec.set_address_control_compression(true);
+    codec.set_protocol_compression(true);
+
+    let mut dst = BytesMut::new();
+    codec
+        .encode(frame(protocol::IPV4, b"\x45\x01"), &mut dst)
+        .unwrap();
+    // No address and control, a one byte protocol, 0x01 left unescaped.
+    assert_eq!(&dst[1..4], b"\x21\x45\x01");
+
+    let mut dst = BytesMut::new();
+    codec
+        .encode(frame(protocol::LCP, b"\x09\x01\x00\x08"), &mut dst)
+        .unwrap();
+    assert_eq!(&dst[..7], b"\x7e\xff\x7d\x23\xc0\x21\x7d");
+
+    assert!(matches!(
+        codec.encode(frame(0x0020, b""), &mut dst),
+        Err(FrameError::Frame(PppError::Unsendable(0x0020)))
+    ));
+}
+
+#[test]
+fn frames_round_trip_and_compressed_fields_are_accepted() {
+    let mut sender = PppCodec::new();
+    sender.set_address_control_compression(true);
+    sender.set_protocol_compression(true);
+    let mut receiver = PppCodec::new();
+
+    let mut line = BytesMut::new();
+    let packets = [
+        frame(protocol::IPV4, b"\x45\x00\x7e\x7d\x11"),
+        frame(protocol::IPCP, b"\x01\x02\x00\x04"),
+        frame(protocol::LCP, b"\x05\x03\x00\x04"),
+    ];
+    for packet in &packets {
+        sender.encode(packet, &mut line).unwrap();
+    }
+    for packet in &packets {
+        assert_eq!(receiver.decode(&mut line).unwrap().as_ref(), Some(packet));
+    }
+    assert_eq!(receiver.decode(&mut line).unwrap(), None);
+}
+
+#[test]
+fn broken_frames_are_reported() {
+    let mut codec = PppCodec::new();
+    let mut good = BytesMut::new();
+    codec
+        .encode(frame(protocol::IPV4, b"ok"), &mut good)
+        .unwrap();
+
+    let mut src = BytesMut::from(&good[..]);
+    let last = src.len() - 2;
+    src[last] ^= 1;
+    src.extend_from_slice(&good);
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(FrameError::Frame(PppError::Hdlc(HdlcError::Fcs)))
+    ));
+    assert_eq!(
+        codec.decode(&mut src).unwrap(),
+        Some(frame(protocol::IPV4, b"ok"))
+    );
+}
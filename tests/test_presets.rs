@@ -0,0 +1,47 @@
+use tokio_serial::{presets, DataBits, FlowControl, Parity, SerialSettings, StopBits};
+
+#[test]
+fn presets_configure_device_classes() {
+    // Builders can only be compared, not read.
+    let builder = |settings: SerialSettings| settings.builder("/dev/ttyUSB0");
+
+    assert_eq!(
+        presets::modbus_rtu("/dev/ttyUSB0"),
+        builder(SerialSettings {
+            baud_rate: 19200,
+            data_bits: DataBits::Eight,
+            parity: Parity::Even,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+        })
+    );
+    assert_eq!(
+        presets::modbus_rtu_no_parity("/dev/ttyUSB0"),
+        builder(SerialSettings {
+            stop_bits: StopBits::Two,
+            ..SerialSettings::new(19200)
+        })
+    );
+    assert_eq!(
+        presets::nmea_gps("/dev/ttyUSB0"),
+        builder(SerialSettings::new(4800))
+    );
+    assert_eq!(
+        presets::console("/dev/ttyUSB0"),
+        builder(SerialSettings::new(115_200))
+    );
+    assert_eq!(
+        presets::stm32_bootloader("/dev/ttyUSB0"),
+        builder(SerialSettings {
+            parity: Parity::Even,
+            ..SerialSettings::new(115_200)
+        })
+    );
+    assert_eq!(
+        presets::dmx512("/dev/ttyUSB0"),
+        builder(SerialSettings {
+            stop_bits: StopBits::Two,
+            ..SerialSettings::new(250_000)
+        })
+    );
+}
@@ -0,0 +1,103 @@
+use bytes::BytesMut;
+use tokio_serial::codec::{ProtobufCodec, ProtobufError};
+use tokio_util::codec::{Decoder, Encoder};
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Measurement {
+    #[prost(uint32, tag = "1")]
+    sensor: u32,
+    #[prost(sint32, tag = "2")]
+    value: i32,
+    #[prost(string, tag = "3")]
+    unit: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Request {
+    #[prost(uint32, tag = "1")]
+    sensor: u32,
+}
+
+fn measurement(sensor: u32, value: i32) -> Measurement {
+    Measurement {
+        sensor,
+        value,
+        unit: "mV".to_string(),
+    }
+}
+
+#[test]
+fn messages_are_varint_delimited() {
+    let mut codec = ProtobufCodec::<Measurement>::new();
+    let mut dst = BytesMut::new();
+    codec.encode(measurement(1, -1), &mut dst).unwrap();
+    assert_eq!(&dst[..], b"\x08\x08\x01\x10\x01\x1a\x02mV");
+
+    // A 200 byte message has a two byte prefix.
+    let long = Measurement {
+        sensor: 2,
+        value: 0,
+        unit: "x".repeat(195),
+    };
+    codec.encode(&long, &mut dst).unwrap();
+    assert_eq!(&dst[9..11], b"\xc8\x01");
+
+    let mut src = BytesMut::new();
+    let mut decoded = Vec::new();
+    for chunk in dst.chunks(7) {
+        src.extend_from_slice(chunk);
+        while let Some(message) = codec.decode(&mut src).unwrap() {
+            decoded.push(message);
+        }
+    }
+    assert_eq!(decoded, [measurement(1, -1), long]);
+}
+
+#[test]
+fn requests_and_responses_can_differ() {
+    let mut host = ProtobufCodec::<Measurement, Request>::new();
+    let mut device = ProtobufCodec::<Request, Measurement>::new();
+    let mut line = BytesMut::new();
+    host.encode(Request { sensor: 4 }, &mut line).unwrap();
+    assert_eq!(
+        device.decode(&mut line).unwrap(),
+        Some(Request { sensor: 4 })
+    );
+    device.encode(&measurement(4, 1200), &mut line).unwrap();
+    assert_eq!(host.decode(&mut line).unwrap(), Some(measurement(4, 1200)));
+}
+
+#[test]
+fn oversized_and_corrupt_messages_are_skipped() {
+    let mut codec = ProtobufCodec::<Measurement>::new().max_message_size(8);
+    let mut dst = BytesMut::new();
+    assert!(matches!(
+        codec.encode(measurement(1, 1_000_000), &mut dst),
+        Err(ProtobufError::TooLong(10))
+    ));
+    assert!(dst.is_empty());
+
+    // A message over the limit split across reads, one with an invalid wire type,
+    // then a good one.
+    let mut src = BytesMut::from(&b"\x20\x00\x00\x00"[..]);
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(ProtobufError::TooLong(32))
+    ));
+    src.extend_from_slice(&[0; 29]);
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+    src.extend_from_slice(b"\x02\x0f\x00");
+    src.extend_from_slice(b"\x08\x08\x01\x10\x01\x1a\x02mV");
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(ProtobufError::Decode(_))
+    ));
+    assert_eq!(codec.decode(&mut src).unwrap(), Some(measurement(1, -1)));
+
+    let mut src = BytesMut::from(&[0xff; 10][..]);
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(ProtobufError::InvalidLength)
+    ));
+    assert_eq!(src.len(), 9);
+}
@@ -0,0 +1,79 @@
+#![cfg(unix)]
+
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::reconnect::{ConnectionState, ReconnectingSerialStream};
+use tokio_serial::retry::RetryPolicy;
+use tokio_serial::{SerialPort, SerialStream};
+
+// A pty pair, with a link to the slave standing for a device path which survives
+// replugging.
+fn device(link: &PathBuf) -> SerialStream {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let _ = std::fs::remove_file(link);
+    std::os::unix::fs::symlink(slave.name().unwrap(), link).unwrap();
+    master
+}
+
+fn link(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("tokio-serial-{}-{}", name, std::process::id()))
+}
+
+#[tokio::test]
+async fn reads_continue_on_the_reopened_port() {
+    let link = link("reconnect");
+    let mut master = device(&link);
+    let mut port = ReconnectingSerialStream::new(tokio_serial::new(link.to_str().unwrap(), 9600));
+    port.set_policy(RetryPolicy::fixed(Duration::from_millis(10)));
+    let mut states = port.subscribe();
+    assert!(matches!(
+        port.state(),
+        ConnectionState::Disconnected { attempts: 0 }
+    ));
+
+    master.write_all(b"a").await.unwrap();
+    let mut buf = [0u8; 1];
+    port.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"a");
+    assert!(states.borrow_and_update().is_connected());
+
+    // Unplugged, then plugged back in while a read waits.
+    drop(master);
+    let replug = {
+        let link = link.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let mut master = device(&link);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            master.write_all(b"b").await.unwrap();
+            master
+        })
+    };
+    port.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"b");
+    assert!(port.state().is_connected());
+
+    port.write_all(b"c").await.unwrap();
+    let mut master = replug.await.unwrap();
+    master.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"c");
+    let _ = std::fs::remove_file(&link);
+}
+
+#[tokio::test]
+async fn gives_up_according_to_the_policy() {
+    let link = link("give-up");
+    let master = device(&link);
+    let mut port = ReconnectingSerialStream::new(tokio_serial::new(link.to_str().unwrap(), 9600));
+    port.set_policy(RetryPolicy::fixed(Duration::from_millis(10)).max_attempts(3));
+    port.connected().await.unwrap();
+
+    drop(master);
+    let _ = std::fs::remove_file(&link);
+    let mut buf = [0u8; 1];
+    assert!(port.read(&mut buf).await.is_err());
+    assert!(matches!(port.state(), ConnectionState::Failed(_)));
+    assert!(port.get_ref().is_none());
+    assert!(port.write(b"a").await.is_err());
+}
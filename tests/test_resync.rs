@@ -0,0 +1,79 @@
+use bytes::BytesMut;
+use tokio_serial::codec::{
+    Checksum, ChecksumCodec, ChecksumError, FrameError, LengthDelimitedCodec, Recovery, Resync,
+};
+use tokio_util::codec::Decoder;
+
+const START: u8 = 0x7e;
+
+// A start byte, a length, the payload and its sum.
+fn codec(recovery: Recovery) -> Resync<ChecksumCodec<LengthDelimitedCodec>> {
+    let frames = LengthDelimitedCodec::new()
+        .length_field_offset(1)
+        .length_field_length(1);
+    Resync::new(ChecksumCodec::new(frames, Checksum::Sum8), recovery)
+}
+
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![START, payload.len() as u8 + 1];
+    frame.extend_from_slice(payload);
+    frame.push(Checksum::Sum8.compute(payload) as u8);
+    frame
+}
+
+fn decode_all<C: Decoder>(codec: &mut C, src: &mut BytesMut) -> Vec<Result<C::Item, C::Error>> {
+    let mut items = Vec::new();
+    loop {
+        match codec.decode(src) {
+            Ok(Some(item)) => items.push(Ok(item)),
+            Ok(None) => return items,
+            Err(err) => items.push(Err(err)),
+        }
+    }
+}
+
+// A first frame whose length got corrupted, which covers the start of the second.
+fn corrupted() -> BytesMut {
+    let mut first = frame(b"one");
+    first[1] += 3;
+    let mut src = BytesMut::from(&first[..]);
+    src.extend_from_slice(&frame(b"two"));
+    src.extend_from_slice(&frame(b"three"));
+    src
+}
+
+#[test]
+fn sync_bytes_are_searched_in_rejected_frames() {
+    let mut codec = codec(Recovery::SkipTo(START));
+    let items = decode_all(&mut codec, &mut corrupted());
+    assert_eq!(items.len(), 3);
+    assert!(matches!(
+        items[0],
+        Err(FrameError::Frame(ChecksumError::Mismatch { .. }))
+    ));
+    assert_eq!(items[1].as_ref().unwrap(), &b"two"[..]);
+    assert_eq!(items[2].as_ref().unwrap(), &b"three"[..]);
+    assert_eq!(codec.errors(), 1);
+}
+
+#[test]
+fn errors_can_go_unreported() {
+    let mut codec = codec(Recovery::SkipTo(START)).report_errors(false);
+    let mut src = BytesMut::from(&b"noise"[..]);
+    src.extend_from_slice(&corrupted());
+    let items = decode_all(&mut codec, &mut src);
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].as_ref().unwrap(), &b"two"[..]);
+    assert_eq!(items[1].as_ref().unwrap(), &b"three"[..]);
+    assert_eq!(codec.errors(), 1);
+}
+
+#[test]
+fn bytes_can_be_dropped_after_errors() {
+    // Continuing right after the corrupted frame loses the second one.
+    let mut codec = codec(Recovery::Drop(3));
+    let items = decode_all(&mut codec, &mut corrupted());
+    assert!(items[0].is_err());
+    assert_eq!(items.last().unwrap().as_ref().unwrap(), &b"three"[..]);
+    assert_eq!(items.len(), 2);
+}
@@ -0,0 +1,88 @@
+use std::cell::Cell;
+use std::time::Duration;
+use tokio_serial::retry::RetryPolicy;
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+#[test]
+fn backoff_sequences() {
+    let fixed = RetryPolicy::fixed(ms(10)).max_attempts(3);
+    assert_eq!(fixed.delay(1), Some(ms(10)));
+    assert_eq!(fixed.delay(2), Some(ms(10)));
+    assert_eq!(fixed.delay(3), None);
+
+    let exponential = RetryPolicy::exponential(ms(10)).max_delay(ms(50));
+    let delays: Vec<_> = (1..=4).map(|r| exponential.delay(r).unwrap()).collect();
+    assert_eq!(delays, [ms(10), ms(20), ms(40), ms(50)]);
+
+    let tripling = RetryPolicy::exponential(ms(1)).factor(3.0);
+    assert_eq!(tripling.delay(3), Some(ms(9)));
+
+    let fibonacci = RetryPolicy::fibonacci(ms(1));
+    let delays: Vec<_> = (1..=6).map(|r| fibonacci.delay(r).unwrap()).collect();
+    assert_eq!(delays, [ms(1), ms(1), ms(2), ms(3), ms(5), ms(8)]);
+
+    let custom = RetryPolicy::custom(|retry| if retry < 3 { Some(ms(7)) } else { None });
+    assert_eq!(custom.delay(2), Some(ms(7)));
+    assert_eq!(custom.delay(3), None);
+
+    assert_eq!(RetryPolicy::never().delay(1), None);
+}
+
+#[test]
+fn jitter_shortens_delays() {
+    let policy = RetryPolicy::fixed(ms(100)).jitter(0.5);
+    for retry in 1..50 {
+        let delay = policy.delay(retry).unwrap();
+        assert!(delay >= ms(50) && delay <= ms(100), "{:?}", delay);
+    }
+}
+
+#[tokio::test]
+async fn retry_until_success_or_give_up() {
+    let attempts = Cell::new(0);
+    let result: Result<u32, &str> = RetryPolicy::fixed(ms(1))
+        .retry(|| {
+            attempts.set(attempts.get() + 1);
+            let n = attempts.get();
+            async move {
+                if n < 3 {
+                    Err("busy")
+                } else {
+                    Ok(n)
+                }
+            }
+        })
+        .await;
+    assert_eq!(result, Ok(3));
+
+    attempts.set(0);
+    let result: Result<(), &str> = RetryPolicy::fixed(ms(1))
+        .max_attempts(4)
+        .retry(|| {
+            attempts.set(attempts.get() + 1);
+            async { Err("busy") }
+        })
+        .await;
+    assert_eq!(result, Err("busy"));
+    assert_eq!(attempts.get(), 4);
+}
+
+#[tokio::test]
+async fn retry_if_stops_on_permanent_errors() {
+    let attempts = Cell::new(0);
+    let result: Result<(), &str> = RetryPolicy::fixed(ms(1))
+        .retry_if(
+            || {
+                attempts.set(attempts.get() + 1);
+                let n = attempts.get();
+                async move { Err(if n < 3 { "busy" } else { "invalid" }) }
+            },
+            |err| *err == "busy",
+        )
+        .await;
+    assert_eq!(result, Err("invalid"));
+    assert_eq!(attempts.get(), 3);
+}
@@ -0,0 +1,224 @@
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_serial::rfc2217::Rfc2217Stream;
+use tokio_serial::{Parity, SerialPort, SerialSettings};
+
+const IAC: u8 = 255;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const WILL: u8 = 251;
+const DO: u8 = 253;
+
+// A server confirming every command but `ignored`, refusing even parity, echoing
+// the data and reporting the COM Port Control subnegotiations it receives.
+async fn server(
+    mut stream: TcpStream,
+    commands: mpsc::UnboundedSender<Vec<u8>>,
+    ignored: &'static [u8],
+) {
+    let mut received = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let n = stream.read(&mut buf).await.unwrap();
+        if n == 0 {
+            return;
+        }
+        received.extend_from_slice(&buf[..n]);
+        let mut reply = Vec::new();
+        let mut i = 0;
+        while i < received.len() {
+            match &received[i..] {
+                [IAC, IAC, ..] => {
+                    reply.extend_from_slice(&[IAC, IAC]);
+                    i += 2;
+                }
+                [IAC, WILL, 44, ..] => {
+                    reply.extend_from_slice(&[IAC, DO, 44]);
+                    i += 3;
+                }
+                [IAC, SB, 44, rest @ ..] => match rest.windows(2).position(|w| w == [IAC, SE]) {
+                    Some(end) => {
+                        let mut command = rest[..end].to_vec();
+                        commands.send(command.clone()).unwrap();
+                        i += 3 + end + 2;
+                        if command == ignored {
+                            continue;
+                        }
+                        if command == [3, 3] {
+                            // Keep no parity
+                            command[1] = 1;
+                        }
+                        command[0] += 100;
+                        reply.extend_from_slice(&[IAC, SB, 44]);
+                        reply.extend_from_slice(&command);
+                        reply.extend_from_slice(&[IAC, SE]);
+                        if command[0] == 105 {
+                            // CTS and DSR
+                            reply.extend_from_slice(&[IAC, SB, 44, 107, 0x30, IAC, SE]);
+                        }
+                    }
+                    None => break,
+                },
+                [IAC, _, _, ..] => i += 3,
+                [IAC, ..] => break,
+                [byte, ..] => {
+                    reply.push(*byte);
+                    i += 1;
+                }
+                [] => unreachable!(),
+            }
+        }
+        received.drain(..i);
+        stream.write_all(&reply).await.unwrap();
+    }
+}
+
+async fn connect() -> (Rfc2217Stream, mpsc::UnboundedReceiver<Vec<u8>>) {
+    connect_ignoring(&[]).await
+}
+
+async fn connect_ignoring(
+    ignored: &'static [u8],
+) -> (Rfc2217Stream, mpsc::UnboundedReceiver<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        server(stream, tx, ignored).await
+    });
+    let port = Rfc2217Stream::connect(addr, &SerialSettings::new(9600))
+        .await
+        .unwrap();
+    (port, rx)
+}
+
+#[tokio::test]
+async fn settings_are_negotiated() {
+    let (mut port, mut commands) = connect().await;
+    let mut sent = Vec::new();
+    while let Ok(command) = commands.try_recv() {
+        sent.push(command);
+    }
+    assert_eq!(
+        sent,
+        [
+            vec![1, 0, 0, 0x25, 0x80],
+            vec![2, 8],
+            vec![3, 1],
+            vec![4, 1],
+            vec![5, 1]
+        ]
+    );
+    assert_eq!(port.baud_rate().unwrap(), 9600);
+    assert!(port.read_clear_to_send().unwrap());
+    assert!(!port.read_carrier_detect().unwrap());
+
+    // The server keeps no parity.
+    let mut settings = SerialSettings::new(19200);
+    settings.parity = Parity::Even;
+    assert!(port.apply_settings(&settings).await.is_err());
+    assert_eq!(port.settings().baud_rate, 19200);
+    assert_eq!(port.parity().unwrap(), Parity::None);
+}
+
+#[tokio::test]
+async fn data_and_control_lines_go_through() {
+    let (mut port, mut commands) = connect().await;
+    while commands.try_recv().is_ok() {}
+
+    port.write_request_to_send(true).unwrap();
+    port.write_all(&[0x01, 0xff, 0x02]).await.unwrap();
+    let mut buf = [0u8; 3];
+    tokio::time::timeout(Duration::from_secs(1), port.read_exact(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(buf, [0x01, 0xff, 0x02]);
+    assert_eq!(commands.recv().await.unwrap(), [5, 11]);
+    assert!(port.name().unwrap().starts_with("rfc2217://127.0.0.1:"));
+}
+
+#[tokio::test]
+async fn line_confirmations_do_not_confirm_the_flow_control() {
+    use tokio_serial::FlowControl;
+
+    // Hardware flow control is never confirmed.
+    let (mut port, _commands) = connect_ignoring(&[5, 3]).await;
+    port.set_timeout(Duration::from_millis(200)).unwrap();
+
+    port.write_data_terminal_ready(true).unwrap();
+    let mut settings = SerialSettings::new(9600);
+    settings.flow_control = FlowControl::Hardware;
+    let err = port.apply_settings(&settings).await.unwrap_err();
+    assert_eq!(
+        err.kind(),
+        tokio_serial::ErrorKind::Io(std::io::ErrorKind::TimedOut)
+    );
+
+    port.write_request_to_send(false).unwrap();
+    port.set_break().unwrap();
+    port.lines_applied().await.unwrap();
+}
+
+#[tokio::test]
+async fn blocking_writes_stop_queueing_for_a_slow_server() {
+    // The server echoes what it gets, never read back it stops reading too.
+    let (mut port, _commands) = connect().await;
+    let chunk = [0x55; 4096];
+    let mut blocked = false;
+    for _ in 0..64 * 1024 {
+        match std::io::Write::write(&mut port, &chunk) {
+            Ok(n) => assert_eq!(n, chunk.len()),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                blocked = true;
+                break;
+            }
+            Err(e) => panic!("unexpected error {}", e),
+        }
+    }
+    assert!(blocked, "writes never blocked");
+    assert!(port.bytes_to_write().unwrap() < 8 * 1024 + 4096);
+}
+
+#[cfg(unix)]
+mod server {
+    use super::*;
+    use tokio_serial::bridge::BridgeServer;
+    use tokio_serial::{FlowControl, SerialStream, StopBits};
+
+    #[tokio::test]
+    async fn clients_configure_the_local_port() {
+        let (a, mut device) = SerialStream::pair().expect("unable to open pty pair");
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = BridgeServer::from_listener(listener);
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.serve_rfc2217(a));
+
+        let mut settings = SerialSettings::new(19200);
+        settings.stop_bits = StopBits::Two;
+        settings.flow_control = FlowControl::Software;
+        let mut port = Rfc2217Stream::connect(addr, &settings).await.unwrap();
+        // The pty shares its settings with the other side.
+        assert_eq!(device.settings().unwrap(), settings);
+
+        port.write_all(&[0x01, 0xff, 0x02]).await.unwrap();
+        let mut buf = [0u8; 3];
+        device.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [0x01, 0xff, 0x02]);
+
+        device.write_all(&[0xff, 0x03]).await.unwrap();
+        let mut buf = [0u8; 2];
+        tokio::time::timeout(Duration::from_secs(1), port.read_exact(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(buf, [0xff, 0x03]);
+
+        settings.baud_rate = 4800;
+        port.apply_settings(&settings).await.unwrap();
+        assert_eq!(device.baud_rate().unwrap(), 4800);
+    }
+}
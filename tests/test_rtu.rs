@@ -0,0 +1,77 @@
+#![cfg(unix)]
+
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio_serial::codec::rtu::silent_interval;
+use tokio_serial::codec::{FrameError, ModbusRtuError, ModbusRtuFramed};
+use tokio_serial::{DataBits, FlowControl, Parity, SerialSettings, SerialStream, StopBits};
+
+// Read holding registers 0 to 9 of slave 1
+const REQUEST: &[u8] = b"\x01\x03\x00\x00\x00\x0a\xc5\xcd";
+
+#[test]
+fn silent_interval_follows_the_baud_rate() {
+    let mut settings = SerialSettings {
+        baud_rate: 9600,
+        data_bits: DataBits::Eight,
+        parity: Parity::Even,
+        stop_bits: StopBits::One,
+        flow_control: FlowControl::None,
+    };
+    assert_eq!(silent_interval(&settings), Duration::from_nanos(4_010_415));
+    settings.baud_rate = 115_200;
+    assert_eq!(silent_interval(&settings), Duration::from_micros(1750));
+}
+
+#[tokio::test]
+async fn frames_are_delimited_by_silence() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut rtu = ModbusRtuFramed::new(slave).unwrap();
+    rtu.set_silent_interval(Duration::from_millis(20));
+
+    // A frame spread over writes, but without silences, then a corrupted one.
+    let writer = tokio::spawn(async move {
+        master.write_all(&REQUEST[..3]).await.unwrap();
+        master.write_all(&REQUEST[3..]).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        master
+            .write_all(b"\x01\x03\x00\x00\x00\x0a\xc5\xce")
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        master.write_all(b"\x01").await.unwrap();
+        master
+    });
+
+    assert_eq!(&rtu.next().await.unwrap().unwrap()[..], REQUEST);
+    assert!(matches!(
+        rtu.next().await.unwrap(),
+        Err(FrameError::Frame(ModbusRtuError::Crc))
+    ));
+    assert!(matches!(
+        rtu.next().await.unwrap(),
+        Err(FrameError::Frame(ModbusRtuError::TooShort(1)))
+    ));
+    writer.await.unwrap();
+}
+
+#[tokio::test]
+async fn sent_frames_get_crc_and_silence() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut sender = ModbusRtuFramed::new(master).unwrap();
+    sender.set_silent_interval(Duration::from_millis(50));
+    let mut receiver = ModbusRtuFramed::new(slave).unwrap();
+    receiver.set_silent_interval(Duration::from_millis(20));
+
+    let writer = tokio::spawn(async move {
+        sender.feed(&REQUEST[..6]).await.unwrap();
+        sender.feed(&REQUEST[..6]).await.unwrap();
+        sender.flush().await.unwrap();
+        sender
+    });
+
+    assert_eq!(&receiver.next().await.unwrap().unwrap()[..], REQUEST);
+    assert_eq!(&receiver.next().await.unwrap().unwrap()[..], REQUEST);
+    let _sender = writer.await.unwrap();
+}
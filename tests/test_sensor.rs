@@ -0,0 +1,97 @@
+use bytes::BytesMut;
+use tokio_serial::codec::sensor::{
+    MhZ19Command, MhZ19Reading, Pms5003Command, Pms5003Reading, Sds011Command, Sds011Reading,
+    SensorError,
+};
+use tokio_serial::codec::{MhZ19Codec, Pms5003Codec, Sds011Codec};
+use tokio_util::codec::{Decoder, Encoder};
+
+#[test]
+fn sds011_readings() {
+    let mut codec = Sds011Codec::new();
+    // Noise, a reply to a command, then a reading.
+    let mut buf = BytesMut::from(
+        &b"\xab\x00\xaa\xc5\x06\x01\x00\x01\xa1\x60\x09\xab\xaa\xc0\xd4\x04\x3a\x0a\xa1\x60\x1d\xab"
+            [..],
+    );
+    let reading = codec.decode(&mut buf).unwrap();
+    assert_eq!(
+        reading,
+        Some(Sds011Reading {
+            pm2_5: 123.6,
+            pm10: 261.8,
+            id: 0x60a1,
+        })
+    );
+    assert!(buf.is_empty());
+
+    let mut buf = BytesMut::from(&b"\xaa\xc0\xd4\x04\x3a\x0a\xa1\x60\x1e\xab"[..]);
+    assert!(matches!(codec.decode(&mut buf), Err(SensorError::Checksum)));
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+    let mut dst = BytesMut::new();
+    codec.encode(Sds011Command::Query, &mut dst).unwrap();
+    assert_eq!(
+        &dst[..],
+        b"\xaa\xb4\x04\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\xff\xff\x02\xab"
+    );
+}
+
+#[test]
+fn pms5003_readings() {
+    let mut frame = b"\x42\x4d\x00\x1c".to_vec();
+    for word in [5u16, 10, 12, 5, 10, 12, 1000, 300, 100, 10, 2, 1, 0] {
+        frame.extend_from_slice(&word.to_be_bytes());
+    }
+    frame.extend_from_slice(&0x026au16.to_be_bytes());
+
+    let mut codec = Pms5003Codec::new();
+    let mut buf = BytesMut::from(&frame[20..]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    // The end of a reading whose start was missed is skipped, even split in two.
+    buf.extend_from_slice(&frame[..10]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    buf.extend_from_slice(&frame[10..]);
+    assert_eq!(
+        codec.decode(&mut buf).unwrap(),
+        Some(Pms5003Reading {
+            pm1_0: 5,
+            pm2_5: 10,
+            pm10: 12,
+            pm1_0_cf1: 5,
+            pm2_5_cf1: 10,
+            pm10_cf1: 12,
+            particles: [1000, 300, 100, 10, 2, 1],
+        })
+    );
+
+    let mut dst = BytesMut::new();
+    codec.encode(Pms5003Command::Read, &mut dst).unwrap();
+    assert_eq!(&dst[..], b"\x42\x4d\xe2\x00\x00\x01\x71");
+}
+
+#[test]
+fn mhz19_readings() {
+    let mut codec = MhZ19Codec::new();
+    let mut dst = BytesMut::new();
+    codec.encode(MhZ19Command::Read, &mut dst).unwrap();
+    assert_eq!(&dst[..], b"\xff\x01\x86\x00\x00\x00\x00\x00\x79");
+    dst.clear();
+    codec
+        .encode(MhZ19Command::SetRange(2000), &mut dst)
+        .unwrap();
+    assert_eq!(&dst[..], b"\xff\x01\x99\x00\x00\x00\x07\xd0\x8f");
+
+    // 500ppm at 25°C
+    let mut buf = BytesMut::from(&b"\xff\x86\x01\xf4\x41\x00\x00\x00\x44"[..]);
+    assert_eq!(
+        codec.decode(&mut buf).unwrap(),
+        Some(MhZ19Reading {
+            co2: 500,
+            temperature: 25,
+        })
+    );
+
+    let mut buf = BytesMut::from(&b"\xff\x86\x01\xf5\x41\x00\x00\x00\x44"[..]);
+    assert!(matches!(codec.decode(&mut buf), Err(SensorError::Checksum)));
+}
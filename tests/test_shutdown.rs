@@ -0,0 +1,74 @@
+#![cfg(unix)]
+
+use futures::future::BoxFuture;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_serial::flow::SoftwareFlowControl;
+use tokio_serial::transcript::Recorder;
+use tokio_serial::{SerialStream, ShutdownLayered};
+
+// A protocol layer which sends a goodbye when it finishes.
+struct Goodbye<S> {
+    inner: S,
+    name: &'static str,
+    finished: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Goodbye<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+impl<S: ShutdownLayered + AsyncWrite + Unpin> ShutdownLayered for Goodbye<S> {
+    fn finish(&mut self) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(async move {
+            self.finished.lock().unwrap().push(self.name);
+            self.inner.write_all(self.name.as_bytes()).await
+        })
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut dyn ShutdownLayered> {
+        Some(&mut self.inner)
+    }
+}
+
+#[tokio::test]
+async fn layers_finish_from_the_top() {
+    let (master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    let finished = Arc::new(Mutex::new(Vec::new()));
+
+    let inner = Goodbye {
+        inner: Recorder::new(SoftwareFlowControl::new(master)),
+        name: "inner;",
+        finished: finished.clone(),
+    };
+    let mut outer = Goodbye {
+        inner,
+        name: "outer;",
+        finished: finished.clone(),
+    };
+
+    outer.shutdown_layered().await.unwrap();
+    assert_eq!(*finished.lock().unwrap(), ["outer;", "inner;"]);
+
+    let mut buf = [0u8; 12];
+    slave.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"outer;inner;");
+    assert_eq!(outer.inner.inner.transcript().records().len(), 2);
+}
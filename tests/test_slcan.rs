@@ -0,0 +1,104 @@
+use bytes::BytesMut;
+use tokio_serial::codec::slcan::{
+    Bitrate, CanFrame, CanId, SlcanCommand, SlcanError, SlcanResponse,
+};
+use tokio_serial::codec::{FrameError, SlcanCodec};
+use tokio_util::codec::{Decoder, Encoder};
+
+fn encode(command: SlcanCommand) -> BytesMut {
+    let mut dst = BytesMut::new();
+    SlcanCodec::new().encode(command, &mut dst).unwrap();
+    dst
+}
+
+#[test]
+fn commands_are_encoded() {
+    assert_eq!(encode(SlcanCommand::Bitrate(Bitrate::K500)), &b"S6\r"[..]);
+    assert_eq!(encode(SlcanCommand::Open), &b"O\r"[..]);
+    assert_eq!(encode(SlcanCommand::BitTiming(0x03, 0x1c)), &b"s031C\r"[..]);
+    assert_eq!(encode(SlcanCommand::Timestamps(true)), &b"Z1\r"[..]);
+
+    let frame = CanFrame::new(CanId::Standard(0x123), &[0xa1, 0xb2, 0xc3, 0xd4]).unwrap();
+    assert_eq!(
+        encode(SlcanCommand::Transmit(frame)),
+        &b"t1234A1B2C3D4\r"[..]
+    );
+    let frame = CanFrame::new(CanId::Extended(0x1234_5678), &[]).unwrap();
+    assert_eq!(encode(SlcanCommand::Transmit(frame)), &b"T123456780\r"[..]);
+    let frame = CanFrame::remote(CanId::Standard(0x7ff), 8).unwrap();
+    assert_eq!(encode(SlcanCommand::Transmit(frame)), &b"r7FF8\r"[..]);
+
+    assert_eq!(CanFrame::new(CanId::Standard(0x800), &[]), None);
+    assert_eq!(CanFrame::new(CanId::Extended(0), &[0; 9]), None);
+}
+
+#[test]
+fn responses_are_decoded() {
+    let mut codec = SlcanCodec::new();
+    let mut src = BytesMut::from(&b"\r\x07z\rt1232ABCD\rR1FFFFFFF4EA60\rV1013\rF04\rT000"[..]);
+
+    assert_eq!(codec.decode(&mut src).unwrap(), Some(SlcanResponse::Ok));
+    assert_eq!(codec.decode(&mut src).unwrap(), Some(SlcanResponse::Error));
+    assert_eq!(
+        codec.decode(&mut src).unwrap(),
+        Some(SlcanResponse::Transmitted)
+    );
+
+    let response = codec.decode(&mut src).unwrap().unwrap();
+    let frame = CanFrame::new(CanId::Standard(0x123), &[0xab, 0xcd]).unwrap();
+    assert_eq!(
+        response,
+        SlcanResponse::Frame {
+            frame,
+            timestamp: None
+        }
+    );
+
+    match codec.decode(&mut src).unwrap().unwrap() {
+        SlcanResponse::Frame { frame, timestamp } => {
+            assert_eq!(frame.id(), CanId::Extended(0x1fff_ffff));
+            assert!(frame.is_remote());
+            assert_eq!(frame.dlc(), 4);
+            assert!(frame.data().is_empty());
+            assert_eq!(timestamp, Some(60000));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    assert_eq!(
+        codec.decode(&mut src).unwrap(),
+        Some(SlcanResponse::Version("1013".into()))
+    );
+    assert_eq!(
+        codec.decode(&mut src).unwrap(),
+        Some(SlcanResponse::Status(0x04))
+    );
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+}
+
+#[test]
+fn invalid_lines_are_skipped() {
+    let mut codec = SlcanCodec::new();
+    let mut src = BytesMut::from(&b"t12\rt1239\rhello\rz\r"[..]);
+    for line in ["t12", "t1239", "hello"] {
+        assert_eq!(
+            codec.decode(&mut src).unwrap_err().to_string(),
+            FrameError::Frame(SlcanError::Invalid(line.into())).to_string()
+        );
+    }
+    assert_eq!(
+        codec.decode(&mut src).unwrap(),
+        Some(SlcanResponse::Transmitted)
+    );
+
+    let mut src = BytesMut::from(&[b'x'; 100][..]);
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(FrameError::Frame(SlcanError::TooLong))
+    ));
+    src.extend_from_slice(b"xxx\rz\r");
+    assert_eq!(
+        codec.decode(&mut src).unwrap(),
+        Some(SlcanResponse::Transmitted)
+    );
+}
@@ -0,0 +1,281 @@
+#![cfg(unix)]
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio_serial::transfer::stk500::{v1, v2, Memory, Stk500, Version};
+use tokio_serial::transfer::TransferError;
+use tokio_serial::{AsyncSerialPort, SerialStream};
+
+// Records the control lines instead of setting them, ptys have none.
+struct Board {
+    port: SerialStream,
+    lines: Arc<Mutex<Vec<(&'static str, bool)>>>,
+}
+
+impl AsyncRead for Board {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.port).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Board {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.port).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.port).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.port).poll_shutdown(cx)
+    }
+}
+
+impl AsyncSerialPort for Board {
+    fn port_name(&self) -> Option<String> {
+        None
+    }
+
+    fn change_baud_rate(&mut self, baud_rate: u32) -> tokio_serial::Result<()> {
+        self.port.change_baud_rate(baud_rate)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> tokio_serial::Result<()> {
+        self.lines.lock().unwrap().push(("dtr", level));
+        Ok(())
+    }
+
+    fn set_rts(&mut self, level: bool) -> tokio_serial::Result<()> {
+        self.lines.lock().unwrap().push(("rts", level));
+        Ok(())
+    }
+
+    fn set_break_condition(&mut self, asserted: bool) -> tokio_serial::Result<()> {
+        self.port.set_break_condition(asserted)
+    }
+}
+
+const SIGNATURE: [u8; 3] = [0x1e, 0x95, 0x0f];
+
+// A memory written through the bootloader, and the byte address of its next page.
+#[derive(Default)]
+struct Memories {
+    flash: Vec<u8>,
+    eeprom: Vec<u8>,
+    address: usize,
+}
+
+impl Memories {
+    fn memory(&mut self, eeprom: bool) -> &mut Vec<u8> {
+        if eeprom {
+            &mut self.eeprom
+        } else {
+            &mut self.flash
+        }
+    }
+
+    fn write(&mut self, eeprom: bool, data: &[u8]) {
+        let address = self.address;
+        let memory = self.memory(eeprom);
+        if memory.len() < address + data.len() {
+            memory.resize(address + data.len(), 0xff);
+        }
+        memory[address..address + data.len()].copy_from_slice(data);
+    }
+
+    fn read(&mut self, eeprom: bool, len: usize) -> Vec<u8> {
+        let address = self.address;
+        self.memory(eeprom)[address..address + len].to_vec()
+    }
+}
+
+// Optiboot, enough of it.
+async fn optiboot(mut port: SerialStream) -> Memories {
+    let mut memories = Memories::default();
+    // Garbage of the application before the reset.
+    port.write_all(b"hello\r\n").await.unwrap();
+    loop {
+        let command = match port.read_u8().await {
+            Ok(command) => command,
+            Err(_) => return memories,
+        };
+        let args = match command {
+            v1::GET_PARAMETER => 1,
+            v1::LOAD_ADDRESS => 2,
+            v1::PROG_PAGE | v1::READ_PAGE => 3,
+            _ => 0,
+        };
+        let mut args = vec![0u8; args];
+        port.read_exact(&mut args).await.unwrap();
+        let mut answer = Vec::new();
+        match command {
+            v1::GET_PARAMETER => answer.push(8),
+            v1::LOAD_ADDRESS => {
+                // Word addresses, for both memories.
+                memories.address = 2 * usize::from(args[0]) + 512 * usize::from(args[1])
+            }
+            v1::PROG_PAGE => {
+                let mut data = vec![0u8; usize::from(args[0]) << 8 | usize::from(args[1])];
+                port.read_exact(&mut data).await.unwrap();
+                memories.write(args[2] == b'E', &data);
+            }
+            v1::READ_PAGE => {
+                let len = usize::from(args[0]) << 8 | usize::from(args[1]);
+                answer = memories.read(args[2] == b'E', len);
+            }
+            v1::READ_SIGN => answer.extend_from_slice(&SIGNATURE),
+            _ => {}
+        }
+        if port.read_u8().await.unwrap() != v1::CRC_EOP {
+            port.write_all(&[v1::NOSYNC]).await.unwrap();
+            continue;
+        }
+        port.write_all(&[v1::INSYNC]).await.unwrap();
+        port.write_all(&answer).await.unwrap();
+        port.write_all(&[v1::OK]).await.unwrap();
+        if command == v1::LEAVE_PROGMODE {
+            return memories;
+        }
+    }
+}
+
+fn xor(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, &byte| sum ^ byte)
+}
+
+// The stk500v2 bootloader of the Mega 2560, enough of it.
+async fn wiring(mut port: SerialStream) -> Memories {
+    let mut memories = Memories::default();
+    loop {
+        let mut header = [0u8; 5];
+        if port.read_exact(&mut header).await.is_err() {
+            return memories;
+        }
+        assert_eq!((header[0], header[4]), (v2::MESSAGE_START, v2::TOKEN));
+        let mut body = vec![0u8; usize::from(u16::from_be_bytes([header[2], header[3]])) + 1];
+        port.read_exact(&mut body).await.unwrap();
+        let checksum = body.pop().unwrap();
+        assert_eq!(xor(&header) ^ xor(&body), checksum);
+
+        let command = body[0];
+        let mut answer = vec![command, v2::STATUS_CMD_OK];
+        match command {
+            v2::CMD_SIGN_ON => answer.extend_from_slice(b"\x08AVRISP_2"),
+            v2::CMD_GET_PARAMETER => answer.push(2),
+            v2::CMD_LOAD_ADDRESS => {
+                let address = u32::from_be_bytes([body[1], body[2], body[3], body[4]]);
+                // Flash addresses in words.
+                memories.address = 2 * (address & 0x7fff_ffff) as usize;
+            }
+            v2::CMD_PROGRAM_FLASH_ISP => memories.write(false, &body[10..]),
+            v2::CMD_READ_FLASH_ISP => {
+                let len = usize::from(u16::from_be_bytes([body[1], body[2]]));
+                answer.extend(memories.read(false, len));
+                answer.push(v2::STATUS_CMD_OK);
+            }
+            v2::CMD_READ_SIGNATURE_ISP => {
+                answer.push(SIGNATURE[usize::from(body[4])]);
+                answer.push(v2::STATUS_CMD_OK);
+            }
+            _ => {}
+        }
+        let mut message = vec![v2::MESSAGE_START, header[1]];
+        message.extend_from_slice(&(answer.len() as u16).to_be_bytes());
+        message.push(v2::TOKEN);
+        message.extend_from_slice(&answer);
+        message.push(xor(&message));
+        port.write_all(&message).await.unwrap();
+        if command == v2::CMD_LEAVE_PROGMODE_ISP {
+            return memories;
+        }
+    }
+}
+
+fn firmware(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 13 + i / 256) as u8).collect()
+}
+
+#[tokio::test]
+async fn optiboot_is_flashed() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let board = Board {
+        port: a,
+        lines: lines.clone(),
+    };
+    let bootloader = tokio::spawn(optiboot(b));
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let recorder = progress.clone();
+    let mut avr = Stk500::new(board).progress(move |progress| {
+        recorder.lock().unwrap().push(progress.transferred);
+    });
+    avr.reset().await.unwrap();
+    assert_eq!(
+        *lines.lock().unwrap(),
+        [("dtr", false), ("rts", false), ("dtr", true), ("rts", true)]
+    );
+    assert_eq!(avr.signature().await.unwrap(), SIGNATURE);
+    assert_eq!(avr.software_version().await.unwrap(), (8, 8));
+
+    avr.enter_programming().await.unwrap();
+    let image = firmware(300);
+    avr.flash(Memory::Flash, 0, &image, 128).await.unwrap();
+    avr.load_address(Memory::Flash, 128).await.unwrap();
+    assert_eq!(
+        avr.read_page(Memory::Flash, 128).await.unwrap(),
+        &image[128..256]
+    );
+    avr.load_address(Memory::Eeprom, 4).await.unwrap();
+    avr.program_page(Memory::Eeprom, &[1, 2]).await.unwrap();
+    avr.leave_programming().await.unwrap();
+
+    let memories = bootloader.await.unwrap();
+    assert_eq!(memories.flash, image);
+    assert_eq!(memories.eeprom, [0xff, 0xff, 0xff, 0xff, 1, 2]);
+    assert_eq!(*progress.lock().unwrap(), [128, 256, 300]);
+}
+
+#[tokio::test]
+async fn mega_bootloader_is_flashed() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let bootloader = tokio::spawn(wiring(b));
+
+    let mut avr = Stk500::new(a).version(Version::V2);
+    avr.sync().await.unwrap();
+    assert_eq!(avr.signature().await.unwrap(), SIGNATURE);
+    assert_eq!(avr.software_version().await.unwrap(), (2, 2));
+
+    avr.enter_programming().await.unwrap();
+    let image = firmware(600);
+    avr.flash(Memory::Flash, 0, &image, 256).await.unwrap();
+    avr.load_address(Memory::Flash, 256).await.unwrap();
+    assert_eq!(
+        avr.read_page(Memory::Flash, 256).await.unwrap(),
+        &image[256..512]
+    );
+    avr.leave_programming().await.unwrap();
+
+    assert_eq!(bootloader.await.unwrap().flash, image);
+}
+
+#[tokio::test]
+async fn silent_boards_fail_to_sync() {
+    let (a, _b) = SerialStream::pair().expect("unable to open pty pair");
+    let mut avr = Stk500::new(a)
+        .timeout(std::time::Duration::from_millis(20))
+        .retries(3);
+    let err = avr.sync().await.unwrap_err();
+    assert!(matches!(err, TransferError::TooManyErrors), "{:?}", err);
+}
@@ -0,0 +1,194 @@
+#![cfg(unix)]
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::transfer::stm32::{command, Erase, Stm32, ACK, FLASH_START, NACK, SYNC};
+use tokio_serial::transfer::TransferError;
+use tokio_serial::SerialStream;
+
+fn xor(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, &byte| sum ^ byte)
+}
+
+#[derive(Debug, Default)]
+struct Chip {
+    flash: Vec<u8>,
+    erased: Vec<Vec<u8>>,
+    started: Option<u32>,
+}
+
+// Reads a block of arguments ending with its checksum, acknowledging it.
+async fn read_checked(port: &mut SerialStream, len: usize) -> Vec<u8> {
+    let mut args = vec![0u8; len + 1];
+    port.read_exact(&mut args).await.unwrap();
+    let checksum = args.pop().unwrap();
+    let ok = xor(&args) == checksum || args == [0xff];
+    port.write_all(&[if ok { ACK } else { NACK }])
+        .await
+        .unwrap();
+    args
+}
+
+async fn read_address(port: &mut SerialStream) -> u32 {
+    let args = read_checked(port, 4).await;
+    u32::from_be_bytes([args[0], args[1], args[2], args[3]])
+}
+
+// An STM32F4 bootloader, enough of it.
+async fn bootloader(mut port: SerialStream, extended: bool) -> Chip {
+    let mut chip = Chip {
+        flash: vec![0xff; 1024],
+        ..Chip::default()
+    };
+    // The first autobaud byte is lost, the bootloader is still starting.
+    assert_eq!(port.read_u8().await.unwrap(), SYNC);
+    assert_eq!(port.read_u8().await.unwrap(), SYNC);
+    port.write_all(&[ACK]).await.unwrap();
+    loop {
+        let mut cmd = [0u8; 2];
+        if port.read_exact(&mut cmd).await.is_err() {
+            return chip;
+        }
+        assert_eq!(cmd[0], !cmd[1]);
+        port.write_all(&[ACK]).await.unwrap();
+        let addr = |address: u32| (address - FLASH_START) as usize;
+        match cmd[0] {
+            command::GET => {
+                let erase = if extended {
+                    command::EXTENDED_ERASE
+                } else {
+                    command::ERASE
+                };
+                let commands = [
+                    command::GET,
+                    command::GET_ID,
+                    command::READ_MEMORY,
+                    command::GO,
+                    command::WRITE_MEMORY,
+                    erase,
+                ];
+                port.write_all(&[commands.len() as u8, 0x31]).await.unwrap();
+                port.write_all(&commands).await.unwrap();
+                port.write_all(&[ACK]).await.unwrap();
+            }
+            command::GET_ID => port.write_all(&[1, 0x04, 0x13, ACK]).await.unwrap(),
+            command::READ_MEMORY => {
+                let address = addr(read_address(&mut port).await);
+                let mut count = [0u8; 2];
+                port.read_exact(&mut count).await.unwrap();
+                assert_eq!(count[0], !count[1]);
+                port.write_all(&[ACK]).await.unwrap();
+                let end = address + usize::from(count[0]) + 1;
+                port.write_all(&chip.flash[address..end]).await.unwrap();
+            }
+            command::WRITE_MEMORY => {
+                let address = addr(read_address(&mut port).await);
+                let len = usize::from(port.read_u8().await.unwrap()) + 1;
+                assert_eq!(len % 4, 0);
+                let mut data = vec![0u8; len + 1];
+                port.read_exact(&mut data).await.unwrap();
+                let checksum = data.pop().unwrap();
+                assert_eq!(xor(&data) ^ (len - 1) as u8, checksum);
+                chip.flash[address..address + len].copy_from_slice(&data);
+                port.write_all(&[ACK]).await.unwrap();
+            }
+            command::ERASE | command::EXTENDED_ERASE => {
+                // Read up to the checksum, whatever the form.
+                let mut args = Vec::new();
+                loop {
+                    args.push(port.read_u8().await.unwrap());
+                    let done = match (cmd[0], &args[..]) {
+                        (command::ERASE, [0xff, 0x00]) => true,
+                        (command::ERASE, [n, ..]) => args.len() == usize::from(*n) + 3,
+                        (_, [0xff, 0xff, 0x00]) => true,
+                        (_, [high, low, ..]) => {
+                            args.len() == 2 * (usize::from(*high) << 8 | usize::from(*low)) + 5
+                        }
+                        _ => false,
+                    };
+                    if done {
+                        break;
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                port.write_all(&[ACK]).await.unwrap();
+                chip.erased.push(args);
+            }
+            command::GO => {
+                chip.started = Some(read_address(&mut port).await);
+                return chip;
+            }
+            other => panic!("unexpected command {:#04x}", other),
+        }
+    }
+}
+
+fn firmware(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 256) as u8).collect()
+}
+
+#[tokio::test]
+async fn firmware_is_flashed() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let chip = tokio::spawn(bootloader(b, true));
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let recorder = progress.clone();
+    let mut stm32 = Stm32::new(a)
+        .timeout(Duration::from_millis(200))
+        .progress(move |progress| recorder.lock().unwrap().push(progress.transferred));
+    let info = stm32.connect().await.unwrap();
+    assert_eq!(info.version, 0x31);
+    assert!(info.supports(command::EXTENDED_ERASE));
+    assert_eq!(stm32.get_id().await.unwrap(), 0x0413);
+
+    stm32.erase(Erase::Mass).await.unwrap();
+    stm32.erase(Erase::Pages(vec![1, 2])).await.unwrap();
+    let image = firmware(600);
+    stm32.write_memory(FLASH_START, &image).await.unwrap();
+    assert_eq!(stm32.read_memory(FLASH_START, 600).await.unwrap(), image);
+    stm32.go(FLASH_START).await.unwrap();
+
+    let chip = chip.await.unwrap();
+    assert_eq!(&chip.flash[..600], &image[..]);
+    // The last block is padded to a word.
+    assert_eq!(&chip.flash[600..604], [0xff; 4]);
+    assert_eq!(
+        chip.erased,
+        [
+            vec![0xff, 0xff, 0x00],
+            vec![0x00, 0x01, 0x00, 0x01, 0x00, 0x02, 0x02]
+        ]
+    );
+    assert_eq!(chip.started, Some(FLASH_START));
+    assert_eq!(*progress.lock().unwrap(), [256, 512, 600]);
+}
+
+#[tokio::test]
+async fn older_chips_erase_with_the_basic_command() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let chip = tokio::spawn(bootloader(b, false));
+
+    let mut stm32 = Stm32::new(a).timeout(Duration::from_millis(200));
+    stm32.connect().await.unwrap();
+    stm32.erase(Erase::Mass).await.unwrap();
+    stm32.erase(Erase::Pages(vec![3, 4])).await.unwrap();
+    let err = stm32.erase(Erase::Pages(vec![300])).await.unwrap_err();
+    assert!(matches!(err, TransferError::Protocol(_)), "{:?}", err);
+    stm32.go(FLASH_START).await.unwrap();
+
+    let chip = chip.await.unwrap();
+    assert_eq!(
+        chip.erased,
+        [vec![0xff, 0x00], vec![0x01, 0x03, 0x04, 0x06]]
+    );
+}
+
+#[tokio::test]
+async fn silent_chips_fail_to_connect() {
+    let (a, _b) = SerialStream::pair().expect("unable to open pty pair");
+    let mut stm32 = Stm32::new(a).timeout(Duration::from_millis(20)).retries(2);
+    let err = stm32.connect().await.unwrap_err();
+    assert!(matches!(err, TransferError::TooManyErrors), "{:?}", err);
+}
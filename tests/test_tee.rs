@@ -0,0 +1,59 @@
+#![cfg(unix)]
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio_serial::tee::{TeeStream, TeeWriter};
+use tokio_serial::transcript::Direction;
+use tokio_serial::SerialStream;
+
+#[tokio::test]
+async fn copies_go_to_a_channel() {
+    let (master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut port = TeeStream::new(master, tx);
+
+    port.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    slave.read_exact(&mut buf).await.unwrap();
+    slave.write_all(b"pong").await.unwrap();
+    port.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"pong");
+
+    assert_eq!(rx.recv().await.unwrap(), (Direction::Tx, b"ping".to_vec()));
+    let mut received = Vec::new();
+    while let Ok((direction, data)) = rx.try_recv() {
+        assert_eq!(direction, Direction::Rx);
+        received.extend(data);
+    }
+    assert_eq!(received, b"pong");
+}
+
+#[tokio::test]
+async fn copies_are_written_as_lines() {
+    let (master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    let (dashboard, mut monitor) = tokio::io::duplex(1024);
+    let mut port = TeeStream::new(master, TeeWriter::new(dashboard));
+
+    port.write_all(b"AT\r").await.unwrap();
+    let mut buf = [0u8; 3];
+    slave.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"AT\r");
+
+    let line = b"TX 41 54 0d\n";
+    let mut buf = [0u8; 12];
+    monitor.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, line);
+}
+
+#[tokio::test]
+async fn slow_writers_drop_lines() {
+    let (master, _slave) = SerialStream::pair().expect("unable to open pty pair");
+    // Nothing reads the monitor side.
+    let (dashboard, _monitor) = tokio::io::duplex(4);
+    let mut port = TeeStream::new(master, TeeWriter::with_limit(dashboard, 8));
+
+    port.write_all(b"a").await.unwrap();
+    port.write_all(b"b").await.unwrap();
+    port.write_all(b"c").await.unwrap();
+    assert_eq!(port.sink().dropped(), 1);
+}
@@ -0,0 +1,54 @@
+#![cfg(unix)]
+
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::Instant;
+use tokio_serial::codec::TimeoutFramed;
+use tokio_serial::SerialStream;
+
+#[tokio::test]
+async fn frames_end_when_the_line_goes_idle() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut framed = TimeoutFramed::new(slave, Duration::from_millis(20));
+
+    let writer = tokio::spawn(async move {
+        master.write_all(b"hel").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        master.write_all(b"lo").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        master.write_all(b"world").await.unwrap();
+        master
+    });
+
+    assert_eq!(&framed.next().await.unwrap().unwrap()[..], b"hello");
+    assert_eq!(&framed.next().await.unwrap().unwrap()[..], b"world");
+    writer.await.unwrap();
+}
+
+#[tokio::test]
+async fn long_frames_are_split() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut framed = TimeoutFramed::new(slave, Duration::from_secs(10));
+    framed.set_max_frame_length(4);
+
+    master.write_all(b"abcdefgh").await.unwrap();
+    assert_eq!(&framed.next().await.unwrap().unwrap()[..], b"abcd");
+    assert_eq!(&framed.next().await.unwrap().unwrap()[..], b"efgh");
+}
+
+#[tokio::test]
+async fn sent_frames_are_kept_apart() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut framed = TimeoutFramed::new(slave, Duration::from_millis(50));
+
+    let start = Instant::now();
+    framed.feed(&b"one"[..]).await.unwrap();
+    framed.feed(&b"two"[..]).await.unwrap();
+    framed.flush().await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    let mut buf = [0u8; 6];
+    master.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"onetwo");
+}
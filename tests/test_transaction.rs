@@ -0,0 +1,63 @@
+#![cfg(unix)]
+
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio_serial::codec::transaction::Client;
+use tokio_serial::codec::TransactionError;
+use tokio_serial::retry::RetryPolicy;
+use tokio_serial::SerialStream;
+
+#[tokio::test]
+async fn concurrent_requests_get_their_own_responses() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let device = tokio::spawn(async move {
+        let mut port = b.lines();
+        for _ in 0..2 {
+            let request = port.next().await.unwrap().unwrap();
+            let name = request.trim_end_matches('?');
+            // A late response to some earlier request first.
+            port.send("STATUS OK").await.unwrap();
+            port.send(format!("{} {}", name, name.len())).await.unwrap();
+        }
+    });
+
+    let client = Client::with_correlation(a.lines(), |request: &String, response: &String| {
+        response.starts_with(request.trim_end_matches('?'))
+    });
+    let other = client.clone();
+    let (temperature, humidity) = futures::join!(
+        client.request("TEMP?".to_string()),
+        other.request("HUMIDITY?".to_string()),
+    );
+    assert_eq!(temperature.unwrap(), "TEMP 4");
+    assert_eq!(humidity.unwrap(), "HUMIDITY 8");
+    device.await.unwrap();
+}
+
+#[tokio::test]
+async fn requests_are_retried_until_answered() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let device = tokio::spawn(async move {
+        let mut port = b.lines();
+        // The first request is lost.
+        assert_eq!(port.next().await.unwrap().unwrap(), "PING");
+        assert_eq!(port.next().await.unwrap().unwrap(), "PING");
+        port.send("PONG").await.unwrap();
+        port
+    });
+
+    let mut client = Client::new(a.lines());
+    client.set_timeout(Duration::from_millis(100));
+    client.set_policy(RetryPolicy::fixed(Duration::from_millis(10)).max_attempts(2));
+    let response = client
+        .request_matching("PING".to_string(), |response| response == "PONG")
+        .await;
+    assert_eq!(response.unwrap(), "PONG");
+
+    // Never answered
+    let _port = device.await.unwrap();
+    match client.request("PING".to_string()).await {
+        Err(TransactionError::Timeout) => {}
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+}
@@ -0,0 +1,145 @@
+use std::time::Duration;
+use tokio_serial::transcript::{Direction, Record, Transcript};
+
+fn transcript() -> Transcript {
+    let mut transcript = Transcript::new();
+    transcript.set_baud_rate(9600);
+    transcript.push(Record {
+        at: Duration::from_millis(10),
+        direction: Direction::Tx,
+        data: b"AT\r".to_vec(),
+    });
+    transcript.push(Record {
+        at: Duration::from_millis(25),
+        direction: Direction::Rx,
+        data: b"<\"ok\">".to_vec(),
+    });
+    transcript
+}
+
+#[test]
+fn csv_export() {
+    let mut out = Vec::new();
+    transcript().write_csv(&mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "time,direction,hex,text\n\
+         0.010000,TX,41540d,\"AT\\r\"\n\
+         0.025000,RX,3c226f6b223e,\"<\\\"\"ok\\\"\">\"\n"
+    );
+}
+
+#[test]
+fn html_export_escapes_data() {
+    let mut out = Vec::new();
+    transcript().write_html(&mut out).unwrap();
+    let html = String::from_utf8(out).unwrap();
+    assert!(html.contains("6 bytes received, 3 bytes sent"));
+    assert!(html.contains("&lt;\\&quot;ok\\&quot;&gt;"));
+}
+
+#[test]
+fn sigrok_annotations_place_bytes() {
+    let mut out = Vec::new();
+    transcript()
+        .write_sigrok_annotations(&mut out, 96_000)
+        .unwrap();
+    let lines: Vec<_> = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    assert_eq!(lines.len(), 9);
+    // 10 bits per byte at 9600 baud are 100 samples at 96 kHz.
+    assert_eq!(lines[0], "960-1060 uart-1: TX data: 41");
+    assert_eq!(lines[1], "1060-1160 uart-1: TX data: 54");
+    assert_eq!(lines[3], "2400-2500 uart-1: RX data: 3C");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn recorder_captures_both_directions() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_serial::transcript::Recorder;
+    use tokio_serial::SerialStream;
+
+    let (master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut recorder = Recorder::new(master);
+
+    recorder.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    slave.read_exact(&mut buf).await.unwrap();
+    slave.write_all(b"pong").await.unwrap();
+    recorder.read_exact(&mut buf).await.unwrap();
+
+    let (_, transcript) = recorder.into_parts();
+    let records = transcript.records();
+    assert_eq!(records[0].direction, Direction::Tx);
+    assert_eq!(records[0].data, b"ping");
+    let received: Vec<u8> = records[1..].iter().flat_map(|r| r.data.clone()).collect();
+    assert!(records[1..].iter().all(|r| r.direction == Direction::Rx));
+    assert_eq!(received, b"pong");
+}
+
+#[test]
+fn captures_round_trip() {
+    let mut out = Vec::new();
+    transcript().write_capture(&mut out).unwrap();
+    assert!(out.starts_with(b"TSCAP\x01"));
+    assert_eq!(Transcript::read_capture(&out[..]).unwrap(), transcript());
+
+    assert!(Transcript::read_capture(&out[..out.len() - 1]).is_err());
+    assert!(Transcript::read_capture(&b"not a capture"[..]).is_err());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn capture_streams_to_the_output() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_serial::transcript::Capture;
+    use tokio_serial::SerialStream;
+
+    let (master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut capture = Capture::new(master, Vec::new()).unwrap();
+
+    capture.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    slave.read_exact(&mut buf).await.unwrap();
+    slave.write_all(b"pong").await.unwrap();
+    capture.read_exact(&mut buf).await.unwrap();
+
+    let (_, out) = capture.into_parts();
+    let transcript = Transcript::read_capture(&out.unwrap()[..]).unwrap();
+    let records = transcript.records();
+    assert_eq!(records[0].direction, Direction::Tx);
+    assert_eq!(records[0].data, b"ping");
+    let received: Vec<u8> = records[1..].iter().flat_map(|r| r.data.clone()).collect();
+    assert_eq!(received, b"pong");
+}
+
+#[tokio::test]
+async fn replay_keeps_the_timing() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_serial::transcript::Replay;
+
+    let mut transcript = transcript();
+    transcript.push(Record {
+        at: Duration::from_millis(150),
+        direction: Direction::Rx,
+        data: b"!".to_vec(),
+    });
+    let mut replay = Replay::new(&transcript);
+    replay.write_all(b"AT\r").await.unwrap();
+    assert_eq!(replay.written(), b"AT\r");
+
+    let mut buf = [0u8; 6];
+    replay.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"<\"ok\">");
+
+    let mut buf = [0u8; 1];
+    let started = tokio::time::Instant::now();
+    replay.read_exact(&mut buf).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(100));
+    assert_eq!(replay.remaining(), 0);
+    assert_eq!(replay.read(&mut buf).await.unwrap(), 0);
+}
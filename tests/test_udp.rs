@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{duplex, AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::UdpSocket;
+use tokio_serial::bridge::udp::{UdpServer, UdpStats, UdpStream};
+
+async fn read<R: AsyncRead + Unpin>(reader: &mut R, len: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; len];
+    tokio::time::timeout(Duration::from_secs(1), reader.read_exact(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    buf
+}
+
+#[tokio::test]
+async fn passes_data_both_ways() {
+    let (port, mut device) = duplex(4096);
+    let server = Arc::new(UdpServer::bind("127.0.0.1:0").await.unwrap());
+    let addr = server.local_addr().unwrap();
+    tokio::spawn({
+        let server = server.clone();
+        async move { server.serve(port).await }
+    });
+
+    let mut client = UdpStream::connect(addr).await.unwrap();
+    client.set_max_payload(4);
+    client.write_all(b"hello").await.unwrap();
+    assert_eq!(read(&mut device, 5).await, b"hello");
+    assert_eq!(server.peer(), Some(client.local_addr().unwrap()));
+    assert_eq!(client.stats().sent, 2);
+    // The empty datagram of the connection, and two with the data
+    assert_eq!(server.stats().received, 3);
+
+    device.write_all(b"world").await.unwrap();
+    assert_eq!(read(&mut client, 5).await, b"world");
+}
+
+#[tokio::test]
+async fn sequence_numbers_detect_losses() {
+    let (port, mut device) = duplex(4096);
+    let mut server = UdpServer::bind("127.0.0.1:0").await.unwrap();
+    server.set_sequence_numbers(true);
+    let server = Arc::new(server);
+    let addr = server.local_addr().unwrap();
+    tokio::spawn({
+        let server = server.clone();
+        async move { server.serve(port).await }
+    });
+
+    let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    peer.connect(addr).await.unwrap();
+    for datagram in [
+        &b"\xff\xfea"[..],
+        b"\xff\xffb",
+        b"\x00\x01c",
+        b"\x00\x00d",
+        b"e",
+    ]
+    .iter()
+    {
+        peer.send(datagram).await.unwrap();
+    }
+    assert_eq!(read(&mut device, 3).await, b"abc");
+    peer.send(b"\x00\x02f").await.unwrap();
+    assert_eq!(read(&mut device, 1).await, b"f");
+    assert_eq!(
+        server.stats(),
+        UdpStats {
+            sent: 0,
+            received: 4,
+            lost: 1,
+            late: 1
+        }
+    );
+
+    device.write_all(b"xy").await.unwrap();
+    let mut buf = [0u8; 16];
+    let n = tokio::time::timeout(Duration::from_secs(1), peer.recv(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(&buf[..n], b"\x00\x00xy");
+}
+
+#[tokio::test]
+async fn fixed_peers_are_the_only_ones_served() {
+    let (port, mut device) = duplex(4096);
+    let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut server = UdpServer::bind("127.0.0.1:0").await.unwrap();
+    server.set_peer(Some(peer.local_addr().unwrap()));
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(async move { server.serve(port).await });
+
+    let mut other = UdpStream::connect(addr).await.unwrap();
+    other.set_sequence_numbers(true);
+    other.write_all(b"ignored").await.unwrap();
+    peer.send_to(b"ok", addr).await.unwrap();
+    assert_eq!(read(&mut device, 2).await, b"ok");
+
+    // The peer is sent the data without sending first.
+    device.write_all(b"z").await.unwrap();
+    let mut buf = [0u8; 16];
+    let (n, _) = peer.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..n], b"z");
+}
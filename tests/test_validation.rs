@@ -0,0 +1,34 @@
+use tokio_serial::validation::Problem;
+use tokio_serial::{PortConfig, SerialSettings};
+
+// Against whatever ports the machine has, Windows drivers report baud rates
+// they cannot set, which 0 always is.
+#[test]
+fn zero_baud_rate_is_reported_once() {
+    for port in tokio_serial::available_ports().unwrap() {
+        let config = PortConfig {
+            path: port.port_name,
+            settings: SerialSettings::new(0),
+        };
+        let report = match config.validate() {
+            Ok(report) => report,
+            // Busy or not accessible.
+            Err(_) => continue,
+        };
+        let baud_rates: Vec<_> = report
+            .problems
+            .iter()
+            .filter(|problem| matches!(problem, Problem::BaudRate { .. }))
+            .collect();
+        assert_eq!(
+            baud_rates,
+            [&Problem::BaudRate {
+                requested: 0,
+                max: report.max_baud_rate
+            }],
+            "{:?}",
+            report
+        );
+        assert!(matches!(report.problems[0], Problem::BaudRate { .. }));
+    }
+}
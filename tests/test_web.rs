@@ -0,0 +1,103 @@
+#![cfg(target_arch = "wasm32")]
+
+// Runs with `wasm-bindgen-test` in node, against a fake of the browser's port:
+//
+//     CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER=wasm-bindgen-test-runner \
+//     RUSTFLAGS="--cfg=web_sys_unstable_apis" \
+//     cargo test --target wasm32-unknown-unknown --features web-serial --test test_web
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::web::WebSerialPort;
+use tokio_serial::AsyncSerialPort;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+// A port recording what is done with it, whose `setSignals` fails if `rejected`.
+#[wasm_bindgen(inline_js = r#"
+export function fakePort(rejected) {
+    const calls = [];
+    return {
+        calls,
+        open(options) {
+            calls.push(["open", options.baudRate]);
+            return Promise.resolve();
+        },
+        readable: new ReadableStream({
+            start(controller) {
+                controller.enqueue(new Uint8Array([1, 2, 3]));
+            },
+        }),
+        writable: new WritableStream({
+            write(chunk) {
+                calls.push(["write", Array.from(chunk)]);
+            },
+        }),
+        setSignals(signals) {
+            calls.push(["signals", signals]);
+            return rejected ? Promise.reject(new Error("device gone")) : Promise.resolve();
+        },
+        getInfo() {
+            return { usbVendorId: 0x2341, usbProductId: 0x0043 };
+        },
+    };
+}
+
+export function calls(port) {
+    return JSON.stringify(port.calls);
+}
+"#)]
+extern "C" {
+    #[wasm_bindgen(js_name = fakePort)]
+    fn fake_port(rejected: bool) -> web_sys::SerialPort;
+
+    fn calls(port: &web_sys::SerialPort) -> String;
+}
+
+async fn open(rejected: bool) -> WebSerialPort {
+    WebSerialPort::builder(fake_port(rejected), 115_200)
+        .open()
+        .await
+        .expect("unable to open the fake port")
+}
+
+#[wasm_bindgen_test]
+async fn control_lines_are_set_right_away() {
+    let mut port = open(false).await;
+    port.set_dtr(true).unwrap();
+    port.set_break_condition(false).unwrap();
+
+    // Without writing, flushing or even waiting.
+    assert_eq!(
+        calls(port.port()),
+        r#"[["open",115200],["signals",{"dataTerminalReady":true}],["signals",{"break":false}]]"#
+    );
+    port.signals_applied().await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn rejected_signals_fail_the_next_write() {
+    let mut port = open(true).await;
+    port.set_rts(false).unwrap();
+
+    assert!(port.write_all(b"hi").await.is_err());
+    port.write_all(b"hi").await.unwrap();
+    port.flush().await.unwrap();
+    assert_eq!(
+        calls(port.port()),
+        r#"[["open",115200],["signals",{"requestToSend":false}],["write",[104,105]]]"#
+    );
+
+    port.set_rts(true).unwrap();
+    assert!(port.signals_applied().await.is_err());
+}
+
+#[wasm_bindgen_test]
+async fn data_goes_through_the_streams() {
+    let mut port = open(false).await;
+    assert_eq!(port.port_name().as_deref(), Some("2341:0043"));
+
+    let mut buf = [0; 3];
+    port.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, [1, 2, 3]);
+    assert!(port.change_baud_rate(9600).is_err());
+}
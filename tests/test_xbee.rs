@@ -0,0 +1,119 @@
+use bytes::{Bytes, BytesMut};
+use tokio_serial::codec::xbee::{
+    AtCommand, AtCommandResponse, ReceivePacket, TransmitRequest, XbeeError, XbeeFrame,
+};
+use tokio_serial::codec::{FrameError, XbeeCodec};
+use tokio_util::codec::{Decoder, Encoder};
+
+// The examples of the XBee manuals
+const AT_NJ: &[u8] = b"\x7e\x00\x04\x08\x52\x4e\x4a\x0d";
+const TX: &[u8] =
+    b"\x7e\x00\x16\x10\x01\x00\x13\xa2\x00\x40\x0a\x01\x27\xff\xfe\x00\x00TxData0A\x13";
+
+#[test]
+fn frames_are_typed() {
+    let mut codec = XbeeCodec::new();
+    let mut src = BytesMut::from(&b"\x00\x13"[..]);
+    src.extend_from_slice(AT_NJ);
+    src.extend_from_slice(&TX[..10]);
+
+    assert_eq!(
+        codec.decode(&mut src).unwrap().unwrap(),
+        XbeeFrame::AtCommand(AtCommand {
+            frame_id: 0x52,
+            command: *b"NJ",
+            parameter: Bytes::new(),
+        })
+    );
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+    src.extend_from_slice(&TX[10..]);
+    let tx = TransmitRequest::new(1, 0x0013_a200_400a_0127, &b"TxData0A"[..]);
+    assert_eq!(
+        codec.decode(&mut src).unwrap().unwrap(),
+        XbeeFrame::TransmitRequest(tx.clone())
+    );
+
+    let mut dst = BytesMut::new();
+    codec.encode(XbeeFrame::from(tx), &mut dst).unwrap();
+    assert_eq!(dst, TX);
+}
+
+#[test]
+fn frames_round_trip() {
+    let mut codec = XbeeCodec::new();
+    let frames = [
+        XbeeFrame::from(AtCommandResponse {
+            frame_id: 1,
+            command: *b"NI",
+            status: 0,
+            data: Bytes::from_static(b"node"),
+        }),
+        XbeeFrame::from(ReceivePacket {
+            source: 0x0013_a200_4010_1234,
+            source_16: 0x7d33,
+            options: 0x01,
+            data: Bytes::from_static(b"\x7e\x11\x13"),
+        }),
+        XbeeFrame::Other {
+            frame_type: 0x8a,
+            data: Bytes::from_static(b"\x06"),
+        },
+    ];
+    let mut dst = BytesMut::new();
+    for frame in &frames {
+        codec.encode(frame, &mut dst).unwrap();
+    }
+    for frame in &frames {
+        assert_eq!(&codec.decode(&mut dst).unwrap().unwrap(), frame);
+    }
+    assert!(dst.is_empty());
+}
+
+#[test]
+fn escaped_mode() {
+    let mut codec = XbeeCodec::new().escaped(true);
+    let frame = XbeeFrame::Other {
+        frame_type: 0x23,
+        data: Bytes::from_static(b"\x11"),
+    };
+    let mut dst = BytesMut::new();
+    codec.encode(&frame, &mut dst).unwrap();
+    assert_eq!(dst, &b"\x7e\x00\x02\x23\x7d\x31\xcb"[..]);
+
+    // A start byte ends the truncated frame before it.
+    let mut src = BytesMut::from(&b"\x7e\x00\x05\x23"[..]);
+    src.extend_from_slice(&dst);
+    assert_eq!(codec.decode(&mut src).unwrap().unwrap(), frame);
+    assert!(src.is_empty());
+}
+
+#[test]
+fn corrupted_frames_are_skipped() {
+    let mut codec = XbeeCodec::new().max_frame_length(32);
+    let mut corrupted = AT_NJ.to_vec();
+    corrupted[4] ^= 1;
+    let mut src = BytesMut::from(&corrupted[..]);
+    src.extend_from_slice(AT_NJ);
+
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(FrameError::Frame(XbeeError::Checksum))
+    ));
+    assert!(matches!(
+        codec.decode(&mut src).unwrap().unwrap(),
+        XbeeFrame::AtCommand(_)
+    ));
+
+    let mut src = BytesMut::from(&b"\x7e\x01\x00"[..]);
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(FrameError::Frame(XbeeError::TooLong(256)))
+    ));
+
+    // A receive packet without its addresses
+    let mut src = BytesMut::from(&b"\x7e\x00\x02\x90\x00\x6f"[..]);
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(FrameError::Frame(XbeeError::Malformed(0x90)))
+    ));
+}
@@ -0,0 +1,107 @@
+#![cfg(unix)]
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_serial::transfer::xmodem::{Checksum, Xmodem, Ymodem, SUB};
+use tokio_serial::transfer::{CancellationToken, FileInfo, TransferError};
+use tokio_serial::SerialStream;
+
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7) as u8).collect()
+}
+
+async fn xmodem(sender: Xmodem, receiver: Xmodem, file: &[u8]) -> Vec<u8> {
+    let (mut a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut sender, mut receiver) = (sender, receiver);
+    let mut received = Vec::new();
+    let (sent, got) = tokio::join!(
+        sender.send(&mut a, file),
+        receiver.receive(&mut b, &mut received)
+    );
+    assert_eq!(sent.unwrap(), file.len() as u64);
+    assert_eq!(got.unwrap(), received.len() as u64);
+    received
+}
+
+#[tokio::test]
+async fn xmodem_pads_files_to_whole_blocks() {
+    let file = data(300);
+    for &checksum in &[Checksum::Crc, Checksum::Sum] {
+        let received = xmodem(Xmodem::new(), Xmodem::new().checksum(checksum), &file).await;
+        assert_eq!(received.len(), 384);
+        assert_eq!(&received[..300], &file[..]);
+        assert!(received[300..].iter().all(|&b| b == SUB));
+    }
+
+    // 1K blocks, but for a short last block
+    let file = data(1100);
+    let received = xmodem(Xmodem::new().one_k(true), Xmodem::new(), &file).await;
+    assert_eq!(received.len(), 1152);
+    assert_eq!(&received[..1100], &file[..]);
+}
+
+#[tokio::test]
+async fn ymodem_sends_batches_with_sizes() {
+    let (mut a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let first = data(2000);
+    let second = b"hello".to_vec();
+    let progress = Arc::new(Mutex::new(Vec::new()));
+
+    let mut sender = Ymodem::new();
+    let mut receiver = Ymodem::new().progress({
+        let progress = progress.clone();
+        move |p| progress.lock().unwrap().push(p.clone())
+    });
+    let files = vec![
+        (FileInfo::new("first.bin", 2000), &first[..]),
+        (FileInfo::new("second.txt", 5), &second[..]),
+    ];
+    let dir = std::env::temp_dir().join(format!("tokio-serial-ymodem-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (sent, infos) = tokio::join!(
+        sender.send(&mut a, files),
+        receiver.receive(&mut b, |info| {
+            std::fs::File::create(dir.join(&info.name)).map(tokio::fs::File::from_std)
+        })
+    );
+    sent.unwrap();
+    let infos = infos.unwrap();
+    assert_eq!(
+        infos,
+        [
+            FileInfo::new("first.bin", 2000),
+            FileInfo::new("second.txt", 5)
+        ]
+    );
+    assert_eq!(std::fs::read(dir.join("first.bin")).unwrap(), first);
+    assert_eq!(std::fs::read(dir.join("second.txt")).unwrap(), second);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let progress = progress.lock().unwrap();
+    let last = progress.last().unwrap();
+    assert_eq!(last.file.as_deref(), Some("second.txt"));
+    assert_eq!((last.transferred, last.total), (5, Some(5)));
+    assert!(progress
+        .iter()
+        .any(|p| p.file.as_deref() == Some("first.bin") && p.transferred == 2000));
+}
+
+#[tokio::test]
+async fn cancelled_transfers_notify_the_peer() {
+    let (mut a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let token = CancellationToken::new();
+    let mut sender = Xmodem::new()
+        .cancellation(token.clone())
+        .progress(move |_| token.cancel());
+    let mut receiver = Xmodem::new().timeout(Duration::from_secs(1));
+
+    let file = data(1000);
+    let mut received = Vec::new();
+    let (sent, got) = tokio::join!(
+        sender.send(&mut a, &file[..]),
+        receiver.receive(&mut b, &mut received)
+    );
+    assert!(matches!(sent, Err(TransferError::Cancelled)));
+    assert!(matches!(got, Err(TransferError::CancelledByPeer)));
+    assert_eq!(&received[..], &file[..128]);
+}
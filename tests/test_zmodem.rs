@@ -0,0 +1,171 @@
+#![cfg(unix)]
+
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_serial::transfer::zmodem::Zmodem;
+use tokio_serial::transfer::{CancellationToken, FileInfo, TransferError};
+use tokio_serial::{AsyncSerialPort, SerialStream};
+
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 256) as u8).collect()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tokio-serial-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn create(dir: &Path, info: &FileInfo) -> std::io::Result<tokio::fs::File> {
+    std::fs::File::create(dir.join(&info.name)).map(tokio::fs::File::from_std)
+}
+
+// Flips a bit of the byte written at `at`, once.
+struct Corrupting {
+    port: SerialStream,
+    written: usize,
+    at: usize,
+}
+
+impl AsyncRead for Corrupting {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.port).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Corrupting {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let mut data = buf.to_vec();
+        let (start, at) = (self.written, self.at);
+        if (start..start + data.len()).contains(&at) {
+            data[at - start] ^= 0x04;
+        }
+        let written = futures::ready!(Pin::new(&mut self.port).poll_write(cx, &data))?;
+        if (start..start + written).contains(&at) {
+            self.at = usize::MAX;
+        }
+        self.written += written;
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.port).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.port).poll_shutdown(cx)
+    }
+}
+
+impl AsyncSerialPort for Corrupting {
+    fn port_name(&self) -> Option<String> {
+        None
+    }
+
+    fn change_baud_rate(&mut self, baud_rate: u32) -> tokio_serial::Result<()> {
+        self.port.change_baud_rate(baud_rate)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> tokio_serial::Result<()> {
+        self.port.set_dtr(level)
+    }
+
+    fn set_rts(&mut self, level: bool) -> tokio_serial::Result<()> {
+        self.port.set_rts(level)
+    }
+
+    fn set_break_condition(&mut self, asserted: bool) -> tokio_serial::Result<()> {
+        self.port.set_break_condition(asserted)
+    }
+}
+
+#[tokio::test]
+async fn files_are_streamed_in_batches() {
+    let (mut a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let dir = temp_dir("zmodem");
+    let first = data(5000);
+    let files = vec![
+        (FileInfo::new("first.bin", 5000), Cursor::new(first.clone())),
+        (FileInfo::new("empty", 0), Cursor::new(Vec::new())),
+    ];
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let mut sender = Zmodem::new();
+    let mut receiver = Zmodem::new().progress({
+        let progress = progress.clone();
+        move |p| progress.lock().unwrap().push(p.transferred)
+    });
+    let (sent, received) = tokio::join!(
+        sender.send(&mut a, files),
+        receiver.receive(&mut b, |info| create(&dir, info))
+    );
+    sent.unwrap();
+    assert_eq!(
+        received.unwrap(),
+        [FileInfo::new("first.bin", 5000), FileInfo::new("empty", 0)]
+    );
+    assert_eq!(std::fs::read(dir.join("first.bin")).unwrap(), first);
+    assert!(std::fs::read(dir.join("empty")).unwrap().is_empty());
+    assert_eq!(progress.lock().unwrap().last(), Some(&5000));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn corrupted_data_is_sent_again() {
+    let (a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let mut a = Corrupting {
+        port: a,
+        written: 0,
+        at: 3000,
+    };
+    let dir = temp_dir("zmodem-recovery");
+    let file = data(8000);
+
+    let mut sender = Zmodem::new();
+    let mut receiver = Zmodem::new();
+    let (sent, received) = tokio::join!(
+        sender.send(
+            &mut a,
+            vec![(FileInfo::new("file", 8000), Cursor::new(file.clone()))]
+        ),
+        receiver.receive(&mut b, |info| create(&dir, info))
+    );
+    sent.unwrap();
+    received.unwrap();
+    assert_eq!(a.at, usize::MAX);
+    assert_eq!(std::fs::read(dir.join("file")).unwrap(), file);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn cancelled_sessions_notify_the_peer() {
+    let (mut a, mut b) = SerialStream::pair().expect("unable to open pty pair");
+    let token = CancellationToken::new();
+    let mut sender = Zmodem::new();
+    let mut receiver = Zmodem::new()
+        .cancellation(token.clone())
+        .progress(move |_| token.cancel());
+
+    let file = data(100_000);
+    let (sent, received) = tokio::join!(
+        sender.send(
+            &mut a,
+            vec![(FileInfo::new("big", 100_000), Cursor::new(file))]
+        ),
+        receiver.receive(&mut b, |_| Ok(tokio::io::sink()))
+    );
+    assert!(matches!(received, Err(TransferError::Cancelled)));
+    assert!(matches!(sent, Err(TransferError::CancelledByPeer)));
+}